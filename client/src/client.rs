@@ -4,9 +4,9 @@ use std::sync::{Arc, RwLock};
 use time;
 use timer;
 
-use opcua_types::{ByteString, MessageSecurityMode, UAString};
+use opcua_types::{ByteString, MessageSecurityMode, UAString, UInt32};
 use opcua_types::{is_opc_ua_binary_url, server_url_from_endpoint_url, url_matches, url_matches_except_host};
-use opcua_types::service_types::{ApplicationDescription, EndpointDescription, RegisteredServer};
+use opcua_types::service_types::{ApplicationDescription, EndpointDescription, RegisteredServer, ServerOnNetwork};
 use opcua_types::status_codes::StatusCode;
 use opcua_types::status_codes::StatusCode::BadUnexpectedError;
 
@@ -15,6 +15,7 @@ use opcua_core::config::Config;
 
 use config::{ANONYMOUS_USER_TOKEN_ID, ClientConfig, ClientEndpoint};
 use session::{Session, SessionInfo};
+use session_retry::SessionRetryPolicy;
 
 #[derive(Debug)]
 pub enum IdentityToken {
@@ -153,14 +154,22 @@ impl Client {
         if !is_opc_ua_binary_url(session_info.endpoint.endpoint_url.as_ref()) {
             Err(format!("Endpoint url {}, is not a valid / supported url", session_info.endpoint.endpoint_url))
         } else {
-            let session = Arc::new(RwLock::new(Session::new(self.application_description(), self.certificate_store.clone(), session_info)));
-            // Set up a timer for the session to process subscriptions
+            let mut session = Session::new(self.application_description(), self.certificate_store.clone(), session_info);
+            session.set_session_retry_policy(SessionRetryPolicy::new(self.config.session_retry_limit,
+                                                                     self.config.session_retry_interval,
+                                                                     self.config.session_retry_max_interval));
+            session.set_message_limits(self.config.max_message_size, self.config.max_chunk_count);
+            let session = Arc::new(RwLock::new(session));
+            // Set up a timer for the session to reconnect if its connection breaks and to
+            // process subscriptions
             let subscription_timer = {
                 let timer = timer::Timer::new();
                 let session = session.clone();
                 let timer_guard = timer.schedule_repeating(time::Duration::milliseconds(Self::SUBSCRIPTION_TIMER_INTERVAL), move || {
-                    let mut session = trace_write_lock_unwrap!(session);
-                    session.subscription_timer();
+                    if Session::maintain_connection(&session) {
+                        let mut session = trace_write_lock_unwrap!(session);
+                        session.subscription_timer();
+                    }
                 });
                 Some((timer, timer_guard))
             };
@@ -212,6 +221,7 @@ impl Client {
             client_certificate,
         };
         let mut session = Session::new(self.application_description(), self.certificate_store.clone(), session_info);
+        session.set_message_limits(self.config.max_message_size, self.config.max_chunk_count);
         let _ = session.connect()?;
         session.get_endpoints()
     }
@@ -248,6 +258,37 @@ impl Client {
         }
     }
 
+    /// Creates a temporary `Session` to the specified discovery endpoint and returns the servers that it
+    /// has found on the network through mDNS, i.e. calls FindServersOnNetwork. All servers are returned
+    /// with their record ids so a later call can ask for just the servers added since.
+    pub fn find_servers_on_network<T>(&mut self, discovery_endpoint_url: T, starting_record_id: UInt32, server_capability_filter: Option<Vec<UAString>>) -> Result<Vec<ServerOnNetwork>, StatusCode> where T: Into<String> {
+        let discovery_endpoint_url = discovery_endpoint_url.into();
+        debug!("Creating a temporary session to discovery server {}", discovery_endpoint_url);
+        let endpoint = Self::make_endpoint_description(&discovery_endpoint_url);
+        let session = self.new_session_from_info(endpoint);
+        if let Ok(session) = session {
+            let mut session = trace_write_lock_unwrap!(session);
+            let connected = session.connect();
+            if let Ok(_) = connected {
+                let result = session.find_servers_on_network(starting_record_id, 0, server_capability_filter);
+                match result {
+                    Ok((_, servers)) => Ok(servers),
+                    Err(result) => {
+                        error!("Cannot find servers on network from discovery server {} - check this error - {:?}", discovery_endpoint_url, result);
+                        Err(result)
+                    }
+                }
+            } else {
+                let result = connected.unwrap_err();
+                error!("Cannot connect to {} - check this error - {:?}", discovery_endpoint_url, result);
+                Err(result)
+            }
+        } else {
+            error!("Cannot create a sesion to {} - check if url is malformed", discovery_endpoint_url);
+            Err(BadUnexpectedError)
+        }
+    }
+
     /// Called by servers who want to register themselves with a discovery server. The server is a
     /// client to the discovery server in this use. Normal clients do not need to call this function.
     pub fn register_server<T>(&mut self, discovery_endpoint_url: T, server: RegisteredServer) -> Result<(), StatusCode> where T: Into<String> {
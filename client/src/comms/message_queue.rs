@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::result::Result;
+
+use chrono;
+
+use opcua_types::*;
+use opcua_types::status_codes::StatusCode;
+use opcua_types::status_codes::StatusCode::*;
+
+/// A callback that receives the response to a request, or the reason there isn't one
+pub struct ResponseCallback {
+    /// The actual call back
+    cb: Box<Fn(Result<SupportedMessage, StatusCode>) + Send + 'static>
+}
+
+impl ResponseCallback {
+    /// Constructs a callback from the supplied function
+    pub fn new<CB>(cb: CB) -> ResponseCallback where CB: Fn(Result<SupportedMessage, StatusCode>) + Send + 'static {
+        ResponseCallback {
+            cb: Box::new(cb)
+        }
+    }
+
+    /// Calls the call back with the response
+    pub fn call(&self, response: Result<SupportedMessage, StatusCode>) {
+        (self.cb)(response);
+    }
+}
+
+/// A request that has been sent to the server and is waiting for its response
+pub struct PendingRequest {
+    /// The request handle in the request header, which the response header must match
+    request_handle: UInt32,
+    /// The time after which the request is abandoned, or none to wait indefinitely
+    deadline: Option<chrono::DateTime<chrono::Utc>>,
+    /// Receives the response
+    callback: ResponseCallback,
+}
+
+impl PendingRequest {
+    /// Calls the request's callback with the response, which must be for this request
+    pub fn complete(self, response: Result<SupportedMessage, StatusCode>) {
+        let response = match response {
+            Ok(response) => {
+                let request_handle = response.response_header().map(|response_header| response_header.request_handle);
+                if request_handle.is_some() && request_handle != Some(self.request_handle) {
+                    error!("Response has request handle {:?} which does not match the expected value {}", request_handle, self.request_handle);
+                    Err(BadUnknownResponse)
+                } else {
+                    Ok(response)
+                }
+            }
+            Err(status_code) => Err(status_code)
+        };
+        self.callback.call(response);
+    }
+}
+
+/// The message queue holds the requests that are waiting for a response, keyed by their request id,
+/// so responses can be routed to them in whatever order they arrive.
+///
+/// Requests are removed from the queue before their callback is called so that callbacks are free
+/// to send more requests.
+pub struct MessageQueue {
+    requests: HashMap<UInt32, PendingRequest>,
+}
+
+impl MessageQueue {
+    pub fn new() -> MessageQueue {
+        MessageQueue {
+            requests: HashMap::new(),
+        }
+    }
+
+    /// Adds a request that was sent with the supplied request id. A timeout hint of 0 means the
+    /// request waits for its response indefinitely.
+    pub fn add_request(&mut self, request_id: UInt32, request_handle: UInt32, timeout_hint: UInt32, callback: ResponseCallback) {
+        let deadline = if timeout_hint > 0 {
+            Some(chrono::Utc::now() + chrono::Duration::milliseconds(timeout_hint as i64))
+        } else {
+            None
+        };
+        self.requests.insert(request_id, PendingRequest {
+            request_handle,
+            deadline,
+            callback,
+        });
+    }
+
+    /// Removes and returns the request waiting for a response with the request id
+    pub fn take_request(&mut self, request_id: UInt32) -> Option<PendingRequest> {
+        self.requests.remove(&request_id)
+    }
+
+    /// Removes and returns the requests whose deadline has passed
+    pub fn take_expired_requests(&mut self, now: &chrono::DateTime<chrono::Utc>) -> Vec<(UInt32, PendingRequest)> {
+        let expired: Vec<UInt32> = self.requests.iter()
+            .filter(|&(_, request)| request.deadline.map_or(false, |deadline| deadline <= *now))
+            .map(|(request_id, _)| *request_id)
+            .collect();
+        expired.into_iter().map(|request_id| (request_id, self.requests.remove(&request_id).unwrap())).collect()
+    }
+
+    /// Removes and returns every request, e.g. because the connection has closed
+    pub fn take_all_requests(&mut self) -> Vec<PendingRequest> {
+        self.requests.drain().map(|(_, request)| request).collect()
+    }
+
+    /// Tests if any requests are waiting for a response
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+}
@@ -1,3 +1,4 @@
 //! Client side communications
 
+pub mod message_queue;
 pub mod tcp_transport;
@@ -1,7 +1,11 @@
-use std::net::TcpStream;
+use std::net::{TcpStream, Shutdown};
 use std::result::Result;
-use std::sync::{Arc, RwLock};
-use std::io::{Read, Write, ErrorKind};
+use std::sync::{Arc, Mutex, RwLock, mpsc};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::io::{Read, Write, Cursor, ErrorKind};
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
 
 use chrono;
 
@@ -10,51 +14,278 @@ use opcua_types::status_codes::StatusCode::*;
 use opcua_types::service_types::ChannelSecurityToken;
 use opcua_core::prelude::*;
 
+use comms::message_queue::{MessageQueue, ResponseCallback};
 use session::SessionState;
 
-// TODO these need to go, and use session settings
-const RECEIVE_BUFFER_SIZE: usize = 1024 * 64;
-//const SEND_BUFFER_SIZE: usize = 1024 * 64;
-//const MAX_MESSAGE_SIZE: usize = 1024 * 64;
+/// Extra space in the send buffer for the padding and signature that security adds to a chunk
+const SECURITY_OVERHEAD_SIZE: usize = 1024;
+/// How long the reader thread blocks on the socket before checking for expired requests and
+/// whether it should stop
+const READ_POLL_MS: u64 = 100;
 const DEFAULT_SENT_SEQUENCE_NUMBER: UInt32 = 0;
 const DEFAULT_RECEIVED_SEQUENCE_NUMBER: UInt32 = 0;
 const DEFAULT_REQUEST_ID: UInt32 = 1000;
 
-pub struct TcpTransport {
+/// The state of the reader thread, which reads chunks from the server, turns them into responses
+/// and routes each response to the request it is for.
+pub struct ResponseReader {
     /// Session state
     session_state: Arc<RwLock<SessionState>>,
-    /// Currently open stream or none
-    stream: Option<TcpStream>,
+    /// Secure channel information
+    secure_channel: Arc<RwLock<SecureChannel>>,
+    /// Requests waiting for a response
+    message_queue: Arc<Mutex<MessageQueue>>,
     /// Message buffer where portions of messages are stored to be built into chunks
     message_buffer: MessageBuffer,
-    /// Last encoded sequence number
-    last_sent_sequence_number: UInt32,
     /// Last decoded sequence number
     last_received_sequence_number: UInt32,
+    /// Intermediate chunks received so far, keyed by the request id of the message they belong to
+    pending_chunks: HashMap<UInt32, Vec<MessageChunk>>,
+}
+
+impl ResponseReader {
+    pub fn new(session_state: Arc<RwLock<SessionState>>, secure_channel: Arc<RwLock<SecureChannel>>, message_queue: Arc<Mutex<MessageQueue>>, receive_buffer_size: usize) -> ResponseReader {
+        ResponseReader {
+            session_state,
+            secure_channel,
+            message_queue,
+            message_buffer: MessageBuffer::new(receive_buffer_size),
+            last_received_sequence_number: DEFAULT_RECEIVED_SEQUENCE_NUMBER,
+            pending_chunks: HashMap::new(),
+        }
+    }
+
+    /// Reads from the stream until the connection breaks or the running flag is cleared. Requests
+    /// that time out are completed with `BadTimeout` along the way and those still waiting when
+    /// the reader stops are completed with the reason it stopped.
+    fn run(mut self, mut stream: TcpStream, running: Arc<AtomicBool>) {
+        let receive_buffer_size = {
+            let session_state = trace_read_lock_unwrap!(self.session_state);
+            session_state.receive_buffer_size
+        };
+        let mut in_buf = vec![0u8; receive_buffer_size];
+
+        let status_code = loop {
+            if !running.load(Ordering::SeqCst) {
+                break BadConnectionClosed;
+            }
+
+            // Complete requests that have waited longer than their timeout hint
+            let expired_requests = {
+                let mut message_queue = trace_lock_unwrap!(self.message_queue);
+                message_queue.take_expired_requests(&chrono::Utc::now())
+            };
+            for (request_id, request) in expired_requests {
+                debug!("Timed out waiting for a response to request id {}", request_id);
+                request.complete(Err(BadTimeout));
+            }
+
+            match stream.read(&mut in_buf) {
+                Ok(0) => {
+                    info!("Server has closed the connection");
+                    break BadConnectionClosed;
+                }
+                Ok(bytes_read) => {
+                    trace!("Bytes read = {}", bytes_read);
+                    if let Err(status_code) = self.process_bytes(&in_buf[0..bytes_read]) {
+                        break status_code;
+                    }
+                }
+                Err(error) => {
+                    if error.kind() == ErrorKind::TimedOut || error.kind() == ErrorKind::WouldBlock {
+                        continue;
+                    }
+                    debug!("Read error - kind = {:?}, {:?}", error.kind(), error);
+                    break BadCommunicationError;
+                }
+            }
+        };
+
+        // Nothing more will arrive so every outstanding request fails. The flag is cleared first so
+        // a request can't be added after the queue is emptied without the sender noticing.
+        running.store(false, Ordering::SeqCst);
+        let requests = {
+            let mut message_queue = trace_lock_unwrap!(self.message_queue);
+            message_queue.take_all_requests()
+        };
+        for request in requests {
+            request.complete(Err(status_code));
+        }
+        debug!("Reader thread has stopped with status {:?}", status_code);
+    }
+
+    /// Processes bytes read from the server, which may hold any number of chunks or parts of them
+    pub fn process_bytes(&mut self, bytes: &[u8]) -> Result<(), StatusCode> {
+        let messages = self.message_buffer.store_bytes(bytes)?;
+        for message in messages {
+            match message {
+                Message::MessageChunk(chunk) => {
+                    self.process_chunk(chunk)?;
+                }
+                Message::Error(error_message) => {
+                    let status_code = if let Ok(status_code) = StatusCode::from_u32(error_message.error) {
+                        status_code
+                    } else {
+                        BadUnexpectedError
+                    };
+                    error!("Expecting a chunk, got an error message {:?}, reason \"{}\"", status_code, error_message.reason.as_ref());
+                    return Err(status_code);
+                }
+                message => {
+                    // This is not a regular message, or an error so what is happening?
+                    error!("Expecting a chunk, got something that was not a chunk or even an error - {:?}", message);
+                    return Err(BadUnexpectedError);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the chunk belongs to the secure channel and follows the last chunk received,
+    /// returning the request id of the message the chunk is part of.
+    fn validate_chunk(&mut self, chunk: &MessageChunk) -> Result<UInt32, StatusCode> {
+        let secure_channel = trace_read_lock_unwrap!(self.secure_channel);
+        let chunk_info = chunk.chunk_info(&secure_channel)?;
+
+        let secure_channel_id = secure_channel.secure_channel_id();
+        if secure_channel_id != 0 && chunk_info.message_header.secure_channel_id != secure_channel_id {
+            error!("Secure channel id {} does not match expected id {}", chunk_info.message_header.secure_channel_id, secure_channel_id);
+            return Err(BadSecureChannelIdInvalid);
+        }
+
+        // Chunks of different messages may be interleaved, so each chunk is checked against the
+        // one received before it rather than against the other chunks of its message
+        let sequence_number = chunk_info.sequence_header.sequence_number;
+        if sequence_number <= self.last_received_sequence_number {
+            error!("Chunk sequence number of {} is not greater than last value {}", sequence_number, self.last_received_sequence_number);
+            return Err(BadSequenceNumberInvalid);
+        }
+        self.last_received_sequence_number = sequence_number;
+
+        Ok(chunk_info.sequence_header.request_id)
+    }
+
+    /// Reads the error and reason from the body of an abort chunk
+    fn abort_reason(&self, chunk: &MessageChunk) -> Result<(StatusCode, UAString), StatusCode> {
+        let secure_channel = trace_read_lock_unwrap!(self.secure_channel);
+        let chunk_info = chunk.chunk_info(&secure_channel)?;
+        let body_start = chunk_info.body_offset;
+        let body_end = body_start + chunk_info.body_length;
+        let mut stream = Cursor::new(&chunk.data[body_start..body_end]);
+        let error = UInt32::decode(&mut stream)?;
+        let reason = UAString::decode(&mut stream)?;
+        Ok((StatusCode::from_u32(error).unwrap_or(BadUnexpectedError), reason))
+    }
+
+    /// Processes a chunk received from the server. Intermediate chunks are held until the final
+    /// chunk of their message arrives, at which point the message is decoded and passed to the
+    /// request it responds to. An abort chunk discards the chunks of its message and fails the
+    /// request. An error is returned if the chunk means the connection can't be trusted any more.
+    fn process_chunk(&mut self, chunk: MessageChunk) -> Result<(), StatusCode> {
+        // trace!("Got a chunk {:?}", chunk);
+        let chunk = {
+            let mut secure_channel = trace_write_lock_unwrap!(self.secure_channel);
+            secure_channel.verify_and_remove_security(&chunk.data)?
+        };
+        let message_header = chunk.message_header()?;
+        let request_id = self.validate_chunk(&chunk)?;
+
+        let response = match message_header.is_final {
+            MessageIsFinalType::Intermediate => {
+                let (max_message_size, max_chunk_count) = {
+                    let session_state = trace_read_lock_unwrap!(self.session_state);
+                    (session_state.max_message_size, session_state.max_chunk_count)
+                };
+                let too_large = {
+                    let chunks = self.pending_chunks.entry(request_id).or_insert_with(Vec::new);
+                    chunks.push(chunk);
+                    // Stop collecting a message that exceeds the limits sent in the hello
+                    let message_size = chunks.iter().fold(0, |size, chunk| size + chunk.data.len());
+                    (max_chunk_count > 0 && chunks.len() >= max_chunk_count) || (max_message_size > 0 && message_size > max_message_size)
+                };
+                if !too_large {
+                    return Ok(());
+                }
+                error!("Response to request id {} exceeds the max chunk count {} or max message size {}", request_id, max_chunk_count, max_message_size);
+                self.pending_chunks.remove(&request_id);
+                Err(BadResponseTooLarge)
+            }
+            MessageIsFinalType::FinalError => {
+                self.pending_chunks.remove(&request_id);
+                let (status_code, reason) = self.abort_reason(&chunk)?;
+                info!("Server aborted response to request id {} with error {:?}, reason \"{}\"", request_id, status_code, reason.as_ref());
+                Err(status_code)
+            }
+            MessageIsFinalType::Final => {
+                let mut chunks = self.pending_chunks.remove(&request_id).unwrap_or_else(Vec::new);
+                chunks.push(chunk);
+                let secure_channel = trace_read_lock_unwrap!(self.secure_channel);
+                Chunker::decode(&chunks, &secure_channel, None)
+            }
+        };
+
+        let request = {
+            let mut message_queue = trace_lock_unwrap!(self.message_queue);
+            message_queue.take_request(request_id)
+        };
+        if let Some(request) = request {
+            request.complete(response);
+        } else {
+            debug!("Discarding response to request id {} which is not waiting for one", request_id);
+        }
+        Ok(())
+    }
+}
+
+pub struct TcpTransport {
+    /// Session state
+    session_state: Arc<RwLock<SessionState>>,
+    /// Currently open stream or none. The reader thread reads from a clone of it.
+    stream: Option<TcpStream>,
+    /// Last encoded sequence number
+    last_sent_sequence_number: UInt32,
     /// Secure channel information
     pub secure_channel: Arc<RwLock<SecureChannel>>,
     /// Last request id, used to track async requests
     last_request_id: UInt32,
+    /// Largest chunk the server can receive, from its acknowledge. 0 until the hello is acknowledged
+    send_buffer_size: usize,
+    /// Largest request message the server can receive, from its acknowledge. 0 is no limit
+    max_message_size: usize,
+    /// Most chunks the server can receive in a request message, from its acknowledge. 0 is no limit
+    max_chunk_count: usize,
+    /// Requests waiting for a response
+    message_queue: Arc<Mutex<MessageQueue>>,
+    /// Set while the reader thread is running, cleared to stop it or by the thread when the
+    /// connection breaks
+    reader_running: Arc<AtomicBool>,
+    /// The reader thread
+    reader_thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Drop for TcpTransport {
+    fn drop(&mut self) {
+        self.disconnect();
+    }
 }
 
 impl TcpTransport {
     /// Create a new TCP transport layer for the session
     pub fn new(certificate_store: Arc<RwLock<CertificateStore>>, session_state: Arc<RwLock<SessionState>>) -> TcpTransport {
-        let receive_buffer_size = {
-            let session_state = trace_read_lock_unwrap!(session_state);
-            session_state.receive_buffer_size
-        };
-
         let secure_channel = Arc::new(RwLock::new(SecureChannel::new(certificate_store, Role::Client)));
 
         TcpTransport {
             session_state,
             stream: None,
-            message_buffer: MessageBuffer::new(receive_buffer_size),
             last_sent_sequence_number: DEFAULT_SENT_SEQUENCE_NUMBER,
-            last_received_sequence_number: DEFAULT_RECEIVED_SEQUENCE_NUMBER,
             last_request_id: DEFAULT_REQUEST_ID,
             secure_channel,
+            send_buffer_size: 0,
+            max_message_size: 0,
+            max_chunk_count: 0,
+            message_queue: Arc::new(Mutex::new(MessageQueue::new())),
+            reader_running: Arc::new(AtomicBool::new(false)),
+            reader_thread: None,
         }
     }
 
@@ -100,7 +331,8 @@ impl TcpTransport {
             HelloMessage::new(endpoint_url,
                               session_state.send_buffer_size as UInt32,
                               session_state.receive_buffer_size as UInt32,
-                              session_state.max_message_size as UInt32)
+                              session_state.max_message_size as UInt32,
+                              session_state.max_chunk_count as UInt32)
         };
         debug!("Sending HEL {:?}", msg);
         let stream = self.stream();
@@ -113,22 +345,75 @@ impl TcpTransport {
         // Process ack
         debug!("Got ACK {:?}", ack);
 
+        // Chunks sent to the server must fit within its receive buffer and ours, and requests must
+        // honour its message size and chunk count limits.
+        let send_buffer_size = {
+            let session_state = trace_read_lock_unwrap!(self.session_state);
+            session_state.send_buffer_size
+        };
+        if !ack.is_valid_buffer_sizes() {
+            error!("ACK buffer sizes are invalid");
+            return Err(BadCommunicationError);
+        }
+        let receive_buffer_size = ack.receive_buffer_size as usize;
+        self.send_buffer_size = if receive_buffer_size < send_buffer_size { receive_buffer_size } else { send_buffer_size };
+        self.max_message_size = ack.max_message_size as usize;
+        self.max_chunk_count = ack.max_chunk_count as usize;
+
+        // Everything that arrives from now on is a response to a request
+        self.start_reader()
+    }
+
+    /// Starts the thread that reads responses from the server
+    fn start_reader(&mut self) -> Result<(), StatusCode> {
+        let stream = self.stream().try_clone().map_err(|error| {
+            error!("Cannot clone the stream for the reader thread, {:?}", error);
+            BadCommunicationError
+        })?;
+        let _ = stream.set_read_timeout(Some(Duration::from_millis(READ_POLL_MS)));
+
+        let receive_buffer_size = {
+            let session_state = trace_read_lock_unwrap!(self.session_state);
+            session_state.receive_buffer_size
+        };
+        let reader = ResponseReader::new(self.session_state.clone(), self.secure_channel.clone(), self.message_queue.clone(), receive_buffer_size);
+
+        self.reader_running.store(true, Ordering::SeqCst);
+        let running = self.reader_running.clone();
+        self.reader_thread = Some(thread::spawn(move || {
+            reader.run(stream, running);
+        }));
         Ok(())
     }
 
     /// Disconnects the stream from the server (if it is connected)
     pub fn disconnect(&mut self) {
+        // Stop the reader, which fails any requests still waiting for a response
+        self.reader_running.store(false, Ordering::SeqCst);
+        if let Some(ref stream) = self.stream {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        if let Some(reader_thread) = self.reader_thread.take() {
+            let _ = reader_thread.join();
+        }
         self.stream = None;
+        {
+            // The server gives the next connection a new secure channel
+            let mut secure_channel = trace_write_lock_unwrap!(self.secure_channel);
+            secure_channel.set_secure_channel_id(0);
+        }
         self.last_sent_sequence_number = DEFAULT_SENT_SEQUENCE_NUMBER;
-        self.last_received_sequence_number = DEFAULT_RECEIVED_SEQUENCE_NUMBER;
         self.last_request_id = DEFAULT_REQUEST_ID;
+        self.send_buffer_size = 0;
+        self.max_message_size = 0;
+        self.max_chunk_count = 0;
     }
 
     /// Tests if the transport is connected
     pub fn is_connected(&self) -> bool {
-        // The assumption is that if a read/write fails, the code that called those functions
-        // will set the stream to None if it breaks.
-        self.stream.is_some()
+        // The stream is set to None if a write fails, and the reader stops if a read fails. Until
+        // the hello is acknowledged there is no reader so only the stream counts.
+        self.stream.is_some() && (self.reader_thread.is_none() || self.reader_running.load(Ordering::SeqCst))
     }
 
     /// Sets the security token info received from an issue / renew request
@@ -161,126 +446,17 @@ impl TcpTransport {
         self.stream.as_mut().unwrap()
     }
 
-    fn turn_received_chunks_into_message(&mut self, chunks: &Vec<MessageChunk>) -> Result<SupportedMessage, StatusCode> {
-        // Validate that all chunks have incrementing sequence numbers and valid chunk types
-        let secure_channel = trace_read_lock_unwrap!(self.secure_channel);
-        self.last_received_sequence_number = Chunker::validate_chunks(self.last_received_sequence_number + 1, &secure_channel, chunks)?;
-        // Now decode
-        Chunker::decode(&chunks, &secure_channel, None)
-    }
-
-    fn process_chunk(&mut self, chunk: MessageChunk) -> Result<Option<SupportedMessage>, StatusCode> {
-        // trace!("Got a chunk {:?}", chunk);
-        let chunk = {
-            let mut secure_channel = trace_write_lock_unwrap!(self.secure_channel);
-            secure_channel.verify_and_remove_security(&chunk.data)?
-        };
-        let message_header = chunk.message_header()?;
-        match message_header.is_final {
-            MessageIsFinalType::Intermediate => {
-                panic!("We don't support intermediate chunks yet");
-            }
-            MessageIsFinalType::FinalError => {
-                info!("Discarding chunk marked in as final error");
-                return Ok(None);
-            }
-            _ => {
-                // Drop through
-            }
-        }
-
-        // TODO test chunk message type and either push to queue, turn to message or clear
-        let in_chunks = vec![chunk];
-        let message = self.turn_received_chunks_into_message(&in_chunks)?;
-
-        Ok(Some(message))
-    }
-
-    fn wait_for_response(&mut self, request_id: UInt32, request_timeout: UInt32) -> Result<SupportedMessage, StatusCode> {
-        // This loop terminates when the corresponding response comes back or a timeout occurs
-
-        debug!("Waiting for a response for request id {}", request_id);
-        // TODO buffer size
-        let mut in_buf = vec![0u8; RECEIVE_BUFFER_SIZE];
-
-        let session_status_code;
-        let start = chrono::Utc::now();
-        'message_loop: loop {
-            // Check for a timeout
-            let now = chrono::Utc::now();
-            let request_duration = now.signed_duration_since(start);
-            if request_duration.num_milliseconds() > request_timeout as i64 {
-                debug!("Time waiting {}ms exceeds timeout {}ms waiting for response from request id {}", request_duration.num_milliseconds(), request_timeout, request_id);
-                session_status_code = BadTimeout;
-                break;
-            }
-
-            // decode response
-            let bytes_read_result = self.stream().read(&mut in_buf);
-            if let Err(error) = bytes_read_result {
-                if error.kind() == ErrorKind::TimedOut {
-                    continue;
-                }
-
-                // TODO check for broken socket. if this occurs, the code should go into an error
-                // recovery state
-
-                debug!("Read error - kind = {:?}, {:?}", error.kind(), error);
-                self.stream = None;
-                session_status_code = BadUnexpectedError;
-                break;
-            }
-            let bytes_read = bytes_read_result.unwrap();
-            if bytes_read == 0 {
-                continue;
-            }
-            trace!("Bytes read = {}", bytes_read);
-
-            // TODO this is practically cut and pasted from server loop and should be common to both
-            let result = self.message_buffer.store_bytes(&in_buf[0..bytes_read]);
-            if result.is_err() {
-                session_status_code = result.unwrap_err();
-                break;
-            }
-            let messages = result.unwrap();
-            for message in messages {
-                match message {
-                    Message::MessageChunk(chunk) => {
-                        if let Some(result) = self.process_chunk(chunk)? {
-                            // TODO check the response request_handle to see if it matches our request
-                            return Ok(result);
-                        }
-                    }
-                    Message::Error(error_message) => {
-                        // TODO if this is an ERROR chunk, then the client should go into an error
-                        // recovery state, dropping the connection and reestablishing it.
-                        session_status_code = if let Ok(status_code) = StatusCode::from_u32(error_message.error) {
-                            status_code
-                        } else {
-                            BadUnexpectedError
-                        };
-                        error!("Expecting a chunk, got an error message {:?}, reason \"{}\"", session_status_code, error_message.reason.as_ref());
-                        break 'message_loop;
-                    }
-                    message => {
-                        // This is not a regular message, or an error so what is happening?
-                        error!("Expecting a chunk, got something that was not a chunk or even an error - {:?}", message);
-                        session_status_code = BadUnexpectedError;
-                        break 'message_loop;
-                    }
-                }
-            }
-            // TODO error recovery state
-        }
-        Err(session_status_code)
-    }
-
+    /// Sends a request and waits for its response. The request waits for as long as the timeout
+    /// hint in its header.
     pub fn send_request(&mut self, request: SupportedMessage) -> Result<SupportedMessage, StatusCode> {
-        // let request_timeout = request_header.timeout_hint;
         trace!("Sending a request");
-        let request_timeout = 5000; // TODO
-        let request_id = self.async_send_request(request)?;
-        self.wait_for_response(request_id, request_timeout)
+        let (sender, receiver) = mpsc::channel();
+        let request_id = self.async_send_request(request, Some(ResponseCallback::new(move |response| {
+            let _ = sender.send(response);
+        })))?;
+        debug!("Waiting for a response for request id {}", request_id);
+        // The reader always completes the request, with an error if it times out or the connection breaks
+        receiver.recv().unwrap_or(Err(BadUnexpectedError))
     }
 
     fn next_request_id(&mut self) -> UInt32 {
@@ -288,32 +464,54 @@ impl TcpTransport {
         self.last_request_id
     }
 
-    pub fn async_send_request(&mut self, request: SupportedMessage) -> Result<UInt32, StatusCode> {
+    /// Sends a request without waiting for its response, returning the request id. The callback,
+    /// if there is one, is called from the reader thread with the response or with an error if
+    /// the request's timeout hint elapses or the connection breaks first.
+    pub fn async_send_request(&mut self, request: SupportedMessage, callback: Option<ResponseCallback>) -> Result<UInt32, StatusCode> {
         if !self.is_connected() {
             return Err(BadServerNotConnected);
         }
 
         let request_id = self.next_request_id();
 
-        // TODO This needs to wait for up to the timeout hint in the request header for a response
-        // with the same request handle to return. Other messages might arrive during that, so somehow
-        // we have to deal with that situation too, e.g. queuing them up.
-
         trace!("Sending request");
 
-        // Turn message to chunk(s)
-        // TODO max message size and max chunk size
+        // Turn message to chunk(s) that fit within the limits of the server
         let chunks = {
             let secure_channel = trace_read_lock_unwrap!(self.secure_channel);
-            Chunker::encode(self.last_sent_sequence_number + 1, request_id, 0, 0, &secure_channel, &request)?
+            Chunker::encode(self.last_sent_sequence_number + 1, request_id, self.max_message_size, self.send_buffer_size, &secure_channel, &request)?
         };
+        if self.max_chunk_count > 0 && chunks.len() > self.max_chunk_count {
+            error!("Request would be sent in {} chunks which exceeds the server's max chunk count {}", chunks.len(), self.max_chunk_count);
+            return Err(BadRequestTooLarge);
+        }
 
         // Sequence number monotonically increases per chunk
         self.last_sent_sequence_number += chunks.len() as UInt32;
 
-        // Send chunks
-        let max_chunk_size = 32768; // FIXME TODO
-        let mut data = vec![0u8; max_chunk_size + 1024];
+        // Send chunks. Before the hello is acknowledged there is a single chunk of any size.
+        let max_chunk_size = if self.send_buffer_size > 0 {
+            self.send_buffer_size
+        } else {
+            chunks.iter().fold(0, |size, chunk| if chunk.data.len() > size { chunk.data.len() } else { size })
+        };
+        let mut data = vec![0u8; max_chunk_size + SECURITY_OVERHEAD_SIZE];
+
+        // The request waits for its response from before it is sent so the response can't beat it
+        if let Some(callback) = callback {
+            let (request_handle, timeout_hint) = request.request_header()
+                .map(|request_header| (request_header.request_handle, request_header.timeout_hint))
+                .unwrap_or((0, 0));
+            let mut message_queue = trace_lock_unwrap!(self.message_queue);
+            message_queue.add_request(request_id, request_handle, timeout_hint, callback);
+            // The reader empties the queue after it stops so if it has stopped already this
+            // request would never be completed
+            if !self.reader_running.load(Ordering::SeqCst) {
+                let _ = message_queue.take_request(request_id);
+                return Err(BadServerNotConnected);
+            }
+        }
+
         for chunk in chunks {
             trace!("Sending chunk of type {:?}", chunk.message_header()?.message_type);
             let size = {
@@ -326,7 +524,9 @@ impl TcpTransport {
                     if let Err(error) = bytes_written_result {
                         error!("Error while writing bytes to stream, connection broken, check error {:?}", error);
                         self.stream = None;
-                        break;
+                        let mut message_queue = trace_lock_unwrap!(self.message_queue);
+                        let _ = message_queue.take_request(request_id);
+                        return Err(BadCommunicationError);
                     }
                 }
                 Err(err) => {
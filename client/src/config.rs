@@ -7,6 +7,9 @@ use opcua_types::{MessageSecurityMode, UAString};
 use opcua_core::config::Config;
 use opcua_core::crypto::SecurityPolicy;
 
+use session_retry::{DEFAULT_SESSION_RETRY_INTERVAL, DEFAULT_SESSION_RETRY_LIMIT, DEFAULT_SESSION_RETRY_MAX_INTERVAL};
+use session::{DEFAULT_MAX_MESSAGE_SIZE, DEFAULT_MAX_CHUNK_COUNT};
+
 pub const ANONYMOUS_USER_TOKEN_ID: &str = "ANONYMOUS";
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
@@ -85,6 +88,23 @@ pub struct ClientConfig {
     pub user_tokens: BTreeMap<String, ClientUserToken>,
     /// List of end points
     pub endpoints: BTreeMap<String, ClientEndpoint>,
+    /// Number of times a session tries to reconnect after its connection breaks before it gives
+    /// up, or 0 to keep trying forever
+    #[serde(default = "ClientConfig::default_session_retry_limit")]
+    pub session_retry_limit: u32,
+    /// Interval in milliseconds between the first failed attempt to reconnect and the next. The
+    /// interval doubles after every failed attempt.
+    #[serde(default = "ClientConfig::default_session_retry_interval")]
+    pub session_retry_interval: u32,
+    /// Longest interval in milliseconds between attempts to reconnect
+    #[serde(default = "ClientConfig::default_session_retry_max_interval")]
+    pub session_retry_max_interval: u32,
+    /// Largest response message in bytes that the client accepts from a server, or 0 for no limit
+    #[serde(default = "ClientConfig::default_max_message_size")]
+    pub max_message_size: usize,
+    /// Most chunks in a response message that the client accepts from a server, or 0 for no limit
+    #[serde(default = "ClientConfig::default_max_chunk_count")]
+    pub max_chunk_count: usize,
 }
 
 impl Config for ClientConfig {
@@ -142,6 +162,21 @@ impl ClientConfig {
             default_endpoint: String::new(),
             user_tokens: BTreeMap::new(),
             endpoints: BTreeMap::new(),
+            session_retry_limit: Self::default_session_retry_limit(),
+            session_retry_interval: Self::default_session_retry_interval(),
+            session_retry_max_interval: Self::default_session_retry_max_interval(),
+            max_message_size: Self::default_max_message_size(),
+            max_chunk_count: Self::default_max_chunk_count(),
         }
     }
+
+    fn default_session_retry_limit() -> u32 { DEFAULT_SESSION_RETRY_LIMIT }
+
+    fn default_session_retry_interval() -> u32 { DEFAULT_SESSION_RETRY_INTERVAL }
+
+    fn default_session_retry_max_interval() -> u32 { DEFAULT_SESSION_RETRY_MAX_INTERVAL }
+
+    fn default_max_message_size() -> usize { DEFAULT_MAX_MESSAGE_SIZE }
+
+    fn default_max_chunk_count() -> usize { DEFAULT_MAX_CHUNK_COUNT }
 }
@@ -19,6 +19,7 @@ extern crate opcua_core;
 mod comms;
 mod subscription;
 mod subscription_state;
+mod session_retry;
 
 pub mod config;
 pub mod client;
@@ -32,6 +33,7 @@ pub mod prelude {
     pub use config::*;
     pub use session::*;
     pub use subscription::{MonitoredItem, DataChangeCallback};
+    pub use session_retry::SessionRetryPolicy;
 }
 
 #[cfg(test)]
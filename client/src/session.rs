@@ -1,7 +1,10 @@
+use std::mem;
 use std::result::Result;
 use std::str::FromStr;
 use std::sync::{Arc, RwLock};
 
+use chrono;
+
 use opcua_core::crypto;
 use opcua_core::crypto::{CertificateStore, PKey, SecurityPolicy, X509};
 use opcua_types::*;
@@ -11,7 +14,9 @@ use opcua_types::status_codes::StatusCode;
 use opcua_types::status_codes::StatusCode::*;
 
 use client;
+use comms::message_queue::ResponseCallback;
 use comms::tcp_transport::TcpTransport;
+use session_retry::{Answer, SessionRetryPolicy};
 use subscription;
 use subscription::{DataChangeCallback, Subscription};
 use subscription_state::SubscriptionState;
@@ -54,10 +59,18 @@ const DEFAULT_SESSION_TIMEOUT: u32 = 60 * 1000;
 const DEFAULT_REQUEST_TIMEOUT: u32 = 10 * 1000;
 const SEND_BUFFER_SIZE: usize = 65536;
 const RECEIVE_BUFFER_SIZE: usize = 65536;
-const MAX_BUFFER_SIZE: usize = 65536;
+/// Largest response message in bytes that a session accepts unless configured otherwise
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+/// Most chunks in a response message that a session accepts unless configured otherwise, 0 for
+/// no limit
+pub const DEFAULT_MAX_CHUNK_COUNT: usize = 0;
+/// The most publish requests the session keeps waiting on the server at once, so notifications
+/// can be returned while the response to an earlier request is still on its way
+const MAX_PUBLISH_REQUESTS: usize = 2;
 
 /// Session's state indicates connection status, negotiated times and sizes,
 /// and security tokens.
+#[derive(Clone)]
 pub struct SessionState {
     /// The request timeout is how long the session will wait from sending a request expecting a response
     /// if no response is received the rclient will terminate.
@@ -70,6 +83,8 @@ pub struct SessionState {
     pub receive_buffer_size: usize,
     /// Maximum message size
     pub max_message_size: usize,
+    /// Maximum number of chunks in a message, 0 for no limit
+    pub max_chunk_count: usize,
     /// The next handle to assign to a request
     pub last_request_handle: UInt32,
     /// The authentication token negotiated with the server (if any)
@@ -83,30 +98,62 @@ impl SessionState {
             request_timeout: DEFAULT_REQUEST_TIMEOUT,
             send_buffer_size: SEND_BUFFER_SIZE,
             receive_buffer_size: RECEIVE_BUFFER_SIZE,
-            max_message_size: MAX_BUFFER_SIZE,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            max_chunk_count: DEFAULT_MAX_CHUNK_COUNT,
             last_request_handle: 1,
             authentication_token: NodeId::null(),
         }
     }
 }
 
+/// This is the callback that clients register to hear when the session's connection to the
+/// server breaks and when it is restored. It is called with the session locked, so it must not
+/// try to lock the session itself.
+pub struct ConnectionStatusCallback {
+    /// The actual call back
+    cb: Box<Fn(bool) + Send + Sync + 'static>
+}
+
+impl ConnectionStatusCallback {
+    /// Constructs a callback from the supplied function
+    pub fn new<CB>(cb: CB) -> ConnectionStatusCallback where CB: Fn(bool) + Send + Sync + 'static {
+        ConnectionStatusCallback {
+            cb: Box::new(cb)
+        }
+    }
+
+    /// Calls the call back with whether the session is connected
+    pub fn call(&self, connected: bool) {
+        (self.cb)(connected);
+    }
+}
+
 /// A session of the client. The session is associated with an endpoint and
 /// maintains a state when it is active.
 pub struct Session {
     /// The client application's name
     application_description: ApplicationDescription,
     /// The session connection info
-    session_info: SessionInfo,
+    session_info: Arc<SessionInfo>,
+    /// Certificate store, used to make new connections
+    certificate_store: Arc<RwLock<CertificateStore>>,
     /// Runtime state of the session, reset if disconnected
     session_state: Arc<RwLock<SessionState>>,
     /// Subscriptions state
     subscription_state: Arc<RwLock<SubscriptionState>>,
-    /// Unacknowledged
-    subscription_acknowledgements: Vec<SubscriptionAcknowledgement>,
     /// Transport layer
     transport: TcpTransport,
     /// Next monitored item handle
     last_monitored_item_handle: UInt32,
+    /// Set from when the session is activated until the application disconnects it. While it is
+    /// set a broken connection is reconnected.
+    keep_connected: bool,
+    /// Set while the connection is broken and the session is trying to reconnect
+    reconnecting: bool,
+    /// How the session reconnects
+    session_retry_policy: SessionRetryPolicy,
+    /// Called when the connection breaks and when it is restored
+    connection_status_callback: Option<ConnectionStatusCallback>,
 }
 
 impl Drop for Session {
@@ -121,19 +168,43 @@ impl Session {
     /// Create a new session.
     pub fn new(application_description: ApplicationDescription, certificate_store: Arc<RwLock<CertificateStore>>, session_info: SessionInfo) -> Session {
         let session_state = Arc::new(RwLock::new(SessionState::new()));
-        let transport = TcpTransport::new(certificate_store, session_state.clone());
+        let transport = TcpTransport::new(certificate_store.clone(), session_state.clone());
         let subscription_state = Arc::new(RwLock::new(SubscriptionState::new()));
         Session {
             application_description,
-            session_info,
+            session_info: Arc::new(session_info),
+            certificate_store,
             session_state,
             subscription_state,
-            subscription_acknowledgements: Vec::new(),
             transport,
             last_monitored_item_handle: 0,
+            keep_connected: false,
+            reconnecting: false,
+            session_retry_policy: SessionRetryPolicy::default(),
+            connection_status_callback: None,
         }
     }
 
+    /// Sets the policy the session follows to reconnect after its connection breaks
+    pub fn set_session_retry_policy(&mut self, session_retry_policy: SessionRetryPolicy) {
+        self.session_retry_policy = session_retry_policy;
+    }
+
+    /// Sets the largest response message in bytes, and the most chunks in one, that the session
+    /// asks the server to limit its responses to. 0 is no limit. Takes effect when the session
+    /// next connects.
+    pub fn set_message_limits(&mut self, max_message_size: usize, max_chunk_count: usize) {
+        let mut session_state = trace_write_lock_unwrap!(self.session_state);
+        session_state.max_message_size = max_message_size;
+        session_state.max_chunk_count = max_chunk_count;
+    }
+
+    /// Sets the callback that is told when the connection to the server breaks and when it is
+    /// restored
+    pub fn set_connection_status_callback(&mut self, connection_status_callback: ConnectionStatusCallback) {
+        self.connection_status_callback = Some(connection_status_callback);
+    }
+
     /// Connects to the server (if possible) using the configured session arguments
     pub fn connect(&mut self) -> Result<(), StatusCode> {
         let endpoint_url = self.session_info.endpoint.endpoint_url.clone();
@@ -168,29 +239,141 @@ impl Session {
         Ok(())
     }
 
-    /// Reconnects to the server and tries to activate the existing session
+    /// Reconnects to the server and tries to activate the existing session. If the server no
+    /// longer has the session, a new session is created and activated and the subscriptions are
+    /// transferred to it, or created again if they can't be transferred.
     pub fn reconnect_and_activate_session(&mut self) -> Result<(), StatusCode> {
-        // TODO Do nothing if already connected
-        // TODO Check if session is already activated, i.e. has a session_id
-        if self.is_connected() {
-            self.disconnect();
+        // Throw away what is left of the old connection
+        self.transport.disconnect();
+
+        let _ = self.connect()?;
+        if let Err(status_code) = self.activate_session() {
+            info!("Session could not be activated again, error {:?}, so a new session will be created", status_code);
+            let _ = self.reconnect_and_create_session()?;
+            let _ = self.restore_subscriptions()?;
         }
+        Ok(())
+    }
 
+    /// Makes a fresh connection to the server and creates and activates a new session on it. A
+    /// fresh connection is used because the server may close the connection after it rejects
+    /// a request.
+    fn reconnect_and_create_session(&mut self) -> Result<(), StatusCode> {
+        self.transport.disconnect();
         let _ = self.connect()?;
-        let activated = self.activate_session();
-        if let Err(error) = activated {
-            // TODO if this doesn't work then we need to
-            // 1) create a new session
-            // 2) activate session
-            // 3) reconstruct all subscriptions and monitored items from their cached values
-            Err(error)
-        } else {
-            Ok(())
+        let _ = self.create_session()?;
+        self.activate_session()
+    }
+
+    /// Detects a broken connection and tries to reconnect according to the session retry
+    /// policy, telling the connection status callback when the connection breaks and when it is
+    /// restored. Returns true if the session is connected. The client calls this periodically
+    /// for each of its sessions.
+    ///
+    /// The session is only locked to decide whether to make an attempt and to swap in the new
+    /// connection afterwards. The attempt itself is made on a separate connection, so the session
+    /// stays usable while the attempt waits on the server.
+    pub fn maintain_connection(session: &Arc<RwLock<Session>>) -> bool {
+        let mut reconnecting_session = {
+            let mut session = trace_write_lock_unwrap!(session);
+            if session.is_connected() {
+                return true;
+            } else if !session.keep_connected {
+                return false;
+            }
+
+            if !session.reconnecting {
+                info!("Connection to the server has broken");
+                session.reconnecting = true;
+                session.session_retry_policy.reset();
+                session.notify_connection_status(false);
+            }
+
+            let now = chrono::Utc::now();
+            match session.session_retry_policy.should_retry(&now) {
+                Answer::WaitFor(_) => {
+                    return false;
+                }
+                Answer::GiveUp => {
+                    error!("Session has given up trying to reconnect after {} attempts", session.session_retry_policy.retry_count());
+                    session.keep_connected = false;
+                    session.reconnecting = false;
+                    return false;
+                }
+                Answer::Retry => {
+                    info!("Trying to reconnect to the server, attempt {}", session.session_retry_policy.retry_count() + 1);
+                    session.reconnecting_session()
+                }
+            }
+        };
+
+        // Reconnect without holding the lock
+        let result = reconnecting_session.reconnect_and_activate_session();
+
+        let mut session = trace_write_lock_unwrap!(session);
+        match result {
+            Ok(_) if session.reconnecting && !session.is_connected() => {
+                info!("Session has reconnected to the server");
+                mem::swap(&mut session.transport, &mut reconnecting_session.transport);
+                mem::swap(&mut session.session_state, &mut reconnecting_session.session_state);
+                session.reconnecting = false;
+                session.session_retry_policy.reset();
+                session.notify_connection_status(true);
+                true
+            }
+            Ok(_) => {
+                // The session was disconnected or connected again while the attempt was made
+                reconnecting_session.transport.disconnect();
+                session.is_connected()
+            }
+            Err(status_code) => {
+                info!("Session could not reconnect to the server, error {:?}", status_code);
+                reconnecting_session.transport.disconnect();
+                session.session_retry_policy.retry_failed(&chrono::Utc::now());
+                false
+            }
+        }
+    }
+
+    /// Makes a session with its own connection that shares the settings, subscriptions and a copy
+    /// of the state of this session, to reconnect to the server with while this session is not
+    /// locked.
+    fn reconnecting_session(&self) -> Session {
+        let session_state = {
+            let session_state = trace_read_lock_unwrap!(self.session_state);
+            Arc::new(RwLock::new(session_state.clone()))
+        };
+        let transport = TcpTransport::new(self.certificate_store.clone(), session_state.clone());
+        Session {
+            application_description: self.application_description.clone(),
+            session_info: self.session_info.clone(),
+            certificate_store: self.certificate_store.clone(),
+            session_state,
+            subscription_state: self.subscription_state.clone(),
+            transport,
+            last_monitored_item_handle: self.last_monitored_item_handle,
+            keep_connected: false,
+            reconnecting: false,
+            session_retry_policy: SessionRetryPolicy::default(),
+            connection_status_callback: None,
+        }
+    }
+
+    /// Tests if the connection is broken and the session is still trying to reconnect
+    pub fn is_reconnecting(&self) -> bool {
+        self.reconnecting
+    }
+
+    fn notify_connection_status(&self, connected: bool) {
+        if let Some(ref connection_status_callback) = self.connection_status_callback {
+            connection_status_callback.call(connected);
         }
     }
 
     /// Disconnect from the server
     pub fn disconnect(&mut self) {
+        self.keep_connected = false;
+        self.reconnecting = false;
         let _ = self.delete_all_subscriptions();
         let _ = self.close_secure_channel();
         self.transport.disconnect();
@@ -211,7 +394,7 @@ impl Session {
             request_header: self.make_request_header(),
         };
         // We do not wait for a response because there may not be one. Just return
-        let _ = self.async_send_request(request);
+        let _ = self.async_send_request(request, None);
         Ok(())
     }
 
@@ -333,6 +516,12 @@ impl Session {
         if let SupportedMessage::ActivateSessionResponse(response) = response {
             // trace!("ActivateSessionResponse = {:#?}", response);
             Self::process_service_result(&response.response_header)?;
+            {
+                // The next activation is signed with the nonce from this response
+                let mut secure_channel = trace_write_lock_unwrap!(self.transport.secure_channel);
+                let _ = secure_channel.set_remote_nonce_from_byte_string(&response.server_nonce);
+            }
+            self.keep_connected = true;
             Ok(())
         } else {
             Err(Self::process_unexpected_response(response))
@@ -361,6 +550,31 @@ impl Session {
         }
     }
 
+    /// Calls FindServersOnNetwork on a discovery server that has multicast discovery. The result is the
+    /// time the server's record ids were last reset and the servers whose record id is at least the
+    /// starting record id and which have every capability in the filter. A max records to return of
+    /// 0 returns them all.
+    pub fn find_servers_on_network(&mut self, starting_record_id: UInt32, max_records_to_return: UInt32, server_capability_filter: Option<Vec<UAString>>) -> Result<(DateTime, Vec<ServerOnNetwork>), StatusCode> {
+        let request = FindServersOnNetworkRequest {
+            request_header: self.make_request_header(),
+            starting_record_id,
+            max_records_to_return,
+            server_capability_filter,
+        };
+        let response = self.send_request(request)?;
+        if let SupportedMessage::FindServersOnNetworkResponse(response) = response {
+            Self::process_service_result(&response.response_header)?;
+            let servers = if let Some(servers) = response.servers {
+                servers
+            } else {
+                Vec::new()
+            };
+            Ok((response.last_counter_reset_time, servers))
+        } else {
+            Err(Self::process_unexpected_response(response))
+        }
+    }
+
     pub fn register_server(&mut self, server: RegisteredServer) -> Result<(), StatusCode> {
         let request = RegisterServerRequest {
             request_header: self.make_request_header(),
@@ -446,6 +660,50 @@ impl Session {
         }
     }
 
+    /// Sends a RegisterNodesRequest to the server, returning the node ids to use for the nodes
+    /// from now on. The server may give nodes with long ids shorter aliases that are quicker to
+    /// send and resolve when the nodes are read, written or monitored repeatedly. The aliases
+    /// are only valid for this session, so nodes must be registered again if the session is
+    /// recreated after a reconnect.
+    pub fn register_nodes(&mut self, nodes_to_register: Vec<NodeId>) -> Result<Vec<NodeId>, StatusCode> {
+        if nodes_to_register.is_empty() {
+            error!("register_nodes() was not supplied with any nodes to register");
+            Err(BadNothingToDo)
+        } else {
+            let request = RegisterNodesRequest {
+                request_header: self.make_request_header(),
+                nodes_to_register: Some(nodes_to_register),
+            };
+            let response = self.send_request(request)?;
+            if let SupportedMessage::RegisterNodesResponse(response) = response {
+                Self::process_service_result(&response.response_header)?;
+                Ok(response.registered_node_ids.unwrap_or_default())
+            } else {
+                Err(Self::process_unexpected_response(response))
+            }
+        }
+    }
+
+    /// Sends an UnregisterNodesRequest to the server for node ids returned by `register_nodes()`
+    pub fn unregister_nodes(&mut self, nodes_to_unregister: Vec<NodeId>) -> Result<(), StatusCode> {
+        if nodes_to_unregister.is_empty() {
+            error!("unregister_nodes() was not supplied with any nodes to unregister");
+            Err(BadNothingToDo)
+        } else {
+            let request = UnregisterNodesRequest {
+                request_header: self.make_request_header(),
+                nodes_to_unregister: Some(nodes_to_unregister),
+            };
+            let response = self.send_request(request)?;
+            if let SupportedMessage::UnregisterNodesResponse(response) = response {
+                Self::process_service_result(&response.response_header)?;
+                Ok(())
+            } else {
+                Err(Self::process_unexpected_response(response))
+            }
+        }
+    }
+
     /// Sends a ReadRequest to the server
     pub fn read_nodes(&mut self, nodes_to_read: Vec<ReadValueId>) -> Result<Option<Vec<DataValue>>, StatusCode> {
         if nodes_to_read.is_empty() {
@@ -472,6 +730,35 @@ impl Session {
         }
     }
 
+    /// Sends a ReadRequest to the server without waiting for the response. The callback is called
+    /// from another thread with the results when the response arrives, or with an error if the
+    /// request fails or times out. The request id is returned.
+    pub fn async_read_nodes<CB>(&mut self, nodes_to_read: Vec<ReadValueId>, callback: CB) -> Result<UInt32, StatusCode>
+        where CB: Fn(Result<Option<Vec<DataValue>>, StatusCode>) + Send + 'static {
+        if nodes_to_read.is_empty() {
+            error!("async_read_nodes() was not supplied with any nodes to read");
+            Err(BadNothingToDo)
+        } else {
+            debug!("async_read_nodes requested to read nodes {:?}", nodes_to_read);
+            let request = ReadRequest {
+                request_header: self.make_request_header(),
+                max_age: 1f64,
+                timestamps_to_return: TimestampsToReturn::Server,
+                nodes_to_read: Some(nodes_to_read),
+            };
+            self.async_send_request(request, Some(ResponseCallback::new(move |response| {
+                callback(response.and_then(|response| {
+                    if let SupportedMessage::ReadResponse(response) = response {
+                        Self::process_service_result(&response.response_header)?;
+                        Ok(response.results)
+                    } else {
+                        Err(Self::process_unexpected_response(response))
+                    }
+                }));
+            })))
+        }
+    }
+
     /// Sends a WriteRequest to the server
     pub fn write_value(&mut self, nodes_to_write: Vec<WriteValue>) -> Result<Option<Vec<StatusCode>>, StatusCode> {
         if nodes_to_write.is_empty() {
@@ -648,6 +935,28 @@ impl Session {
         }
     }
 
+    /// Transfers subscriptions from another session to this one, returning the result for each
+    /// subscription
+    pub fn transfer_subscriptions(&mut self, subscription_ids: Vec<UInt32>, send_initial_values: Boolean) -> Result<Vec<TransferResult>, StatusCode> {
+        if subscription_ids.is_empty() {
+            error!("transfer_subscriptions() no subscription ids were provided");
+            Err(BadNothingToDo)
+        } else {
+            let request = TransferSubscriptionsRequest {
+                request_header: self.make_request_header(),
+                subscription_ids: Some(subscription_ids),
+                send_initial_values,
+            };
+            let response = self.send_request(request)?;
+            if let SupportedMessage::TransferSubscriptionsResponse(response) = response {
+                Self::process_service_result(&response.response_header)?;
+                Ok(response.results.unwrap_or_else(Vec::new))
+            } else {
+                Err(Self::process_unexpected_response(response))
+            }
+        }
+    }
+
     /// Create monitored items request
     pub fn create_monitored_items(&mut self, subscription_id: UInt32, mut items_to_create: Vec<MonitoredItemCreateRequest>) -> Result<Vec<MonitoredItemCreateResult>, StatusCode> {
         if subscription_id == 0 {
@@ -683,6 +992,9 @@ impl Session {
                             item_to_monitor: i.item_to_monitor.clone(),
                             queue_size: r.revised_queue_size,
                             sampling_interval: r.revised_sampling_interval,
+                            monitoring_mode: i.monitoring_mode,
+                            discard_oldest: i.requested_parameters.discard_oldest,
+                            filter: i.requested_parameters.filter.clone(),
                         }
                     }).collect();
                     {
@@ -709,25 +1021,28 @@ impl Session {
             error!("modify_monitored_items() called with no items to modify");
             Err(BadNothingToDo)
         } else {
-            let monitored_item_ids: Vec<UInt32> = items_to_modify.iter().map(|i| i.monitored_item_id).collect();
             let request = ModifyMonitoredItemsRequest {
                 request_header: self.make_request_header(),
                 subscription_id,
                 timestamps_to_return: TimestampsToReturn::Both,
-                items_to_modify: Some(items_to_modify),
+                items_to_modify: Some(items_to_modify.clone()),
             };
             let response = self.send_request(request)?;
             if let SupportedMessage::ModifyMonitoredItemsResponse(response) = response {
                 Self::process_service_result(&response.response_header)?;
                 if let Some(ref results) = response.results {
                     // Set the items in our internal state
-                    let items_to_modify: Vec<subscription::ModifyMonitoredItem> = monitored_item_ids.iter().zip(results.iter()).map(|(id, r)| {
-                        subscription::ModifyMonitoredItem {
-                            id: *id,
-                            queue_size: r.revised_queue_size,
-                            sampling_interval: r.revised_sampling_interval,
-                        }
-                    }).collect();
+                    let items_to_modify: Vec<subscription::ModifyMonitoredItem> = items_to_modify.iter().zip(results.iter())
+                        .filter(|&(_, r)| r.status_code.is_good())
+                        .map(|(i, r)| {
+                            subscription::ModifyMonitoredItem {
+                                id: i.monitored_item_id,
+                                queue_size: r.revised_queue_size,
+                                sampling_interval: r.revised_sampling_interval,
+                                discard_oldest: i.requested_parameters.discard_oldest,
+                                filter: i.requested_parameters.filter.clone(),
+                            }
+                        }).collect();
                     {
                         let mut subscription_state = trace_write_lock_unwrap!(self.subscription_state);
                         subscription_state.modify_monitored_items(subscription_id, items_to_modify);
@@ -771,6 +1086,81 @@ impl Session {
         }
     }
 
+    /// Sets the monitoring mode of monitored items in the subscription. Items in sampling mode
+    /// report their values only when an item that triggers them reports.
+    pub fn set_monitoring_mode(&mut self, subscription_id: UInt32, monitoring_mode: MonitoringMode, monitored_item_ids: Vec<UInt32>) -> Result<Vec<StatusCode>, StatusCode> {
+        if subscription_id == 0 {
+            error!("set_monitoring_mode() subscription id must be non-zero, or the subscription is considered invalid");
+            Err(BadInvalidArgument)
+        } else if !self.subscription_exists(subscription_id) {
+            error!("set_monitoring_mode() subscription id does not exist");
+            Err(BadInvalidArgument)
+        } else if monitored_item_ids.is_empty() {
+            error!("set_monitoring_mode() called with no items to set");
+            Err(BadNothingToDo)
+        } else {
+            let request = SetMonitoringModeRequest {
+                request_header: self.make_request_header(),
+                subscription_id,
+                monitoring_mode,
+                monitored_item_ids: Some(monitored_item_ids.clone()),
+            };
+            let response = self.send_request(request)?;
+            if let SupportedMessage::SetMonitoringModeResponse(response) = response {
+                Self::process_service_result(&response.response_header)?;
+                let results = response.results.unwrap_or_else(Vec::new);
+                {
+                    // Remember the mode of the items that were set so it is restored along with them
+                    let monitored_item_ids = Self::good_results(&monitored_item_ids, &results);
+                    let mut subscription_state = trace_write_lock_unwrap!(self.subscription_state);
+                    subscription_state.set_monitoring_mode(subscription_id, monitoring_mode, monitored_item_ids);
+                }
+                Ok(results)
+            } else {
+                Err(Self::process_unexpected_response(response))
+            }
+        }
+    }
+
+    /// Adds and removes links from a triggering item to the items it triggers, returning the
+    /// results for the links to add and the links to remove
+    pub fn set_triggering(&mut self, subscription_id: UInt32, triggering_item_id: UInt32, links_to_add: Vec<UInt32>, links_to_remove: Vec<UInt32>) -> Result<(Vec<StatusCode>, Vec<StatusCode>), StatusCode> {
+        if subscription_id == 0 {
+            error!("set_triggering() subscription id must be non-zero, or the subscription is considered invalid");
+            Err(BadInvalidArgument)
+        } else if !self.subscription_exists(subscription_id) {
+            error!("set_triggering() subscription id does not exist");
+            Err(BadInvalidArgument)
+        } else if links_to_add.is_empty() && links_to_remove.is_empty() {
+            error!("set_triggering() called with no links to add or remove");
+            Err(BadNothingToDo)
+        } else {
+            let request = SetTriggeringRequest {
+                request_header: self.make_request_header(),
+                subscription_id,
+                triggering_item_id,
+                links_to_add: if links_to_add.is_empty() { None } else { Some(links_to_add.clone()) },
+                links_to_remove: if links_to_remove.is_empty() { None } else { Some(links_to_remove.clone()) },
+            };
+            let response = self.send_request(request)?;
+            if let SupportedMessage::SetTriggeringResponse(response) = response {
+                Self::process_service_result(&response.response_header)?;
+                let add_results = response.add_results.unwrap_or_else(Vec::new);
+                let remove_results = response.remove_results.unwrap_or_else(Vec::new);
+                {
+                    // Remember the links that were set so they are restored along with the items
+                    let links_to_add = Self::good_results(&links_to_add, &add_results);
+                    let links_to_remove = Self::good_results(&links_to_remove, &remove_results);
+                    let mut subscription_state = trace_write_lock_unwrap!(self.subscription_state);
+                    subscription_state.set_triggering(subscription_id, triggering_item_id, links_to_add, links_to_remove);
+                }
+                Ok((add_results, remove_results))
+            } else {
+                Err(Self::process_unexpected_response(response))
+            }
+        }
+    }
+
     /// Calls a single method on an object on the server via a call method request.
     pub fn call_method<T>(&mut self, method: T) -> Result<CallMethodResult, StatusCode> where T: Into<CallMethodRequest> {
         let methods_to_call = Some(vec![method.into()]);
@@ -796,24 +1186,242 @@ impl Session {
         }
     }
 
+    // Returns the ids whose results are good
+    fn good_results(ids: &[UInt32], results: &[StatusCode]) -> Vec<UInt32> {
+        ids.iter().zip(results.iter())
+            .filter(|&(_, status_code)| status_code.is_good())
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
     // Test if the subscription by id exists
     fn subscription_exists(&self, subscription_id: UInt32) -> bool {
         let subscription_state = trace_read_lock_unwrap!(self.subscription_state);
         subscription_state.subscription_exists(subscription_id)
     }
 
-    // Sends a publish request containing any acknowledgements
-    fn publish(&mut self, subscription_acknowledgements: Vec<SubscriptionAcknowledgement>) -> Result<PublishResponse, StatusCode> {
-        let request = PublishRequest {
+    /// Restores the subscriptions after a new session replaces one that was lost. Subscriptions
+    /// are transferred from the old session if the server still has them, otherwise they are
+    /// created again along with their monitored items.
+    fn restore_subscriptions(&mut self) -> Result<(), StatusCode> {
+        let subscription_ids = {
+            let subscription_state = trace_read_lock_unwrap!(self.subscription_state);
+            subscription_state.subscription_ids()
+        };
+        if let Some(subscription_ids) = subscription_ids {
+            let transferred = match self.transfer_subscriptions(subscription_ids.clone(), true) {
+                Ok(results) => results.iter().map(|r| r.status_code.is_good()).collect(),
+                Err(status_code) => {
+                    info!("Subscriptions could not be transferred, error {:?}, so they will be created again", status_code);
+                    if !self.is_connected() {
+                        let _ = self.reconnect_and_create_session()?;
+                    }
+                    Vec::new()
+                }
+            };
+            for (idx, subscription_id) in subscription_ids.iter().enumerate() {
+                if !transferred.get(idx).cloned().unwrap_or(false) {
+                    if let Err(status_code) = self.recreate_subscription(*subscription_id) {
+                        // Forget the new session so the next attempt starts afresh
+                        let mut session_state = trace_write_lock_unwrap!(self.session_state);
+                        session_state.authentication_token = NodeId::null();
+                        return Err(status_code);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates a subscription and its monitored items again from their cached values. The
+    /// subscription and monitored items keep their callback and client handles but take the new
+    /// ids given by the server. Monitored items are created with the monitoring mode, filter and
+    /// other parameters they were last given, and their triggering links are set again.
+    fn recreate_subscription(&mut self, subscription_id: UInt32) -> Result<(), StatusCode> {
+        let (publishing_interval, lifetime_count, max_keep_alive_count, max_notifications_per_publish, priority, publishing_enabled, items_to_create, triggering_links) = {
+            let subscription_state = trace_read_lock_unwrap!(self.subscription_state);
+            let subscription = if let Some(subscription) = subscription_state.subscription(subscription_id) {
+                subscription
+            } else {
+                return Err(BadSubscriptionIdInvalid);
+            };
+            (subscription.publishing_interval(), subscription.lifetime_count(), subscription.max_keep_alive_count(),
+             subscription.max_notifications_per_publish(), subscription.priority(), subscription.publishing_enabled(),
+             subscription.monitored_item_create_requests(), subscription.triggering_links())
+        };
+
+        let request = CreateSubscriptionRequest {
             request_header: self.make_request_header(),
-            subscription_acknowledgements: if subscription_acknowledgements.is_empty() { None } else { Some(subscription_acknowledgements) },
+            requested_publishing_interval: publishing_interval,
+            requested_lifetime_count: lifetime_count,
+            requested_max_keep_alive_count: max_keep_alive_count,
+            max_notifications_per_publish,
+            publishing_enabled,
+            priority,
         };
         let response = self.send_request(request)?;
-        if let SupportedMessage::PublishResponse(response) = response {
+        let new_subscription_id = if let SupportedMessage::CreateSubscriptionResponse(response) = response {
             Self::process_service_result(&response.response_header)?;
-            Ok(response)
+            debug!("Subscription {} has been created again with id {}", subscription_id, response.subscription_id);
+            let mut subscription_state = trace_write_lock_unwrap!(self.subscription_state);
+            subscription_state.change_subscription_id(subscription_id, response.subscription_id);
+            subscription_state.modify_subscription(response.subscription_id,
+                                                   response.revised_publishing_interval,
+                                                   response.revised_lifetime_count,
+                                                   response.revised_max_keep_alive_count,
+                                                   max_notifications_per_publish,
+                                                   priority);
+            response.subscription_id
         } else {
-            Err(Self::process_unexpected_response(response))
+            return Err(Self::process_unexpected_response(response));
+        };
+
+        if !items_to_create.is_empty() {
+            let request = CreateMonitoredItemsRequest {
+                request_header: self.make_request_header(),
+                subscription_id: new_subscription_id,
+                timestamps_to_return: TimestampsToReturn::Both,
+                items_to_create: Some(items_to_create.clone()),
+            };
+            let response = self.send_request(request)?;
+            if let SupportedMessage::CreateMonitoredItemsResponse(response) = response {
+                Self::process_service_result(&response.response_header)?;
+                let results = response.results.unwrap_or_else(Vec::new);
+                let items_to_create: Vec<subscription::CreateMonitoredItem> = items_to_create.iter().zip(results.iter())
+                    .filter(|&(i, r)| {
+                        if r.status_code.is_bad() {
+                            error!("Monitored item for node {:?} could not be created again, error {:?}", i.item_to_monitor.node_id, r.status_code);
+                        }
+                        r.status_code.is_good()
+                    })
+                    .map(|(i, r)| {
+                        subscription::CreateMonitoredItem {
+                            id: r.monitored_item_id,
+                            client_handle: i.requested_parameters.client_handle,
+                            item_to_monitor: i.item_to_monitor.clone(),
+                            queue_size: r.revised_queue_size,
+                            sampling_interval: r.revised_sampling_interval,
+                            monitoring_mode: i.monitoring_mode,
+                            discard_oldest: i.requested_parameters.discard_oldest,
+                            filter: i.requested_parameters.filter.clone(),
+                        }
+                    }).collect();
+                let mut subscription_state = trace_write_lock_unwrap!(self.subscription_state);
+                subscription_state.replace_monitored_items(new_subscription_id, items_to_create);
+            } else {
+                return Err(Self::process_unexpected_response(response));
+            }
+        }
+
+        // Link the triggering items to the items they trigger by their new ids
+        for (triggering_handle, linked_handles) in triggering_links {
+            let (triggering_item_id, links_to_add) = {
+                let subscription_state = trace_read_lock_unwrap!(self.subscription_state);
+                if let Some(subscription) = subscription_state.subscription(new_subscription_id) {
+                    let links_to_add: Vec<UInt32> = linked_handles.iter().filter_map(|h| subscription.monitored_item_id_from_handle(*h)).collect();
+                    (subscription.monitored_item_id_from_handle(triggering_handle), links_to_add)
+                } else {
+                    (None, Vec::new())
+                }
+            };
+            if let Some(triggering_item_id) = triggering_item_id {
+                if !links_to_add.is_empty() {
+                    match self.set_triggering(new_subscription_id, triggering_item_id, links_to_add, Vec::new()) {
+                        Ok((add_results, _)) => {
+                            add_results.iter().filter(|r| r.is_bad()).for_each(|r| {
+                                error!("Triggering link from monitored item {} could not be set again, error {:?}", triggering_item_id, r);
+                            });
+                        }
+                        Err(status_code) => {
+                            error!("Triggering links from monitored item {} could not be set again, error {:?}", triggering_item_id, status_code);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Sends a publish request containing any acknowledgements. The response is processed when it
+    // arrives, which may be after other requests have been sent and answered.
+    fn publish(&mut self, subscription_acknowledgements: Vec<SubscriptionAcknowledgement>) -> Result<UInt32, StatusCode> {
+        let mut request_header = self.make_request_header();
+        // The server holds on to a publish request until it has notifications or a keep alive to
+        // send so the request must wait at least that long
+        let keep_alive_timeout = {
+            let subscription_state = trace_read_lock_unwrap!(self.subscription_state);
+            subscription_state.keep_alive_timeout()
+        };
+        request_header.timeout_hint += keep_alive_timeout as UInt32;
+        let request = PublishRequest {
+            request_header,
+            subscription_acknowledgements: if subscription_acknowledgements.is_empty() { None } else { Some(subscription_acknowledgements) },
+        };
+        let subscription_state = self.subscription_state.clone();
+        self.async_send_request(request, Some(ResponseCallback::new(move |response| {
+            let response = response.and_then(|response| {
+                if let SupportedMessage::PublishResponse(response) = response {
+                    Self::process_service_result(&response.response_header)?;
+                    Ok(response)
+                } else {
+                    Err(Self::process_unexpected_response(response))
+                }
+            });
+            let mut subscription_state = trace_write_lock_unwrap!(subscription_state);
+            subscription_state.end_publish();
+            Self::process_publish_response(&mut subscription_state, response);
+        })))
+    }
+
+    /// Updates subscriptions with the notifications in a publish response
+    fn process_publish_response(subscription_state: &mut SubscriptionState, response: Result<PublishResponse, StatusCode>) {
+        match response {
+            Ok(response) => {
+                trace!("PublishResponse");
+                // Update subscriptions based on response
+
+                // Queue acknowledgements for next request
+                let notification_message = response.notification_message;
+                let subscription_id = response.subscription_id;
+
+                // Queue an acknowledgement for this request
+                subscription_state.add_acknowledgements(vec![SubscriptionAcknowledgement {
+                    subscription_id,
+                    sequence_number: notification_message.sequence_number,
+                }]);
+
+                // Process data change notifications
+                let data_change_notifications = notification_message.data_change_notifications();
+                if !data_change_notifications.is_empty() {
+                    subscription_state.subscription_data_change(subscription_id, data_change_notifications);
+                }
+
+                //pub available_sequence_numbers: Option<Vec<UInt32>>,
+                //pub more_notifications: Boolean,
+                //pub notification_message: NotificationMessage,
+                //pub results: Option<Vec<StatusCode>>,
+                //pub diagnostic_infos: Option<Vec<DiagnosticInfo>>,
+            }
+            Err(status_code) => {
+                // Terminate timer if
+                match status_code {
+                    StatusCode::BadSessionIdInvalid => {
+                        //   BadSessionIdInvalid
+                        trace!("Subscription timer received BadSessionIdInvalid error code");
+                    }
+                    StatusCode::BadNoSubscription => {
+                        //   BadNoSubscription
+                        trace!("Subscription timer received BadNoSubscription error code");
+                    }
+                    StatusCode::BadTooManyPublishRequests => {
+                        //   BadTooManyPublishRequests
+                        trace!("Subscription timer received BadTooManyPublishRequests error code");
+                    }
+                    _ => {
+                        trace!("Subscription timer received error code {:?}", status_code);
+                    }
+                }
+            }
         }
     }
 
@@ -830,7 +1438,7 @@ impl Session {
         self.transport.send_request(request)
     }
 
-    fn async_send_request<T>(&mut self, request: T) -> Result<UInt32, StatusCode> where T: Into<SupportedMessage> {
+    fn async_send_request<T>(&mut self, request: T, callback: Option<ResponseCallback>) -> Result<UInt32, StatusCode> where T: Into<SupportedMessage> {
         let request = request.into();
         match request {
             SupportedMessage::OpenSecureChannelRequest(_) | SupportedMessage::CloseSecureChannelRequest(_) => {}
@@ -840,7 +1448,7 @@ impl Session {
             }
         }
         // Send the request
-        self.transport.async_send_request(request)
+        self.transport.async_send_request(request, callback)
     }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////
@@ -979,69 +1587,25 @@ impl Session {
 
     /// Function that handles subscription
     pub fn subscription_timer(&mut self) {
-        let have_subscriptions = {
-            let subscription_state = trace_read_lock_unwrap!(self.subscription_state);
-            !subscription_state.is_empty()
+        // Send a publish request with any acknowledgements unless enough are already waiting
+        let subscription_acknowledgements = {
+            let mut subscription_state = trace_write_lock_unwrap!(self.subscription_state);
+            if subscription_state.is_empty() {
+                None
+            } else {
+                subscription_state.begin_publish(MAX_PUBLISH_REQUESTS)
+            }
         };
 
-        if have_subscriptions {
+        if let Some(subscription_acknowledgements) = subscription_acknowledgements {
             trace!("Subscription timer has subscriptions and is sending a publish");
-
-            // On timer, send a publish request with optional
-            //   Acknowledgements
-            let subscription_acknowledgements = self.subscription_acknowledgements.drain(..).collect();
-
-            // Receive response
-            trace!("Publish request");
-            match self.publish(subscription_acknowledgements) {
-                Ok(response) => {
-                    trace!("PublishResponse");
-                    // Update subscriptions based on response
-
-                    // Queue acknowledgements for next request
-                    let notification_message = response.notification_message;
-                    let subscription_id = response.subscription_id;
-
-                    // Queue an acknowledgement for this request
-                    self.subscription_acknowledgements.push(SubscriptionAcknowledgement {
-                        subscription_id,
-                        sequence_number: notification_message.sequence_number,
-                    });
-
-                    // Process data change notifications
-                    let data_change_notifications = notification_message.data_change_notifications();
-                    if !data_change_notifications.is_empty() {
-                        let mut subscription_state = trace_write_lock_unwrap!(self.subscription_state);
-                        subscription_state.subscription_data_change(subscription_id, data_change_notifications);
-                    }
-
-                    //pub available_sequence_numbers: Option<Vec<UInt32>>,
-                    //pub more_notifications: Boolean,
-                    //pub notification_message: NotificationMessage,
-                    //pub results: Option<Vec<StatusCode>>,
-                    //pub diagnostic_infos: Option<Vec<DiagnosticInfo>>,
-                }
-                Err(status_code) => {
-                    // Terminate timer if
-                    match status_code {
-                        StatusCode::BadSessionIdInvalid => {
-                            //   BadSessionIdInvalid
-                            trace!("Subscription timer received BadSessionIdInvalid error code");
-                        }
-                        StatusCode::BadNoSubscription => {
-                            //   BadNoSubscription
-                            trace!("Subscription timer received BadNoSubscription error code");
-                        }
-                        StatusCode::BadTooManyPublishRequests => {
-                            //   BadTooManyPublishRequests
-                            trace!("Subscription timer received BadTooManyPublishRequests error code");
-                        }
-                        _ => {
-                            trace!("Subscription timer received error code {:?}", status_code);
-                        }
-                    }
-                }
+            if let Err(status_code) = self.publish(subscription_acknowledgements.clone()) {
+                // The request was never sent so its acknowledgements go with the next one
+                let mut subscription_state = trace_write_lock_unwrap!(self.subscription_state);
+                subscription_state.end_publish();
+                subscription_state.add_acknowledgements(subscription_acknowledgements);
+                trace!("Subscription timer could not send a publish request, error code {:?}", status_code);
             }
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,106 @@
+use chrono;
+use chrono::{DateTime, Utc};
+
+use opcua_types::UInt32;
+
+/// Number of attempts a session makes to reconnect before it gives up
+pub const DEFAULT_SESSION_RETRY_LIMIT: UInt32 = 10;
+/// Interval in milliseconds before the first attempt to reconnect
+pub const DEFAULT_SESSION_RETRY_INTERVAL: UInt32 = 1000;
+/// Longest interval in milliseconds between attempts to reconnect
+pub const DEFAULT_SESSION_RETRY_MAX_INTERVAL: UInt32 = 30000;
+
+/// What a session should do about its broken connection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Answer {
+    /// Attempt to reconnect now
+    Retry,
+    /// Wait for the specified number of milliseconds before the next attempt
+    WaitFor(UInt32),
+    /// Stop trying to reconnect
+    GiveUp,
+}
+
+/// The policy a session follows to reconnect after its connection to the server breaks. The
+/// first attempt is made straight away and the interval between later attempts doubles after
+/// each failure up to a maximum.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionRetryPolicy {
+    /// Number of attempts to make before giving up, 0 to keep trying forever
+    retry_limit: UInt32,
+    /// Interval in milliseconds after the first failed attempt
+    retry_interval: UInt32,
+    /// Longest interval in milliseconds between attempts
+    max_retry_interval: UInt32,
+    /// Number of attempts that have failed since the connection broke
+    retry_count: UInt32,
+    /// When the last attempt failed
+    last_attempt: Option<DateTime<Utc>>,
+}
+
+impl Default for SessionRetryPolicy {
+    fn default() -> Self {
+        SessionRetryPolicy::new(DEFAULT_SESSION_RETRY_LIMIT, DEFAULT_SESSION_RETRY_INTERVAL, DEFAULT_SESSION_RETRY_MAX_INTERVAL)
+    }
+}
+
+impl SessionRetryPolicy {
+    pub fn new(retry_limit: UInt32, retry_interval: UInt32, max_retry_interval: UInt32) -> SessionRetryPolicy {
+        SessionRetryPolicy {
+            retry_limit,
+            retry_interval,
+            max_retry_interval: if max_retry_interval < retry_interval { retry_interval } else { max_retry_interval },
+            retry_count: 0,
+            last_attempt: None,
+        }
+    }
+
+    pub fn retry_limit(&self) -> UInt32 { self.retry_limit }
+
+    pub fn retry_count(&self) -> UInt32 { self.retry_count }
+
+    /// Returns the interval in milliseconds to wait after the last failed attempt
+    pub fn retry_interval(&self) -> UInt32 {
+        if self.retry_count == 0 {
+            0
+        } else {
+            // Double the interval for each failure after the first, taking care not to overflow
+            let mut interval = self.retry_interval;
+            for _ in 1..self.retry_count {
+                if interval >= self.max_retry_interval {
+                    break;
+                }
+                interval = interval.saturating_mul(2);
+            }
+            if interval > self.max_retry_interval { self.max_retry_interval } else { interval }
+        }
+    }
+
+    /// Decides whether the session should attempt to reconnect at the specified time
+    pub fn should_retry(&self, now: &DateTime<Utc>) -> Answer {
+        if self.retry_limit > 0 && self.retry_count >= self.retry_limit {
+            Answer::GiveUp
+        } else if let Some(ref last_attempt) = self.last_attempt {
+            let next_attempt = *last_attempt + chrono::Duration::milliseconds(self.retry_interval() as i64);
+            if *now >= next_attempt {
+                Answer::Retry
+            } else {
+                Answer::WaitFor(next_attempt.signed_duration_since(*now).num_milliseconds() as UInt32)
+            }
+        } else {
+            Answer::Retry
+        }
+    }
+
+    /// Records an attempt to reconnect that failed at the specified time
+    pub fn retry_failed(&mut self, now: &DateTime<Utc>) {
+        self.retry_count += 1;
+        self.last_attempt = Some(*now);
+    }
+
+    /// Clears the failed attempts once the session is connected again
+    pub fn reset(&mut self) {
+        self.retry_count = 0;
+        self.last_attempt = None;
+    }
+}
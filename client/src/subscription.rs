@@ -1,8 +1,8 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::marker::Sync;
 
 use opcua_types::*;
-use opcua_types::service_types::{DataChangeNotification, ReadValueId};
+use opcua_types::service_types::{DataChangeNotification, MonitoredItemCreateRequest, MonitoringParameters, ReadValueId};
 
 // This file will hold functionality related to creating a subscription and monitoring items
 
@@ -12,12 +12,17 @@ pub struct CreateMonitoredItem {
     pub item_to_monitor: ReadValueId,
     pub queue_size: UInt32,
     pub sampling_interval: Double,
+    pub monitoring_mode: MonitoringMode,
+    pub discard_oldest: Boolean,
+    pub filter: ExtensionObject,
 }
 
 pub struct ModifyMonitoredItem {
     pub id: UInt32,
     pub sampling_interval: Double,
     pub queue_size: UInt32,
+    pub discard_oldest: Boolean,
+    pub filter: ExtensionObject,
 }
 
 pub trait WritableMonitoredItem {
@@ -28,6 +33,12 @@ pub trait WritableMonitoredItem {
     fn set_sampling_interval(&mut self, value: Double);
 
     fn set_queue_size(&mut self, value: UInt32);
+
+    fn set_monitoring_mode(&mut self, monitoring_mode: MonitoringMode);
+
+    fn set_discard_oldest(&mut self, discard_oldest: Boolean);
+
+    fn set_filter(&mut self, filter: ExtensionObject);
 }
 
 #[derive(Debug)]
@@ -42,6 +53,14 @@ pub struct MonitoredItem {
     queue_size: UInt32,
     /// Sampling interval
     sampling_interval: Double,
+    /// Monitoring mode
+    monitoring_mode: MonitoringMode,
+    /// Whether the oldest value is discarded when the queue is full
+    discard_oldest: Boolean,
+    /// Filter, or a null extension object for none
+    filter: ExtensionObject,
+    /// Ids of the monitored items that this item triggers
+    triggered_items: BTreeSet<UInt32>,
     /// Last value of the item
     value: DataValue,
 }
@@ -60,6 +79,18 @@ impl WritableMonitoredItem for MonitoredItem {
     fn set_queue_size(&mut self, value: UInt32) {
         self.queue_size = value;
     }
+
+    fn set_monitoring_mode(&mut self, monitoring_mode: MonitoringMode) {
+        self.monitoring_mode = monitoring_mode;
+    }
+
+    fn set_discard_oldest(&mut self, discard_oldest: Boolean) {
+        self.discard_oldest = discard_oldest;
+    }
+
+    fn set_filter(&mut self, filter: ExtensionObject) {
+        self.filter = filter;
+    }
 }
 
 impl MonitoredItem {
@@ -68,6 +99,10 @@ impl MonitoredItem {
             id: 0,
             queue_size: 0,
             sampling_interval: 0.0,
+            monitoring_mode: MonitoringMode::Reporting,
+            discard_oldest: true,
+            filter: ExtensionObject::null(),
+            triggered_items: BTreeSet::new(),
             item_to_monitor: ReadValueId {
                 node_id: NodeId::null(),
                 attribute_id: 0,
@@ -97,6 +132,22 @@ impl MonitoredItem {
         self.queue_size
     }
 
+    pub fn monitoring_mode(&self) -> MonitoringMode {
+        self.monitoring_mode
+    }
+
+    pub fn discard_oldest(&self) -> Boolean {
+        self.discard_oldest
+    }
+
+    pub fn filter(&self) -> ExtensionObject {
+        self.filter.clone()
+    }
+
+    pub fn triggered_items(&self) -> &BTreeSet<UInt32> {
+        &self.triggered_items
+    }
+
     pub fn value(&self) -> DataValue {
         self.value.clone()
     }
@@ -164,30 +215,34 @@ impl Subscription {
 
     pub fn subscription_id(&self) -> UInt32 { self.subscription_id }
 
-    // pub fn publishing_interval(&self) -> Double { self.publishing_interval }
+    pub fn set_subscription_id(&mut self, subscription_id: UInt32) { self.subscription_id = subscription_id; }
+
+    pub fn publishing_interval(&self) -> Double { self.publishing_interval }
 
     pub fn set_publishing_interval(&mut self, publishing_interval: Double) { self.publishing_interval = publishing_interval; }
 
-    // pub fn lifetime_count(&self) -> UInt32 { self.lifetime_count }
+    pub fn lifetime_count(&self) -> UInt32 { self.lifetime_count }
 
     pub fn set_lifetime_count(&mut self, lifetime_count: UInt32) { self.lifetime_count = lifetime_count; }
 
-    // pub fn max_keep_alive_count(&self) -> UInt32 { self.max_keep_alive_count }
+    pub fn max_keep_alive_count(&self) -> UInt32 { self.max_keep_alive_count }
 
     pub fn set_max_keep_alive_count(&mut self, max_keep_alive_count: UInt32) { self.max_keep_alive_count = max_keep_alive_count; }
 
-    // pub fn max_notifications_per_publish(&self) -> UInt32 { self.max_notifications_per_publish }
+    pub fn max_notifications_per_publish(&self) -> UInt32 { self.max_notifications_per_publish }
 
     pub fn set_max_notifications_per_publish(&mut self, max_notifications_per_publish: UInt32) { self.max_notifications_per_publish = max_notifications_per_publish; }
 
-    // pub fn priority(&self) -> Byte { self.priority }
+    pub fn priority(&self) -> Byte { self.priority }
 
     pub fn set_priority(&mut self, priority: Byte) { self.priority = priority; }
 
-    // pub fn publishing_enabled(&self) -> Boolean { self.publishing_enabled }
+    pub fn publishing_enabled(&self) -> Boolean { self.publishing_enabled }
 
     pub fn set_publishing_enabled(&mut self, publishing_enabled: Boolean) { self.publishing_enabled = publishing_enabled; }
 
+    pub fn monitored_items(&self) -> &HashMap<UInt32, MonitoredItem> { &self.monitored_items }
+
     pub fn insert_monitored_items(&mut self, items_to_create: Vec<CreateMonitoredItem>) {
         items_to_create.iter().for_each(|i| {
             let mut monitored_item = MonitoredItem::new(i.client_handle);
@@ -195,6 +250,9 @@ impl Subscription {
             monitored_item.set_sampling_interval(i.sampling_interval);
             monitored_item.set_queue_size(i.queue_size);
             monitored_item.set_item_to_monitor(i.item_to_monitor.clone());
+            monitored_item.set_monitoring_mode(i.monitoring_mode);
+            monitored_item.set_discard_oldest(i.discard_oldest);
+            monitored_item.set_filter(i.filter.clone());
 
             let client_handle = monitored_item.client_handle();
             let monitored_item_id = monitored_item.id();
@@ -208,6 +266,8 @@ impl Subscription {
             if let Some(ref mut monitored_item) = self.monitored_items.get_mut(&i.id) {
                 monitored_item.set_sampling_interval(i.sampling_interval);
                 monitored_item.set_queue_size(i.queue_size);
+                monitored_item.set_discard_oldest(i.discard_oldest);
+                monitored_item.set_filter(i.filter);
             }
         });
     }
@@ -218,10 +278,67 @@ impl Subscription {
             if let Some(monitored_item) = self.monitored_items.remove(&id) {
                 let _ = self.client_handles.remove(&monitored_item.client_handle());
             }
-        })
+        });
+        // Items no longer trigger the deleted items
+        self.monitored_items.values_mut().for_each(|monitored_item| {
+            monitored_item.triggered_items.retain(|id| !items_to_delete.contains(id));
+        });
+    }
+
+    pub fn set_monitoring_mode(&mut self, monitoring_mode: MonitoringMode, monitored_item_ids: Vec<UInt32>) {
+        monitored_item_ids.iter().for_each(|id| {
+            if let Some(ref mut monitored_item) = self.monitored_items.get_mut(id) {
+                monitored_item.set_monitoring_mode(monitoring_mode);
+            }
+        });
+    }
+
+    /// Adds and removes the links from the triggering item to the items it triggers
+    pub fn set_triggering(&mut self, triggering_item_id: UInt32, links_to_add: Vec<UInt32>, links_to_remove: Vec<UInt32>) {
+        if let Some(ref mut monitored_item) = self.monitored_items.get_mut(&triggering_item_id) {
+            links_to_remove.iter().for_each(|id| {
+                monitored_item.triggered_items.remove(id);
+            });
+            monitored_item.triggered_items.extend(links_to_add);
+        }
+    }
+
+    /// Returns the requests that create the monitored items again with the parameters they were
+    /// last given, e.g. after the subscription is lost along with its session
+    pub fn monitored_item_create_requests(&self) -> Vec<MonitoredItemCreateRequest> {
+        self.monitored_items.values().map(|i| {
+            MonitoredItemCreateRequest::new(i.item_to_monitor(), i.monitoring_mode(), MonitoringParameters {
+                client_handle: i.client_handle(),
+                sampling_interval: i.sampling_interval(),
+                filter: i.filter(),
+                queue_size: i.queue_size(),
+                discard_oldest: i.discard_oldest(),
+            })
+        }).collect()
+    }
+
+    /// Returns the links from each triggering item to the items it triggers by their client
+    /// handles, which stay the same when the items are created again with new ids
+    pub fn triggering_links(&self) -> Vec<(UInt32, Vec<UInt32>)> {
+        self.monitored_items.values()
+            .filter(|i| !i.triggered_items.is_empty())
+            .map(|i| {
+                let linked_handles = i.triggered_items.iter()
+                    .filter_map(|id| self.monitored_items.get(id))
+                    .map(|linked_item| linked_item.client_handle())
+                    .collect();
+                (i.client_handle(), linked_handles)
+            }).collect()
+    }
+
+    /// Replaces all the monitored items, e.g. after the server has created them again with new ids
+    pub fn replace_monitored_items(&mut self, items_to_create: Vec<CreateMonitoredItem>) {
+        self.monitored_items.clear();
+        self.client_handles.clear();
+        self.insert_monitored_items(items_to_create);
     }
 
-    fn monitored_item_id_from_handle(&self, client_handle: UInt32) -> Option<UInt32> {
+    pub fn monitored_item_id_from_handle(&self, client_handle: UInt32) -> Option<UInt32> {
         if let Some(monitored_item_id) = self.client_handles.get(&client_handle) {
             Some(*monitored_item_id)
         } else {
@@ -1,19 +1,25 @@
 use std::collections::HashMap;
 
-use opcua_types::{Boolean, Byte, Double, UInt32};
-use opcua_types::service_types::DataChangeNotification;
+use opcua_types::{Boolean, Byte, Double, MonitoringMode, UInt32};
+use opcua_types::service_types::{DataChangeNotification, SubscriptionAcknowledgement};
 
 use subscription::*;
 
 pub struct SubscriptionState {
     /// Subscriptions (key = subscription_id)
     subscriptions: HashMap<UInt32, Subscription>,
+    /// Acknowledgements to send with the next publish request
+    acknowledgements: Vec<SubscriptionAcknowledgement>,
+    /// Number of publish requests waiting for a response
+    publish_requests_in_flight: usize,
 }
 
 impl SubscriptionState {
     pub fn new() -> SubscriptionState {
         SubscriptionState {
             subscriptions: HashMap::new(),
+            acknowledgements: Vec::new(),
+            publish_requests_in_flight: 0,
         }
     }
 
@@ -27,10 +33,56 @@ impl SubscriptionState {
         }
     }
 
+    /// Returns the longest time in milliseconds that the server may wait before responding to a
+    /// publish request with a keep alive
+    pub fn keep_alive_timeout(&self) -> Double {
+        self.subscriptions.values().fold(0f64, |timeout, subscription| {
+            let keep_alive_timeout = subscription.publishing_interval() * subscription.max_keep_alive_count() as Double;
+            if keep_alive_timeout > timeout { keep_alive_timeout } else { timeout }
+        })
+    }
+
+    /// Starts a publish request if there are fewer than the maximum in flight, returning the
+    /// acknowledgements it should send
+    pub fn begin_publish(&mut self, max_publish_requests: usize) -> Option<Vec<SubscriptionAcknowledgement>> {
+        if self.publish_requests_in_flight >= max_publish_requests {
+            None
+        } else {
+            self.publish_requests_in_flight += 1;
+            Some(self.acknowledgements.drain(..).collect())
+        }
+    }
+
+    /// Ends a publish request when its response arrives or it fails
+    pub fn end_publish(&mut self) {
+        if self.publish_requests_in_flight > 0 {
+            self.publish_requests_in_flight -= 1;
+        }
+    }
+
+    /// Queues acknowledgements for the next publish request
+    pub fn add_acknowledgements(&mut self, acknowledgements: Vec<SubscriptionAcknowledgement>) {
+        self.acknowledgements.extend(acknowledgements);
+    }
+
     pub fn subscription_exists(&self, subscription_id: UInt32) -> bool {
         self.subscriptions.contains_key(&subscription_id)
     }
 
+    pub fn subscription(&self, subscription_id: UInt32) -> Option<&Subscription> {
+        self.subscriptions.get(&subscription_id)
+    }
+
+    /// Moves a subscription to the new id the server gave it when it was created again. Any
+    /// acknowledgements for the old id are dropped since the server no longer knows it.
+    pub fn change_subscription_id(&mut self, subscription_id: UInt32, new_subscription_id: UInt32) {
+        if let Some(mut subscription) = self.subscriptions.remove(&subscription_id) {
+            subscription.set_subscription_id(new_subscription_id);
+            self.subscriptions.insert(new_subscription_id, subscription);
+        }
+        self.acknowledgements.retain(|a| a.subscription_id != subscription_id);
+    }
+
     pub fn add_subscription(&mut self, subscription: Subscription) {
         self.subscriptions.insert(subscription.subscription_id(), subscription);
     }
@@ -73,6 +125,12 @@ impl SubscriptionState {
         }
     }
 
+    pub fn replace_monitored_items(&mut self, subscription_id: UInt32, items_to_create: Vec<CreateMonitoredItem>) {
+        if let Some(ref mut subscription) = self.subscriptions.get_mut(&subscription_id) {
+            subscription.replace_monitored_items(items_to_create);
+        }
+    }
+
     pub fn modify_monitored_items(&mut self, subscription_id: UInt32, items_to_modify: Vec<ModifyMonitoredItem>) {
         if let Some(ref mut subscription) = self.subscriptions.get_mut(&subscription_id) {
             subscription.modify_monitored_items(items_to_modify);
@@ -84,4 +142,16 @@ impl SubscriptionState {
             subscription.delete_monitored_items(items_to_delete);
         }
     }
+
+    pub fn set_monitoring_mode(&mut self, subscription_id: UInt32, monitoring_mode: MonitoringMode, monitored_item_ids: Vec<UInt32>) {
+        if let Some(ref mut subscription) = self.subscriptions.get_mut(&subscription_id) {
+            subscription.set_monitoring_mode(monitoring_mode, monitored_item_ids);
+        }
+    }
+
+    pub fn set_triggering(&mut self, subscription_id: UInt32, triggering_item_id: UInt32, links_to_add: Vec<UInt32>, links_to_remove: Vec<UInt32>) {
+        if let Some(ref mut subscription) = self.subscriptions.get_mut(&subscription_id) {
+            subscription.set_triggering(triggering_item_id, links_to_add, links_to_remove);
+        }
+    }
 }
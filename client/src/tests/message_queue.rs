@@ -0,0 +1,79 @@
+use std::sync::{Arc, Mutex};
+
+use chrono;
+
+use opcua_types::*;
+use opcua_types::service_types::*;
+use opcua_types::status_codes::StatusCode;
+use opcua_types::status_codes::StatusCode::*;
+
+use comms::message_queue::{MessageQueue, ResponseCallback};
+
+type Responses = Arc<Mutex<Vec<(UInt32, Result<UInt32, StatusCode>)>>>;
+
+/// Makes a callback that records the request handle of each response it gets
+fn recorder(responses: &Responses, request_id: UInt32) -> ResponseCallback {
+    let responses = responses.clone();
+    ResponseCallback::new(move |response| {
+        let response = response.map(|response| response.response_header().unwrap().request_handle);
+        responses.lock().unwrap().push((request_id, response));
+    })
+}
+
+fn read_response(request_handle: UInt32) -> SupportedMessage {
+    ReadResponse {
+        response_header: ResponseHeader::new_good(&RequestHeader::new(&NodeId::null(), &DateTime::now(), request_handle)),
+        results: None,
+        diagnostic_infos: None,
+    }.into()
+}
+
+#[test]
+fn message_queue_routes_responses() {
+    let responses: Responses = Arc::new(Mutex::new(Vec::new()));
+    let mut message_queue = MessageQueue::new();
+    message_queue.add_request(1001, 10, 5000, recorder(&responses, 1001));
+    message_queue.add_request(1002, 11, 5000, recorder(&responses, 1002));
+    message_queue.add_request(1003, 12, 5000, recorder(&responses, 1003));
+
+    // Responses arrive in a different order to their requests
+    message_queue.take_request(1002).unwrap().complete(Ok(read_response(11)));
+    message_queue.take_request(1001).unwrap().complete(Ok(read_response(10)));
+    assert!(message_queue.take_request(1001).is_none());
+
+    // A response whose request handle doesn't match its request is not passed on
+    message_queue.take_request(1003).unwrap().complete(Ok(read_response(99)));
+    assert!(message_queue.is_empty());
+
+    let responses = responses.lock().unwrap();
+    assert_eq!(*responses, vec![(1002, Ok(11)), (1001, Ok(10)), (1003, Err(BadUnknownResponse))]);
+}
+
+#[test]
+fn message_queue_expires_requests() {
+    let responses: Responses = Arc::new(Mutex::new(Vec::new()));
+    let mut message_queue = MessageQueue::new();
+    message_queue.add_request(1001, 10, 1000, recorder(&responses, 1001));
+    message_queue.add_request(1002, 11, 5000, recorder(&responses, 1002));
+    // A timeout hint of 0 never expires
+    message_queue.add_request(1003, 12, 0, recorder(&responses, 1003));
+
+    let now = chrono::Utc::now();
+    assert!(message_queue.take_expired_requests(&now).is_empty());
+
+    let expired = message_queue.take_expired_requests(&(now + chrono::Duration::milliseconds(2000)));
+    assert_eq!(expired.len(), 1);
+    assert_eq!(expired[0].0, 1001);
+
+    let expired = message_queue.take_expired_requests(&(now + chrono::Duration::days(1)));
+    assert_eq!(expired.len(), 1);
+    assert_eq!(expired[0].0, 1002);
+
+    // Closing the connection fails the rest
+    for request in message_queue.take_all_requests() {
+        request.complete(Err(BadConnectionClosed));
+    }
+    assert!(message_queue.is_empty());
+    let responses = responses.lock().unwrap();
+    assert_eq!(*responses, vec![(1003, Err(BadConnectionClosed))]);
+}
@@ -8,6 +8,13 @@ use opcua_types::MessageSecurityMode;
 use opcua_core::crypto::SecurityPolicy;
 
 use config::{ClientConfig, ClientEndpoint, ClientUserToken, ANONYMOUS_USER_TOKEN_ID};
+use session_retry::{DEFAULT_SESSION_RETRY_INTERVAL, DEFAULT_SESSION_RETRY_LIMIT, DEFAULT_SESSION_RETRY_MAX_INTERVAL};
+use session::{DEFAULT_MAX_MESSAGE_SIZE, DEFAULT_MAX_CHUNK_COUNT};
+
+mod message_queue;
+mod session_retry;
+mod subscription;
+mod tcp_transport;
 
 fn make_test_file(filename: &str) -> PathBuf {
     let mut path = std::env::temp_dir();
@@ -63,7 +70,12 @@ pub fn default_sample_config() -> ClientConfig {
         preferred_locales: Vec::new(),
         default_endpoint: "sample_none".to_string(),
         endpoints,
-        user_tokens
+        user_tokens,
+        session_retry_limit: DEFAULT_SESSION_RETRY_LIMIT,
+        session_retry_interval: DEFAULT_SESSION_RETRY_INTERVAL,
+        session_retry_max_interval: DEFAULT_SESSION_RETRY_MAX_INTERVAL,
+        max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        max_chunk_count: DEFAULT_MAX_CHUNK_COUNT,
     }
 }
 
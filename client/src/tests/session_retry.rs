@@ -0,0 +1,47 @@
+use chrono;
+
+use session_retry::{Answer, SessionRetryPolicy};
+
+#[test]
+fn session_retry_backs_off() {
+    let mut policy = SessionRetryPolicy::new(0, 1000, 5000);
+    let now = chrono::Utc::now();
+
+    // The first attempt is immediate
+    assert_eq!(policy.should_retry(&now), Answer::Retry);
+    assert_eq!(policy.retry_interval(), 0);
+
+    // The interval doubles after each failure until it reaches the maximum
+    let expected_intervals = [1000, 2000, 4000, 5000, 5000];
+    for expected_interval in expected_intervals.iter() {
+        policy.retry_failed(&now);
+        assert_eq!(policy.retry_interval(), *expected_interval);
+        assert_eq!(policy.should_retry(&now), Answer::WaitFor(*expected_interval));
+        assert_eq!(policy.should_retry(&(now + chrono::Duration::milliseconds(*expected_interval as i64 - 1))), Answer::WaitFor(1));
+        assert_eq!(policy.should_retry(&(now + chrono::Duration::milliseconds(*expected_interval as i64))), Answer::Retry);
+    }
+
+    // Success starts over
+    policy.reset();
+    assert_eq!(policy.retry_count(), 0);
+    assert_eq!(policy.should_retry(&now), Answer::Retry);
+}
+
+#[test]
+fn session_retry_gives_up() {
+    let mut policy = SessionRetryPolicy::new(3, 100, 100);
+    let mut now = chrono::Utc::now();
+    for _ in 0..3 {
+        assert_eq!(policy.should_retry(&now), Answer::Retry);
+        policy.retry_failed(&now);
+        now = now + chrono::Duration::milliseconds(100);
+    }
+    assert_eq!(policy.should_retry(&now), Answer::GiveUp);
+
+    // A limit of 0 never gives up
+    let mut policy = SessionRetryPolicy::new(0, 100, 100);
+    for _ in 0..1000 {
+        policy.retry_failed(&now);
+    }
+    assert_eq!(policy.should_retry(&(now + chrono::Duration::milliseconds(100))), Answer::Retry);
+}
@@ -0,0 +1,126 @@
+use opcua_types::*;
+use opcua_types::node_ids::ObjectId;
+use opcua_types::service_types::*;
+
+use subscription::{CreateMonitoredItem, DataChangeCallback, Subscription};
+use subscription_state::SubscriptionState;
+
+fn make_filter() -> ExtensionObject {
+    ExtensionObject::from_encodable(ObjectId::DataChangeFilter_Encoding_DefaultBinary, DataChangeFilter {
+        trigger: DataChangeTrigger::StatusValueTimestamp,
+        deadband_type: 0,
+        deadband_value: 0f64,
+    })
+}
+
+fn make_item(id: UInt32, client_handle: UInt32, monitoring_mode: MonitoringMode, filter: ExtensionObject) -> CreateMonitoredItem {
+    CreateMonitoredItem {
+        id,
+        client_handle,
+        item_to_monitor: ReadValueId {
+            node_id: NodeId::new(2, client_handle),
+            attribute_id: AttributeId::Value as UInt32,
+            index_range: UAString::null(),
+            data_encoding: QualifiedName::null(),
+        },
+        queue_size: 5,
+        sampling_interval: 100f64,
+        monitoring_mode,
+        discard_oldest: false,
+        filter,
+    }
+}
+
+/// Creates the items again from their requests with new ids, as the session does when it
+/// restores a subscription
+fn recreate_items(subscription_state: &mut SubscriptionState, subscription_id: UInt32, new_subscription_id: UInt32) {
+    let (items_to_create, triggering_links) = {
+        let subscription = subscription_state.subscription(subscription_id).unwrap();
+        (subscription.monitored_item_create_requests(), subscription.triggering_links())
+    };
+    subscription_state.change_subscription_id(subscription_id, new_subscription_id);
+    let items_to_create = items_to_create.iter().map(|i| {
+        let mut item = make_item(i.requested_parameters.client_handle + 100, i.requested_parameters.client_handle, i.monitoring_mode, i.requested_parameters.filter.clone());
+        item.item_to_monitor = i.item_to_monitor.clone();
+        item.queue_size = i.requested_parameters.queue_size;
+        item.sampling_interval = i.requested_parameters.sampling_interval;
+        item.discard_oldest = i.requested_parameters.discard_oldest;
+        item
+    }).collect();
+    subscription_state.replace_monitored_items(new_subscription_id, items_to_create);
+    for (triggering_handle, linked_handles) in triggering_links {
+        let (triggering_item_id, links_to_add) = {
+            let subscription = subscription_state.subscription(new_subscription_id).unwrap();
+            let links_to_add: Vec<UInt32> = linked_handles.iter().filter_map(|h| subscription.monitored_item_id_from_handle(*h)).collect();
+            (subscription.monitored_item_id_from_handle(triggering_handle).unwrap(), links_to_add)
+        };
+        subscription_state.set_triggering(new_subscription_id, triggering_item_id, links_to_add, Vec::new());
+    }
+}
+
+#[test]
+fn restore_monitored_items() {
+    let mut subscription_state = SubscriptionState::new();
+    subscription_state.add_subscription(Subscription::new(1, 100f64, 30, 10, 0, true, 0, DataChangeCallback::new(|_| {})));
+
+    // Item 1 reports and triggers items 2 and 3 which only sample, item 4 is disabled
+    subscription_state.insert_monitored_items(1, vec![
+        make_item(1, 11, MonitoringMode::Reporting, make_filter()),
+        make_item(2, 12, MonitoringMode::Reporting, ExtensionObject::null()),
+        make_item(3, 13, MonitoringMode::Sampling, ExtensionObject::null()),
+        make_item(4, 14, MonitoringMode::Reporting, ExtensionObject::null()),
+    ]);
+    subscription_state.set_monitoring_mode(1, MonitoringMode::Sampling, vec![2]);
+    subscription_state.set_monitoring_mode(1, MonitoringMode::Disabled, vec![4]);
+    subscription_state.set_triggering(1, 1, vec![2, 3, 4], Vec::new());
+    subscription_state.set_triggering(1, 1, Vec::new(), vec![4]);
+
+    // The requests to create the items again carry their modes, filters and other parameters
+    {
+        let subscription = subscription_state.subscription(1).unwrap();
+        let mut items_to_create = subscription.monitored_item_create_requests();
+        items_to_create.sort_by_key(|i| i.requested_parameters.client_handle);
+        let modes: Vec<MonitoringMode> = items_to_create.iter().map(|i| i.monitoring_mode).collect();
+        assert_eq!(modes, vec![MonitoringMode::Reporting, MonitoringMode::Sampling, MonitoringMode::Sampling, MonitoringMode::Disabled]);
+        assert_eq!(items_to_create[0].requested_parameters.filter, make_filter());
+        assert!(items_to_create[1].requested_parameters.filter.is_null());
+        assert_eq!(items_to_create[0].requested_parameters.queue_size, 5);
+        assert_eq!(items_to_create[0].requested_parameters.sampling_interval, 100f64);
+        assert!(!items_to_create[0].requested_parameters.discard_oldest);
+        assert_eq!(items_to_create[0].item_to_monitor.node_id, NodeId::new(2, 11));
+
+        let mut triggering_links = subscription.triggering_links();
+        assert_eq!(triggering_links.len(), 1);
+        triggering_links[0].1.sort();
+        assert_eq!(triggering_links[0], (11, vec![12, 13]));
+    }
+
+    // The items created again under a new subscription id keep their parameters and links
+    recreate_items(&mut subscription_state, 1, 7);
+    assert!(!subscription_state.subscription_exists(1));
+    let subscription = subscription_state.subscription(7).unwrap();
+    assert_eq!(subscription.monitored_items().len(), 4);
+    let triggering_item = subscription.monitored_items().get(&111).unwrap();
+    assert_eq!(triggering_item.client_handle(), 11);
+    assert_eq!(triggering_item.filter(), make_filter());
+    assert_eq!(triggering_item.triggered_items().iter().cloned().collect::<Vec<UInt32>>(), vec![112, 113]);
+    assert_eq!(subscription.monitored_items().get(&112).unwrap().monitoring_mode(), MonitoringMode::Sampling);
+    assert_eq!(subscription.monitored_items().get(&114).unwrap().monitoring_mode(), MonitoringMode::Disabled);
+    assert!(!subscription.monitored_items().get(&114).unwrap().discard_oldest());
+}
+
+#[test]
+fn delete_triggered_items() {
+    let mut subscription = Subscription::new(1, 100f64, 30, 10, 0, true, 0, DataChangeCallback::new(|_| {}));
+    subscription.insert_monitored_items(vec![
+        make_item(1, 11, MonitoringMode::Reporting, ExtensionObject::null()),
+        make_item(2, 12, MonitoringMode::Sampling, ExtensionObject::null()),
+    ]);
+    subscription.set_triggering(1, vec![2], Vec::new());
+    assert_eq!(subscription.triggering_links(), vec![(11, vec![12])]);
+
+    // Deleting a triggered item removes the links to it
+    subscription.delete_monitored_items(vec![2]);
+    assert!(subscription.monitored_items().get(&1).unwrap().triggered_items().is_empty());
+    assert!(subscription.triggering_links().is_empty());
+}
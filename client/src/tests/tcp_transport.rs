@@ -0,0 +1,171 @@
+use std::io::Cursor;
+use std::sync::{Arc, Mutex, RwLock};
+
+use opcua_types::*;
+use opcua_types::service_types::*;
+use opcua_types::status_codes::StatusCode;
+use opcua_types::status_codes::StatusCode::*;
+use opcua_core::prelude::*;
+
+use comms::message_queue::{MessageQueue, ResponseCallback};
+use comms::tcp_transport::ResponseReader;
+use session::SessionState;
+
+type Responses = Arc<Mutex<Vec<Result<UInt32, StatusCode>>>>;
+
+const RECEIVE_BUFFER_SIZE: usize = 65536;
+/// Chunks are small so that a response is split into several of them
+const CHUNK_SIZE: usize = 1024;
+
+struct TestReader {
+    reader: ResponseReader,
+    session_state: Arc<RwLock<SessionState>>,
+    message_queue: Arc<Mutex<MessageQueue>>,
+    secure_channel: SecureChannel,
+    responses: Responses,
+    sequence_number: UInt32,
+}
+
+impl TestReader {
+    fn new() -> TestReader {
+        let session_state = Arc::new(RwLock::new(SessionState::new()));
+        let message_queue = Arc::new(Mutex::new(MessageQueue::new()));
+        let reader_secure_channel: SecureChannel = (SecurityPolicy::None, MessageSecurityMode::None).into();
+        let reader = ResponseReader::new(session_state.clone(), Arc::new(RwLock::new(reader_secure_channel)), message_queue.clone(), RECEIVE_BUFFER_SIZE);
+        TestReader {
+            reader,
+            session_state,
+            message_queue,
+            secure_channel: (SecurityPolicy::None, MessageSecurityMode::None).into(),
+            responses: Arc::new(Mutex::new(Vec::new())),
+            sequence_number: 1,
+        }
+    }
+
+    /// Waits for a response to the request, recording the number of values in it
+    fn add_request(&self, request_id: UInt32, request_handle: UInt32) {
+        let responses = self.responses.clone();
+        let callback = ResponseCallback::new(move |response| {
+            let response = response.map(|response| {
+                if let SupportedMessage::ReadResponse(response) = response {
+                    response.results.map_or(0, |results| results.len() as UInt32)
+                } else {
+                    panic!("Expected a read response");
+                }
+            });
+            responses.lock().unwrap().push(response);
+        });
+        let mut message_queue = self.message_queue.lock().unwrap();
+        message_queue.add_request(request_id, request_handle, 0, callback);
+    }
+
+    /// Splits a response with the number of values into chunks
+    fn response_chunks(&mut self, request_id: UInt32, request_handle: UInt32, value_count: usize) -> Vec<MessageChunk> {
+        let response: SupportedMessage = ReadResponse {
+            response_header: ResponseHeader::new_good(&RequestHeader::new(&NodeId::null(), &DateTime::now(), request_handle)),
+            results: Some((0..value_count).map(|i| DataValue::new(i as Int32)).collect()),
+            diagnostic_infos: None,
+        }.into();
+        let chunks = Chunker::encode(self.sequence_number, request_id, 0, CHUNK_SIZE, &self.secure_channel, &response).unwrap();
+        self.sequence_number += chunks.len() as UInt32;
+        chunks
+    }
+
+    /// Makes a chunk that aborts the message of the request
+    fn abort_chunk(&mut self, request_id: UInt32, error: StatusCode, reason: &str) -> MessageChunk {
+        let mut stream = Cursor::new(Vec::new());
+        let _ = (error as UInt32).encode(&mut stream);
+        let _ = UAString::from(reason).encode(&mut stream);
+        let chunk = MessageChunk::new(self.sequence_number, request_id, MessageChunkType::Message, MessageIsFinalType::FinalError, &self.secure_channel, &stream.into_inner()).unwrap();
+        self.sequence_number += 1;
+        chunk
+    }
+
+    fn receive(&mut self, chunks: &[MessageChunk]) -> Result<(), StatusCode> {
+        for chunk in chunks {
+            self.reader.process_bytes(&chunk.data)?;
+        }
+        Ok(())
+    }
+
+    fn take_responses(&self) -> Vec<Result<UInt32, StatusCode>> {
+        self.responses.lock().unwrap().drain(..).collect()
+    }
+}
+
+#[test]
+fn response_reader_reassembles_chunks() {
+    let mut test = TestReader::new();
+    test.add_request(1001, 10);
+    test.add_request(1002, 11);
+
+    let chunks1 = test.response_chunks(1001, 10, 500);
+    let chunks2 = test.response_chunks(1002, 11, 300);
+    assert!(chunks1.len() > 2);
+    assert!(chunks2.len() > 2);
+
+    // Nothing is passed on until the final chunk of a message arrives
+    assert!(test.receive(&chunks1[..chunks1.len() - 1]).is_ok());
+    assert!(test.take_responses().is_empty());
+    assert!(test.receive(&chunks1[chunks1.len() - 1..]).is_ok());
+    assert_eq!(test.take_responses(), vec![Ok(500)]);
+
+    // Bytes that hold several chunks, or split one, are reassembled too
+    let bytes: Vec<u8> = chunks2.iter().flat_map(|chunk| chunk.data.iter().cloned()).collect();
+    let (first, rest) = bytes.split_at(CHUNK_SIZE / 2 + 3);
+    assert!(test.reader.process_bytes(first).is_ok());
+    assert!(test.reader.process_bytes(rest).is_ok());
+    assert_eq!(test.take_responses(), vec![Ok(300)]);
+    assert!(test.message_queue.lock().unwrap().is_empty());
+}
+
+#[test]
+fn response_reader_abort_chunk() {
+    let mut test = TestReader::new();
+    test.add_request(1001, 10);
+    test.add_request(1002, 11);
+
+    // The server gives up on a response part way through, which fails just its request
+    let chunks = test.response_chunks(1001, 10, 500);
+    assert!(test.receive(&chunks[..2]).is_ok());
+    let abort = test.abort_chunk(1001, BadResponseTooLarge, "Too large");
+    assert!(test.receive(&[abort]).is_ok());
+    assert_eq!(test.take_responses(), vec![Err(BadResponseTooLarge)]);
+
+    // Later responses are unaffected
+    let chunks = test.response_chunks(1002, 11, 100);
+    assert!(test.receive(&chunks).is_ok());
+    assert_eq!(test.take_responses(), vec![Ok(100)]);
+}
+
+#[test]
+fn response_reader_limits() {
+    // A response in more chunks than the session allows is failed
+    let mut test = TestReader::new();
+    test.session_state.write().unwrap().max_chunk_count = 2;
+    test.add_request(1001, 10);
+    let chunks = test.response_chunks(1001, 10, 500);
+    assert!(chunks.len() > 2);
+    assert!(test.receive(&chunks[..2]).is_ok());
+    assert_eq!(test.take_responses(), vec![Err(BadResponseTooLarge)]);
+
+    // As is a response larger than the session allows
+    let mut test = TestReader::new();
+    test.session_state.write().unwrap().max_message_size = CHUNK_SIZE * 2;
+    test.add_request(1001, 10);
+    let chunks = test.response_chunks(1001, 10, 500);
+    assert!(chunks.len() > 3);
+    assert!(test.receive(&chunks[..2]).is_ok());
+    assert!(test.take_responses().is_empty());
+    assert!(test.receive(&chunks[2..3]).is_ok());
+    assert_eq!(test.take_responses(), vec![Err(BadResponseTooLarge)]);
+
+    // Responses within the limits are fine, and the default allows multi megabyte responses
+    let mut test = TestReader::new();
+    assert_eq!(test.session_state.read().unwrap().max_message_size, ::session::DEFAULT_MAX_MESSAGE_SIZE);
+    assert!(::session::DEFAULT_MAX_MESSAGE_SIZE >= 1024 * 1024);
+    test.add_request(1001, 10);
+    let chunks = test.response_chunks(1001, 10, 500);
+    assert!(test.receive(&chunks).is_ok());
+    assert_eq!(test.take_responses(), vec![Ok(500)]);
+}
@@ -3,7 +3,7 @@ use std::io::{Read, Write, Cursor, Result, Error, ErrorKind};
 use opcua_types::*;
 use opcua_types::status_codes::StatusCode;
 
-use comms::{MAX_CHUNK_COUNT, MIN_CHUNK_SIZE};
+use comms::MIN_CHUNK_SIZE;
 use comms::{HELLO_MESSAGE, ACKNOWLEDGE_MESSAGE, ERROR_MESSAGE, CHUNK_MESSAGE, OPEN_SECURE_CHANNEL_MESSAGE, CLOSE_SECURE_CHANNEL_MESSAGE};
 use comms::{CHUNK_FINAL, CHUNK_INTERMEDIATE, CHUNK_FINAL_ERROR};
 
@@ -191,14 +191,14 @@ impl HelloMessage {
     const MAX_URL_LEN: usize = 4096;
 
     /// Creates a HEL message
-    pub fn new(endpoint_url: &str, send_buffer_size: UInt32, receive_buffer_size: UInt32, max_message_size: UInt32) -> HelloMessage {
+    pub fn new(endpoint_url: &str, send_buffer_size: UInt32, receive_buffer_size: UInt32, max_message_size: UInt32, max_chunk_count: UInt32) -> HelloMessage {
         let mut msg = HelloMessage {
             message_header: MessageHeader::new(MessageType::Hello),
             protocol_version: 0,
             receive_buffer_size,
             send_buffer_size,
             max_message_size,
-            max_chunk_count: MAX_CHUNK_COUNT as UInt32,
+            max_chunk_count,
             endpoint_url: UAString::from(endpoint_url),
         };
         msg.message_header.message_size = msg.byte_len() as UInt32;
@@ -265,7 +265,12 @@ impl BinaryEncoder<AcknowledgeMessage> for AcknowledgeMessage {
     }
 }
 
-impl AcknowledgeMessage {}
+impl AcknowledgeMessage {
+    pub fn is_valid_buffer_sizes(&self) -> bool {
+        // Set in part 6 as minimum transport buffer size
+        self.receive_buffer_size >= MIN_CHUNK_SIZE as UInt32 && self.send_buffer_size >= MIN_CHUNK_SIZE as UInt32
+    }
+}
 
 /// Implementation of the ERR message in OPC UA
 #[derive(Debug, Clone, PartialEq)]
@@ -203,6 +203,68 @@ impl X509 {
         Thumbprint::new(&digest)
     }
 
+    /// Returns the application uri of the certificate, which is the uniform resource identifier in
+    /// its subject alternative names
+    pub fn application_uri(&self) -> Result<String, ()> {
+        // The subject alt name extension is its object id, an optional critical flag and an octet
+        // string holding a sequence of general names. The openssl crate does not expose uri names
+        // so the extension is read from the DER form of the certificate.
+        const SUBJECT_ALT_NAME_OID: [u8; 5] = [0x06, 0x03, 0x55, 0x1d, 0x11];
+        const BOOLEAN: u8 = 0x01;
+        const OCTET_STRING: u8 = 0x04;
+        const SEQUENCE: u8 = 0x30;
+        const URI_NAME: u8 = 0x86;
+
+        let der = self.value.to_der().map_err(|_| ())?;
+        let extension = der.windows(SUBJECT_ALT_NAME_OID.len())
+            .position(|window| window == SUBJECT_ALT_NAME_OID)
+            .map(|pos| &der[pos + SUBJECT_ALT_NAME_OID.len()..])
+            .ok_or(())?;
+        let (mut tag, mut value, rest) = Self::der_element(extension)?;
+        if tag == BOOLEAN {
+            let element = Self::der_element(rest)?;
+            tag = element.0;
+            value = element.1;
+        }
+        if tag != OCTET_STRING {
+            return Err(());
+        }
+        let (tag, mut names, _) = Self::der_element(value)?;
+        if tag != SEQUENCE {
+            return Err(());
+        }
+        while !names.is_empty() {
+            let (tag, name, rest) = Self::der_element(names)?;
+            if tag == URI_NAME {
+                return String::from_utf8(name.to_vec()).map_err(|_| ());
+            }
+            names = rest;
+        }
+        Err(())
+    }
+
+    /// Splits a DER encoded element into its tag, its value and what follows it
+    fn der_element(data: &[u8]) -> Result<(u8, &[u8], &[u8]), ()> {
+        if data.len() < 2 {
+            return Err(());
+        }
+        let (length, start) = if data[1] & 0x80 == 0 {
+            (data[1] as usize, 2)
+        } else {
+            // Long form, the low bits are the number of bytes holding the length
+            let length_bytes = (data[1] & 0x7f) as usize;
+            if length_bytes == 0 || length_bytes > 4 || data.len() < 2 + length_bytes {
+                return Err(());
+            }
+            let length = data[2..2 + length_bytes].iter().fold(0usize, |length, b| (length << 8) | *b as usize);
+            (length, 2 + length_bytes)
+        };
+        if data.len() - start < length {
+            return Err(());
+        }
+        Ok((data[0], &data[start..start + length], &data[start + length..]))
+    }
+
     /// Turn the Asn1 values into useful portable types
     pub fn not_before(&self) -> Result<DateTime<Utc>, ()> {
         let date = self.value.not_before().to_string();
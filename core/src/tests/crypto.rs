@@ -60,6 +60,13 @@ fn create_cert() {
     println!("Not after = {}", not_after);
 }
 
+#[test]
+fn cert_application_uri() {
+    // The first alt host name is the application uri
+    let (x509, _) = make_test_cert_1024();
+    assert_eq!(x509.application_uri().unwrap(), "host1");
+}
+
 #[test]
 fn ensure_pki_path() {
     let (tmp_dir, cert_store) = make_certificate_store();
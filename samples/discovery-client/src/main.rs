@@ -14,7 +14,7 @@ fn main() {
     //opcua_core::init_logging();
 
     // Read the argument
-    let url = {
+    let (url, network) = {
         use clap::*;
         let matches = App::new("OPC UA Discovery client")
             .about(
@@ -24,10 +24,18 @@ fn main() {
                 .help("The url for the discover server")
                 .default_value("opc.tcp://localhost:4840/")
                 .takes_value(true))
+            .arg(Arg::with_name("network")
+                .long("network")
+                .help("Lists the servers that the discovery server has found on the network through mDNS"))
             .get_matches();
-        matches.value_of("url").unwrap().to_string()
+        (matches.value_of("url").unwrap().to_string(), matches.is_present("network"))
     };
 
+    if network {
+        find_servers_on_network(url);
+        return;
+    }
+
     println!("Attempting to connect to discovery server {} ...", url);
 
     // The client API has a simple `find_servers` function that connects and returns servers for us.
@@ -70,3 +78,24 @@ fn main() {
     }
 }
 
+/// Lists the servers that a discovery server with multicast discovery has found on the network
+fn find_servers_on_network(url: String) {
+    let mut client = Client::new(ClientConfig::new("DiscoveryClient", "urn:DiscoveryClient"));
+    match client.find_servers_on_network(url, 0, None) {
+        Ok(servers) => {
+            println!("Discovery server has found {} servers on the network:", servers.len());
+            for server in &servers {
+                // Each server is a `ServerOnNetwork`
+                println!("Server : {} (record {})", server.server_name, server.record_id);
+                println!("  {}", server.discovery_url);
+                if let Some(ref server_capabilities) = server.server_capabilities {
+                    let server_capabilities: Vec<&str> = server_capabilities.iter().map(|c| c.as_ref()).collect();
+                    println!("  Capabilities: {}", server_capabilities.join(", "));
+                }
+            }
+        }
+        Err(status_code) => {
+            println!("ERROR: Cannot find servers on network from discovery server - check this error - {:?}", status_code);
+        }
+    }
+}
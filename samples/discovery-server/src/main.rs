@@ -0,0 +1,42 @@
+//! This is a local discovery server (LDS). Other servers register themselves with it and clients
+//! call FindServers on it to find them. Servers built with this crate register with it when their
+//! `discovery_server_url` is set to `opc.tcp://localhost:4840/UADiscovery`.
+extern crate opcua_core;
+extern crate opcua_server;
+extern crate opcua_types;
+
+use std::sync::{Arc, RwLock};
+
+use opcua_server::prelude::*;
+use opcua_server::constants;
+
+fn main() {
+    // This enables logging via env_logger & log crate macros. If you don't need logging or want
+    // to implement your own, omit this line.
+    opcua_core::init_logging();
+
+    // The discovery server listens on the well known port and path
+    let path = "/UADiscovery";
+    let user_token_ids = vec![ANONYMOUS_USER_TOKEN_ID.to_string()];
+
+    let mut config = ServerConfig::new_anonymous("OPC UA Local Discovery Server");
+    config.discovery_server = true;
+    // Announce the discovery server, and the servers that register with an mDNS configuration, on
+    // the network and find the servers that others announce
+    config.multicast_discovery = true;
+    config.discovery_server_url = None;
+    config.create_sample_keypair = true;
+    config.tcp_config.port = constants::DEFAULT_DISCOVERY_SERVER_PORT;
+    config.discovery_url = format!("opc.tcp://{}:{}{}", config.tcp_config.host, config.tcp_config.port, path);
+    // Servers register through the strongest endpoint they support. This replaces the anonymous
+    // config's endpoint on the default path.
+    config.add_endpoint("none", ServerEndpoint::new_none(path, &user_token_ids));
+    config.add_endpoint("basic128rsa15_sign_encrypt", ServerEndpoint::new_basic128rsa15_sign_encrypt(path, &user_token_ids));
+    config.add_endpoint("basic256_sign_encrypt", ServerEndpoint::new_basic256_sign_encrypt(path, &user_token_ids));
+    config.add_endpoint("basic256sha256_sign_encrypt", ServerEndpoint::new_basic256sha256_sign_encrypt(path, &user_token_ids));
+
+    let server = Server::new(config);
+
+    // Run the server. This does not ordinarily exit so you must Ctrl+C to terminate
+    Server::run(Arc::new(RwLock::new(server)));
+}
@@ -93,6 +93,12 @@ fn subscription_loop(session: Arc<RwLock<Session>>) -> Result<(), StatusCode> {
     {
         let mut session = session.write().unwrap();
 
+        // Hear about the connection breaking. The session reconnects by itself and restores the
+        // subscription when it can.
+        session.set_connection_status_callback(ConnectionStatusCallback::new(|connected| {
+            println!("Connection to server is {}", if connected { "restored" } else { "broken, reconnecting" });
+        }));
+
         // Creates our subscription - one update every 5 seconds
         let subscription_id = session.create_subscription(5f64, 10, 30, 0, 0, true, DataChangeCallback::new(|items| {
             println!("Data change from server:");
@@ -113,9 +119,9 @@ fn subscription_loop(session: Arc<RwLock<Session>>) -> Result<(), StatusCode> {
     // Loops forever. The publish thread will call the callback with changes on the variables
     loop {
         {
-            // Break the loop if connection goes down
+            // Break the loop if connection goes down and can't be restored
             let session = session.read().unwrap();
-            if !session.is_connected() {
+            if !session.is_connected() && !session.is_reconnecting() {
                 println!("Connection to server broke, so terminating");
                 break;
             }
@@ -1,17 +1,28 @@
-use address_space::AttrFnGetter;
+use address_space::{AttrFnGetter, AttrFnSetter, access_level, user_access_level};
 use address_space::node::{Node, NodeType};
 use address_space::object::Object;
 use address_space::variable::Variable;
+use address_space::method::Method;
+use address_space::view::View;
+use address_space::event_notifier;
+use aggregates::Aggregate;
 use chrono::Utc;
 use constants;
 use DateTimeUtc;
+use diagnostics::ServerDiagnostics;
+use events::condition::{self, Condition};
+use events::event::{BaseEventType, next_event_number};
+use history::{HistoricalDataProvider, MemoryHistoricalDataProvider};
+use subscriptions::value_changes::{ValueChanges, ValueChangeListener};
+use subscriptions::sampler::{Sampler, SampledItem};
 use opcua_types::*;
 use opcua_types::node_ids::*;
-use opcua_types::service_types::{BrowseDirection, RelativePath, RelativePathElement, ServerDiagnosticsSummaryDataType};
+use opcua_types::service_types::{Argument, BrowseDirection, RelativePath, RelativePathElement, ServerDiagnosticsSummaryDataType};
 use opcua_types::status_codes::StatusCode;
 use opcua_types::status_codes::StatusCode::*;
+use session::Session;
 use state::ServerState;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::{Arc, Mutex, RwLock};
 
 /// The `NodeId` is the target node. The reference is held in a list by the source node.
@@ -31,6 +42,13 @@ impl Reference {
     }
 }
 
+/// Makes an array of extension objects holding the values
+fn extension_object_array<T>(object_id: ObjectId, values: Vec<T>) -> Variant where T: BinaryEncoder<T> {
+    Variant::Array(values.into_iter().map(|value| {
+        Variant::ExtensionObject(Box::new(ExtensionObject::from_encodable(object_id, value)))
+    }).collect())
+}
+
 /// The address space holds references between nodes. It is populated with some standard nodes
 /// and any that the server implementation chooses to add for itself.
 pub struct AddressSpace {
@@ -42,6 +60,19 @@ pub struct AddressSpace {
     pub inverse_references: HashMap<NodeId, Vec<Reference>>,
     /// This is the last time that references to nodes were added or removed from the address space.
     pub last_modified: DateTimeUtc,
+    /// Stores the values of historizing variables
+    history_provider: Arc<Mutex<HistoricalDataProvider + Send>>,
+    /// Passes the values set on variables to the monitored items listening for them
+    value_changes: Arc<Mutex<ValueChanges>>,
+    /// Shares the samples of node attributes between the monitored items that sample them
+    sampler: Arc<Mutex<Sampler>>,
+    /// Events that have been raised, with their event number, oldest first
+    events: VecDeque<(usize, BaseEventType)>,
+    /// Alarm conditions by the node id of their condition node
+    conditions: HashMap<NodeId, Condition>,
+    /// The user access levels of variables for anonymous users, where they are narrower than the
+    /// UserAccessLevel attribute that other users get
+    anonymous_user_access_levels: HashMap<NodeId, Byte>,
 }
 
 impl AddressSpace {
@@ -52,8 +83,24 @@ impl AddressSpace {
             references: HashMap::new(),
             inverse_references: HashMap::new(),
             last_modified: Utc::now(),
+            history_provider: Arc::new(Mutex::new(MemoryHistoricalDataProvider::new(constants::DEFAULT_HISTORY_VALUES_PER_NODE))),
+            value_changes: Arc::new(Mutex::new(ValueChanges::new())),
+            sampler: Arc::new(Mutex::new(Sampler::new())),
+            events: VecDeque::new(),
+            conditions: HashMap::new(),
+            anonymous_user_access_levels: HashMap::new(),
         };
         address_space.add_default_nodes();
+        condition::add_condition_methods(&mut address_space);
+        // The aggregates that the server calculates
+        let aggregate_functions_id: NodeId = ObjectId::Server_ServerCapabilities_AggregateFunctions.into();
+        for object_id in Aggregate::supported_object_ids() {
+            address_space.add_organizes(&aggregate_functions_id, &object_id.into());
+        }
+        // Clients may subscribe to all events through the Server object
+        if let Some(&mut NodeType::Object(ref mut server)) = address_space.find_node_mut(&ObjectId::Server.into()) {
+            server.set_event_notifier(event_notifier::SUBSCRIBE_TO_EVENTS);
+        }
         address_space
     }
 
@@ -134,25 +181,83 @@ impl AddressSpace {
         // Server_ServerCapabilities_LocaleIdArray
         // Server_ServerCapabilities_MinSupportedSampleRate
 
-        // Server_ServerDiagnostics_ServerDiagnosticsSummary
-        self.set_server_diagnostics_summary(ServerDiagnosticsSummaryDataType {
-            server_view_count: 0,
-            current_session_count: 0,
-            cumulated_session_count: 0,
-            security_rejected_session_count: 0,
-            rejected_session_count: 0,
-            session_timeout_count: 0,
-            session_abort_count: 0,
-            current_subscription_count: 0,
-            cumulated_subscription_count: 0,
-            publishing_interval_count: 0,
-            security_rejected_requests_count: 0,
-            rejected_requests_count: 0,
+        // Server_ServerDiagnostics - values are made from the server diagnostics whenever they are read
+        let diagnostics = {
+            let server_state = trace_read_lock_unwrap!(server_state);
+            server_state.diagnostics.clone()
+        };
+        self.set_diagnostics_getter(Server_ServerDiagnostics_ServerDiagnosticsSummary, &diagnostics, |diagnostics| {
+            let server_diagnostics_summary = diagnostics.server_diagnostics_summary();
+            Variant::ExtensionObject(Box::new(ExtensionObject::from_encodable(ObjectId::ServerDiagnosticsSummaryDataType_Encoding_DefaultBinary, server_diagnostics_summary)))
+        });
+        let summary_variables: [(VariableId, fn(&ServerDiagnosticsSummaryDataType) -> UInt32); 12] = [
+            (Server_ServerDiagnostics_ServerDiagnosticsSummary_ServerViewCount, |sds| sds.server_view_count),
+            (Server_ServerDiagnostics_ServerDiagnosticsSummary_CurrentSessionCount, |sds| sds.current_session_count),
+            (Server_ServerDiagnostics_ServerDiagnosticsSummary_CumulatedSessionCount, |sds| sds.cumulated_session_count),
+            (Server_ServerDiagnostics_ServerDiagnosticsSummary_SecurityRejectedSessionCount, |sds| sds.security_rejected_session_count),
+            (Server_ServerDiagnostics_ServerDiagnosticsSummary_RejectedSessionCount, |sds| sds.rejected_session_count),
+            (Server_ServerDiagnostics_ServerDiagnosticsSummary_SessionTimeoutCount, |sds| sds.session_timeout_count),
+            (Server_ServerDiagnostics_ServerDiagnosticsSummary_SessionAbortCount, |sds| sds.session_abort_count),
+            (Server_ServerDiagnostics_ServerDiagnosticsSummary_PublishingIntervalCount, |sds| sds.publishing_interval_count),
+            (Server_ServerDiagnostics_ServerDiagnosticsSummary_CurrentSubscriptionCount, |sds| sds.current_subscription_count),
+            (Server_ServerDiagnostics_ServerDiagnosticsSummary_CumulatedSubscriptionCount, |sds| sds.cumulated_subscription_count),
+            (Server_ServerDiagnostics_ServerDiagnosticsSummary_SecurityRejectedRequestsCount, |sds| sds.security_rejected_requests_count),
+            (Server_ServerDiagnostics_ServerDiagnosticsSummary_RejectedRequestsCount, |sds| sds.rejected_requests_count),
+        ];
+        for &(variable_id, value) in summary_variables.iter() {
+            self.set_diagnostics_getter(variable_id, &diagnostics, move |diagnostics| {
+                Variant::UInt32(value(&diagnostics.server_diagnostics_summary()))
+            });
+        }
+
+        // Server_ServerDiagnostics_SessionsDiagnosticsSummary
+        self.set_diagnostics_getter(Server_ServerDiagnostics_SessionsDiagnosticsSummary_SessionDiagnosticsArray, &diagnostics, |diagnostics| {
+            extension_object_array(ObjectId::SessionDiagnosticsDataType_Encoding_DefaultBinary, diagnostics.session_diagnostics())
+        });
+        self.set_diagnostics_getter(Server_ServerDiagnostics_SessionsDiagnosticsSummary_SessionSecurityDiagnosticsArray, &diagnostics, |diagnostics| {
+            extension_object_array(ObjectId::SessionSecurityDiagnosticsDataType_Encoding_DefaultBinary, diagnostics.session_security_diagnostics())
         });
+        // The security diagnostics of sessions hold their client certificates so anonymous users
+        // may not read them
+        self.set_anonymous_user_access_level(&Server_ServerDiagnostics_SessionsDiagnosticsSummary_SessionSecurityDiagnosticsArray.into(), 0);
+
+        // Server_ServerDiagnostics_SamplingIntervalDiagnosticsArray - monitored items by the
+        // interval they sample at
+        let sampler = self.sampler.clone();
+        if let Some(ref mut v) = self.find_variable_by_variable_id(Server_ServerDiagnostics_SamplingIntervalDiagnosticsArray) {
+            let getter = AttrFnGetter::new(move |_: NodeId, _: AttributeId| -> Result<Option<DataValue>, StatusCode> {
+                let mut sampler = trace_lock_unwrap!(sampler);
+                let value = extension_object_array(ObjectId::SamplingIntervalDiagnosticsDataType_Encoding_DefaultBinary, sampler.sampling_interval_diagnostics());
+                Ok(Some(DataValue::new(value)))
+            });
+            v.set_value_getter(Arc::new(Mutex::new(getter)));
+        }
 
-        // Server_ServerDiagnostics_SamplingIntervalDiagnosticsArray
         // Server_ServerDiagnostics_SubscriptionDiagnosticsArray
-        // Server_ServerDiagnostics_EnabledFlag
+        self.set_diagnostics_getter(Server_ServerDiagnostics_SubscriptionDiagnosticsArray, &diagnostics, |diagnostics| {
+            extension_object_array(ObjectId::SubscriptionDiagnosticsDataType_Encoding_DefaultBinary, diagnostics.subscription_diagnostics())
+        });
+
+        // Server_ServerDiagnostics_EnabledFlag - the server may turn the collection of session and
+        // subscription diagnostics on and off. Clients may only read it.
+        self.set_diagnostics_getter(Server_ServerDiagnostics_EnabledFlag, &diagnostics, |diagnostics| {
+            Variant::Boolean(diagnostics.enabled())
+        });
+        if let Some(ref mut v) = self.find_variable_by_variable_id(Server_ServerDiagnostics_EnabledFlag) {
+            let diagnostics = diagnostics.clone();
+            let setter = AttrFnSetter::new(move |_: NodeId, _: AttributeId, data_value: DataValue| -> Result<(), StatusCode> {
+                if let Some(Variant::Boolean(enabled)) = data_value.value {
+                    let mut diagnostics = trace_write_lock_unwrap!(diagnostics);
+                    diagnostics.set_enabled(enabled);
+                    Ok(())
+                } else {
+                    Err(BadTypeMismatch)
+                }
+            });
+            v.set_value_setter(Arc::new(Mutex::new(setter)));
+            v.set_access_level(access_level::CURRENT_READ | access_level::CURRENT_WRITE);
+            v.set_user_access_level(user_access_level::CURRENT_READ);
+        }
 
         // ServiceLevel - 0-255 worst to best quality of service
         self.set_value_by_variable_id(Server_ServiceLevel, Variant::Byte(255));
@@ -198,19 +303,17 @@ impl AddressSpace {
         }
     }
 
-    pub fn set_server_diagnostics_summary(&mut self, sds: ServerDiagnosticsSummaryDataType) {
-        use opcua_types::node_ids::VariableId::*;
-        self.set_value_by_variable_id(Server_ServerDiagnostics_ServerDiagnosticsSummary_ServerViewCount, Variant::UInt32(sds.server_view_count));
-        self.set_value_by_variable_id(Server_ServerDiagnostics_ServerDiagnosticsSummary_CurrentSessionCount, Variant::UInt32(sds.current_session_count));
-        self.set_value_by_variable_id(Server_ServerDiagnostics_ServerDiagnosticsSummary_CumulatedSessionCount, Variant::UInt32(sds.cumulated_session_count));
-        self.set_value_by_variable_id(Server_ServerDiagnostics_ServerDiagnosticsSummary_SecurityRejectedSessionCount, Variant::UInt32(sds.security_rejected_session_count));
-        self.set_value_by_variable_id(Server_ServerDiagnostics_ServerDiagnosticsSummary_SessionTimeoutCount, Variant::UInt32(sds.session_timeout_count));
-        self.set_value_by_variable_id(Server_ServerDiagnostics_ServerDiagnosticsSummary_SessionAbortCount, Variant::UInt32(sds.session_abort_count));
-        self.set_value_by_variable_id(Server_ServerDiagnostics_ServerDiagnosticsSummary_PublishingIntervalCount, Variant::UInt32(sds.publishing_interval_count));
-        self.set_value_by_variable_id(Server_ServerDiagnostics_ServerDiagnosticsSummary_CurrentSubscriptionCount, Variant::UInt32(sds.current_subscription_count));
-        self.set_value_by_variable_id(Server_ServerDiagnostics_ServerDiagnosticsSummary_CumulatedSubscriptionCount, Variant::UInt32(sds.cumulated_subscription_count));
-        self.set_value_by_variable_id(Server_ServerDiagnostics_ServerDiagnosticsSummary_SecurityRejectedRequestsCount, Variant::UInt32(sds.security_rejected_requests_count));
-        self.set_value_by_variable_id(Server_ServerDiagnostics_ServerDiagnosticsSummary_RejectedRequestsCount, Variant::UInt32(sds.rejected_requests_count));
+    /// Puts a getter on the variable that makes its value from the server diagnostics
+    fn set_diagnostics_getter<F>(&mut self, variable_id: VariableId, diagnostics: &Arc<RwLock<ServerDiagnostics>>, value: F)
+        where F: Fn(&ServerDiagnostics) -> Variant + Send + 'static {
+        if let Some(ref mut v) = self.find_variable_by_variable_id(variable_id) {
+            let diagnostics = diagnostics.clone();
+            let getter = AttrFnGetter::new(move |_: NodeId, _: AttributeId| -> Result<Option<DataValue>, StatusCode> {
+                let diagnostics = trace_read_lock_unwrap!(diagnostics);
+                Ok(Some(DataValue::new(value(&*diagnostics))))
+            });
+            v.set_value_getter(Arc::new(Mutex::new(getter)));
+        }
     }
 
     pub fn root_folder_id() -> NodeId {
@@ -262,7 +365,11 @@ impl AddressSpace {
     }
 
     pub fn insert<T>(&mut self, node: T) where T: 'static + Into<NodeType> {
-        let node_type = node.into();
+        let mut node_type = node.into();
+        if let NodeType::Variable(ref mut variable) = node_type {
+            variable.set_history_provider(self.history_provider.clone());
+            variable.set_value_changes(self.value_changes.clone());
+        }
         let node_id = node_type.node_id();
         if self.node_exists(&node_id) {
             panic!("This node {:?} already exists", node_id);
@@ -271,13 +378,263 @@ impl AddressSpace {
         self.update_last_modified();
     }
 
+    /// Sets the provider that stores the values of historizing variables, replacing the memory
+    /// provider that the address space starts with.
+    pub fn set_history_provider(&mut self, history_provider: Arc<Mutex<HistoricalDataProvider + Send>>) {
+        self.node_map.values_mut().for_each(|node| {
+            if let NodeType::Variable(ref mut variable) = *node {
+                variable.set_history_provider(history_provider.clone());
+            }
+        });
+        self.history_provider = history_provider;
+    }
+
+    pub fn history_provider(&self) -> Arc<Mutex<HistoricalDataProvider + Send>> {
+        self.history_provider.clone()
+    }
+
+    /// Creates a listener for the values set on a variable. There is no listener for a node that
+    /// is not a variable, or a variable whose value is supplied by a getter, since the address
+    /// space does not see its value change.
+    pub fn listen_for_value_changes(&self, node_id: &NodeId) -> Option<ValueChangeListener> {
+        match self.find_node(node_id) {
+            Some(&NodeType::Variable(ref variable)) if !variable.has_value_getter() => {
+                let mut value_changes = trace_lock_unwrap!(self.value_changes);
+                Some(value_changes.listen(node_id))
+            }
+            _ => None
+        }
+    }
+
+    pub fn value_changes(&self) -> Arc<Mutex<ValueChanges>> {
+        self.value_changes.clone()
+    }
+
+    /// Adds an item that samples the part of a node's attribute in the index range at the sampling
+    /// interval. The attribute is read once per interval however many items sample it.
+    pub fn add_sampled_item(&self, node_id: &NodeId, attribute_id: AttributeId, index_range: &UAString, sampling_interval: Double) -> SampledItem {
+        let mut sampler = trace_lock_unwrap!(self.sampler);
+        sampler.add_item(node_id, attribute_id, index_range, sampling_interval)
+    }
+
+    pub fn sampler(&self) -> Arc<Mutex<Sampler>> {
+        self.sampler.clone()
+    }
+
+    /// Raises an event that monitored items on the event's source node can deliver. Items on
+    /// objects that are notifiers of the source, and on the Server object, deliver it too. The
+    /// address space holds recent events until the items have had a chance to see them.
+    pub fn raise_event(&mut self, mut event: BaseEventType) -> Result<(), StatusCode> {
+        if !self.node_exists(&event.source_node) {
+            error!("Cannot raise event on unknown source node {:?}", event.source_node);
+            return Err(BadNodeIdUnknown);
+        }
+        event.receive_time = DateTime::now();
+        self.events.push_back((next_event_number(), event));
+        while self.events.len() > constants::MAX_BUFFERED_EVENTS {
+            let _ = self.events.pop_front();
+        }
+        Ok(())
+    }
+
+    /// Finds the events raised after the event with the number, oldest first
+    pub fn find_events_since(&self, event_number: usize) -> Vec<(usize, &BaseEventType)> {
+        self.events.iter().filter(|e| e.0 > event_number).map(|e| (e.0, &e.1)).collect()
+    }
+
+    /// Tests if events from the source node are notified by the notifier. That is so when the
+    /// notifier is the source, is the Server object, or leads to the source by a chain of
+    /// HasEventSource or HasNotifier references.
+    pub fn is_event_notifier_of(&self, notifier_id: &NodeId, source_node_id: &NodeId) -> bool {
+        if notifier_id == source_node_id || *notifier_id == ObjectId::Server.into() {
+            return true;
+        }
+        let mut visited = HashSet::new();
+        let mut to_visit = vec![notifier_id.clone()];
+        while let Some(node_id) = to_visit.pop() {
+            if let Some(references) = self.find_references_from(&node_id, Some((ReferenceTypeId::HasEventSource, true))) {
+                for r in references {
+                    if r.node_id == *source_node_id {
+                        return true;
+                    }
+                    if visited.insert(r.node_id.clone()) {
+                        to_visit.push(r.node_id);
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    /// Adds an alarm condition on its source node. The condition node is a component of the source
+    /// with variables that mirror the condition's state.
+    pub fn add_condition(&mut self, condition: Condition) -> Result<NodeId, ()> {
+        let node_id = condition.node_id.clone();
+        if self.node_exists(&node_id) || !self.node_exists(&condition.source_node) {
+            return Err(());
+        }
+        self.insert(Object::new(&node_id, condition.condition_name.as_ref(), condition.condition_name.as_ref(), ""));
+        self.insert_reference(&node_id, &condition.condition_type, ReferenceTypeId::HasTypeDefinition);
+        self.add_has_component(&condition.source_node, &node_id);
+        self.insert_reference(&condition.source_node, &node_id, ReferenceTypeId::HasCondition);
+
+        // Add a variable for each field under the field it belongs to, or an object if that is not a field
+        for (browse_path, value) in condition.fields() {
+            let mut parent_id = node_id.clone();
+            for (i, browse_name) in browse_path.iter().enumerate() {
+                if let Some(child_id) = self.find_child(&parent_id, browse_name) {
+                    parent_id = child_id;
+                    continue;
+                }
+                let child_id = NodeId::next_numeric();
+                if i < browse_path.len() - 1 {
+                    self.insert(Object::new(&child_id, browse_name, browse_name, ""));
+                    self.add_has_component(&parent_id, &child_id);
+                } else {
+                    let data_type = value.data_type().unwrap_or(DataTypeId::BaseDataType);
+                    self.insert(Variable::new_with_data_type(&child_id, browse_name, browse_name, "", data_type, value.clone()));
+                    if browse_name.ends_with("State") || *browse_name == "Comment" || *browse_name == "Quality" || *browse_name == "LastSeverity" {
+                        self.add_has_component(&parent_id, &child_id);
+                    } else {
+                        self.add_has_property(&parent_id, &child_id);
+                        self.set_variable_as_property_type(&child_id);
+                    }
+                }
+                parent_id = child_id;
+            }
+        }
+        self.conditions.insert(node_id.clone(), condition);
+        // A limit alarm starts in the state of its input
+        self.update_limit_alarms_where(|condition| condition.node_id == node_id);
+        Ok(node_id)
+    }
+
+    /// Finds the alarm condition with the node id
+    pub fn find_condition(&self, node_id: &NodeId) -> Option<&Condition> {
+        self.conditions.get(node_id)
+    }
+
+    /// Changes the state of the condition with the supplied function. If that succeeds, the
+    /// condition's variables are updated and it raises an event with its new state.
+    pub fn update_condition<F>(&mut self, node_id: &NodeId, f: F) -> Result<(), StatusCode> where F: FnOnce(&mut Condition) -> Result<(), StatusCode> {
+        let event = if let Some(condition) = self.conditions.get_mut(node_id) {
+            f(condition)?;
+            condition.event_id = ByteString::random(16);
+            condition.event()
+        } else {
+            return Err(BadNodeIdInvalid);
+        };
+        self.set_condition_variables(node_id);
+        self.raise_event(event)
+    }
+
+    /// Sends the state of every retained condition to the subscription, between a
+    /// RefreshStartEvent and a RefreshEndEvent, so the client can show its alarms afresh.
+    pub fn refresh_conditions(&mut self, subscription_id: UInt32) {
+        let mut events = vec![BaseEventType::new(ObjectTypeId::RefreshStartEventType, &ObjectId::Server.into(), "Server", "", 100)];
+        events.extend(self.conditions.values().filter(|c| c.retain).map(|c| c.event()));
+        events.push(BaseEventType::new(ObjectTypeId::RefreshEndEventType, &ObjectId::Server.into(), "Server", "", 100));
+        for mut event in events {
+            event.subscription_id = Some(subscription_id);
+            let _ = self.raise_event(event);
+        }
+    }
+
+    /// Tests if there are any limit alarms, whose inputs need checking against their limits
+    pub fn has_limit_alarms(&self) -> bool {
+        self.conditions.values().any(|c| c.input_node.is_some())
+    }
+
+    /// Checks the inputs of all limit alarms against their limits. Inputs are also checked when
+    /// they are set through the address space or written by clients, so this is only needed for
+    /// values that change some other way.
+    pub fn update_limit_alarms(&mut self) {
+        self.update_limit_alarms_where(|_| true);
+    }
+
+    fn update_limit_alarms_where<F>(&mut self, predicate: F) where F: Fn(&Condition) -> bool {
+        let inputs: Vec<(NodeId, Double)> = self.conditions.values()
+            .filter(|c| predicate(c))
+            .filter_map(|c| c.input_node.as_ref().map(|input_node| (c.node_id.clone(), input_node)))
+            .filter_map(|(node_id, input_node)| {
+                // Only numeric values can be checked against the limits
+                let value = self.find_node(input_node)
+                    .and_then(|node| node.as_node().find_attribute(AttributeId::Value))
+                    .and_then(|value| value.value)
+                    .and_then(|value| value.as_f64());
+                value.map(|value| (node_id, value))
+            })
+            .collect();
+        for (node_id, value) in inputs {
+            let changed = self.conditions.get_mut(&node_id).map_or(false, |c| c.set_input_value(value));
+            if changed {
+                let _ = self.update_condition(&node_id, |_| Ok(()));
+            }
+        }
+    }
+
+    /// Sets the variables of the condition to the values of its fields
+    fn set_condition_variables(&mut self, node_id: &NodeId) {
+        let fields = if let Some(condition) = self.conditions.get(node_id) { condition.fields() } else { return; };
+        let now = DateTime::now();
+        for (browse_path, value) in fields {
+            let mut variable_id = Some(node_id.clone());
+            for browse_name in browse_path {
+                variable_id = variable_id.and_then(|parent_id| self.find_child(&parent_id, browse_name));
+            }
+            if let Some(variable) = variable_id.and_then(|variable_id| self.find_variable_by_node_id(&variable_id)) {
+                variable.set_value_direct(&now, value);
+            }
+        }
+    }
+
+    /// Finds the child of the node with the browse name in namespace 0
+    fn find_child(&self, node_id: &NodeId, browse_name: &str) -> Option<NodeId> {
+        let browse_name = QualifiedName::new(0, browse_name);
+        self.find_references_from(node_id, Some((ReferenceTypeId::HasChild, true)))
+            .and_then(|references| references.into_iter().find(|r| {
+                self.find_node(&r.node_id).map_or(false, |node| node.as_node().browse_name() == browse_name)
+            }))
+            .map(|r| r.node_id)
+    }
+
+    /// Tests if the type is the base type or one of its subtypes by following HasSubtype
+    /// references up from the type.
+    pub fn is_subtype(&self, type_id: &NodeId, base_type_id: &NodeId) -> bool {
+        let mut type_id = type_id.clone();
+        let mut visited = HashSet::new();
+        loop {
+            if type_id == *base_type_id {
+                return true;
+            }
+            if !visited.insert(type_id.clone()) {
+                return false;
+            }
+            let supertype_id = self.find_references_to(&type_id, Some((ReferenceTypeId::HasSubtype, false)))
+                .and_then(|references| references.first().map(|r| r.node_id.clone()));
+            if let Some(supertype_id) = supertype_id {
+                type_id = supertype_id;
+            } else {
+                return false;
+            }
+        }
+    }
+
     pub fn node_exists(&self, node_id: &NodeId) -> bool {
         self.node_map.contains_key(node_id)
     }
 
     pub fn find_nodes_relative_path(&self, node_id: &NodeId, relative_path: &RelativePath) -> Result<Vec<NodeId>, StatusCode> {
+        self.find_nodes_relative_path_in_view(node_id, relative_path, None)
+    }
+
+    /// Finds the nodes at the end of the relative path from the node. If a view is supplied, the
+    /// path may only pass through nodes in the view.
+    pub fn find_nodes_relative_path_in_view(&self, node_id: &NodeId, relative_path: &RelativePath, view: Option<&View>) -> Result<Vec<NodeId>, StatusCode> {
         if self.find_node(node_id).is_none() {
             return Err(BadNodeIdUnknown);
+        } else if view.map_or(false, |view| !view.contains_node(node_id)) {
+            return Err(BadNodeNotInView);
         }
 
         let relative_path_elements = relative_path.elements.as_ref().unwrap();
@@ -298,7 +655,7 @@ impl AddressSpace {
 
             for node_id in &matching_nodes {
                 // Iterate current set of nodes and put the results into next
-                if let Some(mut result) = self.follow_relative_path(&node_id, relative_path_element) {
+                if let Some(mut result) = self.follow_relative_path(&node_id, relative_path_element, view) {
                     next_matching_nodes.append(&mut result);
                 }
             }
@@ -310,7 +667,7 @@ impl AddressSpace {
         Ok(matching_nodes)
     }
 
-    fn follow_relative_path(&self, node_id: &NodeId, relative_path: &RelativePathElement) -> Option<Vec<NodeId>> {
+    fn follow_relative_path(&self, node_id: &NodeId, relative_path: &RelativePathElement, view: Option<&View>) -> Option<Vec<NodeId>> {
         let reference_type_id = relative_path.reference_type_id.as_reference_type_id().unwrap();
         let reference_filter = Some((reference_type_id, relative_path.include_subtypes));
         let references = if relative_path.is_inverse {
@@ -322,6 +679,9 @@ impl AddressSpace {
             let compare_target_name = !relative_path.target_name.is_null();
             let mut result = Vec::with_capacity(references.len());
             for reference in &references {
+                if view.map_or(false, |view| !view.contains_node(&reference.node_id)) {
+                    continue;
+                }
                 if let Some(node) = self.find_node(&reference.node_id) {
                     let node = node.as_node();
                     if !compare_target_name || node.browse_name() == relative_path.target_name {
@@ -360,6 +720,40 @@ impl AddressSpace {
         self.add_folder_with_id(&NodeId::next_numeric(), browse_name, display_name, parent_node_id)
     }
 
+    /// Adds a view, organized by the parent node which is usually the views folder. The view
+    /// contains the nodes added to it, with `add_nodes_to_view()`, and the references between them.
+    pub fn add_view(&mut self, view: View, parent_node_id: &NodeId) -> Result<NodeId, ()> {
+        let node_id = view.node_id();
+        if !self.node_map.contains_key(&node_id) {
+            self.add_organizes(&parent_node_id, &node_id);
+            self.insert(NodeType::View(view));
+            self.update_last_modified();
+            Ok(node_id)
+        } else {
+            Err(())
+        }
+    }
+
+    /// Adds nodes to the view, returning false if there is no such view
+    pub fn add_nodes_to_view(&mut self, view_id: &NodeId, node_ids: &[NodeId]) -> bool {
+        if let Some(&mut NodeType::View(ref mut view)) = self.node_map.get_mut(view_id) {
+            view.add_nodes(node_ids);
+        } else {
+            return false;
+        }
+        self.update_last_modified();
+        true
+    }
+
+    /// Finds the view with the node id
+    pub fn find_view(&self, view_id: &NodeId) -> Option<&View> {
+        if let Some(&NodeType::View(ref view)) = self.find_node(view_id) {
+            Some(view)
+        } else {
+            None
+        }
+    }
+
     /// Adds a list of varables to the specified parent node
     pub fn add_variables(&mut self, variables: Vec<Variable>, parent_node_id: &NodeId) -> Vec<Result<NodeId, ()>> {
         let mut result = Vec::with_capacity(variables.len());
@@ -383,6 +777,108 @@ impl AddressSpace {
         }
     }
 
+    /// Adds a method as a component of the specified object. If the method takes or returns arguments
+    /// then InputArguments / OutputArguments properties are created to describe them.
+    pub fn add_method(&mut self, method: Method, object_id: &NodeId, input_arguments: &[Argument], output_arguments: &[Argument]) -> Result<NodeId, ()> {
+        let method_id = method.node_id();
+        if !self.node_map.contains_key(&method_id) {
+            self.insert(NodeType::Method(method));
+            self.add_has_component(object_id, &method_id);
+            if !input_arguments.is_empty() {
+                self.add_method_arguments(&method_id, "InputArguments", input_arguments);
+            }
+            if !output_arguments.is_empty() {
+                self.add_method_arguments(&method_id, "OutputArguments", output_arguments);
+            }
+            Ok(method_id)
+        } else {
+            Err(())
+        }
+    }
+
+    /// Adds an arguments property to the method
+    fn add_method_arguments(&mut self, method_id: &NodeId, browse_name: &str, arguments: &[Argument]) {
+        let node_id = NodeId::next_numeric();
+        let value = arguments.iter().map(|argument| {
+            Variant::from(ExtensionObject::from_encodable(ObjectId::Argument_Encoding_DefaultBinary, argument.clone()))
+        }).collect::<Vec<Variant>>();
+        let variable = Variable::new_array(&node_id, browse_name, browse_name, "", DataTypeId::Argument, DataValue::new(Variant::Array(value)), &[arguments.len() as UInt32]);
+        self.insert(NodeType::Variable(variable));
+        self.add_has_property(method_id, &node_id);
+        self.set_variable_as_property_type(&node_id);
+    }
+
+    /// Returns the input arguments of a method by reading its InputArguments property. A method
+    /// without this property takes no arguments.
+    pub fn find_method_input_arguments(&self, method_id: &NodeId) -> Result<Vec<Argument>, StatusCode> {
+        self.find_method_arguments(method_id, "InputArguments")
+    }
+
+    /// Returns the output arguments of a method by reading its OutputArguments property.
+    pub fn find_method_output_arguments(&self, method_id: &NodeId) -> Result<Vec<Argument>, StatusCode> {
+        self.find_method_arguments(method_id, "OutputArguments")
+    }
+
+    fn find_method_arguments(&self, method_id: &NodeId, browse_name: &str) -> Result<Vec<Argument>, StatusCode> {
+        let browse_name = QualifiedName::new(0, browse_name);
+        if let Some(references) = self.find_references_from(method_id, Some((ReferenceTypeId::HasProperty, false))) {
+            let property = references.iter()
+                .filter_map(|r| self.find_node(&r.node_id))
+                .find(|n| n.as_node().browse_name() == browse_name);
+            if let Some(&NodeType::Variable(ref variable)) = property {
+                return match variable.value().value {
+                    Some(Variant::Array(ref values)) => {
+                        let mut result = Vec::with_capacity(values.len());
+                        for value in values {
+                            if let Variant::ExtensionObject(ref value) = *value {
+                                result.push(value.decode_inner::<Argument>()?);
+                            } else {
+                                error!("Method {:?} has a {} property containing a value that is not an Argument", method_id, browse_name.name.as_ref());
+                                return Err(BadUnexpectedError);
+                            }
+                        }
+                        Ok(result)
+                    }
+                    Some(Variant::Empty) | None => Ok(Vec::new()),
+                    _ => {
+                        error!("Method {:?} has a {} property that is not an array", method_id, browse_name.name.as_ref());
+                        Err(BadUnexpectedError)
+                    }
+                };
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    /// Tests if the method is a component of the object, or of the object's type definition or
+    /// one of its supertypes.
+    pub fn is_method_of_object(&self, object_id: &NodeId, method_id: &NodeId) -> bool {
+        let has_method = |node_id: &NodeId| {
+            if let Some(references) = self.find_references_from(node_id, Some((ReferenceTypeId::HasComponent, true))) {
+                references.iter().any(|r| r.node_id == *method_id)
+            } else {
+                false
+            }
+        };
+        if has_method(object_id) {
+            return true;
+        }
+        let mut type_id = self.find_references_from(object_id, Some((ReferenceTypeId::HasTypeDefinition, false)))
+            .and_then(|references| references.first().map(|r| r.node_id.clone()));
+        let mut visited = HashSet::new();
+        while let Some(id) = type_id {
+            if has_method(&id) {
+                return true;
+            }
+            if !visited.insert(id.clone()) {
+                break;
+            }
+            type_id = self.find_references_to(&id, Some((ReferenceTypeId::HasSubtype, false)))
+                .and_then(|references| references.first().map(|r| r.node_id.clone()));
+        }
+        false
+    }
+
     /// Adds a reference between one node and a target
     fn add_reference(reference_map: &mut HashMap<NodeId, Vec<Reference>>, node_id: &NodeId, reference: Reference) {
         if reference_map.contains_key(node_id) {
@@ -414,12 +910,21 @@ impl AddressSpace {
 
     /// Set a variable value
     pub fn set_value_by_node_id(&mut self, node_id: &NodeId, value: Variant) -> bool {
-        if let Some(ref mut variable) = self.find_variable_by_node_id(node_id) {
+        let found = if let Some(ref mut variable) = self.find_variable_by_node_id(node_id) {
             variable.set_value_direct(&DateTime::now(), value);
             true
         } else {
             false
+        };
+        if found {
+            self.update_limit_alarm_inputs(node_id);
         }
+        found
+    }
+
+    /// Checks the limit alarms whose input is the variable against their limits
+    pub fn update_limit_alarm_inputs(&mut self, input_node_id: &NodeId) {
+        self.update_limit_alarms_where(|condition| condition.input_node.as_ref() == Some(input_node_id));
     }
 
     /// This is a convenience method. It sets a value directly on a variable assuming the supplied
@@ -553,6 +1058,68 @@ impl AddressSpace {
         (references, inverse_ref_idx)
     }
 
+    /// Tests if a reference of the specified type exists from one node to another
+    pub fn has_reference(&self, node_id_from: &NodeId, node_id_to: &NodeId, reference_type_id: ReferenceTypeId) -> bool {
+        if let Some(references) = self.references.get(node_id_from) {
+            references.iter().any(|r| r.node_id == *node_id_to && r.reference_type_id == reference_type_id)
+        } else {
+            false
+        }
+    }
+
+    /// Removes references from the node in the reference map that match the predicate
+    fn remove_references<F>(reference_map: &mut HashMap<NodeId, Vec<Reference>>, node_id: &NodeId, predicate: F) -> bool where F: Fn(&Reference) -> bool {
+        let (removed, is_empty) = if let Some(references) = reference_map.get_mut(node_id) {
+            let len = references.len();
+            references.retain(|r| !predicate(r));
+            (references.len() != len, references.is_empty())
+        } else {
+            (false, false)
+        };
+        if is_empty {
+            reference_map.remove(node_id);
+        }
+        removed
+    }
+
+    /// Deletes a reference of the specified type from one node to another. The result is true
+    /// if the reference existed and was removed.
+    pub fn delete_reference(&mut self, node_id_from: &NodeId, node_id_to: &NodeId, reference_type_id: ReferenceTypeId) -> bool {
+        let removed = AddressSpace::remove_references(&mut self.references, node_id_from, |r| r.node_id == *node_id_to && r.reference_type_id == reference_type_id);
+        AddressSpace::remove_references(&mut self.inverse_references, node_id_to, |r| r.node_id == *node_id_from && r.reference_type_id == reference_type_id);
+        if removed {
+            self.update_last_modified();
+        }
+        removed
+    }
+
+    /// Deletes a node from the address space along with every reference whose source or target
+    /// is the node, so nothing is left pointing at it from either direction. If
+    /// delete_target_references is true, the references other nodes own to this node are also
+    /// deleted, otherwise they are kept as references to a node that does not exist. The result
+    /// is true if the node existed and was removed.
+    pub fn delete_node(&mut self, node_id: &NodeId, delete_target_references: bool) -> bool {
+        if self.node_map.remove(node_id).is_none() {
+            return false;
+        }
+        // Remove references from the node, and their inverse
+        if let Some(references) = self.references.remove(node_id) {
+            for r in references {
+                AddressSpace::remove_references(&mut self.inverse_references, &r.node_id, |r| r.node_id == *node_id);
+            }
+        }
+        // Remove the inverse references to the node, and the references other nodes own to it
+        if let Some(references) = self.inverse_references.remove(node_id) {
+            if delete_target_references {
+                for r in references {
+                    AddressSpace::remove_references(&mut self.references, &r.node_id, |r| r.node_id == *node_id);
+                }
+            }
+        }
+        self.update_last_modified();
+        true
+    }
+
     fn update_last_modified(&mut self) {
         self.last_modified = Utc::now();
     }
@@ -614,4 +1181,36 @@ impl AddressSpace {
             None
         }
     }
+
+    /// Narrows the user access level of the variable for anonymous users. Other users keep the
+    /// access given by its UserAccessLevel attribute.
+    pub fn set_anonymous_user_access_level(&mut self, node_id: &NodeId, user_access_level: Byte) {
+        self.anonymous_user_access_levels.insert(node_id.clone(), user_access_level);
+    }
+
+    /// Returns the user access level of the variable for the user of the session, or `None` if the
+    /// node is not a variable
+    pub fn user_access_level(&self, session: &Session, node_id: &NodeId) -> Option<Byte> {
+        if let Some(&NodeType::Variable(ref variable)) = self.find_node(node_id) {
+            let user_access_level = variable.user_access_level();
+            if session.is_anonymous() {
+                if let Some(anonymous_user_access_level) = self.anonymous_user_access_levels.get(node_id) {
+                    return Some(user_access_level & *anonymous_user_access_level);
+                }
+            }
+            Some(user_access_level)
+        } else {
+            None
+        }
+    }
+
+    /// Tests if the user of the session may read the attribute of the node. The values of variables
+    /// are readable if the user access level for the user says so, other attributes always are.
+    pub fn is_user_readable(&self, session: &Session, node_id: &NodeId, attribute_id: UInt32) -> bool {
+        if attribute_id == AttributeId::Value as UInt32 {
+            self.user_access_level(session, node_id).map_or(true, |user_access_level| user_access_level & user_access_level::CURRENT_READ != 0)
+        } else {
+            true
+        }
+    }
 }
\ No newline at end of file
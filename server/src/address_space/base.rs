@@ -10,6 +10,9 @@ use opcua_types::service_types::*;
 
 use address_space::{AttributeGetter, AttributeSetter};
 use address_space::node::Node;
+use diagnostic_info::Diagnostic;
+use history::HistoricalDataProvider;
+use subscriptions::value_changes::ValueChanges;
 
 // This should match size of AttributeId
 const NUM_ATTRIBUTES: usize = 22;
@@ -54,6 +57,10 @@ pub struct Base {
     attribute_getters: HashMap<AttributeId, Arc<Mutex<AttributeGetter + Send>>>,
     /// Attribute setters - if None, handled by Base
     attribute_setters: HashMap<AttributeId, Arc<Mutex<AttributeSetter + Send>>>,
+    /// History provider - records the values set on the Value attribute when the node is historizing
+    history_provider: Option<Arc<Mutex<HistoricalDataProvider + Send>>>,
+    /// Value changes - passes the values set on the Value attribute to monitored items listening for them
+    value_changes: Option<Arc<Mutex<ValueChanges>>>,
 }
 
 impl Debug for Base {
@@ -112,33 +119,36 @@ impl Node for Base {
     }
 
     fn find_attribute(&self, attribute_id: AttributeId) -> Option<DataValue> {
+        self.find_attribute_with_diagnostic(attribute_id).unwrap_or(None)
+    }
+
+    fn set_attribute(&mut self, attribute_id: AttributeId, value: DataValue) -> Result<(), StatusCode> {
+        self.set_attribute_with_diagnostic(attribute_id, value).map_err(|diagnostic| diagnostic.status_code)
+    }
+
+    fn find_attribute_with_diagnostic(&self, attribute_id: AttributeId) -> Result<Option<DataValue>, Diagnostic> {
         if let Some(getter) = self.attribute_getters.get(&attribute_id) {
             let mut getter = getter.lock().unwrap();
-            let value = getter.get(self.node_id(), attribute_id);
-            if value.is_ok() {
-                value.unwrap()
-            } else {
-                None
-            }
+            getter.get_with_diagnostic(self.node_id(), attribute_id)
         } else {
             let attribute_idx = Self::attribute_idx(attribute_id);
             if attribute_idx >= self.attributes.len() {
                 warn!("Attribute id {:?} is out of range and invalid", attribute_id);
-                None
+                Ok(None)
             } else {
-                self.attributes[attribute_idx].clone()
+                Ok(self.attributes[attribute_idx].clone())
             }
         }
     }
 
-    fn set_attribute(&mut self, attribute_id: AttributeId, value: DataValue) -> Result<(), StatusCode> {
+    fn set_attribute_with_diagnostic(&mut self, attribute_id: AttributeId, value: DataValue) -> Result<(), Diagnostic> {
         // Check the type of the datavalue
         let type_is_valid = match attribute_id {
             AttributeId::NodeId | AttributeId::NodeClass => {
                 false
             }
             AttributeId::BrowseName => {
-                is_valid_value_type!(value, String)
+                is_valid_value_type!(value, QualifiedName)
             }
             AttributeId::DisplayName | AttributeId::Description | AttributeId::InverseName => {
                 is_valid_value_type!(value, LocalizedText)
@@ -192,15 +202,31 @@ impl Node for Base {
             }
         };
         if !type_is_valid {
-            Err(BadTypeMismatch)
+            Err(Diagnostic::new(BadTypeMismatch))
         } else {
             let attribute_idx = Self::attribute_idx(attribute_id);
+            let history_value = if attribute_id == AttributeId::Value && self.is_historizing() {
+                Some(value.clone())
+            } else {
+                None
+            };
             if let Some(setter) = self.attribute_setters.get(&attribute_id) {
                 let mut setter = setter.lock().unwrap();
-                setter.set(self.node_id(), attribute_id, value)?;
+                setter.set_with_diagnostic(self.node_id(), attribute_id, value)?;
             } else {
+                if attribute_id == AttributeId::Value {
+                    if let Some(ref value_changes) = self.value_changes {
+                        let mut value_changes = value_changes.lock().unwrap();
+                        value_changes.notify(&self.node_id(), &value);
+                    }
+                }
                 self.attributes[attribute_idx] = Some(value);
             }
+            if let Some(history_value) = history_value {
+                let history_provider = self.history_provider.as_ref().unwrap();
+                let mut history_provider = history_provider.lock().unwrap();
+                history_provider.record_value(&self.node_id(), &history_value);
+            }
             Ok(())
         }
     }
@@ -228,6 +254,7 @@ impl Base {
             attributes[attribute_idx] = Some(DataValue {
                 value: Some(value),
                 status: Some(Good),
+                status_info_bits: 0,
                 server_timestamp: Some(now.clone()),
                 server_picoseconds: Some(0),
                 source_timestamp: Some(now.clone()),
@@ -239,6 +266,8 @@ impl Base {
             attributes,
             attribute_getters: HashMap::new(),
             attribute_setters: HashMap::new(),
+            history_provider: None,
+            value_changes: None,
         }
     }
 
@@ -250,10 +279,29 @@ impl Base {
         self.attribute_setters.insert(attribute_id, setter);
     }
 
+    pub fn set_history_provider(&mut self, history_provider: Arc<Mutex<HistoricalDataProvider + Send>>) {
+        self.history_provider = Some(history_provider);
+    }
+
+    pub fn set_value_changes(&mut self, value_changes: Arc<Mutex<ValueChanges>>) {
+        self.value_changes = Some(value_changes);
+    }
+
+    /// Tests if the attribute's value is supplied by a getter
+    pub fn has_attribute_getter(&self, attribute_id: AttributeId) -> bool {
+        self.attribute_getters.contains_key(&attribute_id)
+    }
+
+    /// Tests if the node has a history provider and its Historizing attribute is true
+    fn is_historizing(&self) -> bool {
+        self.history_provider.is_some() && find_attribute_value_optional!(self, Historizing, Boolean) == Some(true)
+    }
+
     pub fn set_attribute_value(&mut self, attribute_id: AttributeId, value: Variant, server_timestamp: &DateTime, source_timestamp: &DateTime) -> Result<(), StatusCode> {
         self.set_attribute(attribute_id, DataValue {
             value: Some(value),
             status: Some(Good),
+            status_info_bits: 0,
             server_timestamp: Some(server_timestamp.clone()),
             server_picoseconds: Some(0),
             source_timestamp: Some(source_timestamp.clone()),
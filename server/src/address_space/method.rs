@@ -1,13 +1,27 @@
+use std;
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, Mutex};
+
 use address_space::base::Base;
 use address_space::node::Node;
+use address_space::MethodCallback;
 
-#[derive(Debug)]
 pub struct Method {
     base: Base,
+    /// Callback that is invoked when the method is called. A method without a callback
+    /// cannot be called.
+    callback: Option<Arc<Mutex<MethodCallback + Send>>>,
 }
 
 node_impl!(Method);
 
+impl Debug for Method {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        // The callback is not written out
+        write!(f, "Method {{ base: {:?}, callback: {} }}", self.base, if self.callback.is_some() { "Some" } else { "None" })
+    }
+}
+
 impl Method {
     pub fn new(node_id: &NodeId, browse_name: &str, display_name: &str, description: &str, is_abstract: Boolean, executable: Boolean, user_executable: Boolean) -> Method {
         // Mandatory
@@ -18,6 +32,7 @@ impl Method {
         ];
         Method {
             base: Base::new(NodeClass::Method, node_id, browse_name, display_name, description, attributes),
+            callback: None,
         }
     }
 
@@ -32,4 +47,14 @@ impl Method {
     pub fn user_executable(&self) -> Boolean {
         find_attribute_value_mandatory!(&self.base, UserExecutable, Boolean)
     }
+
+    /// Sets the callback that is invoked when a client calls this method
+    pub fn set_callback(&mut self, callback: Arc<Mutex<MethodCallback + Send>>) {
+        self.callback = Some(callback);
+    }
+
+    /// Returns the callback for this method, if there is one
+    pub fn callback(&self) -> Option<Arc<Mutex<MethodCallback + Send>>> {
+        self.callback.clone()
+    }
 }
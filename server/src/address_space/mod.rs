@@ -3,15 +3,25 @@
 
 use std::result::Result;
 
-use opcua_types::{NodeId, AttributeId, DataValue};
+use opcua_types::{NodeId, AttributeId, DataValue, Variant};
 use opcua_types::status_codes::StatusCode;
 
+use address_space::address_space::AddressSpace;
+use diagnostic_info::Diagnostic;
+use session::Session;
+
 /// An attribute getter trait is used to obtain the datavalue associated with the particular attribute id
 /// This allows server implementations to supply a value on demand, usually in response to a polling action
 /// such as a monitored item in a subscription.
 pub trait AttributeGetter {
     /// Returns some datavalue or none
     fn get(&mut self, node_id: NodeId, attribute_id: AttributeId) -> Result<Option<DataValue>, StatusCode>;
+
+    /// Returns some datavalue or none, or a diagnostic that describes why the value could not be
+    /// obtained. Getters that can say more about a failure than its status code override this.
+    fn get_with_diagnostic(&mut self, node_id: NodeId, attribute_id: AttributeId) -> Result<Option<DataValue>, Diagnostic> {
+        self.get(node_id, attribute_id).map_err(Diagnostic::from)
+    }
 }
 
 /// An implementation of attribute getter that can be easily constructed from a mutable function
@@ -33,6 +43,12 @@ impl<F> AttrFnGetter<F> where F: FnMut(NodeId, AttributeId) -> Result<Option<Dat
 pub trait AttributeSetter {
     /// Sets the attribute on the specified node
     fn set(&mut self, node_id: NodeId, attribute_id: AttributeId, data_value: DataValue) -> Result<(), StatusCode>;
+
+    /// Sets the attribute on the specified node, or returns a diagnostic that describes why it
+    /// could not be set. Setters that can say more about a failure than its status code override this.
+    fn set_with_diagnostic(&mut self, node_id: NodeId, attribute_id: AttributeId, data_value: DataValue) -> Result<(), Diagnostic> {
+        self.set(node_id, attribute_id, data_value).map_err(Diagnostic::from)
+    }
 }
 
 /// An implementation of attribute setter that can be easily constructed using a mutable function
@@ -50,6 +66,30 @@ impl<F> AttrFnSetter<F> where F: FnMut(NodeId, AttributeId, DataValue) -> Result
     pub fn new(setter: F) -> AttrFnSetter<F> { AttrFnSetter { setter } }
 }
 
+/// A method callback is invoked when a client calls a method. It is supplied the session making
+/// the call, the object the method was called on and input arguments that have already been
+/// validated against the method's InputArguments property. It returns the output arguments, or a
+/// status code if the call failed.
+pub trait MethodCallback {
+    /// Calls the method on the specified object
+    fn call(&mut self, session: &Session, address_space: &mut AddressSpace, object_id: &NodeId, input_arguments: &[Variant]) -> Result<Vec<Variant>, StatusCode>;
+}
+
+/// An implementation of method callback that can be easily constructed using a mutable function
+pub struct MethodFnCallback<F> where F: FnMut(&Session, &mut AddressSpace, &NodeId, &[Variant]) -> Result<Vec<Variant>, StatusCode> + Send {
+    callback: F
+}
+
+impl<F> MethodCallback for MethodFnCallback<F> where F: FnMut(&Session, &mut AddressSpace, &NodeId, &[Variant]) -> Result<Vec<Variant>, StatusCode> + Send {
+    fn call(&mut self, session: &Session, address_space: &mut AddressSpace, object_id: &NodeId, input_arguments: &[Variant]) -> Result<Vec<Variant>, StatusCode> {
+        (self.callback)(session, address_space, object_id, input_arguments)
+    }
+}
+
+impl<F> MethodFnCallback<F> where F: FnMut(&Session, &mut AddressSpace, &NodeId, &[Variant]) -> Result<Vec<Variant>, StatusCode> + Send {
+    pub fn new(callback: F) -> MethodFnCallback<F> { MethodFnCallback { callback } }
+}
+
 /// This is a sanity saving macro that adds Node trait methods to all types that have a base
 /// member.
 macro_rules! node_impl {
@@ -58,6 +98,7 @@ macro_rules! node_impl {
         use opcua_types::status_codes::StatusCode;
         use opcua_types::service_types::NodeClass;
         use address_space::node::NodeType;
+        use diagnostic_info::Diagnostic;
 
         impl Node for $node_struct {
             fn node_class(&self) -> NodeClass { self.base.node_class() }
@@ -71,6 +112,8 @@ macro_rules! node_impl {
             fn set_user_write_mask(&mut self, write_mask: UInt32) { self.base.set_user_write_mask(write_mask) }
             fn find_attribute(&self, attribute_id: AttributeId) -> Option<DataValue> { self.base.find_attribute(attribute_id) }
             fn set_attribute(&mut self, attribute_id: AttributeId, value: DataValue) -> Result<(), StatusCode> { self.base.set_attribute(attribute_id, value) }
+            fn find_attribute_with_diagnostic(&self, attribute_id: AttributeId) -> Result<Option<DataValue>, Diagnostic> { self.base.find_attribute_with_diagnostic(attribute_id) }
+            fn set_attribute_with_diagnostic(&mut self, attribute_id: AttributeId, value: DataValue) -> Result<(), Diagnostic> { self.base.set_attribute_with_diagnostic(attribute_id, value) }
         }
 
         impl Into<NodeType> for $node_struct {
@@ -134,26 +177,33 @@ pub mod view;
 pub mod access_level {
     pub const CURRENT_READ: u8 = 1;
     pub const CURRENT_WRITE: u8 = 1 << 1;
+    pub const HISTORY_READ: u8 = 1 << 2;
+    pub const HISTORY_WRITE: u8 = 1 << 3;
     // These can be uncommented if they become used
-    //pub const HISTORY_READ: u8 = 1 << 2;
-    //pub const HISTORY_WRITE: u8 = 1 << 3;
     //pub const SEMANTIC_CHANGE: u8 = 1 << 4;
     //pub const STATUS_WRITE: u8 = 1 << 5;
     //pub const TIMESTAMP_WRITE: u8 = 1 << 6;
 }
 
+pub mod event_notifier {
+    pub const SUBSCRIBE_TO_EVENTS: u8 = 1;
+    // These can be uncommented if they become used
+    //pub const HISTORY_READ: u8 = 1 << 2;
+    //pub const HISTORY_WRITE: u8 = 1 << 3;
+}
+
 pub mod user_access_level {
     pub const CURRENT_READ: u8 = 1;
     pub const CURRENT_WRITE: u8 = 1 << 1;
+    pub const HISTORY_READ: u8 = 1 << 2;
+    pub const HISTORY_WRITE: u8 = 1 << 3;
     // These can be uncommented if they become used
-    //pub const HISTORY_READ: u8 = 1 << 2;
-    //pub const HISTORY_WRITE: u8 = 1 << 3;
     //pub const STATUS_WRITE: u8 = 1 << 5;
     //pub const TIMESTAMP_WRITE: u8 = 1 << 6;
 }
 
 pub mod types {
-    pub use super::{AttrFnGetter, AttrFnSetter};
+    pub use super::{AttrFnGetter, AttrFnSetter, MethodFnCallback};
     pub use super::address_space::AddressSpace;
     pub use super::data_type::DataType;
     pub use super::object::Object;
@@ -2,6 +2,7 @@ use opcua_types::{UInt32, NodeId, QualifiedName, LocalizedText, AttributeId, Dat
 use opcua_types::service_types::NodeClass;
 use opcua_types::status_codes::StatusCode;
 
+use diagnostic_info::Diagnostic;
 use address_space::types::{Object, ObjectType, ReferenceType, Variable, VariableType, View, DataType, Method};
 
 #[derive(Debug)]
@@ -62,4 +63,14 @@ pub trait Node {
     fn set_user_write_mask(&mut self, write_mask: UInt32);
     fn find_attribute(&self, attribute_id: AttributeId) -> Option<DataValue>;
     fn set_attribute(&mut self, attribute_id: AttributeId, value: DataValue) -> Result<(), StatusCode>;
+    /// Finds the attribute like `find_attribute` but returns a diagnostic if a getter fails to
+    /// supply it. Nodes that can say more about a failure than `find_attribute` does override this.
+    fn find_attribute_with_diagnostic(&self, attribute_id: AttributeId) -> Result<Option<DataValue>, Diagnostic> {
+        Ok(self.find_attribute(attribute_id))
+    }
+    /// Sets the attribute like `set_attribute` but returns a diagnostic describing a failure. Nodes
+    /// that can say more about a failure than its status code override this.
+    fn set_attribute_with_diagnostic(&mut self, attribute_id: AttributeId, value: DataValue) -> Result<(), Diagnostic> {
+        self.set_attribute(attribute_id, value).map_err(Diagnostic::from)
+    }
 }
@@ -19,7 +19,11 @@ impl Object {
         }
     }
 
-    pub fn event_notifier(&self) -> Boolean {
-        find_attribute_value_mandatory!(&self.base, EventNotifier, Boolean)
+    pub fn event_notifier(&self) -> Byte {
+        find_attribute_value_mandatory!(&self.base, EventNotifier, Byte)
+    }
+
+    pub fn set_event_notifier(&mut self, event_notifier: Byte) {
+        let _ = self.base.set_attribute(AttributeId::EventNotifier, DataValue::new(event_notifier));
     }
 }
\ No newline at end of file
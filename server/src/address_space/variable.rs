@@ -8,6 +8,8 @@ use address_space::node::Node;
 use address_space::{AttributeGetter, AttributeSetter};
 use address_space::access_level;
 use address_space::user_access_level;
+use history::HistoricalDataProvider;
+use subscriptions::value_changes::ValueChanges;
 
 #[derive(Debug)]
 pub struct Variable {
@@ -77,6 +79,11 @@ impl Variable {
         self.base.set_attribute_getter(AttributeId::Value, getter);
     }
 
+    /// Tests if the variable's value is supplied by a getter rather than held by the variable
+    pub fn has_value_getter(&self) -> bool {
+        self.base.has_attribute_getter(AttributeId::Value)
+    }
+
     pub fn set_value_setter(&mut self, setter: Arc<Mutex<AttributeSetter + Send>>) {
         self.base.set_attribute_setter(AttributeId::Value, setter);
     }
@@ -163,4 +170,29 @@ impl Variable {
     pub fn historizing(&self) -> Boolean {
         find_attribute_value_mandatory!(&self.base, Historizing, Boolean)
     }
+
+    /// Sets the historizing flag. A historizing variable records changes to its value with its
+    /// history provider, and its access levels allow history to be read.
+    pub fn set_historizing(&mut self, historizing: Boolean) {
+        let _ = self.base.set_attribute(AttributeId::Historizing, DataValue::new(historizing));
+        let (access_level, user_access_level) = if historizing {
+            (self.access_level() | access_level::HISTORY_READ, self.user_access_level() | user_access_level::HISTORY_READ)
+        } else {
+            (self.access_level() & !access_level::HISTORY_READ, self.user_access_level() & !user_access_level::HISTORY_READ)
+        };
+        self.set_access_level(access_level);
+        self.set_user_access_level(user_access_level);
+    }
+
+    /// Sets the provider that records the variable's value when it is historizing. The address
+    /// space sets its provider on variables as they are inserted.
+    pub fn set_history_provider(&mut self, history_provider: Arc<Mutex<HistoricalDataProvider + Send>>) {
+        self.base.set_history_provider(history_provider);
+    }
+
+    /// Sets the listeners that the values set on the variable are passed to. The address space sets
+    /// its listeners when the variable is inserted.
+    pub fn set_value_changes(&mut self, value_changes: Arc<Mutex<ValueChanges>>) {
+        self.base.set_value_changes(value_changes);
+    }
 }
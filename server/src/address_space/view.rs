@@ -1,9 +1,18 @@
+use std::collections::HashSet;
+
 use address_space::base::Base;
 use address_space::node::Node;
 
+/// A view is a subset of the address space that clients may browse within, e.g. to hide
+/// engineering details from an operator. The view contains the nodes that are added to it and
+/// the references between them, as well as the view node itself.
 #[derive(Debug)]
 pub struct View {
     base: Base,
+    /// The nodes in the view
+    nodes: HashSet<NodeId>,
+    /// Incremented whenever the nodes in the view change
+    view_version: UInt32,
 }
 
 node_impl!(View);
@@ -19,6 +28,8 @@ impl View {
         ];
         View {
             base: Base::new(NodeClass::View, node_id, browse_name, display_name, description, attributes),
+            nodes: HashSet::new(),
+            view_version: 0,
         }
     }
 
@@ -29,4 +40,28 @@ impl View {
     pub fn contains_no_loops(&self) -> Boolean {
         find_attribute_value_mandatory!(&self.base, ContainsNoLoops, Boolean)
     }
+
+    /// Adds nodes to the view
+    pub fn add_nodes(&mut self, node_ids: &[NodeId]) {
+        self.nodes.extend(node_ids.iter().cloned());
+        self.view_version += 1;
+    }
+
+    /// Removes nodes from the view
+    pub fn remove_nodes(&mut self, node_ids: &[NodeId]) {
+        for node_id in node_ids {
+            self.nodes.remove(node_id);
+        }
+        self.view_version += 1;
+    }
+
+    /// Tests if the node is in the view. The view node is always in its own view.
+    pub fn contains_node(&self, node_id: &NodeId) -> bool {
+        *node_id == self.base.node_id() || self.nodes.contains(node_id)
+    }
+
+    /// The version of the view which clients may supply to be sure that the view has not changed
+    pub fn view_version(&self) -> UInt32 {
+        self.view_version
+    }
 }
@@ -0,0 +1,894 @@
+//! Aggregates. The standard aggregates of Part 13 calculate a value for each processing interval
+//! from the raw values of a variable, e.g. its average or maximum over a minute. Monitored items
+//! with an aggregate filter report them as values are sampled and the HistoryRead service
+//! calculates them from historical values.
+
+use opcua_types::*;
+use opcua_types::node_ids::{ObjectId, ReferenceTypeId};
+use opcua_types::status_codes::StatusCode;
+use opcua_types::status_codes::StatusCode::*;
+use opcua_types::service_types::AggregateConfiguration;
+
+use address_space::address_space::AddressSpace;
+use address_space::node::NodeType;
+use constants;
+use history::value_ticks;
+
+/// Ticks of 100 nanoseconds in a millisecond
+const TICKS_PER_MILLISECOND: i64 = 10_000;
+
+/// The standard aggregates that the server can calculate
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Aggregate {
+    Interpolative,
+    Average,
+    TimeAverage,
+    TimeAverage2,
+    Total,
+    Total2,
+    Minimum,
+    Maximum,
+    MinimumActualTime,
+    MaximumActualTime,
+    Range,
+    Minimum2,
+    Maximum2,
+    MinimumActualTime2,
+    MaximumActualTime2,
+    Range2,
+    Count,
+    NumberOfTransitions,
+    DurationInStateZero,
+    DurationInStateNonZero,
+    Start,
+    End,
+    Delta,
+    StartBound,
+    EndBound,
+    DeltaBounds,
+    DurationGood,
+    DurationBad,
+    PercentGood,
+    PercentBad,
+    WorstQuality,
+    WorstQuality2,
+    StandardDeviationSample,
+    StandardDeviationPopulation,
+    VarianceSample,
+    VariancePopulation,
+}
+
+/// The aggregate function object of each aggregate
+const AGGREGATE_FUNCTIONS: [(Aggregate, ObjectId); 36] = [
+    (Aggregate::Interpolative, ObjectId::AggregateFunction_Interpolative),
+    (Aggregate::Average, ObjectId::AggregateFunction_Average),
+    (Aggregate::TimeAverage, ObjectId::AggregateFunction_TimeAverage),
+    (Aggregate::TimeAverage2, ObjectId::AggregateFunction_TimeAverage2),
+    (Aggregate::Total, ObjectId::AggregateFunction_Total),
+    (Aggregate::Total2, ObjectId::AggregateFunction_Total2),
+    (Aggregate::Minimum, ObjectId::AggregateFunction_Minimum),
+    (Aggregate::Maximum, ObjectId::AggregateFunction_Maximum),
+    (Aggregate::MinimumActualTime, ObjectId::AggregateFunction_MinimumActualTime),
+    (Aggregate::MaximumActualTime, ObjectId::AggregateFunction_MaximumActualTime),
+    (Aggregate::Range, ObjectId::AggregateFunction_Range),
+    (Aggregate::Minimum2, ObjectId::AggregateFunction_Minimum2),
+    (Aggregate::Maximum2, ObjectId::AggregateFunction_Maximum2),
+    (Aggregate::MinimumActualTime2, ObjectId::AggregateFunction_MinimumActualTime2),
+    (Aggregate::MaximumActualTime2, ObjectId::AggregateFunction_MaximumActualTime2),
+    (Aggregate::Range2, ObjectId::AggregateFunction_Range2),
+    (Aggregate::Count, ObjectId::AggregateFunction_Count),
+    (Aggregate::NumberOfTransitions, ObjectId::AggregateFunction_NumberOfTransitions),
+    (Aggregate::DurationInStateZero, ObjectId::AggregateFunction_DurationInStateZero),
+    (Aggregate::DurationInStateNonZero, ObjectId::AggregateFunction_DurationInStateNonZero),
+    (Aggregate::Start, ObjectId::AggregateFunction_Start),
+    (Aggregate::End, ObjectId::AggregateFunction_End),
+    (Aggregate::Delta, ObjectId::AggregateFunction_Delta),
+    (Aggregate::StartBound, ObjectId::AggregateFunction_StartBound),
+    (Aggregate::EndBound, ObjectId::AggregateFunction_EndBound),
+    (Aggregate::DeltaBounds, ObjectId::AggregateFunction_DeltaBounds),
+    (Aggregate::DurationGood, ObjectId::AggregateFunction_DurationGood),
+    (Aggregate::DurationBad, ObjectId::AggregateFunction_DurationBad),
+    (Aggregate::PercentGood, ObjectId::AggregateFunction_PercentGood),
+    (Aggregate::PercentBad, ObjectId::AggregateFunction_PercentBad),
+    (Aggregate::WorstQuality, ObjectId::AggregateFunction_WorstQuality),
+    (Aggregate::WorstQuality2, ObjectId::AggregateFunction_WorstQuality2),
+    (Aggregate::StandardDeviationSample, ObjectId::AggregateFunction_StandardDeviationSample),
+    (Aggregate::StandardDeviationPopulation, ObjectId::AggregateFunction_StandardDeviationPopulation),
+    (Aggregate::VarianceSample, ObjectId::AggregateFunction_VarianceSample),
+    (Aggregate::VariancePopulation, ObjectId::AggregateFunction_VariancePopulation),
+];
+
+impl Aggregate {
+    /// Finds the aggregate of an aggregate function node. Returns `BadAggregateNotSupported` for
+    /// anything else, including the AnnotationCount aggregate since values carry no annotations.
+    pub fn from_node_id(node_id: &NodeId) -> Result<Aggregate, StatusCode> {
+        if let Ok(object_id) = node_id.as_object_id() {
+            if let Some(&(aggregate, _)) = AGGREGATE_FUNCTIONS.iter().find(|&&(_, id)| id == object_id) {
+                return Ok(aggregate);
+            }
+        }
+        error!("Aggregate {:?} is not supported", node_id);
+        Err(BadAggregateNotSupported)
+    }
+
+    /// The aggregate function object of the aggregate
+    pub fn object_id(&self) -> ObjectId {
+        AGGREGATE_FUNCTIONS.iter().find(|&&(aggregate, _)| aggregate == *self).unwrap().1
+    }
+
+    /// The aggregate function objects of all the aggregates that the server supports
+    pub fn supported_object_ids() -> Vec<ObjectId> {
+        AGGREGATE_FUNCTIONS.iter().map(|&(_, object_id)| object_id).collect()
+    }
+}
+
+/// The historian bits that qualify the status of a value calculated from raw values. They are
+/// returned in the info bits of the value's status.
+pub mod historian_bits {
+    /// A raw value
+    pub const RAW: u32 = 0x0;
+    /// A value calculated from the raw values
+    pub const CALCULATED: u32 = 0x1;
+    /// A value interpolated between, or extrapolated from, the raw values
+    pub const INTERPOLATED: u32 = 0x2;
+    /// A value calculated over an interval that was only partly covered by raw values
+    pub const PARTIAL: u32 = 0x4;
+    /// A raw value that hides other values with the same timestamp
+    pub const EXTRA_DATA: u32 = 0x8;
+    /// A value that occurs more than once in the interval, e.g. a minimum, at the first time
+    pub const MULTI_VALUE: u32 = 0x10;
+}
+
+/// A value calculated by an aggregate, with the historian bits of its status
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggregateValue {
+    pub value: DataValue,
+    /// The historian bits of the value's status, see `historian_bits`
+    pub historian_bits: u32,
+}
+
+impl AggregateValue {
+    /// Returns the value with the historian bits in the info bits of its status
+    pub fn into_data_value(self) -> DataValue {
+        let mut value = self.value;
+        if self.historian_bits != historian_bits::RAW {
+            value.status_info_bits = status_info_bits::INFO_TYPE_DATA_VALUE | (self.historian_bits as UInt16 & status_info_bits::DATA_VALUE_MASK);
+        }
+        value
+    }
+}
+
+/// The aggregate configuration that the server uses when a client asks for the server's defaults
+pub fn default_configuration() -> AggregateConfiguration {
+    AggregateConfiguration {
+        use_server_capabilities_defaults: true,
+        treat_uncertain_as_bad: constants::AGGREGATE_TREAT_UNCERTAIN_AS_BAD,
+        percent_data_bad: constants::AGGREGATE_PERCENT_DATA_BAD,
+        percent_data_good: constants::AGGREGATE_PERCENT_DATA_GOOD,
+        use_sloped_extrapolation: constants::AGGREGATE_USE_SLOPED_EXTRAPOLATION,
+    }
+}
+
+/// Tests if the values of a variable are stepped rather than sloped, from the Stepped property of
+/// its historical configuration. Values are sloped if the variable has no such configuration.
+pub fn is_stepped(address_space: &AddressSpace, node_id: &NodeId) -> bool {
+    let configurations = if let Some(references) = address_space.find_references_from(node_id, Some((ReferenceTypeId::HasHistoricalConfiguration, false))) {
+        references
+    } else {
+        return false;
+    };
+    let browse_name = QualifiedName::new(0, "Stepped");
+    configurations.iter()
+        .filter_map(|c| address_space.find_references_from(&c.node_id, Some((ReferenceTypeId::HasProperty, false))))
+        .flat_map(|references| references.into_iter())
+        .filter_map(|r| address_space.find_node(&r.node_id))
+        .filter(|n| n.as_node().browse_name() == browse_name)
+        .any(|n| {
+            if let NodeType::Variable(ref variable) = *n {
+                variable.value().value == Some(Variant::Boolean(true))
+            } else {
+                false
+            }
+        })
+}
+
+/// Calculates an aggregate over intervals of raw values.
+///
+/// Raw values are ordered by their timestamps and each value holds from its timestamp until the
+/// next value. An interval holds the values from its start up to, but not including, its end and
+/// the values either side of it are used as its bounds. Values that are bad, or uncertain when the
+/// configuration treats uncertain as bad, are only counted towards the duration of bad data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggregateCalculator {
+    aggregate: Aggregate,
+    configuration: AggregateConfiguration,
+    /// Values are held until the next value rather than sloped towards it
+    stepped: bool,
+}
+
+impl AggregateCalculator {
+    /// Creates a calculator for the aggregate function node. The configuration is replaced by the
+    /// server's defaults if the client asked for them.
+    ///
+    /// # Errors
+    ///
+    /// BadAggregateNotSupported for an unknown aggregate and BadAggregateInvalidInputs if the
+    /// percentages of the configuration are out of range or cannot both be met.
+    pub fn new(aggregate_type: &NodeId, configuration: &AggregateConfiguration, stepped: bool) -> Result<AggregateCalculator, StatusCode> {
+        let aggregate = Aggregate::from_node_id(aggregate_type)?;
+        let configuration = if configuration.use_server_capabilities_defaults {
+            default_configuration()
+        } else {
+            configuration.clone()
+        };
+        if configuration.percent_data_bad > 100 || configuration.percent_data_good > 100 ||
+            (configuration.percent_data_bad as u32 + configuration.percent_data_good as u32) < 100 {
+            error!("Aggregate configuration percentages are invalid, {:?}", configuration);
+            return Err(BadAggregateInvalidInputs);
+        }
+        Ok(AggregateCalculator {
+            aggregate,
+            configuration,
+            stepped,
+        })
+    }
+
+    pub fn aggregate(&self) -> Aggregate {
+        self.aggregate
+    }
+
+    /// The configuration that the calculator uses, with the server's defaults if they were asked for
+    pub fn configuration(&self) -> &AggregateConfiguration {
+        &self.configuration
+    }
+
+    /// Calculates the aggregate over the interval from the start up to the end, in ticks, from raw
+    /// values in time order. The value is timestamped with the start of the interval unless the
+    /// aggregate reports the time of a raw value.
+    pub fn calculate(&self, values: &[DataValue], start: i64, end: i64) -> AggregateValue {
+        let (good_ticks, bad_ticks) = self.quality_durations(values, start, end);
+        let interval_status = self.interval_status(good_ticks, bad_ticks);
+        // An interval without a value holding at its start is only partly covered
+        let partial = !values.iter().any(|v| value_ticks(v) <= start);
+
+        let raw_values: Vec<&DataValue> = values.iter().filter(|v| {
+            let ticks = value_ticks(v);
+            ticks >= start && ticks < end
+        }).collect();
+        let good_values: Vec<(i64, f64)> = raw_values.iter()
+            .filter(|v| self.is_good(v))
+            .filter_map(|v| v.value.as_ref().and_then(|value| value.as_f64()).map(|value| (value_ticks(v), value)))
+            .collect();
+
+        let (value, status, timestamp, bits) = match self.aggregate {
+            Aggregate::Interpolative | Aggregate::StartBound => self.bound_result(values, start),
+            Aggregate::EndBound => {
+                let (value, status, _, bits) = self.bound_result(values, end);
+                (value, status, end, bits)
+            }
+            Aggregate::DeltaBounds => {
+                match (self.bound_value(values, start), self.bound_value(values, end)) {
+                    (Some((v1, s1, _)), Some((v2, s2, _))) => {
+                        match (v1.as_f64(), v2.as_f64()) {
+                            (Some(v1), Some(v2)) => {
+                                let status = if s1.is_good() && s2.is_good() { interval_status } else { UncertainDataSubNormal };
+                                (Some(Variant::Double(v2 - v1)), status, start, historian_bits::CALCULATED)
+                            }
+                            _ => (None, BadAggregateInvalidInputs, start, historian_bits::CALCULATED)
+                        }
+                    }
+                    _ => (None, BadNoData, start, historian_bits::CALCULATED)
+                }
+            }
+            Aggregate::Average => {
+                self.good_values_result(&good_values, interval_status, start, |values| {
+                    Some(values.iter().map(|&(_, v)| v).sum::<f64>() / values.len() as f64)
+                })
+            }
+            Aggregate::TimeAverage | Aggregate::Total | Aggregate::TimeAverage2 | Aggregate::Total2 => {
+                let simple_bounds = self.aggregate == Aggregate::TimeAverage2 || self.aggregate == Aggregate::Total2;
+                let points = self.integration_points(values, &good_values, start, end, simple_bounds);
+                if points.len() < 2 {
+                    (None, BadNoData, start, historian_bits::CALCULATED)
+                } else {
+                    let area = self.integrate(&points);
+                    let duration = points[points.len() - 1].0 - points[0].0;
+                    let value = match self.aggregate {
+                        Aggregate::Total | Aggregate::Total2 => area / (1000 * TICKS_PER_MILLISECOND) as f64,
+                        _ => area / duration as f64
+                    };
+                    (Some(Variant::Double(value)), interval_status, start, historian_bits::CALCULATED)
+                }
+            }
+            Aggregate::Minimum | Aggregate::Maximum | Aggregate::MinimumActualTime | Aggregate::MaximumActualTime => {
+                let candidates: Vec<(i64, Variant, f64)> = raw_values.iter()
+                    .filter(|v| self.is_good(v))
+                    .filter_map(|v| match v.value {
+                        Some(ref value) => value.as_f64().map(|f| (value_ticks(v), value.clone(), f)),
+                        None => None
+                    })
+                    .collect();
+                self.extreme_result(candidates, interval_status, start)
+            }
+            Aggregate::Minimum2 | Aggregate::Maximum2 | Aggregate::MinimumActualTime2 | Aggregate::MaximumActualTime2 => {
+                let mut candidates: Vec<(i64, Variant, f64)> = Vec::new();
+                if let Some((value, _, _)) = self.simple_bound_value(values, start) {
+                    if let Some(f) = value.as_f64() {
+                        candidates.push((start, value, f));
+                    }
+                }
+                for v in raw_values.iter().filter(|v| self.is_good(v) && value_ticks(v) > start) {
+                    if let Some(ref value) = v.value {
+                        if let Some(f) = value.as_f64() {
+                            candidates.push((value_ticks(v), value.clone(), f));
+                        }
+                    }
+                }
+                self.extreme_result(candidates, interval_status, start)
+            }
+            Aggregate::Range => {
+                self.good_values_result(&good_values, interval_status, start, |values| {
+                    let (min, max) = min_max(values);
+                    Some(max - min)
+                })
+            }
+            Aggregate::Range2 => {
+                let mut values_with_bound = good_values.clone();
+                if let Some((value, _, _)) = self.simple_bound_value(values, start) {
+                    if let Some(f) = value.as_f64() {
+                        values_with_bound.insert(0, (start, f));
+                    }
+                }
+                self.good_values_result(&values_with_bound, interval_status, start, |values| {
+                    let (min, max) = min_max(values);
+                    Some(max - min)
+                })
+            }
+            Aggregate::Count => {
+                let count = raw_values.iter().filter(|v| self.is_good(v) && v.value.is_some()).count();
+                let status = if count == 0 { GoodNoData } else { interval_status };
+                (Some(Variant::Int32(count as Int32)), status, start, historian_bits::CALCULATED)
+            }
+            Aggregate::NumberOfTransitions => {
+                // Transitions from the value holding at the start count too
+                let mut previous = values.iter().rev()
+                    .find(|v| value_ticks(v) < start && self.is_good(v))
+                    .and_then(|v| v.value.clone());
+                let mut transitions = 0;
+                for v in raw_values.iter().filter(|v| self.is_good(v)) {
+                    if v.value.is_some() {
+                        if previous.is_some() && previous != v.value {
+                            transitions += 1;
+                        }
+                        previous = v.value.clone();
+                    }
+                }
+                (Some(Variant::Int32(transitions)), interval_status, start, historian_bits::CALCULATED)
+            }
+            Aggregate::DurationInStateZero | Aggregate::DurationInStateNonZero => {
+                let zero = self.aggregate == Aggregate::DurationInStateZero;
+                let duration = self.state_duration(values, start, end, zero);
+                (Some(Variant::Double(ticks_to_millis(duration))), interval_status, start, historian_bits::CALCULATED)
+            }
+            Aggregate::Start | Aggregate::End => {
+                let value = if self.aggregate == Aggregate::Start { raw_values.first() } else { raw_values.last() };
+                if let Some(value) = value {
+                    let status = value.status.unwrap_or(Good);
+                    (value.value.clone(), status, value_ticks(value), historian_bits::RAW)
+                } else {
+                    (None, BadNoData, start, historian_bits::RAW)
+                }
+            }
+            Aggregate::Delta => {
+                if good_values.is_empty() {
+                    (None, BadNoData, start, historian_bits::CALCULATED)
+                } else {
+                    let delta = good_values[good_values.len() - 1].1 - good_values[0].1;
+                    (Some(Variant::Double(delta)), interval_status, start, historian_bits::CALCULATED)
+                }
+            }
+            Aggregate::DurationGood => (Some(Variant::Double(ticks_to_millis(good_ticks))), Good, start, historian_bits::CALCULATED),
+            Aggregate::DurationBad => (Some(Variant::Double(ticks_to_millis(bad_ticks))), Good, start, historian_bits::CALCULATED),
+            Aggregate::PercentGood | Aggregate::PercentBad => {
+                let ticks = if self.aggregate == Aggregate::PercentGood { good_ticks } else { bad_ticks };
+                let total = good_ticks + bad_ticks;
+                let percent = if total > 0 { 100f64 * ticks as f64 / total as f64 } else { 0f64 };
+                (Some(Variant::Double(percent)), Good, start, historian_bits::CALCULATED)
+            }
+            Aggregate::WorstQuality | Aggregate::WorstQuality2 => {
+                let mut statuses: Vec<StatusCode> = raw_values.iter().map(|v| v.status.unwrap_or(Good)).collect();
+                if self.aggregate == Aggregate::WorstQuality2 {
+                    if let Some(v) = values.iter().rev().find(|v| value_ticks(v) < start) {
+                        statuses.push(v.status.unwrap_or(Good));
+                    }
+                }
+                if statuses.is_empty() {
+                    (None, BadNoData, start, historian_bits::CALCULATED)
+                } else {
+                    let worst = statuses.iter().fold(Good, |worst, &status| worse_status(worst, status));
+                    (Some(Variant::StatusCode(worst)), Good, start, historian_bits::CALCULATED)
+                }
+            }
+            Aggregate::StandardDeviationSample | Aggregate::StandardDeviationPopulation |
+            Aggregate::VarianceSample | Aggregate::VariancePopulation => {
+                let sample = self.aggregate == Aggregate::StandardDeviationSample || self.aggregate == Aggregate::VarianceSample;
+                let standard_deviation = self.aggregate == Aggregate::StandardDeviationSample || self.aggregate == Aggregate::StandardDeviationPopulation;
+                self.good_values_result(&good_values, interval_status, start, |values| {
+                    let n = values.len() as f64;
+                    if sample && values.len() < 2 {
+                        return None;
+                    }
+                    let mean = values.iter().map(|&(_, v)| v).sum::<f64>() / n;
+                    let sum_of_squares = values.iter().map(|&(_, v)| (v - mean) * (v - mean)).sum::<f64>();
+                    let variance = sum_of_squares / if sample { n - 1f64 } else { n };
+                    Some(if standard_deviation { variance.sqrt() } else { variance })
+                })
+            }
+        };
+
+        let bits = if partial && bits != historian_bits::RAW { bits | historian_bits::PARTIAL } else { bits };
+        AggregateValue {
+            value: DataValue {
+                value,
+                status: Some(status),
+                status_info_bits: 0,
+                source_timestamp: Some(DateTime::from(timestamp)),
+                source_picoseconds: None,
+                server_timestamp: None,
+                server_picoseconds: None,
+            },
+            historian_bits: bits,
+        }
+    }
+
+    /// Calculates the aggregate over each processing interval from the start time to the end time,
+    /// in ticks, with the processing interval in milliseconds. A processing interval of zero makes
+    /// one interval of the whole range. If the start time is after the end time, intervals run
+    /// backwards from the start time and are returned newest first. A last interval that is cut
+    /// short by the end time is partial.
+    ///
+    /// Each interval is calculated from just the raw values in time order that it and its bounds
+    /// need, so the values are scanned once rather than once per interval.
+    ///
+    /// # Errors
+    ///
+    /// BadTooManyOperations if there are more intervals than the server calculates in one read.
+    pub fn calculate_intervals(&self, values: &[DataValue], start: i64, end: i64, processing_interval: f64) -> Result<Vec<AggregateValue>, StatusCode> {
+        let length = (start - end).abs();
+        let processing_interval = processing_interval * TICKS_PER_MILLISECOND as f64;
+        let processing_interval = if processing_interval.is_nan() || processing_interval < 1f64 || processing_interval > length as f64 { length } else { processing_interval as i64 };
+        if processing_interval == 0 {
+            return Ok(Vec::new());
+        }
+        let interval_count = (length - 1) / processing_interval + 1;
+        if interval_count > constants::MAX_PROCESSED_INTERVALS as i64 {
+            error!("Processed read of {} intervals exceeds the maximum of {}", interval_count, constants::MAX_PROCESSED_INTERVALS);
+            return Err(BadTooManyOperations);
+        }
+
+        // The intervals in time order, and whether each is cut short by the end time
+        let mut intervals = Vec::with_capacity(interval_count as usize);
+        let mut offset = 0;
+        while offset < length {
+            let interval = ::std::cmp::min(processing_interval, length - offset);
+            let interval_bounds = if start <= end {
+                (start + offset, start + offset + interval)
+            } else {
+                (start - offset - interval, start - offset)
+            };
+            intervals.push((interval_bounds, interval < processing_interval));
+            offset += interval;
+        }
+        if start > end {
+            intervals.reverse();
+        }
+
+        let mut window = ValueWindow::new(values);
+        let mut result: Vec<AggregateValue> = intervals.into_iter().map(|((interval_start, interval_end), cut_short)| {
+            let mut value = self.calculate(window.values_for(self, interval_start, interval_end), interval_start, interval_end);
+            if cut_short && value.historian_bits != historian_bits::RAW {
+                value.historian_bits |= historian_bits::PARTIAL;
+            }
+            value
+        }).collect();
+        if start > end {
+            result.reverse();
+        }
+        Ok(result)
+    }
+
+    /// Finds the value at the time, in ticks, from the raw values in time order. A raw value at the
+    /// time is returned as it is, otherwise the value is interpolated as a bound is. With simple
+    /// bounds the good value before the time holds instead.
+    pub fn value_at(&self, values: &[DataValue], ticks: i64, simple_bounds: bool) -> AggregateValue {
+        let bound = if simple_bounds { self.simple_bound_value(values, ticks) } else { self.bound_value(values, ticks) };
+        let (value, status, bits) = match bound {
+            Some((value, status, interpolated)) => {
+                (Some(value), status, if interpolated { historian_bits::INTERPOLATED } else { historian_bits::RAW })
+            }
+            None => (None, BadNoData, historian_bits::INTERPOLATED)
+        };
+        AggregateValue {
+            value: DataValue {
+                value,
+                status: Some(status),
+                status_info_bits: 0,
+                source_timestamp: Some(DateTime::from(ticks)),
+                source_picoseconds: None,
+                server_timestamp: None,
+                server_picoseconds: None,
+            },
+            historian_bits: bits,
+        }
+    }
+
+    /// Finds the value at the time from the good values either side of it. A raw value at the time
+    /// is returned as it is. Otherwise numeric values are interpolated unless values are stepped,
+    /// in which case the value before holds. Past the last value, the value is extrapolated from
+    /// the slope of the last two values if the configuration asks for it, otherwise the last value
+    /// holds. Returns the value, its status and whether it was interpolated rather than raw.
+    pub fn bound_value(&self, values: &[DataValue], ticks: i64) -> Option<(Variant, StatusCode, bool)> {
+        let before_idx = values.iter().rposition(|v| value_ticks(v) <= ticks && self.is_good(v) && v.value.is_some())?;
+        let before = &values[before_idx];
+        let before_ticks = value_ticks(before);
+        let before_value = before.value.clone().unwrap();
+        if before_ticks == ticks {
+            return Some((before_value, before.status.unwrap_or(Good), false));
+        }
+
+        // Bad values skipped over between the bounds make the value uncertain
+        let after_idx = values.iter().position(|v| value_ticks(v) > ticks && self.is_good(v) && v.value.is_some());
+        let skipped_bad = {
+            let end_idx = after_idx.unwrap_or(values.len());
+            values[before_idx + 1..end_idx].iter().any(|v| !self.is_good(v))
+        };
+        let status = if skipped_bad || !before.status.unwrap_or(Good).is_good() { UncertainDataSubNormal } else { Good };
+
+        let before_f64 = before_value.as_f64();
+        if self.stepped || before_f64.is_none() {
+            return Some((before_value, status, true));
+        }
+        let before_f64 = before_f64.unwrap();
+
+        if let Some(after_idx) = after_idx {
+            let after = &values[after_idx];
+            if let Some(after_f64) = after.value.as_ref().and_then(|v| v.as_f64()) {
+                let after_ticks = value_ticks(after);
+                let status = if after.status.unwrap_or(Good).is_good() { status } else { UncertainDataSubNormal };
+                let value = before_f64 + (after_f64 - before_f64) * (ticks - before_ticks) as f64 / (after_ticks - before_ticks) as f64;
+                return Some((Variant::Double(value), status, true));
+            }
+            Some((before_value, status, true))
+        } else {
+            // Extrapolated values are uncertain
+            if self.configuration.use_sloped_extrapolation {
+                let previous = values[..before_idx].iter().rev()
+                    .find(|v| self.is_good(v) && value_ticks(v) < before_ticks && v.value.as_ref().and_then(|v| v.as_f64()).is_some());
+                if let Some(previous) = previous {
+                    let previous_ticks = value_ticks(previous);
+                    let previous_f64 = previous.value.as_ref().unwrap().as_f64().unwrap();
+                    let value = before_f64 + (before_f64 - previous_f64) * (ticks - before_ticks) as f64 / (before_ticks - previous_ticks) as f64;
+                    return Some((Variant::Double(value), UncertainDataSubNormal, true));
+                }
+            }
+            Some((before_value, UncertainDataSubNormal, true))
+        }
+    }
+
+    /// Finds the value holding at the time, i.e. the good value at or before it
+    fn simple_bound_value(&self, values: &[DataValue], ticks: i64) -> Option<(Variant, StatusCode, bool)> {
+        values.iter().rev()
+            .find(|v| value_ticks(v) <= ticks && self.is_good(v) && v.value.is_some())
+            .map(|v| (v.value.clone().unwrap(), v.status.unwrap_or(Good), value_ticks(v) != ticks))
+    }
+
+    fn bound_result(&self, values: &[DataValue], ticks: i64) -> (Option<Variant>, StatusCode, i64, u32) {
+        match self.bound_value(values, ticks) {
+            Some((value, status, interpolated)) => {
+                let bits = if interpolated { historian_bits::INTERPOLATED } else { historian_bits::RAW };
+                (Some(value), status, ticks, bits)
+            }
+            None => (None, BadNoData, ticks, historian_bits::INTERPOLATED)
+        }
+    }
+
+    /// Calculates a value from the good numeric values of the interval. There is no value if there
+    /// are no good values or the calculation cannot be made from them.
+    fn good_values_result<F>(&self, good_values: &[(i64, f64)], interval_status: StatusCode, start: i64, f: F) -> (Option<Variant>, StatusCode, i64, u32)
+        where F: Fn(&[(i64, f64)]) -> Option<f64> {
+        if good_values.is_empty() {
+            (None, BadNoData, start, historian_bits::CALCULATED)
+        } else if let Some(value) = f(good_values) {
+            (Some(Variant::Double(value)), interval_status, start, historian_bits::CALCULATED)
+        } else {
+            (None, BadAggregateInvalidInputs, start, historian_bits::CALCULATED)
+        }
+    }
+
+    /// Picks the minimum or maximum of the candidates, keeping its original type. The aggregates
+    /// that report the actual time use the time of the first occurrence and flag more than one.
+    fn extreme_result(&self, candidates: Vec<(i64, Variant, f64)>, interval_status: StatusCode, start: i64) -> (Option<Variant>, StatusCode, i64, u32) {
+        let minimum = match self.aggregate {
+            Aggregate::Minimum | Aggregate::MinimumActualTime | Aggregate::Minimum2 | Aggregate::MinimumActualTime2 => true,
+            _ => false
+        };
+        let actual_time = match self.aggregate {
+            Aggregate::MinimumActualTime | Aggregate::MaximumActualTime | Aggregate::MinimumActualTime2 | Aggregate::MaximumActualTime2 => true,
+            _ => false
+        };
+        let mut extreme: Option<&(i64, Variant, f64)> = None;
+        let mut occurrences = 0;
+        for candidate in candidates.iter() {
+            let better = match extreme {
+                None => true,
+                Some(&(_, _, e)) => if minimum { candidate.2 < e } else { candidate.2 > e }
+            };
+            if better {
+                extreme = Some(candidate);
+                occurrences = 1;
+            } else if extreme.map_or(false, |e| e.2 == candidate.2) {
+                occurrences += 1;
+            }
+        }
+        if let Some(&(ticks, ref value, _)) = extreme {
+            if actual_time {
+                let bits = if occurrences > 1 { historian_bits::CALCULATED | historian_bits::MULTI_VALUE } else { historian_bits::CALCULATED };
+                (Some(value.clone()), interval_status, ticks, bits)
+            } else {
+                (Some(value.clone()), interval_status, start, historian_bits::CALCULATED)
+            }
+        } else {
+            (None, BadNoData, start, historian_bits::CALCULATED)
+        }
+    }
+
+    /// Makes the points that a time weighted aggregate integrates over, from the bound at the start
+    /// through the good values of the interval to the bound at the end. Simple bounds are the values
+    /// holding at the start and end rather than interpolated ones.
+    fn integration_points(&self, values: &[DataValue], good_values: &[(i64, f64)], start: i64, end: i64, simple_bounds: bool) -> Vec<(i64, f64)> {
+        let bound = |ticks: i64| {
+            let bound = if simple_bounds { self.simple_bound_value(values, ticks) } else { self.bound_value(values, ticks) };
+            bound.and_then(|(value, _, _)| value.as_f64())
+        };
+        let mut points = Vec::with_capacity(good_values.len() + 2);
+        if let Some(value) = bound(start) {
+            points.push((start, value));
+        }
+        points.extend(good_values.iter().filter(|&&(ticks, _)| ticks > start).cloned());
+        if !points.is_empty() {
+            if let Some(value) = bound(end) {
+                points.push((end, value));
+            } else {
+                let last = points[points.len() - 1].1;
+                points.push((end, last));
+            }
+        }
+        points
+    }
+
+    /// Integrates the points over time, in value ticks, either stepped or sloped between points
+    fn integrate(&self, points: &[(i64, f64)]) -> f64 {
+        points.windows(2).map(|w| {
+            let (t1, v1) = w[0];
+            let (t2, v2) = w[1];
+            let duration = (t2 - t1) as f64;
+            if self.stepped { v1 * duration } else { (v1 + v2) / 2f64 * duration }
+        }).sum()
+    }
+
+    /// Tests if a value is good enough to be used by the aggregate
+    fn is_good(&self, value: &DataValue) -> bool {
+        let status = value.status.unwrap_or(Good);
+        if status.is_bad() {
+            false
+        } else if status.is_uncertain() {
+            !self.configuration.treat_uncertain_as_bad
+        } else {
+            true
+        }
+    }
+
+    /// Works out how long the interval held good and bad data, in ticks. Time in the interval before
+    /// the first value counts as bad.
+    fn quality_durations(&self, values: &[DataValue], start: i64, end: i64) -> (i64, i64) {
+        let mut good = 0;
+        let mut bad = 0;
+        self.for_each_span(values, start, end, |value, duration| {
+            if value.map_or(false, |v| self.is_good(v)) {
+                good += duration;
+            } else {
+                bad += duration;
+            }
+        });
+        (good, bad)
+    }
+
+    /// Works out how long the good values of the interval were zero, or non-zero, in ticks
+    fn state_duration(&self, values: &[DataValue], start: i64, end: i64, zero: bool) -> i64 {
+        let mut total = 0;
+        self.for_each_span(values, start, end, |value, duration| {
+            if let Some(value) = value {
+                if self.is_good(value) {
+                    if let Some(f) = value.value.as_ref().and_then(|v| v.as_f64()) {
+                        if (f == 0f64) == zero {
+                            total += duration;
+                        }
+                    }
+                }
+            }
+        });
+        total
+    }
+
+    /// Calls the function with each value holding in the interval and how long it held for
+    fn for_each_span<F>(&self, values: &[DataValue], start: i64, end: i64, mut f: F) where F: FnMut(Option<&DataValue>, i64) {
+        let mut current = values.iter().rev().find(|v| value_ticks(v) <= start);
+        let mut from = start;
+        for v in values.iter() {
+            let ticks = value_ticks(v);
+            if ticks > start && ticks < end {
+                f(current, ticks - from);
+                current = Some(v);
+                from = ticks;
+            }
+        }
+        f(current, end - from);
+    }
+
+    /// The status of a calculated value from how much of its interval held good and bad data. The
+    /// status codes have no plain bad code, so an interval without enough good data is `BadNoData`.
+    fn interval_status(&self, good_ticks: i64, bad_ticks: i64) -> StatusCode {
+        let total = good_ticks + bad_ticks;
+        if total <= 0 || good_ticks == 0 {
+            BadNoData
+        } else if bad_ticks * 100 >= self.configuration.percent_data_bad as i64 * total {
+            BadNoData
+        } else if good_ticks * 100 >= self.configuration.percent_data_good as i64 * total {
+            Good
+        } else {
+            UncertainDataSubNormal
+        }
+    }
+}
+
+/// Narrows raw values in time order to those that each of a run of consecutive intervals needs,
+/// moving forward through the values as the intervals do.
+struct ValueWindow<'a> {
+    values: &'a [DataValue],
+    /// The index of the first value after the start of the interval
+    next: usize,
+    /// The last value at or before the start of the interval
+    last: Option<usize>,
+    /// The last good value at or before the start of the interval
+    last_good: Option<usize>,
+    /// The last two good numeric values at or before the start of the interval, latest last
+    last_numeric: (Option<usize>, Option<usize>),
+    /// The index of the first good value after the end of the interval, or past the values
+    after: usize,
+}
+
+impl<'a> ValueWindow<'a> {
+    fn new(values: &'a [DataValue]) -> ValueWindow<'a> {
+        ValueWindow {
+            values,
+            next: 0,
+            last: None,
+            last_good: None,
+            last_numeric: (None, None),
+            after: 0,
+        }
+    }
+
+    /// Returns the values of the interval and the values either side of it that its bounds need,
+    /// i.e. the values holding at its start, the good values before it to interpolate or
+    /// extrapolate from and the values up to the good value after it. Intervals must not go back.
+    fn values_for(&mut self, calculator: &AggregateCalculator, start: i64, end: i64) -> &'a [DataValue] {
+        let values = self.values;
+        while self.next < values.len() && value_ticks(&values[self.next]) <= start {
+            let v = &values[self.next];
+            self.last = Some(self.next);
+            if calculator.is_good(v) && v.value.is_some() {
+                self.last_good = Some(self.next);
+                if v.value.as_ref().and_then(|value| value.as_f64()).is_some() {
+                    self.last_numeric = (self.last_numeric.1, Some(self.next));
+                }
+            }
+            self.next += 1;
+        }
+        self.after = ::std::cmp::max(self.after, self.next);
+        while self.after < values.len() {
+            let v = &values[self.after];
+            if value_ticks(v) > end && calculator.is_good(v) && v.value.is_some() {
+                break;
+            }
+            self.after += 1;
+        }
+        let from = [self.last, self.last_good, self.last_numeric.0, self.last_numeric.1].iter()
+            .filter_map(|i| *i)
+            .min()
+            .unwrap_or(self.next);
+        let to = ::std::cmp::min(self.after + 1, values.len());
+        &values[from..to]
+    }
+}
+
+fn ticks_to_millis(ticks: i64) -> f64 {
+    ticks as f64 / TICKS_PER_MILLISECOND as f64
+}
+
+fn min_max(values: &[(i64, f64)]) -> (f64, f64) {
+    values.iter().fold((::std::f64::MAX, ::std::f64::MIN), |(min, max), &(_, v)| (min.min(v), max.max(v)))
+}
+
+/// Returns the worse of two status codes, bad being worse than uncertain and uncertain worse
+/// than good
+fn worse_status(s1: StatusCode, s2: StatusCode) -> StatusCode {
+    let severity = |s: StatusCode| if s.is_bad() { 2 } else if s.is_uncertain() { 1 } else { 0 };
+    if severity(s2) > severity(s1) { s2 } else { s1 }
+}
+
+/// Calculates an aggregate over consecutive processing intervals as raw values arrive, as a
+/// monitored item with an aggregate filter does.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntervalAggregator {
+    calculator: AggregateCalculator,
+    /// The length of each interval in ticks
+    processing_interval: i64,
+    /// The start of the interval that values are being gathered for, in ticks
+    interval_start: i64,
+    /// The raw values of the interval, and those before it that bound it
+    values: Vec<DataValue>,
+}
+
+impl IntervalAggregator {
+    /// Creates an aggregator whose intervals are aligned to the start time, gathering values for
+    /// the interval that holds the time now. Times are in ticks and the processing interval is in
+    /// milliseconds.
+    pub fn new(calculator: AggregateCalculator, start_time: i64, processing_interval: f64, now: i64) -> IntervalAggregator {
+        let processing_interval = ::std::cmp::max((processing_interval * TICKS_PER_MILLISECOND as f64) as i64, 1);
+        let interval_start = if start_time < now {
+            start_time + (now - start_time) / processing_interval * processing_interval
+        } else {
+            start_time
+        };
+        IntervalAggregator {
+            calculator,
+            processing_interval,
+            interval_start,
+            values: Vec::new(),
+        }
+    }
+
+    pub fn calculator(&self) -> &AggregateCalculator {
+        &self.calculator
+    }
+
+    /// Adds a sampled value. A value with the same timestamp as the last is the same sample and is
+    /// ignored.
+    pub fn add_value(&mut self, value: DataValue) {
+        let ticks = value_ticks(&value);
+        if let Some(last) = self.values.last() {
+            if value_ticks(last) >= ticks {
+                return;
+            }
+        }
+        self.values.push(value);
+    }
+
+    /// Calculates the aggregate of each interval that has ended by now, in ticks. Values that are
+    /// no longer needed as bounds are discarded.
+    pub fn completed_intervals(&mut self, now: i64) -> Vec<AggregateValue> {
+        let mut result = Vec::new();
+        while self.interval_start + self.processing_interval <= now {
+            let end = self.interval_start + self.processing_interval;
+            result.push(self.calculator.calculate(&self.values, self.interval_start, end));
+            self.interval_start = end;
+            // The last two values before the interval are kept for bounds and extrapolation
+            let before = self.values.iter().filter(|v| value_ticks(v) < end).count();
+            if before > 2 {
+                let _ = self.values.drain(..before - 2);
+            }
+        }
+        result
+    }
+}
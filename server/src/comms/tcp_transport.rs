@@ -176,12 +176,22 @@ impl Transport for TcpTransport {
         self.client_address
     }
 
-    // Terminates the connection and the session
+    // Terminates the connection and the session. An activated session is detached rather than
+    // discarded so the client can resume it from a new connection.
     fn terminate_session(&mut self, status_code: StatusCode) {
         self.transport_state = TransportState::Finished;
         self.set_session_status(status_code);
-        let mut session = trace_write_lock_unwrap!(self.session);
-        session.set_terminated();
+        let resumable = {
+            let mut session = trace_write_lock_unwrap!(self.session);
+            let resumable = !session.terminated() && session.is_resumable();
+            session.set_terminated();
+            resumable
+        };
+        if resumable {
+            // Session lock must be released before locking server state
+            let mut server_state = trace_write_lock_unwrap!(self.server_state);
+            server_state.detach_session(self.session.clone());
+        }
     }
 
     /// Test if the connection is terminated
@@ -297,7 +307,11 @@ impl TcpTransport {
                     connection.transport_state.clone()
                 };
 
-                if connection_state.bytes_read > 0 {
+                if connection_state.bytes_read == 0 {
+                    // Reading nothing means the client has closed the connection
+                    let mut connection = trace_write_lock_unwrap!(connection_state.connection);
+                    connection.set_session_status(BadConnectionClosed);
+                } else {
                     let mut session_status_code = Good;
                     let result = connection_state.message_buffer.store_bytes(&connection_state.in_buf[..connection_state.bytes_read]);
                     if result.is_err() {
@@ -186,6 +186,18 @@ pub struct ServerConfig {
     /// Url to a discovery server - adding this string causes the server to assume you wish to
     /// register the server with a discovery server.
     pub discovery_server_url: Option<String>,
+    /// Runs the server as a local discovery server (LDS) that other servers register with and that
+    /// clients find them through. A discovery server does not register itself with another.
+    #[serde(default)]
+    pub discovery_server: bool,
+    /// Announces the server on the local network through mDNS as an `_opcua-tcp._tcp` service
+    /// and answers FindServersOnNetwork from the servers that others announce.
+    #[serde(default)]
+    pub multicast_discovery: bool,
+    /// Capability identifiers announced with the server, e.g. "DA" or "HD". When empty the server
+    /// is announced as "LDS" if it is a discovery server, otherwise "NA".
+    #[serde(default)]
+    pub server_capabilities: Vec<String>,
     /// tcp configuration information
     pub tcp_config: TcpConfig,
     /// User tokens
@@ -194,6 +206,10 @@ pub struct ServerConfig {
     pub discovery_url: String,
     /// Endpoints supported by the server
     pub endpoints: BTreeMap<String, ServerEndpoint>,
+    /// User token ids that are permitted to add and delete nodes and references through the
+    /// node management services. Use ANONYMOUS to permit anonymous users.
+    #[serde(default)]
+    pub node_management_user_token_ids: BTreeSet<String>,
     /// Maximum number of subscriptions in a session
     pub max_subscriptions: u32,
     /// Max array length in elements
@@ -221,6 +237,12 @@ impl Config for ServerConfig {
                 valid = false;
             }
         }
+        for id in &self.node_management_user_token_ids {
+            if id != ANONYMOUS_USER_TOKEN_ID && !self.user_tokens.contains_key(id) {
+                error!("Server configuration is invalid. Node management refers to user token id {} which does not exist", id);
+                valid = false;
+            }
+        }
         if self.max_array_length == 0 {
             error!("Server configuration is invalid.  Max array length is invalid");
             valid = false;
@@ -262,6 +284,9 @@ impl ServerConfig {
             pki_dir,
             create_sample_keypair: false,
             discovery_server_url,
+            discovery_server: false,
+            multicast_discovery: false,
+            server_capabilities: Vec::new(),
             tcp_config: TcpConfig {
                 host,
                 port,
@@ -270,6 +295,7 @@ impl ServerConfig {
             user_tokens,
             discovery_url,
             endpoints,
+            node_management_user_token_ids: BTreeSet::new(),
             max_array_length: opcua_types_constants::MAX_ARRAY_LENGTH,
             max_string_length: opcua_types_constants::MAX_STRING_LENGTH,
             max_byte_string_length: opcua_types_constants::MAX_BYTE_STRING_LENGTH,
@@ -319,6 +345,7 @@ impl ServerConfig {
         config.add_endpoint("basic256sha256_sign", ServerEndpoint::new_basic256sha256_sign(path, &user_token_ids));
         config.add_endpoint("basic256sha256_sign_encrypt", ServerEndpoint::new_basic256sha256_sign_encrypt(path, &user_token_ids));
         config.add_endpoint("no_access", ServerEndpoint::new_none("/noaccess", &[]));
+        config.node_management_user_token_ids.insert(sample_user_id.to_string());
         config
     }
 
@@ -326,6 +353,11 @@ impl ServerConfig {
         self.endpoints.insert(id.to_string(), endpoint);
     }
 
+    /// Test if the user token id is permitted to use the node management services
+    pub fn is_node_management_permitted(&self, user_token_id: &str) -> bool {
+        self.node_management_user_token_ids.contains(user_token_id)
+    }
+
     /// Returns a opc.tcp://server:port url that paths can be appended onto
     pub fn base_endpoint_url(&self) -> String {
         format!("opc.tcp://{}:{}", self.tcp_config.host, self.tcp_config.port)
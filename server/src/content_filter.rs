@@ -0,0 +1,508 @@
+//! Content filters select the items, e.g. events, that satisfy a set of conditions. A filter is
+//! a list of elements, each of which applies an operator to its operands. The first element is
+//! the root of the filter and the filter passes an item when the root evaluates to true.
+//!
+//! Operands are literal values, other elements of the filter, or the attributes of the item being
+//! filtered which are supplied by a `FilterTarget`. Values of different types are compared by
+//! converting the value whose type has the lower precedence to the type of the other value, as
+//! described in OPC UA Part 4. A comparison that cannot be made evaluates to null, which is
+//! neither true nor false.
+
+use std::cell::RefCell;
+use std::cmp::Ordering;
+
+use opcua_types::*;
+use opcua_types::node_ids::{DataTypeId, ObjectId};
+use opcua_types::status_codes::StatusCode;
+use opcua_types::status_codes::StatusCode::*;
+use opcua_types::service_types::{ContentFilter, ContentFilterElement, ContentFilterResult, ContentFilterElementResult, FilterOperator, LiteralOperand, ElementOperand, AttributeOperand, SimpleAttributeOperand};
+
+/// Supplies the values that the operands of a content filter refer to
+pub trait FilterTarget {
+    /// Returns the value that the simple attribute operand selects, or `Variant::Empty`
+    fn simple_attribute_value(&self, operand: &SimpleAttributeOperand) -> Variant;
+
+    /// Returns the value that the attribute operand selects, or `Variant::Empty`
+    fn attribute_value(&self, operand: &AttributeOperand) -> Variant;
+
+    /// Tests if the target is an instance of the type or one of its subtypes
+    fn is_of_type(&self, type_id: &NodeId) -> bool;
+
+    /// Tests if the target is in the view. Targets that are not nodes are not in any view.
+    fn is_in_view(&self, _view_id: &NodeId) -> bool {
+        false
+    }
+
+    /// Tests if the target is an instance of the source type that is related to an instance of
+    /// the target type by a chain of references of the reference type, no longer than the number
+    /// of hops. Targets that are not nodes are related to nothing.
+    fn is_related_to(&self, _source_type_id: &NodeId, _target_type_id: &NodeId, _reference_type_id: &NodeId, _hops: UInt32, _include_type_subtypes: bool, _include_reference_subtypes: bool) -> bool {
+        false
+    }
+}
+
+/// An operand of a filter element, decoded from its extension object
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Literal(Variant),
+    Element(usize),
+    Attribute(AttributeOperand),
+    SimpleAttribute(SimpleAttributeOperand),
+}
+
+impl Operand {
+    fn decode(operand: &ExtensionObject) -> Result<Operand, StatusCode> {
+        match operand.node_id.as_object_id() {
+            Ok(ObjectId::LiteralOperand_Encoding_DefaultBinary) => {
+                Ok(Operand::Literal(operand.decode_inner::<LiteralOperand>()?.value))
+            }
+            Ok(ObjectId::ElementOperand_Encoding_DefaultBinary) => {
+                Ok(Operand::Element(operand.decode_inner::<ElementOperand>()?.index as usize))
+            }
+            Ok(ObjectId::AttributeOperand_Encoding_DefaultBinary) => {
+                Ok(Operand::Attribute(operand.decode_inner::<AttributeOperand>()?))
+            }
+            Ok(ObjectId::SimpleAttributeOperand_Encoding_DefaultBinary) => {
+                Ok(Operand::SimpleAttribute(operand.decode_inner::<SimpleAttributeOperand>()?))
+            }
+            _ => {
+                error!("Filter operand {:?} is not a supported operand type", operand.node_id);
+                Err(BadFilterOperandInvalid)
+            }
+        }
+    }
+}
+
+/// Decodes the operands of the element
+fn decode_operands(element: &ContentFilterElement) -> Vec<Result<Operand, StatusCode>> {
+    if let Some(ref operands) = element.filter_operands {
+        operands.iter().map(|operand| Operand::decode(operand)).collect()
+    } else {
+        Vec::new()
+    }
+}
+
+/// Tests that a simple attribute operand can be used to select a value
+pub fn validate_simple_attribute_operand(operand: &SimpleAttributeOperand) -> StatusCode {
+    if AttributeId::from_u32(operand.attribute_id).is_err() {
+        BadAttributeIdInvalid
+    } else if !operand.index_range.is_null() {
+        // Index ranges of values are not supported
+        BadIndexRangeInvalid
+    } else if let Some(ref browse_path) = operand.browse_path {
+        if browse_path.iter().any(|name| name.name.is_null()) {
+            BadBrowseNameInvalid
+        } else {
+            Good
+        }
+    } else {
+        Good
+    }
+}
+
+/// Validates the elements of the filter and their operands. If any element is invalid, the error
+/// holds the result of each element with the status of each operand of the invalid ones.
+pub fn validate(content_filter: &ContentFilter) -> Result<(), ContentFilterResult> {
+    let elements: &[ContentFilterElement] = if let Some(ref elements) = content_filter.elements { elements } else { &[] };
+    let element_results: Vec<ContentFilterElementResult> = elements.iter().enumerate().map(|(index, element)| {
+        validate_element(elements.len(), index, element)
+    }).collect();
+    if element_results.iter().any(|r| r.status_code.is_bad()) {
+        Err(ContentFilterResult {
+            element_results: Some(element_results),
+            element_diagnostic_infos: None,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Validates an element which is at the index of a filter with the number of elements
+fn validate_element(num_elements: usize, index: usize, element: &ContentFilterElement) -> ContentFilterElementResult {
+    let operands = decode_operands(element);
+
+    // Check the number of operands suits the operator
+    let (min_operands, max_operands) = match element.filter_operator {
+        FilterOperator::IsNull | FilterOperator::Not | FilterOperator::InView | FilterOperator::OfType => (1, 1),
+        FilterOperator::Between => (3, 3),
+        FilterOperator::InList => (2, usize::max_value()),
+        FilterOperator::RelatedTo => (4, 6),
+        _ => (2, 2)
+    };
+    if operands.len() < min_operands || operands.len() > max_operands {
+        return ContentFilterElementResult {
+            status_code: BadFilterOperandCountMismatch,
+            operand_status_codes: None,
+            operand_diagnostic_infos: None,
+        };
+    }
+
+    let operand_status_codes: Vec<StatusCode> = operands.iter().enumerate().map(|(i, operand)| {
+        match *operand {
+            Err(status_code) => status_code,
+            // Elements may only refer to elements after them so that a filter has no loops
+            Ok(Operand::Element(element_index)) => if element_index > index && element_index < num_elements {
+                Good
+            } else {
+                BadFilterElementInvalid
+            },
+            Ok(Operand::Attribute(ref operand)) => if AttributeId::from_u32(operand.attribute_id).is_err() {
+                BadAttributeIdInvalid
+            } else {
+                Good
+            },
+            Ok(Operand::SimpleAttribute(ref operand)) => validate_simple_attribute_operand(operand),
+            Ok(Operand::Literal(ref value)) => {
+                // Types, views and data types are identified by node ids
+                let expects_node_id = match element.filter_operator {
+                    FilterOperator::OfType | FilterOperator::InView => true,
+                    FilterOperator::Cast => i == 1,
+                    FilterOperator::RelatedTo => i < 3,
+                    _ => false
+                };
+                if expects_node_id && value.convert(DataTypeId::NodeId) == Variant::Empty {
+                    BadFilterLiteralInvalid
+                } else {
+                    Good
+                }
+            }
+        }
+    }).collect();
+
+    if let Some(status_code) = operand_status_codes.iter().find(|s| s.is_bad()) {
+        let status_code = if *status_code == BadFilterElementInvalid { BadFilterElementInvalid } else { BadFilterOperandInvalid };
+        ContentFilterElementResult {
+            status_code,
+            operand_status_codes: Some(operand_status_codes),
+            operand_diagnostic_infos: None,
+        }
+    } else {
+        ContentFilterElementResult {
+            status_code: Good,
+            operand_status_codes: None,
+            operand_diagnostic_infos: None,
+        }
+    }
+}
+
+/// Evaluates the filter against the target, returning true if the target passes. A filter with
+/// no elements passes every target.
+pub fn evaluate<T>(content_filter: &ContentFilter, target: &T) -> bool where T: FilterTarget {
+    match content_filter.elements {
+        Some(ref elements) if !elements.is_empty() => {
+            let results = RefCell::new(vec![None; elements.len()]);
+            evaluate_element(elements, 0, target, &results) == Variant::Boolean(true)
+        }
+        _ => true
+    }
+}
+
+/// Evaluates the element at the index, returning its value. Elements may be the operand of more
+/// than one element so the value of each element is kept in the results and only evaluated once.
+fn evaluate_element<T>(elements: &[ContentFilterElement], index: usize, target: &T, results: &RefCell<Vec<Option<Variant>>>) -> Variant where T: FilterTarget {
+    if let Some(ref value) = results.borrow()[index] {
+        return value.clone();
+    }
+    let value = element_value(elements, index, target, results);
+    results.borrow_mut()[index] = Some(value.clone());
+    value
+}
+
+/// Works out the value of the element at the index. Operators that test something produce a
+/// boolean or `Variant::Empty` when the result is null.
+fn element_value<T>(elements: &[ContentFilterElement], index: usize, target: &T, results: &RefCell<Vec<Option<Variant>>>) -> Variant where T: FilterTarget {
+    let element = &elements[index];
+    let operands: Vec<Operand> = match decode_operands(element).into_iter().collect() {
+        Ok(operands) => operands,
+        Err(_) => return Variant::Empty
+    };
+    let value = |i: usize| -> Variant {
+        match operands.get(i) {
+            Some(&Operand::Literal(ref value)) => value.clone(),
+            Some(&Operand::Element(element_index)) if element_index > index && element_index < elements.len() => {
+                evaluate_element(elements, element_index, target, results)
+            }
+            Some(&Operand::Attribute(ref operand)) => target.attribute_value(operand),
+            Some(&Operand::SimpleAttribute(ref operand)) => target.simple_attribute_value(operand),
+            _ => Variant::Empty
+        }
+    };
+    let node_id = |i: usize| -> Option<NodeId> {
+        if let Variant::NodeId(node_id) = value(i).convert(DataTypeId::NodeId) { Some(*node_id) } else { None }
+    };
+
+    let result = match element.filter_operator {
+        FilterOperator::Equals => equals(&value(0), &value(1)),
+        FilterOperator::IsNull => Some(value(0) == Variant::Empty),
+        FilterOperator::GreaterThan => compare(&value(0), &value(1)).map(|o| o == Ordering::Greater),
+        FilterOperator::LessThan => compare(&value(0), &value(1)).map(|o| o == Ordering::Less),
+        FilterOperator::GreaterThanOrEqual => compare(&value(0), &value(1)).map(|o| o != Ordering::Less),
+        FilterOperator::LessThanOrEqual => compare(&value(0), &value(1)).map(|o| o != Ordering::Greater),
+        FilterOperator::Like => {
+            match (value(0).convert(DataTypeId::String), value(1).convert(DataTypeId::String)) {
+                (Variant::String(ref v), Variant::String(ref pattern)) if !v.is_null() && !pattern.is_null() => {
+                    let v: Vec<char> = v.as_ref().chars().collect();
+                    let pattern: Vec<char> = pattern.as_ref().chars().collect();
+                    Some(like(&v, &pattern))
+                }
+                _ => None
+            }
+        }
+        FilterOperator::Not => as_bool(&value(0)).map(|v| !v),
+        FilterOperator::Between => {
+            let v = value(0);
+            match (compare(&v, &value(1)), compare(&v, &value(2))) {
+                (Some(low), Some(high)) => Some(low != Ordering::Less && high != Ordering::Greater),
+                _ => None
+            }
+        }
+        FilterOperator::InList => {
+            let v = value(0);
+            if v == Variant::Empty {
+                None
+            } else {
+                Some((1..operands.len()).any(|i| equals(&v, &value(i)) == Some(true)))
+            }
+        }
+        FilterOperator::And => match (as_bool(&value(0)), as_bool(&value(1))) {
+            (Some(false), _) | (_, Some(false)) => Some(false),
+            (Some(true), Some(true)) => Some(true),
+            _ => None
+        },
+        FilterOperator::Or => match (as_bool(&value(0)), as_bool(&value(1))) {
+            (Some(true), _) | (_, Some(true)) => Some(true),
+            (Some(false), Some(false)) => Some(false),
+            _ => None
+        },
+        FilterOperator::Cast => {
+            // Cast produces a value rather than a boolean
+            return match node_id(1).and_then(|data_type| data_type.as_data_type_id().ok()) {
+                Some(data_type) => value(0).convert(data_type),
+                None => Variant::Empty
+            };
+        }
+        FilterOperator::InView => node_id(0).map(|view_id| target.is_in_view(&view_id)),
+        FilterOperator::OfType => node_id(0).map(|type_id| target.is_of_type(&type_id)),
+        FilterOperator::RelatedTo => {
+            let hops = if let Variant::UInt32(hops) = value(3).convert(DataTypeId::UInt32) { Some(hops) } else { None };
+            // Subtypes are included unless the optional operands say otherwise
+            let include_type_subtypes = operands.len() < 5 || as_bool(&value(4)).unwrap_or(true);
+            let include_reference_subtypes = operands.len() < 6 || as_bool(&value(5)).unwrap_or(true);
+            match (node_id(0), node_id(1), node_id(2), hops) {
+                (Some(source_type_id), Some(target_type_id), Some(reference_type_id), Some(hops)) => {
+                    Some(target.is_related_to(&source_type_id, &target_type_id, &reference_type_id, hops, include_type_subtypes, include_reference_subtypes))
+                }
+                _ => None
+            }
+        }
+        FilterOperator::BitwiseAnd | FilterOperator::BitwiseOr => {
+            // Bitwise operators produce a value rather than a boolean
+            let is_and = element.filter_operator == FilterOperator::BitwiseAnd;
+            return match coerce(&value(0), &value(1)) {
+                Some((a, b)) => bitwise(&a, &b, is_and),
+                None => Variant::Empty
+            };
+        }
+    };
+    result.map(Variant::Boolean).unwrap_or(Variant::Empty)
+}
+
+/// Returns the precedence of the value's type when values of different types are compared. The
+/// value whose type has the lower precedence (a higher number) is converted to the other type.
+/// Values of other types can only be compared to values of the same type.
+fn precedence(value: &Variant) -> Option<u8> {
+    let precedence = match *value {
+        Variant::Double(_) => 1,
+        Variant::Float(_) => 2,
+        Variant::Int64(_) => 3,
+        Variant::UInt64(_) => 4,
+        Variant::Int32(_) => 5,
+        Variant::UInt32(_) => 6,
+        Variant::StatusCode(_) => 7,
+        Variant::Int16(_) => 8,
+        Variant::UInt16(_) => 9,
+        Variant::SByte(_) => 10,
+        Variant::Byte(_) => 11,
+        Variant::Boolean(_) => 12,
+        Variant::Guid(_) => 13,
+        Variant::String(_) => 14,
+        Variant::ExpandedNodeId(_) => 15,
+        Variant::NodeId(_) => 16,
+        Variant::LocalizedText(_) => 17,
+        Variant::QualifiedName(_) => 18,
+        _ => return None
+    };
+    Some(precedence)
+}
+
+/// Converts the two values to the same type so they can be compared, or returns `None` if that
+/// is not possible
+fn coerce(a: &Variant, b: &Variant) -> Option<(Variant, Variant)> {
+    if *a == Variant::Empty || *b == Variant::Empty {
+        return None;
+    }
+    let (a_type, b_type) = (a.data_type(), b.data_type());
+    if a_type.is_some() && a_type == b_type {
+        return Some((a.clone(), b.clone()));
+    }
+    let (a, b) = match (precedence(a), precedence(b), a_type, b_type) {
+        (Some(a_precedence), Some(b_precedence), Some(a_type), _) if a_precedence < b_precedence => (a.clone(), b.convert(a_type)),
+        (Some(_), Some(_), _, Some(b_type)) => (a.convert(b_type), b.clone()),
+        _ => return None
+    };
+    if a == Variant::Empty || b == Variant::Empty { None } else { Some((a, b)) }
+}
+
+/// Tests if the values are equal, or returns `None` if they cannot be compared
+fn equals(a: &Variant, b: &Variant) -> Option<bool> {
+    coerce(a, b).map(|(a, b)| a == b)
+}
+
+/// Orders the values, or returns `None` if they cannot be ordered
+fn compare(a: &Variant, b: &Variant) -> Option<Ordering> {
+    let (a, b) = coerce(a, b)?;
+    match (&a, &b) {
+        (&Variant::Int64(a), &Variant::Int64(b)) => Some(a.cmp(&b)),
+        (&Variant::UInt64(a), &Variant::UInt64(b)) => Some(a.cmp(&b)),
+        (&Variant::Boolean(a), &Variant::Boolean(b)) => Some(a.cmp(&b)),
+        (&Variant::StatusCode(a), &Variant::StatusCode(b)) => Some((a as u32).cmp(&(b as u32))),
+        (&Variant::String(ref a), &Variant::String(ref b)) => Some(a.as_ref().cmp(b.as_ref())),
+        (&Variant::DateTime(ref a), &Variant::DateTime(ref b)) => Some(a.ticks().cmp(&b.ticks())),
+        _ => if a.is_numeric() && b.is_numeric() {
+            a.as_f64().unwrap().partial_cmp(&b.as_f64().unwrap())
+        } else {
+            None
+        }
+    }
+}
+
+/// Converts the value to a boolean for the logical operators, or `None` if it is null
+fn as_bool(value: &Variant) -> Option<bool> {
+    if let Variant::Boolean(value) = value.convert(DataTypeId::Boolean) { Some(value) } else { None }
+}
+
+/// Applies a bitwise and / or to two integers of the same type
+fn bitwise(a: &Variant, b: &Variant, is_and: bool) -> Variant {
+    macro_rules! bitwise_op {
+        ( $variant: ident, $a: expr, $b: expr ) => {
+            Variant::$variant(if is_and { $a & $b } else { $a | $b })
+        }
+    }
+    match (a, b) {
+        (&Variant::SByte(a), &Variant::SByte(b)) => bitwise_op!(SByte, a, b),
+        (&Variant::Byte(a), &Variant::Byte(b)) => bitwise_op!(Byte, a, b),
+        (&Variant::Int16(a), &Variant::Int16(b)) => bitwise_op!(Int16, a, b),
+        (&Variant::UInt16(a), &Variant::UInt16(b)) => bitwise_op!(UInt16, a, b),
+        (&Variant::Int32(a), &Variant::Int32(b)) => bitwise_op!(Int32, a, b),
+        (&Variant::UInt32(a), &Variant::UInt32(b)) => bitwise_op!(UInt32, a, b),
+        (&Variant::Int64(a), &Variant::Int64(b)) => bitwise_op!(Int64, a, b),
+        (&Variant::UInt64(a), &Variant::UInt64(b)) => bitwise_op!(UInt64, a, b),
+        _ => Variant::Empty
+    }
+}
+
+/// A part of a Like pattern
+#[derive(Debug, PartialEq)]
+enum LikeToken<'a> {
+    /// `%`, any number of characters
+    AnyChars,
+    /// `_`, any one character
+    AnyChar,
+    /// Any one of the characters or ranges of characters in the set, or any one character not in
+    /// it if the set is negated
+    Set(&'a [char], bool),
+    /// The character itself
+    Char(char),
+}
+
+impl<'a> LikeToken<'a> {
+    /// Tests if the token matches the character. `%` is matched by `like()` itself.
+    fn matches(&self, c: char) -> bool {
+        match *self {
+            LikeToken::AnyChars => false,
+            LikeToken::AnyChar => true,
+            LikeToken::Set(set, negate) => like_set(c, set) != negate,
+            LikeToken::Char(token_c) => c == token_c,
+        }
+    }
+}
+
+/// Splits a Like pattern into its tokens. A run of `%` is the same as one `%` so it becomes one
+/// token.
+fn like_tokens(pattern: &[char]) -> Vec<LikeToken> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < pattern.len() {
+        let (token, token_len) = match pattern[i] {
+            '%' => (LikeToken::AnyChars, 1),
+            '_' => (LikeToken::AnyChar, 1),
+            '\\' if i + 1 < pattern.len() => (LikeToken::Char(pattern[i + 1]), 2),
+            '[' if i + 2 < pattern.len() => {
+                // The first character of the set may be a ']' so search for the end after it
+                let negate = pattern[i + 1] == '^';
+                let start = if negate { i + 2 } else { i + 1 };
+                match pattern.iter().skip(start + 1).position(|&c| c == ']').map(|end| end + start + 1) {
+                    Some(end) => (LikeToken::Set(&pattern[start..end], negate), end + 1 - i),
+                    None => (LikeToken::Char('['), 1)
+                }
+            }
+            c => (LikeToken::Char(c), 1)
+        };
+        if token != LikeToken::AnyChars || tokens.last() != Some(&LikeToken::AnyChars) {
+            tokens.push(token);
+        }
+        i += token_len;
+    }
+    tokens
+}
+
+/// Matches the value against a Like pattern. In the pattern `%` matches any number of
+/// characters, `_` matches any one character, `[]` matches any one of the characters or ranges
+/// of characters inside it, `[^]` matches any one character not inside it, and `\` matches the
+/// character after it literally.
+///
+/// Every token other than `%` matches one character, so when a token does not match, only the
+/// last `%` needs to match one more character. The match takes at most the length of the value
+/// times the length of the pattern steps.
+fn like(value: &[char], pattern: &[char]) -> bool {
+    let tokens = like_tokens(pattern);
+    let (mut v, mut t) = (0, 0);
+    // The token after the last `%` and the position in the value that it was matched from
+    let mut last_any_chars: Option<(usize, usize)> = None;
+    while v < value.len() {
+        if t < tokens.len() && tokens[t] == LikeToken::AnyChars {
+            t += 1;
+            last_any_chars = Some((t, v));
+        } else if t < tokens.len() && tokens[t].matches(value[v]) {
+            t += 1;
+            v += 1;
+        } else if let Some((any_chars_t, any_chars_v)) = last_any_chars {
+            t = any_chars_t;
+            v = any_chars_v + 1;
+            last_any_chars = Some((any_chars_t, v));
+        } else {
+            return false;
+        }
+    }
+    // Only `%` can match the end of the value
+    tokens[t..].iter().all(|token| *token == LikeToken::AnyChars)
+}
+
+/// Tests if the character is one of the characters or ranges of characters in the set
+fn like_set(c: char, set: &[char]) -> bool {
+    let mut i = 0;
+    while i < set.len() {
+        if i + 2 < set.len() && set[i + 1] == '-' {
+            if c >= set[i] && c <= set[i + 2] {
+                return true;
+            }
+            i += 3;
+        } else {
+            if c == set[i] {
+                return true;
+            }
+            i += 1;
+        }
+    }
+    false
+}
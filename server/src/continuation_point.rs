@@ -1,7 +1,8 @@
+use std::collections::VecDeque;
 use std::sync::{Arc, Mutex};
 
-use opcua_types::{ByteString};
-use opcua_types::service_types::ReferenceDescription;
+use opcua_types::{ByteString, DataValue, NodeId};
+use opcua_types::service_types::{ContentFilter, ModificationInfo, NodeTypeDescription, ReferenceDescription, ViewDescription};
 
 use DateTimeUtc;
 
@@ -22,4 +23,27 @@ impl BrowseContinuationPoint {
     pub fn is_valid_browse_continuation_point(&self, address_space: &AddressSpace) -> bool {
         self.address_space_last_modified >= address_space.last_modified
     }
-}
\ No newline at end of file
+}
+
+/// Holds the values of a HistoryRead that are yet to be returned to the client
+#[derive(Clone)]
+pub struct HistoryContinuationPoint {
+    pub id: ByteString,
+    pub node_id: NodeId,
+    pub num_values_per_node: usize,
+    pub data_values: Vec<DataValue>,
+    /// Modification infos of the data values, if this is a read of modified values
+    pub modification_infos: Option<Vec<ModificationInfo>>,
+}
+
+/// Holds a query whose data sets are yet to be returned to the client, with the nodes that are
+/// yet to be examined
+#[derive(Clone)]
+pub struct QueryContinuationPoint {
+    pub id: ByteString,
+    pub view: ViewDescription,
+    pub node_types: Vec<NodeTypeDescription>,
+    pub filter: ContentFilter,
+    pub max_data_sets_to_return: usize,
+    pub candidates: VecDeque<NodeId>,
+}
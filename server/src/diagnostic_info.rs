@@ -0,0 +1,201 @@
+//! Diagnostics returned to a client with the response to a request. A client asks for them through
+//! the `return_diagnostics` mask of the request header, for the service as a whole and / or for
+//! each operation of the service.
+
+use opcua_types::*;
+use opcua_types::status_codes::StatusCode;
+use opcua_types::service_types::*;
+
+/// Namespace of the symbolic ids that name the standard status codes
+const UA_NAMESPACE_URI: &str = "http://opcfoundation.org/UA/";
+
+/// Operation level bits of the return diagnostics mask are the service level bits shifted by this
+/// many places.
+const OPERATION_LEVEL_SHIFT: u32 = 5;
+
+/// Mask of the service level bits of the return diagnostics mask
+const SERVICE_LEVEL_MASK: u32 = 0x1f;
+
+/// Describes why a service or an operation failed. Service code, attribute getters and attribute
+/// setters can return one to say more about a failure than the status code does. Anything left
+/// unset is filled in from the status code if the client asked for it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// The status code of the failure
+    pub status_code: StatusCode,
+    /// A symbolic id for the failure, in place of the status code's name
+    pub symbolic_id: Option<String>,
+    /// The namespace that qualifies the symbolic id
+    pub namespace_uri: Option<String>,
+    /// Human readable text for the failure, in place of the status code's description
+    pub localized_text: Option<LocalizedText>,
+    /// Detailed application specific information, e.g. an error from a device
+    pub additional_info: Option<String>,
+    /// A status code reported by an underlying system
+    pub inner_status_code: Option<StatusCode>,
+    /// Diagnostics of the underlying system
+    pub inner_diagnostic: Option<Box<Diagnostic>>,
+}
+
+impl From<StatusCode> for Diagnostic {
+    fn from(status_code: StatusCode) -> Self {
+        Diagnostic::new(status_code)
+    }
+}
+
+impl Diagnostic {
+    pub fn new(status_code: StatusCode) -> Diagnostic {
+        Diagnostic {
+            status_code,
+            symbolic_id: None,
+            namespace_uri: None,
+            localized_text: None,
+            additional_info: None,
+            inner_status_code: None,
+            inner_diagnostic: None,
+        }
+    }
+
+    pub fn with_symbolic_id(mut self, namespace_uri: &str, symbolic_id: &str) -> Diagnostic {
+        self.namespace_uri = Some(namespace_uri.to_string());
+        self.symbolic_id = Some(symbolic_id.to_string());
+        self
+    }
+
+    pub fn with_localized_text(mut self, localized_text: LocalizedText) -> Diagnostic {
+        self.localized_text = Some(localized_text);
+        self
+    }
+
+    pub fn with_additional_info(mut self, additional_info: &str) -> Diagnostic {
+        self.additional_info = Some(additional_info.to_string());
+        self
+    }
+
+    pub fn with_inner_status_code(mut self, inner_status_code: StatusCode) -> Diagnostic {
+        self.inner_status_code = Some(inner_status_code);
+        self
+    }
+
+    pub fn with_inner_diagnostic(mut self, inner_diagnostic: Diagnostic) -> Diagnostic {
+        self.inner_diagnostic = Some(Box::new(inner_diagnostic));
+        self
+    }
+}
+
+/// Builds the diagnostic infos of a response and the string table that their ids index into,
+/// according to the diagnostics the client asked for.
+pub struct ResponseDiagnostics {
+    /// The return diagnostics mask from the request header
+    return_diagnostics: UInt32,
+    /// Strings referenced by the diagnostic infos
+    string_table: Vec<String>,
+}
+
+impl ResponseDiagnostics {
+    pub fn new(request_header: &RequestHeader) -> ResponseDiagnostics {
+        ResponseDiagnostics {
+            return_diagnostics: request_header.return_diagnostics,
+            string_table: Vec::new(),
+        }
+    }
+
+    /// Makes a service fault for the request, with service level diagnostics for the status code
+    pub fn service_fault(request_header: &RequestHeader, service_result: StatusCode) -> SupportedMessage {
+        let response_header = ResponseDiagnostics::new(request_header).response_header(request_header, service_result, None);
+        ServiceFault { response_header }.into()
+    }
+
+    /// Makes the response header, with service level diagnostics and the string table. A bad
+    /// service result without a diagnostic is described by its status code.
+    pub fn response_header(mut self, request_header: &RequestHeader, service_result: StatusCode, diagnostic: Option<&Diagnostic>) -> ResponseHeader {
+        let mut response_header = ResponseHeader::new_service_result(request_header, service_result);
+        let mask = self.return_diagnostics & SERVICE_LEVEL_MASK;
+        if mask != 0 {
+            let service_diagnostic = match diagnostic {
+                Some(diagnostic) => Some(diagnostic.clone()),
+                None if service_result.is_bad() => Some(Diagnostic::new(service_result)),
+                None => None
+            };
+            if let Some(service_diagnostic) = service_diagnostic {
+                response_header.service_diagnostics = self.diagnostic_info(mask, &service_diagnostic);
+            }
+        }
+        if !self.string_table.is_empty() {
+            response_header.string_table = Some(self.string_table.into_iter().map(UAString::from).collect());
+        }
+        response_header
+    }
+
+    /// Makes the diagnostic infos for the operations of a service, one per operation in the same
+    /// order. Nothing is returned if the client did not ask for operation diagnostics or none of
+    /// the operations have any.
+    pub fn operation_diagnostic_infos(&mut self, diagnostics: &[Option<Diagnostic>]) -> Option<Vec<DiagnosticInfo>> {
+        let mask = (self.return_diagnostics >> OPERATION_LEVEL_SHIFT) & SERVICE_LEVEL_MASK;
+        if mask == 0 || diagnostics.iter().all(|d| d.is_none()) {
+            None
+        } else {
+            Some(diagnostics.iter().map(|diagnostic| {
+                if let Some(ref diagnostic) = *diagnostic {
+                    self.diagnostic_info(mask, diagnostic)
+                } else {
+                    DiagnosticInfo::new()
+                }
+            }).collect())
+        }
+    }
+
+    /// Makes a diagnostic for each bad status code of a service's operations
+    pub fn operation_status_diagnostics(results: &[StatusCode]) -> Vec<Option<Diagnostic>> {
+        results.iter().map(|status_code| {
+            if status_code.is_bad() { Some(Diagnostic::new(*status_code)) } else { None }
+        }).collect()
+    }
+
+    /// Makes the diagnostic info holding the parts of the diagnostic asked for by the mask. The
+    /// mask is in terms of the service level bits.
+    fn diagnostic_info(&mut self, mask: UInt32, diagnostic: &Diagnostic) -> DiagnosticInfo {
+        let mut diagnostic_info = DiagnosticInfo::new();
+        if mask & DiagnosticFlags::SERVICE_LEVEL_SYMBOLIC_ID != 0 {
+            if let Some(ref symbolic_id) = diagnostic.symbolic_id {
+                diagnostic_info.symbolic_id = Some(self.add_string(symbolic_id));
+                diagnostic_info.namespace_uri = diagnostic.namespace_uri.as_ref().map(|namespace_uri| self.add_string(namespace_uri));
+            } else {
+                diagnostic_info.symbolic_id = Some(self.add_string(diagnostic.status_code.name()));
+                diagnostic_info.namespace_uri = Some(self.add_string(UA_NAMESPACE_URI));
+            }
+        }
+        if mask & DiagnosticFlags::SERVICE_LEVEL_LOCALIZED_TEXT != 0 {
+            if let Some(ref localized_text) = diagnostic.localized_text {
+                if !localized_text.locale.as_ref().is_empty() {
+                    diagnostic_info.locale = Some(self.add_string(localized_text.locale.as_ref()));
+                }
+                diagnostic_info.localized_text = Some(self.add_string(localized_text.text.as_ref()));
+            } else {
+                diagnostic_info.localized_text = Some(self.add_string(diagnostic.status_code.description()));
+            }
+        }
+        if mask & DiagnosticFlags::SERVICE_LEVEL_ADDITIONAL_INFO != 0 {
+            diagnostic_info.additional_info = diagnostic.additional_info.as_ref().map(|additional_info| UAString::from(additional_info.as_str()));
+        }
+        if mask & DiagnosticFlags::SERVICE_LEVEL_INNER_STATUS_CODE != 0 {
+            diagnostic_info.inner_status_code = diagnostic.inner_status_code;
+        }
+        if mask & DiagnosticFlags::SERVICE_LEVEL_INNER_DIAGNOSTICS != 0 {
+            if let Some(ref inner_diagnostic) = diagnostic.inner_diagnostic {
+                diagnostic_info.inner_diagnostic_info = Some(Box::new(self.diagnostic_info(mask, inner_diagnostic)));
+            }
+        }
+        diagnostic_info
+    }
+
+    /// Adds a string to the string table, returning its index. Strings are only held once.
+    fn add_string(&mut self, value: &str) -> Int32 {
+        if let Some(idx) = self.string_table.iter().position(|s| s == value) {
+            idx as Int32
+        } else {
+            self.string_table.push(value.to_string());
+            (self.string_table.len() - 1) as Int32
+        }
+    }
+}
@@ -1,15 +1,218 @@
-use opcua_types::UInt32;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use opcua_types::*;
+use opcua_types::node_ids::ObjectId;
+use opcua_types::profiles;
+use opcua_types::service_types::{ServerDiagnosticsSummaryDataType, ServiceCounterDataType, SessionDiagnosticsDataType, SessionSecurityDiagnosticsDataType, SubscriptionDiagnosticsDataType};
+use opcua_types::status_codes::StatusCode;
+use opcua_types::status_codes::StatusCode::*;
 
 use subscriptions::subscription::Subscription;
 use session::Session;
 
+/// Diagnostics of a session, as exposed through the address space
+#[derive(Clone, Debug)]
+struct SessionRecord {
+    diagnostics: SessionDiagnosticsDataType,
+    security_diagnostics: SessionSecurityDiagnosticsDataType,
+}
+
+impl SessionRecord {
+    fn new(session: &Session) -> SessionRecord {
+        let now = DateTime::now();
+        SessionRecord {
+            diagnostics: SessionDiagnosticsDataType {
+                session_id: session.session_id.clone(),
+                session_name: session.session_name.clone(),
+                client_description: session.client_description.clone(),
+                server_uri: UAString::null(),
+                endpoint_url: session.endpoint_url.clone(),
+                locale_ids: None,
+                actual_session_timeout: session.session_timeout,
+                max_response_message_size: session.max_response_message_size,
+                client_connection_time: now.clone(),
+                client_last_contact_time: now,
+                current_subscriptions_count: 0,
+                current_monitored_items_count: 0,
+                current_publish_requests_in_queue: 0,
+                total_request_count: new_service_counter(),
+                unauthorized_request_count: 0,
+                read_count: new_service_counter(),
+                history_read_count: new_service_counter(),
+                write_count: new_service_counter(),
+                history_update_count: new_service_counter(),
+                call_count: new_service_counter(),
+                create_monitored_items_count: new_service_counter(),
+                modify_monitored_items_count: new_service_counter(),
+                set_monitoring_mode_count: new_service_counter(),
+                set_triggering_count: new_service_counter(),
+                delete_monitored_items_count: new_service_counter(),
+                create_subscription_count: new_service_counter(),
+                modify_subscription_count: new_service_counter(),
+                set_publishing_mode_count: new_service_counter(),
+                publish_count: new_service_counter(),
+                republish_count: new_service_counter(),
+                transfer_subscriptions_count: new_service_counter(),
+                delete_subscriptions_count: new_service_counter(),
+                add_nodes_count: new_service_counter(),
+                add_references_count: new_service_counter(),
+                delete_nodes_count: new_service_counter(),
+                delete_references_count: new_service_counter(),
+                browse_count: new_service_counter(),
+                browse_next_count: new_service_counter(),
+                translate_browse_paths_to_node_ids_count: new_service_counter(),
+                query_first_count: new_service_counter(),
+                query_next_count: new_service_counter(),
+                register_nodes_count: new_service_counter(),
+                unregister_nodes_count: new_service_counter(),
+            },
+            security_diagnostics: SessionSecurityDiagnosticsDataType {
+                session_id: session.session_id.clone(),
+                client_user_id_of_session: UAString::null(),
+                client_user_id_history: None,
+                authentication_mechanism: UAString::null(),
+                encoding: UAString::from("UA Binary"),
+                transport_protocol: UAString::from(profiles::TRANSPORT_PROFILE_URI_BINARY),
+                security_mode: session.secure_channel.security_mode(),
+                security_policy_uri: UAString::from(session.security_policy_uri.as_str()),
+                client_certificate: ByteString::null(),
+            },
+        }
+    }
+
+    /// Updates the values that change over the life of the session
+    fn update(&mut self, session: &Session) {
+        let diagnostics = &mut self.diagnostics;
+        diagnostics.session_name = session.session_name.clone();
+        diagnostics.client_description = session.client_description.clone();
+        diagnostics.locale_ids = session.locale_ids.clone();
+        diagnostics.actual_session_timeout = session.session_timeout;
+        diagnostics.current_subscriptions_count = session.subscriptions.len() as UInt32;
+        diagnostics.current_monitored_items_count = session.subscriptions.subscriptions().values()
+            .map(|subscription| subscription.monitored_items.len() as UInt32)
+            .sum();
+        diagnostics.current_publish_requests_in_queue = session.subscriptions.publish_request_queue.len() as UInt32;
+
+        let security_diagnostics = &mut self.security_diagnostics;
+        let user_id = match session.user_token_id {
+            Some(ref user_token_id) => UAString::from(user_token_id.as_str()),
+            None => UAString::null()
+        };
+        if !user_id.is_null() && user_id != security_diagnostics.client_user_id_of_session {
+            // Every user that the session has been activated with is remembered
+            let mut client_user_id_history = security_diagnostics.client_user_id_history.take().unwrap_or(Vec::new());
+            client_user_id_history.push(user_id.clone());
+            security_diagnostics.client_user_id_history = Some(client_user_id_history);
+            security_diagnostics.client_user_id_of_session = user_id;
+        }
+        security_diagnostics.authentication_mechanism = UAString::from(authentication_mechanism(&session.user_identity));
+        security_diagnostics.client_certificate = match session.client_certificate {
+            Some(ref client_certificate) => client_certificate.as_byte_string(),
+            None => ByteString::null()
+        };
+    }
+
+    /// Returns the counter of the service that the request is for
+    fn service_counter_mut(&mut self, request_object_id: ObjectId) -> Option<&mut ServiceCounterDataType> {
+        let diagnostics = &mut self.diagnostics;
+        let service_counter = match request_object_id {
+            ObjectId::ReadRequest_Encoding_DefaultBinary => &mut diagnostics.read_count,
+            ObjectId::HistoryReadRequest_Encoding_DefaultBinary => &mut diagnostics.history_read_count,
+            ObjectId::WriteRequest_Encoding_DefaultBinary => &mut diagnostics.write_count,
+            ObjectId::HistoryUpdateRequest_Encoding_DefaultBinary => &mut diagnostics.history_update_count,
+            ObjectId::CallRequest_Encoding_DefaultBinary => &mut diagnostics.call_count,
+            ObjectId::CreateMonitoredItemsRequest_Encoding_DefaultBinary => &mut diagnostics.create_monitored_items_count,
+            ObjectId::ModifyMonitoredItemsRequest_Encoding_DefaultBinary => &mut diagnostics.modify_monitored_items_count,
+            ObjectId::SetMonitoringModeRequest_Encoding_DefaultBinary => &mut diagnostics.set_monitoring_mode_count,
+            ObjectId::SetTriggeringRequest_Encoding_DefaultBinary => &mut diagnostics.set_triggering_count,
+            ObjectId::DeleteMonitoredItemsRequest_Encoding_DefaultBinary => &mut diagnostics.delete_monitored_items_count,
+            ObjectId::CreateSubscriptionRequest_Encoding_DefaultBinary => &mut diagnostics.create_subscription_count,
+            ObjectId::ModifySubscriptionRequest_Encoding_DefaultBinary => &mut diagnostics.modify_subscription_count,
+            ObjectId::SetPublishingModeRequest_Encoding_DefaultBinary => &mut diagnostics.set_publishing_mode_count,
+            ObjectId::PublishRequest_Encoding_DefaultBinary => &mut diagnostics.publish_count,
+            ObjectId::RepublishRequest_Encoding_DefaultBinary => &mut diagnostics.republish_count,
+            ObjectId::TransferSubscriptionsRequest_Encoding_DefaultBinary => &mut diagnostics.transfer_subscriptions_count,
+            ObjectId::DeleteSubscriptionsRequest_Encoding_DefaultBinary => &mut diagnostics.delete_subscriptions_count,
+            ObjectId::AddNodesRequest_Encoding_DefaultBinary => &mut diagnostics.add_nodes_count,
+            ObjectId::AddReferencesRequest_Encoding_DefaultBinary => &mut diagnostics.add_references_count,
+            ObjectId::DeleteNodesRequest_Encoding_DefaultBinary => &mut diagnostics.delete_nodes_count,
+            ObjectId::DeleteReferencesRequest_Encoding_DefaultBinary => &mut diagnostics.delete_references_count,
+            ObjectId::BrowseRequest_Encoding_DefaultBinary => &mut diagnostics.browse_count,
+            ObjectId::BrowseNextRequest_Encoding_DefaultBinary => &mut diagnostics.browse_next_count,
+            ObjectId::TranslateBrowsePathsToNodeIdsRequest_Encoding_DefaultBinary => &mut diagnostics.translate_browse_paths_to_node_ids_count,
+            ObjectId::QueryFirstRequest_Encoding_DefaultBinary => &mut diagnostics.query_first_count,
+            ObjectId::QueryNextRequest_Encoding_DefaultBinary => &mut diagnostics.query_next_count,
+            ObjectId::RegisterNodesRequest_Encoding_DefaultBinary => &mut diagnostics.register_nodes_count,
+            ObjectId::UnregisterNodesRequest_Encoding_DefaultBinary => &mut diagnostics.unregister_nodes_count,
+            _ => {
+                return None;
+            }
+        };
+        Some(service_counter)
+    }
+}
+
+fn new_service_counter() -> ServiceCounterDataType {
+    ServiceCounterDataType {
+        total_count: 0,
+        error_count: 0,
+    }
+}
+
+fn count_service_call(service_counter: &mut ServiceCounterDataType, is_error: bool) {
+    service_counter.total_count += 1;
+    if is_error {
+        service_counter.error_count += 1;
+    }
+}
+
+/// Names the kind of user identity token that a session was activated with
+fn authentication_mechanism(user_identity: &Option<ExtensionObject>) -> &'static str {
+    if let Some(ref user_identity) = *user_identity {
+        if user_identity.is_null() || user_identity.is_empty() {
+            // Empty tokens are treated as anonymous
+            return "Anonymous";
+        }
+        match user_identity.node_id.as_object_id() {
+            Ok(ObjectId::AnonymousIdentityToken_Encoding_DefaultBinary) => "Anonymous",
+            Ok(ObjectId::UserNameIdentityToken_Encoding_DefaultBinary) => "UserName",
+            Ok(ObjectId::X509IdentityToken_Encoding_DefaultBinary) => "Certificate",
+            Ok(ObjectId::IssuedIdentityToken_Encoding_DefaultBinary) => "IssuedToken",
+            _ => ""
+        }
+    } else {
+        ""
+    }
+}
+
+/// Tests if a request failed because the session or its user is not allowed to make it
+fn is_security_rejection(service_result: StatusCode) -> bool {
+    match service_result {
+        BadSecurityChecksFailed | BadUserAccessDenied | BadIdentityTokenInvalid |
+        BadIdentityTokenRejected | BadSessionIdInvalid | BadSessionNotActivated => true,
+        _ => false
+    }
+}
+
 /// Structure that captures diagnostics information for the server
 #[derive(Clone, Serialize, Debug)]
 pub struct ServerDiagnostics {
     pub session_count: UInt32,
     pub session_count_cumulative: UInt32,
+    pub session_timeout_count: UInt32,
     pub subscription_count: UInt32,
     pub subscription_count_cumulative: UInt32,
+    pub rejected_requests_count: UInt32,
+    pub security_rejected_requests_count: UInt32,
+    /// Diagnostics of sessions and subscriptions are only collected while this is set
+    enabled: bool,
+    /// Diagnostics of each session, in the order they were created
+    #[serde(skip_serializing)]
+    sessions: Vec<SessionRecord>,
+    /// Diagnostics of each subscription, by subscription id
+    #[serde(skip_serializing)]
+    subscriptions: BTreeMap<UInt32, SubscriptionDiagnosticsDataType>,
 }
 
 impl ServerDiagnostics {
@@ -17,8 +220,28 @@ impl ServerDiagnostics {
         ServerDiagnostics {
             session_count: 0,
             session_count_cumulative: 0,
+            session_timeout_count: 0,
             subscription_count: 0,
             subscription_count_cumulative: 0,
+            rejected_requests_count: 0,
+            security_rejected_requests_count: 0,
+            enabled: true,
+            sessions: Vec::new(),
+            subscriptions: BTreeMap::new(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Turns the collection of session and subscription diagnostics on or off. Whatever has been
+    /// collected is thrown away when it is turned off.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.sessions.clear();
+            self.subscriptions.clear();
         }
     }
 
@@ -27,8 +250,14 @@ impl ServerDiagnostics {
         self.session_count_cumulative += 1;
     }
 
-    pub fn on_destroy_session(&mut self, _session: &Session) {
+    pub fn on_destroy_session(&mut self, session: &Session) {
         self.session_count -= 1;
+        self.remove_session(&session.session_id);
+    }
+
+    /// Called when a session that was waiting to be resumed expires
+    pub fn on_session_timeout(&mut self, _session: &Session) {
+        self.session_timeout_count += 1;
     }
 
     pub fn on_create_subscription(&mut self, _subscription: &Subscription) {
@@ -39,5 +268,110 @@ impl ServerDiagnostics {
     pub fn on_destroy_subscription(&mut self, _subscription: &Subscription) {
         self.subscription_count -= 1;
     }
-}
 
+    /// Counts a request made on a session and the result of handling it. The session's
+    /// diagnostics are removed once the session is closed.
+    pub fn on_service_request(&mut self, session: &Session, request_object_id: ObjectId, service_result: StatusCode) {
+        let is_error = service_result.is_bad();
+        let is_security_rejection = is_security_rejection(service_result);
+        if is_error {
+            self.rejected_requests_count += 1;
+            if is_security_rejection {
+                self.security_rejected_requests_count += 1;
+            }
+        }
+        if request_object_id == ObjectId::CloseSessionRequest_Encoding_DefaultBinary {
+            self.remove_session(&session.session_id);
+        } else {
+            self.on_update_session(session);
+            if let Some(record) = self.sessions.iter_mut().find(|record| record.diagnostics.session_id == session.session_id) {
+                record.diagnostics.client_last_contact_time = DateTime::now();
+                count_service_call(&mut record.diagnostics.total_request_count, is_error);
+                if is_security_rejection {
+                    record.diagnostics.unauthorized_request_count += 1;
+                }
+                if let Some(service_counter) = record.service_counter_mut(request_object_id) {
+                    count_service_call(service_counter, is_error);
+                }
+            }
+        }
+    }
+
+    /// Refreshes the diagnostics of a session and its subscriptions
+    pub fn on_update_session(&mut self, session: &Session) {
+        if !self.enabled || session.session_id.is_null() {
+            return;
+        }
+        let session_id = &session.session_id;
+        if let Some(record) = self.sessions.iter_mut().find(|record| record.diagnostics.session_id == *session_id) {
+            record.update(session);
+        } else {
+            let mut record = SessionRecord::new(session);
+            record.update(session);
+            self.sessions.push(record);
+        }
+
+        // Subscriptions that have gone from the session are removed, the rest are replaced
+        let removed_subscription_ids: Vec<UInt32> = self.subscriptions.values()
+            .filter(|subscription| subscription.session_id == *session_id && !session.subscriptions.contains(subscription.subscription_id))
+            .map(|subscription| subscription.subscription_id)
+            .collect();
+        for subscription_id in removed_subscription_ids {
+            self.subscriptions.remove(&subscription_id);
+        }
+        for subscription in session.subscriptions.subscription_diagnostics(session_id) {
+            self.subscriptions.insert(subscription.subscription_id, subscription);
+        }
+    }
+
+    /// Returns the diagnostics of every session
+    pub fn session_diagnostics(&self) -> Vec<SessionDiagnosticsDataType> {
+        self.sessions.iter().map(|record| record.diagnostics.clone()).collect()
+    }
+
+    /// Returns the security diagnostics of every session
+    pub fn session_security_diagnostics(&self) -> Vec<SessionSecurityDiagnosticsDataType> {
+        self.sessions.iter().map(|record| record.security_diagnostics.clone()).collect()
+    }
+
+    /// Returns the diagnostics of every subscription
+    pub fn subscription_diagnostics(&self) -> Vec<SubscriptionDiagnosticsDataType> {
+        self.subscriptions.values().cloned().collect()
+    }
+
+    /// Returns a summary of the server's diagnostics
+    pub fn server_diagnostics_summary(&self) -> ServerDiagnosticsSummaryDataType {
+        // Publishing intervals are counted from the subscriptions that diagnostics are kept for
+        let mut publishing_intervals: Vec<Double> = self.subscriptions.values().map(|subscription| subscription.publishing_interval).collect();
+        publishing_intervals.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        publishing_intervals.dedup();
+        ServerDiagnosticsSummaryDataType {
+            server_view_count: 0,
+            current_session_count: self.session_count,
+            cumulated_session_count: self.session_count_cumulative,
+            security_rejected_session_count: 0,
+            rejected_session_count: 0,
+            session_timeout_count: self.session_timeout_count,
+            session_abort_count: 0,
+            current_subscription_count: self.subscription_count,
+            cumulated_subscription_count: self.subscription_count_cumulative,
+            publishing_interval_count: publishing_intervals.len() as UInt32,
+            security_rejected_requests_count: self.security_rejected_requests_count,
+            rejected_requests_count: self.rejected_requests_count,
+        }
+    }
+
+    fn remove_session(&mut self, session_id: &NodeId) {
+        if session_id.is_null() {
+            return;
+        }
+        self.sessions.retain(|record| record.diagnostics.session_id != *session_id);
+        let subscription_ids: Vec<UInt32> = self.subscriptions.values()
+            .filter(|subscription| subscription.session_id == *session_id)
+            .map(|subscription| subscription.subscription_id)
+            .collect();
+        for subscription_id in subscription_ids {
+            self.subscriptions.remove(&subscription_id);
+        }
+    }
+}
@@ -0,0 +1,352 @@
+//! A minimal DNS message codec. It handles the records that mDNS / DNS-SD uses to announce
+//! services - A, PTR, SRV and TXT - and keeps any other record as raw bytes.
+
+use std::io::{self, Cursor, Read, Write};
+use std::iter::Chain;
+use std::net::Ipv4Addr;
+use std::slice::Iter;
+
+use opcua_types::status_codes::StatusCode;
+use opcua_types::status_codes::StatusCode::*;
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_PTR: u16 = 12;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_SRV: u16 = 33;
+pub const TYPE_ANY: u16 = 255;
+
+pub const CLASS_IN: u16 = 1;
+
+/// The top bit of a question's class asks for a unicast response, the top bit of a record's class
+/// tells the receiver to flush its cached records of the same name and type
+const CLASS_FLAG: u16 = 0x8000;
+/// Flags of a response - it is a response and it is authoritative
+const FLAGS_RESPONSE: u16 = 0x8400;
+const FLAG_IS_RESPONSE: u16 = 0x8000;
+/// Top bits of a label length that make it a pointer to a name earlier in the message
+const LABEL_POINTER: u8 = 0xc0;
+/// Guards against compression pointers that loop back on themselves
+const MAX_LABEL_POINTERS: usize = 32;
+const MAX_LABEL_LENGTH: usize = 63;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Question {
+    pub name: String,
+    pub question_type: u16,
+    pub unicast_response: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordData {
+    A(Ipv4Addr),
+    Ptr(String),
+    Txt(Vec<String>),
+    Srv { priority: u16, weight: u16, port: u16, target: String },
+    Other(u16, Vec<u8>),
+}
+
+impl RecordData {
+    pub fn record_type(&self) -> u16 {
+        match *self {
+            RecordData::A(_) => TYPE_A,
+            RecordData::Ptr(_) => TYPE_PTR,
+            RecordData::Txt(_) => TYPE_TXT,
+            RecordData::Srv { .. } => TYPE_SRV,
+            RecordData::Other(record_type, _) => record_type,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub name: String,
+    pub cache_flush: bool,
+    /// Time to live in seconds. A record with a zero TTL says goodbye, i.e. the service has gone.
+    pub ttl: u32,
+    pub data: RecordData,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub id: u16,
+    pub is_response: bool,
+    pub questions: Vec<Question>,
+    pub answers: Vec<Record>,
+    /// Authority records are read but play no part in mDNS discovery
+    pub authorities: Vec<Record>,
+    pub additionals: Vec<Record>,
+}
+
+/// Compares DNS names, which are case insensitive
+pub fn name_matches(name1: &str, name2: &str) -> bool {
+    name1.eq_ignore_ascii_case(name2)
+}
+
+impl Message {
+    /// Makes a query containing the questions
+    pub fn query(questions: Vec<Question>) -> Message {
+        Message {
+            id: 0,
+            is_response: false,
+            questions,
+            answers: Vec::new(),
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+        }
+    }
+
+    /// Makes a response containing the records
+    pub fn response(answers: Vec<Record>, additionals: Vec<Record>) -> Message {
+        Message {
+            id: 0,
+            is_response: true,
+            questions: Vec::new(),
+            answers,
+            authorities: Vec::new(),
+            additionals,
+        }
+    }
+
+    /// Iterates the answers and additional records of the message
+    pub fn records(&self) -> Chain<Iter<Record>, Iter<Record>> {
+        self.answers.iter().chain(self.additionals.iter())
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut stream = Cursor::new(Vec::with_capacity(512));
+        let flags = if self.is_response { FLAGS_RESPONSE } else { 0 };
+        let _ = write_u16(&mut stream, self.id);
+        let _ = write_u16(&mut stream, flags);
+        let _ = write_u16(&mut stream, self.questions.len() as u16);
+        let _ = write_u16(&mut stream, self.answers.len() as u16);
+        let _ = write_u16(&mut stream, self.authorities.len() as u16);
+        let _ = write_u16(&mut stream, self.additionals.len() as u16);
+        for question in &self.questions {
+            encode_name(&mut stream, &question.name);
+            let class = if question.unicast_response { CLASS_IN | CLASS_FLAG } else { CLASS_IN };
+            let _ = write_u16(&mut stream, question.question_type);
+            let _ = write_u16(&mut stream, class);
+        }
+        for record in self.answers.iter().chain(self.authorities.iter()).chain(self.additionals.iter()) {
+            encode_record(&mut stream, record);
+        }
+        stream.into_inner()
+    }
+
+    pub fn decode(buffer: &[u8]) -> Result<Message, StatusCode> {
+        let mut stream = Cursor::new(buffer);
+        let id = read_u16(&mut stream)?;
+        let flags = read_u16(&mut stream)?;
+        let question_count = read_u16(&mut stream)?;
+        let answer_count = read_u16(&mut stream)?;
+        let authority_count = read_u16(&mut stream)?;
+        let additional_count = read_u16(&mut stream)?;
+
+        let mut questions = Vec::with_capacity(question_count as usize);
+        for _ in 0..question_count {
+            let name = decode_name(buffer, &mut stream)?;
+            let question_type = read_u16(&mut stream)?;
+            let class = read_u16(&mut stream)?;
+            questions.push(Question {
+                name,
+                question_type,
+                unicast_response: class & CLASS_FLAG != 0,
+            });
+        }
+        let answers = decode_records(buffer, &mut stream, answer_count)?;
+        let authorities = decode_records(buffer, &mut stream, authority_count)?;
+        let additionals = decode_records(buffer, &mut stream, additional_count)?;
+        Ok(Message {
+            id,
+            is_response: flags & FLAG_IS_RESPONSE != 0,
+            questions,
+            answers,
+            authorities,
+            additionals,
+        })
+    }
+}
+
+fn write_u16(stream: &mut Cursor<Vec<u8>>, value: u16) -> io::Result<()> {
+    stream.write_all(&[(value >> 8) as u8, value as u8])
+}
+
+fn write_u32(stream: &mut Cursor<Vec<u8>>, value: u32) -> io::Result<()> {
+    stream.write_all(&[(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+fn read_u8(stream: &mut Cursor<&[u8]>) -> Result<u8, StatusCode> {
+    let mut buf = [0u8; 1];
+    stream.read_exact(&mut buf).map_err(|_| BadDecodingError)?;
+    Ok(buf[0])
+}
+
+fn read_u16(stream: &mut Cursor<&[u8]>) -> Result<u16, StatusCode> {
+    let mut buf = [0u8; 2];
+    stream.read_exact(&mut buf).map_err(|_| BadDecodingError)?;
+    Ok((buf[0] as u16) << 8 | buf[1] as u16)
+}
+
+fn read_u32(stream: &mut Cursor<&[u8]>) -> Result<u32, StatusCode> {
+    let mut buf = [0u8; 4];
+    stream.read_exact(&mut buf).map_err(|_| BadDecodingError)?;
+    Ok((buf[0] as u32) << 24 | (buf[1] as u32) << 16 | (buf[2] as u32) << 8 | buf[3] as u32)
+}
+
+fn read_bytes(stream: &mut Cursor<&[u8]>, len: usize) -> Result<Vec<u8>, StatusCode> {
+    let mut buf = vec![0u8; len];
+    stream.read_exact(&mut buf).map_err(|_| BadDecodingError)?;
+    Ok(buf)
+}
+
+/// Writes the name as a sequence of labels. Names are not compressed.
+fn encode_name(stream: &mut Cursor<Vec<u8>>, name: &str) {
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        let label = label.as_bytes();
+        let label = if label.len() > MAX_LABEL_LENGTH { &label[..MAX_LABEL_LENGTH] } else { label };
+        let _ = stream.write_all(&[label.len() as u8]);
+        let _ = stream.write_all(label);
+    }
+    let _ = stream.write_all(&[0]);
+}
+
+/// Reads a name, following any compression pointers into the rest of the message
+fn decode_name(buffer: &[u8], stream: &mut Cursor<&[u8]>) -> Result<String, StatusCode> {
+    let mut labels: Vec<String> = Vec::new();
+    // The stream carries on after the first pointer, the name is read from wherever it points
+    let mut position = stream.position() as usize;
+    let mut end_of_name = None;
+    let mut pointers = 0;
+    loop {
+        if position >= buffer.len() {
+            return Err(BadDecodingError);
+        }
+        let len = buffer[position];
+        if len & LABEL_POINTER == LABEL_POINTER {
+            if position + 1 >= buffer.len() || pointers >= MAX_LABEL_POINTERS {
+                return Err(BadDecodingError);
+            }
+            if end_of_name.is_none() {
+                end_of_name = Some(position + 2);
+            }
+            pointers += 1;
+            position = ((len & !LABEL_POINTER) as usize) << 8 | buffer[position + 1] as usize;
+        } else if len == 0 {
+            if end_of_name.is_none() {
+                end_of_name = Some(position + 1);
+            }
+            break;
+        } else {
+            let start = position + 1;
+            let end = start + len as usize;
+            if end > buffer.len() {
+                return Err(BadDecodingError);
+            }
+            labels.push(String::from_utf8_lossy(&buffer[start..end]).to_string());
+            position = end;
+        }
+    }
+    stream.set_position(end_of_name.unwrap() as u64);
+    Ok(labels.join("."))
+}
+
+fn encode_record(stream: &mut Cursor<Vec<u8>>, record: &Record) {
+    encode_name(stream, &record.name);
+    let class = if record.cache_flush { CLASS_IN | CLASS_FLAG } else { CLASS_IN };
+    let _ = write_u16(stream, record.data.record_type());
+    let _ = write_u16(stream, class);
+    let _ = write_u32(stream, record.ttl);
+
+    // The data is written after its length, so write it to its own buffer first
+    let mut data = Cursor::new(Vec::new());
+    match record.data {
+        RecordData::A(ref address) => {
+            let _ = data.write_all(&address.octets());
+        }
+        RecordData::Ptr(ref name) => {
+            encode_name(&mut data, name);
+        }
+        RecordData::Txt(ref strings) => {
+            if strings.is_empty() {
+                let _ = data.write_all(&[0]);
+            }
+            for s in strings {
+                let s = s.as_bytes();
+                let s = if s.len() > 255 { &s[..255] } else { s };
+                let _ = data.write_all(&[s.len() as u8]);
+                let _ = data.write_all(s);
+            }
+        }
+        RecordData::Srv { priority, weight, port, ref target } => {
+            let _ = write_u16(&mut data, priority);
+            let _ = write_u16(&mut data, weight);
+            let _ = write_u16(&mut data, port);
+            encode_name(&mut data, target);
+        }
+        RecordData::Other(_, ref bytes) => {
+            let _ = data.write_all(bytes);
+        }
+    }
+    let data = data.into_inner();
+    let _ = write_u16(stream, data.len() as u16);
+    let _ = stream.write_all(&data);
+}
+
+fn decode_records(buffer: &[u8], stream: &mut Cursor<&[u8]>, count: u16) -> Result<Vec<Record>, StatusCode> {
+    let mut records = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        records.push(decode_record(buffer, stream)?);
+    }
+    Ok(records)
+}
+
+fn decode_record(buffer: &[u8], stream: &mut Cursor<&[u8]>) -> Result<Record, StatusCode> {
+    let name = decode_name(buffer, stream)?;
+    let record_type = read_u16(stream)?;
+    let class = read_u16(stream)?;
+    let ttl = read_u32(stream)?;
+    let data_len = read_u16(stream)? as usize;
+    let data_start = stream.position() as usize;
+    let data_end = data_start + data_len;
+    if data_end > buffer.len() {
+        return Err(BadDecodingError);
+    }
+    let data = match record_type {
+        TYPE_A if data_len == 4 => {
+            let octets = read_bytes(stream, 4)?;
+            RecordData::A(Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]))
+        }
+        TYPE_PTR => {
+            RecordData::Ptr(decode_name(buffer, stream)?)
+        }
+        TYPE_TXT => {
+            let mut strings = Vec::new();
+            while (stream.position() as usize) < data_end {
+                let len = read_u8(stream)? as usize;
+                if len > 0 {
+                    let s = read_bytes(stream, len)?;
+                    strings.push(String::from_utf8_lossy(&s).to_string());
+                }
+            }
+            RecordData::Txt(strings)
+        }
+        TYPE_SRV => {
+            let priority = read_u16(stream)?;
+            let weight = read_u16(stream)?;
+            let port = read_u16(stream)?;
+            let target = decode_name(buffer, stream)?;
+            RecordData::Srv { priority, weight, port, target }
+        }
+        _ => {
+            RecordData::Other(record_type, read_bytes(stream, data_len)?)
+        }
+    };
+    // Whatever the data held, the next record follows it
+    stream.set_position(data_end as u64);
+    Ok(Record {
+        name,
+        cache_flush: class & CLASS_FLAG != 0,
+        ttl,
+        data,
+    })
+}
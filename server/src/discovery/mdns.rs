@@ -0,0 +1,441 @@
+//! Multicast DNS (mDNS / DNS-SD) discovery as described in part 12 of the spec. The server
+//! announces itself, and the servers registered with it through RegisterServer2, as
+//! `_opcua-tcp._tcp` services and answers queries for them. The announcements of other servers
+//! are cached for FindServersOnNetwork.
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono;
+use net2::UdpBuilder;
+#[cfg(unix)]
+use net2::unix::UnixUdpBuilderExt;
+
+use opcua_types::url::opc_url_from_str;
+
+use constants;
+use discovery::dns::{self, Message, Question, Record, RecordData};
+use discovery::servers_on_network::ServersOnNetwork;
+use state::ServerState;
+use DateTimeUtc;
+
+/// The DNS-SD service type of servers that support the opc.tcp transport
+pub const OPCUA_SERVICE_TYPE: &str = "_opcua-tcp._tcp.local";
+/// The DNS-SD name that enumerates all the service types on the network
+const SERVICES_META_QUERY: &str = "_services._dns-sd._udp.local";
+/// The well known mDNS port
+pub const MDNS_PORT: u16 = 5353;
+/// Largest mDNS message received
+const MAX_MESSAGE_SIZE: usize = 9000;
+
+/// The mDNS multicast group
+pub fn mdns_group() -> SocketAddr {
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(224, 0, 0, 251), MDNS_PORT))
+}
+
+/// Extracts the server name from a service instance name, e.g. "Server._opcua-tcp._tcp.local"
+pub fn server_name_from_instance_name(instance_name: &str) -> Option<String> {
+    let suffix_len = OPCUA_SERVICE_TYPE.len() + 1;
+    if instance_name.len() > suffix_len && instance_name.is_char_boundary(instance_name.len() - suffix_len) {
+        let (server_name, suffix) = instance_name.split_at(instance_name.len() - suffix_len);
+        if suffix.starts_with('.') && dns::name_matches(&suffix[1..], OPCUA_SERVICE_TYPE) {
+            return Some(server_name.to_string());
+        }
+    }
+    None
+}
+
+#[cfg(unix)]
+fn reuse_port(builder: &UdpBuilder) -> io::Result<()> {
+    builder.reuse_port(true).map(|_| ())
+}
+
+#[cfg(not(unix))]
+fn reuse_port(_: &UdpBuilder) -> io::Result<()> {
+    Ok(())
+}
+
+/// A server as it is announced on the network
+#[derive(Debug, Clone, PartialEq)]
+pub struct MdnsService {
+    /// The name of the server, which is the first part of its service instance name
+    pub server_name: String,
+    /// The host name that the SRV record points to
+    pub host: String,
+    /// The address of the host, if it was announced
+    pub address: Option<Ipv4Addr>,
+    pub port: u16,
+    /// The path of the discovery url
+    pub path: String,
+    /// Server capability identifiers, e.g. "DA" or "LDS"
+    pub capabilities: Vec<String>,
+}
+
+impl MdnsService {
+    /// Makes the service announced for a server from its discovery url. A host that is an IPv4
+    /// address is announced as a host name made from the address, with an A record for it.
+    pub fn from_discovery_url(server_name: &str, discovery_url: &str, capabilities: &[String]) -> Option<MdnsService> {
+        let url = opc_url_from_str(discovery_url).ok()?;
+        let host = url.host_str()?.to_string();
+        let address = host.parse::<Ipv4Addr>().ok();
+        let host = if address.is_some() {
+            format!("{}.local", host.replace('.', "-"))
+        } else if host.to_lowercase().ends_with(".local") {
+            host
+        } else {
+            format!("{}.local", host)
+        };
+        Some(MdnsService {
+            server_name: server_name.to_string(),
+            host,
+            address,
+            port: url.port().unwrap_or(constants::DEFAULT_DISCOVERY_SERVER_PORT),
+            path: url.path().to_string(),
+            capabilities: capabilities.to_vec(),
+        })
+    }
+
+    /// Reads the service with the instance name from the SRV, TXT and A records of the message
+    pub fn from_message(server_name: &str, instance_name: &str, message: &Message) -> Option<MdnsService> {
+        let (port, host) = message.records()
+            .filter(|record| dns::name_matches(&record.name, instance_name))
+            .filter_map(|record| {
+                if let RecordData::Srv { port, ref target, .. } = record.data {
+                    Some((port, target.clone()))
+                } else {
+                    None
+                }
+            })
+            .next()?;
+        let mut path = String::new();
+        let mut capabilities = Vec::new();
+        for record in message.records().filter(|record| dns::name_matches(&record.name, instance_name)) {
+            if let RecordData::Txt(ref strings) = record.data {
+                for s in strings {
+                    if s.starts_with("path=") {
+                        path = s["path=".len()..].to_string();
+                    } else if s.starts_with("caps=") {
+                        capabilities = s["caps=".len()..].split(',')
+                            .filter(|c| !c.is_empty())
+                            .map(|c| c.to_string())
+                            .collect();
+                    }
+                }
+            }
+        }
+        let address = message.records()
+            .filter(|record| dns::name_matches(&record.name, &host))
+            .filter_map(|record| if let RecordData::A(address) = record.data { Some(address) } else { None })
+            .next();
+        Some(MdnsService {
+            server_name: server_name.to_string(),
+            host,
+            address,
+            port,
+            path,
+            capabilities,
+        })
+    }
+
+    pub fn instance_name(&self) -> String {
+        format!("{}.{}", self.server_name, OPCUA_SERVICE_TYPE)
+    }
+
+    /// The discovery url of the server. The host is the announced address if there is one,
+    /// otherwise the host name.
+    pub fn discovery_url(&self) -> String {
+        let host = if let Some(address) = self.address { address.to_string() } else { self.host.trim_right_matches('.').to_string() };
+        let path = if self.path.starts_with('/') { self.path.clone() } else { format!("/{}", self.path) };
+        format!("opc.tcp://{}:{}{}", host, self.port, path)
+    }
+
+    /// The PTR record that answers a query for the service type, followed by the records that
+    /// describe the service instance
+    fn records(&self, ttl: u32) -> (Record, Vec<Record>) {
+        let instance_name = self.instance_name();
+        let ptr = Record {
+            name: OPCUA_SERVICE_TYPE.to_string(),
+            cache_flush: false,
+            ttl,
+            data: RecordData::Ptr(instance_name.clone()),
+        };
+        let mut txt = vec![format!("path={}", self.path)];
+        if !self.capabilities.is_empty() {
+            txt.push(format!("caps={}", self.capabilities.join(",")));
+        }
+        let mut records = vec![
+            Record {
+                name: instance_name.clone(),
+                cache_flush: true,
+                ttl,
+                data: RecordData::Srv { priority: 0, weight: 0, port: self.port, target: self.host.clone() },
+            },
+            Record {
+                name: instance_name,
+                cache_flush: true,
+                ttl,
+                data: RecordData::Txt(txt),
+            }
+        ];
+        if let Some(address) = self.address {
+            records.push(Record {
+                name: self.host.clone(),
+                cache_flush: true,
+                ttl,
+                data: RecordData::A(address),
+            });
+        }
+        (ptr, records)
+    }
+}
+
+/// The services that the server announces - itself and the servers registered with it that supplied
+/// an mDNS configuration.
+pub fn services(server_state: &ServerState) -> Vec<MdnsService> {
+    let mut services = Vec::new();
+    if let Some(discovery_urls) = server_state.discovery_urls() {
+        if let Some(discovery_url) = discovery_urls.first() {
+            let server_name = server_state.application_name.text.as_ref();
+            if let Some(service) = MdnsService::from_discovery_url(server_name, discovery_url.as_ref(), &server_state.server_capabilities()) {
+                services.push(service);
+            }
+        }
+    }
+    for registration in server_state.registered_servers.iter() {
+        if let Some(ref mdns_configuration) = registration.mdns_configuration {
+            let server = &registration.server;
+            let server_name = if mdns_configuration.mdns_server_name.is_null() {
+                server.server_names.as_ref().and_then(|names| names.first()).map_or(String::new(), |name| name.text.as_ref().to_string())
+            } else {
+                mdns_configuration.mdns_server_name.as_ref().to_string()
+            };
+            let capabilities: Vec<String> = mdns_configuration.server_capabilities.as_ref()
+                .map_or(Vec::new(), |capabilities| capabilities.iter().map(|c| c.as_ref().to_string()).collect());
+            if let Some(discovery_url) = server.discovery_urls.as_ref().and_then(|urls| urls.first()) {
+                if let Some(service) = MdnsService::from_discovery_url(&server_name, discovery_url.as_ref(), &capabilities) {
+                    services.push(service);
+                }
+            }
+        }
+    }
+    services
+}
+
+/// Answers the questions in a query that are about the services, or returns `None` if there is
+/// nothing to answer
+pub fn answer_query(query: &Message, services: &[MdnsService], ttl: u32) -> Option<Message> {
+    let mut answers = Vec::new();
+    let mut additionals = Vec::new();
+    for question in &query.questions {
+        let is_ptr_question = question.question_type == dns::TYPE_PTR || question.question_type == dns::TYPE_ANY;
+        if dns::name_matches(&question.name, SERVICES_META_QUERY) && is_ptr_question && !services.is_empty() {
+            answers.push(Record {
+                name: SERVICES_META_QUERY.to_string(),
+                cache_flush: false,
+                ttl,
+                data: RecordData::Ptr(OPCUA_SERVICE_TYPE.to_string()),
+            });
+        }
+        for service in services {
+            let (ptr, records) = service.records(ttl);
+            if dns::name_matches(&question.name, OPCUA_SERVICE_TYPE) && is_ptr_question {
+                answers.push(ptr);
+                additionals.extend(records);
+            } else if dns::name_matches(&question.name, &service.instance_name()) {
+                additionals.extend(records);
+            }
+        }
+    }
+    // Records answering more than one question are sent once
+    answers.dedup();
+    let mut unique_additionals: Vec<Record> = Vec::with_capacity(additionals.len());
+    for record in additionals {
+        if !unique_additionals.contains(&record) && !answers.contains(&record) {
+            unique_additionals.push(record);
+        }
+    }
+    if answers.is_empty() && unique_additionals.is_empty() {
+        None
+    } else {
+        Some(Message::response(answers, unique_additionals))
+    }
+}
+
+/// Sends and receives the mDNS messages of the server
+pub struct MdnsDiscovery {
+    socket: UdpSocket,
+    /// Where announcements and queries are sent, ordinarily the mDNS multicast group
+    destination: SocketAddr,
+    last_announcement: Option<Instant>,
+}
+
+impl MdnsDiscovery {
+    /// Joins the mDNS multicast group on the interface. The port is shared with any other mDNS
+    /// responder on the host.
+    pub fn new_multicast(interface: Ipv4Addr) -> io::Result<MdnsDiscovery> {
+        let builder = UdpBuilder::new_v4()?;
+        builder.reuse_address(true)?;
+        reuse_port(&builder)?;
+        let socket = builder.bind(SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), MDNS_PORT))?;
+        socket.join_multicast_v4(&Ipv4Addr::new(224, 0, 0, 251), &interface)?;
+        socket.set_multicast_loop_v4(true)?;
+        socket.set_multicast_ttl_v4(255)?;
+        Self::new(socket, mdns_group())
+    }
+
+    /// Uses a socket that has already been bound, sending announcements and queries to the
+    /// destination. Tests use this to talk over the loopback interface without multicast.
+    pub fn new(socket: UdpSocket, destination: SocketAddr) -> io::Result<MdnsDiscovery> {
+        socket.set_read_timeout(Some(Duration::from_millis(constants::MDNS_POLL_MS)))?;
+        Ok(MdnsDiscovery {
+            socket,
+            destination,
+            last_announcement: None,
+        })
+    }
+
+    fn send(&self, message: &Message, to: &SocketAddr) {
+        if let Err(err) = self.socket.send_to(&message.encode(), to) {
+            error!("Cannot send mDNS message to {}, error = {:?}", to, err);
+        }
+    }
+
+    /// Tests if it is time to announce the services again
+    pub fn announcement_due(&self) -> bool {
+        self.last_announcement.map_or(true, |last_announcement| {
+            last_announcement.elapsed() >= Duration::from_millis(constants::MDNS_ANNOUNCEMENT_INTERVAL_MS)
+        })
+    }
+
+    /// Announces the services. Services are announced with a zero time to live when they go away.
+    pub fn announce(&mut self, services: &[MdnsService], ttl: u32) {
+        self.last_announcement = Some(Instant::now());
+        if services.is_empty() {
+            return;
+        }
+        let mut answers = Vec::with_capacity(services.len());
+        let mut additionals = Vec::new();
+        for service in services {
+            let (ptr, records) = service.records(ttl);
+            answers.push(ptr);
+            additionals.extend(records);
+        }
+        let destination = self.destination;
+        self.send(&Message::response(answers, additionals), &destination);
+    }
+
+    /// Asks the servers on the network to announce themselves
+    pub fn query(&self) {
+        let query = Message::query(vec![Question {
+            name: OPCUA_SERVICE_TYPE.to_string(),
+            question_type: dns::TYPE_PTR,
+            unicast_response: false,
+        }]);
+        self.send(&query, &self.destination);
+    }
+
+    /// Waits a short time for a message, returning the message and who sent it
+    pub fn receive(&self) -> Option<(Message, SocketAddr)> {
+        let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+        match self.socket.recv_from(&mut buffer) {
+            Ok((len, source)) => {
+                match Message::decode(&buffer[..len]) {
+                    Ok(message) => Some((message, source)),
+                    Err(_) => {
+                        debug!("Ignoring mDNS message from {} that cannot be decoded", source);
+                        None
+                    }
+                }
+            }
+            Err(_) => None
+        }
+    }
+
+    /// Answers a query about the services or caches the servers in a response. A query that comes
+    /// from a port other than the mDNS port, or that asks for it, is answered to the sender alone.
+    pub fn handle(&self, message: &Message, source: &SocketAddr, services: &[MdnsService], servers_on_network: &mut ServersOnNetwork, now: &DateTimeUtc) {
+        if message.is_response {
+            servers_on_network.handle_response(message, now);
+        } else {
+            self.answer(message, source, services);
+        }
+    }
+
+    /// Answers a query about the services, if it asks about them
+    pub fn answer(&self, message: &Message, source: &SocketAddr, services: &[MdnsService]) {
+        let is_legacy_unicast = source.port() != MDNS_PORT;
+        if let Some(mut response) = answer_query(message, services, constants::MDNS_RECORD_TTL) {
+            if is_legacy_unicast {
+                // A one-shot resolver expects its query id and questions back
+                response.id = message.id;
+                response.questions = message.questions.clone();
+                self.send(&response, source);
+            } else if message.questions.iter().any(|question| question.unicast_response) {
+                self.send(&response, source);
+            } else {
+                self.send(&response, &self.destination);
+            }
+        }
+    }
+}
+
+/// Starts a thread that announces the server through mDNS and caches the servers announced by
+/// others, until the server is aborted. The services are announced on the interface of the
+/// server's tcp host, or the default interface if that is not an address.
+pub fn start(server_state: Arc<RwLock<ServerState>>) {
+    let interface = {
+        let server_state = trace_read_lock_unwrap!(server_state);
+        let config = trace_read_lock_unwrap!(server_state.config);
+        config.tcp_config.host.parse::<Ipv4Addr>().unwrap_or(Ipv4Addr::new(0, 0, 0, 0))
+    };
+    let _ = thread::spawn(move || {
+        let mut discovery = match MdnsDiscovery::new_multicast(interface) {
+            Ok(discovery) => discovery,
+            Err(err) => {
+                error!("Cannot start multicast discovery on interface {}, error = {:?}", interface, err);
+                return;
+            }
+        };
+        info!("Multicast discovery has started on interface {}", interface);
+        loop {
+            let received = discovery.receive();
+            let (abort, services) = {
+                let server_state = trace_read_lock_unwrap!(server_state);
+                (server_state.abort, services(&server_state))
+            };
+            if abort {
+                // Say goodbye
+                discovery.announce(&services, 0);
+                info!("Multicast discovery has stopped");
+                break;
+            }
+            // The server state is only written when there is something to cache. Expired servers
+            // are removed then, or when a client asks for the servers on the network.
+            let now = chrono::Utc::now();
+            if discovery.announcement_due() {
+                discovery.announce(&services, constants::MDNS_RECORD_TTL);
+                {
+                    // The server is one of the servers on the network
+                    let mut server_state = trace_write_lock_unwrap!(server_state);
+                    for service in &services {
+                        server_state.servers_on_network.update(service, constants::MDNS_RECORD_TTL, &now);
+                    }
+                    server_state.servers_on_network.remove_expired(&now);
+                }
+                discovery.query();
+            }
+            if let Some((message, source)) = received {
+                if !message.is_response {
+                    discovery.answer(&message, &source, &services);
+                } else if ServersOnNetwork::is_announcement(&message) {
+                    let mut server_state = trace_write_lock_unwrap!(server_state);
+                    server_state.servers_on_network.handle_response(&message, &now);
+                    server_state.servers_on_network.remove_expired(&now);
+                }
+            }
+        }
+    });
+}
@@ -5,6 +5,11 @@ use opcua_client::config::ClientConfig;
 
 use state::ServerState;
 
+pub mod dns;
+pub mod mdns;
+pub mod registered_servers;
+pub mod servers_on_network;
+
 /// Registers the specified endpoints with the specified discovery server
 pub fn register_discover_server(discovery_server_url: &str, server_state: &ServerState) {
     // This follows the local discovery process described in part 12 of the spec, calling
@@ -0,0 +1,177 @@
+//! Servers that have registered themselves with this server when it runs as a local discovery
+//! server (LDS).
+
+use std::collections::BTreeMap;
+use std::collections::btree_map::Values;
+
+use time;
+
+use opcua_types::*;
+use opcua_types::node_ids::ObjectId;
+use opcua_types::service_types::{ApplicationDescription, ApplicationType, MdnsDiscoveryConfiguration, RegisteredServer};
+use opcua_types::status_codes::StatusCode;
+use opcua_types::status_codes::StatusCode::*;
+
+use constants;
+use DateTimeUtc;
+
+/// A registration held for a server
+pub struct Registration {
+    /// The server as it described itself in its last RegisterServer / RegisterServer2 call
+    pub server: RegisteredServer,
+    /// The mDNS configuration supplied through RegisterServer2, if any
+    pub mdns_configuration: Option<MdnsDiscoveryConfiguration>,
+    /// The time of the last registration
+    pub registered_at: DateTimeUtc,
+}
+
+impl Registration {
+    /// Describes the registered server as FindServers returns it. The server name is the one
+    /// matching the first of the locale ids that the server supplied a name for, otherwise its
+    /// first name.
+    pub fn application_description(&self, locale_ids: &Option<Vec<UAString>>) -> ApplicationDescription {
+        let server = &self.server;
+        ApplicationDescription {
+            application_uri: server.server_uri.clone(),
+            product_uri: server.product_uri.clone(),
+            application_name: localized_name(&server.server_names, locale_ids),
+            application_type: server.server_type,
+            gateway_server_uri: server.gateway_server_uri.clone(),
+            discovery_profile_uri: UAString::null(),
+            discovery_urls: server.discovery_urls.clone(),
+        }
+    }
+}
+
+/// Picks the name whose locale matches the first of the locale ids possible, otherwise the first
+/// name in the list.
+pub fn localized_name(names: &Option<Vec<LocalizedText>>, locale_ids: &Option<Vec<UAString>>) -> LocalizedText {
+    if let Some(ref names) = *names {
+        if let Some(ref locale_ids) = *locale_ids {
+            for locale_id in locale_ids {
+                if let Some(name) = names.iter().find(|name| name.locale == *locale_id) {
+                    return name.clone();
+                }
+            }
+        }
+        if let Some(name) = names.first() {
+            return name.clone();
+        }
+    }
+    LocalizedText::null()
+}
+
+/// The servers registered with this server, keyed by server uri. A server must register again
+/// before its registration expires or it is dropped from the list.
+pub struct RegisteredServers {
+    registrations: BTreeMap<String, Registration>,
+}
+
+impl RegisteredServers {
+    pub fn new() -> RegisteredServers {
+        RegisteredServers {
+            registrations: BTreeMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.registrations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.registrations.is_empty()
+    }
+
+    pub fn get(&self, server_uri: &str) -> Option<&Registration> {
+        self.registrations.get(server_uri)
+    }
+
+    pub fn iter(&self) -> Values<String, Registration> {
+        self.registrations.values()
+    }
+
+    /// Validates the server that is registering itself. The server must supply a server uri, at least
+    /// one name and one discovery url, and must be something other than a client.
+    pub fn validate(server: &RegisteredServer) -> Result<(), StatusCode> {
+        if server.server_uri.is_null() || server.server_uri.as_ref().is_empty() {
+            error!("Registered server has no server uri");
+            Err(BadServerUriInvalid)
+        } else if server.server_names.as_ref().map_or(true, |names| names.is_empty()) {
+            error!("Registered server {} has no server names", server.server_uri);
+            Err(BadServerNameMissing)
+        } else if server.discovery_urls.as_ref().map_or(true, |urls| urls.iter().all(|url| url.is_null() || url.as_ref().is_empty())) {
+            error!("Registered server {} has no discovery urls", server.server_uri);
+            Err(BadDiscoveryUrlMissing)
+        } else if server.server_type == ApplicationType::Client {
+            error!("Registered server {} is a client", server.server_uri);
+            Err(BadInvalidArgument)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Registers the server, or unregisters it if the server says it is no longer online. Servers that
+    /// are not registered already are refused when the most servers allowed are registered.
+    pub fn register(&mut self, server: RegisteredServer, mdns_configuration: Option<MdnsDiscoveryConfiguration>, now: &DateTimeUtc) -> Result<(), StatusCode> {
+        Self::validate(&server)?;
+        self.remove_expired(now);
+        let server_uri = server.server_uri.as_ref().to_string();
+        if server.is_online {
+            if !self.registrations.contains_key(&server_uri) && self.registrations.len() >= constants::MAX_REGISTERED_SERVERS {
+                error!("Server {} cannot register because too many servers are registered", server_uri);
+                return Err(BadTooManyOperations);
+            }
+            debug!("Server {} has registered", server_uri);
+            self.registrations.insert(server_uri, Registration {
+                server,
+                mdns_configuration,
+                registered_at: now.clone(),
+            });
+        } else {
+            debug!("Server {} has unregistered", server_uri);
+            self.registrations.remove(&server_uri);
+        }
+        Ok(())
+    }
+
+    /// Removes registrations that have not been renewed within the registration expiry period
+    pub fn remove_expired(&mut self, now: &DateTimeUtc) {
+        let expiry = time::Duration::milliseconds(constants::DISCOVERY_REGISTRATION_EXPIRY_MS);
+        self.registrations.retain(|server_uri, registration| {
+            if *now >= registration.registered_at + expiry {
+                info!("Registration of server {} has expired", server_uri);
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    /// Decodes the discovery configurations supplied with a RegisterServer2 request. The result
+    /// holds a status code for each configuration and the mDNS configuration if there was one.
+    pub fn decode_discovery_configuration(discovery_configuration: &Option<Vec<ExtensionObject>>) -> (Vec<StatusCode>, Option<MdnsDiscoveryConfiguration>) {
+        let mut mdns_configuration = None;
+        let configuration_results = if let Some(ref discovery_configuration) = *discovery_configuration {
+            discovery_configuration.iter().map(|configuration| {
+                match configuration.node_id.as_object_id() {
+                    Ok(ObjectId::MdnsDiscoveryConfiguration_Encoding_DefaultBinary) => {
+                        if let Ok(configuration) = configuration.decode_inner::<MdnsDiscoveryConfiguration>() {
+                            mdns_configuration = Some(configuration);
+                            Good
+                        } else {
+                            error!("Mdns discovery configuration could not be decoded");
+                            BadDecodingError
+                        }
+                    }
+                    _ => {
+                        error!("Discovery configuration {:?} is not supported", configuration.node_id);
+                        BadNotSupported
+                    }
+                }
+            }).collect()
+        } else {
+            Vec::new()
+        };
+        (configuration_results, mdns_configuration)
+    }
+}
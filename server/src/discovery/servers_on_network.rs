@@ -0,0 +1,163 @@
+//! Servers announced on the network through mDNS, as FindServersOnNetwork returns them.
+
+use std::cmp;
+use std::collections::BTreeMap;
+
+use time;
+
+use opcua_types::*;
+use opcua_types::service_types::ServerOnNetwork;
+
+use constants;
+use discovery::dns::{self, Message, RecordData};
+use discovery::mdns::{self, MdnsService};
+use DateTimeUtc;
+
+struct CachedServer {
+    server: ServerOnNetwork,
+    expires_at: DateTimeUtc,
+}
+
+/// A cache of the servers announced on the network, keyed by their lower case server name. Each
+/// server is given a record id when it is first seen, or when its announcement changes, so clients
+/// can ask for the servers that they have not seen yet.
+pub struct ServersOnNetwork {
+    last_counter_reset_time: DateTime,
+    last_record_id: UInt32,
+    servers: BTreeMap<String, CachedServer>,
+}
+
+impl ServersOnNetwork {
+    pub fn new() -> ServersOnNetwork {
+        ServersOnNetwork {
+            last_counter_reset_time: DateTime::now(),
+            last_record_id: 0,
+            servers: BTreeMap::new(),
+        }
+    }
+
+    /// The time that record ids started from
+    pub fn last_counter_reset_time(&self) -> DateTime {
+        self.last_counter_reset_time.clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.servers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.servers.is_empty()
+    }
+
+    /// Adds or refreshes a server announced with the time to live in seconds. A server announced
+    /// with a zero time to live has said goodbye and is removed. The time to live is cut short if
+    /// it is longer than the server caches announcements for, and a new server is ignored if the
+    /// cache is full.
+    pub fn update(&mut self, service: &MdnsService, ttl: u32, now: &DateTimeUtc) {
+        if ttl == 0 {
+            self.remove(&service.server_name);
+            return;
+        }
+        let ttl = cmp::min(ttl, constants::MDNS_MAX_RECORD_TTL);
+        let expires_at = *now + time::Duration::seconds(ttl as i64);
+        let discovery_url = UAString::from(service.discovery_url());
+        let server_capabilities = Some(service.capabilities.iter().map(|c| UAString::from(c.as_str())).collect());
+        let key = service.server_name.to_lowercase();
+        if let Some(cached) = self.servers.get_mut(&key) {
+            if cached.server.discovery_url == discovery_url && cached.server.server_capabilities == server_capabilities {
+                cached.expires_at = expires_at;
+                return;
+            }
+        }
+        if !self.servers.contains_key(&key) && self.servers.len() >= constants::MAX_SERVERS_ON_NETWORK {
+            self.remove_expired(now);
+            if self.servers.len() >= constants::MAX_SERVERS_ON_NETWORK {
+                warn!("Ignoring server {} on the network since {} servers are already cached", service.server_name, self.servers.len());
+                return;
+            }
+        }
+        self.last_record_id += 1;
+        debug!("Server {} is on the network at {}", service.server_name, discovery_url);
+        self.servers.insert(key, CachedServer {
+            server: ServerOnNetwork {
+                record_id: self.last_record_id,
+                server_name: UAString::from(service.server_name.as_str()),
+                discovery_url,
+                server_capabilities,
+            },
+            expires_at,
+        });
+    }
+
+    pub fn remove(&mut self, server_name: &str) {
+        if self.servers.remove(&server_name.to_lowercase()).is_some() {
+            debug!("Server {} has left the network", server_name);
+        }
+    }
+
+    /// Removes servers whose announcements have not been renewed within their time to live
+    pub fn remove_expired(&mut self, now: &DateTimeUtc) {
+        self.servers.retain(|server_name, cached| {
+            if *now >= cached.expires_at {
+                debug!("Announcement of server {} has expired", server_name);
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    /// Tests if an mDNS message announces, or says goodbye to, any servers
+    pub fn is_announcement(message: &Message) -> bool {
+        message.is_response && message.records().any(|record| {
+            if let RecordData::Ptr(_) = record.data {
+                dns::name_matches(&record.name, mdns::OPCUA_SERVICE_TYPE)
+            } else {
+                false
+            }
+        })
+    }
+
+    /// Caches the servers announced in an mDNS response
+    pub fn handle_response(&mut self, message: &Message, now: &DateTimeUtc) {
+        for record in message.records() {
+            if let RecordData::Ptr(ref instance_name) = record.data {
+                if !dns::name_matches(&record.name, mdns::OPCUA_SERVICE_TYPE) {
+                    continue;
+                }
+                if let Some(server_name) = mdns::server_name_from_instance_name(instance_name) {
+                    if record.ttl == 0 {
+                        self.remove(&server_name);
+                    } else if let Some(service) = MdnsService::from_message(&server_name, instance_name, message) {
+                        self.update(&service, record.ttl, now);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Finds the servers whose record id is at least the starting record id and that have all the
+    /// capabilities in the filter, in record id order. A max records to return of 0 means no limit.
+    pub fn find(&self, starting_record_id: UInt32, max_records_to_return: UInt32, server_capability_filter: &Option<Vec<UAString>>) -> Vec<ServerOnNetwork> {
+        let mut servers: Vec<ServerOnNetwork> = self.servers.values()
+            .map(|cached| &cached.server)
+            .filter(|server| server.record_id >= starting_record_id)
+            .filter(|server| {
+                if let Some(ref server_capability_filter) = *server_capability_filter {
+                    let server_capabilities = server.server_capabilities.as_ref().map_or(&[][..], |c| &c[..]);
+                    server_capability_filter.iter().all(|filter| {
+                        server_capabilities.iter().any(|c| c.as_ref().eq_ignore_ascii_case(filter.as_ref()))
+                    })
+                } else {
+                    true
+                }
+            })
+            .cloned()
+            .collect();
+        servers.sort_by_key(|server| server.record_id);
+        if max_records_to_return > 0 {
+            servers.truncate(max_records_to_return as usize);
+        }
+        servers
+    }
+}
@@ -0,0 +1,397 @@
+use std::sync::{Arc, Mutex};
+
+use opcua_types::*;
+use opcua_types::node_ids::{DataTypeId, MethodId, ObjectId, ObjectTypeId, ReferenceTypeId};
+use opcua_types::status_codes::StatusCode;
+use opcua_types::status_codes::StatusCode::*;
+use opcua_types::service_types::Argument;
+
+use address_space::address_space::AddressSpace;
+use address_space::node::{Node, NodeType};
+use address_space::MethodFnCallback;
+use events::event::BaseEventType;
+use session::Session;
+
+/// The limits of a limit alarm. A limit that is `None` is not checked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    pub high_high: Option<Double>,
+    pub high: Option<Double>,
+    pub low: Option<Double>,
+    pub low_low: Option<Double>,
+}
+
+/// The limit that the input of an exclusive limit alarm is beyond
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LimitState {
+    HighHigh,
+    High,
+    Low,
+    LowLow,
+}
+
+impl LimitState {
+    /// The name of the state, which is also the browse name of its node
+    pub fn name(&self) -> &'static str {
+        match *self {
+            LimitState::HighHigh => "HighHigh",
+            LimitState::High => "High",
+            LimitState::Low => "Low",
+            LimitState::LowLow => "LowLow",
+        }
+    }
+
+    /// The node of the state in the `ExclusiveLimitStateMachineType`
+    pub fn state_id(&self) -> ObjectId {
+        match *self {
+            LimitState::HighHigh => ObjectId::ExclusiveLimitStateMachineType_HighHigh,
+            LimitState::High => ObjectId::ExclusiveLimitStateMachineType_High,
+            LimitState::Low => ObjectId::ExclusiveLimitStateMachineType_Low,
+            LimitState::LowLow => ObjectId::ExclusiveLimitStateMachineType_LowLow,
+        }
+    }
+}
+
+/// An alarm condition, i.e. an instance of `AlarmConditionType` or one of its subtypes. The
+/// condition has a node in the address space whose state variables mirror its state, and it
+/// raises an event each time its state changes.
+///
+/// An alarm is active while the problem it reports exists. Operators acknowledge an alarm to say
+/// they have seen it and confirm it to say the problem has been dealt with. The condition is
+/// retained, i.e. of interest to clients, until it is inactive, acknowledged and confirmed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    /// The node of the condition
+    pub node_id: NodeId,
+    /// The type of the condition, `AlarmConditionType` or one of its subtypes
+    pub condition_type: NodeId,
+    /// Name that identifies the condition on its source
+    pub condition_name: UAString,
+    /// The node that the condition reports on
+    pub source_node: NodeId,
+    pub source_name: UAString,
+    pub enabled: bool,
+    pub active: bool,
+    pub acked: bool,
+    pub confirmed: bool,
+    pub retain: bool,
+    pub severity: UInt16,
+    pub message: LocalizedText,
+    /// The most recent comment from an operator
+    pub comment: LocalizedText,
+    /// The id of the condition's most recent event, which clients must supply to acknowledge,
+    /// confirm or comment on the condition
+    pub event_id: ByteString,
+    /// The variable whose value drives a limit alarm
+    pub input_node: Option<NodeId>,
+    pub limits: Option<Limits>,
+    pub limit_state: Option<LimitState>,
+}
+
+impl Condition {
+    /// Creates an enabled, inactive alarm condition of the type on the source node. Application
+    /// code makes the alarm active or inactive.
+    pub fn new<T>(node_id: &NodeId, condition_type: T, condition_name: &str, source_node: &NodeId, source_name: &str, severity: UInt16) -> Condition where T: Into<NodeId> {
+        Condition {
+            node_id: node_id.clone(),
+            condition_type: condition_type.into(),
+            condition_name: UAString::from(condition_name),
+            source_node: source_node.clone(),
+            source_name: UAString::from(source_name),
+            enabled: true,
+            active: false,
+            acked: true,
+            confirmed: true,
+            retain: false,
+            severity,
+            message: LocalizedText::new("", ""),
+            comment: LocalizedText::new("", ""),
+            event_id: ByteString::random(16),
+            input_node: None,
+            limits: None,
+            limit_state: None,
+        }
+    }
+
+    /// Creates an exclusive limit alarm which is active while the value of the input variable is
+    /// beyond any of the limits. The alarm is updated automatically as the value changes.
+    pub fn new_exclusive_limit_alarm(node_id: &NodeId, condition_name: &str, source_node: &NodeId, source_name: &str, severity: UInt16, input_node: &NodeId, limits: Limits) -> Condition {
+        let mut condition = Condition::new(node_id, ObjectTypeId::ExclusiveLimitAlarmType, condition_name, source_node, source_name, severity);
+        condition.input_node = Some(input_node.clone());
+        condition.limits = Some(limits);
+        condition
+    }
+
+    /// Enables the condition
+    pub fn enable(&mut self) -> Result<(), StatusCode> {
+        if self.enabled {
+            return Err(BadConditionAlreadyEnabled);
+        }
+        self.enabled = true;
+        self.update_retain();
+        Ok(())
+    }
+
+    /// Disables the condition. A disabled condition ignores changes to its input and is not
+    /// retained.
+    pub fn disable(&mut self) -> Result<(), StatusCode> {
+        if !self.enabled {
+            return Err(BadConditionAlreadyDisabled);
+        }
+        self.enabled = false;
+        self.update_retain();
+        Ok(())
+    }
+
+    /// Adds a comment to the event with the id
+    pub fn add_comment(&mut self, event_id: &ByteString, comment: LocalizedText) -> Result<(), StatusCode> {
+        self.validate_event_id(event_id)?;
+        self.comment = comment;
+        Ok(())
+    }
+
+    /// Acknowledges the event with the id, optionally with a comment
+    pub fn acknowledge(&mut self, event_id: &ByteString, comment: LocalizedText) -> Result<(), StatusCode> {
+        self.validate_event_id(event_id)?;
+        if self.acked {
+            return Err(BadConditionBranchAlreadyAcked);
+        }
+        self.acked = true;
+        self.set_comment(comment);
+        self.update_retain();
+        Ok(())
+    }
+
+    /// Confirms the event with the id, optionally with a comment
+    pub fn confirm(&mut self, event_id: &ByteString, comment: LocalizedText) -> Result<(), StatusCode> {
+        self.validate_event_id(event_id)?;
+        if self.confirmed {
+            return Err(BadConditionBranchAlreadyConfirmed);
+        }
+        self.confirmed = true;
+        self.set_comment(comment);
+        self.update_retain();
+        Ok(())
+    }
+
+    /// Makes the alarm active or inactive, returning true if that changes its state. An alarm
+    /// that becomes active must be acknowledged and confirmed again.
+    pub fn set_active(&mut self, active: bool, message: &str) -> bool {
+        if !self.enabled || self.active == active {
+            return false;
+        }
+        self.active = active;
+        if active {
+            self.acked = false;
+            self.confirmed = false;
+        }
+        self.message = LocalizedText::new("", message);
+        self.update_retain();
+        true
+    }
+
+    /// Checks the value of a limit alarm's input against its limits, returning true if that
+    /// changes the state of the alarm.
+    pub fn set_input_value(&mut self, value: Double) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let limit_state = if let Some(ref limits) = self.limits {
+            let beyond = |limit: Option<Double>, high: bool| limit.map_or(false, |limit| if high { value >= limit } else { value <= limit });
+            if beyond(limits.high_high, true) {
+                Some(LimitState::HighHigh)
+            } else if beyond(limits.high, true) {
+                Some(LimitState::High)
+            } else if beyond(limits.low_low, false) {
+                Some(LimitState::LowLow)
+            } else if beyond(limits.low, false) {
+                Some(LimitState::Low)
+            } else {
+                None
+            }
+        } else {
+            return false;
+        };
+        if limit_state == self.limit_state {
+            return false;
+        }
+        self.limit_state = limit_state;
+        let message = if let Some(limit_state) = limit_state {
+            format!("{} is beyond the {} limit with a value of {}", self.source_name.as_ref(), limit_state.name(), value)
+        } else {
+            format!("{} is back within its limits with a value of {}", self.source_name.as_ref(), value)
+        };
+        if !self.set_active(limit_state.is_some(), &message) {
+            // The alarm stays active but moves from one limit to another
+            self.message = LocalizedText::new("", &message);
+        }
+        true
+    }
+
+    /// Creates the event that reports the current state of the condition. The condition's node
+    /// is the event's `NodeId` attribute which clients select with an empty browse path.
+    pub fn event(&self) -> BaseEventType {
+        let mut event = BaseEventType::new(self.condition_type.clone(), &self.source_node, self.source_name.as_ref(), "", self.severity);
+        event.event_id = self.event_id.clone();
+        event.message = self.message.clone();
+        event.set_field(&[], self.node_id.clone());
+        for (browse_path, value) in self.fields() {
+            let browse_path: Vec<QualifiedName> = browse_path.iter().map(|name| QualifiedName::new(0, name)).collect();
+            event.set_field(&browse_path, value);
+        }
+        event
+    }
+
+    /// Returns the condition's fields, by their browse path from the condition, that are not
+    /// fields of every event. A field comes after the field it belongs to, e.g. a state comes
+    /// before its id.
+    pub fn fields(&self) -> Vec<(Vec<&'static str>, Variant)> {
+        let two_state = |value: bool, true_state: &str, false_state: &str| {
+            Variant::from(LocalizedText::new("", if value { true_state } else { false_state }))
+        };
+        let mut fields = vec![
+            (vec!["ConditionName"], Variant::from(self.condition_name.clone())),
+            (vec!["BranchId"], Variant::from(NodeId::null())),
+            (vec!["Retain"], Variant::from(self.retain)),
+            (vec!["EnabledState"], two_state(self.enabled, "Enabled", "Disabled")),
+            (vec!["EnabledState", "Id"], Variant::from(self.enabled)),
+            (vec!["Quality"], Variant::from(Good)),
+            (vec!["LastSeverity"], Variant::from(self.severity)),
+            (vec!["Comment"], Variant::from(self.comment.clone())),
+            (vec!["ClientUserId"], Variant::from(UAString::null())),
+            (vec!["AckedState"], two_state(self.acked, "Acknowledged", "Unacknowledged")),
+            (vec!["AckedState", "Id"], Variant::from(self.acked)),
+            (vec!["ConfirmedState"], two_state(self.confirmed, "Confirmed", "Unconfirmed")),
+            (vec!["ConfirmedState", "Id"], Variant::from(self.confirmed)),
+            (vec!["ActiveState"], two_state(self.active, "Active", "Inactive")),
+            (vec!["ActiveState", "Id"], Variant::from(self.active)),
+        ];
+        if let Some(ref input_node) = self.input_node {
+            fields.push((vec!["InputNode"], Variant::from(input_node.clone())));
+        }
+        if let Some(ref limits) = self.limits {
+            let limits = [("HighHighLimit", limits.high_high), ("HighLimit", limits.high), ("LowLimit", limits.low), ("LowLowLimit", limits.low_low)];
+            for &(name, limit) in limits.iter() {
+                if let Some(limit) = limit {
+                    fields.push((vec![name], Variant::from(limit)));
+                }
+            }
+            // The current state of an inactive alarm is null
+            let (state_name, state_id) = if let Some(limit_state) = self.limit_state {
+                (limit_state.name(), limit_state.state_id().into())
+            } else {
+                ("", NodeId::null())
+            };
+            fields.push((vec!["LimitState", "CurrentState"], Variant::from(LocalizedText::new("", state_name))));
+            fields.push((vec!["LimitState", "CurrentState", "Id"], Variant::from(state_id)));
+        }
+        fields
+    }
+
+    /// Checks that the condition is enabled and that the event id is that of its latest event
+    fn validate_event_id(&self, event_id: &ByteString) -> Result<(), StatusCode> {
+        if !self.enabled {
+            Err(BadConditionDisabled)
+        } else if *event_id != self.event_id {
+            Err(BadEventIdUnknown)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn set_comment(&mut self, comment: LocalizedText) {
+        if !comment.text.is_null() && !comment.text.as_ref().is_empty() {
+            self.comment = comment;
+        }
+    }
+
+    fn update_retain(&mut self) {
+        self.retain = self.enabled && (self.active || !self.acked || !self.confirmed);
+    }
+}
+
+fn argument(name: &str, data_type: DataTypeId) -> Argument {
+    Argument {
+        name: UAString::from(name),
+        data_type: data_type.into(),
+        value_rank: -1,
+        array_dimensions: None,
+        description: LocalizedText::new("", ""),
+    }
+}
+
+/// Makes the standard method executable with the callback, describing its input arguments
+fn set_method_callback<F>(address_space: &mut AddressSpace, method_id: MethodId, input_arguments: &[Argument], callback: F)
+    where F: FnMut(&Session, &mut AddressSpace, &NodeId, &[Variant]) -> Result<Vec<Variant>, StatusCode> + Send + 'static {
+    let method_id: NodeId = method_id.into();
+    if let Some(&mut NodeType::Method(ref mut method)) = address_space.find_node_mut(&method_id) {
+        let _ = method.set_attribute(AttributeId::Executable, DataValue::new(true));
+        let _ = method.set_attribute(AttributeId::UserExecutable, DataValue::new(true));
+        method.set_callback(Arc::new(Mutex::new(MethodFnCallback::new(callback))));
+    } else {
+        error!("Standard method {:?} is missing from the address space", method_id);
+        return;
+    }
+    if !input_arguments.is_empty() {
+        // The nodeset has the InputArguments property but not its value
+        let property_id = address_space.find_references_from(&method_id, Some((ReferenceTypeId::HasProperty, false)))
+            .and_then(|references| references.into_iter().find(|r| {
+                address_space.find_node(&r.node_id).map_or(false, |n| n.as_node().browse_name() == QualifiedName::new(0, "InputArguments"))
+            }))
+            .map(|r| r.node_id);
+        if let Some(variable) = property_id.and_then(|property_id| address_space.find_variable_by_node_id(&property_id)) {
+            let value = input_arguments.iter().map(|argument| {
+                Variant::from(ExtensionObject::from_encodable(ObjectId::Argument_Encoding_DefaultBinary, argument.clone()))
+            }).collect::<Vec<Variant>>();
+            variable.set_value_direct(&DateTime::now(), Variant::Array(value));
+            variable.set_array_dimensions(&[input_arguments.len() as UInt32]);
+        }
+    }
+}
+
+/// Gets the event id and comment arguments of the Acknowledge, Confirm and AddComment methods
+fn event_id_and_comment(input_arguments: &[Variant]) -> Result<(ByteString, LocalizedText), StatusCode> {
+    match (&input_arguments[0], &input_arguments[1]) {
+        (&Variant::ByteString(ref event_id), &Variant::LocalizedText(ref comment)) => Ok((event_id.clone(), *comment.clone())),
+        _ => Err(BadInvalidArgument)
+    }
+}
+
+/// Sets callbacks on the standard methods of conditions so clients can enable, disable, comment
+/// on, acknowledge and confirm conditions, and refresh their state.
+pub fn add_condition_methods(address_space: &mut AddressSpace) {
+    let comment_arguments = [argument("EventId", DataTypeId::ByteString), argument("Comment", DataTypeId::LocalizedText)];
+
+    set_method_callback(address_space, MethodId::ConditionType_Enable, &[], |_, address_space, object_id, _| {
+        address_space.update_condition(object_id, |condition| condition.enable()).map(|_| Vec::new())
+    });
+    set_method_callback(address_space, MethodId::ConditionType_Disable, &[], |_, address_space, object_id, _| {
+        address_space.update_condition(object_id, |condition| condition.disable()).map(|_| Vec::new())
+    });
+    set_method_callback(address_space, MethodId::ConditionType_AddComment, &comment_arguments, |_, address_space, object_id, input_arguments| {
+        let (event_id, comment) = event_id_and_comment(input_arguments)?;
+        address_space.update_condition(object_id, |condition| condition.add_comment(&event_id, comment)).map(|_| Vec::new())
+    });
+    set_method_callback(address_space, MethodId::AcknowledgeableConditionType_Acknowledge, &comment_arguments, |_, address_space, object_id, input_arguments| {
+        let (event_id, comment) = event_id_and_comment(input_arguments)?;
+        address_space.update_condition(object_id, |condition| condition.acknowledge(&event_id, comment)).map(|_| Vec::new())
+    });
+    set_method_callback(address_space, MethodId::AcknowledgeableConditionType_Confirm, &comment_arguments, |_, address_space, object_id, input_arguments| {
+        let (event_id, comment) = event_id_and_comment(input_arguments)?;
+        address_space.update_condition(object_id, |condition| condition.confirm(&event_id, comment)).map(|_| Vec::new())
+    });
+    // ConditionRefresh is called on the ConditionType itself. A session may only refresh the
+    // conditions of its own subscriptions.
+    set_method_callback(address_space, MethodId::ConditionType_ConditionRefresh, &[argument("SubscriptionId", DataTypeId::UInt32)], |session, address_space, _, input_arguments| {
+        if let Variant::UInt32(subscription_id) = input_arguments[0] {
+            if !session.subscriptions.contains(subscription_id) {
+                debug!("ConditionRefresh called for subscription {} which is not of the session", subscription_id);
+                return Err(BadSubscriptionIdInvalid);
+            }
+            address_space.refresh_conditions(subscription_id);
+            Ok(Vec::new())
+        } else {
+            Err(BadInvalidArgument)
+        }
+    });
+}
@@ -0,0 +1,97 @@
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use opcua_types::*;
+
+/// The number of the most recently raised event
+static LAST_EVENT_NUMBER: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Returns the number of the most recently raised event. Events are numbered in the order they
+/// are raised so a monitored item can tell which events it has yet to see.
+pub fn last_event_number() -> usize {
+    LAST_EVENT_NUMBER.load(Ordering::SeqCst)
+}
+
+/// Returns the number for an event that is being raised
+pub fn next_event_number() -> usize {
+    LAST_EVENT_NUMBER.fetch_add(1, Ordering::SeqCst) + 1
+}
+
+/// An instance of an event. `BaseEventType` holds the fields that every event has. The fields of
+/// event subtypes are set by their browse path from the event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BaseEventType {
+    /// Uniquely identifies the event
+    pub event_id: ByteString,
+    /// The type of the event, i.e. `BaseEventType` or one of its subtypes
+    pub event_type: NodeId,
+    /// The node that the event originated from
+    pub source_node: NodeId,
+    /// Description of the source of the event, usually the display name of the source node
+    pub source_name: UAString,
+    /// Time that the event occurred
+    pub time: DateTime,
+    /// Time that the server received the event
+    pub receive_time: DateTime,
+    /// Human readable description of the event
+    pub message: LocalizedText,
+    /// Urgency of the event from 1 (lowest) to 1000 (highest)
+    pub severity: UInt16,
+    /// If set, only the monitored items of this subscription deliver the event, e.g. the events
+    /// that refresh conditions for the subscription that asked
+    pub subscription_id: Option<UInt32>,
+    /// Fields of event subtypes with their browse path from the event
+    fields: Vec<(Vec<QualifiedName>, Variant)>,
+}
+
+impl BaseEventType {
+    /// Creates an event of the type that occurred now on the source node
+    pub fn new<T>(event_type: T, source_node: &NodeId, source_name: &str, message: &str, severity: UInt16) -> BaseEventType where T: Into<NodeId> {
+        let now = DateTime::now();
+        BaseEventType {
+            event_id: ByteString::random(16),
+            event_type: event_type.into(),
+            source_node: source_node.clone(),
+            source_name: UAString::from(source_name),
+            time: now.clone(),
+            receive_time: now,
+            message: LocalizedText::new("", message),
+            severity,
+            subscription_id: None,
+            fields: Vec::new(),
+        }
+    }
+
+    /// Sets the value of a field that is not one of the base event type's, e.g. a field of an
+    /// event subtype. The browse path is relative to the event. The value with an empty browse
+    /// path is the node id of the event's condition, if it has one.
+    pub fn set_field<T>(&mut self, browse_path: &[QualifiedName], value: T) where T: Into<Variant> {
+        let value = value.into();
+        if let Some(field) = self.fields.iter_mut().find(|f| f.0.as_slice() == browse_path) {
+            field.1 = value;
+            return;
+        }
+        self.fields.push((browse_path.to_vec(), value));
+    }
+
+    /// Finds the value of the field with the browse path from the event, or `None` if the event
+    /// has no such field.
+    pub fn field(&self, browse_path: &[QualifiedName]) -> Option<Variant> {
+        if browse_path.len() == 1 && browse_path[0].namespace_index == 0 {
+            let value = match browse_path[0].name.as_ref() {
+                "EventId" => Some(Variant::from(self.event_id.clone())),
+                "EventType" => Some(Variant::from(self.event_type.clone())),
+                "SourceNode" => Some(Variant::from(self.source_node.clone())),
+                "SourceName" => Some(Variant::from(self.source_name.clone())),
+                "Time" => Some(Variant::from(self.time.clone())),
+                "ReceiveTime" => Some(Variant::from(self.receive_time.clone())),
+                "Message" => Some(Variant::from(self.message.clone())),
+                "Severity" => Some(Variant::from(self.severity)),
+                _ => None
+            };
+            if value.is_some() {
+                return value;
+            }
+        }
+        self.fields.iter().find(|f| f.0.as_slice() == browse_path).map(|f| f.1.clone())
+    }
+}
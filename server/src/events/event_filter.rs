@@ -0,0 +1,102 @@
+use opcua_types::*;
+use opcua_types::status_codes::StatusCode;
+use opcua_types::status_codes::StatusCode::*;
+use opcua_types::service_types::{EventFilter, EventFilterResult, EventFieldList, ContentFilterResult, AttributeOperand, SimpleAttributeOperand};
+
+use address_space::address_space::AddressSpace;
+use content_filter::{self, FilterTarget};
+use events::event::BaseEventType;
+
+/// Validates an event filter, returning the result for each of its select clauses and each element
+/// of its where clause. An error is returned if the filter cannot be used at all, together with the
+/// result that explains why if there is one.
+pub fn validate(event_filter: &EventFilter) -> Result<EventFilterResult, (StatusCode, Option<EventFilterResult>)> {
+    let select_clause_results: Vec<StatusCode> = if let Some(ref select_clauses) = event_filter.select_clauses {
+        select_clauses.iter().map(|clause| content_filter::validate_simple_attribute_operand(clause)).collect()
+    } else {
+        Vec::new()
+    };
+    if select_clause_results.is_empty() {
+        error!("Event filter has no select clauses");
+        return Err((BadEventFilterInvalid, None));
+    }
+    match content_filter::validate(&event_filter.where_clause) {
+        Ok(_) => Ok(EventFilterResult {
+            select_clause_results: Some(select_clause_results),
+            select_clause_diagnostic_infos: None,
+            where_clause_result: ContentFilterResult {
+                element_results: None,
+                element_diagnostic_infos: None,
+            },
+        }),
+        Err(where_clause_result) => {
+            error!("Event filter has an invalid where clause");
+            Err((BadEventFilterInvalid, Some(EventFilterResult {
+                select_clause_results: Some(select_clause_results),
+                select_clause_diagnostic_infos: None,
+                where_clause_result,
+            })))
+        }
+    }
+}
+
+/// Evaluates the filter against an event. If the event satisfies the where clause, the result
+/// is the fields that the select clauses choose. Fields that the event does not have are null,
+/// and fields whose select clause is invalid hold the error.
+pub fn evaluate(address_space: &AddressSpace, event_filter: &EventFilter, client_handle: UInt32, event: &BaseEventType) -> Option<EventFieldList> {
+    let target = EventTarget { address_space, event };
+    if !content_filter::evaluate(&event_filter.where_clause, &target) {
+        return None;
+    }
+    let event_fields = if let Some(ref select_clauses) = event_filter.select_clauses {
+        select_clauses.iter().map(|clause| target.simple_attribute_value(clause)).collect()
+    } else {
+        Vec::new()
+    };
+    Some(EventFieldList {
+        client_handle,
+        event_fields: Some(event_fields),
+    })
+}
+
+/// An event that a content filter is evaluated against
+struct EventTarget<'a> {
+    address_space: &'a AddressSpace,
+    event: &'a BaseEventType,
+}
+
+impl<'a> FilterTarget for EventTarget<'a> {
+    /// Selects the value of a field from the event
+    fn simple_attribute_value(&self, clause: &SimpleAttributeOperand) -> Variant {
+        let status_code = content_filter::validate_simple_attribute_operand(clause);
+        if status_code.is_bad() {
+            return Variant::from(status_code);
+        }
+        // The browse path is relative to the type definition so the event must be of that type
+        if !clause.type_definition_id.is_null() && !self.is_of_type(&clause.type_definition_id) {
+            return Variant::Empty;
+        }
+        // Fields are values of the event's properties and variables, except for the condition id
+        // which is the node id of the event's condition
+        let browse_path: &[QualifiedName] = if let Some(ref browse_path) = clause.browse_path { browse_path } else { &[] };
+        let selects_field = if browse_path.is_empty() {
+            clause.attribute_id == AttributeId::NodeId as UInt32
+        } else {
+            clause.attribute_id == AttributeId::Value as UInt32
+        };
+        if selects_field {
+            self.event.field(browse_path).unwrap_or(Variant::Empty)
+        } else {
+            Variant::Empty
+        }
+    }
+
+    /// Events are not nodes so attribute operands, which start from a node, select nothing
+    fn attribute_value(&self, _operand: &AttributeOperand) -> Variant {
+        Variant::Empty
+    }
+
+    fn is_of_type(&self, type_id: &NodeId) -> bool {
+        self.address_space.is_subtype(&self.event.event_type, type_id)
+    }
+}
@@ -0,0 +1,7 @@
+//! Events. Application code raises events on objects whose `EventNotifier` attribute permits
+//! clients to subscribe to events. Monitored items on those objects select fields from the events
+//! with an event filter and deliver them through their subscription.
+
+pub mod condition;
+pub mod event;
+pub mod event_filter;
@@ -0,0 +1,195 @@
+//! Historical access. A historical data provider stores the values of variables that are
+//! historizing so that clients can read them back with the HistoryRead service. The server
+//! supplies a memory based provider but implementations can substitute their own, e.g. to
+//! store values in a database.
+
+use std::collections::{HashMap, VecDeque};
+
+use opcua_types::*;
+use opcua_types::status_codes::StatusCode;
+use opcua_types::status_codes::StatusCode::*;
+use opcua_types::service_types::{HistoryUpdateType, ModificationInfo};
+
+/// A historical data provider stores and retrieves the values of historizing nodes. Values are
+/// ordered by their source timestamp, or their server timestamp if they have no source timestamp.
+///
+/// Time ranges passed to the provider are inclusive and the start time is never after the end time.
+pub trait HistoricalDataProvider {
+    /// Records a value that has been set on a historizing node
+    fn record_value(&mut self, node_id: &NodeId, value: &DataValue);
+
+    /// Reads the raw values of the node in the time range, oldest first. If bounds are requested,
+    /// the closest values either side of the range are also returned.
+    fn read_raw_values(&self, node_id: &NodeId, start_time: &DateTime, end_time: &DateTime, return_bounds: bool) -> Result<Vec<DataValue>, StatusCode>;
+
+    /// Reads the values of the node in the time range that were replaced or deleted, oldest first,
+    /// together with information about the modification.
+    fn read_modified_values(&self, node_id: &NodeId, start_time: &DateTime, end_time: &DateTime) -> Result<Vec<(DataValue, ModificationInfo)>, StatusCode>;
+
+    /// Deletes the raw values of the node in the time range. Returns `BadNoData` if there was
+    /// nothing to delete.
+    fn delete_raw_values(&mut self, node_id: &NodeId, start_time: &DateTime, end_time: &DateTime) -> Result<(), StatusCode>;
+
+    /// Deletes the modified values of the node in the time range. Returns `BadNoData` if there was
+    /// nothing to delete.
+    fn delete_modified_values(&mut self, node_id: &NodeId, start_time: &DateTime, end_time: &DateTime) -> Result<(), StatusCode>;
+}
+
+/// Returns the timestamp that a value is ordered by in history, in ticks
+pub fn value_ticks(value: &DataValue) -> i64 {
+    if let Some(ref source_timestamp) = value.source_timestamp {
+        source_timestamp.ticks()
+    } else if let Some(ref server_timestamp) = value.server_timestamp {
+        server_timestamp.ticks()
+    } else {
+        0
+    }
+}
+
+/// A historical data provider that holds values in memory. Each node has a ring buffer of values
+/// so that when it is full the oldest value is discarded to make way for the newest. Values that
+/// are replaced or deleted are kept in a second ring buffer so they can be read as modified values.
+pub struct MemoryHistoricalDataProvider {
+    /// Maximum number of values held per node, in each buffer
+    max_values_per_node: usize,
+    /// The raw values of each node, oldest first
+    values: HashMap<NodeId, VecDeque<DataValue>>,
+    /// The replaced or deleted values of each node, in order of modification
+    modified_values: HashMap<NodeId, VecDeque<(DataValue, ModificationInfo)>>,
+}
+
+impl HistoricalDataProvider for MemoryHistoricalDataProvider {
+    fn record_value(&mut self, node_id: &NodeId, value: &DataValue) {
+        let mut value = value.clone();
+        if value.source_timestamp.is_none() && value.server_timestamp.is_none() {
+            value.server_timestamp = Some(DateTime::now());
+            value.server_picoseconds = Some(0);
+        }
+        let ticks = value_ticks(&value);
+
+        let replaced_value = {
+            let values = self.values.entry(node_id.clone()).or_insert_with(VecDeque::new);
+            // Values usually arrive in time order so search for the insertion point from the back
+            let idx = values.iter().rposition(|v| value_ticks(v) <= ticks);
+            match idx {
+                Some(idx) if value_ticks(&values[idx]) == ticks => {
+                    // A value with the same timestamp is replaced
+                    Some(::std::mem::replace(&mut values[idx], value))
+                }
+                Some(idx) => {
+                    values.insert(idx + 1, value);
+                    None
+                }
+                None => {
+                    values.push_front(value);
+                    None
+                }
+            }
+        };
+
+        if let Some(replaced_value) = replaced_value {
+            self.add_modified_value(node_id, replaced_value, HistoryUpdateType::Replace);
+        } else {
+            let values = self.values.get_mut(node_id).unwrap();
+            while values.len() > self.max_values_per_node {
+                let _ = values.pop_front();
+            }
+        }
+    }
+
+    fn read_raw_values(&self, node_id: &NodeId, start_time: &DateTime, end_time: &DateTime, return_bounds: bool) -> Result<Vec<DataValue>, StatusCode> {
+        let (start_ticks, end_ticks) = (start_time.ticks(), end_time.ticks());
+        let result = if let Some(values) = self.values.get(node_id) {
+            let mut result: Vec<DataValue> = values.iter().filter(|v| {
+                let ticks = value_ticks(v);
+                ticks >= start_ticks && ticks <= end_ticks
+            }).cloned().collect();
+            if return_bounds {
+                if let Some(start_bound) = values.iter().rev().find(|v| value_ticks(v) < start_ticks) {
+                    result.insert(0, start_bound.clone());
+                }
+                if let Some(end_bound) = values.iter().find(|v| value_ticks(v) > end_ticks) {
+                    result.push(end_bound.clone());
+                }
+            }
+            result
+        } else {
+            Vec::new()
+        };
+        Ok(result)
+    }
+
+    fn read_modified_values(&self, node_id: &NodeId, start_time: &DateTime, end_time: &DateTime) -> Result<Vec<(DataValue, ModificationInfo)>, StatusCode> {
+        let (start_ticks, end_ticks) = (start_time.ticks(), end_time.ticks());
+        let mut result: Vec<(DataValue, ModificationInfo)> = if let Some(modified_values) = self.modified_values.get(node_id) {
+            modified_values.iter().filter(|&&(ref v, _)| {
+                let ticks = value_ticks(v);
+                ticks >= start_ticks && ticks <= end_ticks
+            }).cloned().collect()
+        } else {
+            Vec::new()
+        };
+        result.sort_by_key(|&(ref v, _)| value_ticks(v));
+        Ok(result)
+    }
+
+    fn delete_raw_values(&mut self, node_id: &NodeId, start_time: &DateTime, end_time: &DateTime) -> Result<(), StatusCode> {
+        let (start_ticks, end_ticks) = (start_time.ticks(), end_time.ticks());
+        let deleted_values = if let Some(values) = self.values.get_mut(node_id) {
+            let (deleted_values, retained_values): (VecDeque<DataValue>, VecDeque<DataValue>) = values.drain(..).partition(|v| {
+                let ticks = value_ticks(v);
+                ticks >= start_ticks && ticks <= end_ticks
+            });
+            *values = retained_values;
+            deleted_values
+        } else {
+            VecDeque::new()
+        };
+        if deleted_values.is_empty() {
+            Err(BadNoData)
+        } else {
+            for value in deleted_values {
+                self.add_modified_value(node_id, value, HistoryUpdateType::Delete);
+            }
+            Ok(())
+        }
+    }
+
+    fn delete_modified_values(&mut self, node_id: &NodeId, start_time: &DateTime, end_time: &DateTime) -> Result<(), StatusCode> {
+        let (start_ticks, end_ticks) = (start_time.ticks(), end_time.ticks());
+        if let Some(modified_values) = self.modified_values.get_mut(node_id) {
+            let len = modified_values.len();
+            modified_values.retain(|&(ref v, _)| {
+                let ticks = value_ticks(v);
+                ticks < start_ticks || ticks > end_ticks
+            });
+            if modified_values.len() < len {
+                return Ok(());
+            }
+        }
+        Err(BadNoData)
+    }
+}
+
+impl MemoryHistoricalDataProvider {
+    pub fn new(max_values_per_node: usize) -> MemoryHistoricalDataProvider {
+        MemoryHistoricalDataProvider {
+            max_values_per_node,
+            values: HashMap::new(),
+            modified_values: HashMap::new(),
+        }
+    }
+
+    fn add_modified_value(&mut self, node_id: &NodeId, value: DataValue, update_type: HistoryUpdateType) {
+        let modification_info = ModificationInfo {
+            modification_time: DateTime::now(),
+            update_type,
+            user_name: UAString::null(),
+        };
+        let modified_values = self.modified_values.entry(node_id.clone()).or_insert_with(VecDeque::new);
+        modified_values.push_back((value, modification_info));
+        while modified_values.len() > self.max_values_per_node {
+            let _ = modified_values.pop_front();
+        }
+    }
+}
@@ -6,6 +6,7 @@ extern crate futures;
 extern crate hyper;
 #[macro_use]
 extern crate log;
+extern crate net2;
 extern crate opcua_client;
 #[macro_use]
 extern crate opcua_core;
@@ -31,11 +32,16 @@ pub mod metrics;
 pub mod server;
 pub mod state;
 pub mod diagnostics;
+pub mod diagnostic_info;
 pub mod subscriptions;
 pub mod config;
 pub mod address_space;
 pub mod util;
 pub mod continuation_point;
+pub mod history;
+pub mod aggregates;
+pub mod events;
+pub mod content_filter;
 pub mod http;
 
 pub mod prelude {
@@ -49,6 +55,11 @@ pub mod prelude {
     pub use subscriptions::subscription::*;
     pub use subscriptions::monitored_item::*;
     pub use util::*;
+    pub use history::*;
+    pub use aggregates::{Aggregate, AggregateCalculator, AggregateValue};
+    pub use diagnostic_info::Diagnostic;
+    pub use events::event::BaseEventType;
+    pub use events::condition::{Condition, Limits, LimitState};
 }
 
 /// Constants that govern the internal workings of the server impl.
@@ -63,19 +74,56 @@ pub mod constants {
     pub const DEFAULT_MAX_SUBSCRIPTIONS: u32 = 100;
     /// Default, well known address for TCP discovery server
     pub const DEFAULT_DISCOVERY_SERVER_URL: &str = "opc.tcp://localhost:4840/UADiscovery";
+    /// Well known port that a local discovery server listens on
+    pub const DEFAULT_DISCOVERY_SERVER_PORT: u16 = 4840;
 
     // Internally controlled values
 
     /// Minimum publishing interval for subscriptions
     pub const MIN_PUBLISHING_INTERVAL: Double = 0.05f64;
+    /// Publishing interval in MS that subscriptions are given when they ask for one that is not a number
+    pub const DEFAULT_PUBLISHING_INTERVAL: Double = 1000f64;
     /// Minimum sampling interval in seconds allowed by clients on subscriptions or monitored_items
     pub const MIN_SAMPLING_INTERVAL: Double = 0.05f64;
+    /// Minimum processing interval in MS of monitored items with an aggregate filter
+    pub const MIN_PROCESSING_INTERVAL: Double = 100f64;
+    /// Maximum processing interval in MS of monitored items with an aggregate filter, one day
+    pub const MAX_PROCESSING_INTERVAL: Double = 86_400_000f64;
     /// Default data change queue size
     pub const DEFAULT_DATA_CHANGE_QUEUE_SIZE: usize = 1;
     /// Minimum data change queue allowed by clients on monitored items
     pub const MIN_DATA_CHANGE_QUEUE_SIZE: usize = 1;
     /// Maximum data change queue allowed by clients on monitored items
     pub const MAX_DATA_CHANGE_QUEUE_SIZE: usize = 10;
+    /// Default event queue size
+    pub const DEFAULT_EVENT_QUEUE_SIZE: usize = 10;
+    /// Maximum event queue allowed by clients on monitored items
+    pub const MAX_EVENT_QUEUE_SIZE: usize = 100;
+    /// Maximum number of raised events that the address space holds for monitored items to deliver
+    pub const MAX_BUFFERED_EVENTS: usize = 1000;
+    /// Maximum number of values set on a variable that a monitored item's listener holds until the
+    /// item next takes them
+    pub const MAX_PENDING_VALUE_CHANGES: usize = 1000;
+    /// Interval in MS at which limit alarms check inputs whose values change without the address
+    /// space knowing, e.g. values supplied by a getter
+    pub const LIMIT_ALARM_POLL_MS: u32 = 1000;
+    /// Interval in MS at which sessions whose connection dropped are checked for expiry
+    pub const DETACHED_SESSION_POLL_MS: u32 = 1000;
+    /// Time in MS after which a server registered with this discovery server is dropped unless it
+    /// registers again. Servers built with this crate register every 5 minutes.
+    pub const DISCOVERY_REGISTRATION_EXPIRY_MS: i64 = 10 * 60 * 1000;
+    /// The most servers that can be registered with this discovery server at once
+    pub const MAX_REGISTERED_SERVERS: usize = 100;
+    /// Time to live in seconds of the records that announce the server through mDNS
+    pub const MDNS_RECORD_TTL: u32 = 120;
+    /// Longest time to live in seconds that a server announced by others is cached for
+    pub const MDNS_MAX_RECORD_TTL: u32 = 4 * MDNS_RECORD_TTL;
+    /// The most servers announced by others that are cached at once
+    pub const MAX_SERVERS_ON_NETWORK: usize = 1000;
+    /// Interval in MS at which the server announces itself through mDNS, well inside the record TTL
+    pub const MDNS_ANNOUNCEMENT_INTERVAL_MS: u64 = 60000;
+    /// Time in MS that the mDNS thread waits for a message before checking if it should announce
+    pub const MDNS_POLL_MS: u64 = 500;
     /// The default size of preallocated vecs of monitored items per subscription
     pub const DEFAULT_MONITORED_ITEM_CAPACITY: usize = 100;
     /// Sampling interval in MS used internally to poll subscriptions. The more finegrained this is
@@ -94,9 +142,25 @@ pub mod constants {
     /// Maximum browse continuation points
     pub const MAX_BROWSE_CONTINUATION_POINTS: usize = 10;
     /// Maximum history continuation points
-    pub const MAX_HISTORY_CONTINUATION_POINTS: usize = 0;
+    pub const MAX_HISTORY_CONTINUATION_POINTS: usize = 10;
+    /// Maximum number of nodes that a session may register
+    pub const MAX_REGISTERED_NODES: usize = 10000;
+    /// Default number of values per node held by the memory history provider
+    pub const DEFAULT_HISTORY_VALUES_PER_NODE: usize = 1000;
     /// Maximum query continuation points
-    pub const MAX_QUERY_CONTINUATION_POINTS: usize = 0;
+    pub const MAX_QUERY_CONTINUATION_POINTS: usize = 10;
+    /// Maximum number of data sets returned by a single QueryFirst or QueryNext
+    pub const MAX_QUERY_DATA_SETS: usize = 1000;
+    /// Maximum number of processing intervals that a single processed history read calculates
+    pub const MAX_PROCESSED_INTERVALS: usize = 10000;
+    /// Default for whether aggregates treat uncertain values as bad
+    pub const AGGREGATE_TREAT_UNCERTAIN_AS_BAD: bool = true;
+    /// Default percentage of bad data in an interval for an aggregate to be bad
+    pub const AGGREGATE_PERCENT_DATA_BAD: u8 = 100;
+    /// Default percentage of good data in an interval for an aggregate to be good
+    pub const AGGREGATE_PERCENT_DATA_GOOD: u8 = 100;
+    /// Default for whether aggregates extrapolate past the last value from its slope
+    pub const AGGREGATE_USE_SLOPED_EXTRAPOLATION: bool = false;
 }
 
 #[cfg(test)]
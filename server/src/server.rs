@@ -26,6 +26,8 @@ use config::ServerConfig;
 use constants;
 use diagnostics::ServerDiagnostics;
 use discovery;
+use discovery::registered_servers::RegisteredServers;
+use discovery::servers_on_network::ServersOnNetwork;
 use metrics::ServerMetrics;
 use services::message_handler::MessageHandler;
 use session::Session;
@@ -100,6 +102,10 @@ impl Server {
             max_keep_alive_count: constants::MAX_KEEP_ALIVE_COUNT,
             diagnostics,
             abort: false,
+            detached_sessions: Vec::new(),
+            sessions: Vec::new(),
+            registered_servers: RegisteredServers::new(),
+            servers_on_network: ServersOnNetwork::new(),
         };
         let server_state = Arc::new(RwLock::new(server_state));
 
@@ -117,15 +123,35 @@ impl Server {
         // Cert store
         let certificate_store = Arc::new(RwLock::new(certificate_store));
 
-        let server = Server {
+        let mut server = Server {
             pending_polling_actions: Vec::new(),
             server_state,
             server_metrics: server_metrics.clone(),
-            address_space,
+            address_space: address_space.clone(),
             certificate_store,
             connections: Arc::new(RwLock::new(Vec::new())),
         };
 
+        // Limit alarms poll inputs which may change without the address space knowing. The address
+        // space is only locked for writing if there are limit alarms to update.
+        server.add_polling_action(constants::LIMIT_ALARM_POLL_MS, move || {
+            let has_limit_alarms = {
+                let address_space = trace_read_lock_unwrap!(address_space);
+                address_space.has_limit_alarms()
+            };
+            if has_limit_alarms {
+                let mut address_space = trace_write_lock_unwrap!(address_space);
+                address_space.update_limit_alarms();
+            }
+        });
+
+        // Detached sessions are discarded once their session timeout elapses
+        let detached_server_state = server.server_state.clone();
+        server.add_polling_action(constants::DETACHED_SESSION_POLL_MS, move || {
+            let mut server_state = trace_write_lock_unwrap!(detached_server_state);
+            server_state.remove_expired_sessions(&chrono::Utc::now());
+        });
+
         let mut server_metrics = trace_write_lock_unwrap!(server_metrics);
         server_metrics.set_server_info(&server);
 
@@ -160,12 +186,14 @@ impl Server {
         }
 
         // Get the address and discovery url
-        let (sock_addr, discovery_server_url) = {
+        let (sock_addr, discovery_server_url, multicast_discovery) = {
             let server = trace_read_lock_unwrap!(server);
             let server_state = trace_read_lock_unwrap!(server.server_state);
             let config = trace_read_lock_unwrap!(server_state.config);
             let sock_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::from_str(&config.tcp_config.host).unwrap()), config.tcp_config.port);
-            (sock_addr, config.discovery_server_url.clone())
+            // A discovery server does not register itself with another
+            let discovery_server_url = if config.discovery_server { None } else { config.discovery_server_url.clone() };
+            (sock_addr, discovery_server_url, config.multicast_discovery)
         };
 
         info!("Waiting for Connection");
@@ -187,6 +215,10 @@ impl Server {
 
                 // Start a timer that registers the server with a discovery server
                 server.start_discovery_server_registration_timer(discovery_server_url);
+                // Announce the server on the network
+                if multicast_discovery {
+                    discovery::mdns::start(server.server_state.clone());
+                }
                 // Start any pending polling action timers
                 server.start_pending_polling_actions();
 
@@ -304,6 +336,10 @@ impl Server {
         let session = {
             Arc::new(RwLock::new(Session::new(self)))
         };
+        {
+            let mut server_state = trace_write_lock_unwrap!(self.server_state);
+            server_state.register_session(&session);
+        }
         let address_space = self.address_space.clone();
         let message_handler = MessageHandler::new(self.certificate_store.clone(), self.server_state.clone(), session.clone(), address_space.clone());
         TcpTransport::new(self.server_state.clone(), session, address_space, message_handler)
@@ -1,14 +1,21 @@
 use std::result::Result;
 
 use opcua_types::*;
+use opcua_types::node_ids::ObjectId;
 use opcua_types::status_codes::StatusCode;
 use opcua_types::status_codes::StatusCode::*;
 use opcua_types::service_types::*;
 
 use services::Service;
 use address_space::access_level;
+use address_space::user_access_level;
 use address_space::address_space::AddressSpace;
-use address_space::node::NodeType;
+use address_space::node::{Node, NodeType};
+use aggregates::{self, AggregateCalculator, AggregateValue};
+use continuation_point::HistoryContinuationPoint;
+use diagnostic_info::{Diagnostic, ResponseDiagnostics};
+use history::HistoricalDataProvider;
+use session::Session;
 
 pub struct AttributeService {}
 
@@ -27,7 +34,7 @@ impl AttributeService {
     /// elements or to read ranges of elements of the composite. Servers may make historical
     /// values available to Clients using this Service, although the historical values themselves
     /// are not visible in the AddressSpace.
-    pub fn read(&self, address_space: &AddressSpace, request: ReadRequest) -> Result<SupportedMessage, StatusCode> {
+    pub fn read(&self, session: &Session, address_space: &AddressSpace, mut request: ReadRequest) -> Result<SupportedMessage, StatusCode> {
         // Read nodes and their attributes
         let timestamps_to_return = request.timestamps_to_return;
 
@@ -36,84 +43,506 @@ impl AttributeService {
             return Ok(self.service_fault(&request.request_header, BadMaxAgeInvalid));
         }
 
-        let results = if let Some(ref nodes_to_read) = request.nodes_to_read {
-            let results = nodes_to_read.iter().map(|node_to_read| {
-                Self::read_node_value(&address_space, node_to_read, timestamps_to_return)
-            }).collect();
-            Some(results)
+        let (results, diagnostics): (Vec<DataValue>, Vec<Option<Diagnostic>>) = if let Some(ref mut nodes_to_read) = request.nodes_to_read {
+            nodes_to_read.iter_mut().map(|node_to_read| {
+                session.resolve_registered_node(&mut node_to_read.node_id);
+                if address_space.is_user_readable(session, &node_to_read.node_id, node_to_read.attribute_id) {
+                    let (mut value, diagnostic) = Self::read_node_value(&address_space, node_to_read, timestamps_to_return);
+                    // The user access level is the one for the user of the session
+                    if node_to_read.attribute_id == AttributeId::UserAccessLevel as UInt32 && value.value.is_some() {
+                        if let Some(user_access_level) = address_space.user_access_level(session, &node_to_read.node_id) {
+                            value.value = Some(Variant::Byte(user_access_level));
+                        }
+                    }
+                    (value, diagnostic)
+                } else {
+                    let mut value = DataValue::null();
+                    value.status = Some(BadUserAccessDenied);
+                    (value, Some(Diagnostic::new(BadUserAccessDenied)))
+                }
+            }).unzip()
         } else {
             warn!("ReadRequest nothing to do");
             return Ok(self.service_fault(&request.request_header, BadNothingToDo));
         };
 
-        let diagnostic_infos = None;
+        let mut response_diagnostics = ResponseDiagnostics::new(&request.request_header);
+        let diagnostic_infos = response_diagnostics.operation_diagnostic_infos(&diagnostics);
         let response = ReadResponse {
-            response_header: ResponseHeader::new_good(&request.request_header),
-            results,
+            response_header: response_diagnostics.response_header(&request.request_header, Good, None),
+            results: Some(results),
             diagnostic_infos,
         };
 
         Ok(SupportedMessage::ReadResponse(response))
     }
 
-    fn read_node_value(address_space: &AddressSpace, node_to_read: &ReadValueId, timestamps_to_return: TimestampsToReturn) -> DataValue {
+    /// Spec:
+    ///
+    /// This Service is used to read historical values or Events of one or more Nodes. Servers may
+    /// make historical values available to Clients using this Service, although the historical
+    /// values themselves are not visible in the AddressSpace.
+    ///
+    /// Raw and modified values, processed values and values at times can be read. Raw values
+    /// beyond the number of values per node are returned through a continuation point. Events
+    /// cannot be read.
+    pub fn history_read(&self, session: &mut Session, address_space: &AddressSpace, request: HistoryReadRequest) -> Result<SupportedMessage, StatusCode> {
+        let nodes_to_read = if let Some(ref nodes_to_read) = request.nodes_to_read {
+            nodes_to_read
+        } else {
+            warn!("HistoryReadRequest nothing to do");
+            return Ok(self.service_fault(&request.request_header, BadNothingToDo));
+        };
+        if request.timestamps_to_return == TimestampsToReturn::Neither {
+            warn!("HistoryReadRequest timestamps to return is invalid");
+            return Ok(self.service_fault(&request.request_header, BadTimestampsToReturnInvalid));
+        }
+
+        let results: Vec<HistoryReadResult> = if request.release_continuation_points {
+            // Continuation points are released without reading any more values
+            nodes_to_read.iter().map(|node_to_read| {
+                let status_code = if session.take_history_continuation_point(&node_to_read.continuation_point).is_some() {
+                    Good
+                } else {
+                    BadContinuationPointInvalid
+                };
+                Self::history_read_result(status_code, ByteString::null(), ExtensionObject::null())
+            }).collect()
+        } else {
+            let history_read_details = &request.history_read_details;
+            match history_read_details.node_id.as_object_id() {
+                Ok(ObjectId::ReadRawModifiedDetails_Encoding_DefaultBinary) => {
+                    let details = if let Ok(details) = history_read_details.decode_inner::<ReadRawModifiedDetails>() {
+                        details
+                    } else {
+                        return Ok(self.service_fault(&request.request_header, BadHistoryOperationInvalid));
+                    };
+                    let history_provider = address_space.history_provider();
+                    let history_provider = trace_lock_unwrap!(history_provider);
+                    nodes_to_read.iter().map(|node_to_read| {
+                        Self::history_read_raw_modified(session, address_space, &*history_provider, &details, request.timestamps_to_return, node_to_read)
+                    }).collect()
+                }
+                Ok(ObjectId::ReadProcessedDetails_Encoding_DefaultBinary) => {
+                    let details = if let Ok(details) = history_read_details.decode_inner::<ReadProcessedDetails>() {
+                        details
+                    } else {
+                        return Ok(self.service_fault(&request.request_header, BadHistoryOperationInvalid));
+                    };
+                    // Each node is read with the aggregate in the same position
+                    let aggregate_types = if let Some(ref aggregate_types) = details.aggregate_type {
+                        aggregate_types
+                    } else {
+                        return Ok(self.service_fault(&request.request_header, BadAggregateListMismatch));
+                    };
+                    if aggregate_types.len() != nodes_to_read.len() {
+                        return Ok(self.service_fault(&request.request_header, BadAggregateListMismatch));
+                    }
+                    let history_provider = address_space.history_provider();
+                    let history_provider = trace_lock_unwrap!(history_provider);
+                    nodes_to_read.iter().zip(aggregate_types.iter()).map(|(node_to_read, aggregate_type)| {
+                        Self::history_read_processed(address_space, &*history_provider, &details, aggregate_type, request.timestamps_to_return, node_to_read)
+                    }).collect()
+                }
+                Ok(ObjectId::ReadAtTimeDetails_Encoding_DefaultBinary) => {
+                    let details = if let Ok(details) = history_read_details.decode_inner::<ReadAtTimeDetails>() {
+                        details
+                    } else {
+                        return Ok(self.service_fault(&request.request_header, BadHistoryOperationInvalid));
+                    };
+                    if details.req_times.as_ref().map_or(true, |req_times| req_times.is_empty()) {
+                        warn!("HistoryReadRequest no times to read at");
+                        return Ok(self.service_fault(&request.request_header, BadNothingToDo));
+                    }
+                    let history_provider = address_space.history_provider();
+                    let history_provider = trace_lock_unwrap!(history_provider);
+                    nodes_to_read.iter().map(|node_to_read| {
+                        Self::history_read_at_time(address_space, &*history_provider, &details, request.timestamps_to_return, node_to_read)
+                    }).collect()
+                }
+                Ok(ObjectId::ReadEventDetails_Encoding_DefaultBinary) => {
+                    warn!("HistoryReadRequest details {:?} are not supported", history_read_details.node_id);
+                    return Ok(self.service_fault(&request.request_header, BadHistoryOperationUnsupported));
+                }
+                _ => {
+                    warn!("HistoryReadRequest details {:?} are invalid", history_read_details.node_id);
+                    return Ok(self.service_fault(&request.request_header, BadHistoryOperationInvalid));
+                }
+            }
+        };
+
+        let mut response_diagnostics = ResponseDiagnostics::new(&request.request_header);
+        let status_codes: Vec<StatusCode> = results.iter().map(|r| r.status_code).collect();
+        let diagnostic_infos = response_diagnostics.operation_diagnostic_infos(&ResponseDiagnostics::operation_status_diagnostics(&status_codes));
+        let response = HistoryReadResponse {
+            response_header: response_diagnostics.response_header(&request.request_header, Good, None),
+            results: Some(results),
+            diagnostic_infos,
+        };
+
+        Ok(SupportedMessage::HistoryReadResponse(response))
+    }
+
+    /// Spec:
+    ///
+    /// This Service is used to update historical values or Events of one or more Nodes. Several
+    /// request parameters indicate how the Server is to update the historical value or Event.
+    ///
+    /// Only the deletion of raw and modified values is supported.
+    pub fn history_update(&self, address_space: &AddressSpace, request: HistoryUpdateRequest) -> Result<SupportedMessage, StatusCode> {
+        let results: Vec<HistoryUpdateResult> = if let Some(ref history_update_details) = request.history_update_details {
+            history_update_details.iter().map(|details| {
+                let status_code = match Self::history_update_node(address_space, details) {
+                    Ok(_) => Good,
+                    Err(status_code) => status_code
+                };
+                HistoryUpdateResult {
+                    status_code,
+                    operation_results: None,
+                    diagnostic_infos: None,
+                }
+            }).collect()
+        } else {
+            warn!("HistoryUpdateRequest nothing to do");
+            return Ok(self.service_fault(&request.request_header, BadNothingToDo));
+        };
+
+        let mut response_diagnostics = ResponseDiagnostics::new(&request.request_header);
+        let status_codes: Vec<StatusCode> = results.iter().map(|r| r.status_code).collect();
+        let diagnostic_infos = response_diagnostics.operation_diagnostic_infos(&ResponseDiagnostics::operation_status_diagnostics(&status_codes));
+        let response = HistoryUpdateResponse {
+            response_header: response_diagnostics.response_header(&request.request_header, Good, None),
+            results: Some(results),
+            diagnostic_infos,
+        };
+
+        Ok(SupportedMessage::HistoryUpdateResponse(response))
+    }
+
+    fn history_read_result(status_code: StatusCode, continuation_point: ByteString, history_data: ExtensionObject) -> HistoryReadResult {
+        HistoryReadResult {
+            status_code,
+            continuation_point,
+            history_data,
+        }
+    }
+
+    /// Reads the raw or modified values of a node, or the values remaining from a previous read
+    /// if a continuation point is supplied.
+    fn history_read_raw_modified(session: &mut Session, address_space: &AddressSpace, history_provider: &HistoricalDataProvider, details: &ReadRawModifiedDetails, timestamps_to_return: TimestampsToReturn, node_to_read: &HistoryReadValueId) -> HistoryReadResult {
+        let values = if node_to_read.continuation_point.is_null() {
+            match Self::read_raw_modified_values(address_space, history_provider, details, node_to_read) {
+                Ok(values) => values,
+                Err(status_code) => {
+                    return Self::history_read_result(status_code, ByteString::null(), ExtensionObject::null());
+                }
+            }
+        } else {
+            match session.take_history_continuation_point(&node_to_read.continuation_point) {
+                Some(ref values) if values.node_id != node_to_read.node_id => {
+                    return Self::history_read_result(BadContinuationPointInvalid, ByteString::null(), ExtensionObject::null());
+                }
+                Some(values) => values,
+                None => {
+                    return Self::history_read_result(BadContinuationPointInvalid, ByteString::null(), ExtensionObject::null());
+                }
+            }
+        };
+
+        // Return the number of values asked for and hold the remainder in a continuation point
+        let HistoryContinuationPoint { node_id, num_values_per_node, mut data_values, mut modification_infos, .. } = values;
+        let continuation_point = if num_values_per_node > 0 && data_values.len() > num_values_per_node {
+            let continuation_point = ByteString::random(6);
+            session.add_history_continuation_point(HistoryContinuationPoint {
+                id: continuation_point.clone(),
+                node_id,
+                num_values_per_node,
+                data_values: data_values.split_off(num_values_per_node),
+                modification_infos: modification_infos.as_mut().map(|m| m.split_off(num_values_per_node)),
+            });
+            continuation_point
+        } else {
+            ByteString::null()
+        };
+
+        let status_code = if data_values.is_empty() { GoodNoData } else { Good };
+        let data_values: Vec<DataValue> = data_values.into_iter().map(|value| Self::history_timestamps(value, timestamps_to_return)).collect();
+        let history_data = if let Some(modification_infos) = modification_infos {
+            ExtensionObject::from_encodable(ObjectId::HistoryModifiedData_Encoding_DefaultBinary, HistoryModifiedData {
+                data_values: Some(data_values),
+                modification_infos: Some(modification_infos),
+            })
+        } else {
+            ExtensionObject::from_encodable(ObjectId::HistoryData_Encoding_DefaultBinary, HistoryData {
+                data_values: Some(data_values),
+            })
+        };
+        Self::history_read_result(status_code, continuation_point, history_data)
+    }
+
+    /// Reads all of the raw or modified values of a node that match the details, in the order
+    /// that they are to be returned to the client
+    fn read_raw_modified_values(address_space: &AddressSpace, history_provider: &HistoricalDataProvider, details: &ReadRawModifiedDetails, node_to_read: &HistoryReadValueId) -> Result<HistoryContinuationPoint, StatusCode> {
+        Self::validate_history_read_node(address_space, node_to_read)?;
+
+        // An unspecified start or end time is read as far as the number of values per node. If the
+        // start time is after the end time, or only the end time is specified, values are returned
+        // newest first.
+        let num_values_per_node = details.num_values_per_node as usize;
+        let (start_time, end_time, reverse) = match (details.start_time.ticks() > 0, details.end_time.ticks() > 0) {
+            (true, true) => {
+                if details.start_time.ticks() <= details.end_time.ticks() {
+                    (details.start_time.clone(), details.end_time.clone(), false)
+                } else {
+                    (details.end_time.clone(), details.start_time.clone(), true)
+                }
+            }
+            (true, false) if num_values_per_node > 0 => (details.start_time.clone(), DateTime::endtimes(), false),
+            (false, true) if num_values_per_node > 0 => (DateTime::epoch(), details.end_time.clone(), true),
+            _ => {
+                return Err(BadHistoryOperationInvalid);
+            }
+        };
+
+        let (mut data_values, mut modification_infos) = if details.is_read_modified {
+            let (data_values, modification_infos): (Vec<DataValue>, Vec<ModificationInfo>) = history_provider.read_modified_values(&node_to_read.node_id, &start_time, &end_time)?.into_iter().unzip();
+            (data_values, Some(modification_infos))
+        } else {
+            (history_provider.read_raw_values(&node_to_read.node_id, &start_time, &end_time, details.return_bounds)?, None)
+        };
+        if reverse {
+            data_values.reverse();
+            if let Some(ref mut modification_infos) = modification_infos {
+                modification_infos.reverse();
+            }
+        }
+
+        Ok(HistoryContinuationPoint {
+            id: ByteString::null(),
+            node_id: node_to_read.node_id.clone(),
+            num_values_per_node,
+            data_values,
+            modification_infos,
+        })
+    }
+
+    /// Reads the values of an aggregate over each processing interval of a node's history. The
+    /// historian bits of the calculated values are returned in the info bits of their status.
+    fn history_read_processed(address_space: &AddressSpace, history_provider: &HistoricalDataProvider, details: &ReadProcessedDetails, aggregate_type: &NodeId, timestamps_to_return: TimestampsToReturn, node_to_read: &HistoryReadValueId) -> HistoryReadResult {
+        let result = Self::validate_history_read_node(address_space, node_to_read).and_then(|_| {
+            if details.start_time.ticks() <= 0 || details.end_time.ticks() <= 0 || details.processing_interval < 0f64 {
+                return Err(BadHistoryOperationInvalid);
+            }
+            let stepped = aggregates::is_stepped(address_space, &node_to_read.node_id);
+            let calculator = AggregateCalculator::new(aggregate_type, &details.aggregate_configuration, stepped).map_err(|status_code| {
+                if status_code == BadAggregateInvalidInputs { BadAggregateConfigurationRejected } else { status_code }
+            })?;
+            let (start_time, end_time) = if details.start_time.ticks() <= details.end_time.ticks() {
+                (&details.start_time, &details.end_time)
+            } else {
+                (&details.end_time, &details.start_time)
+            };
+            let values = history_provider.read_raw_values(&node_to_read.node_id, start_time, end_time, true)?;
+            calculator.calculate_intervals(&values, details.start_time.ticks(), details.end_time.ticks(), details.processing_interval)
+        });
+        Self::history_aggregate_result(result, timestamps_to_return)
+    }
+
+    /// Reads the values of a node's history at each of the requested times, interpolating between
+    /// the raw values either side of a time that has no raw value.
+    fn history_read_at_time(address_space: &AddressSpace, history_provider: &HistoricalDataProvider, details: &ReadAtTimeDetails, timestamps_to_return: TimestampsToReturn, node_to_read: &HistoryReadValueId) -> HistoryReadResult {
+        let result = Self::validate_history_read_node(address_space, node_to_read).and_then(|_| {
+            let req_times = details.req_times.as_ref().unwrap();
+            let start_ticks = req_times.iter().map(|t| t.ticks()).min().unwrap();
+            let end_ticks = req_times.iter().map(|t| t.ticks()).max().unwrap();
+            let values = history_provider.read_raw_values(&node_to_read.node_id, &DateTime::from(start_ticks), &DateTime::from(end_ticks), true)?;
+            let stepped = aggregates::is_stepped(address_space, &node_to_read.node_id);
+            let calculator = AggregateCalculator::new(&ObjectId::AggregateFunction_Interpolative.into(), &aggregates::default_configuration(), stepped)?;
+            Ok(req_times.iter().map(|t| calculator.value_at(&values, t.ticks(), details.use_simple_bounds)).collect())
+        });
+        Self::history_aggregate_result(result, timestamps_to_return)
+    }
+
+    /// Makes the result of reading calculated values as history data
+    fn history_aggregate_result(result: Result<Vec<AggregateValue>, StatusCode>, timestamps_to_return: TimestampsToReturn) -> HistoryReadResult {
+        match result {
+            Ok(values) => {
+                let status_code = if values.is_empty() { GoodNoData } else { Good };
+                // Calculated values are timestamped by the server with the time they are calculated for
+                let data_values: Vec<DataValue> = values.into_iter().map(|value| {
+                    let mut value = value.into_data_value();
+                    value.server_timestamp = value.source_timestamp.clone();
+                    Self::history_timestamps(value, timestamps_to_return)
+                }).collect();
+                let history_data = ExtensionObject::from_encodable(ObjectId::HistoryData_Encoding_DefaultBinary, HistoryData {
+                    data_values: Some(data_values),
+                });
+                Self::history_read_result(status_code, ByteString::null(), history_data)
+            }
+            Err(status_code) => Self::history_read_result(status_code, ByteString::null(), ExtensionObject::null())
+        }
+    }
+
+    /// Applies a single set of history update details
+    fn history_update_node(address_space: &AddressSpace, details: &ExtensionObject) -> Result<(), StatusCode> {
+        match details.node_id.as_object_id() {
+            Ok(ObjectId::DeleteRawModifiedDetails_Encoding_DefaultBinary) => {
+                let details = details.decode_inner::<DeleteRawModifiedDetails>()?;
+                Self::validate_history_node(address_space, &details.node_id, user_access_level::HISTORY_WRITE)?;
+                // An unspecified end time deletes everything after the start time
+                let end_time = if details.end_time.ticks() > 0 { details.end_time.clone() } else { DateTime::endtimes() };
+                if details.start_time.ticks() > end_time.ticks() {
+                    return Err(BadHistoryOperationInvalid);
+                }
+                let history_provider = address_space.history_provider();
+                let mut history_provider = trace_lock_unwrap!(history_provider);
+                if details.is_delete_modified {
+                    history_provider.delete_modified_values(&details.node_id, &details.start_time, &end_time)
+                } else {
+                    history_provider.delete_raw_values(&details.node_id, &details.start_time, &end_time)
+                }
+            }
+            Ok(ObjectId::UpdateDataDetails_Encoding_DefaultBinary) |
+            Ok(ObjectId::UpdateStructureDataDetails_Encoding_DefaultBinary) |
+            Ok(ObjectId::UpdateEventDetails_Encoding_DefaultBinary) |
+            Ok(ObjectId::DeleteAtTimeDetails_Encoding_DefaultBinary) |
+            Ok(ObjectId::DeleteEventDetails_Encoding_DefaultBinary) => {
+                Err(BadHistoryOperationUnsupported)
+            }
+            _ => {
+                Err(BadHistoryOperationInvalid)
+            }
+        }
+    }
+
+    /// Tests that the node's history can be read as a whole with the default encoding
+    fn validate_history_read_node(address_space: &AddressSpace, node_to_read: &HistoryReadValueId) -> Result<(), StatusCode> {
+        Self::validate_history_node(address_space, &node_to_read.node_id, user_access_level::HISTORY_READ)?;
+        if !node_to_read.index_range.is_null() {
+            // Index ranges are not supported
+            Err(BadIndexRangeInvalid)
+        } else if !node_to_read.data_encoding.is_null() {
+            Err(BadDataEncodingUnsupported)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Tests that the node is a variable whose user access level permits the history access
+    fn validate_history_node(address_space: &AddressSpace, node_id: &NodeId, required_access: u8) -> Result<(), StatusCode> {
+        match address_space.find_node(node_id) {
+            Some(&NodeType::Variable(ref variable)) => {
+                if variable.user_access_level() & required_access == 0 {
+                    Err(BadUserAccessDenied)
+                } else {
+                    Ok(())
+                }
+            }
+            Some(_) => Err(BadHistoryOperationUnsupported),
+            None => Err(BadNodeIdUnknown),
+        }
+    }
+
+    /// Removes the timestamps the client did not ask for from a historical value
+    fn history_timestamps(mut value: DataValue, timestamps_to_return: TimestampsToReturn) -> DataValue {
+        match timestamps_to_return {
+            TimestampsToReturn::Source => {
+                value.server_timestamp = None;
+                value.server_picoseconds = None;
+            }
+            TimestampsToReturn::Server => {
+                value.source_timestamp = None;
+                value.source_picoseconds = None;
+            }
+            _ => {}
+        }
+        value
+    }
+
+    fn read_node_value(address_space: &AddressSpace, node_to_read: &ReadValueId, timestamps_to_return: TimestampsToReturn) -> (DataValue, Option<Diagnostic>) {
         let mut result_value = DataValue {
             value: None,
             status: None,
+            status_info_bits: 0,
             source_timestamp: None,
             source_picoseconds: None,
             server_timestamp: None,
             server_picoseconds: None,
         };
-        // Node node found
+        let attribute = match Self::find_readable_attribute(address_space, node_to_read) {
+            Ok(attribute) => attribute,
+            Err(diagnostic) => {
+                result_value.status = Some(diagnostic.status_code);
+                return (result_value, Some(diagnostic));
+            }
+        };
+
+        // Result value is clone from the attribute, or the part of it in the index range
+        let value = match NumericRange::from_index_range(&node_to_read.index_range) {
+            Ok(Some(ref index_range)) => match attribute.value {
+                Some(ref value) => value.range_of(index_range).map(Some),
+                None => Err(BadIndexRangeNoData)
+            },
+            Ok(None) => Ok(attribute.value.clone()),
+            Err(status_code) => Err(status_code)
+        };
+        match value {
+            Ok(value) => {
+                result_value.value = value;
+                result_value.status = attribute.status;
+            }
+            Err(status_code) => {
+                result_value.status = Some(status_code);
+                return (result_value, Some(Diagnostic::new(status_code)));
+            }
+        }
+        match timestamps_to_return {
+            TimestampsToReturn::Source => {
+                result_value.source_timestamp = attribute.source_timestamp.clone();
+                result_value.source_picoseconds = attribute.source_picoseconds;
+            }
+            TimestampsToReturn::Server => {
+                result_value.server_timestamp = attribute.server_timestamp.clone();
+                result_value.server_picoseconds = attribute.server_picoseconds;
+            }
+            TimestampsToReturn::Both => {
+                result_value.source_timestamp = attribute.source_timestamp.clone();
+                result_value.source_picoseconds = attribute.source_picoseconds;
+                result_value.server_timestamp = attribute.server_timestamp.clone();
+                result_value.server_picoseconds = attribute.server_picoseconds;
+            }
+            TimestampsToReturn::Neither => {
+                // Nothing needs to change
+            }
+        }
+        (result_value, None)
+    }
+
+    /// Finds the attribute to read, or a diagnostic explaining why it cannot be read
+    fn find_readable_attribute(address_space: &AddressSpace, node_to_read: &ReadValueId) -> Result<DataValue, Diagnostic> {
         if let Some(node) = address_space.find_node(&node_to_read.node_id) {
             if let Ok(attribute_id) = AttributeId::from_u32(node_to_read.attribute_id) {
-                if let Some(attribute) = node.as_node().find_attribute(attribute_id) {
-                    let is_readable = Self::is_readable(&node);
-                    if !is_readable {
-                        result_value.status = Some(BadNotReadable)
-                    } else if !node_to_read.index_range.is_null() {
-                        // Index ranges are not supported
-                        result_value.status = Some(BadNotReadable);
+                if let Some(attribute) = node.as_node().find_attribute_with_diagnostic(attribute_id)? {
+                    if !Self::is_readable(&node) {
+                        Err(Diagnostic::new(BadNotReadable))
                     } else {
-                        // Result value is clone from the attribute
-                        result_value.value = attribute.value.clone();
-                        result_value.status = attribute.status;
-                        match timestamps_to_return {
-                            TimestampsToReturn::Source => {
-                                result_value.source_timestamp = attribute.source_timestamp.clone();
-                                result_value.source_picoseconds = attribute.source_picoseconds;
-                            }
-                            TimestampsToReturn::Server => {
-                                result_value.server_timestamp = attribute.server_timestamp.clone();
-                                result_value.server_picoseconds = attribute.server_picoseconds;
-                            }
-                            TimestampsToReturn::Both => {
-                                result_value.source_timestamp = attribute.source_timestamp.clone();
-                                result_value.source_picoseconds = attribute.source_picoseconds;
-                                result_value.server_timestamp = attribute.server_timestamp.clone();
-                                result_value.server_picoseconds = attribute.server_picoseconds;
-                            }
-                            TimestampsToReturn::Neither => {
-                                // Nothing needs to change
-                            }
-                        }
+                        Ok(attribute)
                     }
                 } else {
-                    result_value.status = Some(BadAttributeIdInvalid);
+                    Err(Diagnostic::new(BadAttributeIdInvalid))
                 }
             } else {
                 warn!("Attribute id {} is invalid", node_to_read.attribute_id);
-                result_value.status = Some(BadAttributeIdInvalid);
+                Err(Diagnostic::new(BadAttributeIdInvalid))
             }
         } else {
             warn!("Cannot find node id {:?}", node_to_read.node_id);
-            result_value.status = Some(BadNodeIdUnknown);
+            Err(Diagnostic::new(BadNodeIdUnknown))
         }
-        result_value
     }
 
-    fn is_readable(node: &NodeType) -> bool {
+    /// Tests if the node can be read. Variables are only readable if their access level says so.
+    pub fn is_readable(node: &NodeType) -> bool {
         // Check for access level, user access level
         if let NodeType::Variable(ref node) = *node {
             if node.access_level() & access_level::CURRENT_READ == 0 {
@@ -129,51 +558,81 @@ impl AttributeService {
     /// constructed Attribute values whose elements are indexed, such as an array, this Service
     /// allows Clients to write the entire set of indexed values as a composite, to write individual
     /// elements or to write ranges of elements of the composite.
-    pub fn write(&self, address_space: &mut AddressSpace, request: WriteRequest) -> Result<SupportedMessage, StatusCode> {
-        let results = if let Some(ref nodes_to_write) = request.nodes_to_write {
-            let results = nodes_to_write.iter().map(|node_to_write| {
-                Self::write_node_value(address_space, node_to_write)
-            }).collect();
-            Some(results)
+    pub fn write(&self, session: &Session, address_space: &mut AddressSpace, mut request: WriteRequest) -> Result<SupportedMessage, StatusCode> {
+        let (results, diagnostics): (Vec<StatusCode>, Vec<Option<Diagnostic>>) = if let Some(ref mut nodes_to_write) = request.nodes_to_write {
+            nodes_to_write.iter_mut().map(|node_to_write| {
+                session.resolve_registered_node(&mut node_to_write.node_id);
+                match Self::write_node_value(address_space, node_to_write) {
+                    Ok(_) => {
+                        // Limit alarms driven by the variable react to its new value
+                        if node_to_write.attribute_id == AttributeId::Value as UInt32 {
+                            address_space.update_limit_alarm_inputs(&node_to_write.node_id);
+                        }
+                        (Good, None)
+                    }
+                    Err(diagnostic) => (diagnostic.status_code, Some(diagnostic))
+                }
+            }).unzip()
         } else {
             return Ok(self.service_fault(&request.request_header, BadNothingToDo));
         };
 
-        let diagnostic_infos = None;
+        let mut response_diagnostics = ResponseDiagnostics::new(&request.request_header);
+        let diagnostic_infos = response_diagnostics.operation_diagnostic_infos(&diagnostics);
         let response = WriteResponse {
-            response_header: ResponseHeader::new_good(&request.request_header),
-            results,
+            response_header: response_diagnostics.response_header(&request.request_header, Good, None),
+            results: Some(results),
             diagnostic_infos,
         };
 
         Ok(SupportedMessage::WriteResponse(response))
     }
 
-    fn write_node_value(address_space: &mut AddressSpace, node_to_write: &WriteValue) -> StatusCode {
+    fn write_node_value(address_space: &mut AddressSpace, node_to_write: &WriteValue) -> Result<(), Diagnostic> {
         if let Some(node) = address_space.find_node_mut(&node_to_write.node_id) {
             if let Ok(attribute_id) = AttributeId::from_u32(node_to_write.attribute_id) {
                 let is_writable = Self::is_writable(&node, attribute_id);
                 if !is_writable {
-                    BadNotWritable
-                } else if !node_to_write.index_range.is_null() {
-                    // Index ranges are not supported
-                    BadWriteNotSupported
+                    Err(Diagnostic::new(BadNotWritable))
+                } else if !Self::is_user_writable(&node, attribute_id) {
+                    Err(Diagnostic::new(BadUserAccessDenied))
                 } else {
                     let node = node.as_mut_node();
-                    let result = node.set_attribute(attribute_id, node_to_write.value.clone());
-                    if result.is_err() {
-                        result.unwrap_err()
-                    } else {
-                        Good
-                    }
+                    let value = match NumericRange::from_index_range(&node_to_write.index_range) {
+                        Ok(Some(ref index_range)) => Self::write_index_range(node, attribute_id, index_range, &node_to_write.value),
+                        Ok(None) => Ok(node_to_write.value.clone()),
+                        Err(status_code) => Err(status_code)
+                    };
+                    node.set_attribute_with_diagnostic(attribute_id, value?)
                 }
             } else {
                 warn!("Attribute id {} is invalid", node_to_write.attribute_id);
-                BadAttributeIdInvalid
+                Err(Diagnostic::new(BadAttributeIdInvalid))
             }
         } else {
             warn!("Cannot find node id {:?}", node_to_write.node_id);
-            BadNodeIdUnknown
+            Err(Diagnostic::new(BadNodeIdUnknown))
+        }
+    }
+
+    /// Returns the current value of the attribute with the elements in the index range replaced
+    /// by the value being written. The rest of the value is unchanged.
+    fn write_index_range(node: &Node, attribute_id: AttributeId, index_range: &NumericRange, value: &DataValue) -> Result<DataValue, StatusCode> {
+        let mut current_value = node.find_attribute(attribute_id).and_then(|v| v.value).ok_or(BadIndexRangeNoData)?;
+        let range_value = value.value.as_ref().ok_or(BadTypeMismatch)?;
+        current_value.set_range_of(index_range, range_value)?;
+        Ok(DataValue {
+            value: Some(current_value),
+            ..value.clone()
+        })
+    }
+
+    /// Tests if the user may write the value of a variable. Other attributes are writable by the
+    /// user if they are writable at all.
+    fn is_user_writable(node: &NodeType, attribute_id: AttributeId) -> bool {
+        match (node, attribute_id) {
+            (&NodeType::Variable(ref node), AttributeId::Value) => node.user_access_level() & user_access_level::CURRENT_WRITE != 0,
+            _ => true
         }
     }
 
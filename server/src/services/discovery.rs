@@ -1,9 +1,15 @@
 use std::result::Result;
 
+use chrono;
+
 use opcua_types::*;
 use opcua_types::status_codes::StatusCode;
+use opcua_types::status_codes::StatusCode::*;
 use opcua_types::service_types::*;
+use opcua_core::comms::secure_channel::SecureChannel;
 
+use diagnostic_info::ResponseDiagnostics;
+use discovery::registered_servers::RegisteredServers;
 use state::ServerState;
 use services::Service;
 
@@ -26,5 +32,106 @@ impl DiscoveryService {
 //        error!("Endpoint response = {:#?}", response);
         Ok(SupportedMessage::GetEndpointsResponse(response))
     }
-}
 
+    /// Returns this server and, if it is a discovery server, the servers registered with it. The
+    /// servers may be filtered by the server uris in the request.
+    pub fn find_servers(&self, server_state: &mut ServerState, request: FindServersRequest) -> Result<SupportedMessage, StatusCode> {
+        server_state.registered_servers.remove_expired(&chrono::Utc::now());
+
+        // Registered servers are named in the first of the requested locales that they have a name for
+        let mut servers = vec![server_state.application_description()];
+        servers.extend(server_state.registered_servers.iter().map(|registration| {
+            registration.application_description(&request.locale_ids)
+        }));
+
+        // Filter by the server uris, if there are any
+        if let Some(ref server_uris) = request.server_uris {
+            if !server_uris.is_empty() {
+                servers.retain(|server| server_uris.contains(&server.application_uri));
+            }
+        }
+
+        let response = FindServersResponse {
+            response_header: ResponseHeader::new_good(&request.request_header),
+            servers: Some(servers),
+        };
+        Ok(SupportedMessage::FindServersResponse(response))
+    }
+
+    /// Returns the servers announced on the network through mDNS, starting at a record id and filtered
+    /// by their capabilities. The server must have multicast discovery enabled.
+    pub fn find_servers_on_network(&self, server_state: &mut ServerState, request: FindServersOnNetworkRequest) -> Result<SupportedMessage, StatusCode> {
+        if !server_state.is_multicast_discovery() {
+            error!("Find servers on network was called on a server that does not have multicast discovery enabled");
+            return Ok(self.service_fault(&request.request_header, BadServiceUnsupported));
+        }
+        server_state.servers_on_network.remove_expired(&chrono::Utc::now());
+        let servers = server_state.servers_on_network.find(request.starting_record_id, request.max_records_to_return, &request.server_capability_filter);
+        let response = FindServersOnNetworkResponse {
+            response_header: ResponseHeader::new_good(&request.request_header),
+            last_counter_reset_time: server_state.servers_on_network.last_counter_reset_time(),
+            servers: Some(servers),
+        };
+        Ok(SupportedMessage::FindServersOnNetworkResponse(response))
+    }
+
+    /// Registers a server with this discovery server. Servers that are not discovery servers do not
+    /// support the service.
+    pub fn register_server(&self, server_state: &mut ServerState, secure_channel: &SecureChannel, request: RegisterServerRequest) -> Result<SupportedMessage, StatusCode> {
+        if !server_state.is_discovery_server() {
+            error!("Register server was called on a server that is not a discovery server");
+            return Ok(self.service_fault(&request.request_header, BadServiceUnsupported));
+        }
+        if let Err(status_code) = RegisteredServers::validate(&request.server).and_then(|_| Self::validate_registering_channel(secure_channel, &request.server)) {
+            return Ok(self.service_fault(&request.request_header, status_code));
+        }
+        if let Err(status_code) = server_state.registered_servers.register(request.server, None, &chrono::Utc::now()) {
+            Ok(self.service_fault(&request.request_header, status_code))
+        } else {
+            let response = RegisterServerResponse {
+                response_header: ResponseHeader::new_good(&request.request_header),
+            };
+            Ok(SupportedMessage::RegisterServerResponse(response))
+        }
+    }
+
+    /// Registers a server with this discovery server along with its discovery configuration. The
+    /// response holds a result for each configuration in the request.
+    pub fn register_server2(&self, server_state: &mut ServerState, secure_channel: &SecureChannel, request: RegisterServer2Request) -> Result<SupportedMessage, StatusCode> {
+        if !server_state.is_discovery_server() {
+            error!("Register server 2 was called on a server that is not a discovery server");
+            return Ok(self.service_fault(&request.request_header, BadServiceUnsupported));
+        }
+        if let Err(status_code) = RegisteredServers::validate(&request.server).and_then(|_| Self::validate_registering_channel(secure_channel, &request.server)) {
+            return Ok(self.service_fault(&request.request_header, status_code));
+        }
+        let (configuration_results, mdns_configuration) = RegisteredServers::decode_discovery_configuration(&request.discovery_configuration);
+        if let Err(status_code) = server_state.registered_servers.register(request.server, mdns_configuration, &chrono::Utc::now()) {
+            Ok(self.service_fault(&request.request_header, status_code))
+        } else {
+            let mut response_diagnostics = ResponseDiagnostics::new(&request.request_header);
+            let diagnostic_infos = response_diagnostics.operation_diagnostic_infos(&ResponseDiagnostics::operation_status_diagnostics(&configuration_results));
+            let response = RegisterServer2Response {
+                response_header: response_diagnostics.response_header(&request.request_header, Good, None),
+                configuration_results: Some(configuration_results),
+                diagnostic_infos,
+            };
+            Ok(SupportedMessage::RegisterServer2Response(response))
+        }
+    }
+
+    /// Servers may only register over a secure channel that signs and encrypts, and which was
+    /// opened with the certificate of the server that is registering.
+    fn validate_registering_channel(secure_channel: &SecureChannel, server: &RegisteredServer) -> Result<(), StatusCode> {
+        if secure_channel.security_mode() != MessageSecurityMode::SignAndEncrypt {
+            error!("Server {} tried to register over a secure channel that does not sign and encrypt", server.server_uri);
+            return Err(BadSecurityChecksFailed);
+        }
+        let application_uri = secure_channel.remote_cert().and_then(|cert| cert.application_uri().ok());
+        if application_uri.as_ref().map(|uri| uri.as_str()) != Some(server.server_uri.as_ref()) {
+            error!("Server {} tried to register with a certificate for {:?}", server.server_uri, application_uri);
+            return Err(BadSecurityChecksFailed);
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use address_space::address_space::AddressSpace;
+use diagnostic_info::ResponseDiagnostics;
 use opcua_core::crypto::CertificateStore;
 use opcua_types::*;
 use opcua_types::service_types::*;
@@ -9,7 +10,9 @@ use services::attribute::AttributeService;
 use services::discovery::DiscoveryService;
 use services::method::MethodService;
 use services::monitored_item::MonitoredItemService;
+use services::node_management::NodeManagementService;
 use services::session::SessionService;
+use services::query::QueryService;
 use services::subscription::SubscriptionService;
 use services::view::ViewService;
 use session::Session;
@@ -33,12 +36,16 @@ pub struct MessageHandler {
     method_service: MethodService,
     /// MonitoredItem service
     monitored_item_service: MonitoredItemService,
+    /// NodeManagement service
+    node_management_service: NodeManagementService,
     /// Session service
     session_service: SessionService,
     /// Subscription service
     subscription_service: SubscriptionService,
     /// View service
     view_service: ViewService,
+    /// Query service
+    query_service: QueryService,
 }
 
 impl MessageHandler {
@@ -52,8 +59,10 @@ impl MessageHandler {
             discovery_service: DiscoveryService::new(),
             method_service: MethodService::new(),
             monitored_item_service: MonitoredItemService::new(),
+            node_management_service: NodeManagementService::new(),
             session_service: SessionService::new(),
             view_service: ViewService::new(),
+            query_service: QueryService::new(),
             subscription_service: SubscriptionService::new(),
         }
     }
@@ -63,12 +72,10 @@ impl MessageHandler {
     /// The request header should contain the session authentication token issued during a
     /// CreateSession or the request is invalid. An invalid token can cause the session to close.
     fn validate_request(&self, session: &mut Session, request_header: &RequestHeader) -> Result<(), SupportedMessage> {
-        // TODO if session's token is null, it might be possible to retrieve session state from a
-        // previously closed session and reassociate it if the authentication token is recognized
         if session.authentication_token != request_header.authentication_token {
             // Session should terminate
             session.terminate_session = true;
-            Err(ServiceFault::new_supported_message(request_header, BadIdentityTokenRejected))
+            Err(ResponseDiagnostics::service_fault(request_header, BadIdentityTokenRejected))
         } else {
             Ok(())
         }
@@ -80,132 +87,256 @@ impl MessageHandler {
         let mut server_state = trace_write_lock_unwrap!(self.server_state);
         let mut session = trace_write_lock_unwrap!(self.session);
 
-        // This MUST be last of the lockable items because server impls may set timers on this but not
-        // state / session.
-        let mut address_space = trace_write_lock_unwrap!(self.address_space);
+        // The request is counted in the diagnostics of the session once it has been handled
+        let request_object_id = if message.request_header().is_some() { message.node_id().as_object_id().ok() } else { None };
+        let response = if let SupportedMessage::TransferSubscriptionsRequest(request) = message {
+            // Subscriptions are taken from the sessions of other connections, which may be ticking
+            // their subscriptions and waiting for the address space, so it must not be held
+            self.transfer_subscriptions(&mut server_state, &mut session, request)
+        } else {
+            // This MUST be last of the lockable items because server impls may set timers on this but not
+            // state / session.
+            let mut address_space = trace_write_lock_unwrap!(self.address_space);
+            self.dispatch_message(request_id, message, &mut server_state, &mut session, &mut address_space)
+        };
+        if let Some(request_object_id) = request_object_id {
+            let service_result = match response {
+                Ok(Some(ref response)) => response.response_header().map_or(Good, |response_header| response_header.service_result),
+                Ok(None) => Good,
+                Err(status_code) => status_code,
+            };
+            let mut diagnostics = trace_write_lock_unwrap!(server_state.diagnostics);
+            diagnostics.on_service_request(&session, request_object_id, service_result);
+        }
+        response
+    }
+
+    /// Hands a TransferSubscriptionsRequest to the subscription service
+    fn transfer_subscriptions(&self, server_state: &mut ServerState, session: &mut Session, request: TransferSubscriptionsRequest) -> Result<Option<SupportedMessage>, StatusCode> {
+        if let Err(response) = self.validate_request(session, &request.request_header) {
+            Ok(Some(response))
+        } else {
+            Ok(Some(self.subscription_service.transfer_subscriptions(server_state, session, &self.session, request)?))
+        }
+    }
 
+    /// Hands the message to the service that handles it
+    fn dispatch_message(&mut self, request_id: UInt32, message: SupportedMessage, server_state: &mut ServerState, session: &mut Session, address_space: &mut AddressSpace) -> Result<Option<SupportedMessage>, StatusCode> {
         let response = match message {
             SupportedMessage::GetEndpointsRequest(request) => {
-                Some(self.discovery_service.get_endpoints(&server_state, request)?)
+                Some(self.discovery_service.get_endpoints(server_state, request)?)
+            }
+            SupportedMessage::FindServersRequest(request) => {
+                Some(self.discovery_service.find_servers(server_state, request)?)
+            }
+            SupportedMessage::FindServersOnNetworkRequest(request) => {
+                Some(self.discovery_service.find_servers_on_network(server_state, request)?)
+            }
+            SupportedMessage::RegisterServerRequest(request) => {
+                Some(self.discovery_service.register_server(server_state, &session.secure_channel, request)?)
+            }
+            SupportedMessage::RegisterServer2Request(request) => {
+                Some(self.discovery_service.register_server2(server_state, &session.secure_channel, request)?)
             }
             SupportedMessage::CreateSessionRequest(request) => {
                 let certificate_store = trace_read_lock_unwrap!(self.certificate_store);
-                Some(self.session_service.create_session(&certificate_store, &mut server_state, &mut session, request)?)
+                Some(self.session_service.create_session(&certificate_store, server_state, session, request)?)
             }
             SupportedMessage::CloseSessionRequest(request) => {
-                Some(self.session_service.close_session(&mut session, request)?)
+                Some(self.session_service.close_session(session, request)?)
             }
             // ALL THE REQUESTS BELOW MUST BE VALIDATED AGAINST THE SESSION
             SupportedMessage::ActivateSessionRequest(request) => {
-                if let Err(response) = self.validate_request(&mut session, &request.request_header) {
-                    Some(response)
-                } else {
-                    Some(self.session_service.activate_session(&mut server_state, &mut session, request)?)
-                }
+                // The session service validates the token because it may belong to a detached session
+                Some(self.session_service.activate_session(server_state, session, request)?)
             }
             SupportedMessage::CreateSubscriptionRequest(request) => {
-                if let Err(response) = self.validate_request(&mut session, &request.request_header) {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
                     Some(response)
                 } else {
-                    Some(self.subscription_service.create_subscription(&mut server_state, &mut session, request)?)
+                    Some(self.subscription_service.create_subscription(server_state, session, request)?)
                 }
             }
             SupportedMessage::ModifySubscriptionRequest(request) => {
-                if let Err(response) = self.validate_request(&mut session, &request.request_header) {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
                     Some(response)
                 } else {
-                    Some(self.subscription_service.modify_subscription(&mut server_state, &mut session, request)?)
+                    Some(self.subscription_service.modify_subscription(server_state, session, request)?)
                 }
             }
             SupportedMessage::DeleteSubscriptionsRequest(request) => {
-                if let Err(response) = self.validate_request(&mut session, &request.request_header) {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
                     Some(response)
                 } else {
-                    Some(self.subscription_service.delete_subscriptions(&mut session, request)?)
+                    Some(self.subscription_service.delete_subscriptions(session, request)?)
                 }
             }
             SupportedMessage::SetPublishingModeRequest(request) => {
-                if let Err(response) = self.validate_request(&mut session, &request.request_header) {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
                     Some(response)
                 } else {
-                    Some(self.subscription_service.set_publishing_mode(&mut session, request)?)
+                    Some(self.subscription_service.set_publishing_mode(session, request)?)
                 }
             }
             SupportedMessage::PublishRequest(request) => {
-                if let Err(response) = self.validate_request(&mut session, &request.request_header) {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
                     Some(response)
                 } else {
-                    self.subscription_service.publish(&mut session, request_id, &address_space, request)?
+                    self.subscription_service.publish(session, request_id, address_space, request)?
                 }
             }
             SupportedMessage::RepublishRequest(request) => {
-                if let Err(response) = self.validate_request(&mut session, &request.request_header) {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
                     Some(response)
                 } else {
-                    Some(self.subscription_service.republish(&mut session, request)?)
+                    Some(self.subscription_service.republish(session, request)?)
                 }
             }
             SupportedMessage::BrowseRequest(request) => {
-                if let Err(response) = self.validate_request(&mut session, &request.request_header) {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
                     Some(response)
                 } else {
-                    Some(self.view_service.browse(&mut session, &address_space, request)?)
+                    Some(self.view_service.browse(session, address_space, request)?)
                 }
             }
             SupportedMessage::BrowseNextRequest(request) => {
-                if let Err(response) = self.validate_request(&mut session, &request.request_header) {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
                     Some(response)
                 } else {
-                    Some(self.view_service.browse_next(&mut session, &address_space, request)?)
+                    Some(self.view_service.browse_next(session, address_space, request)?)
                 }
             }
             SupportedMessage::TranslateBrowsePathsToNodeIdsRequest(request) => {
-                if let Err(response) = self.validate_request(&mut session, &request.request_header) {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
+                    Some(response)
+                } else {
+                    Some(self.view_service.translate_browse_paths_to_node_ids(address_space, request)?)
+                }
+            }
+            SupportedMessage::RegisterNodesRequest(request) => {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
+                    Some(response)
+                } else {
+                    Some(self.view_service.register_nodes(session, address_space, request)?)
+                }
+            }
+            SupportedMessage::UnregisterNodesRequest(request) => {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
+                    Some(response)
+                } else {
+                    Some(self.view_service.unregister_nodes(session, request)?)
+                }
+            }
+            SupportedMessage::QueryFirstRequest(request) => {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
+                    Some(response)
+                } else {
+                    Some(self.query_service.query_first(session, address_space, request)?)
+                }
+            }
+            SupportedMessage::QueryNextRequest(request) => {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
                     Some(response)
                 } else {
-                    Some(self.view_service.translate_browse_paths_to_node_ids(&address_space, request)?)
+                    Some(self.query_service.query_next(session, address_space, request)?)
                 }
             }
             SupportedMessage::ReadRequest(request) => {
-                if let Err(response) = self.validate_request(&mut session, &request.request_header) {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
                     Some(response)
                 } else {
-                    Some(self.attribute_service.read(&address_space, request)?)
+                    Some(self.attribute_service.read(session, address_space, request)?)
                 }
             }
             SupportedMessage::WriteRequest(request) => {
-                if let Err(response) = self.validate_request(&mut session, &request.request_header) {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
                     Some(response)
                 } else {
-                    Some(self.attribute_service.write(&mut address_space, request)?)
+                    Some(self.attribute_service.write(session, address_space, request)?)
+                }
+            }
+            SupportedMessage::HistoryReadRequest(request) => {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
+                    Some(response)
+                } else {
+                    Some(self.attribute_service.history_read(session, address_space, request)?)
+                }
+            }
+            SupportedMessage::HistoryUpdateRequest(request) => {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
+                    Some(response)
+                } else {
+                    Some(self.attribute_service.history_update(address_space, request)?)
                 }
             }
             SupportedMessage::CreateMonitoredItemsRequest(request) => {
-                if let Err(response) = self.validate_request(&mut session, &request.request_header) {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
                     Some(response)
                 } else {
-                    Some(self.monitored_item_service.create_monitored_items(&mut session, request)?)
+                    Some(self.monitored_item_service.create_monitored_items(session, address_space, request)?)
                 }
             }
             SupportedMessage::ModifyMonitoredItemsRequest(request) => {
-                if let Err(response) = self.validate_request(&mut session, &request.request_header) {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
                     Some(response)
                 } else {
-                    Some(self.monitored_item_service.modify_monitored_items(&mut session, request)?)
+                    Some(self.monitored_item_service.modify_monitored_items(session, address_space, request)?)
                 }
             }
             SupportedMessage::DeleteMonitoredItemsRequest(request) => {
-                if let Err(response) = self.validate_request(&mut session, &request.request_header) {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
+                    Some(response)
+                } else {
+                    Some(self.monitored_item_service.delete_monitored_items(session, request)?)
+                }
+            }
+            SupportedMessage::SetMonitoringModeRequest(request) => {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
+                    Some(response)
+                } else {
+                    Some(self.monitored_item_service.set_monitoring_mode(session, request)?)
+                }
+            }
+            SupportedMessage::SetTriggeringRequest(request) => {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
                     Some(response)
                 } else {
-                    Some(self.monitored_item_service.delete_monitored_items(&mut session, request)?)
+                    Some(self.monitored_item_service.set_triggering(session, request)?)
                 }
             }
             SupportedMessage::CallRequest(request) => {
-                if let Err(response) = self.validate_request(&mut session, &request.request_header) {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
+                    Some(response)
+                } else {
+                    Some(self.method_service.call(session, address_space, request)?)
+                }
+            }
+            SupportedMessage::AddNodesRequest(request) => {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
+                    Some(response)
+                } else {
+                    Some(self.node_management_service.add_nodes(server_state, session, address_space, request)?)
+                }
+            }
+            SupportedMessage::AddReferencesRequest(request) => {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
+                    Some(response)
+                } else {
+                    Some(self.node_management_service.add_references(server_state, session, address_space, request)?)
+                }
+            }
+            SupportedMessage::DeleteNodesRequest(request) => {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
+                    Some(response)
+                } else {
+                    Some(self.node_management_service.delete_nodes(server_state, session, address_space, request)?)
+                }
+            }
+            SupportedMessage::DeleteReferencesRequest(request) => {
+                if let Err(response) = self.validate_request(session, &request.request_header) {
                     Some(response)
                 } else {
-                    Some(self.method_service.call(&mut session, request)?)
+                    Some(self.node_management_service.delete_references(server_state, session, address_space, request)?)
                 }
             }
             _ => {
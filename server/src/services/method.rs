@@ -1,9 +1,14 @@
 use std::result::Result;
 
 use opcua_types::*;
+use opcua_types::node_ids::DataTypeId;
 use opcua_types::status_codes::StatusCode;
+use opcua_types::status_codes::StatusCode::*;
 use opcua_types::service_types::*;
 
+use address_space::address_space::AddressSpace;
+use address_space::node::NodeType;
+use diagnostic_info::ResponseDiagnostics;
 use services::Service;
 use session::Session;
 
@@ -16,29 +21,131 @@ impl MethodService {
         MethodService {}
     }
 
-    pub fn call(&self, session: &mut Session, request: CallRequest) -> Result<SupportedMessage, StatusCode> {
-        if let Some(calls) = request.methods_to_call {
-            // TODO for each call
-            {
-                // get the object id
-                // BadNodeIdUnknown
+    pub fn call(&self, session: &mut Session, address_space: &mut AddressSpace, request: CallRequest) -> Result<SupportedMessage, StatusCode> {
+        if let Some(ref calls) = request.methods_to_call {
+            let results: Vec<CallMethodResult> = calls.iter().map(|call| {
+                Self::call_method(session, address_space, call)
+            }).collect();
+            let mut response_diagnostics = ResponseDiagnostics::new(&request.request_header);
+            let status_codes: Vec<StatusCode> = results.iter().map(|r| r.status_code).collect();
+            let diagnostic_infos = response_diagnostics.operation_diagnostic_infos(&ResponseDiagnostics::operation_status_diagnostics(&status_codes));
+            let response = CallResponse {
+                response_header: response_diagnostics.response_header(&request.request_header, Good, None),
+                results: Some(results),
+                diagnostic_infos,
+            };
+            Ok(response.into())
+        } else {
+            warn!("Call has nothing to do");
+            return Ok(self.service_fault(&request.request_header, BadNothingToDo));
+        }
+    }
 
-                // look for the corresponding method id
-                // BadMethodInvalid
-                // BadNodeIdUnknown
+    /// Validates and invokes a single method call, producing its result
+    fn call_method(session: &Session, address_space: &mut AddressSpace, request: &CallMethodRequest) -> CallMethodResult {
+        let (status_code, input_argument_results, output_arguments) = match Self::invoke_method(session, address_space, request) {
+            Ok(output_arguments) => (Good, None, Some(output_arguments)),
+            Err((status_code, input_argument_results)) => (status_code, input_argument_results, None)
+        };
+        CallMethodResult {
+            status_code,
+            input_argument_results,
+            input_argument_diagnostic_infos: None,
+            output_arguments,
+        }
+    }
 
-                // look up method implementation in some kind of table
+    /// Finds the method, checks the arguments and calls the method's callback. An error holds
+    /// the status code for the call and, if the arguments were checked, a result per argument.
+    fn invoke_method(session: &Session, address_space: &mut AddressSpace, request: &CallMethodRequest) -> Result<Vec<Variant>, (StatusCode, Option<Vec<StatusCode>>)> {
+        // The object must exist and be an object or object type
+        match address_space.find_node(&request.object_id) {
+            Some(&NodeType::Object(_)) | Some(&NodeType::ObjectType(_)) => {}
+            _ => {
+                debug!("Call to method {:?} on unknown object {:?}", request.method_id, request.object_id);
+                return Err((BadNodeIdUnknown, None));
+            }
+        }
 
-                // invoke the method
+        // The method must exist and belong to the object
+        let callback = match address_space.find_node(&request.method_id) {
+            Some(&NodeType::Method(ref method)) => {
+                if !method.executable() || !method.user_executable() {
+                    return Err((BadUserAccessDenied, None));
+                }
+                method.callback()
+            }
+            _ => {
+                debug!("Call to unknown method {:?}", request.method_id);
+                return Err((BadMethodInvalid, None));
+            }
+        };
+        if !address_space.is_method_of_object(&request.object_id, &request.method_id) {
+            debug!("Method {:?} is not a method of object {:?}", request.method_id, request.object_id);
+            return Err((BadMethodInvalid, None));
+        }
 
-                // produce a call result
+        // Validate the input arguments against the InputArguments property
+        let expected_arguments = address_space.find_method_input_arguments(&request.method_id).map_err(|status_code| (status_code, None))?;
+        let input_arguments: &[Variant] = if let Some(ref input_arguments) = request.input_arguments { input_arguments } else { &[] };
+        if input_arguments.len() < expected_arguments.len() {
+            return Err((BadArgumentsMissing, None));
+        } else if input_arguments.len() > expected_arguments.len() {
+            return Err((BadTooManyArguments, None));
+        }
+        let input_argument_results = input_arguments.iter().zip(expected_arguments.iter()).map(|(value, argument)| {
+            if Self::is_valid_argument(value, argument) { Good } else { BadTypeMismatch }
+        }).collect::<Vec<StatusCode>>();
+        if input_argument_results.iter().any(|r| r.is_bad()) {
+            return Err((BadInvalidArgument, Some(input_argument_results)));
+        }
+
+        // Invoke the method
+        if let Some(callback) = callback {
+            let mut callback = trace_lock_unwrap!(callback);
+            callback.call(session, address_space, &request.object_id, input_arguments).map_err(|status_code| (status_code, None))
+        } else {
+            warn!("Method {:?} has no callback so it cannot be called", request.method_id);
+            Err((BadNotImplemented, None))
+        }
+    }
+
+    /// Tests if the supplied value is of the data type and rank expected by the argument
+    fn is_valid_argument(value: &Variant, argument: &Argument) -> bool {
+        let (dimensions, valid_data_type) = match *value {
+            Variant::Array(ref values) => {
+                (Some(1), values.iter().all(|v| Self::is_valid_data_type(v, &argument.data_type)))
+            }
+            Variant::MultiDimensionArray(ref array) => {
+                (Some(array.dimensions.len()), array.values.iter().all(|v| Self::is_valid_data_type(v, &argument.data_type)))
             }
-            // Produce response
+            _ => (None, Self::is_valid_data_type(value, &argument.data_type))
+        };
+        // Value rank is described in Part 3, 5.6.2
+        let valid_rank = match argument.value_rank {
+            -3 => dimensions.is_none() || dimensions == Some(1),
+            -2 => true,
+            -1 => dimensions.is_none(),
+            0 => dimensions.is_some(),
+            value_rank => dimensions == Some(value_rank as usize),
+        };
+        valid_rank && valid_data_type
+    }
 
-            Err(StatusCode::BadNotImplemented)
+    /// Tests if the scalar value matches the data type. Abstract types for numbers and the base
+    /// data type are also understood.
+    fn is_valid_data_type(value: &Variant, data_type: &NodeId) -> bool {
+        if *data_type == DataTypeId::BaseDataType.into() {
+            true
+        } else if *data_type == DataTypeId::Number.into() {
+            value.is_numeric()
+        } else if let Variant::ExtensionObject(_) = *value {
+            // Structures cannot be checked without the type system
+            true
+        } else if let Some(value_data_type) = value.data_type() {
+            *data_type == value_data_type.into()
         } else {
-            warn!("Call has nothing to do");
-            return Ok(self.service_fault(&request.request_header, StatusCode::BadNothingToDo));
+            false
         }
     }
 }
@@ -1,13 +1,15 @@
 use opcua_types::{SupportedMessage};
-use opcua_types::service_types::{RequestHeader, ServiceFault};
+use opcua_types::service_types::RequestHeader;
 use opcua_types::status_codes::StatusCode;
 
+use diagnostic_info::ResponseDiagnostics;
+
 pub mod message_handler;
 
 trait Service {
     fn service_fault(&self, request_header: &RequestHeader, service_result: StatusCode) -> SupportedMessage {
         warn!("Service fault with status code {:?} is being created", service_result);
-        ServiceFault::new_supported_message(request_header, service_result)
+        ResponseDiagnostics::service_fault(request_header, service_result)
     }
 }
 
@@ -15,6 +17,8 @@ pub mod attribute;
 pub mod discovery;
 pub mod method;
 pub mod monitored_item;
+pub mod node_management;
+pub mod query;
 pub mod session;
 pub mod subscription;
 pub mod view;
@@ -5,6 +5,8 @@ use opcua_types::status_codes::StatusCode;
 use opcua_types::status_codes::StatusCode::*;
 use opcua_types::service_types::*;
 
+use address_space::address_space::AddressSpace;
+use diagnostic_info::ResponseDiagnostics;
 use session::Session;
 use services::Service;
 
@@ -17,15 +19,46 @@ impl MonitoredItemService {
         MonitoredItemService {}
     }
 
-    pub fn create_monitored_items(&self, session: &mut Session, request: CreateMonitoredItemsRequest) -> Result<SupportedMessage, StatusCode> {
-        if let Some(ref items_to_create) = request.items_to_create {
+    pub fn create_monitored_items(&self, session: &mut Session, address_space: &AddressSpace, mut request: CreateMonitoredItemsRequest) -> Result<SupportedMessage, StatusCode> {
+        if let Some(ref mut items_to_create) = request.items_to_create {
+            // Items monitor the nodes that registered node ids stand for
+            for item_to_create in items_to_create.iter_mut() {
+                session.resolve_registered_node(&mut item_to_create.item_to_monitor.node_id);
+            }
+            let user_readable: Vec<bool> = items_to_create.iter().map(|item_to_create| {
+                let item_to_monitor = &item_to_create.item_to_monitor;
+                address_space.is_user_readable(session, &item_to_monitor.node_id, item_to_monitor.attribute_id)
+            }).collect();
+            let timestamps_to_return = request.timestamps_to_return;
             // Find subscription and add items to it
             if let Some(subscription) = session.subscriptions.get_mut(request.subscription_id) {
-                let results = Some(subscription.create_monitored_items(request.timestamps_to_return, items_to_create));
+                // Items cannot monitor what the user cannot read. The others are created together
+                // and their results put back in the order of the request.
+                let permitted_items: Vec<MonitoredItemCreateRequest> = items_to_create.iter().zip(user_readable.iter())
+                    .filter(|&(_, readable)| *readable)
+                    .map(|(item_to_create, _)| item_to_create.clone())
+                    .collect();
+                let mut permitted_results = subscription.create_monitored_items(address_space, timestamps_to_return, &permitted_items).into_iter();
+                let results = user_readable.iter().map(|readable| {
+                    if *readable {
+                        permitted_results.next().unwrap()
+                    } else {
+                        MonitoredItemCreateResult {
+                            status_code: BadUserAccessDenied,
+                            monitored_item_id: 0,
+                            revised_sampling_interval: 0f64,
+                            revised_queue_size: 0,
+                            filter_result: ExtensionObject::null(),
+                        }
+                    }
+                }).collect::<Vec<MonitoredItemCreateResult>>();
+                let mut response_diagnostics = ResponseDiagnostics::new(&request.request_header);
+                let status_codes: Vec<StatusCode> = results.iter().map(|r| r.status_code).collect();
+                let diagnostic_infos = response_diagnostics.operation_diagnostic_infos(&ResponseDiagnostics::operation_status_diagnostics(&status_codes));
                 let response = CreateMonitoredItemsResponse {
-                    response_header: ResponseHeader::new_good(&request.request_header),
-                    results,
-                    diagnostic_infos: None
+                    response_header: response_diagnostics.response_header(&request.request_header, Good, None),
+                    results: Some(results),
+                    diagnostic_infos,
                 };
                 Ok(SupportedMessage::CreateMonitoredItemsResponse(response))
             } else {
@@ -38,16 +71,19 @@ impl MonitoredItemService {
         }
     }
 
-    pub fn modify_monitored_items(&self, session: &mut Session, request: ModifyMonitoredItemsRequest) -> Result<SupportedMessage, StatusCode> {
+    pub fn modify_monitored_items(&self, session: &mut Session, address_space: &AddressSpace, request: ModifyMonitoredItemsRequest) -> Result<SupportedMessage, StatusCode> {
         if let Some(ref items_to_modify) = request.items_to_modify {
             // Find subscription and modify items in it
             let subscription_id = request.subscription_id;
             if let Some(subscription) = session.subscriptions.get_mut(subscription_id) {
-                let results = Some(subscription.modify_monitored_items(request.timestamps_to_return, items_to_modify));
+                let results = subscription.modify_monitored_items(address_space, request.timestamps_to_return, items_to_modify);
+                let mut response_diagnostics = ResponseDiagnostics::new(&request.request_header);
+                let status_codes: Vec<StatusCode> = results.iter().map(|r| r.status_code).collect();
+                let diagnostic_infos = response_diagnostics.operation_diagnostic_infos(&ResponseDiagnostics::operation_status_diagnostics(&status_codes));
                 let response = ModifyMonitoredItemsResponse {
-                    response_header: ResponseHeader::new_good(&request.request_header),
-                    results,
-                    diagnostic_infos: None
+                    response_header: response_diagnostics.response_header(&request.request_header, Good, None),
+                    results: Some(results),
+                    diagnostic_infos,
                 };
                 Ok(SupportedMessage::ModifyMonitoredItemsResponse(response))
             } else {
@@ -65,12 +101,13 @@ impl MonitoredItemService {
             // Find subscription and delete items from it
             let subscription_id = request.subscription_id;
             if let Some(subscription) = session.subscriptions.get_mut(subscription_id) {
-                let results = Some(subscription.delete_monitored_items(items_to_delete));
-                let diagnostic_infos = None;
+                let results = subscription.delete_monitored_items(items_to_delete);
+                let mut response_diagnostics = ResponseDiagnostics::new(&request.request_header);
+                let diagnostic_infos = response_diagnostics.operation_diagnostic_infos(&ResponseDiagnostics::operation_status_diagnostics(&results));
                 let response = DeleteMonitoredItemsResponse {
-                    response_header: ResponseHeader::new_good(&request.request_header),
-                    results,
-                    diagnostic_infos
+                    response_header: response_diagnostics.response_header(&request.request_header, Good, None),
+                    results: Some(results),
+                    diagnostic_infos,
                 };
                 Ok(SupportedMessage::DeleteMonitoredItemsResponse(response))
             } else {
@@ -82,4 +119,57 @@ impl MonitoredItemService {
             Ok(self.service_fault(&request.request_header, BadNothingToDo))
         }
     }
-}
\ No newline at end of file
+
+    pub fn set_monitoring_mode(&self, session: &mut Session, request: SetMonitoringModeRequest) -> Result<SupportedMessage, StatusCode> {
+        if let Some(ref monitored_item_ids) = request.monitored_item_ids {
+            // Find subscription and set the mode of items in it
+            let subscription_id = request.subscription_id;
+            if let Some(subscription) = session.subscriptions.get_mut(subscription_id) {
+                let results = subscription.set_monitoring_mode(request.monitoring_mode, monitored_item_ids);
+                let mut response_diagnostics = ResponseDiagnostics::new(&request.request_header);
+                let diagnostic_infos = response_diagnostics.operation_diagnostic_infos(&ResponseDiagnostics::operation_status_diagnostics(&results));
+                let response = SetMonitoringModeResponse {
+                    response_header: response_diagnostics.response_header(&request.request_header, Good, None),
+                    results: Some(results),
+                    diagnostic_infos,
+                };
+                Ok(SupportedMessage::SetMonitoringModeResponse(response))
+            } else {
+                // No matching subscription
+                Ok(self.service_fault(&request.request_header, BadSubscriptionIdInvalid))
+            }
+        } else {
+            // No items to set so nothing to do
+            Ok(self.service_fault(&request.request_header, BadNothingToDo))
+        }
+    }
+
+    pub fn set_triggering(&self, session: &mut Session, request: SetTriggeringRequest) -> Result<SupportedMessage, StatusCode> {
+        let links_to_add = request.links_to_add.as_ref().map(|v| v.as_slice()).unwrap_or(&[]);
+        let links_to_remove = request.links_to_remove.as_ref().map(|v| v.as_slice()).unwrap_or(&[]);
+        if links_to_add.is_empty() && links_to_remove.is_empty() {
+            // No links to change so nothing to do
+            Ok(self.service_fault(&request.request_header, BadNothingToDo))
+        } else if let Some(subscription) = session.subscriptions.get_mut(request.subscription_id) {
+            match subscription.set_triggering(request.triggering_item_id, links_to_add, links_to_remove) {
+                Ok((add_results, remove_results)) => {
+                    let mut response_diagnostics = ResponseDiagnostics::new(&request.request_header);
+                    let add_diagnostic_infos = response_diagnostics.operation_diagnostic_infos(&ResponseDiagnostics::operation_status_diagnostics(&add_results));
+                    let remove_diagnostic_infos = response_diagnostics.operation_diagnostic_infos(&ResponseDiagnostics::operation_status_diagnostics(&remove_results));
+                    let response = SetTriggeringResponse {
+                        response_header: response_diagnostics.response_header(&request.request_header, Good, None),
+                        add_results: if request.links_to_add.is_some() { Some(add_results) } else { None },
+                        add_diagnostic_infos,
+                        remove_results: if request.links_to_remove.is_some() { Some(remove_results) } else { None },
+                        remove_diagnostic_infos,
+                    };
+                    Ok(SupportedMessage::SetTriggeringResponse(response))
+                }
+                Err(status_code) => Ok(self.service_fault(&request.request_header, status_code))
+            }
+        } else {
+            // No matching subscription
+            Ok(self.service_fault(&request.request_header, BadSubscriptionIdInvalid))
+        }
+    }
+}
@@ -0,0 +1,410 @@
+use std::result::Result;
+
+use opcua_types::*;
+use opcua_types::node_ids::{DataTypeId, ObjectId, ReferenceTypeId};
+use opcua_types::status_codes::StatusCode;
+use opcua_types::status_codes::StatusCode::*;
+use opcua_types::service_types::*;
+
+use address_space::address_space::AddressSpace;
+use address_space::node::{Node, NodeType};
+use address_space::types::{DataType, Method, Object, ObjectType, ReferenceType, Variable, VariableType, View};
+use diagnostic_info::ResponseDiagnostics;
+use services::Service;
+use session::Session;
+use state::ServerState;
+
+/// Bits of the specified_attributes field in the *Attributes types, Part 4 7.19.1
+mod attributes_mask {
+    pub const ACCESS_LEVEL: u32 = 1;
+    pub const ARRAY_DIMENSIONS: u32 = 1 << 1;
+    pub const CONTAINS_NO_LOOPS: u32 = 1 << 3;
+    pub const DATA_TYPE: u32 = 1 << 4;
+    pub const DESCRIPTION: u32 = 1 << 5;
+    pub const DISPLAY_NAME: u32 = 1 << 6;
+    pub const EVENT_NOTIFIER: u32 = 1 << 7;
+    pub const EXECUTABLE: u32 = 1 << 8;
+    pub const HISTORIZING: u32 = 1 << 9;
+    pub const INVERSE_NAME: u32 = 1 << 10;
+    pub const IS_ABSTRACT: u32 = 1 << 11;
+    pub const MINIMUM_SAMPLING_INTERVAL: u32 = 1 << 12;
+    pub const SYMMETRIC: u32 = 1 << 15;
+    pub const USER_ACCESS_LEVEL: u32 = 1 << 16;
+    pub const USER_EXECUTABLE: u32 = 1 << 17;
+    pub const USER_WRITE_MASK: u32 = 1 << 18;
+    pub const VALUE_RANK: u32 = 1 << 19;
+    pub const WRITE_MASK: u32 = 1 << 20;
+    pub const VALUE: u32 = 1 << 21;
+}
+
+/// The node management service. Allows clients to add and delete nodes and references
+/// from the address space, providing the session's user is permitted to do so.
+pub struct NodeManagementService {}
+
+impl Service for NodeManagementService {}
+
+impl NodeManagementService {
+    pub fn new() -> NodeManagementService {
+        NodeManagementService {}
+    }
+
+    /// Implements the AddNodes service
+    pub fn add_nodes(&self, server_state: &ServerState, session: &Session, address_space: &mut AddressSpace, request: AddNodesRequest) -> Result<SupportedMessage, StatusCode> {
+        if !Self::is_permitted(server_state, session) {
+            return Ok(self.service_fault(&request.request_header, BadUserAccessDenied));
+        }
+        if let Some(ref nodes_to_add) = request.nodes_to_add {
+            let results = nodes_to_add.iter().map(|node_to_add| {
+                let (status_code, added_node_id) = match Self::add_node(address_space, node_to_add) {
+                    Ok(added_node_id) => (Good, added_node_id),
+                    Err(status_code) => (status_code, NodeId::null())
+                };
+                AddNodesResult {
+                    status_code,
+                    added_node_id,
+                }
+            }).collect::<Vec<AddNodesResult>>();
+            let mut response_diagnostics = ResponseDiagnostics::new(&request.request_header);
+            let status_codes: Vec<StatusCode> = results.iter().map(|r| r.status_code).collect();
+            let diagnostic_infos = response_diagnostics.operation_diagnostic_infos(&ResponseDiagnostics::operation_status_diagnostics(&status_codes));
+            let response = AddNodesResponse {
+                response_header: response_diagnostics.response_header(&request.request_header, Good, None),
+                results: Some(results),
+                diagnostic_infos,
+            };
+            Ok(response.into())
+        } else {
+            Ok(self.service_fault(&request.request_header, BadNothingToDo))
+        }
+    }
+
+    /// Implements the AddReferences service
+    pub fn add_references(&self, server_state: &ServerState, session: &Session, address_space: &mut AddressSpace, request: AddReferencesRequest) -> Result<SupportedMessage, StatusCode> {
+        if !Self::is_permitted(server_state, session) {
+            return Ok(self.service_fault(&request.request_header, BadUserAccessDenied));
+        }
+        if let Some(ref references_to_add) = request.references_to_add {
+            let results = references_to_add.iter().map(|reference_to_add| {
+                match Self::add_reference(address_space, reference_to_add) {
+                    Ok(_) => Good,
+                    Err(status_code) => status_code
+                }
+            }).collect::<Vec<StatusCode>>();
+            let mut response_diagnostics = ResponseDiagnostics::new(&request.request_header);
+            let diagnostic_infos = response_diagnostics.operation_diagnostic_infos(&ResponseDiagnostics::operation_status_diagnostics(&results));
+            let response = AddReferencesResponse {
+                response_header: response_diagnostics.response_header(&request.request_header, Good, None),
+                results: Some(results),
+                diagnostic_infos,
+            };
+            Ok(response.into())
+        } else {
+            Ok(self.service_fault(&request.request_header, BadNothingToDo))
+        }
+    }
+
+    /// Implements the DeleteNodes service
+    pub fn delete_nodes(&self, server_state: &ServerState, session: &Session, address_space: &mut AddressSpace, request: DeleteNodesRequest) -> Result<SupportedMessage, StatusCode> {
+        if !Self::is_permitted(server_state, session) {
+            return Ok(self.service_fault(&request.request_header, BadUserAccessDenied));
+        }
+        if let Some(ref nodes_to_delete) = request.nodes_to_delete {
+            let results = nodes_to_delete.iter().map(|node_to_delete| {
+                if address_space.delete_node(&node_to_delete.node_id, node_to_delete.delete_target_references) {
+                    Good
+                } else {
+                    BadNodeIdUnknown
+                }
+            }).collect::<Vec<StatusCode>>();
+            let mut response_diagnostics = ResponseDiagnostics::new(&request.request_header);
+            let diagnostic_infos = response_diagnostics.operation_diagnostic_infos(&ResponseDiagnostics::operation_status_diagnostics(&results));
+            let response = DeleteNodesResponse {
+                response_header: response_diagnostics.response_header(&request.request_header, Good, None),
+                results: Some(results),
+                diagnostic_infos,
+            };
+            Ok(response.into())
+        } else {
+            Ok(self.service_fault(&request.request_header, BadNothingToDo))
+        }
+    }
+
+    /// Implements the DeleteReferences service
+    pub fn delete_references(&self, server_state: &ServerState, session: &Session, address_space: &mut AddressSpace, request: DeleteReferencesRequest) -> Result<SupportedMessage, StatusCode> {
+        if !Self::is_permitted(server_state, session) {
+            return Ok(self.service_fault(&request.request_header, BadUserAccessDenied));
+        }
+        if let Some(ref references_to_delete) = request.references_to_delete {
+            let results = references_to_delete.iter().map(|reference_to_delete| {
+                match Self::delete_reference(address_space, reference_to_delete) {
+                    Ok(_) => Good,
+                    Err(status_code) => status_code
+                }
+            }).collect::<Vec<StatusCode>>();
+            let mut response_diagnostics = ResponseDiagnostics::new(&request.request_header);
+            let diagnostic_infos = response_diagnostics.operation_diagnostic_infos(&ResponseDiagnostics::operation_status_diagnostics(&results));
+            let response = DeleteReferencesResponse {
+                response_header: response_diagnostics.response_header(&request.request_header, Good, None),
+                results: Some(results),
+                diagnostic_infos,
+            };
+            Ok(response.into())
+        } else {
+            Ok(self.service_fault(&request.request_header, BadNothingToDo))
+        }
+    }
+
+    /// Tests if the user of the session is permitted to modify the address space
+    fn is_permitted(server_state: &ServerState, session: &Session) -> bool {
+        if let Some(ref user_token_id) = session.user_token_id {
+            let config = trace_read_lock_unwrap!(server_state.config);
+            if config.is_node_management_permitted(user_token_id) {
+                true
+            } else {
+                warn!("User token id {} is not permitted to use node management services", user_token_id);
+                false
+            }
+        } else {
+            warn!("Session has no authenticated user so it cannot use node management services");
+            false
+        }
+    }
+
+    fn add_node(address_space: &mut AddressSpace, item: &AddNodesItem) -> Result<NodeId, StatusCode> {
+        // Only local nodes are supported
+        if !item.requested_new_node_id.namespace_uri.is_null() || item.requested_new_node_id.server_index != 0 {
+            return Err(BadNodeIdRejected);
+        }
+        if !item.parent_node_id.namespace_uri.is_null() || item.parent_node_id.server_index != 0 ||
+            !address_space.node_exists(&item.parent_node_id.node_id) {
+            return Err(BadParentNodeIdInvalid);
+        }
+        let reference_type_id = item.reference_type_id.as_reference_type_id().map_err(|_| BadReferenceTypeIdInvalid)?;
+        // The new node must be organized under its parent by a hierarchical reference
+        if !address_space.is_subtype(&item.reference_type_id, &ReferenceTypeId::HierarchicalReferences.into()) {
+            return Err(BadReferenceTypeIdInvalid);
+        }
+        if item.browse_name.is_null() {
+            return Err(BadBrowseNameInvalid);
+        }
+
+        // Objects and variables must have a type definition, nothing else may have one
+        let type_definition = &item.type_definition.node_id;
+        match item.node_class {
+            NodeClass::Object | NodeClass::Variable => {
+                if type_definition.is_null() || !address_space.node_exists(type_definition) {
+                    return Err(BadTypeDefinitionInvalid);
+                }
+            }
+            NodeClass::Unspecified => {
+                return Err(BadNodeClassInvalid);
+            }
+            _ => {
+                if !type_definition.is_null() {
+                    return Err(BadTypeDefinitionInvalid);
+                }
+            }
+        }
+
+        let node_id = if item.requested_new_node_id.is_null() {
+            NodeId::next_numeric()
+        } else if address_space.node_exists(&item.requested_new_node_id.node_id) {
+            return Err(BadNodeIdExists);
+        } else {
+            item.requested_new_node_id.node_id.clone()
+        };
+
+        let node = Self::create_node(&node_id, item.node_class, &item.browse_name, &item.node_attributes)?;
+        address_space.insert(node);
+        address_space.insert_reference(&item.parent_node_id.node_id, &node_id, reference_type_id);
+        if !type_definition.is_null() {
+            address_space.insert_reference(&node_id, type_definition, ReferenceTypeId::HasTypeDefinition);
+        }
+        Ok(node_id)
+    }
+
+    fn add_reference(address_space: &mut AddressSpace, item: &AddReferencesItem) -> Result<(), StatusCode> {
+        if !address_space.node_exists(&item.source_node_id) {
+            return Err(BadSourceNodeIdInvalid);
+        }
+        let reference_type_id = item.reference_type_id.as_reference_type_id().map_err(|_| BadReferenceTypeIdInvalid)?;
+        // Only local references are supported
+        if !item.target_server_uri.is_null() || item.target_node_id.server_index != 0 {
+            return Err(BadServerUriInvalid);
+        }
+        let target_node_id = &item.target_node_id.node_id;
+        if let Some(node) = address_space.find_node(target_node_id) {
+            if item.target_node_class != NodeClass::Unspecified && item.target_node_class != node.as_node().node_class() {
+                return Err(BadNodeClassInvalid);
+            }
+        } else {
+            return Err(BadTargetNodeIdInvalid);
+        }
+        if item.source_node_id == *target_node_id {
+            return Err(BadReferenceNotAllowed);
+        }
+        let (node_id_from, node_id_to) = if item.is_forward {
+            (&item.source_node_id, target_node_id)
+        } else {
+            (target_node_id, &item.source_node_id)
+        };
+        if address_space.has_reference(node_id_from, node_id_to, reference_type_id) {
+            Err(BadDuplicateReferenceNotAllowed)
+        } else {
+            address_space.insert_reference(node_id_from, node_id_to, reference_type_id);
+            Ok(())
+        }
+    }
+
+    fn delete_reference(address_space: &mut AddressSpace, item: &DeleteReferencesItem) -> Result<(), StatusCode> {
+        if !address_space.node_exists(&item.source_node_id) {
+            return Err(BadSourceNodeIdInvalid);
+        }
+        let reference_type_id = item.reference_type_id.as_reference_type_id().map_err(|_| BadReferenceTypeIdInvalid)?;
+        if item.target_node_id.server_index != 0 {
+            return Err(BadServerUriInvalid);
+        }
+        let target_node_id = &item.target_node_id.node_id;
+        let (node_id_from, node_id_to) = if item.is_forward {
+            (&item.source_node_id, target_node_id)
+        } else {
+            (target_node_id, &item.source_node_id)
+        };
+        if !address_space.delete_reference(node_id_from, node_id_to, reference_type_id) {
+            return Err(BadNotFound);
+        }
+        if item.delete_bidirectional {
+            // Remove any reference going the opposite way too
+            let _ = address_space.delete_reference(node_id_to, node_id_from, reference_type_id);
+        }
+        Ok(())
+    }
+
+    /// Creates a node of the specified class from the attributes held in the extension object
+    fn create_node(node_id: &NodeId, node_class: NodeClass, browse_name: &QualifiedName, node_attributes: &ExtensionObject) -> Result<NodeType, StatusCode> {
+        let object_id = node_attributes.node_id.as_object_id().map_err(|_| BadNodeAttributesInvalid)?;
+        let name = browse_name.name.as_ref();
+        let mut node = match (node_class, object_id) {
+            (NodeClass::Object, ObjectId::ObjectAttributes_Encoding_DefaultBinary) => {
+                let a = node_attributes.decode_inner::<ObjectAttributes>()?;
+                let mut node = Object::new(node_id, name, name, "");
+                if a.specified_attributes & attributes_mask::EVENT_NOTIFIER != 0 {
+                    let _ = node.set_attribute(AttributeId::EventNotifier, DataValue::new(a.event_notifier));
+                }
+                Self::set_common_attributes(&mut node, a.specified_attributes, a.display_name, a.description, a.write_mask, a.user_write_mask);
+                NodeType::Object(node)
+            }
+            (NodeClass::Variable, ObjectId::VariableAttributes_Encoding_DefaultBinary) => {
+                let a = node_attributes.decode_inner::<VariableAttributes>()?;
+                let value = if a.specified_attributes & attributes_mask::VALUE != 0 { a.value } else { Variant::Empty };
+                let mut node = Variable::new_data_value(node_id, name, name, "", DataTypeId::BaseDataType, DataValue::new(value));
+                if a.specified_attributes & attributes_mask::DATA_TYPE != 0 {
+                    let _ = node.set_attribute(AttributeId::DataType, DataValue::new(a.data_type));
+                }
+                if a.specified_attributes & attributes_mask::VALUE_RANK != 0 {
+                    let _ = node.set_attribute(AttributeId::ValueRank, DataValue::new(a.value_rank));
+                }
+                if a.specified_attributes & attributes_mask::ARRAY_DIMENSIONS != 0 {
+                    if let Some(ref array_dimensions) = a.array_dimensions {
+                        let _ = node.set_attribute(AttributeId::ArrayDimensions, DataValue::new(Variant::new_u32_array(array_dimensions)));
+                    }
+                }
+                if a.specified_attributes & attributes_mask::ACCESS_LEVEL != 0 {
+                    let _ = node.set_attribute(AttributeId::AccessLevel, DataValue::new(a.access_level));
+                }
+                if a.specified_attributes & attributes_mask::USER_ACCESS_LEVEL != 0 {
+                    let _ = node.set_attribute(AttributeId::UserAccessLevel, DataValue::new(a.user_access_level));
+                }
+                if a.specified_attributes & attributes_mask::MINIMUM_SAMPLING_INTERVAL != 0 {
+                    let _ = node.set_attribute(AttributeId::MinimumSamplingInterval, DataValue::new(a.minimum_sampling_interval));
+                }
+                if a.specified_attributes & attributes_mask::HISTORIZING != 0 {
+                    let _ = node.set_attribute(AttributeId::Historizing, DataValue::new(a.historizing));
+                }
+                Self::set_common_attributes(&mut node, a.specified_attributes, a.display_name, a.description, a.write_mask, a.user_write_mask);
+                NodeType::Variable(node)
+            }
+            (NodeClass::Method, ObjectId::MethodAttributes_Encoding_DefaultBinary) => {
+                let a = node_attributes.decode_inner::<MethodAttributes>()?;
+                let executable = a.specified_attributes & attributes_mask::EXECUTABLE == 0 || a.executable;
+                let user_executable = a.specified_attributes & attributes_mask::USER_EXECUTABLE == 0 || a.user_executable;
+                let mut node = Method::new(node_id, name, name, "", false, executable, user_executable);
+                Self::set_common_attributes(&mut node, a.specified_attributes, a.display_name, a.description, a.write_mask, a.user_write_mask);
+                NodeType::Method(node)
+            }
+            (NodeClass::ObjectType, ObjectId::ObjectTypeAttributes_Encoding_DefaultBinary) => {
+                let a = node_attributes.decode_inner::<ObjectTypeAttributes>()?;
+                let is_abstract = a.specified_attributes & attributes_mask::IS_ABSTRACT != 0 && a.is_abstract;
+                let mut node = ObjectType::new(node_id, name, name, "", is_abstract);
+                Self::set_common_attributes(&mut node, a.specified_attributes, a.display_name, a.description, a.write_mask, a.user_write_mask);
+                NodeType::ObjectType(node)
+            }
+            (NodeClass::VariableType, ObjectId::VariableTypeAttributes_Encoding_DefaultBinary) => {
+                let a = node_attributes.decode_inner::<VariableTypeAttributes>()?;
+                let is_abstract = a.specified_attributes & attributes_mask::IS_ABSTRACT != 0 && a.is_abstract;
+                let value_rank = if a.specified_attributes & attributes_mask::VALUE_RANK != 0 { a.value_rank } else { -1 };
+                let mut node = VariableType::new(node_id, name, name, "", is_abstract, value_rank);
+                if a.specified_attributes & attributes_mask::VALUE != 0 {
+                    let _ = node.set_attribute(AttributeId::Value, DataValue::new(a.value));
+                }
+                if a.specified_attributes & attributes_mask::DATA_TYPE != 0 {
+                    let _ = node.set_attribute(AttributeId::DataType, DataValue::new(a.data_type));
+                }
+                if a.specified_attributes & attributes_mask::ARRAY_DIMENSIONS != 0 {
+                    if let Some(ref array_dimensions) = a.array_dimensions {
+                        let _ = node.set_attribute(AttributeId::ArrayDimensions, DataValue::new(Variant::new_u32_array(array_dimensions)));
+                    }
+                }
+                Self::set_common_attributes(&mut node, a.specified_attributes, a.display_name, a.description, a.write_mask, a.user_write_mask);
+                NodeType::VariableType(node)
+            }
+            (NodeClass::ReferenceType, ObjectId::ReferenceTypeAttributes_Encoding_DefaultBinary) => {
+                let a = node_attributes.decode_inner::<ReferenceTypeAttributes>()?;
+                let is_abstract = a.specified_attributes & attributes_mask::IS_ABSTRACT != 0 && a.is_abstract;
+                let symmetric = a.specified_attributes & attributes_mask::SYMMETRIC != 0 && a.symmetric;
+                let inverse_name = if a.specified_attributes & attributes_mask::INVERSE_NAME != 0 { Some(a.inverse_name) } else { None };
+                let mut node = ReferenceType::new(node_id, name, name, "", inverse_name, symmetric, is_abstract);
+                Self::set_common_attributes(&mut node, a.specified_attributes, a.display_name, a.description, a.write_mask, a.user_write_mask);
+                NodeType::ReferenceType(node)
+            }
+            (NodeClass::DataType, ObjectId::DataTypeAttributes_Encoding_DefaultBinary) => {
+                let a = node_attributes.decode_inner::<DataTypeAttributes>()?;
+                let is_abstract = a.specified_attributes & attributes_mask::IS_ABSTRACT != 0 && a.is_abstract;
+                let mut node = DataType::new(node_id, name, name, "", is_abstract);
+                Self::set_common_attributes(&mut node, a.specified_attributes, a.display_name, a.description, a.write_mask, a.user_write_mask);
+                NodeType::DataType(node)
+            }
+            (NodeClass::View, ObjectId::ViewAttributes_Encoding_DefaultBinary) => {
+                let a = node_attributes.decode_inner::<ViewAttributes>()?;
+                let event_notifier = a.specified_attributes & attributes_mask::EVENT_NOTIFIER != 0 && a.event_notifier != 0;
+                let contains_no_loops = a.specified_attributes & attributes_mask::CONTAINS_NO_LOOPS != 0 && a.contains_no_loops;
+                let mut node = View::new(node_id, name, name, "", event_notifier, contains_no_loops);
+                Self::set_common_attributes(&mut node, a.specified_attributes, a.display_name, a.description, a.write_mask, a.user_write_mask);
+                NodeType::View(node)
+            }
+            _ => {
+                error!("Node attributes of type {:?} cannot be used to create a node of class {:?}", object_id, node_class);
+                return Err(BadNodeAttributesInvalid);
+            }
+        };
+        // The browse name keeps the namespace it was supplied with
+        let _ = node.as_mut_node().set_attribute(AttributeId::BrowseName, DataValue::new(browse_name.clone()));
+        Ok(node)
+    }
+
+    /// Sets the attributes that all node attribute types have in common
+    fn set_common_attributes(node: &mut Node, specified_attributes: UInt32, display_name: LocalizedText, description: LocalizedText, write_mask: UInt32, user_write_mask: UInt32) {
+        if specified_attributes & attributes_mask::DISPLAY_NAME != 0 {
+            let _ = node.set_attribute(AttributeId::DisplayName, DataValue::new(display_name));
+        }
+        if specified_attributes & attributes_mask::DESCRIPTION != 0 {
+            let _ = node.set_attribute(AttributeId::Description, DataValue::new(description));
+        }
+        if specified_attributes & attributes_mask::WRITE_MASK != 0 {
+            node.set_write_mask(write_mask);
+        }
+        if specified_attributes & attributes_mask::USER_WRITE_MASK != 0 {
+            node.set_user_write_mask(user_write_mask);
+        }
+    }
+}
@@ -0,0 +1,380 @@
+use std::collections::{HashSet, VecDeque};
+use std::result::Result;
+
+use opcua_types::*;
+use opcua_types::status_codes::StatusCode;
+use opcua_types::status_codes::StatusCode::*;
+use opcua_types::node_ids::ReferenceTypeId;
+use opcua_types::service_types::*;
+
+use address_space::address_space::AddressSpace;
+use address_space::node::NodeType;
+use constants;
+use content_filter::{self, FilterTarget};
+use continuation_point::QueryContinuationPoint;
+use diagnostic_info::ResponseDiagnostics;
+use session::Session;
+use services::Service;
+use services::attribute::AttributeService;
+use services::view::ViewService;
+
+/// A node that is tested against the content filter of a query
+struct NodeTarget<'a> {
+    session: &'a Session,
+    address_space: &'a AddressSpace,
+    node_id: &'a NodeId,
+    type_definition_id: Option<NodeId>,
+}
+
+impl<'a> FilterTarget for NodeTarget<'a> {
+    /// Selects the attribute of the node, or of a child found by following the browse path
+    /// through hierarchical references
+    fn simple_attribute_value(&self, operand: &SimpleAttributeOperand) -> Variant {
+        let status_code = content_filter::validate_simple_attribute_operand(operand);
+        if status_code.is_bad() {
+            return Variant::from(status_code);
+        }
+        // The browse path is relative to the type definition so the node must be of that type
+        if !operand.type_definition_id.is_null() && !self.is_of_type(&operand.type_definition_id) {
+            return Variant::Empty;
+        }
+        let browse_path: &[QualifiedName] = if let Some(ref browse_path) = operand.browse_path { browse_path } else { &[] };
+        let relative_path = RelativePath {
+            elements: Some(browse_path.iter().map(|browse_name| RelativePathElement {
+                reference_type_id: ReferenceTypeId::HierarchicalReferences.into(),
+                is_inverse: false,
+                include_subtypes: true,
+                target_name: browse_name.clone(),
+            }).collect())
+        };
+        // Values the user cannot read are null
+        QueryService::node_value(self.session, self.address_space, self.node_id, &relative_path, operand.attribute_id, &operand.index_range).unwrap_or(Variant::Empty)
+    }
+
+    /// Selects the attribute of the node, or of the node found by following the relative path
+    fn attribute_value(&self, operand: &AttributeOperand) -> Variant {
+        // The node id of the operand is the type definition the browse path is relative to
+        if !operand.node_id.is_null() && !self.is_of_type(&operand.node_id) {
+            return Variant::Empty;
+        }
+        QueryService::node_value(self.session, self.address_space, self.node_id, &operand.browse_path, operand.attribute_id, &operand.index_range).unwrap_or(Variant::Empty)
+    }
+
+    fn is_of_type(&self, type_id: &NodeId) -> bool {
+        QueryService::is_type_match(self.address_space, self.type_definition_id.as_ref(), type_id, true)
+    }
+
+    fn is_in_view(&self, view_id: &NodeId) -> bool {
+        self.address_space.find_view(view_id).map_or(false, |view| view.contains_node(self.node_id))
+    }
+
+    /// Follows forward references from the node looking for an instance of the target type. A
+    /// hop count of zero is treated as one, i.e. only direct references are followed.
+    fn is_related_to(&self, source_type_id: &NodeId, target_type_id: &NodeId, reference_type_id: &NodeId, hops: UInt32, include_type_subtypes: bool, include_reference_subtypes: bool) -> bool {
+        if !QueryService::is_type_match(self.address_space, self.type_definition_id.as_ref(), source_type_id, include_type_subtypes) {
+            return false;
+        }
+        let reference_type_id = match reference_type_id.as_reference_type_id() {
+            Ok(reference_type_id) => reference_type_id,
+            Err(_) => return false
+        };
+        let mut visited = HashSet::new();
+        let mut node_ids = vec![self.node_id.clone()];
+        visited.insert(self.node_id.clone());
+        for _ in 0..hops.max(1) {
+            let mut next_node_ids = Vec::new();
+            for node_id in &node_ids {
+                if let Some(references) = self.address_space.find_references_from(node_id, Some((reference_type_id, include_reference_subtypes))) {
+                    for reference in references {
+                        if !visited.insert(reference.node_id.clone()) {
+                            continue;
+                        }
+                        let type_definition_id = QueryService::find_type_definition(self.address_space, &reference.node_id);
+                        if QueryService::is_type_match(self.address_space, type_definition_id.as_ref(), target_type_id, include_type_subtypes) {
+                            return true;
+                        }
+                        next_node_ids.push(reference.node_id);
+                    }
+                }
+            }
+            if next_node_ids.is_empty() {
+                break;
+            }
+            node_ids = next_node_ids;
+        }
+        false
+    }
+}
+
+/// The query service. Allows a client to find the instances of types in the address space that
+/// pass a content filter, without browsing for them.
+pub struct QueryService {}
+
+impl Service for QueryService {}
+
+impl QueryService {
+    pub fn new() -> QueryService {
+        QueryService {}
+    }
+
+    pub fn query_first(&self, session: &mut Session, address_space: &AddressSpace, request: QueryFirstRequest) -> Result<SupportedMessage, StatusCode> {
+        let node_types: &[NodeTypeDescription] = match request.node_types {
+            Some(ref node_types) if !node_types.is_empty() => node_types,
+            _ => {
+                return Ok(self.service_fault(&request.request_header, BadNothingToDo));
+            }
+        };
+
+        // The query may be restricted to a view
+        let view = match ViewService::find_view(address_space, &request.view) {
+            Ok(view) => view,
+            Err(status_code) => {
+                return Ok(self.service_fault(&request.request_header, status_code));
+            }
+        };
+
+        if let Err(filter_result) = content_filter::validate(&request.filter) {
+            return Ok(Self::query_first_response(&request.request_header, BadContentFilterInvalid, Vec::new(), ByteString::null(), None, filter_result));
+        }
+
+        // Every node type must be valid or the query is not run and the parsing results say why
+        let parsing_results: Vec<ParsingResult> = node_types.iter().map(|node_type| Self::parse_node_type(address_space, node_type)).collect();
+        if parsing_results.iter().any(|r| r.status_code.is_bad()) {
+            return Ok(Self::query_first_response(&request.request_header, BadInvalidArgument, Vec::new(), ByteString::null(), Some(parsing_results), ContentFilterResult {
+                element_results: None,
+                element_diagnostic_infos: None,
+            }));
+        }
+
+        // The nodes of the view, or the address space, are examined until enough data sets are
+        // found. The nodes not yet examined are held in a continuation point for QueryNext.
+        let mut candidates: VecDeque<NodeId> = address_space.node_map.keys()
+            .filter(|node_id| view.map_or(true, |view| view.contains_node(node_id)))
+            .cloned()
+            .collect();
+
+        let max_data_sets_to_return = request.max_data_sets_to_return as usize;
+        let max_data_sets_to_return = if max_data_sets_to_return == 0 || max_data_sets_to_return > constants::MAX_QUERY_DATA_SETS {
+            constants::MAX_QUERY_DATA_SETS
+        } else {
+            max_data_sets_to_return
+        };
+        let query_data_sets = Self::next_data_sets(session, address_space, node_types, &request.filter, &mut candidates, max_data_sets_to_return);
+        let continuation_point = Self::hold_candidates(session, QueryContinuationPoint {
+            id: ByteString::null(),
+            view: request.view.clone(),
+            node_types: node_types.to_vec(),
+            filter: request.filter.clone(),
+            max_data_sets_to_return,
+            candidates,
+        });
+
+        Ok(Self::query_first_response(&request.request_header, Good, query_data_sets, continuation_point, None, ContentFilterResult {
+            element_results: None,
+            element_diagnostic_infos: None,
+        }))
+    }
+
+    pub fn query_next(&self, session: &mut Session, address_space: &AddressSpace, request: QueryNextRequest) -> Result<SupportedMessage, StatusCode> {
+        let mut continuation_point = if let Some(continuation_point) = session.take_query_continuation_point(&request.continuation_point) {
+            continuation_point
+        } else {
+            return Ok(self.service_fault(&request.request_header, BadContinuationPointInvalid));
+        };
+
+        let (query_data_sets, revised_continuation_point) = if request.release_continuation_point {
+            (Vec::new(), ByteString::null())
+        } else {
+            // The view may have changed since the query started
+            if let Err(status_code) = ViewService::find_view(address_space, &continuation_point.view) {
+                return Ok(self.service_fault(&request.request_header, status_code));
+            }
+            let query_data_sets = Self::next_data_sets(session, address_space, &continuation_point.node_types, &continuation_point.filter, &mut continuation_point.candidates, continuation_point.max_data_sets_to_return);
+            let revised_continuation_point = Self::hold_candidates(session, continuation_point);
+            (query_data_sets, revised_continuation_point)
+        };
+
+        let response = QueryNextResponse {
+            response_header: ResponseDiagnostics::new(&request.request_header).response_header(&request.request_header, Good, None),
+            query_data_sets: Some(query_data_sets),
+            revised_continuation_point,
+        };
+        Ok(response.into())
+    }
+
+    /// Makes the response to QueryFirst, with diagnostics for each node type that was parsed and
+    /// each element of the filter if the client asked for them
+    fn query_first_response(request_header: &RequestHeader, service_result: StatusCode, query_data_sets: Vec<QueryDataSet>, continuation_point: ByteString, parsing_results: Option<Vec<ParsingResult>>, mut filter_result: ContentFilterResult) -> SupportedMessage {
+        let mut response_diagnostics = ResponseDiagnostics::new(request_header);
+        let diagnostic_infos = if let Some(ref parsing_results) = parsing_results {
+            let status_codes: Vec<StatusCode> = parsing_results.iter().map(|r| r.status_code).collect();
+            response_diagnostics.operation_diagnostic_infos(&ResponseDiagnostics::operation_status_diagnostics(&status_codes))
+        } else {
+            None
+        };
+        let element_diagnostic_infos = if let Some(ref element_results) = filter_result.element_results {
+            let status_codes: Vec<StatusCode> = element_results.iter().map(|r| r.status_code).collect();
+            response_diagnostics.operation_diagnostic_infos(&ResponseDiagnostics::operation_status_diagnostics(&status_codes))
+        } else {
+            None
+        };
+        filter_result.element_diagnostic_infos = element_diagnostic_infos;
+        QueryFirstResponse {
+            response_header: response_diagnostics.response_header(request_header, service_result, None),
+            query_data_sets: Some(query_data_sets),
+            continuation_point,
+            parsing_results,
+            diagnostic_infos,
+            filter_result,
+        }.into()
+    }
+
+    /// Holds the query in a continuation point if it has nodes left to examine, returning the id of
+    /// the continuation point, or a null id if nothing remains.
+    fn hold_candidates(session: &mut Session, mut continuation_point: QueryContinuationPoint) -> ByteString {
+        if continuation_point.candidates.is_empty() {
+            ByteString::null()
+        } else {
+            let id = ByteString::random(6);
+            continuation_point.id = id.clone();
+            session.add_query_continuation_point(continuation_point);
+            id
+        }
+    }
+
+    /// Examines the candidate nodes in turn, returning the data sets of those that match the
+    /// node types and pass the filter, up to the number of data sets to return. Candidates that
+    /// have not been examined are left for the next call. Candidates are only left if at least
+    /// one of them matches.
+    fn next_data_sets(session: &Session, address_space: &AddressSpace, node_types: &[NodeTypeDescription], filter: &ContentFilter, candidates: &mut VecDeque<NodeId>, max_data_sets_to_return: usize) -> Vec<QueryDataSet> {
+        let mut query_data_sets = Vec::new();
+        while let Some(node_id) = candidates.pop_front() {
+            if let Some(query_data_set) = Self::query_data_set(session, address_space, node_types, filter, &node_id) {
+                if query_data_sets.len() == max_data_sets_to_return {
+                    // Examined again by QueryNext
+                    candidates.push_front(node_id);
+                    break;
+                }
+                query_data_sets.push(query_data_set);
+            }
+        }
+        query_data_sets
+    }
+
+    /// Returns the data set of the node if it is an instance of one of the node types and passes
+    /// the filter
+    fn query_data_set(session: &Session, address_space: &AddressSpace, node_types: &[NodeTypeDescription], filter: &ContentFilter, node_id: &NodeId) -> Option<QueryDataSet> {
+        // Only instances have a type definition
+        let type_definition_id = Self::find_type_definition(address_space, node_id)?;
+        // The data to return comes from the first node type that the node is an instance of
+        let node_type = node_types.iter().find(|node_type| {
+            Self::is_type_match(address_space, Some(&type_definition_id), &node_type.type_definition_node.node_id, node_type.include_sub_types)
+        })?;
+        let target = NodeTarget {
+            session,
+            address_space,
+            node_id,
+            type_definition_id: Some(type_definition_id.clone()),
+        };
+        if content_filter::evaluate(filter, &target) {
+            let values = node_type.data_to_return.as_ref().map(|data_to_return| {
+                data_to_return.iter().map(|data| {
+                    Self::node_value(session, address_space, node_id, &data.relative_path, data.attribute_id, &data.index_range).unwrap_or_else(Variant::from)
+                }).collect()
+            });
+            Some(QueryDataSet {
+                node_id: node_id.clone().into(),
+                type_definition_node: type_definition_id.into(),
+                values,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Checks that the node type is an object or variable type, and that the data to return
+    /// names valid attributes and index ranges.
+    fn parse_node_type(address_space: &AddressSpace, node_type: &NodeTypeDescription) -> ParsingResult {
+        let type_definition_id = &node_type.type_definition_node.node_id;
+        let status_code = match address_space.find_node(type_definition_id) {
+            Some(&NodeType::ObjectType(_)) | Some(&NodeType::VariableType(_)) => Good,
+            Some(_) => BadNotTypeDefinition,
+            None => BadNodeIdUnknown
+        };
+        let data_status_codes: Option<Vec<StatusCode>> = node_type.data_to_return.as_ref().map(|data_to_return| {
+            data_to_return.iter().map(|data| {
+                if AttributeId::from_u32(data.attribute_id).is_err() {
+                    BadAttributeIdInvalid
+                } else if NumericRange::from_index_range(&data.index_range).is_err() {
+                    BadIndexRangeInvalid
+                } else {
+                    Good
+                }
+            }).collect()
+        });
+        let status_code = if status_code.is_good() && data_status_codes.as_ref().map_or(false, |s| s.iter().any(|s| s.is_bad())) {
+            BadInvalidArgument
+        } else {
+            status_code
+        };
+        ParsingResult {
+            status_code,
+            data_status_codes,
+            data_diagnostic_infos: None,
+        }
+    }
+
+    /// Finds the type definition of an instance
+    fn find_type_definition(address_space: &AddressSpace, node_id: &NodeId) -> Option<NodeId> {
+        address_space.find_references_from(node_id, Some((ReferenceTypeId::HasTypeDefinition, false)))
+            .and_then(|references| references.first().map(|r| r.node_id.clone()))
+    }
+
+    /// Tests if the type definition is the type, or optionally one of its subtypes
+    fn is_type_match(address_space: &AddressSpace, type_definition_id: Option<&NodeId>, type_id: &NodeId, include_subtypes: bool) -> bool {
+        match type_definition_id {
+            Some(type_definition_id) if include_subtypes => address_space.is_subtype(type_definition_id, type_id),
+            Some(type_definition_id) => type_definition_id == type_id,
+            None => false
+        }
+    }
+
+    /// Returns the attribute of the node found by following the relative path from the node, or
+    /// of the node itself if the path is empty. Only the part of the value in the index range is
+    /// returned. Anything that cannot be found is `Variant::Empty`. Nodes that Read would not read
+    /// are `BadNotReadable`, or `BadUserAccessDenied` if the user may not read them.
+    fn node_value(session: &Session, address_space: &AddressSpace, node_id: &NodeId, relative_path: &RelativePath, attribute_id: UInt32, index_range: &UAString) -> Result<Variant, StatusCode> {
+        let attribute_id = match AttributeId::from_u32(attribute_id) {
+            Ok(attribute_id) => attribute_id,
+            Err(_) => return Ok(Variant::Empty)
+        };
+        let node_id = match relative_path.elements {
+            Some(ref elements) if !elements.is_empty() => {
+                if elements.iter().any(|e| e.reference_type_id.as_reference_type_id().is_err()) {
+                    return Ok(Variant::Empty);
+                }
+                match address_space.find_nodes_relative_path(node_id, relative_path) {
+                    Ok(ref node_ids) if !node_ids.is_empty() => node_ids[0].clone(),
+                    _ => return Ok(Variant::Empty)
+                }
+            }
+            _ => node_id.clone()
+        };
+        let node = match address_space.find_node(&node_id) {
+            Some(node) => node,
+            None => return Ok(Variant::Empty)
+        };
+        if !AttributeService::is_readable(node) {
+            return Err(BadNotReadable);
+        }
+        if !address_space.is_user_readable(session, &node_id, attribute_id as UInt32) {
+            return Err(BadUserAccessDenied);
+        }
+        let value = node.as_node().find_attribute(attribute_id).and_then(|data_value| data_value.value);
+        Ok(match (value, NumericRange::from_index_range(index_range)) {
+            (Some(value), Ok(None)) => value,
+            (Some(value), Ok(Some(ref index_range))) => value.range_of(index_range).unwrap_or(Variant::Empty),
+            _ => Variant::Empty
+        })
+    }
+}
@@ -82,6 +82,8 @@ impl SessionService {
             let server_endpoints = Some(endpoints);
 
             session.session_id = session_id.clone();
+            session.session_name = request.session_name.clone();
+            session.client_description = request.client_description.clone();
             session.authentication_token = authentication_token.clone();
             session.session_timeout = session_timeout;
             session.max_request_message_size = max_request_message_size;
@@ -89,6 +91,7 @@ impl SessionService {
             session.endpoint_url = request.endpoint_url.clone();
             session.security_policy_uri = security_policy.to_uri().to_string();
             session.user_identity = None;
+            session.user_token_id = None;
             session.client_certificate = client_certificate;
             session.session_nonce = server_nonce.clone();
 
@@ -109,6 +112,42 @@ impl SessionService {
     }
 
     pub fn activate_session(&self, server_state: &mut ServerState, session: &mut Session, request: ActivateSessionRequest) -> Result<SupportedMessage, StatusCode> {
+        // A client whose connection dropped activates its session again from a new secure channel,
+        // in which case the session's state is taken over from the detached session
+        let detached_session = if session.authentication_token != request.request_header.authentication_token {
+            if let Some(detached_session) = server_state.take_detached_session(&request.request_header.authentication_token) {
+                {
+                    let mut detached_session = trace_write_lock_unwrap!(detached_session);
+                    session.swap_state(&mut detached_session);
+                }
+                Some(detached_session)
+            } else {
+                error!("Activate session has an authentication token that does not match any session");
+                session.terminate_session = true;
+                return Ok(self.service_fault(&request.request_header, BadIdentityTokenRejected));
+            }
+        } else {
+            None
+        };
+
+        let response = self.activate_session_on_channel(server_state, session, request)?;
+
+        if let Some(detached_session) = detached_session {
+            if let SupportedMessage::ActivateSessionResponse(_) = response {
+                info!("Session {:?} has been resumed on a new secure channel", session.session_id);
+            } else {
+                // Give the session back so the client can try again before it expires
+                {
+                    let mut detached_session = trace_write_lock_unwrap!(detached_session);
+                    session.swap_state(&mut detached_session);
+                }
+                server_state.detach_session(detached_session);
+            }
+        }
+        Ok(response)
+    }
+
+    fn activate_session_on_channel(&self, server_state: &mut ServerState, session: &mut Session, request: ActivateSessionRequest) -> Result<SupportedMessage, StatusCode> {
         let server_nonce = ByteString::nonce();
 
         let endpoint_url = session.endpoint_url.as_ref();
@@ -129,12 +168,19 @@ impl SessionService {
         };
 
         // Authenticate the user identity token
+        let mut user_token_id = None;
         if service_result.is_good() {
-            service_result = server_state.authenticate_endpoint(endpoint_url, security_policy, security_mode, &request.user_identity_token);
+            match server_state.authenticate_endpoint(endpoint_url, security_policy, security_mode, &request.user_identity_token) {
+                Ok(id) => user_token_id = Some(id),
+                Err(result) => service_result = result
+            }
         }
 
         let response = if service_result.is_good() {
             session.activated = true;
+            session.user_identity = Some(request.user_identity_token.clone());
+            session.user_token_id = user_token_id;
+            session.locale_ids = request.locale_ids.clone();
             session.session_nonce = server_nonce;
             let diagnostic_infos = None;
             SupportedMessage::ActivateSessionResponse(ActivateSessionResponse {
@@ -152,6 +198,7 @@ impl SessionService {
     pub fn close_session(&self, session: &mut Session, request: CloseSessionRequest) -> Result<SupportedMessage, StatusCode> {
         session.authentication_token = NodeId::null();
         session.user_identity = None;
+        session.user_token_id = None;
         session.activated = false;
         let response = CloseSessionResponse {
             response_header: ResponseHeader::new_good(&request.request_header),
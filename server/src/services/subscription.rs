@@ -1,15 +1,18 @@
 use std::result::Result;
+use std::sync::{Arc, RwLock};
 
 use opcua_types::*;
 use opcua_types::status_codes::StatusCode;
 use opcua_types::status_codes::StatusCode::*;
 use opcua_types::service_types::*;
 
+use diagnostic_info::ResponseDiagnostics;
 use subscriptions::subscription::Subscription;
 use state::ServerState;
 use session::Session;
 use services::Service;
 use address_space::address_space::AddressSpace;
+use constants;
 
 pub struct SubscriptionService {}
 
@@ -34,7 +37,7 @@ impl SubscriptionService {
 
             // Create a new subscription
             let publishing_enabled = request.publishing_enabled;
-            let subscription = Subscription::new(server_state.diagnostics.clone(), subscription_id, publishing_enabled, revised_publishing_interval, revised_lifetime_count, revised_max_keep_alive_count, request.priority);
+            let subscription = Subscription::new(server_state.diagnostics.clone(), subscription_id, publishing_enabled, revised_publishing_interval, revised_lifetime_count, revised_max_keep_alive_count, request.max_notifications_per_publish, request.priority);
             subscriptions.insert(subscription_id, subscription);
 
             // Create the response
@@ -66,7 +69,8 @@ impl SubscriptionService {
             subscription.max_keep_alive_count = revised_max_keep_alive_count;
             subscription.max_lifetime_count = revised_lifetime_count;
             subscription.priority = request.priority;
-            // ...max_notifications_per_publish??
+            subscription.max_notifications_per_publish = request.max_notifications_per_publish;
+            subscription.counters.modify_count += 1;
 
             ModifySubscriptionResponse {
                 response_header: ResponseHeader::new_good(&request.request_header),
@@ -97,18 +101,91 @@ impl SubscriptionService {
                         results.push(BadSubscriptionIdInvalid);
                     }
                 }
-                Some(results)
+                results
             };
-            let diagnostic_infos = None;
+            let mut response_diagnostics = ResponseDiagnostics::new(&request.request_header);
+            let diagnostic_infos = response_diagnostics.operation_diagnostic_infos(&ResponseDiagnostics::operation_status_diagnostics(&results));
             let response = DeleteSubscriptionsResponse {
-                response_header: ResponseHeader::new_good(&request.request_header),
-                results,
+                response_header: response_diagnostics.response_header(&request.request_header, Good, None),
+                results: Some(results),
                 diagnostic_infos,
             };
             Ok(SupportedMessage::DeleteSubscriptionsResponse(response))
         }
     }
 
+    /// Handles a TransferSubscriptionsRequest. Subscriptions are transferred to this session from
+    /// sessions whose connection dropped, or from the sessions of other connections, together with
+    /// the notifications that are waiting to be sent or acknowledged. The user of both sessions
+    /// must be the same. The session is the locked contents of `session_lock`, which tells it apart
+    /// from the other sessions.
+    pub fn transfer_subscriptions(&self, server_state: &mut ServerState, session: &mut Session, session_lock: &Arc<RwLock<Session>>, request: TransferSubscriptionsRequest) -> Result<SupportedMessage, StatusCode> {
+        if request.subscription_ids.is_none() {
+            Ok(self.service_fault(&request.request_header, BadNothingToDo))
+        } else {
+            let results = {
+                let send_initial_values = request.send_initial_values;
+                let subscription_ids = request.subscription_ids.as_ref().unwrap();
+                let mut results = Vec::with_capacity(subscription_ids.len());
+                for subscription_id in subscription_ids {
+                    let subscription_id = *subscription_id;
+                    let result = if session.subscriptions.contains(subscription_id) {
+                        // The subscription already belongs to this session
+                        let subscription = session.subscriptions.get_mut(subscription_id).unwrap();
+                        subscription.counters.transfer_request_count += 1;
+                        if send_initial_values {
+                            subscription.send_initial_values();
+                        }
+                        TransferResult {
+                            status_code: Good,
+                            available_sequence_numbers: session.subscriptions.available_sequence_numbers(subscription_id),
+                        }
+                    } else if server_state.max_subscriptions > 0 && session.subscriptions.len() >= server_state.max_subscriptions {
+                        TransferResult {
+                            status_code: BadTooManySubscriptions,
+                            available_sequence_numbers: None,
+                        }
+                    } else {
+                        match server_state.take_subscription(subscription_id, session_lock, &session.user_token_id) {
+                            Ok(mut transferred_subscription) => {
+                                debug!("Subscription {} is transferred to session {:?}", subscription_id, session.session_id);
+                                {
+                                    // Subscriptions are only transferred between sessions of the same user
+                                    let counters = &mut transferred_subscription.subscription.counters;
+                                    counters.transfer_request_count += 1;
+                                    counters.transferred_to_same_client_count += 1;
+                                }
+                                if send_initial_values {
+                                    transferred_subscription.subscription.send_initial_values();
+                                }
+                                let available_sequence_numbers = session.subscriptions.adopt_subscription(transferred_subscription);
+                                TransferResult {
+                                    status_code: Good,
+                                    available_sequence_numbers: if available_sequence_numbers.is_empty() { None } else { Some(available_sequence_numbers) },
+                                }
+                            }
+                            Err(status_code) => TransferResult {
+                                status_code,
+                                available_sequence_numbers: None,
+                            }
+                        }
+                    };
+                    results.push(result);
+                }
+                results
+            };
+            let mut response_diagnostics = ResponseDiagnostics::new(&request.request_header);
+            let status_codes: Vec<StatusCode> = results.iter().map(|r| r.status_code).collect();
+            let diagnostic_infos = response_diagnostics.operation_diagnostic_infos(&ResponseDiagnostics::operation_status_diagnostics(&status_codes));
+            let response = TransferSubscriptionsResponse {
+                response_header: response_diagnostics.response_header(&request.request_header, Good, None),
+                results: Some(results),
+                diagnostic_infos,
+            };
+            Ok(SupportedMessage::TransferSubscriptionsResponse(response))
+        }
+    }
+
     /// Handles a SerPublishingModeRequest
     pub fn set_publishing_mode(&self, session: &mut Session, request: SetPublishingModeRequest) -> Result<SupportedMessage, StatusCode> {
         if request.subscription_ids.is_none() {
@@ -122,17 +199,23 @@ impl SubscriptionService {
                 for subscription_id in subscription_ids {
                     if let Some(subscription) = subscriptions.get_mut(*subscription_id) {
                         subscription.publishing_enabled = publishing_enabled;
+                        if publishing_enabled {
+                            subscription.counters.enable_count += 1;
+                        } else {
+                            subscription.counters.disable_count += 1;
+                        }
                         results.push(Good);
                     } else {
                         results.push(BadSubscriptionIdInvalid);
                     }
                 }
-                Some(results)
+                results
             };
-            let diagnostic_infos = None;
+            let mut response_diagnostics = ResponseDiagnostics::new(&request.request_header);
+            let diagnostic_infos = response_diagnostics.operation_diagnostic_infos(&ResponseDiagnostics::operation_status_diagnostics(&results));
             let response = SetPublishingModeResponse {
-                response_header: ResponseHeader::new_good(&request.request_header),
-                results,
+                response_header: response_diagnostics.response_header(&request.request_header, Good, None),
+                results: Some(results),
                 diagnostic_infos,
             };
             Ok(SupportedMessage::SetPublishingModeResponse(response))
@@ -142,7 +225,7 @@ impl SubscriptionService {
     /// Handles a PublishRequest. This is asynchronous, so the response will be sent later on.
     pub fn publish(&self, session: &mut Session, request_id: UInt32, address_space: &AddressSpace, request: PublishRequest) -> Result<Option<SupportedMessage>, StatusCode> {
         trace!("--> Receive a PublishRequest {:?}", request);
-        if session.subscriptions.is_empty() {
+        if session.subscriptions.is_empty() && !session.subscriptions.has_unsent_notifications() {
             Ok(Some(self.service_fault(&request.request_header, BadNoSubscription)))
         } else {
             let request_header = request.request_header.clone();
@@ -160,6 +243,13 @@ impl SubscriptionService {
         trace!("Republish {:?}", request);
         // Look for a matching notification message
         let result = session.subscriptions.find_notification_message(request.subscription_id, request.retransmit_sequence_number);
+        if let Some(subscription) = session.subscriptions.get_mut(request.subscription_id) {
+            subscription.counters.republish_request_count += 1;
+            subscription.counters.republish_message_request_count += 1;
+            if result.is_ok() {
+                subscription.counters.republish_message_count += 1;
+            }
+        }
         if let Ok(notification_message) = result {
             let response = RepublishResponse {
                 response_header: ResponseHeader::new_good(&request.request_header),
@@ -174,7 +264,9 @@ impl SubscriptionService {
     /// This function takes the requested values passed in a create / modify and returns revised
     /// values that conform to the server's limits. For simplicity the return type is a tuple
     fn revise_subscription_values(server_state: &ServerState, requested_publishing_interval: Duration, requested_max_keep_alive_count: UInt32, requested_lifetime_count: UInt32) -> (Duration, UInt32, UInt32) {
-        let revised_publishing_interval = if requested_publishing_interval < server_state.min_publishing_interval {
+        let revised_publishing_interval = if !requested_publishing_interval.is_finite() {
+            constants::DEFAULT_PUBLISHING_INTERVAL
+        } else if requested_publishing_interval < server_state.min_publishing_interval {
             server_state.min_publishing_interval
         } else {
             requested_publishing_interval
@@ -8,9 +8,11 @@ use opcua_types::node_ids::{ReferenceTypeId};
 use opcua_types::service_types::*;
 
 use address_space::address_space::AddressSpace;
+use address_space::view::View;
 use session::Session;
 use services::Service;
 use continuation_point::BrowseContinuationPoint;
+use diagnostic_info::ResponseDiagnostics;
 
 // Bits that control the reference description coming back from browse()
 
@@ -34,22 +36,27 @@ impl ViewService {
         let browse_results = if request.nodes_to_browse.is_some() {
             let nodes_to_browse = request.nodes_to_browse.as_ref().unwrap();
 
-            if !request.view.view_id.is_null() {
-                // Views are not supported
-                info!("Browse request ignored because view was specified (views not supported)");
-                return Ok(self.service_fault(&request.request_header, BadViewIdUnknown));
-            }
+            // Browsing may be restricted to a view
+            let view = match Self::find_view(address_space, &request.view) {
+                Ok(view) => view,
+                Err(status_code) => {
+                    info!("Browse request ignored because of the view {:?}, {:?}", request.view, status_code);
+                    return Ok(self.service_fault(&request.request_header, status_code));
+                }
+            };
 
-            Some(Self::browse_nodes(session, address_space, nodes_to_browse, request.requested_max_references_per_node as usize))
+            Self::browse_nodes(session, address_space, view, nodes_to_browse, request.requested_max_references_per_node as usize)
         } else {
             // Nothing to do
             return Ok(self.service_fault(&request.request_header, BadNothingToDo));
         };
 
-        let diagnostic_infos = None;
+        let mut response_diagnostics = ResponseDiagnostics::new(&request.request_header);
+        let status_codes: Vec<StatusCode> = browse_results.iter().map(|r| r.status_code).collect();
+        let diagnostic_infos = response_diagnostics.operation_diagnostic_infos(&ResponseDiagnostics::operation_status_diagnostics(&status_codes));
         let response = BrowseResponse {
-            response_header: ResponseHeader::new_good(&request.request_header),
-            results: browse_results,
+            response_header: response_diagnostics.response_header(&request.request_header, Good, None),
+            results: Some(browse_results),
             diagnostic_infos,
         };
 
@@ -68,13 +75,15 @@ impl ViewService {
                 // Iterate from the continuation point, assuming it is valid
                 let results = continuation_points.iter().map(|continuation_point| {
                     Self::browse_from_continuation_point(session, address_space, continuation_point)
-                }).collect();
+                }).collect::<Vec<BrowseResult>>();
                 Some(results)
             };
 
-            let diagnostic_infos = None;
+            let mut response_diagnostics = ResponseDiagnostics::new(&request.request_header);
+            let status_codes: Vec<StatusCode> = results.as_ref().map_or(Vec::new(), |results| results.iter().map(|r| r.status_code).collect());
+            let diagnostic_infos = response_diagnostics.operation_diagnostic_infos(&ResponseDiagnostics::operation_status_diagnostics(&status_codes));
             let response = BrowseNextResponse {
-                response_header: ResponseHeader::new_good(&request.request_header),
+                response_header: response_diagnostics.response_header(&request.request_header, Good, None),
                 results,
                 diagnostic_infos,
             };
@@ -101,8 +110,10 @@ impl ViewService {
                     targets: None,
                 }
             } else {
-                // Starting from the node_id, find paths
-                let result = address_space.find_nodes_relative_path(&node_id, &browse_path.relative_path);
+                // Starting from the node_id, find paths. The request has no view but a path that
+                // starts from a view node only passes through the nodes of that view.
+                let view = address_space.find_view(&node_id);
+                let result = address_space.find_nodes_relative_path_in_view(&node_id, &browse_path.relative_path, view);
                 if result.is_err() {
                     BrowsePathResult {
                         status_code: result.unwrap_err(),
@@ -128,20 +139,82 @@ impl ViewService {
                     }
                 }
             }
-        }).collect();
+        }).collect::<Vec<BrowsePathResult>>();
 
+        let mut response_diagnostics = ResponseDiagnostics::new(&request.request_header);
+        let status_codes: Vec<StatusCode> = results.iter().map(|r| r.status_code).collect();
+        let diagnostic_infos = response_diagnostics.operation_diagnostic_infos(&ResponseDiagnostics::operation_status_diagnostics(&status_codes));
         let response = TranslateBrowsePathsToNodeIdsResponse {
-            response_header: ResponseHeader::new_good(&request.request_header),
+            response_header: response_diagnostics.response_header(&request.request_header, Good, None),
             results: Some(results),
-            diagnostic_infos: None,
+            diagnostic_infos,
         };
 
         Ok(SupportedMessage::TranslateBrowsePathsToNodeIdsResponse(response))
     }
 
-    fn browse_nodes(session: &mut Session, address_space: &AddressSpace, nodes_to_browse: &[BrowseDescription], max_references_per_node: usize) -> Vec<BrowseResult> {
+    /// Registers nodes that the client accesses repeatedly. Nodes with long ids are given numeric
+    /// aliases which Read, Write and CreateMonitoredItems resolve to the node for the rest of the
+    /// session.
+    pub fn register_nodes(&self, session: &mut Session, address_space: &AddressSpace, request: RegisterNodesRequest) -> Result<SupportedMessage, StatusCode> {
+        if let Some(ref nodes_to_register) = request.nodes_to_register {
+            if nodes_to_register.is_empty() {
+                return Ok(self.service_fault(&request.request_header, BadNothingToDo));
+            }
+            if nodes_to_register.iter().any(|node_id| node_id.is_null()) {
+                return Ok(self.service_fault(&request.request_header, BadNodeIdInvalid));
+            }
+            match session.register_nodes(address_space, nodes_to_register) {
+                Ok(registered_node_ids) => {
+                    let response = RegisterNodesResponse {
+                        response_header: ResponseHeader::new_good(&request.request_header),
+                        registered_node_ids: Some(registered_node_ids),
+                    };
+                    Ok(SupportedMessage::RegisterNodesResponse(response))
+                }
+                Err(status_code) => Ok(self.service_fault(&request.request_header, status_code))
+            }
+        } else {
+            Ok(self.service_fault(&request.request_header, BadNothingToDo))
+        }
+    }
+
+    pub fn unregister_nodes(&self, session: &mut Session, request: UnregisterNodesRequest) -> Result<SupportedMessage, StatusCode> {
+        if let Some(ref nodes_to_unregister) = request.nodes_to_unregister {
+            if nodes_to_unregister.is_empty() {
+                return Ok(self.service_fault(&request.request_header, BadNothingToDo));
+            }
+            session.unregister_nodes(nodes_to_unregister);
+            let response = UnregisterNodesResponse {
+                response_header: ResponseHeader::new_good(&request.request_header),
+            };
+            Ok(SupportedMessage::UnregisterNodesResponse(response))
+        } else {
+            Ok(self.service_fault(&request.request_header, BadNothingToDo))
+        }
+    }
+
+    /// Finds the view that a browse or query is restricted to, if any. Views can only be browsed as they
+    /// are now, so a timestamp is rejected, and a version must match the view's if supplied.
+    pub fn find_view<'a>(address_space: &'a AddressSpace, view: &ViewDescription) -> Result<Option<&'a View>, StatusCode> {
+        if view.view_id.is_null() {
+            Ok(None)
+        } else if let Some(found_view) = address_space.find_view(&view.view_id) {
+            if view.timestamp.ticks() != 0 {
+                Err(BadViewTimestampInvalid)
+            } else if view.view_version != 0 && view.view_version != found_view.view_version() {
+                Err(BadViewVersionInvalid)
+            } else {
+                Ok(Some(found_view))
+            }
+        } else {
+            Err(BadViewIdUnknown)
+        }
+    }
+
+    fn browse_nodes(session: &mut Session, address_space: &AddressSpace, view: Option<&View>, nodes_to_browse: &[BrowseDescription], max_references_per_node: usize) -> Vec<BrowseResult> {
         nodes_to_browse.iter().map(|node_to_browse| {
-            let browse_result = Self::browse_node(session, &address_space, 0, node_to_browse, max_references_per_node);
+            let browse_result = Self::browse_node(session, &address_space, view, 0, node_to_browse, max_references_per_node);
             if let Ok(browse_result) = browse_result {
                 browse_result
             } else {
@@ -154,10 +227,12 @@ impl ViewService {
         }).collect()
     }
 
-    fn browse_node(session: &mut Session, address_space: &AddressSpace, starting_index: usize, node_to_browse: &BrowseDescription, max_references_per_node: usize) -> Result<BrowseResult, StatusCode> {
+    fn browse_node(session: &mut Session, address_space: &AddressSpace, view: Option<&View>, starting_index: usize, node_to_browse: &BrowseDescription, max_references_per_node: usize) -> Result<BrowseResult, StatusCode> {
         // Node must exist or there will be no references
         if node_to_browse.node_id.is_null() || !address_space.node_exists(&node_to_browse.node_id) {
             return Err(BadNodeIdUnknown);
+        } else if view.map_or(false, |view| !view.contains_node(&node_to_browse.node_id)) {
+            return Err(BadNodeNotInView);
         }
 
         // Request may wish to filter by a kind of reference
@@ -188,6 +263,10 @@ impl ViewService {
             if target_node_id.is_null() {
                 continue;
             }
+            // Only references to other nodes in the view are seen through it
+            if view.map_or(false, |view| !view.contains_node(&target_node_id)) {
+                continue;
+            }
             let target_node = address_space.find_node(&target_node_id);
             if target_node.is_none() {
                 continue;
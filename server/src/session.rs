@@ -1,18 +1,23 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::mem;
 use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
 
 use chrono;
 
 use opcua_core::comms::secure_channel::{Role, SecureChannel};
 use opcua_core::crypto::X509;
 use opcua_types::*;
-use opcua_types::service_types::PublishRequest;
+use opcua_types::service_types::{ApplicationDescription, PublishRequest};
 use opcua_types::status_codes::StatusCode;
+use opcua_types::status_codes::StatusCode::*;
 
 use address_space::address_space::AddressSpace;
-use continuation_point::BrowseContinuationPoint;
+use config::ANONYMOUS_USER_TOKEN_ID;
+use continuation_point::{BrowseContinuationPoint, HistoryContinuationPoint, QueryContinuationPoint};
 use diagnostics::ServerDiagnostics;
 use DateTimeUtc;
+use constants;
 use server::Server;
 use subscriptions::subscription::TickReason;
 use subscriptions::subscriptions::Subscriptions;
@@ -23,6 +28,13 @@ pub struct SessionInfo {}
 
 const MAX_DEFAULT_PUBLISH_REQUEST_QUEUE_SIZE: usize = 100;
 const PUBLISH_REQUEST_TIMEOUT: i64 = 30000;
+/// Registered nodes are given numeric aliases counting up from this value, well clear of the
+/// numeric ids that servers typically give their nodes
+const FIRST_REGISTERED_NODE_ALIAS: UInt32 = 0x8000_0000;
+
+/// Internal value used to create new session ids. Ids are unique across the server so that
+/// diagnostics can tell sessions apart.
+static LAST_SESSION_ID: AtomicUsize = ATOMIC_USIZE_INIT;
 
 /// The Session is any state maintained between the client and server
 pub struct Session {
@@ -30,6 +42,12 @@ pub struct Session {
     pub subscriptions: Subscriptions,
     /// The session identifier
     pub session_id: NodeId,
+    /// The name that the client gave the session
+    pub session_name: UAString,
+    /// Describes the client application
+    pub client_description: ApplicationDescription,
+    /// Locales that the client asked for, in order of preference
+    pub locale_ids: Option<Vec<UAString>>,
     /// Flag to indicate session should be terminated
     pub terminate_session: bool,
     /// Security policy
@@ -46,6 +64,9 @@ pub struct Session {
     pub session_timeout: Double,
     /// User identity token
     pub user_identity: Option<ExtensionObject>,
+    /// The id of the user token in the server configuration that the user identity was
+    /// authenticated against
+    pub user_token_id: Option<String>,
     /// Negotiated max request message size
     pub max_request_message_size: UInt32,
     /// Negotiated max response message size
@@ -56,6 +77,20 @@ pub struct Session {
     max_browse_continuation_points: usize,
     /// Browse continuation points (oldest to newest)
     browse_continuation_points: VecDeque<BrowseContinuationPoint>,
+    /// Maximum number of history continuation points
+    max_history_continuation_points: usize,
+    /// History continuation points (oldest to newest)
+    history_continuation_points: VecDeque<HistoryContinuationPoint>,
+    /// Maximum number of query continuation points
+    max_query_continuation_points: usize,
+    /// Query continuation points (oldest to newest)
+    query_continuation_points: VecDeque<QueryContinuationPoint>,
+    /// Nodes that the client has registered, keyed by the alias that the client uses for them
+    registered_nodes: HashMap<NodeId, NodeId>,
+    /// Aliases of the registered nodes, keyed by the node id that they stand for
+    registered_node_aliases: HashMap<NodeId, NodeId>,
+    /// Internal value used to create aliases for registered nodes
+    last_registered_node_alias: UInt32,
     /// Diagnostics associated with the session
     diagnostics: Arc<RwLock<ServerDiagnostics>>,
     /// Indicates if the session has received an ActivateSession
@@ -64,8 +99,6 @@ pub struct Session {
     pub terminated_at: DateTimeUtc,
     /// Flag indicating session is actually terminated
     pub terminated: bool,
-    /// Internal value used to create new session ids.
-    last_session_id: UInt32,
 }
 
 impl Drop for Session {
@@ -80,9 +113,14 @@ impl Session {
     pub fn new_no_certificate_store(secure_channel: SecureChannel) -> Session {
         let max_publish_requests = MAX_DEFAULT_PUBLISH_REQUEST_QUEUE_SIZE;
         let max_browse_continuation_points = super::constants::MAX_BROWSE_CONTINUATION_POINTS;
+        let max_history_continuation_points = super::constants::MAX_HISTORY_CONTINUATION_POINTS;
+        let max_query_continuation_points = super::constants::MAX_QUERY_CONTINUATION_POINTS;
         let session = Session {
             subscriptions: Subscriptions::new(max_publish_requests, PUBLISH_REQUEST_TIMEOUT),
             session_id: NodeId::null(),
+            session_name: UAString::null(),
+            client_description: ApplicationDescription::null(),
+            locale_ids: None,
             activated: false,
             terminate_session: false,
             terminated: false,
@@ -94,13 +132,20 @@ impl Session {
             session_nonce: ByteString::null(),
             session_timeout: 0f64,
             user_identity: None,
+            user_token_id: None,
             max_request_message_size: 0,
             max_response_message_size: 0,
             endpoint_url: UAString::null(),
             max_browse_continuation_points,
             browse_continuation_points: VecDeque::with_capacity(max_browse_continuation_points),
+            max_history_continuation_points,
+            history_continuation_points: VecDeque::with_capacity(max_history_continuation_points),
+            max_query_continuation_points,
+            query_continuation_points: VecDeque::with_capacity(max_query_continuation_points),
+            registered_nodes: HashMap::new(),
+            registered_node_aliases: HashMap::new(),
+            last_registered_node_alias: FIRST_REGISTERED_NODE_ALIAS - 1,
             diagnostics: Arc::new(RwLock::new(ServerDiagnostics::new())),
-            last_session_id: 0,
         };
         {
             let mut diagnostics = trace_write_lock_unwrap!(session.diagnostics);
@@ -112,6 +157,8 @@ impl Session {
     pub fn new(server: &Server) -> Session {
         let max_publish_requests = MAX_DEFAULT_PUBLISH_REQUEST_QUEUE_SIZE;
         let max_browse_continuation_points = super::constants::MAX_BROWSE_CONTINUATION_POINTS;
+        let max_history_continuation_points = super::constants::MAX_HISTORY_CONTINUATION_POINTS;
+        let max_query_continuation_points = super::constants::MAX_QUERY_CONTINUATION_POINTS;
 
         let diagnostics = {
             let server_state = trace_read_lock_unwrap!(server.server_state);
@@ -121,6 +168,9 @@ impl Session {
         let session = Session {
             subscriptions: Subscriptions::new(max_publish_requests, PUBLISH_REQUEST_TIMEOUT),
             session_id: NodeId::null(),
+            session_name: UAString::null(),
+            client_description: ApplicationDescription::null(),
+            locale_ids: None,
             activated: false,
             terminate_session: false,
             terminated: false,
@@ -132,13 +182,20 @@ impl Session {
             session_nonce: ByteString::null(),
             session_timeout: 0f64,
             user_identity: None,
+            user_token_id: None,
             max_request_message_size: 0,
             max_response_message_size: 0,
             endpoint_url: UAString::null(),
             max_browse_continuation_points,
             browse_continuation_points: VecDeque::with_capacity(max_browse_continuation_points),
+            max_history_continuation_points,
+            history_continuation_points: VecDeque::with_capacity(max_history_continuation_points),
+            max_query_continuation_points,
+            query_continuation_points: VecDeque::with_capacity(max_query_continuation_points),
+            registered_nodes: HashMap::new(),
+            registered_node_aliases: HashMap::new(),
+            last_registered_node_alias: FIRST_REGISTERED_NODE_ALIAS - 1,
             diagnostics,
-            last_session_id: 0,
         };
         {
             let mut diagnostics = trace_write_lock_unwrap!(session.diagnostics);
@@ -148,23 +205,50 @@ impl Session {
     }
 
     pub fn next_session_id(&mut self) -> NodeId {
-        self.last_session_id += 1;
-        NodeId::new(1, self.last_session_id)
+        let session_id = LAST_SESSION_ID.fetch_add(1, Ordering::SeqCst) + 1;
+        NodeId::new(1, session_id as UInt32)
     }
 
     pub fn terminated(&self) -> bool { self.terminated }
 
+    /// Tests if the session has no user, or an anonymous one
+    pub fn is_anonymous(&self) -> bool {
+        self.user_token_id.as_ref().map_or(true, |user_token_id| user_token_id == ANONYMOUS_USER_TOKEN_ID)
+    }
+
     pub fn set_terminated(&mut self) {
         self.terminated = true;
         self.terminated_at = chrono::Utc::now();
     }
 
+    /// Exchanges the state of this session with that of another, except for the secure channel and
+    /// termination flags which belong to each session's connection. This is how a session whose
+    /// connection dropped is resumed on the new connection that the client activates it from.
+    pub fn swap_state(&mut self, other: &mut Session) {
+        mem::swap(self, other);
+        mem::swap(&mut self.secure_channel, &mut other.secure_channel);
+        mem::swap(&mut self.terminate_session, &mut other.terminate_session);
+        mem::swap(&mut self.terminated, &mut other.terminated);
+        mem::swap(&mut self.terminated_at, &mut other.terminated_at);
+    }
+
+    /// Tests if the session can be resumed by the client from a new connection should this
+    /// connection drop, i.e. it has been activated and not closed.
+    pub fn is_resumable(&self) -> bool {
+        self.activated && !self.authentication_token.is_null()
+    }
+
     pub fn enqueue_publish_request(&mut self, address_space: &AddressSpace, request_id: UInt32, request: PublishRequest) -> Result<(), StatusCode> {
         self.subscriptions.enqueue_publish_request(address_space, request_id, request)
     }
 
     pub fn tick_subscriptions(&mut self, now: &DateTimeUtc, address_space: &AddressSpace, reason: TickReason) -> Result<(), StatusCode> {
-        self.subscriptions.tick(now, address_space, reason)
+        let result = self.subscriptions.tick(now, address_space, reason);
+        if !self.subscriptions.is_empty() {
+            let mut diagnostics = trace_write_lock_unwrap!(self.diagnostics);
+            diagnostics.on_update_session(self);
+        }
+        result
     }
 
     /// Iterates through the existing queued publish requests and creates a timeout
@@ -216,4 +300,87 @@ impl Session {
             !continuation_points_set.contains(&continuation_point.id)
         });
     }
+
+    /// Registers nodes that the client accesses repeatedly, returning the node ids that the client
+    /// should use for them from now on. Nodes with a string, guid or opaque id are given a
+    /// numeric alias that is cheaper to send and look up. Other node ids are returned as they are.
+    pub fn register_nodes(&mut self, address_space: &AddressSpace, node_ids: &[NodeId]) -> Result<Vec<NodeId>, StatusCode> {
+        if self.registered_nodes.len() + node_ids.len() > constants::MAX_REGISTERED_NODES {
+            return Err(BadTooManyOperations);
+        }
+        let results = node_ids.iter().map(|node_id| {
+            if node_id.is_numeric() {
+                return node_id.clone();
+            }
+            // A node that is registered again keeps its alias
+            if let Some(alias) = self.registered_node_aliases.get(node_id) {
+                return alias.clone();
+            }
+            let alias = loop {
+                self.last_registered_node_alias = self.last_registered_node_alias.wrapping_add(1).max(FIRST_REGISTERED_NODE_ALIAS);
+                let alias = NodeId::new(node_id.namespace, self.last_registered_node_alias);
+                if !address_space.node_exists(&alias) && !self.registered_nodes.contains_key(&alias) {
+                    break alias;
+                }
+            };
+            self.registered_nodes.insert(alias.clone(), node_id.clone());
+            self.registered_node_aliases.insert(node_id.clone(), alias.clone());
+            alias
+        }).collect();
+        Ok(results)
+    }
+
+    /// Unregisters nodes, forgetting their aliases. Node ids that are not aliases are ignored.
+    pub fn unregister_nodes(&mut self, node_ids: &[NodeId]) {
+        for node_id in node_ids {
+            if let Some(registered_node_id) = self.registered_nodes.remove(node_id) {
+                self.registered_node_aliases.remove(&registered_node_id);
+            }
+        }
+    }
+
+    /// Replaces an alias of a registered node with the node id that it stands for
+    pub fn resolve_registered_node(&self, node_id: &mut NodeId) {
+        if !self.registered_nodes.is_empty() {
+            if let Some(registered_node_id) = self.registered_nodes.get(node_id) {
+                *node_id = registered_node_id.clone();
+            }
+        }
+    }
+
+    pub fn add_history_continuation_point(&mut self, continuation_point: HistoryContinuationPoint) {
+        // Remove excess history continuation points
+        while self.history_continuation_points.len() >= self.max_history_continuation_points {
+            let _ = self.history_continuation_points.pop_front();
+        }
+        self.history_continuation_points.push_back(continuation_point);
+    }
+
+    /// Find a history continuation point by id and remove it from the session. A history
+    /// continuation point can only be used once.
+    pub fn take_history_continuation_point(&mut self, id: &ByteString) -> Option<HistoryContinuationPoint> {
+        if let Some(idx) = self.history_continuation_points.iter().position(|continuation_point| continuation_point.id.eq(id)) {
+            self.history_continuation_points.remove(idx)
+        } else {
+            None
+        }
+    }
+
+    pub fn add_query_continuation_point(&mut self, continuation_point: QueryContinuationPoint) {
+        // Remove excess query continuation points
+        while self.query_continuation_points.len() >= self.max_query_continuation_points {
+            let _ = self.query_continuation_points.pop_front();
+        }
+        self.query_continuation_points.push_back(continuation_point);
+    }
+
+    /// Find a query continuation point by id and remove it from the session. A query
+    /// continuation point can only be used once.
+    pub fn take_query_continuation_point(&mut self, id: &ByteString) -> Option<QueryContinuationPoint> {
+        if let Some(idx) = self.query_continuation_points.iter().position(|continuation_point| continuation_point.id.eq(id)) {
+            self.query_continuation_points.remove(idx)
+        } else {
+            None
+        }
+    }
 }
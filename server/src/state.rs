@@ -1,7 +1,10 @@
 //! The server module defines types related to the server, it's current running state
 //! and end point information.
 
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, RwLock, Weak};
+
+use chrono;
+use time;
 
 use opcua_core::prelude::*;
 use opcua_types::node_ids::ObjectId;
@@ -11,8 +14,13 @@ use opcua_types::service_types::ServerState as ServerStateType;
 use opcua_types::status_codes::StatusCode;
 use opcua_types::status_codes::StatusCode::*;
 
-use config::{ServerConfig, ServerEndpoint};
+use config::{ServerConfig, ServerEndpoint, ANONYMOUS_USER_TOKEN_ID};
 use diagnostics::ServerDiagnostics;
+use discovery::registered_servers::RegisteredServers;
+use discovery::servers_on_network::ServersOnNetwork;
+use session::Session;
+use subscriptions::subscriptions::TransferredSubscription;
+use DateTimeUtc;
 
 const TOKEN_POLICY_ANONYMOUS: &'static str = "anonymous";
 const TOKEN_POLICY_USER_PASS_PLAINTEXT: &'static str = "userpass_plaintext";
@@ -55,6 +63,15 @@ pub struct ServerState {
     pub abort: bool,
     /// Diagnostic information
     pub diagnostics: Arc<RwLock<ServerDiagnostics>>,
+    /// Sessions whose connection has dropped. Each is kept until its session timeout elapses in
+    /// case the client activates it again from a new secure channel.
+    pub detached_sessions: Vec<Arc<RwLock<Session>>>,
+    /// The sessions of the server's connections, which are dropped with their connection
+    pub sessions: Vec<Weak<RwLock<Session>>>,
+    /// Servers registered with this server when it runs as a local discovery server
+    pub registered_servers: RegisteredServers,
+    /// Servers announced on the network through mDNS
+    pub servers_on_network: ServersOnNetwork,
 }
 
 impl ServerState {
@@ -128,15 +145,7 @@ impl ServerState {
         // and docs say not to bother sending the server and server
         // certificate info.
         let (server, server_certificate) = if all_fields {
-            (ApplicationDescription {
-                application_uri: self.application_uri.clone(),
-                product_uri: self.product_uri.clone(),
-                application_name: self.application_name.clone(),
-                application_type: self.application_type(),
-                gateway_server_uri: self.gateway_server_uri(),
-                discovery_profile_uri: UAString::null(),
-                discovery_urls: self.discovery_urls(),
-            }, self.server_certificate_as_byte_string())
+            (self.application_description(), self.server_certificate_as_byte_string())
         } else {
             (ApplicationDescription {
                 application_uri: UAString::null(),
@@ -170,7 +179,46 @@ impl ServerState {
         }
     }
 
-    pub fn application_type(&self) -> ApplicationType { ApplicationType::Server }
+    pub fn application_type(&self) -> ApplicationType {
+        if self.is_discovery_server() { ApplicationType::DiscoveryServer } else { ApplicationType::Server }
+    }
+
+    /// Tests if the server runs as a local discovery server
+    pub fn is_discovery_server(&self) -> bool {
+        let config = trace_read_lock_unwrap!(self.config);
+        config.discovery_server
+    }
+
+    /// Tests if the server announces itself through mDNS
+    pub fn is_multicast_discovery(&self) -> bool {
+        let config = trace_read_lock_unwrap!(self.config);
+        config.multicast_discovery
+    }
+
+    /// The capability identifiers that the server is announced with
+    pub fn server_capabilities(&self) -> Vec<String> {
+        let config = trace_read_lock_unwrap!(self.config);
+        if !config.server_capabilities.is_empty() {
+            config.server_capabilities.clone()
+        } else if config.discovery_server {
+            vec!["LDS".to_string()]
+        } else {
+            vec!["NA".to_string()]
+        }
+    }
+
+    /// Describes this server as FindServers returns it
+    pub fn application_description(&self) -> ApplicationDescription {
+        ApplicationDescription {
+            application_uri: self.application_uri.clone(),
+            product_uri: self.product_uri.clone(),
+            application_name: self.application_name.clone(),
+            application_type: self.application_type(),
+            gateway_server_uri: self.gateway_server_uri(),
+            discovery_profile_uri: UAString::null(),
+            discovery_urls: self.discovery_urls(),
+        }
+    }
 
     pub fn gateway_server_uri(&self) -> UAString { UAString::null() }
 
@@ -205,6 +253,78 @@ impl ServerState {
         }
     }
 
+    /// Keeps a session whose connection has dropped so the client may activate it again, or transfer
+    /// its subscriptions, before its session timeout elapses.
+    pub fn detach_session(&mut self, session: Arc<RwLock<Session>>) {
+        self.remove_expired_sessions(&chrono::Utc::now());
+        self.detached_sessions.push(session);
+    }
+
+    /// Removes detached sessions that have waited longer than their session timeout.
+    pub fn remove_expired_sessions(&mut self, now: &DateTimeUtc) {
+        let diagnostics = &self.diagnostics;
+        self.detached_sessions.retain(|session| {
+            let session = trace_read_lock_unwrap!(session);
+            let expires_at = session.terminated_at + time::Duration::milliseconds(session.session_timeout as i64);
+            if *now >= expires_at {
+                info!("Detached session {:?} has expired", session.session_id);
+                let mut diagnostics = trace_write_lock_unwrap!(diagnostics);
+                diagnostics.on_session_timeout(&session);
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    /// Takes the detached session that has the authentication token, if it hasn't expired.
+    pub fn take_detached_session(&mut self, authentication_token: &NodeId) -> Option<Arc<RwLock<Session>>> {
+        if authentication_token.is_null() {
+            return None;
+        }
+        self.remove_expired_sessions(&chrono::Utc::now());
+        let idx = self.detached_sessions.iter().position(|session| {
+            let session = trace_read_lock_unwrap!(session);
+            session.authentication_token == *authentication_token
+        });
+        idx.map(|idx| self.detached_sessions.remove(idx))
+    }
+
+    /// Registers the session of a connection so that its subscriptions can be transferred to other
+    /// sessions. Sessions whose connection has gone are forgotten.
+    pub fn register_session(&mut self, session: &Arc<RwLock<Session>>) {
+        self.sessions.retain(|session| session.upgrade().is_some());
+        self.sessions.push(Arc::downgrade(session));
+    }
+
+    /// Takes a subscription out of a detached session, or a session of another connection, so it
+    /// can be transferred to the session of the same user that asks for it. Returns
+    /// `BadSubscriptionIdInvalid` if no other session has the subscription, or `BadUserAccessDenied`
+    /// if it belongs to another user.
+    ///
+    /// The asking session is locked by the caller. Other sessions are locked here, so the caller
+    /// must not hold the address space that their subscription ticks wait on.
+    pub fn take_subscription(&mut self, subscription_id: UInt32, asking_session: &Arc<RwLock<Session>>, user_token_id: &Option<String>) -> Result<TransferredSubscription, StatusCode> {
+        self.remove_expired_sessions(&chrono::Utc::now());
+        self.sessions.retain(|session| session.upgrade().is_some());
+        let other_sessions: Vec<Arc<RwLock<Session>>> = self.detached_sessions.iter().cloned()
+            .chain(self.sessions.iter().filter_map(|session| session.upgrade()))
+            .filter(|session| !Arc::ptr_eq(session, asking_session))
+            .collect();
+        for session in other_sessions {
+            let mut session = trace_write_lock_unwrap!(session);
+            if session.subscriptions.contains(subscription_id) {
+                if session.user_token_id != *user_token_id {
+                    error!("Subscription {} cannot be transferred to a session of another user", subscription_id);
+                    return Err(BadUserAccessDenied);
+                }
+                debug!("Subscription {} is taken from session {:?}", subscription_id, session.session_id);
+                return Ok(session.subscriptions.take_subscription(subscription_id).unwrap());
+            }
+        }
+        Err(BadSubscriptionIdInvalid)
+    }
+
     pub fn create_subscription_id(&mut self) -> UInt32 {
         self.last_subscription_id += 1;
         self.last_subscription_id
@@ -215,8 +335,9 @@ impl ServerState {
     ///
     /// It is possible that the endpoint does not exist, or that the token is invalid / unsupported
     /// or that the token cannot be used with the end point. The return codes reflect the responses
-    /// that ActivateSession would expect from a service call.
-    pub fn authenticate_endpoint(&self, endpoint_url: &str, security_policy: SecurityPolicy, security_mode: MessageSecurityMode, user_identity_token: &ExtensionObject) -> StatusCode {
+    /// that ActivateSession would expect from a service call. On success the id of the user token
+    /// in the server configuration that the identity was authenticated against is returned.
+    pub fn authenticate_endpoint(&self, endpoint_url: &str, security_policy: SecurityPolicy, security_mode: MessageSecurityMode, user_identity_token: &ExtensionObject) -> Result<String, StatusCode> {
         // Get security from endpoint url
         let config = trace_read_lock_unwrap!(self.config);
         if let Some(endpoint) = config.find_endpoint(endpoint_url, security_policy, security_mode) {
@@ -240,7 +361,7 @@ impl ServerState {
                             } else {
                                 // Garbage in the extension object
                                 error!("User name identity token could not be decoded");
-                                BadIdentityTokenInvalid
+                                Err(BadIdentityTokenInvalid)
                             }
                         }
                         ObjectId::X509IdentityToken_Encoding_DefaultBinary => {
@@ -248,51 +369,51 @@ impl ServerState {
                             let result = user_identity_token.decode_inner::<X509IdentityToken>();
                             if let Ok(_) = result {
                                 error!("X509 identity token type is not supported");
-                                BadIdentityTokenRejected
+                                Err(BadIdentityTokenRejected)
                             } else {
                                 // Garbage in the extension object
                                 error!("X509 identity token could not be decoded");
-                                BadIdentityTokenInvalid
+                                Err(BadIdentityTokenInvalid)
                             }
                         }
                         _ => {
                             error!("User identity token type {:?} is unrecognized", object_id);
-                            BadIdentityTokenInvalid
+                            Err(BadIdentityTokenInvalid)
                         }
                     }
                 } else {
                     error!("Cannot read user identity token");
-                    BadIdentityTokenInvalid
+                    Err(BadIdentityTokenInvalid)
                 }
             }
         } else {
             error!("Cannot find endpoint that matches path \"{}\", security policy {:?}, and security mode {:?}", endpoint_url, security_policy, security_mode);
-            BadTcpEndpointUrlInvalid
+            Err(BadTcpEndpointUrlInvalid)
         }
     }
 
     /// Authenticates an anonymous token, i.e. does the endpoint support anonymous access or not
-    fn authenticate_anonymous_token(endpoint: &ServerEndpoint) -> StatusCode {
+    fn authenticate_anonymous_token(endpoint: &ServerEndpoint) -> Result<String, StatusCode> {
         if endpoint.supports_anonymous() {
             debug!("Anonymous identity is authenticated");
-            Good
+            Ok(ANONYMOUS_USER_TOKEN_ID.to_string())
         } else {
             error!("Endpoint \"{}\" does not support anonymous authentication", endpoint.path);
-            BadIdentityTokenRejected
+            Err(BadIdentityTokenRejected)
         }
     }
 
     /// Authenticates the username identity token with the supplied endpoint
-    fn authenticate_username_identity_token(&self, config: &ServerConfig, endpoint: &ServerEndpoint, token: &UserNameIdentityToken) -> StatusCode {
+    fn authenticate_username_identity_token(&self, config: &ServerConfig, endpoint: &ServerEndpoint, token: &UserNameIdentityToken) -> Result<String, StatusCode> {
         // TODO Server's user token policy should be checked here.
         // The policy_id should be used to determine the algorithm for encoding passwords etc.
         if !token.encryption_algorithm.is_null() {
             // Plaintext is the only supported algorithm at this time
             error!("Only unencrypted passwords are supported, {:?}", token);
-            BadIdentityTokenInvalid
+            Err(BadIdentityTokenInvalid)
         } else if token.user_name.is_null() {
             error!("User identify token supplies no user name");
-            BadIdentityTokenInvalid
+            Err(BadIdentityTokenInvalid)
         } else {
             // Iterate ids in endpoint
             for user_token_id in &endpoint.user_token_ids {
@@ -310,15 +431,15 @@ impl ServerState {
                         let valid = result.is_ok();
                         if !valid {
                             error!("Cannot authenticate \"{}\", password is invalid", server_user_token.user);
-                            return BadIdentityTokenRejected;
+                            return Err(BadIdentityTokenRejected);
                         } else {
-                            return Good;
+                            return Ok(user_token_id.clone());
                         }
                     }
                 }
             }
             error!("Cannot authenticate \"{}\", user not found for endpoint", token.user_name);
-            BadIdentityTokenRejected
+            Err(BadIdentityTokenRejected)
         }
     }
 }
@@ -20,3 +20,5 @@ pub struct PublishResponseEntry {
 pub mod subscriptions;
 pub mod subscription;
 pub mod monitored_item;
+pub mod value_changes;
+pub mod sampler;
@@ -1,5 +1,5 @@
 use std::result::Result;
-use std::collections::VecDeque;
+use std::collections::{BTreeSet, VecDeque};
 
 use chrono;
 use time;
@@ -7,19 +7,28 @@ use time;
 use opcua_types::*;
 use opcua_types::status_codes::StatusCode;
 use opcua_types::status_codes::StatusCode::*;
-use opcua_types::node_ids::ObjectId;
-use opcua_types::service_types::{TimestampsToReturn, DataChangeFilter, ReadValueId, MonitoredItemCreateRequest, MonitoredItemModifyRequest, MonitoredItemNotification};
+use opcua_types::node_ids::{ObjectId, ObjectTypeId, ReferenceTypeId};
+use opcua_types::service_types::{TimestampsToReturn, DataChangeFilter, AggregateFilter, AggregateFilterResult, Range, EventFilter, EventFieldList, ReadValueId, MonitoredItemCreateRequest, MonitoredItemModifyRequest, MonitoredItemNotification};
 
 use constants;
 
 use DateTimeUtc;
+use aggregates::{self, AggregateCalculator, IntervalAggregator};
 use address_space::address_space::AddressSpace;
+use address_space::node::NodeType;
+use address_space::event_notifier;
+use subscriptions::value_changes::ValueChangeListener;
+use subscriptions::sampler::{self, SampledItem};
+use events::event::{self, BaseEventType};
+use events::event_filter;
 use subscriptions::subscription::TickReason;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum FilterType {
     None,
     DataChangeFilter(DataChangeFilter),
+    EventFilter(EventFilter),
+    AggregateFilter(AggregateFilter),
 }
 
 impl FilterType {
@@ -31,6 +40,10 @@ impl FilterType {
             Ok(FilterType::None)
         } else if filter_type_id == &ObjectId::DataChangeFilter_Encoding_DefaultBinary.into() {
             Ok(FilterType::DataChangeFilter(filter.decode_inner::<DataChangeFilter>()?))
+        } else if filter_type_id == &ObjectId::EventFilter_Encoding_DefaultBinary.into() {
+            Ok(FilterType::EventFilter(filter.decode_inner::<EventFilter>()?))
+        } else if filter_type_id == &ObjectId::AggregateFilter_Encoding_DefaultBinary.into() {
+            Ok(FilterType::AggregateFilter(filter.decode_inner::<AggregateFilter>()?))
         } else {
             error!("Requested data filter type is not supported, {:?}", filter_type_id);
             Err(BadFilterNotAllowed)
@@ -38,64 +51,202 @@ impl FilterType {
     }
 }
 
+/// A notification that a monitored item has queued for its subscription to publish
+#[derive(Debug, Clone, PartialEq)]
+pub enum Notification {
+    MonitoredItemNotification(MonitoredItemNotification),
+    EventFieldList(EventFieldList),
+}
+
+impl From<MonitoredItemNotification> for Notification {
+    fn from(v: MonitoredItemNotification) -> Self {
+        Notification::MonitoredItemNotification(v)
+    }
+}
+
+impl From<EventFieldList> for Notification {
+    fn from(v: EventFieldList) -> Self {
+        Notification::EventFieldList(v)
+    }
+}
+
+/// Tests if the event marks the start or end of a condition refresh
+fn is_refresh_event(event: &BaseEventType) -> bool {
+    event.event_type == ObjectTypeId::RefreshStartEventType.into() || event.event_type == ObjectTypeId::RefreshEndEventType.into()
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct MonitoredItem {
     pub monitored_item_id: UInt32,
     pub item_to_monitor: ReadValueId,
+    /// The part of the value that is monitored, parsed from the index range of the item
+    index_range: Option<NumericRange>,
     pub monitoring_mode: MonitoringMode,
     pub client_handle: UInt32,
     pub sampling_interval: Duration,
     pub filter: FilterType,
+    /// The result of validating the filter which is returned to the client
+    pub filter_result: ExtensionObject,
     pub discard_oldest: Boolean,
     pub queue_size: usize,
-    pub notification_queue: VecDeque<MonitoredItemNotification>,
+    pub notification_queue: VecDeque<Notification>,
     pub queue_overflow: bool,
     timestamps_to_return: TimestampsToReturn,
     last_sample_time: DateTimeUtc,
     last_data_value: Option<DataValue>,
+    /// The number of the last event that the item has seen
+    last_event_number: usize,
+    /// The subscription that the item belongs to
+    subscription_id: UInt32,
+    /// The items whose queued notifications are reported whenever this item reports
+    triggered_items: BTreeSet<UInt32>,
+    /// The engineering unit range of the monitored variable, used by a percent deadband
+    eu_range: Option<(f64, f64)>,
+    /// Calculates the aggregate of each processing interval of an item with an aggregate filter
+    aggregator: Option<IntervalAggregator>,
+    /// Receives the values set on the monitored variable if the address space holds its value
+    value_listener: Option<ValueChangeListener>,
+    /// Shares the samples of the monitored attribute with other items. Items listening for their
+    /// values do not sample but are counted in the diagnostics of their sampling interval.
+    sampled_item: Option<SampledItem>,
 }
 
 impl MonitoredItem {
-    pub fn new(monitored_item_id: UInt32, timestamps_to_return: TimestampsToReturn, request: &MonitoredItemCreateRequest) -> Result<MonitoredItem, StatusCode> {
-        let filter = FilterType::from_filter(&request.requested_parameters.filter)?;
-        let sampling_interval = MonitoredItem::sanitize_sampling_interval(request.requested_parameters.sampling_interval);
-        let queue_size = MonitoredItem::sanitize_queue_size(request.requested_parameters.queue_size as usize);
+    pub fn new(address_space: &AddressSpace, monitored_item_id: UInt32, timestamps_to_return: TimestampsToReturn, request: &MonitoredItemCreateRequest) -> Result<MonitoredItem, (StatusCode, ExtensionObject)> {
+        let filter = FilterType::from_filter(&request.requested_parameters.filter).map_err(|e| (e, ExtensionObject::null()))?;
+        let filter_result = MonitoredItem::validate_filter(&request.item_to_monitor, &filter)?;
+        let eu_range = MonitoredItem::validate_deadband(address_space, &request.item_to_monitor, &filter).map_err(|e| (e, ExtensionObject::null()))?;
+        let index_range = NumericRange::from_index_range(&request.item_to_monitor.index_range).map_err(|e| (e, ExtensionObject::null()))?;
+        let value_listener = MonitoredItem::listen_for_value_changes(address_space, &request.item_to_monitor, &filter);
+        let sampling_interval = MonitoredItem::sanitize_sampling_interval(&filter, value_listener.is_some(), request.requested_parameters.sampling_interval);
+        let sampled_item = MonitoredItem::add_sampled_item(address_space, &request.item_to_monitor, &filter, sampling_interval);
+        let queue_size = MonitoredItem::sanitize_queue_size(&filter, request.requested_parameters.queue_size as usize);
+        let (aggregator, filter_result) = match MonitoredItem::create_aggregator(address_space, &request.item_to_monitor, &filter, sampling_interval).map_err(|e| (e, ExtensionObject::null()))? {
+            Some((aggregator, aggregate_filter_result)) => (Some(aggregator), aggregate_filter_result),
+            None => (None, filter_result)
+        };
         Ok(MonitoredItem {
             monitored_item_id,
             item_to_monitor: request.item_to_monitor.clone(),
+            index_range,
             monitoring_mode: request.monitoring_mode,
             client_handle: request.requested_parameters.client_handle,
             sampling_interval,
             filter,
+            filter_result,
             discard_oldest: request.requested_parameters.discard_oldest,
             timestamps_to_return,
             last_sample_time: chrono::Utc::now(),
             last_data_value: None,
+            // Only events raised from now on are of interest
+            last_event_number: event::last_event_number(),
+            subscription_id: 0,
+            triggered_items: BTreeSet::new(),
+            eu_range,
+            aggregator,
+            value_listener,
+            sampled_item,
             queue_size,
             notification_queue: VecDeque::with_capacity(queue_size),
             queue_overflow: false,
         })
     }
 
-    /// Modifies the existing item with the values of the modify request
-    pub fn modify(&mut self, timestamps_to_return: TimestampsToReturn, request: &MonitoredItemModifyRequest) -> Result<(), StatusCode> {
+    /// Modifies the existing item with the values of the modify request. If the filter is invalid,
+    /// the error holds the result of validating it for the client.
+    pub fn modify(&mut self, address_space: &AddressSpace, timestamps_to_return: TimestampsToReturn, request: &MonitoredItemModifyRequest) -> Result<(), (StatusCode, ExtensionObject)> {
+        let filter = FilterType::from_filter(&request.requested_parameters.filter).map_err(|e| (e, ExtensionObject::null()))?;
+        let filter_result = MonitoredItem::validate_filter(&self.item_to_monitor, &filter)?;
+        let eu_range = MonitoredItem::validate_deadband(address_space, &self.item_to_monitor, &filter).map_err(|e| (e, ExtensionObject::null()))?;
+        let sampling_interval = MonitoredItem::sanitize_sampling_interval(&filter, self.value_listener.is_some(), request.requested_parameters.sampling_interval);
+        let (aggregator, filter_result) = match MonitoredItem::create_aggregator(address_space, &self.item_to_monitor, &filter, sampling_interval).map_err(|e| (e, ExtensionObject::null()))? {
+            Some((aggregator, aggregate_filter_result)) => (Some(aggregator), aggregate_filter_result),
+            None => (None, filter_result)
+        };
+        if sampling_interval != self.sampling_interval || filter != self.filter {
+            // Share samples with the items sampling at the new interval
+            self.sampled_item = MonitoredItem::add_sampled_item(address_space, &self.item_to_monitor, &filter, sampling_interval);
+            if let Some(ref sampled_item) = self.sampled_item {
+                sampled_item.set_enabled(self.monitoring_mode != MonitoringMode::Disabled);
+            }
+        }
+        self.eu_range = eu_range;
+        self.aggregator = aggregator;
+        self.filter_result = filter_result;
+        self.filter = filter;
         self.timestamps_to_return = timestamps_to_return;
-        self.filter = FilterType::from_filter(&request.requested_parameters.filter)?;
-        self.sampling_interval = MonitoredItem::sanitize_sampling_interval(request.requested_parameters.sampling_interval);
-        self.queue_size = MonitoredItem::sanitize_queue_size(request.requested_parameters.queue_size as usize);
+        self.sampling_interval = sampling_interval;
+        self.queue_size = MonitoredItem::sanitize_queue_size(&self.filter, request.requested_parameters.queue_size as usize);
         self.client_handle = request.requested_parameters.client_handle;
         self.discard_oldest = request.requested_parameters.discard_oldest;
         Ok(())
     }
 
+    /// Sets the subscription that the item belongs to. Some events are only for the items of one
+    /// subscription.
+    pub fn set_subscription_id(&mut self, subscription_id: UInt32) {
+        self.subscription_id = subscription_id;
+    }
+
+    /// Changes the monitoring mode of the item. Disabling the item discards its queued notifications
+    /// and the value it last sampled, so its current value is reported once it is enabled again.
+    pub fn set_monitoring_mode(&mut self, monitoring_mode: MonitoringMode) {
+        if monitoring_mode == MonitoringMode::Disabled {
+            self.notification_queue.clear();
+            self.queue_overflow = false;
+            self.last_data_value = None;
+            if let Some(ref value_listener) = self.value_listener {
+                let _ = value_listener.take_values();
+            }
+        }
+        if let Some(ref sampled_item) = self.sampled_item {
+            sampled_item.set_enabled(monitoring_mode != MonitoringMode::Disabled);
+        }
+        self.monitoring_mode = monitoring_mode;
+    }
+
+    /// Returns the items that report their queued notifications whenever this item reports
+    pub fn triggered_items(&self) -> &BTreeSet<UInt32> {
+        &self.triggered_items
+    }
+
+    /// Links an item to this one so the item reports its queued notifications whenever this item
+    /// reports
+    pub fn add_triggered_item(&mut self, monitored_item_id: UInt32) {
+        self.triggered_items.insert(monitored_item_id);
+    }
+
+    /// Removes the link to an item, returning false if the item was not linked
+    pub fn remove_triggered_item(&mut self, monitored_item_id: UInt32) -> bool {
+        self.triggered_items.remove(&monitored_item_id)
+    }
+
+    /// Makes the item report its current value on the next sample whether it has changed or not.
+    /// Event items have no value to report.
+    pub fn send_initial_value(&mut self) {
+        if let FilterType::EventFilter(_) = self.filter {
+            return;
+        }
+        self.last_data_value = None;
+        if self.sampling_interval > 0f64 {
+            // Sample on the next tick rather than waiting for the interval to elapse
+            self.last_sample_time = self.last_sample_time - time::Duration::milliseconds(self.sampling_interval as i64);
+        }
+    }
+
     /// Called repeatedly on the monitored item.
     ///
     /// If the monitored item has a negative interval and subscription interval has elapsed,
     /// the value is tested immediately. Otherwise, the monitored items sampling interval is enforced
-    /// the subscriptions and controls the rate.
+    /// the subscriptions and controls the rate. An item with a sampling interval of zero reports
+    /// every value set on its variable since the last tick.
     ///
     /// Function returns true if a notification message was added to the queue
     pub fn tick(&mut self, address_space: &AddressSpace, now: &DateTimeUtc, reason: TickReason) -> bool {
+        if let FilterType::EventFilter(_) = self.filter {
+            return self.tick_events(address_space);
+        }
+
         let check_value = if self.sampling_interval > 0f64 {
             // Compare sample interval
             let sampling_interval = time::Duration::milliseconds(self.sampling_interval as i64);
@@ -112,6 +263,10 @@ impl MonitoredItem {
             self.last_data_value.is_none()
         };
 
+        if self.aggregator.is_some() {
+            return self.tick_aggregate(address_space, now, check_value);
+        }
+
         // Test the value (or don't)
         if !check_value {
             return false;
@@ -124,80 +279,205 @@ impl MonitoredItem {
 
         self.last_sample_time = *now;
 
+        let mut data_change = false;
+        for data_value in self.take_values(address_space, now) {
+            if self.report_data_change(data_value) {
+                data_change = true;
+            }
+        }
+        data_change
+    }
+
+    /// Compares the value with the last value, enqueueing a notification if it has changed
+    ///
+    /// Function returns true if a notification message was added to the queue
+    fn report_data_change(&mut self, mut data_value: DataValue) -> bool {
+        // Test for data change
+        let data_change = if self.last_data_value.is_none() {
+            // There is no previous check so yes it changed
+            trace!("No last data value so item has changed, node {:?}", self.item_to_monitor.node_id);
+            true
+        } else {
+            match self.filter {
+                FilterType::None => {
+                    data_value.value != self.last_data_value.as_ref().unwrap().value
+                }
+                FilterType::DataChangeFilter(ref filter) => {
+                    // Use filter to compare values
+                    !filter.compare(&data_value, self.last_data_value.as_ref().unwrap(), self.eu_range)
+                }
+                FilterType::EventFilter(_) | FilterType::AggregateFilter(_) => false
+            }
+        };
+        if data_change {
+            trace!("Data change on item -, node {:?}, data_value = {:?}", self.item_to_monitor.node_id, data_value);
+
+            // Store current data value to compare against on the next tick
+            self.last_data_value = Some(data_value.clone());
+
+            // Strip out timestamps that subscriber is not interested in
+            self.strip_timestamps(&mut data_value);
+
+            // Enqueue notification message
+            let client_handle = self.client_handle;
+            self.enqueue_notification_message(MonitoredItemNotification {
+                client_handle,
+                value: data_value,
+            });
+
+            trace!("Monitored item state = {:?}", self);
+        } else {
+            trace!("No data change on item, node {:?}", self.item_to_monitor.node_id);
+        }
+        data_change
+    }
+
+    /// Takes the values to compare with the last value, oldest first. An item listening for the
+    /// values set on its variable takes those set since the last tick, all of them if it samples as
+    /// fast as it can or aggregates them, otherwise just the latest. An item that has yet to see a
+    /// value reads it, and an item that is not listening samples it.
+    fn take_values(&self, address_space: &AddressSpace, now: &DateTimeUtc) -> Vec<DataValue> {
+        if let Some(ref value_listener) = self.value_listener {
+            let values = value_listener.take_values();
+            if self.last_data_value.is_none() {
+                // The value read now already includes the changes
+                self.sample_value(address_space).into_iter().collect()
+            } else if self.sampling_interval == 0f64 || self.aggregator.is_some() {
+                values.into_iter().map(|value| self.apply_index_range(value)).collect()
+            } else {
+                values.into_iter().last().map(|value| self.apply_index_range(value)).into_iter().collect()
+            }
+        } else if let Some(ref sampled_item) = self.sampled_item {
+            sampled_item.sample(address_space, now).into_iter().collect()
+        } else {
+            self.sample_value(address_space).into_iter().collect()
+        }
+    }
+
+    /// Reads the monitored attribute of the item's node, narrowed to the item's index range
+    fn sample_value(&self, address_space: &AddressSpace) -> Option<DataValue> {
         if let Some(node) = address_space.find_node(&self.item_to_monitor.node_id) {
             let node = node.as_node();
             let attribute_id = AttributeId::from_u32(self.item_to_monitor.attribute_id);
             if attribute_id.is_err() {
                 trace!("Item has no attribute_id {:?} so it hasn't changed, node {:?}", attribute_id, self.item_to_monitor.node_id);
-                return false;
+                return None;
             }
             let attribute_id = attribute_id.unwrap();
-            let data_value = node.find_attribute(attribute_id);
-            if let Some(mut data_value) = data_value {
-                // Test for data change
-                let data_change = if self.last_data_value.is_none() {
-                    // There is no previous check so yes it changed
-                    trace!("No last data value so item has changed, node {:?}", self.item_to_monitor.node_id);
-                    true
-                } else {
-                    match self.filter {
-                        FilterType::None => {
-                            data_value.value != self.last_data_value.as_ref().unwrap().value
-                        }
-                        FilterType::DataChangeFilter(ref filter) => {
-                            // Use filter to compare values
-                            !filter.compare(&data_value, self.last_data_value.as_ref().unwrap(), None)
-                        }
-                    }
-                };
-                if data_change {
-                    trace!("Data change on item -, node {:?}, data_value = {:?}", self.item_to_monitor.node_id, data_value);
-
-                    // Store current data value to compare against on the next tick
-                    self.last_data_value = Some(data_value.clone());
-
-                    // Strip out timestamps that subscriber is not interested in
-                    match self.timestamps_to_return {
-                        TimestampsToReturn::Neither => {
-                            data_value.source_timestamp = None;
-                            data_value.source_picoseconds = None;
-                            data_value.server_timestamp = None;
-                            data_value.server_picoseconds = None
-                        }
-                        TimestampsToReturn::Server => {
-                            data_value.source_timestamp = None;
-                            data_value.source_picoseconds = None;
-                        }
-                        TimestampsToReturn::Source => {
-                            data_value.server_timestamp = None;
-                            data_value.server_picoseconds = None
-                        }
-                        _ => {}
-                    }
+            node.find_attribute(attribute_id).map(|data_value| self.apply_index_range(data_value))
+        } else {
+            trace!("Can't find item to monitor, node {:?}", self.item_to_monitor.node_id);
+            None
+        }
+    }
 
-                    // Enqueue notification message
-                    let client_handle = self.client_handle;
-                    self.enqueue_notification_message(MonitoredItemNotification {
-                        client_handle,
-                        value: data_value,
-                    });
+    /// Strips out the timestamps that the subscriber is not interested in
+    fn strip_timestamps(&self, data_value: &mut DataValue) {
+        match self.timestamps_to_return {
+            TimestampsToReturn::Neither => {
+                data_value.source_timestamp = None;
+                data_value.source_picoseconds = None;
+                data_value.server_timestamp = None;
+                data_value.server_picoseconds = None
+            }
+            TimestampsToReturn::Server => {
+                data_value.source_timestamp = None;
+                data_value.source_picoseconds = None;
+            }
+            TimestampsToReturn::Source => {
+                data_value.server_timestamp = None;
+                data_value.server_picoseconds = None
+            }
+            _ => {}
+        }
+    }
 
-                    trace!("Monitored item state = {:?}", self);
-                } else {
-                    trace!("No data change on item, node {:?}", self.item_to_monitor.node_id);
+    /// Samples the value of an item with an aggregate filter, if it is time to, and enqueues the
+    /// aggregate of each processing interval that has ended.
+    ///
+    /// Function returns true if a notification message was added to the queue
+    fn tick_aggregate(&mut self, address_space: &AddressSpace, now: &DateTimeUtc, check_value: bool) -> bool {
+        if self.monitoring_mode == MonitoringMode::Disabled {
+            return false;
+        }
+        let sample_time = *now;
+        let now = DateTime::from(*now);
+        if check_value {
+            for mut data_value in self.take_values(address_space, &sample_time) {
+                // Values without a timestamp are taken to be from the time they were sampled
+                if data_value.source_timestamp.is_none() && data_value.server_timestamp.is_none() {
+                    data_value.source_timestamp = Some(now.clone());
                 }
-                data_change
+                self.last_data_value = Some(data_value.clone());
+                self.aggregator.as_mut().unwrap().add_value(data_value);
+            }
+        }
+        let aggregate_values = self.aggregator.as_mut().unwrap().completed_intervals(now.ticks());
+        let notified = !aggregate_values.is_empty();
+        for aggregate_value in aggregate_values {
+            let mut data_value = aggregate_value.into_data_value();
+            data_value.server_timestamp = Some(now.clone());
+            trace!("Aggregate on item, node {:?}, data_value = {:?}", self.item_to_monitor.node_id, data_value);
+            self.strip_timestamps(&mut data_value);
+            let client_handle = self.client_handle;
+            self.enqueue_notification_message(MonitoredItemNotification {
+                client_handle,
+                value: data_value,
+            });
+        }
+        notified
+    }
+
+    /// Delivers the events raised since the last tick whose source this item's node is a notifier
+    /// of. The fields chosen by the event filter of each event are enqueued.
+    ///
+    /// Function returns true if a notification message was added to the queue
+    fn tick_events(&mut self, address_space: &AddressSpace) -> bool {
+        let events = address_space.find_events_since(self.last_event_number);
+        if let Some(&(event_number, _)) = events.last() {
+            self.last_event_number = event_number;
+        }
+        if events.is_empty() || self.monitoring_mode == MonitoringMode::Disabled {
+            return false;
+        }
+
+        let event_field_lists: Vec<EventFieldList> = {
+            let node_id = &self.item_to_monitor.node_id;
+            // Clients can only subscribe to events from nodes whose event notifier allows it
+            let subscribe_to_events = match address_space.find_node(node_id).and_then(|node| node.as_node().find_attribute(AttributeId::EventNotifier)) {
+                Some(DataValue { value: Some(Variant::Byte(notifier)), .. }) => notifier & event_notifier::SUBSCRIBE_TO_EVENTS != 0,
+                _ => false
+            };
+            if !subscribe_to_events {
+                trace!("Node {:?} is not an event notifier that can be subscribed to", node_id);
+                return false;
+            }
+            if let FilterType::EventFilter(ref filter) = self.filter {
+                events.iter()
+                    .filter(|&&(_, event)| match event.subscription_id {
+                        // Events for one subscription only go to its items, all of which are told
+                        // when a refresh starts and ends
+                        Some(subscription_id) => subscription_id == self.subscription_id &&
+                            (is_refresh_event(event) || address_space.is_event_notifier_of(node_id, &event.source_node)),
+                        None => address_space.is_event_notifier_of(node_id, &event.source_node)
+                    })
+                    .filter_map(|&(_, event)| event_filter::evaluate(address_space, filter, self.client_handle, event))
+                    .collect()
             } else {
-                false
+                Vec::new()
             }
-        } else {
-            trace!("Can't find item to monitor, node {:?}", self.item_to_monitor.node_id);
-            false
+        };
+
+        let notified = !event_field_lists.is_empty();
+        for event_field_list in event_field_lists {
+            trace!("Event on item, node {:?}, event_fields = {:?}", self.item_to_monitor.node_id, event_field_list.event_fields);
+            self.enqueue_notification_message(event_field_list);
         }
+        notified
     }
 
     /// Enqueues a notification message for the monitored item
-    pub fn enqueue_notification_message(&mut self, notification: MonitoredItemNotification) {
+    pub fn enqueue_notification_message<T>(&mut self, notification: T) where T: Into<Notification> {
         // test for overflow
         self.queue_overflow = if self.notification_queue.len() == self.queue_size {
             trace!("Data change overflow, node {:?}", self.item_to_monitor.node_id);
@@ -215,11 +495,11 @@ impl MonitoredItem {
             false
         };
         // Add to end
-        self.notification_queue.push_back(notification);
+        self.notification_queue.push_back(notification.into());
     }
 
     /// Gets the oldest notification message from the notification queue
-    pub fn remove_first_notification_message(&mut self) -> Option<MonitoredItemNotification> {
+    pub fn remove_first_notification_message(&mut self) -> Option<Notification> {
         if self.notification_queue.is_empty() {
             None
         } else {
@@ -230,7 +510,7 @@ impl MonitoredItem {
     }
 
     /// Gets all the notification messages from the queue
-    pub fn remove_all_notification_messages(&mut self) -> Option<Vec<MonitoredItemNotification>> {
+    pub fn remove_all_notification_messages(&mut self) -> Option<Vec<Notification>> {
         if self.notification_queue.is_empty() {
             None
         } else {
@@ -242,7 +522,7 @@ impl MonitoredItem {
 
     /// Gets the last notification (and discards the remainder to prevent out of sequence events) from
     /// the notification queue.
-    pub fn remove_last_notification_message(&mut self) -> Option<MonitoredItemNotification> {
+    pub fn remove_last_notification_message(&mut self) -> Option<Notification> {
         let result = self.notification_queue.pop_back();
         if result.is_some() {
             self.queue_overflow = false;
@@ -251,12 +531,164 @@ impl MonitoredItem {
         result
     }
 
+    /// Narrows the sampled value down to the elements in the item's index range. If the range
+    /// selects nothing, the value is replaced by the status saying why.
+    fn apply_index_range(&self, data_value: DataValue) -> DataValue {
+        sampler::apply_index_range(&self.index_range, data_value)
+    }
+
+    /// Checks that the filter suits the attribute being monitored. Events are monitored through
+    /// the EventNotifier attribute with an event filter, and only that attribute takes one. The
+    /// result of validating the filter is returned for the client, whether it is valid or not.
+    fn validate_filter(item_to_monitor: &ReadValueId, filter: &FilterType) -> Result<ExtensionObject, (StatusCode, ExtensionObject)> {
+        let is_event_notifier = item_to_monitor.attribute_id == AttributeId::EventNotifier as UInt32;
+        match *filter {
+            FilterType::EventFilter(ref filter) => {
+                if !is_event_notifier {
+                    error!("Event filter is not allowed on attribute {}", item_to_monitor.attribute_id);
+                    Err((BadFilterNotAllowed, ExtensionObject::null()))
+                } else {
+                    match event_filter::validate(filter) {
+                        Ok(filter_result) => Ok(ExtensionObject::from_encodable(ObjectId::EventFilterResult_Encoding_DefaultBinary, filter_result)),
+                        Err((status_code, Some(filter_result))) => Err((status_code, ExtensionObject::from_encodable(ObjectId::EventFilterResult_Encoding_DefaultBinary, filter_result))),
+                        Err((status_code, None)) => Err((status_code, ExtensionObject::null()))
+                    }
+                }
+            }
+            FilterType::AggregateFilter(_) if item_to_monitor.attribute_id != AttributeId::Value as UInt32 => {
+                error!("Aggregate filter is not allowed on attribute {}", item_to_monitor.attribute_id);
+                Err((BadFilterNotAllowed, ExtensionObject::null()))
+            }
+            _ => {
+                if is_event_notifier {
+                    error!("EventNotifier attribute must be monitored with an event filter");
+                    Err((BadFilterNotAllowed, ExtensionObject::null()))
+                } else {
+                    Ok(ExtensionObject::null())
+                }
+            }
+        }
+    }
+
+    /// Checks the deadband of a data change filter and returns the engineering unit range of the
+    /// monitored variable, which is read from its EURange property. A percent deadband is only
+    /// allowed on a variable with a valid range.
+    fn validate_deadband(address_space: &AddressSpace, item_to_monitor: &ReadValueId, filter: &FilterType) -> Result<Option<(f64, f64)>, StatusCode> {
+        let eu_range = MonitoredItem::find_eu_range(address_space, &item_to_monitor.node_id);
+        if let FilterType::DataChangeFilter(ref filter) = *filter {
+            match filter.deadband_type {
+                0 => {}
+                1 if filter.deadband_value >= 0f64 => {}
+                2 if filter.deadband_value >= 0f64 && filter.deadband_value <= 100f64 => {
+                    match eu_range {
+                        Some((low, high)) if low < high => {}
+                        _ => {
+                            error!("Percent deadband is not allowed on node {:?} which has no valid EURange", item_to_monitor.node_id);
+                            return Err(BadDeadbandFilterInvalid);
+                        }
+                    }
+                }
+                _ => {
+                    error!("Deadband type {} with value {} is invalid", filter.deadband_type, filter.deadband_value);
+                    return Err(BadDeadbandFilterInvalid);
+                }
+            }
+        }
+        Ok(eu_range)
+    }
+
+    /// Finds the low and high of the EURange property of an analog item
+    fn find_eu_range(address_space: &AddressSpace, node_id: &NodeId) -> Option<(f64, f64)> {
+        let browse_name = QualifiedName::new(0, "EURange");
+        let references = address_space.find_references_from(node_id, Some((ReferenceTypeId::HasProperty, false)))?;
+        let property = references.iter()
+            .filter_map(|r| address_space.find_node(&r.node_id))
+            .find(|n| n.as_node().browse_name() == browse_name);
+        if let Some(&NodeType::Variable(ref variable)) = property {
+            if let Some(Variant::ExtensionObject(ref value)) = variable.value().value {
+                if let Ok(range) = value.decode_inner::<Range>() {
+                    return Some((range.low, range.high));
+                }
+            }
+        }
+        None
+    }
+
+    /// Listens for the values set on the item's variable if the item monitors its value and the
+    /// address space holds it
+    fn listen_for_value_changes(address_space: &AddressSpace, item_to_monitor: &ReadValueId, filter: &FilterType) -> Option<ValueChangeListener> {
+        if let FilterType::EventFilter(_) = *filter {
+            None
+        } else if item_to_monitor.attribute_id == AttributeId::Value as UInt32 {
+            address_space.listen_for_value_changes(&item_to_monitor.node_id)
+        } else {
+            None
+        }
+    }
+
+    /// Shares the samples of the item's attribute with the items that sample it at the same
+    /// interval. Items that deliver events do not sample. Items that listen for the values set on
+    /// their variable do not sample either, but are added so that the diagnostics of the sampling
+    /// interval count them.
+    fn add_sampled_item(address_space: &AddressSpace, item_to_monitor: &ReadValueId, filter: &FilterType, sampling_interval: Double) -> Option<SampledItem> {
+        if let FilterType::EventFilter(_) = *filter {
+            None
+        } else if let Ok(attribute_id) = AttributeId::from_u32(item_to_monitor.attribute_id) {
+            Some(address_space.add_sampled_item(&item_to_monitor.node_id, attribute_id, &item_to_monitor.index_range, sampling_interval))
+        } else {
+            None
+        }
+    }
+
+    /// Creates the aggregator of an item with an aggregate filter, with the result of revising the
+    /// filter for the client. The first interval starts now unless the filter says otherwise, and
+    /// intervals are at least as long as the sampling interval.
+    fn create_aggregator(address_space: &AddressSpace, item_to_monitor: &ReadValueId, filter: &FilterType, sampling_interval: Double) -> Result<Option<(IntervalAggregator, ExtensionObject)>, StatusCode> {
+        if let FilterType::AggregateFilter(ref filter) = *filter {
+            let stepped = aggregates::is_stepped(address_space, &item_to_monitor.node_id);
+            let calculator = AggregateCalculator::new(&filter.aggregate_type, &filter.aggregate_configuration, stepped)?;
+            let now = DateTime::now();
+            let start_time = if filter.start_time.ticks() <= 0 { now.clone() } else { filter.start_time.clone() };
+            if !filter.processing_interval.is_finite() {
+                error!("Aggregate filter processing interval {} is not a number of milliseconds", filter.processing_interval);
+                return Err(BadMonitoredItemFilterInvalid);
+            }
+            // The processing interval is revised to be no shorter than the sampling interval and
+            // within the range that the server supports
+            let min_processing_interval = if sampling_interval > constants::MIN_PROCESSING_INTERVAL { sampling_interval } else { constants::MIN_PROCESSING_INTERVAL };
+            let processing_interval = if filter.processing_interval < min_processing_interval {
+                min_processing_interval
+            } else if filter.processing_interval > constants::MAX_PROCESSING_INTERVAL {
+                constants::MAX_PROCESSING_INTERVAL
+            } else {
+                filter.processing_interval
+            };
+            let filter_result = AggregateFilterResult {
+                revised_start_time: start_time.clone(),
+                revised_processing_interval: processing_interval,
+                revised_aggregate_configuration: calculator.configuration().clone(),
+            };
+            let aggregator = IntervalAggregator::new(calculator, start_time.ticks(), processing_interval, now.ticks());
+            Ok(Some((aggregator, ExtensionObject::from_encodable(ObjectId::AggregateFilterResult_Encoding_DefaultBinary, filter_result))))
+        } else {
+            Ok(None)
+        }
+    }
+
     /// Takes the requested sampling interval value supplied by client and ensures it is within
     /// the range supported by the server
-    fn sanitize_sampling_interval(requested_sampling_interval: Double) -> Double {
-        if requested_sampling_interval < 0.0 {
+    fn sanitize_sampling_interval(filter: &FilterType, listening: bool, requested_sampling_interval: Double) -> Double {
+        if let FilterType::EventFilter(_) = *filter {
+            // Events are not sampled
+            0.0
+        } else if requested_sampling_interval.is_nan() {
+            constants::MIN_SAMPLING_INTERVAL
+        } else if requested_sampling_interval < 0.0 {
             // Defaults to the subscription's publishing interval
             -1.0
+        } else if requested_sampling_interval == 0.0 && listening {
+            // Every value set on the variable is reported
+            0.0
         } else if requested_sampling_interval == 0.0 || requested_sampling_interval < constants::MIN_SAMPLING_INTERVAL {
             constants::MIN_SAMPLING_INTERVAL
         } else {
@@ -265,8 +697,16 @@ impl MonitoredItem {
     }
 
     /// Takes the requested queue size and ensures it is within the range supported by the server
-    fn sanitize_queue_size(requested_queue_size: usize) -> usize {
-        if requested_queue_size == 0 {
+    fn sanitize_queue_size(filter: &FilterType, requested_queue_size: usize) -> usize {
+        if let FilterType::EventFilter(_) = *filter {
+            if requested_queue_size == 0 {
+                constants::DEFAULT_EVENT_QUEUE_SIZE
+            } else if requested_queue_size > constants::MAX_EVENT_QUEUE_SIZE {
+                constants::MAX_EVENT_QUEUE_SIZE
+            } else {
+                requested_queue_size
+            }
+        } else if requested_queue_size == 0 {
             constants::DEFAULT_DATA_CHANGE_QUEUE_SIZE
         } else if requested_queue_size == 1 {
             constants::MIN_DATA_CHANGE_QUEUE_SIZE
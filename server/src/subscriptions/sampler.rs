@@ -0,0 +1,243 @@
+//! Sampling. Monitored items that sample an attribute of a node, e.g. a value supplied by a getter,
+//! share each sample with the other items that sample the same part of the attribute at the same
+//! interval, whichever session or subscription they belong to. The attribute is read once per
+//! interval however many items sample it.
+
+use std;
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, Mutex, Weak};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use opcua_types::*;
+use opcua_types::status_codes::StatusCode::*;
+use opcua_types::service_types::SamplingIntervalDiagnosticsDataType;
+
+use constants;
+use DateTimeUtc;
+use address_space::address_space::AddressSpace;
+
+/// What is sampled. Items with the same key share their samples.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SamplingKey {
+    node_id: NodeId,
+    attribute_id: UInt32,
+    index_range: UAString,
+    /// The bits of the sampling interval since a float cannot be hashed
+    sampling_interval: u64,
+}
+
+/// The last sample of a key
+#[derive(Debug)]
+struct Sample {
+    sample_time: Option<DateTimeUtc>,
+    value: Option<DataValue>,
+}
+
+/// The sample of a key and the items sharing it
+struct SamplingEntry {
+    sample: Arc<Mutex<Sample>>,
+    /// Whether each item is enabled. Items that have been dropped no longer share the sample.
+    items: Vec<Weak<AtomicBool>>,
+}
+
+impl SamplingEntry {
+    /// Removes the items that have been dropped, returning the number of items left and the
+    /// number of them that are disabled
+    fn item_counts(&mut self) -> (usize, usize) {
+        self.items.retain(|item| item.upgrade().is_some());
+        let disabled = self.items.iter()
+            .filter_map(|item| item.upgrade())
+            .filter(|enabled| !enabled.load(Ordering::SeqCst))
+            .count();
+        (self.items.len(), disabled)
+    }
+}
+
+/// A monitored item's share of the samples of an attribute. The item stops sharing the samples
+/// when it and any clones of it are dropped.
+#[derive(Clone)]
+pub struct SampledItem {
+    node_id: NodeId,
+    attribute_id: AttributeId,
+    index_range: Option<NumericRange>,
+    /// Samples younger than this in MS are shared rather than the attribute being read again
+    max_age: i64,
+    sample: Arc<Mutex<Sample>>,
+    enabled: Arc<AtomicBool>,
+}
+
+impl Debug for SampledItem {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "SampledItem {{ node_id: {:?}, attribute_id: {:?}, max_age: {} }}", self.node_id, self.attribute_id, self.max_age)
+    }
+}
+
+impl PartialEq for SampledItem {
+    fn eq(&self, other: &SampledItem) -> bool {
+        Arc::ptr_eq(&self.enabled, &other.enabled)
+    }
+}
+
+impl SampledItem {
+    /// Returns the value of the attribute now, from the last sample if it is young enough or else
+    /// by reading the attribute.
+    pub fn sample(&self, address_space: &AddressSpace, now: &DateTimeUtc) -> Option<DataValue> {
+        let mut sample = self.sample.lock().unwrap();
+        let fresh = if let Some(ref sample_time) = sample.sample_time {
+            let age = now.signed_duration_since(*sample_time).num_milliseconds();
+            age >= 0 && age < self.max_age
+        } else {
+            false
+        };
+        if !fresh {
+            sample.value = address_space.find_node(&self.node_id)
+                .and_then(|node| node.as_node().find_attribute(self.attribute_id))
+                .map(|data_value| apply_index_range(&self.index_range, data_value));
+            sample.sample_time = Some(*now);
+        }
+        sample.value.clone()
+    }
+
+    /// Sets whether the item is enabled, which the diagnostics of its sampling interval count
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::SeqCst);
+    }
+}
+
+/// Narrows the value to the elements in the index range. If the range is not in the value, the
+/// value is replaced by the reason why.
+pub fn apply_index_range(index_range: &Option<NumericRange>, mut data_value: DataValue) -> DataValue {
+    if let Some(ref index_range) = *index_range {
+        let value = match data_value.value {
+            Some(ref value) => value.range_of(index_range),
+            None => Err(BadIndexRangeNoData)
+        };
+        match value {
+            Ok(value) => {
+                data_value.value = Some(value);
+            }
+            Err(status_code) => {
+                data_value.value = None;
+                data_value.status = Some(status_code);
+            }
+        }
+    }
+    data_value
+}
+
+/// Shares the samples of node attributes between the monitored items that sample them. The
+/// address space holds the server's sampler.
+pub struct Sampler {
+    entries: HashMap<SamplingKey, SamplingEntry>,
+    /// The most items that have sampled at each sampling interval that items sample at, keyed by
+    /// the bits of the interval
+    max_item_counts: HashMap<u64, UInt32>,
+}
+
+impl Sampler {
+    pub fn new() -> Sampler {
+        Sampler {
+            entries: HashMap::new(),
+            max_item_counts: HashMap::new(),
+        }
+    }
+
+    /// Adds an item that samples the part of the node's attribute in the index range at the
+    /// sampling interval, sharing samples with the items that sample the same.
+    pub fn add_item(&mut self, node_id: &NodeId, attribute_id: AttributeId, index_range: &UAString, sampling_interval: Double) -> SampledItem {
+        let key = Self::key(node_id, attribute_id, index_range, sampling_interval);
+        let enabled = Arc::new(AtomicBool::new(true));
+        let sample = {
+            let entry = self.entries.entry(key).or_insert_with(|| SamplingEntry {
+                sample: Arc::new(Mutex::new(Sample {
+                    sample_time: None,
+                    value: None,
+                })),
+                items: Vec::new(),
+            });
+            entry.items.push(Arc::downgrade(&enabled));
+            entry.sample.clone()
+        };
+
+        // Remember the most items that have sampled at the interval, forgetting the intervals that
+        // no item samples at any more
+        self.remove_unused_entries();
+        let item_count = self.interval_item_counts(sampling_interval).0 as UInt32;
+        let max_item_count = self.max_item_counts.entry(sampling_interval.to_bits()).or_insert(0);
+        *max_item_count = cmp::max(*max_item_count, item_count);
+
+        let max_age = if sampling_interval > 0f64 {
+            std::cmp::max(sampling_interval as i64, 1)
+        } else {
+            // Items sampling at the publishing interval of their subscription share samples taken
+            // in the same tick of the subscriptions
+            constants::SUBSCRIPTION_TIMER_RATE_MS
+        };
+        SampledItem {
+            node_id: node_id.clone(),
+            attribute_id,
+            index_range: NumericRange::from_index_range(index_range).unwrap_or(None),
+            max_age,
+            sample,
+            enabled,
+        }
+    }
+
+    /// Returns the number of items that sample the part of the node's attribute in the index
+    /// range at the sampling interval
+    pub fn item_count(&mut self, node_id: &NodeId, attribute_id: AttributeId, index_range: &UAString, sampling_interval: Double) -> usize {
+        let key = Self::key(node_id, attribute_id, index_range, sampling_interval);
+        self.entries.get_mut(&key).map_or(0, |entry| entry.item_counts().0)
+    }
+
+    /// Returns the diagnostics of each sampling interval that items sample at, shortest first
+    pub fn sampling_interval_diagnostics(&mut self) -> Vec<SamplingIntervalDiagnosticsDataType> {
+        let mut sampling_intervals: Vec<Double> = Vec::new();
+        self.remove_unused_entries();
+        for key in self.entries.keys() {
+            let sampling_interval = Double::from_bits(key.sampling_interval);
+            if !sampling_intervals.contains(&sampling_interval) {
+                sampling_intervals.push(sampling_interval);
+            }
+        }
+        sampling_intervals.sort_by(|a, b| a.partial_cmp(b).unwrap_or(cmp::Ordering::Equal));
+        sampling_intervals.into_iter().map(|sampling_interval| {
+            let (monitored_item_count, disabled_monitored_item_count) = self.interval_item_counts(sampling_interval);
+            let max_monitored_item_count = self.max_item_counts.get(&sampling_interval.to_bits()).cloned().unwrap_or(0);
+            SamplingIntervalDiagnosticsDataType {
+                sampling_interval,
+                monitored_item_count: monitored_item_count as UInt32,
+                max_monitored_item_count,
+                disabled_monitored_item_count: disabled_monitored_item_count as UInt32,
+            }
+        }).collect()
+    }
+
+    fn key(node_id: &NodeId, attribute_id: AttributeId, index_range: &UAString, sampling_interval: Double) -> SamplingKey {
+        SamplingKey {
+            node_id: node_id.clone(),
+            attribute_id: attribute_id as UInt32,
+            index_range: index_range.clone(),
+            sampling_interval: sampling_interval.to_bits(),
+        }
+    }
+
+    /// Returns the number of items that sample at the interval and the number of them disabled
+    fn interval_item_counts(&mut self, sampling_interval: Double) -> (usize, usize) {
+        let sampling_interval = sampling_interval.to_bits();
+        self.entries.iter_mut()
+            .filter(|&(key, _)| key.sampling_interval == sampling_interval)
+            .map(|(_, entry)| entry.item_counts())
+            .fold((0, 0), |(items, disabled), (entry_items, entry_disabled)| (items + entry_items, disabled + entry_disabled))
+    }
+
+    /// Removes the samples that no item shares any more, and the most items of the intervals that
+    /// no item samples at
+    fn remove_unused_entries(&mut self) {
+        self.entries.retain(|_, entry| entry.item_counts().0 > 0);
+        let entries = &self.entries;
+        self.max_item_counts.retain(|sampling_interval, _| entries.keys().any(|key| key.sampling_interval == *sampling_interval));
+    }
+}
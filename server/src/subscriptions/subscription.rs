@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::cmp;
+use std::collections::{BTreeSet, HashMap};
 use std::sync::{Arc, RwLock};
 
 use chrono;
@@ -7,11 +8,11 @@ use time;
 use opcua_types::*;
 use opcua_types::status_codes::StatusCode;
 use opcua_types::status_codes::StatusCode::*;
-use opcua_types::service_types::{TimestampsToReturn, NotificationMessage, MonitoredItemCreateRequest, MonitoredItemCreateResult, MonitoredItemModifyRequest, MonitoredItemModifyResult};
+use opcua_types::service_types::{TimestampsToReturn, NotificationMessage, MonitoredItemCreateRequest, MonitoredItemCreateResult, MonitoredItemModifyRequest, MonitoredItemModifyResult, MonitoredItemNotification, EventFieldList, SubscriptionDiagnosticsDataType};
 
 use constants;
 use DateTimeUtc;
-use subscriptions::monitored_item::MonitoredItem;
+use subscriptions::monitored_item::{MonitoredItem, Notification};
 use address_space::address_space::AddressSpace;
 use diagnostics::ServerDiagnostics;
 
@@ -61,6 +62,25 @@ pub enum TickReason {
     TickTimerFired,
 }
 
+/// Counts of what has happened to a subscription, reported through its diagnostics
+#[derive(Debug, Clone, Default)]
+pub struct SubscriptionCounters {
+    pub modify_count: UInt32,
+    pub enable_count: UInt32,
+    pub disable_count: UInt32,
+    pub republish_request_count: UInt32,
+    pub republish_message_request_count: UInt32,
+    pub republish_message_count: UInt32,
+    pub transfer_request_count: UInt32,
+    pub transferred_to_alt_client_count: UInt32,
+    pub transferred_to_same_client_count: UInt32,
+    pub publish_request_count: UInt32,
+    pub data_change_notifications_count: UInt32,
+    pub event_notifications_count: UInt32,
+    pub late_publish_request_count: UInt32,
+    pub discarded_message_count: UInt32,
+}
+
 #[derive(Debug, Clone)]
 pub struct Subscription {
     /// Subscription id
@@ -71,6 +91,9 @@ pub struct Subscription {
     pub max_lifetime_count: UInt32,
     /// Keep alive count enforced
     pub max_keep_alive_count: UInt32,
+    /// The most notifications that a notification message holds, or 0 for no limit. Notifications
+    /// beyond the limit are sent in further messages.
+    pub max_notifications_per_publish: UInt32,
     /// Relative priority of the subscription. When more than one subscriptio
     ///  needs to send notifications the highest priority subscription should
     /// be sent first.
@@ -92,6 +115,8 @@ pub struct Subscription {
     pub message_sent: bool,
     /// The parameter that requests publishing to be enabled or disabled.
     pub publishing_enabled: bool,
+    /// Counts reported through the subscription's diagnostics
+    pub counters: SubscriptionCounters,
     // The last monitored item id
     last_monitored_item_id: UInt32,
     // The time that the subscription interval last fired
@@ -108,7 +133,7 @@ impl Drop for Subscription {
 }
 
 impl Subscription {
-    pub fn new(diagnostics: Arc<RwLock<ServerDiagnostics>>, subscription_id: UInt32, publishing_enabled: bool, publishing_interval: Double, lifetime_count: UInt32, keep_alive_count: UInt32, priority: Byte) -> Subscription {
+    pub fn new(diagnostics: Arc<RwLock<ServerDiagnostics>>, subscription_id: UInt32, publishing_enabled: bool, publishing_interval: Double, lifetime_count: UInt32, keep_alive_count: UInt32, max_notifications_per_publish: UInt32, priority: Byte) -> Subscription {
         let subscription = Subscription {
             subscription_id,
             publishing_interval,
@@ -116,12 +141,14 @@ impl Subscription {
             monitored_items: HashMap::with_capacity(constants::DEFAULT_MONITORED_ITEM_CAPACITY),
             max_lifetime_count: lifetime_count,
             max_keep_alive_count: keep_alive_count,
+            max_notifications_per_publish,
             // State variables
             state: SubscriptionState::Creating,
             lifetime_counter: lifetime_count,
             keep_alive_counter: keep_alive_count,
             message_sent: false,
             publishing_enabled,
+            counters: SubscriptionCounters::default(),
             // Counters for new items
             last_monitored_item_id: 0,
             last_timer_expired_time: chrono::Utc::now(),
@@ -135,7 +162,7 @@ impl Subscription {
     }
 
     /// Creates monitored items on the specified subscription, returning the creation results
-    pub fn create_monitored_items(&mut self, timestamps_to_return: TimestampsToReturn, items_to_create: &[MonitoredItemCreateRequest]) -> Vec<MonitoredItemCreateResult> {
+    pub fn create_monitored_items(&mut self, address_space: &AddressSpace, timestamps_to_return: TimestampsToReturn, items_to_create: &[MonitoredItemCreateRequest]) -> Vec<MonitoredItemCreateResult> {
         let mut results = Vec::with_capacity(items_to_create.len());
         // Add items to the subscription if they're not already in its
         for item_to_create in items_to_create {
@@ -143,27 +170,31 @@ impl Subscription {
             // Process items to create here
             let monitored_item_id = self.last_monitored_item_id;
             // Create a monitored item, if possible
-            let monitored_item = MonitoredItem::new(monitored_item_id, timestamps_to_return, item_to_create);
-            let result = if let Ok(monitored_item) = monitored_item {
-                // Return the status
-                let result = MonitoredItemCreateResult {
-                    status_code: Good,
-                    monitored_item_id,
-                    revised_sampling_interval: monitored_item.sampling_interval,
-                    revised_queue_size: monitored_item.queue_size as UInt32,
-                    filter_result: ExtensionObject::null(),
-                };
-                // Register the item with the subscription
-                self.monitored_items.insert(monitored_item_id, monitored_item);
-                result
-            } else {
-                // Monitored item couldn't be created
-                MonitoredItemCreateResult {
-                    status_code: monitored_item.unwrap_err(),
-                    monitored_item_id,
-                    revised_sampling_interval: 0f64,
-                    revised_queue_size: 0,
-                    filter_result: ExtensionObject::null(),
+            let monitored_item = MonitoredItem::new(address_space, monitored_item_id, timestamps_to_return, item_to_create);
+            let result = match monitored_item {
+                Ok(mut monitored_item) => {
+                    monitored_item.set_subscription_id(self.subscription_id);
+                    // Return the status
+                    let result = MonitoredItemCreateResult {
+                        status_code: Good,
+                        monitored_item_id,
+                        revised_sampling_interval: monitored_item.sampling_interval,
+                        revised_queue_size: monitored_item.queue_size as UInt32,
+                        filter_result: monitored_item.filter_result.clone(),
+                    };
+                    // Register the item with the subscription
+                    self.monitored_items.insert(monitored_item_id, monitored_item);
+                    result
+                }
+                Err((status_code, filter_result)) => {
+                    // Monitored item couldn't be created
+                    MonitoredItemCreateResult {
+                        status_code,
+                        monitored_item_id,
+                        revised_sampling_interval: 0f64,
+                        revised_queue_size: 0,
+                        filter_result,
+                    }
                 }
             };
             results.push(result);
@@ -172,30 +203,25 @@ impl Subscription {
     }
 
     /// Modify the specified monitored items, returning a result for each
-    pub fn modify_monitored_items(&mut self, timestamps_to_return: TimestampsToReturn, items_to_modify: &[MonitoredItemModifyRequest]) -> Vec<MonitoredItemModifyResult> {
+    pub fn modify_monitored_items(&mut self, address_space: &AddressSpace, timestamps_to_return: TimestampsToReturn, items_to_modify: &[MonitoredItemModifyRequest]) -> Vec<MonitoredItemModifyResult> {
         let mut result = Vec::with_capacity(items_to_modify.len());
         for item_to_modify in items_to_modify {
             let monitored_item = self.monitored_items.get_mut(&item_to_modify.monitored_item_id);
             if let Some(monitored_item) = monitored_item {
-                // Skip items not being reported
-                if monitored_item.monitoring_mode != MonitoringMode::Reporting {
-                    continue;
-                }
                 // Try to change the monitored item according to the modify request
-                let modify_result = monitored_item.modify(timestamps_to_return, item_to_modify);
-                result.push(if modify_result.is_ok() {
-                    MonitoredItemModifyResult {
+                let modify_result = monitored_item.modify(address_space, timestamps_to_return, item_to_modify);
+                result.push(match modify_result {
+                    Ok(_) => MonitoredItemModifyResult {
                         status_code: Good,
                         revised_sampling_interval: monitored_item.sampling_interval,
                         revised_queue_size: monitored_item.queue_size as UInt32,
-                        filter_result: ExtensionObject::null(),
-                    }
-                } else {
-                    MonitoredItemModifyResult {
-                        status_code: modify_result.unwrap_err(),
+                        filter_result: monitored_item.filter_result.clone(),
+                    },
+                    Err((status_code, filter_result)) => MonitoredItemModifyResult {
+                        status_code,
                         revised_sampling_interval: 0f64,
                         revised_queue_size: 0,
-                        filter_result: ExtensionObject::null(),
+                        filter_result,
                     }
                 });
             } else {
@@ -213,16 +239,110 @@ impl Subscription {
 
     /// Delete the specified monitored items (by item id), returning a status code for each
     pub fn delete_monitored_items(&mut self, items_to_delete: &[UInt32]) -> Vec<StatusCode> {
-        items_to_delete.iter().map(|item_to_delete| {
+        let results = items_to_delete.iter().map(|item_to_delete| {
             // Remove the item (or report an error with the id)
             let removed = self.monitored_items.remove(item_to_delete);
             if removed.is_some() { Good } else { BadMonitoredItemIdInvalid }
+        }).collect();
+        // Links from triggering items to the deleted items go too
+        self.monitored_items.values_mut().for_each(|monitored_item| {
+            items_to_delete.iter().for_each(|item_to_delete| {
+                let _ = monitored_item.remove_triggered_item(*item_to_delete);
+            });
+        });
+        results
+    }
+
+    /// Sets the monitoring mode of the specified monitored items, returning a status code for each
+    pub fn set_monitoring_mode(&mut self, monitoring_mode: MonitoringMode, monitored_item_ids: &[UInt32]) -> Vec<StatusCode> {
+        monitored_item_ids.iter().map(|monitored_item_id| {
+            if let Some(monitored_item) = self.monitored_items.get_mut(monitored_item_id) {
+                monitored_item.set_monitoring_mode(monitoring_mode);
+                Good
+            } else {
+                BadMonitoredItemIdInvalid
+            }
         }).collect()
     }
 
+    /// Adds and removes links from the triggering item to the items it triggers, returning a status
+    /// code for each link to add and each link to remove. Returns `BadMonitoredItemIdInvalid` if
+    /// the triggering item does not exist.
+    pub fn set_triggering(&mut self, triggering_item_id: UInt32, links_to_add: &[UInt32], links_to_remove: &[UInt32]) -> Result<(Vec<StatusCode>, Vec<StatusCode>), StatusCode> {
+        if !self.monitored_items.contains_key(&triggering_item_id) {
+            return Err(BadMonitoredItemIdInvalid);
+        }
+        // Links can only be made to items that exist
+        let add_results: Vec<StatusCode> = links_to_add.iter().map(|monitored_item_id| {
+            if self.monitored_items.contains_key(monitored_item_id) { Good } else { BadMonitoredItemIdInvalid }
+        }).collect();
+        let triggering_item = self.monitored_items.get_mut(&triggering_item_id).unwrap();
+        let remove_results = links_to_remove.iter().map(|monitored_item_id| {
+            if triggering_item.remove_triggered_item(*monitored_item_id) { Good } else { BadMonitoredItemIdInvalid }
+        }).collect();
+        links_to_add.iter().zip(add_results.iter()).for_each(|(monitored_item_id, result)| {
+            if result.is_good() {
+                triggering_item.add_triggered_item(*monitored_item_id);
+            }
+        });
+        Ok((add_results, remove_results))
+    }
+
+    /// Makes the monitored items report their current values on their next sample whether they
+    /// have changed or not, e.g. after the subscription is transferred to another session
+    pub fn send_initial_values(&mut self) {
+        self.monitored_items.values_mut().for_each(|monitored_item| monitored_item.send_initial_value());
+    }
+
+    /// Returns the diagnostics of the subscription. The caller fills in what the subscription
+    /// does not know, i.e. the session and the state of the notification queues.
+    pub fn diagnostics(&self) -> SubscriptionDiagnosticsDataType {
+        let counters = &self.counters;
+        let disabled_monitored_item_count = self.monitored_items.values()
+            .filter(|monitored_item| monitored_item.monitoring_mode == MonitoringMode::Disabled)
+            .count();
+        SubscriptionDiagnosticsDataType {
+            session_id: NodeId::null(),
+            subscription_id: self.subscription_id,
+            priority: self.priority,
+            publishing_interval: self.publishing_interval,
+            max_keep_alive_count: self.max_keep_alive_count,
+            max_lifetime_count: self.max_lifetime_count,
+            max_notifications_per_publish: self.max_notifications_per_publish,
+            publishing_enabled: self.publishing_enabled,
+            modify_count: counters.modify_count,
+            enable_count: counters.enable_count,
+            disable_count: counters.disable_count,
+            republish_request_count: counters.republish_request_count,
+            republish_message_request_count: counters.republish_message_request_count,
+            republish_message_count: counters.republish_message_count,
+            transfer_request_count: counters.transfer_request_count,
+            transferred_to_alt_client_count: counters.transferred_to_alt_client_count,
+            transferred_to_same_client_count: counters.transferred_to_same_client_count,
+            publish_request_count: counters.publish_request_count,
+            data_change_notifications_count: counters.data_change_notifications_count,
+            event_notifications_count: counters.event_notifications_count,
+            notifications_count: counters.data_change_notifications_count + counters.event_notifications_count,
+            late_publish_request_count: counters.late_publish_request_count,
+            current_keep_alive_count: self.keep_alive_counter,
+            current_lifetime_count: self.lifetime_counter,
+            unacknowledged_message_count: 0,
+            discarded_message_count: counters.discarded_message_count,
+            monitored_item_count: self.monitored_items.len() as UInt32,
+            disabled_monitored_item_count: disabled_monitored_item_count as UInt32,
+            monitoring_queue_overflow_count: 0,
+            next_sequence_number: 0,
+            event_queue_over_flow_count: 0,
+        }
+    }
+
     /// Checks the subscription and monitored items for state change, messages. If the tick does
-    /// nothing, the function returns None. Otherwise it returns one or more messages in an Vec.
-    pub fn tick(&mut self, address_space: &AddressSpace, tick_reason: TickReason, publishing_req_queued: bool, now: &DateTimeUtc) -> Option<NotificationMessage> {
+    /// nothing, the function returns an empty Vec. Otherwise it returns one or more messages in a
+    /// Vec, more than one if the notifications exceed the maximum per publish.
+    ///
+    /// When the lifetime of the subscription expires, the subscription is closed and its last
+    /// message tells the client so with a `BadTimeout` status change.
+    pub fn tick(&mut self, address_space: &AddressSpace, tick_reason: TickReason, publishing_req_queued: bool, now: &DateTimeUtc) -> Vec<NotificationMessage> {
         // Check if the publishing interval has elapsed. Only checks on the tick timer.
         let publishing_interval_elapsed = match tick_reason {
             TickReason::ReceivedPublishRequest => false,
@@ -244,12 +364,13 @@ impl Subscription {
         // Do a tick on monitored items. Note that monitored items normally update when the interval
         // elapses but they don't have to. So this is called every tick just to catch items with their
         // own intervals.
-        let (notification_message, more_notifications) = self.tick_monitored_items(address_space, now, tick_reason);
+        let notification_messages = self.tick_monitored_items(address_space, now, tick_reason);
 
         // If items have changed or subscription interval elapsed then we may have notifications
         // to send or state to update
-        let result = if notification_message.is_some() || publishing_interval_elapsed || publishing_req_queued {
-            let notifications_available = notification_message.is_some();
+        let mut result = if !notification_messages.is_empty() || publishing_interval_elapsed || publishing_req_queued {
+            let notifications_available = !notification_messages.is_empty();
+            let more_notifications = notification_messages.len() > 1;
 
             let subscription_state_params = SubscriptionStateParams {
                 publishing_req_queued,
@@ -263,36 +384,39 @@ impl Subscription {
 
             match update_state_result.update_state_action {
                 UpdateStateAction::None => {
-                    if notification_message.is_some() {
+                    if !notification_messages.is_empty() {
                         trace!("Notification message was being discarded for a do-nothing");
                     }
                     // Send nothing
-                    None
+                    Vec::new()
                 }
                 UpdateStateAction::ReturnKeepAlive => {
-                    if notification_message.is_some() {
+                    if !notification_messages.is_empty() {
                         trace!("Notification message was being discarded for a keep alive");
                     }
                     // Send a keep alive
-                    Some(NotificationMessage {
+                    vec![NotificationMessage {
                         sequence_number: 0,
                         publish_time: DateTime::from(now.clone()),
                         notification_data: None,
-                    })
+                    }]
                 }
                 UpdateStateAction::ReturnNotifications => {
-                    // Send the notification message
-                    notification_message
+                    // Send the notification messages
+                    notification_messages
                 }
             }
         } else {
-            None
+            Vec::new()
         };
 
         // Check if the subscription interval has been exceeded since last call
         if self.lifetime_counter == 1 {
-            info!("Subscription {} has expired and will be removed shortly", self.subscription_id);
+            info!("Subscription {} has expired and will be removed", self.subscription_id);
             self.state = SubscriptionState::Closed;
+            // State #27 - the monitored items are deleted and the client is told why
+            self.monitored_items.clear();
+            result.push(NotificationMessage::status_change(0, DateTime::from(now.clone()), BadTimeout));
         }
 
         result
@@ -301,22 +425,71 @@ impl Subscription {
     /// Iterate through the monitored items belonging to the subscription, calling tick on each in turn.
     /// The function returns true if any of the monitored items due to the subscription interval
     /// elapsing, or their own interval elapsing.
-    fn tick_monitored_items(&mut self, address_space: &AddressSpace, now: &DateTimeUtc, tick_reason: TickReason) -> (Option<NotificationMessage>, bool) {
+    ///
+    /// Items in sampling mode hold on to their notifications until an item that triggers them
+    /// reports.
+    fn tick_monitored_items(&mut self, address_space: &AddressSpace, now: &DateTimeUtc, tick_reason: TickReason) -> Vec<NotificationMessage> {
         let mut monitored_item_notifications = Vec::new();
+        let mut event_notifications = Vec::new();
+        let mut triggered_items = BTreeSet::new();
         for (_, monitored_item) in &mut self.monitored_items {
-            if monitored_item.tick(address_space, now, tick_reason) {
-                // Take the monitored item's first notification
-                if let Some(mut notification_messages) = monitored_item.remove_all_notification_messages() {
-                    monitored_item_notifications.append(&mut notification_messages);
+            let _ = monitored_item.tick(address_space, now, tick_reason);
+            if monitored_item.monitoring_mode == MonitoringMode::Reporting {
+                // Take the monitored item's notifications
+                if let Some(notification_messages) = monitored_item.remove_all_notification_messages() {
+                    Self::sort_notifications(notification_messages, &mut monitored_item_notifications, &mut event_notifications);
+                    triggered_items.extend(monitored_item.triggered_items().iter().cloned());
                 }
             }
         }
-        if !monitored_item_notifications.is_empty() {
-            // Create a notification message and push it onto the queue
-            let notification = NotificationMessage::new_data_change(0, DateTime::now(), monitored_item_notifications);
-            (Some(notification), false)
+        // Items triggered by the items that reported, report what they have sampled
+        for monitored_item_id in triggered_items {
+            if let Some(monitored_item) = self.monitored_items.get_mut(&monitored_item_id) {
+                if monitored_item.monitoring_mode == MonitoringMode::Sampling {
+                    if let Some(notification_messages) = monitored_item.remove_all_notification_messages() {
+                        Self::sort_notifications(notification_messages, &mut monitored_item_notifications, &mut event_notifications);
+                    }
+                }
+            }
+        }
+        if !monitored_item_notifications.is_empty() || !event_notifications.is_empty() {
+            self.counters.data_change_notifications_count += monitored_item_notifications.len() as UInt32;
+            self.counters.event_notifications_count += event_notifications.len() as UInt32;
+            // Create the notification messages to push onto the queue
+            self.make_notification_messages(monitored_item_notifications, event_notifications)
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Puts the notifications into as many notification messages as it takes to hold no more than
+    /// the maximum notifications per publish in each. Data changes come before events.
+    fn make_notification_messages(&self, mut monitored_item_notifications: Vec<MonitoredItemNotification>, mut event_notifications: Vec<EventFieldList>) -> Vec<NotificationMessage> {
+        let max_notifications = if self.max_notifications_per_publish == 0 {
+            usize::max_value()
         } else {
-            (None, false)
+            self.max_notifications_per_publish as usize
+        };
+        let mut notification_messages = Vec::new();
+        while !monitored_item_notifications.is_empty() || !event_notifications.is_empty() {
+            let data_change_count = cmp::min(max_notifications, monitored_item_notifications.len());
+            let event_count = cmp::min(max_notifications - data_change_count, event_notifications.len());
+            let more_monitored_item_notifications = monitored_item_notifications.split_off(data_change_count);
+            let more_event_notifications = event_notifications.split_off(event_count);
+            notification_messages.push(NotificationMessage::new(0, DateTime::now(), monitored_item_notifications, event_notifications));
+            monitored_item_notifications = more_monitored_item_notifications;
+            event_notifications = more_event_notifications;
+        }
+        notification_messages
+    }
+
+    /// Sorts the notifications of a monitored item into data changes and events
+    fn sort_notifications(notifications: Vec<Notification>, monitored_item_notifications: &mut Vec<MonitoredItemNotification>, event_notifications: &mut Vec<EventFieldList>) {
+        for notification in notifications {
+            match notification {
+                Notification::MonitoredItemNotification(n) => monitored_item_notifications.push(n),
+                Notification::EventFieldList(n) => event_notifications.push(n),
+            }
         }
     }
 
@@ -434,12 +607,14 @@ impl Subscription {
                 if tick_reason == TickReason::ReceivedPublishRequest {
                     if self.publishing_enabled && (p.notifications_available || p.more_notifications) {
                         // State #10
+                        self.counters.late_publish_request_count += 1;
                         self.reset_lifetime_counter();
                         self.state = SubscriptionState::Normal;
                         self.message_sent = true;
                         return UpdateStateResult::new(10, UpdateStateAction::ReturnNotifications);
                     } else if !self.publishing_enabled || (self.publishing_enabled && !p.notifications_available && !p.more_notifications) {
                         // State #11
+                        self.counters.late_publish_request_count += 1;
                         self.reset_lifetime_counter();
                         self.state = SubscriptionState::KeepAlive;
                         self.message_sent = true;
@@ -486,9 +661,8 @@ impl Subscription {
             SubscriptionState::Normal | SubscriptionState::Late | SubscriptionState::KeepAlive => {
                 if self.lifetime_counter == 1 {
                     // State #27
-                    // TODO
-                    // delete monitored items
-                    // issue_status_change_notification
+                    // Handled at the end of tick() which deletes the monitored items and issues
+                    // the status change notification
                 }
             }
             _ => {
@@ -1,7 +1,7 @@
 use address_space::types::AddressSpace;
 use DateTimeUtc;
 use opcua_types::*;
-use opcua_types::service_types::{NotificationMessage, PublishRequest, PublishResponse, ResponseHeader, ServiceFault};
+use opcua_types::service_types::{NotificationMessage, PublishRequest, PublishResponse, ResponseHeader, ServiceFault, SubscriptionDiagnosticsDataType};
 use opcua_types::status_codes::StatusCode;
 use opcua_types::status_codes::StatusCode::*;
 use std::collections::{BTreeMap, VecDeque};
@@ -33,6 +33,29 @@ impl SequenceNumber {
         }
         self.last_number
     }
+
+    /// Returns the number that the next call to `next_number` will return
+    pub fn peek_next_number(&self) -> UInt32 {
+        if self.last_number >= Self::SEQUENCE_NUMBER_WRAPAROUND { 1 } else { self.last_number + 1 }
+    }
+
+    /// Ensures the numbers returned from now on follow the specified number, e.g. one carried by a
+    /// notification from another session
+    pub fn advance_past(&mut self, number: UInt32) {
+        if number > self.last_number && number <= Self::SEQUENCE_NUMBER_WRAPAROUND {
+            self.last_number = number;
+        }
+    }
+}
+
+/// A subscription on its way from one session to another, with the notifications that the client
+/// has not received or acknowledged yet.
+pub struct TransferredSubscription {
+    pub subscription: Subscription,
+    /// Notifications waiting to be sent, oldest first
+    pub unsent: Vec<NotificationMessage>,
+    /// Notifications sent but not acknowledged, in sequence number order
+    pub unacknowledged: Vec<NotificationMessage>,
 }
 
 
@@ -133,6 +156,12 @@ impl Subscriptions {
         self.subscriptions.is_empty()
     }
 
+    /// Tests if notifications are waiting for publish requests, e.g. the status change of a
+    /// subscription whose lifetime has expired
+    pub fn has_unsent_notifications(&self) -> bool {
+        !self.transmission_queue.is_empty()
+    }
+
     pub fn len(&self) -> usize {
         self.subscriptions.len()
     }
@@ -157,14 +186,96 @@ impl Subscriptions {
         self.subscriptions.get_mut(&subscription_id)
     }
 
+    /// Returns the diagnostics of the subscriptions, which belong to the session with the id
+    pub fn subscription_diagnostics(&self, session_id: &NodeId) -> Vec<SubscriptionDiagnosticsDataType> {
+        let next_sequence_number = self.sequence_number.peek_next_number();
+        self.subscriptions.values().map(|subscription| {
+            let subscription_id = subscription.subscription_id;
+            let mut diagnostics = subscription.diagnostics();
+            diagnostics.session_id = session_id.clone();
+            diagnostics.unacknowledged_message_count = self.retransmission_queue.values().filter(|v| v.0 == subscription_id).count() as UInt32;
+            diagnostics.next_sequence_number = next_sequence_number;
+            diagnostics
+        }).collect()
+    }
+
+    /// Takes a subscription out of this session along with its notifications that are waiting to
+    /// be sent or acknowledged, so it can be transferred to another session. The client of this
+    /// session is told that the subscription has been transferred by its next publish response.
+    pub fn take_subscription(&mut self, subscription_id: UInt32) -> Option<TransferredSubscription> {
+        if let Some(subscription) = self.subscriptions.remove(&subscription_id) {
+            // The transmission queue holds the oldest notifications at the back
+            let mut unsent = Vec::new();
+            let mut transmission_queue = VecDeque::with_capacity(self.transmission_queue.len());
+            while let Some((id, notification_message)) = self.transmission_queue.pop_back() {
+                if id == subscription_id {
+                    unsent.push(notification_message);
+                } else {
+                    transmission_queue.push_front((id, notification_message));
+                }
+            }
+            self.transmission_queue = transmission_queue;
+
+            let sequence_numbers: Vec<UInt32> = self.retransmission_queue.iter()
+                .filter(|&(_, v)| v.0 == subscription_id)
+                .map(|(k, _)| *k)
+                .collect();
+            let unacknowledged = sequence_numbers.iter()
+                .map(|sequence_number| self.retransmission_queue.remove(sequence_number).unwrap().1)
+                .collect();
+
+            let status_change = NotificationMessage::status_change(self.sequence_number.next_number(), DateTime::now(), GoodSubscriptionTransferred);
+            self.transmission_queue.push_front((subscription_id, status_change));
+
+            Some(TransferredSubscription {
+                subscription,
+                unsent,
+                unacknowledged,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Adds a subscription transferred from another session. Its unacknowledged notifications keep
+    /// their sequence numbers where possible so the client can still republish them. Returns the
+    /// sequence numbers of the notifications that are available for republishing.
+    pub fn adopt_subscription(&mut self, transferred_subscription: TransferredSubscription) -> Vec<UInt32> {
+        let TransferredSubscription { subscription, unsent, unacknowledged } = transferred_subscription;
+        let subscription_id = subscription.subscription_id;
+
+        // Numbers handed out by this session from now on must not clash with the transferred ones
+        if let Some(notification_message) = unacknowledged.last() {
+            self.sequence_number.advance_past(notification_message.sequence_number);
+        }
+
+        let mut available_sequence_numbers = Vec::with_capacity(unacknowledged.len());
+        for mut notification_message in unacknowledged {
+            if self.retransmission_queue.contains_key(&notification_message.sequence_number) {
+                notification_message.sequence_number = self.sequence_number.next_number();
+            }
+            available_sequence_numbers.push(notification_message.sequence_number);
+            self.retransmission_queue.insert(notification_message.sequence_number, (subscription_id, notification_message));
+        }
+
+        // Unsent notifications are numbered as if this session had produced them
+        for mut notification_message in unsent {
+            notification_message.sequence_number = self.sequence_number.next_number();
+            self.transmission_queue.push_front((subscription_id, notification_message));
+        }
+
+        self.subscriptions.insert(subscription_id, subscription);
+        available_sequence_numbers
+    }
+
     /// The tick causes the subscription manager to iterate through individual subscriptions calling tick
-    /// on each in order of priority. In each case this could generate data change notifications. Data change
-    /// notifications will be attached to the next available publish response and queued for sending
-    /// to the client.
+    /// on each in order of priority, highest first. In each case this could generate data change
+    /// notifications. Data change notifications will be attached to the next available publish response
+    /// and queued for sending to the client. Subscriptions whose lifetime has expired are removed.
     pub fn tick(&mut self, now: &DateTimeUtc, address_space: &AddressSpace, tick_reason: TickReason) -> Result<(), StatusCode> {
         let subscription_ids = {
             let mut subscription_priority: Vec<(u32, u8)> = self.subscriptions.values().map(|v| (v.subscription_id, v.priority)).collect();
-            subscription_priority.sort_by(|s1, s2| s1.1.cmp(&s2.1));
+            subscription_priority.sort_by(|s1, s2| s2.1.cmp(&s1.1));
             subscription_priority.iter().map(|s| s.0).collect::<Vec<u32>>()
         };
 
@@ -183,15 +294,16 @@ impl Subscriptions {
                 // Subscription is dead so remove it
                 self.subscriptions.remove(&subscription_id);
             } else {
-                let notification_message = {
+                let (notification_messages, expired) = {
                     let publishing_req_queued = publish_request_len > 0;
                     let subscription = self.subscriptions.get_mut(subscription_id).unwrap();
                     // Now tick the subscription to see if it has any notifications. If there are
                     // notifications then the publish response will be associated with his subscription
                     // and ready to go.
-                    subscription.tick(address_space, tick_reason, publishing_req_queued, now)
+                    let notification_messages = subscription.tick(address_space, tick_reason, publishing_req_queued, now);
+                    (notification_messages, subscription.state == SubscriptionState::Closed)
                 };
-                if let Some(mut notification_message) = notification_message {
+                for mut notification_message in notification_messages {
                     debug!("Subscription {} produced a notification message", subscription_id);
                     // Give the notification message a sequence number
                     notification_message.sequence_number = self.sequence_number.next_number();
@@ -201,6 +313,11 @@ impl Subscriptions {
                         publish_request_len -= 1;
                     }
                 }
+                if expired {
+                    // The status change notification outlives the subscription so the client can
+                    // learn what happened to it
+                    self.subscriptions.remove(subscription_id);
+                }
             }
         });
 
@@ -211,8 +328,14 @@ impl Subscriptions {
             debug!("Pairing a notification from the transmission queue to a publish request");
             let publish_request = self.publish_request_queue.pop_back().unwrap();
 
-            // Get the oldest notification to send
-            let (subscription_id, notification_message) = self.transmission_queue.pop_back().unwrap();
+            // Get the next notification to send
+            let (subscription_id, notification_message) = self.take_next_notification().unwrap();
+            let subscription_exists = if let Some(subscription) = self.subscriptions.get_mut(&subscription_id) {
+                subscription.counters.publish_request_count += 1;
+                true
+            } else {
+                false
+            };
 
             // Search the transmission queue for more notifications from this same subscription
             let more_notifications = self.more_notifications(subscription_id);
@@ -220,8 +343,11 @@ impl Subscriptions {
             // Get a list of available sequence numbers
             let available_sequence_numbers = self.available_sequence_numbers(subscription_id);
 
-            // The notification to be sent is now put into the retransmission queue
-            self.retransmission_queue.insert(notification_message.sequence_number, (subscription_id, notification_message.clone()));
+            // The notification to be sent is now put into the retransmission queue, unless its
+            // subscription has gone and it can no longer be republished
+            if subscription_exists {
+                self.retransmission_queue.insert(notification_message.sequence_number, (subscription_id, notification_message.clone()));
+            }
 
             let response = self.make_publish_response(&publish_request, subscription_id, now, notification_message, more_notifications, available_sequence_numbers);
             self.publish_response_queue.push_front(response);
@@ -305,6 +431,25 @@ impl Subscriptions {
         }
     }
 
+    /// Takes the next notification to send from the transmission queue. That is the oldest
+    /// notification of the subscription with the highest priority, or of the subscription that
+    /// has been waiting longest when their priorities are the same.
+    fn take_next_notification(&mut self) -> Option<(UInt32, NotificationMessage)> {
+        let next = {
+            let subscriptions = &self.subscriptions;
+            // The oldest notifications are at the back of the queue. Subscriptions that have gone
+            // have no priority.
+            self.transmission_queue.iter().enumerate().rev().fold(None, |next: Option<(usize, Byte)>, (index, &(subscription_id, _))| {
+                let priority = subscriptions.get(&subscription_id).map_or(0, |s| s.priority);
+                match next {
+                    Some((_, next_priority)) if next_priority >= priority => next,
+                    _ => Some((index, priority))
+                }
+            })
+        };
+        next.and_then(|(index, _)| self.transmission_queue.remove(index))
+    }
+
     /// Searches the transmission queue to see if there are more notifications for the specified
     /// subscription id
     fn more_notifications(&self, subscription_id: UInt32) -> bool {
@@ -313,7 +458,7 @@ impl Subscriptions {
     }
 
     /// Returns the array of available sequence numbers for the specified subscription
-    fn available_sequence_numbers(&self, subscription_id: UInt32) -> Option<Vec<UInt32>> {
+    pub fn available_sequence_numbers(&self, subscription_id: UInt32) -> Option<Vec<UInt32>> {
         if self.retransmission_queue.is_empty() {
             None
         } else {
@@ -382,7 +527,11 @@ impl Subscriptions {
 
         // Remove expired sequence numbers from the retransmission queue
         for sequence_number in sequence_numbers_to_remove {
-            let _ = self.retransmission_queue.remove(&sequence_number);
+            if let Some((subscription_id, _)) = self.retransmission_queue.remove(&sequence_number) {
+                if let Some(subscription) = self.subscriptions.get_mut(&subscription_id) {
+                    subscription.counters.discarded_message_count += 1;
+                }
+            }
         }
     }
 }
\ No newline at end of file
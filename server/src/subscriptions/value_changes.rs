@@ -0,0 +1,94 @@
+//! Value changes. Monitored items on the value of a variable that the address space holds listen
+//! for the values set on it rather than reading the variable every time they sample, so they see
+//! every change and not just the value at the time of sampling. Values supplied by a getter are
+//! not held by the address space and are still sampled.
+
+use std;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, Mutex, Weak};
+
+use opcua_types::*;
+
+use constants;
+
+type ValueQueue = Mutex<VecDeque<DataValue>>;
+
+/// Receives the values set on a variable, oldest first. The listener stops listening when it and
+/// any clones of it are dropped. Clones share the values they receive.
+#[derive(Clone)]
+pub struct ValueChangeListener {
+    values: Arc<ValueQueue>,
+}
+
+impl Debug for ValueChangeListener {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "ValueChangeListener {{ values: {:?} }}", self.values.lock().unwrap().len())
+    }
+}
+
+impl PartialEq for ValueChangeListener {
+    fn eq(&self, other: &ValueChangeListener) -> bool {
+        Arc::ptr_eq(&self.values, &other.values)
+    }
+}
+
+impl ValueChangeListener {
+    /// Takes the values that have been set since they were last taken, oldest first
+    pub fn take_values(&self) -> Vec<DataValue> {
+        let mut values = self.values.lock().unwrap();
+        values.drain(..).collect()
+    }
+}
+
+/// The listeners for the values set on each variable of the address space
+pub struct ValueChanges {
+    listeners: HashMap<NodeId, Vec<Weak<ValueQueue>>>,
+}
+
+impl ValueChanges {
+    pub fn new() -> ValueChanges {
+        ValueChanges {
+            listeners: HashMap::new(),
+        }
+    }
+
+    /// Creates a listener for the values set on the node
+    pub fn listen(&mut self, node_id: &NodeId) -> ValueChangeListener {
+        let values = Arc::new(Mutex::new(VecDeque::new()));
+        let listeners = self.listeners.entry(node_id.clone()).or_insert_with(Vec::new);
+        listeners.retain(|l| l.upgrade().is_some());
+        listeners.push(Arc::downgrade(&values));
+        ValueChangeListener { values }
+    }
+
+    /// Returns the number of listeners for the values set on the node
+    pub fn listener_count(&self, node_id: &NodeId) -> usize {
+        self.listeners.get(node_id).map_or(0, |listeners| listeners.iter().filter(|l| l.upgrade().is_some()).count())
+    }
+
+    /// Passes a value set on the node to its listeners. Listeners that have been dropped are
+    /// removed. A listener that has not taken its values for a while loses the oldest.
+    pub fn notify(&mut self, node_id: &NodeId, value: &DataValue) {
+        let remove = if let Some(listeners) = self.listeners.get_mut(node_id) {
+            listeners.retain(|l| {
+                if let Some(values) = l.upgrade() {
+                    let mut values = values.lock().unwrap();
+                    if values.len() == constants::MAX_PENDING_VALUE_CHANGES {
+                        let _ = values.pop_front();
+                    }
+                    values.push_back(value.clone());
+                    true
+                } else {
+                    false
+                }
+            });
+            listeners.is_empty()
+        } else {
+            false
+        };
+        if remove {
+            let _ = self.listeners.remove(node_id);
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use prelude::*;
+use aggregates::{self, historian_bits, IntervalAggregator};
+
+use tests::*;
+
+const TICKS_PER_SECOND: i64 = 10_000_000;
+
+fn base_ticks() -> i64 {
+    DateTime::ymd_hms(2018, 1, 1, 0, 0, 0).ticks()
+}
+
+fn at(seconds: i64) -> i64 {
+    base_ticks() + seconds * TICKS_PER_SECOND
+}
+
+fn raw_value(seconds: i64, value: f64, status: StatusCode) -> DataValue {
+    DataValue {
+        value: Some(Variant::Double(value)),
+        status: Some(status),
+        status_info_bits: 0,
+        source_timestamp: Some(DateTime::from(at(seconds))),
+        source_picoseconds: None,
+        server_timestamp: None,
+        server_picoseconds: None,
+    }
+}
+
+fn configuration(treat_uncertain_as_bad: bool, percent_data_bad: Byte, percent_data_good: Byte) -> AggregateConfiguration {
+    AggregateConfiguration {
+        use_server_capabilities_defaults: false,
+        treat_uncertain_as_bad,
+        percent_data_bad,
+        percent_data_good,
+        use_sloped_extrapolation: false,
+    }
+}
+
+fn calculator(aggregate: ObjectId, stepped: bool) -> AggregateCalculator {
+    AggregateCalculator::new(&aggregate.into(), &configuration(true, 100, 100), stepped).unwrap()
+}
+
+fn calculate(aggregate: ObjectId, values: &[DataValue], start: i64, end: i64) -> AggregateValue {
+    calculator(aggregate, false).calculate(values, at(start), at(end))
+}
+
+fn assert_value(result: &AggregateValue, value: Variant, status: StatusCode) {
+    assert_eq!(result.value.value, Some(value));
+    assert_eq!(result.value.status, Some(status));
+}
+
+fn ramp() -> Vec<DataValue> {
+    vec![
+        raw_value(0, 10f64, Good),
+        raw_value(10, 20f64, Good),
+        raw_value(20, 30f64, Good),
+        raw_value(30, 40f64, Good),
+    ]
+}
+
+#[test]
+fn aggregate_not_supported() {
+    let configuration = configuration(true, 100, 100);
+    assert_eq!(AggregateCalculator::new(&ObjectId::AggregateFunction_AnnotationCount.into(), &configuration, false).unwrap_err(), BadAggregateNotSupported);
+    assert_eq!(AggregateCalculator::new(&NodeId::new(1, 100), &configuration, false).unwrap_err(), BadAggregateNotSupported);
+    assert_eq!(Aggregate::from_node_id(&ObjectId::AggregateFunction_Average.into()), Ok(Aggregate::Average));
+    assert_eq!(Aggregate::Average.object_id(), ObjectId::AggregateFunction_Average);
+}
+
+#[test]
+fn aggregate_configuration() {
+    // Percentages must be in range and cannot both be met at once
+    let average: NodeId = ObjectId::AggregateFunction_Average.into();
+    assert_eq!(AggregateCalculator::new(&average, &configuration(true, 101, 100), false).unwrap_err(), BadAggregateInvalidInputs);
+    assert_eq!(AggregateCalculator::new(&average, &configuration(true, 30, 50), false).unwrap_err(), BadAggregateInvalidInputs);
+
+    // The server's defaults replace the configuration when they are asked for
+    let mut requested = configuration(false, 50, 50);
+    requested.use_server_capabilities_defaults = true;
+    let calculator = AggregateCalculator::new(&average, &requested, false).unwrap();
+    assert_eq!(*calculator.configuration(), aggregates::default_configuration());
+}
+
+#[test]
+fn aggregate_raw_values() {
+    let values = ramp();
+
+    let result = calculate(ObjectId::AggregateFunction_Average, &values, 0, 40);
+    assert_value(&result, Variant::Double(25f64), Good);
+    assert_eq!(result.value.source_timestamp, Some(DateTime::from(at(0))));
+    assert_eq!(result.historian_bits, historian_bits::CALCULATED);
+
+    assert_value(&calculate(ObjectId::AggregateFunction_Count, &values, 0, 40), Variant::Int32(4), Good);
+    assert_value(&calculate(ObjectId::AggregateFunction_Range, &values, 0, 40), Variant::Double(30f64), Good);
+    assert_value(&calculate(ObjectId::AggregateFunction_Delta, &values, 0, 40), Variant::Double(30f64), Good);
+    assert_value(&calculate(ObjectId::AggregateFunction_Minimum, &values, 0, 40), Variant::Double(10f64), Good);
+
+    let result = calculate(ObjectId::AggregateFunction_MaximumActualTime, &values, 0, 40);
+    assert_value(&result, Variant::Double(40f64), Good);
+    assert_eq!(result.value.source_timestamp, Some(DateTime::from(at(30))));
+
+    let result = calculate(ObjectId::AggregateFunction_End, &values, 0, 40);
+    assert_value(&result, Variant::Double(40f64), Good);
+    assert_eq!(result.historian_bits, historian_bits::RAW);
+
+    // The population and sample variance of 10, 20, 30, 40
+    assert_value(&calculate(ObjectId::AggregateFunction_VariancePopulation, &values, 0, 40), Variant::Double(125f64), Good);
+    let result = calculate(ObjectId::AggregateFunction_VarianceSample, &values, 0, 40);
+    assert_eq!(result.value.value.unwrap().as_f64().map(|v| (v * 1000f64).round()), Some(166667f64));
+
+    assert_value(&calculate(ObjectId::AggregateFunction_NumberOfTransitions, &values, 0, 40), Variant::Int32(3), Good);
+}
+
+#[test]
+fn aggregate_minimum_multi_value() {
+    let values = vec![
+        raw_value(0, 10f64, Good),
+        raw_value(10, 5f64, Good),
+        raw_value(20, 5f64, Good),
+    ];
+    let result = calculate(ObjectId::AggregateFunction_MinimumActualTime, &values, 0, 30);
+    assert_value(&result, Variant::Double(5f64), Good);
+    assert_eq!(result.value.source_timestamp, Some(DateTime::from(at(10))));
+    assert_eq!(result.historian_bits, historian_bits::CALCULATED | historian_bits::MULTI_VALUE);
+}
+
+#[test]
+fn aggregate_time_weighted() {
+    let values = ramp();
+
+    // Sloped values are integrated as trapezoids, the last value holding to the end
+    assert_value(&calculate(ObjectId::AggregateFunction_TimeAverage, &values, 0, 40), Variant::Double(28.75f64), Good);
+    assert_value(&calculate(ObjectId::AggregateFunction_Total, &values, 0, 40), Variant::Double(1150f64), Good);
+
+    // Stepped values hold until the next value
+    let result = calculator(ObjectId::AggregateFunction_TimeAverage, true).calculate(&values, at(0), at(40));
+    assert_value(&result, Variant::Double(25f64), Good);
+
+    // Zero and non-zero durations
+    let values = vec![
+        raw_value(0, 0f64, Good),
+        raw_value(10, 1f64, Good),
+        raw_value(25, 0f64, Good),
+    ];
+    assert_value(&calculate(ObjectId::AggregateFunction_DurationInStateZero, &values, 0, 40), Variant::Double(25000f64), Good);
+    assert_value(&calculate(ObjectId::AggregateFunction_DurationInStateNonZero, &values, 0, 40), Variant::Double(15000f64), Good);
+}
+
+#[test]
+fn aggregate_interpolation() {
+    let values = ramp();
+
+    // Between two values the bound is interpolated
+    let result = calculate(ObjectId::AggregateFunction_Interpolative, &values, 5, 15);
+    assert_value(&result, Variant::Double(15f64), Good);
+    assert_eq!(result.historian_bits, historian_bits::INTERPOLATED);
+
+    // At a raw value the bound is the raw value
+    let result = calculate(ObjectId::AggregateFunction_StartBound, &values, 10, 20);
+    assert_value(&result, Variant::Double(20f64), Good);
+    assert_eq!(result.historian_bits, historian_bits::RAW);
+
+    // Stepped values hold
+    let result = calculator(ObjectId::AggregateFunction_Interpolative, true).calculate(&values, at(5), at(15));
+    assert_value(&result, Variant::Double(10f64), Good);
+
+    // Past the last value, the value is extrapolated as uncertain
+    let result = calculate(ObjectId::AggregateFunction_EndBound, &values, 30, 50);
+    assert_value(&result, Variant::Double(40f64), UncertainDataSubNormal);
+    assert_eq!(result.value.source_timestamp, Some(DateTime::from(at(50))));
+
+    let mut configuration = configuration(true, 100, 100);
+    configuration.use_sloped_extrapolation = true;
+    let calculator = AggregateCalculator::new(&ObjectId::AggregateFunction_EndBound.into(), &configuration, false).unwrap();
+    assert_value(&calculator.calculate(&values, at(30), at(50)), Variant::Double(60f64), UncertainDataSubNormal);
+}
+
+#[test]
+fn aggregate_quality() {
+    let values = vec![
+        raw_value(0, 10f64, Good),
+        raw_value(10, 20f64, BadNoCommunication),
+        raw_value(20, 30f64, Good),
+    ];
+
+    // Bad values are left out and a third of the interval being bad makes the result uncertain
+    assert_value(&calculate(ObjectId::AggregateFunction_Average, &values, 0, 30), Variant::Double(20f64), UncertainDataSubNormal);
+    assert_value(&calculate(ObjectId::AggregateFunction_DurationBad, &values, 0, 30), Variant::Double(10000f64), Good);
+    assert_value(&calculate(ObjectId::AggregateFunction_WorstQuality, &values, 0, 30), Variant::StatusCode(BadNoCommunication), Good);
+
+    // Unless the configuration says two thirds of good data is good enough
+    let calculator = AggregateCalculator::new(&ObjectId::AggregateFunction_Average.into(), &configuration(true, 50, 60), false).unwrap();
+    assert_value(&calculator.calculate(&values, at(0), at(30)), Variant::Double(20f64), Good);
+
+    // Uncertain values are used if they are not treated as bad
+    let values = vec![
+        raw_value(0, 10f64, Good),
+        raw_value(10, 20f64, UncertainLastUsableValue),
+        raw_value(20, 30f64, Good),
+    ];
+    assert_value(&calculate(ObjectId::AggregateFunction_Average, &values, 0, 30), Variant::Double(20f64), UncertainDataSubNormal);
+    let calculator = AggregateCalculator::new(&ObjectId::AggregateFunction_Count.into(), &configuration(false, 100, 100), false).unwrap();
+    assert_value(&calculator.calculate(&values, at(0), at(30)), Variant::Int32(3), Good);
+}
+
+#[test]
+fn aggregate_no_data() {
+    let values = vec![raw_value(20, 10f64, Good)];
+
+    let result = calculate(ObjectId::AggregateFunction_Average, &values, 0, 10);
+    assert_eq!(result.value.value, None);
+    assert_eq!(result.value.status, Some(BadNoData));
+    assert_value(&calculate(ObjectId::AggregateFunction_Count, &values, 0, 10), Variant::Int32(0), GoodNoData);
+
+    // An interval that data only covers part of is partial
+    let result = calculate(ObjectId::AggregateFunction_Average, &values, 15, 25);
+    assert_eq!(result.historian_bits, historian_bits::CALCULATED | historian_bits::PARTIAL);
+}
+
+#[test]
+fn aggregate_intervals() {
+    // Values with gaps, bad values and values exactly on interval boundaries
+    let values = vec![
+        raw_value(0, 10f64, Good),
+        raw_value(3, 12f64, Good),
+        raw_value(5, 14f64, BadNoData),
+        raw_value(9, 16f64, BadNoData),
+        raw_value(10, 18f64, Good),
+        raw_value(22, 30f64, UncertainDataSubNormal),
+        raw_value(31, 24f64, Good),
+        raw_value(33, 25f64, Good),
+        raw_value(45, 20f64, Good),
+    ];
+    let aggregates = [
+        ObjectId::AggregateFunction_Interpolative,
+        ObjectId::AggregateFunction_Average,
+        ObjectId::AggregateFunction_TimeAverage,
+        ObjectId::AggregateFunction_Total2,
+        ObjectId::AggregateFunction_Minimum2,
+        ObjectId::AggregateFunction_MaximumActualTime,
+        ObjectId::AggregateFunction_DeltaBounds,
+        ObjectId::AggregateFunction_EndBound,
+        ObjectId::AggregateFunction_DurationBad,
+    ];
+    for aggregate in aggregates.iter() {
+        let calculator = calculator(*aggregate, false);
+        // Each interval is calculated as it would be from all of the values
+        let expected: Vec<AggregateValue> = (0..12).map(|i| calculator.calculate(&values, at(i * 5), at(i * 5 + 5))).collect();
+        assert_eq!(calculator.calculate_intervals(&values, at(0), at(60), 5000f64).unwrap(), expected);
+        let backwards: Vec<AggregateValue> = expected.into_iter().rev().collect();
+        assert_eq!(calculator.calculate_intervals(&values, at(60), at(0), 5000f64).unwrap(), backwards);
+    }
+
+    // Too many intervals are rejected
+    let calculator = calculator(ObjectId::AggregateFunction_Average, false);
+    assert_eq!(calculator.calculate_intervals(&values, at(0), at(3600), 1f64), Err(BadTooManyOperations));
+    assert_eq!(calculator.calculate_intervals(&values, at(0), at(3600), 1000f64).unwrap().len(), 3600);
+}
+
+#[test]
+fn interval_aggregator() {
+    let calculator = calculator(ObjectId::AggregateFunction_Average, false);
+    let mut aggregator = IntervalAggregator::new(calculator, at(0), 10000f64, at(0));
+
+    aggregator.add_value(raw_value(0, 10f64, Good));
+    aggregator.add_value(raw_value(5, 20f64, Good));
+    // A value sampled again is ignored
+    aggregator.add_value(raw_value(5, 20f64, Good));
+    assert!(aggregator.completed_intervals(at(9)).is_empty());
+
+    aggregator.add_value(raw_value(12, 30f64, Good));
+    let results = aggregator.completed_intervals(at(20));
+    assert_eq!(results.len(), 2);
+    assert_value(&results[0], Variant::Double(15f64), Good);
+    assert_eq!(results[0].value.source_timestamp, Some(DateTime::from(at(0))));
+    // The value from before the second interval holds at its start
+    assert_value(&results[1], Variant::Double(30f64), Good);
+    assert_eq!(results[1].value.source_timestamp, Some(DateTime::from(at(10))));
+
+    // Intervals are aligned to the start time
+    let calculator = self::calculator(ObjectId::AggregateFunction_Count, false);
+    let mut aggregator = IntervalAggregator::new(calculator, at(0), 10000f64, at(25));
+    assert!(aggregator.completed_intervals(at(29)).is_empty());
+    let results = aggregator.completed_intervals(at(30));
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].value.source_timestamp, Some(DateTime::from(at(20))));
+}
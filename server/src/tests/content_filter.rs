@@ -0,0 +1,224 @@
+use std::cell::Cell;
+
+use prelude::*;
+use content_filter::{self, FilterTarget};
+
+use tests::*;
+
+/// A target with a few named values and a type
+struct TestTarget;
+
+impl FilterTarget for TestTarget {
+    fn simple_attribute_value(&self, operand: &SimpleAttributeOperand) -> Variant {
+        let name = operand.browse_path.as_ref().and_then(|browse_path| browse_path.first()).map(|name| name.name.as_ref().to_string());
+        match name.as_ref().map(|name| name.as_str()) {
+            Some("Severity") => Variant::UInt16(500),
+            Some("SourceName") => Variant::from("Boiler #1"),
+            Some("Temperature") => Variant::Double(80.5),
+            _ => Variant::Empty
+        }
+    }
+
+    fn attribute_value(&self, _operand: &AttributeOperand) -> Variant {
+        Variant::Empty
+    }
+
+    fn is_of_type(&self, type_id: &NodeId) -> bool {
+        *type_id == ObjectTypeId::BaseEventType.into()
+    }
+}
+
+fn literal<T>(value: T) -> ExtensionObject where T: Into<Variant> {
+    ExtensionObject::from_encodable(ObjectId::LiteralOperand_Encoding_DefaultBinary, LiteralOperand { value: value.into() })
+}
+
+fn element(index: UInt32) -> ExtensionObject {
+    ExtensionObject::from_encodable(ObjectId::ElementOperand_Encoding_DefaultBinary, ElementOperand { index })
+}
+
+fn field(name: &str) -> ExtensionObject {
+    ExtensionObject::from_encodable(ObjectId::SimpleAttributeOperand_Encoding_DefaultBinary, SimpleAttributeOperand {
+        type_definition_id: NodeId::null(),
+        browse_path: Some(vec![QualifiedName::new(0, name)]),
+        attribute_id: AttributeId::Value as UInt32,
+        index_range: UAString::null(),
+    })
+}
+
+fn make_filter(elements: Vec<(FilterOperator, Vec<ExtensionObject>)>) -> ContentFilter {
+    ContentFilter {
+        elements: Some(elements.into_iter().map(|(filter_operator, filter_operands)| ContentFilterElement {
+            filter_operator,
+            filter_operands: Some(filter_operands),
+        }).collect())
+    }
+}
+
+fn passes(filter_operator: FilterOperator, filter_operands: Vec<ExtensionObject>) -> bool {
+    let filter = make_filter(vec![(filter_operator, filter_operands)]);
+    assert!(content_filter::validate(&filter).is_ok());
+    content_filter::evaluate(&filter, &TestTarget)
+}
+
+#[test]
+fn content_filter_empty() {
+    let filter = ContentFilter { elements: None };
+    assert!(content_filter::validate(&filter).is_ok());
+    assert!(content_filter::evaluate(&filter, &TestTarget));
+}
+
+#[test]
+fn content_filter_compare() {
+    assert!(passes(FilterOperator::Equals, vec![field("Severity"), literal(500i32)]));
+    assert!(!passes(FilterOperator::Equals, vec![field("Severity"), literal(501i32)]));
+    // Strings are converted to numbers when compared with them
+    assert!(passes(FilterOperator::Equals, vec![field("Severity"), literal("500")]));
+    assert!(passes(FilterOperator::GreaterThan, vec![field("Temperature"), literal(80i64)]));
+    assert!(!passes(FilterOperator::LessThan, vec![field("Temperature"), literal(80u32)]));
+    assert!(passes(FilterOperator::GreaterThanOrEqual, vec![field("Severity"), literal(500u16)]));
+    assert!(passes(FilterOperator::LessThanOrEqual, vec![field("Severity"), literal(500.0f32)]));
+    assert!(passes(FilterOperator::Between, vec![field("Severity"), literal(100i32), literal(900i32)]));
+    assert!(!passes(FilterOperator::Between, vec![field("Severity"), literal(600i32), literal(900i32)]));
+    assert!(passes(FilterOperator::InList, vec![field("Severity"), literal(1i32), literal(500i32), literal(1000i32)]));
+    assert!(!passes(FilterOperator::InList, vec![field("Severity"), literal(1i32), literal(1000i32)]));
+
+    // Values that can't be compared are neither equal nor unequal
+    assert!(!passes(FilterOperator::Equals, vec![field("Unknown"), literal(1i32)]));
+    assert!(!passes(FilterOperator::Equals, vec![field("SourceName"), literal(1i32)]));
+    assert!(passes(FilterOperator::IsNull, vec![field("Unknown")]));
+    assert!(!passes(FilterOperator::IsNull, vec![field("Severity")]));
+}
+
+#[test]
+fn content_filter_like() {
+    assert!(passes(FilterOperator::Like, vec![field("SourceName"), literal("Boiler%")]));
+    assert!(passes(FilterOperator::Like, vec![field("SourceName"), literal("Boiler #_")]));
+    assert!(passes(FilterOperator::Like, vec![field("SourceName"), literal("%[0-9]")]));
+    assert!(passes(FilterOperator::Like, vec![field("SourceName"), literal("%[^a-z]1")]));
+    assert!(passes(FilterOperator::Like, vec![literal("100%"), literal("100\\%")]));
+    assert!(!passes(FilterOperator::Like, vec![literal("1000"), literal("100\\%")]));
+    assert!(!passes(FilterOperator::Like, vec![field("SourceName"), literal("Pump%")]));
+    assert!(!passes(FilterOperator::Like, vec![field("SourceName"), literal("Boiler")]));
+    assert!(passes(FilterOperator::Like, vec![field("SourceName"), literal("%%B%%1")]));
+    assert!(passes(FilterOperator::Like, vec![literal("a_b"), literal("a\\_%")]));
+    assert!(!passes(FilterOperator::Like, vec![literal("ab"), literal("a\\_%")]));
+
+    // Patterns with many % do not take exponential time to fail
+    let value = "a".repeat(1000);
+    let pattern = format!("{}b", "a%".repeat(100));
+    assert!(!passes(FilterOperator::Like, vec![literal(value.as_str()), literal(pattern.as_str())]));
+}
+
+/// A target that counts how many times its values are read
+struct CountingTarget {
+    reads: Cell<usize>,
+}
+
+impl FilterTarget for CountingTarget {
+    fn simple_attribute_value(&self, operand: &SimpleAttributeOperand) -> Variant {
+        self.reads.set(self.reads.get() + 1);
+        TestTarget.simple_attribute_value(operand)
+    }
+
+    fn attribute_value(&self, _operand: &AttributeOperand) -> Variant {
+        Variant::Empty
+    }
+
+    fn is_of_type(&self, _type_id: &NodeId) -> bool {
+        false
+    }
+}
+
+#[test]
+fn content_filter_shared_elements() {
+    // Each element is both operands of the element before it, so the last element is an operand
+    // of the first 2^40 times over. It is evaluated once.
+    let mut elements: Vec<(FilterOperator, Vec<ExtensionObject>)> = (1..41).map(|i| {
+        (FilterOperator::And, vec![element(i), element(i)])
+    }).collect();
+    elements.push((FilterOperator::Equals, vec![field("Severity"), literal(500i32)]));
+    let filter = make_filter(elements);
+    assert!(content_filter::validate(&filter).is_ok());
+    let target = CountingTarget { reads: Cell::new(0) };
+    assert!(content_filter::evaluate(&filter, &target));
+    assert_eq!(target.reads.get(), 1);
+}
+
+#[test]
+fn content_filter_logic() {
+    // Severity > 100 and (SourceName like 'Pump%' or Temperature < 90)
+    let filter = make_filter(vec![
+        (FilterOperator::And, vec![element(1), element(2)]),
+        (FilterOperator::GreaterThan, vec![field("Severity"), literal(100i32)]),
+        (FilterOperator::Or, vec![element(3), element(4)]),
+        (FilterOperator::Like, vec![field("SourceName"), literal("Pump%")]),
+        (FilterOperator::LessThan, vec![field("Temperature"), literal(90i32)]),
+    ]);
+    assert!(content_filter::validate(&filter).is_ok());
+    assert!(content_filter::evaluate(&filter, &TestTarget));
+
+    // Not null is null, so it doesn't pass
+    let filter = make_filter(vec![
+        (FilterOperator::Not, vec![element(1)]),
+        (FilterOperator::Equals, vec![field("Unknown"), literal(1i32)]),
+    ]);
+    assert!(!content_filter::evaluate(&filter, &TestTarget));
+
+    // False and null is false, true or null is true
+    assert!(!passes(FilterOperator::And, vec![literal(false), field("Unknown")]));
+    assert!(passes(FilterOperator::Not, vec![literal(false)]));
+    assert!(passes(FilterOperator::Or, vec![literal(true), field("Unknown")]));
+    assert!(!passes(FilterOperator::Or, vec![literal(false), field("Unknown")]));
+}
+
+#[test]
+fn content_filter_cast_and_bitwise() {
+    // Cast the temperature to an integer before comparing
+    let filter = make_filter(vec![
+        (FilterOperator::Equals, vec![element(1), literal(81i32)]),
+        (FilterOperator::Cast, vec![field("Temperature"), literal(Into::<NodeId>::into(DataTypeId::Int32))]),
+    ]);
+    assert!(content_filter::validate(&filter).is_ok());
+    assert!(content_filter::evaluate(&filter, &TestTarget));
+
+    // Severity & 0x1f4 == 0x1f4
+    let filter = make_filter(vec![
+        (FilterOperator::Equals, vec![element(1), literal(0x1f4i32)]),
+        (FilterOperator::BitwiseAnd, vec![field("Severity"), literal(0x1fcu16)]),
+    ]);
+    assert!(content_filter::evaluate(&filter, &TestTarget));
+
+    assert!(passes(FilterOperator::OfType, vec![literal(Into::<NodeId>::into(ObjectTypeId::BaseEventType))]));
+    assert!(!passes(FilterOperator::OfType, vec![literal(Into::<NodeId>::into(ObjectTypeId::AuditEventType))]));
+    assert!(!passes(FilterOperator::InView, vec![literal(NodeId::new(1, 1))]));
+}
+
+#[test]
+fn content_filter_invalid() {
+    let invalid_operand = ExtensionObject::from_encodable(ObjectId::ReadValueId_Encoding_DefaultBinary, ReadValueId {
+        node_id: NodeId::null(),
+        attribute_id: AttributeId::Value as UInt32,
+        index_range: UAString::null(),
+        data_encoding: QualifiedName::null(),
+    });
+    let filter = make_filter(vec![
+        (FilterOperator::And, vec![element(1), element(0)]),
+        (FilterOperator::Equals, vec![field("Severity")]),
+        (FilterOperator::Equals, vec![field("Severity"), invalid_operand]),
+        (FilterOperator::OfType, vec![literal(1.5f64)]),
+        (FilterOperator::IsNull, vec![field("Severity")]),
+    ]);
+    let result = content_filter::validate(&filter).unwrap_err();
+    let element_results = result.element_results.unwrap();
+    assert_eq!(element_results.len(), 5);
+
+    // Elements may not refer to themselves or elements before them
+    assert_eq!(element_results[0].status_code, BadFilterElementInvalid);
+    assert_eq!(element_results[0].operand_status_codes.as_ref().unwrap(), &vec![Good, BadFilterElementInvalid]);
+    assert_eq!(element_results[1].status_code, BadFilterOperandCountMismatch);
+    assert_eq!(element_results[2].status_code, BadFilterOperandInvalid);
+    assert_eq!(element_results[2].operand_status_codes.as_ref().unwrap(), &vec![Good, BadFilterOperandInvalid]);
+    assert_eq!(element_results[3].status_code, BadFilterOperandInvalid);
+    assert_eq!(element_results[3].operand_status_codes.as_ref().unwrap(), &vec![BadFilterLiteralInvalid]);
+    assert_eq!(element_results[4].status_code, Good);
+}
@@ -0,0 +1,301 @@
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+
+use chrono;
+use time;
+
+use discovery::dns::{self, Message, Question, Record, RecordData};
+use discovery::mdns::{self, MdnsDiscovery, MdnsService};
+use discovery::servers_on_network::ServersOnNetwork;
+use constants;
+
+fn make_service(server_name: &str, port: u16, capabilities: &[&str]) -> MdnsService {
+    let capabilities: Vec<String> = capabilities.iter().map(|c| c.to_string()).collect();
+    MdnsService::from_discovery_url(server_name, &format!("opc.tcp://127.0.0.1:{}/UADiscovery", port), &capabilities).unwrap()
+}
+
+fn loopback_socket() -> UdpSocket {
+    UdpSocket::bind("127.0.0.1:0").unwrap()
+}
+
+#[test]
+fn dns_message_round_trip() {
+    let message = Message::response(vec![
+        Record {
+            name: "_opcua-tcp._tcp.local".to_string(),
+            cache_flush: false,
+            ttl: 120,
+            data: RecordData::Ptr("Server._opcua-tcp._tcp.local".to_string()),
+        }
+    ], vec![
+        Record {
+            name: "Server._opcua-tcp._tcp.local".to_string(),
+            cache_flush: true,
+            ttl: 120,
+            data: RecordData::Srv { priority: 0, weight: 0, port: 4840, target: "host.local".to_string() },
+        },
+        Record {
+            name: "Server._opcua-tcp._tcp.local".to_string(),
+            cache_flush: true,
+            ttl: 120,
+            data: RecordData::Txt(vec!["path=/".to_string(), "caps=LDS,DA".to_string()]),
+        },
+        Record {
+            name: "host.local".to_string(),
+            cache_flush: true,
+            ttl: 120,
+            data: RecordData::A(Ipv4Addr::new(192, 168, 1, 10)),
+        },
+        Record {
+            name: "host.local".to_string(),
+            cache_flush: false,
+            ttl: 120,
+            data: RecordData::Other(28, vec![0u8; 16]),
+        },
+    ]);
+    let decoded = Message::decode(&message.encode()).unwrap();
+    assert_eq!(decoded, message);
+
+    let query = Message::query(vec![Question {
+        name: "_opcua-tcp._tcp.local".to_string(),
+        question_type: dns::TYPE_PTR,
+        unicast_response: true,
+    }]);
+    let decoded = Message::decode(&query.encode()).unwrap();
+    assert_eq!(decoded, query);
+
+    // Truncated messages are rejected
+    let encoded = message.encode();
+    assert!(Message::decode(&encoded[..encoded.len() - 5]).is_err());
+}
+
+#[test]
+fn dns_compressed_names() {
+    // A PTR record whose data points back at the name of the question
+    let mut buffer = vec![0, 0, 0x84, 0, 0, 1, 0, 1, 0, 0, 0, 0];
+    // Question "_opcua-tcp._tcp.local" starts at offset 12
+    for label in &["_opcua-tcp", "_tcp", "local"] {
+        buffer.push(label.len() as u8);
+        buffer.extend_from_slice(label.as_bytes());
+    }
+    buffer.extend_from_slice(&[0, 0, 12, 0, 1]);
+    // Answer name is a pointer to offset 12
+    buffer.extend_from_slice(&[0xc0, 12, 0, 12, 0, 1, 0, 0, 0, 120]);
+    // Data is the label "Server" followed by a pointer to offset 12
+    buffer.extend_from_slice(&[0, 9, 6]);
+    buffer.extend_from_slice(b"Server");
+    buffer.extend_from_slice(&[0xc0, 12]);
+
+    let message = Message::decode(&buffer).unwrap();
+    assert!(message.is_response);
+    assert_eq!(message.questions[0].name, "_opcua-tcp._tcp.local");
+    assert_eq!(message.answers[0].name, "_opcua-tcp._tcp.local");
+    assert_eq!(message.answers[0].data, RecordData::Ptr("Server._opcua-tcp._tcp.local".to_string()));
+
+    // A pointer to itself is rejected
+    let buffer = vec![0, 0, 0x84, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0xc0, 12, 0, 12, 0, 1];
+    assert!(Message::decode(&buffer).is_err());
+}
+
+#[test]
+fn mdns_service() {
+    let service = make_service("Server", 4855, &["DA"]);
+    assert_eq!(service.host, "127-0-0-1.local");
+    assert_eq!(service.address, Some(Ipv4Addr::new(127, 0, 0, 1)));
+    assert_eq!(service.discovery_url(), "opc.tcp://127.0.0.1:4855/UADiscovery");
+    assert_eq!(service.instance_name(), "Server._opcua-tcp._tcp.local");
+
+    let service = MdnsService::from_discovery_url("Server", "opc.tcp://plant-pc:4840/", &[]).unwrap();
+    assert_eq!(service.host, "plant-pc.local");
+    assert!(service.address.is_none());
+    assert_eq!(service.discovery_url(), "opc.tcp://plant-pc.local:4840/");
+
+    assert_eq!(mdns::server_name_from_instance_name("My Server._OPCUA-tcp._tcp.local"), Some("My Server".to_string()));
+    assert!(mdns::server_name_from_instance_name("Server._http._tcp.local").is_none());
+    assert!(mdns::server_name_from_instance_name("_opcua-tcp._tcp.local").is_none());
+}
+
+#[test]
+fn answer_query() {
+    let services = vec![make_service("Server1", 4855, &["DA"]), make_service("Server2", 4856, &[])];
+
+    // Services are listed by a query for the service type
+    let query = Message::query(vec![Question {
+        name: mdns::OPCUA_SERVICE_TYPE.to_string(),
+        question_type: dns::TYPE_PTR,
+        unicast_response: false,
+    }]);
+    let response = mdns::answer_query(&query, &services, 120).unwrap();
+    assert!(response.is_response);
+    assert_eq!(response.answers.len(), 2);
+    let service = MdnsService::from_message("Server1", "Server1._opcua-tcp._tcp.local", &response).unwrap();
+    assert_eq!(service, services[0]);
+
+    // A query for an instance is answered with the records of that instance
+    let query = Message::query(vec![Question {
+        name: "server2._opcua-tcp._tcp.local".to_string(),
+        question_type: dns::TYPE_ANY,
+        unicast_response: false,
+    }]);
+    let response = mdns::answer_query(&query, &services, 120).unwrap();
+    assert!(response.answers.is_empty());
+    assert!(response.additionals.iter().all(|record| record.name.starts_with("Server2") || record.name == "127-0-0-1.local"));
+
+    // Other queries are not answered
+    let query = Message::query(vec![Question {
+        name: "_http._tcp.local".to_string(),
+        question_type: dns::TYPE_PTR,
+        unicast_response: false,
+    }]);
+    assert!(mdns::answer_query(&query, &services, 120).is_none());
+}
+
+#[test]
+fn servers_on_network() {
+    let mut servers_on_network = ServersOnNetwork::new();
+    let now = chrono::Utc::now();
+
+    servers_on_network.update(&make_service("Server1", 4855, &["DA"]), 120, &now);
+    servers_on_network.update(&make_service("Server2", 4856, &["DA", "HD"]), 120, &now);
+    servers_on_network.update(&make_service("Server3", 4857, &["NA"]), 60, &now);
+
+    let servers = servers_on_network.find(0, 0, &None);
+    assert_eq!(servers.len(), 3);
+    assert_eq!(servers[0].record_id, 1);
+    assert_eq!(servers[0].server_name.as_ref(), "Server1");
+    assert_eq!(servers[0].discovery_url.as_ref(), "opc.tcp://127.0.0.1:4855/UADiscovery");
+    assert_eq!(servers[2].record_id, 3);
+
+    // Starting record and max records
+    let servers = servers_on_network.find(2, 1, &None);
+    assert_eq!(servers.len(), 1);
+    assert_eq!(servers[0].server_name.as_ref(), "Server2");
+
+    // Capability filter
+    let servers = servers_on_network.find(0, 0, &Some(vec!["da".into(), "HD".into()]));
+    assert_eq!(servers.len(), 1);
+    assert_eq!(servers[0].server_name.as_ref(), "Server2");
+
+    // Announcing the same server again keeps its record id, a change gives it a new one
+    servers_on_network.update(&make_service("Server1", 4855, &["DA"]), 120, &now);
+    assert_eq!(servers_on_network.find(0, 0, &None)[0].record_id, 1);
+    servers_on_network.update(&make_service("server1", 4860, &["DA"]), 120, &now);
+    let servers = servers_on_network.find(0, 0, &None);
+    assert_eq!(servers.len(), 3);
+    assert_eq!(servers[2].record_id, 4);
+    assert_eq!(servers[2].discovery_url.as_ref(), "opc.tcp://127.0.0.1:4860/UADiscovery");
+
+    // Expiry and goodbye
+    servers_on_network.remove_expired(&(now + time::Duration::seconds(90)));
+    assert_eq!(servers_on_network.len(), 2);
+    servers_on_network.update(&make_service("Server2", 4856, &[]), 0, &now);
+    assert_eq!(servers_on_network.len(), 1);
+}
+
+#[test]
+fn servers_on_network_limits() {
+    let mut servers_on_network = ServersOnNetwork::new();
+    let now = chrono::Utc::now();
+
+    // A server announced with an overly long time to live is only cached for the longest allowed
+    servers_on_network.update(&make_service("Server1", 4855, &[]), u32::max_value(), &now);
+    servers_on_network.remove_expired(&(now + time::Duration::seconds(constants::MDNS_MAX_RECORD_TTL as i64 - 1)));
+    assert_eq!(servers_on_network.len(), 1);
+    servers_on_network.remove_expired(&(now + time::Duration::seconds(constants::MDNS_MAX_RECORD_TTL as i64 + 1)));
+    assert_eq!(servers_on_network.len(), 0);
+
+    // New servers are ignored while the cache is full, unless some have expired
+    for i in 0..constants::MAX_SERVERS_ON_NETWORK {
+        servers_on_network.update(&make_service(&format!("Server{}", i), 4855, &[]), if i == 0 { 10 } else { 120 }, &now);
+    }
+    assert_eq!(servers_on_network.len(), constants::MAX_SERVERS_ON_NETWORK);
+    servers_on_network.update(&make_service("Extra1", 4855, &[]), 120, &now);
+    assert_eq!(servers_on_network.len(), constants::MAX_SERVERS_ON_NETWORK);
+    assert!(servers_on_network.find(0, 0, &None).iter().all(|server| server.server_name.as_ref() != "Extra1"));
+    let later = now + time::Duration::seconds(60);
+    servers_on_network.update(&make_service("Extra2", 4855, &[]), 120, &later);
+    assert_eq!(servers_on_network.len(), constants::MAX_SERVERS_ON_NETWORK);
+    let servers = servers_on_network.find(0, 0, &None);
+    assert!(servers.iter().any(|server| server.server_name.as_ref() == "Extra2"));
+    assert!(servers.iter().all(|server| server.server_name.as_ref() != "Server0"));
+
+    // Known servers are still refreshed while the cache is full
+    servers_on_network.update(&make_service("Server1", 4855, &[]), 120, &later);
+    servers_on_network.remove_expired(&(now + time::Duration::seconds(150)));
+    assert_eq!(servers_on_network.len(), 2);
+}
+
+#[test]
+fn servers_on_network_is_announcement() {
+    let services = vec![make_service("Server1", 4855, &["DA"])];
+    let query = Message::query(vec![Question {
+        name: mdns::OPCUA_SERVICE_TYPE.to_string(),
+        question_type: dns::TYPE_PTR,
+        unicast_response: false,
+    }]);
+    assert!(!ServersOnNetwork::is_announcement(&query));
+
+    // The answer to a query about OPC UA servers announces them
+    let response = mdns::answer_query(&query, &services, 120).unwrap();
+    assert!(ServersOnNetwork::is_announcement(&response));
+
+    // Responses about other services do not
+    let response = Message::response(vec![
+        Record {
+            name: "_http._tcp.local".to_string(),
+            cache_flush: false,
+            ttl: 120,
+            data: RecordData::Ptr("Web._http._tcp.local".to_string()),
+        }
+    ], vec![]);
+    assert!(!ServersOnNetwork::is_announcement(&response));
+}
+
+#[test]
+fn mdns_loopback() {
+    // Two servers exchange announcements and queries over the loopback interface. Each sends to the
+    // other's socket rather than the multicast group.
+    let socket1 = loopback_socket();
+    let socket2 = loopback_socket();
+    let address1: SocketAddr = socket1.local_addr().unwrap();
+    let address2: SocketAddr = socket2.local_addr().unwrap();
+    let mut discovery1 = MdnsDiscovery::new(socket1, address2).unwrap();
+    let discovery2 = MdnsDiscovery::new(socket2, address1).unwrap();
+
+    let services1 = vec![make_service("Server1", 4855, &["DA"])];
+    let services2 = vec![make_service("Server2", 4856, &["HD"])];
+    let mut servers_on_network1 = ServersOnNetwork::new();
+    let mut servers_on_network2 = ServersOnNetwork::new();
+    let now = chrono::Utc::now();
+
+    // Server 1 announces itself and server 2 caches it
+    assert!(discovery1.announcement_due());
+    discovery1.announce(&services1, 120);
+    assert!(!discovery1.announcement_due());
+    let (message, source) = discovery2.receive().unwrap();
+    assert_eq!(source, address1);
+    discovery2.handle(&message, &source, &services2, &mut servers_on_network2, &now);
+    let servers = servers_on_network2.find(0, 0, &None);
+    assert_eq!(servers.len(), 1);
+    assert_eq!(servers[0].server_name.as_ref(), "Server1");
+    assert_eq!(servers[0].discovery_url.as_ref(), "opc.tcp://127.0.0.1:4855/UADiscovery");
+    assert_eq!(servers[0].server_capabilities, Some(vec!["DA".into()]));
+
+    // Server 1 asks who is out there and server 2 answers it directly
+    discovery1.query();
+    let (message, source) = discovery2.receive().unwrap();
+    assert!(!message.is_response);
+    discovery2.handle(&message, &source, &services2, &mut servers_on_network2, &now);
+    let (message, source) = discovery1.receive().unwrap();
+    assert!(message.is_response);
+    discovery1.handle(&message, &source, &services1, &mut servers_on_network1, &now);
+    let servers = servers_on_network1.find(0, 0, &None);
+    assert_eq!(servers.len(), 1);
+    assert_eq!(servers[0].server_name.as_ref(), "Server2");
+    assert_eq!(servers[0].discovery_url.as_ref(), "opc.tcp://127.0.0.1:4856/UADiscovery");
+
+    // Server 1 says goodbye
+    discovery1.announce(&services1, 0);
+    let (message, source) = discovery2.receive().unwrap();
+    discovery2.handle(&message, &source, &services2, &mut servers_on_network2, &now);
+    assert!(servers_on_network2.is_empty());
+}
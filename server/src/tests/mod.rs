@@ -1,12 +1,13 @@
 use std;
 use std::collections::VecDeque;
+use std::io::{Read, Write};
 use std::path::PathBuf;
 
 use chrono;
 use time;
 
 use opcua_types::*;
-use opcua_types::node_ids::{ObjectId, ObjectTypeId, DataTypeId, ReferenceTypeId, VariableId};
+use opcua_types::node_ids::{ObjectId, ObjectTypeId, DataTypeId, MethodId, ReferenceTypeId, VariableId};
 use opcua_types::status_codes::StatusCode::*;
 use opcua_types::service_types::*;
 
@@ -22,6 +23,9 @@ use subscriptions::*;
 use config::ServerConfig;
 
 mod address_space;
+mod aggregates;
+mod content_filter;
+mod mdns;
 mod services;
 mod subscriptions;
 
@@ -74,6 +78,24 @@ pub fn server_config_save() {
     }
 }
 
+#[test]
+pub fn server_config_load_without_node_management_users() {
+    // Configs written before node management users could be configured still load
+    let path = make_test_file("server_config_no_node_management.yaml");
+    let config = ServerConfig::new_anonymous("foo");
+    assert!(config.node_management_user_token_ids.is_empty());
+    assert!(config.save(&path).is_ok());
+    let mut yaml = String::new();
+    assert!(std::fs::File::open(&path).unwrap().read_to_string(&mut yaml).is_ok());
+    let yaml: Vec<&str> = yaml.lines().filter(|line| !line.starts_with("node_management_user_token_ids")).collect();
+    assert!(std::fs::File::create(&path).unwrap().write_all(yaml.join("\n").as_bytes()).is_ok());
+    if let Ok(config2) = ServerConfig::load(&path) {
+        assert_eq!(config, config2);
+    } else {
+        panic!("Cannot load config without node management users from file");
+    }
+}
+
 #[test]
 pub fn server_config_invalid() {
     // Remove the endpoint
@@ -1,7 +1,13 @@
+use std::sync::{Arc, Mutex};
+
 use super::*;
 use services::attribute::AttributeService;
+use address_space::AttributeSetter;
 use address_space::access_level;
+use address_space::user_access_level;
 use opcua_types::write_mask;
+use opcua_types::status_info_bits;
+use aggregates::historian_bits;
 
 fn read_value(node_id: &NodeId, attribute_id: AttributeId) -> ReadValueId {
     ReadValueId {
@@ -51,8 +57,9 @@ fn read_test() {
             nodes_to_read: Some(nodes_to_read),
         };
 
+        let session = st.session.read().unwrap();
         let address_space = st.server.address_space.read().unwrap();
-        let response = ats.read(&address_space, request);
+        let response = ats.read(&session, &address_space, request);
         assert!(response.is_ok());
         let response: ReadResponse = supported_message_as!(response.unwrap(), ReadResponse);
 
@@ -80,7 +87,6 @@ fn read_test() {
 
     // OTHER POTENTIAL TESTS
 
-    // read index range
     // distinguish between read and user read
     // test max_age
     // test timestamps to return Server, Source, None, Both
@@ -117,12 +123,17 @@ fn write_test() {
                     // No write access
                     let _ = node.as_mut_node().set_attribute(AttributeId::AccessLevel, DataValue::new(0 as Byte)).unwrap();
                 }
+                3 => {
+                    // Write access, but not for the user
+                    let _ = node.as_mut_node().set_attribute(AttributeId::AccessLevel, DataValue::new(access_level::CURRENT_WRITE as Byte)).unwrap();
+                }
                 6 => {
                     node.as_mut_node().set_write_mask(write_mask::ACCESS_LEVEL);
                 }
                 _ => {
                     // Write access
                     let _ = node.as_mut_node().set_attribute(AttributeId::AccessLevel, DataValue::new(access_level::CURRENT_WRITE as Byte)).unwrap();
+                    let _ = node.as_mut_node().set_attribute(AttributeId::UserAccessLevel, DataValue::new(user_access_level::CURRENT_WRITE as Byte)).unwrap();
                 }
             }
         }
@@ -152,6 +163,8 @@ fn write_test() {
         write_value(&NodeId::new_string(2, "vxxx"), AttributeId::Value, DataValue::new(100 as Int32)),
         // 7. wrong type for attribute
         write_value(&node_ids[6], AttributeId::AccessLevel, DataValue::new(-1 as SByte)),
+        // 8. a variable value which the user has no write access to
+        write_value(&node_ids[3], AttributeId::Value, DataValue::new(300 as Int32)),
     ];
 
     let request = WriteRequest {
@@ -160,8 +173,9 @@ fn write_test() {
     };
 
     // do a write with the following write
+    let session = st.session.read().unwrap();
     let mut address_space = st.server.address_space.write().unwrap();
-    let response = ats.write(&mut address_space, request);
+    let response = ats.write(&session, &mut address_space, request);
     assert!(response.is_ok());
     let response: WriteResponse = supported_message_as!(response.unwrap(), WriteResponse);
     let results = response.results.unwrap();
@@ -180,10 +194,602 @@ fn write_test() {
     assert_eq!(results[5], BadNodeIdUnknown);
     // 7. wrong type for attribute
     assert_eq!(results[6], BadTypeMismatch);
+    // 8. a variable value which the user has no write access to
+    assert_eq!(results[7], BadUserAccessDenied);
 
     // OTHER POTENTIAL TESTS
 
-    // write index range
-    // distinguish between write and user write
     // test max_age
 }
+
+fn index_range(node_id: &NodeId, index_range: &str) -> ReadValueId {
+    ReadValueId {
+        index_range: UAString::from(index_range),
+        ..read_value(node_id, AttributeId::Value)
+    }
+}
+
+fn read_values(st: &ServiceTest, nodes_to_read: Vec<ReadValueId>) -> Vec<DataValue> {
+    let request = ReadRequest {
+        request_header: make_request_header(),
+        max_age: 0f64,
+        timestamps_to_return: TimestampsToReturn::Both,
+        nodes_to_read: Some(nodes_to_read),
+    };
+    let session = st.session.read().unwrap();
+    let address_space = st.server.address_space.read().unwrap();
+    let response = AttributeService::new().read(&session, &address_space, request);
+    let response: ReadResponse = supported_message_as!(response.unwrap(), ReadResponse);
+    response.results.unwrap()
+}
+
+#[test]
+fn read_write_index_range_test() {
+    let st = ServiceTest::new();
+
+    let array_id = NodeId::new_string(1, "array");
+    let string_id = NodeId::new_string(1, "string");
+    {
+        let mut address_space = st.server.address_space.write().unwrap();
+        let mut array = Variable::new_array(&array_id, "array", "array", "", DataTypeId::Int32, DataValue::new(Variant::new_i32_array(&[0, 1, 2, 3, 4])), &[5]);
+        array.set_access_level(access_level::CURRENT_READ | access_level::CURRENT_WRITE);
+        array.set_user_access_level(user_access_level::CURRENT_READ | user_access_level::CURRENT_WRITE);
+        let _ = address_space.add_variable(array, &AddressSpace::objects_folder_id());
+        let _ = address_space.add_variable(Variable::new(&string_id, "string", "string", "", "Hello world"), &AddressSpace::objects_folder_id());
+    }
+
+    let results = read_values(&st, vec![
+        index_range(&array_id, "1:2"),
+        index_range(&array_id, "3:10"),
+        index_range(&array_id, "5"),
+        index_range(&array_id, "2:1"),
+        index_range(&array_id, "0,1"),
+        index_range(&string_id, "6:10"),
+    ]);
+    assert_eq!(results[0].value.as_ref().unwrap(), &Variant::new_i32_array(&[1, 2]));
+    assert_eq!(results[1].value.as_ref().unwrap(), &Variant::new_i32_array(&[3, 4]));
+    assert_eq!(results[2].status.as_ref().unwrap(), &BadIndexRangeNoData);
+    assert_eq!(results[3].status.as_ref().unwrap(), &BadIndexRangeInvalid);
+    assert_eq!(results[4].status.as_ref().unwrap(), &BadIndexRangeInvalid);
+    assert_eq!(results[5].value.as_ref().unwrap(), &Variant::from("world"));
+
+    let write_range = |node_id: &NodeId, index_range: &str, value: Variant| {
+        WriteValue {
+            index_range: UAString::from(index_range),
+            ..write_value(node_id, AttributeId::Value, DataValue::new(value))
+        }
+    };
+    let request = WriteRequest {
+        request_header: make_request_header(),
+        nodes_to_write: Some(vec![
+            write_range(&array_id, "1:2", Variant::new_i32_array(&[10, 20])),
+            write_range(&array_id, "4:5", Variant::new_i32_array(&[40, 50])),
+            write_range(&array_id, "0", Variant::new_i32_array(&[1, 2])),
+            write_range(&array_id, "0", Variant::new_u32_array(&[1])),
+            write_range(&array_id, "x", Variant::new_i32_array(&[1])),
+        ]),
+    };
+    {
+        let session = st.session.read().unwrap();
+        let mut address_space = st.server.address_space.write().unwrap();
+        let response = AttributeService::new().write(&session, &mut address_space, request);
+        let response: WriteResponse = supported_message_as!(response.unwrap(), WriteResponse);
+        assert_eq!(response.results.unwrap(), vec![Good, BadIndexRangeNoData, BadIndexRangeInvalid, BadTypeMismatch, BadIndexRangeInvalid]);
+    }
+
+    // Only the elements in the range were written
+    let results = read_values(&st, vec![read_value(&array_id, AttributeId::Value)]);
+    assert_eq!(results[0].value.as_ref().unwrap(), &Variant::new_i32_array(&[0, 10, 20, 3, 4]));
+}
+
+/// Adds a historizing variable and sets values on it, one a second from midnight 1 Jan 2018
+fn add_historizing_variable(address_space: &mut AddressSpace, node_id: &NodeId, values: &[Int32]) {
+    let mut variable = Variable::new(node_id, "History", "History", "", 0 as Int32);
+    variable.set_historizing(true);
+    let _ = address_space.add_variable(variable, &AddressSpace::objects_folder_id());
+    let variable = address_space.find_variable_by_node_id(node_id).unwrap();
+    for (i, value) in values.iter().enumerate() {
+        let timestamp = DateTime::ymd_hms(2018, 1, 1, 0, 0, i as UInt16);
+        variable.set_value(DataValue {
+            value: Some(Variant::Int32(*value)),
+            status: Some(Good),
+            status_info_bits: 0,
+            source_timestamp: Some(timestamp.clone()),
+            source_picoseconds: Some(0),
+            server_timestamp: Some(timestamp),
+            server_picoseconds: Some(0),
+        });
+    }
+}
+
+fn history_read_value_id(node_id: &NodeId, continuation_point: ByteString) -> HistoryReadValueId {
+    HistoryReadValueId {
+        node_id: node_id.clone(),
+        index_range: UAString::null(),
+        data_encoding: QualifiedName::null(),
+        continuation_point,
+    }
+}
+
+fn history_read_response(st: &ServiceTest, history_read_details: ExtensionObject, release_continuation_points: bool, nodes_to_read: Vec<HistoryReadValueId>) -> SupportedMessage {
+    let ats = AttributeService::new();
+    let (_, mut session) = st.get_server_state_and_session();
+    let address_space = st.get_address_space();
+    let request = HistoryReadRequest {
+        request_header: make_request_header(),
+        history_read_details,
+        timestamps_to_return: TimestampsToReturn::Source,
+        release_continuation_points,
+        nodes_to_read: Some(nodes_to_read),
+    };
+    ats.history_read(&mut session, &address_space, request).unwrap()
+}
+
+fn history_read(st: &ServiceTest, details: ReadRawModifiedDetails, release_continuation_points: bool, nodes_to_read: Vec<HistoryReadValueId>) -> Vec<HistoryReadResult> {
+    let details = ExtensionObject::from_encodable(ObjectId::ReadRawModifiedDetails_Encoding_DefaultBinary, details);
+    let response: HistoryReadResponse = supported_message_as!(history_read_response(st, details, release_continuation_points, nodes_to_read), HistoryReadResponse);
+    response.results.unwrap()
+}
+
+fn read_raw_details(start_time: DateTime, end_time: DateTime, num_values_per_node: UInt32) -> ReadRawModifiedDetails {
+    ReadRawModifiedDetails {
+        is_read_modified: false,
+        start_time,
+        end_time,
+        num_values_per_node,
+        return_bounds: false,
+    }
+}
+
+fn history_values(result: &HistoryReadResult) -> Vec<Variant> {
+    let history_data = result.history_data.decode_inner::<HistoryData>().unwrap();
+    history_data.data_values.unwrap().into_iter().map(|v| v.value.unwrap()).collect()
+}
+
+fn int32_values(values: &[Int32]) -> Vec<Variant> {
+    values.iter().map(|v| Variant::Int32(*v)).collect()
+}
+
+#[test]
+fn history_read_raw_test() {
+    let st = ServiceTest::new();
+
+    let node_id = NodeId::new_string(1, "History");
+    let not_historizing_node_id = {
+        let mut address_space = st.get_address_space();
+        add_historizing_variable(&mut address_space, &node_id, &[1, 2, 3, 4, 5]);
+        let (_, node_ids) = add_many_vars_to_address_space(&mut address_space, 1);
+        node_ids[0].clone()
+    };
+
+    let start_time = DateTime::ymd_hms(2018, 1, 1, 0, 0, 0);
+    let end_time = DateTime::ymd_hms(2018, 1, 1, 0, 0, 3);
+
+    // Read values in the time range
+    let results = history_read(&st, read_raw_details(start_time.clone(), end_time.clone(), 0), false, vec![
+        // 1. a historizing variable
+        history_read_value_id(&node_id, ByteString::null()),
+        // 2. a variable that is not historizing
+        history_read_value_id(&not_historizing_node_id, ByteString::null()),
+        // 3. a node that does not exist
+        history_read_value_id(&NodeId::new_string(1, "Unknown"), ByteString::null()),
+    ]);
+    // 1. a historizing variable
+    assert_eq!(results[0].status_code, Good);
+    assert!(results[0].continuation_point.is_null());
+    assert_eq!(history_values(&results[0]), int32_values(&[1, 2, 3, 4]));
+    // 2. a variable that is not historizing
+    assert_eq!(results[1].status_code, BadUserAccessDenied);
+    // 3. a node that does not exist
+    assert_eq!(results[2].status_code, BadNodeIdUnknown);
+
+    // Read values newest first when the start time is after the end time
+    let results = history_read(&st, read_raw_details(end_time.clone(), start_time.clone(), 0), false, vec![history_read_value_id(&node_id, ByteString::null())]);
+    assert_eq!(history_values(&results[0]), int32_values(&[4, 3, 2, 1]));
+
+    // Read values from the start time onwards, two at a time
+    let results = history_read(&st, read_raw_details(start_time.clone(), DateTime::epoch(), 2), false, vec![history_read_value_id(&node_id, ByteString::null())]);
+    assert_eq!(history_values(&results[0]), int32_values(&[1, 2]));
+    let continuation_point = results[0].continuation_point.clone();
+    assert!(!continuation_point.is_null());
+
+    let results = history_read(&st, read_raw_details(start_time.clone(), DateTime::epoch(), 2), false, vec![history_read_value_id(&node_id, continuation_point.clone())]);
+    assert_eq!(history_values(&results[0]), int32_values(&[3, 4]));
+    let next_continuation_point = results[0].continuation_point.clone();
+    assert!(!next_continuation_point.is_null());
+
+    // A continuation point cannot be used twice
+    let results = history_read(&st, read_raw_details(start_time.clone(), DateTime::epoch(), 2), false, vec![history_read_value_id(&node_id, continuation_point)]);
+    assert_eq!(results[0].status_code, BadContinuationPointInvalid);
+
+    // Release the continuation point instead of reading the last value
+    let results = history_read(&st, read_raw_details(start_time.clone(), DateTime::epoch(), 2), true, vec![history_read_value_id(&node_id, next_continuation_point.clone())]);
+    assert_eq!(results[0].status_code, Good);
+    let results = history_read(&st, read_raw_details(start_time.clone(), DateTime::epoch(), 2), false, vec![history_read_value_id(&node_id, next_continuation_point)]);
+    assert_eq!(results[0].status_code, BadContinuationPointInvalid);
+
+    // Neither time is specified
+    let results = history_read(&st, read_raw_details(DateTime::epoch(), DateTime::epoch(), 2), false, vec![history_read_value_id(&node_id, ByteString::null())]);
+    assert_eq!(results[0].status_code, BadHistoryOperationInvalid);
+}
+
+#[test]
+fn history_diagnostics() {
+    let st = ServiceTest::new();
+    let node_id = NodeId::new_string(1, "History");
+    add_historizing_variable(&mut st.get_address_space(), &node_id, &[1, 2, 3]);
+    let ats = AttributeService::new();
+
+    // A diagnostic for the node that could not be read
+    let mut request_header = make_request_header();
+    request_header.return_diagnostics = DiagnosticFlags::OPERATIONS_LEVEL_SYMBOLIC_ID;
+    let request = HistoryReadRequest {
+        request_header,
+        history_read_details: ExtensionObject::from_encodable(ObjectId::ReadRawModifiedDetails_Encoding_DefaultBinary, read_raw_details(DateTime::ymd_hms(2018, 1, 1, 0, 0, 0), DateTime::ymd_hms(2018, 1, 1, 0, 0, 3), 0)),
+        timestamps_to_return: TimestampsToReturn::Source,
+        release_continuation_points: false,
+        nodes_to_read: Some(vec![
+            history_read_value_id(&node_id, ByteString::null()),
+            history_read_value_id(&NodeId::new_string(1, "Unknown"), ByteString::null()),
+        ]),
+    };
+    let response = {
+        let (_, mut session) = st.get_server_state_and_session();
+        let address_space = st.get_address_space();
+        supported_message_as!(ats.history_read(&mut session, &address_space, request).unwrap(), HistoryReadResponse)
+    };
+    let string_table = response.response_header.string_table.unwrap();
+    let diagnostic_infos = response.diagnostic_infos.unwrap();
+    assert_eq!(diagnostic_infos.len(), 2);
+    assert_eq!(diagnostic_infos[0], DiagnosticInfo::new());
+    assert_eq!(string_table[diagnostic_infos[1].symbolic_id.unwrap() as usize].as_ref(), "BadNodeIdUnknown");
+
+    // And for the node that could not be updated
+    let mut request_header = make_request_header();
+    request_header.return_diagnostics = DiagnosticFlags::OPERATIONS_LEVEL_SYMBOLIC_ID;
+    let details = DeleteRawModifiedDetails {
+        node_id: NodeId::new_string(1, "Unknown"),
+        is_delete_modified: false,
+        start_time: DateTime::ymd_hms(2018, 1, 1, 0, 0, 1),
+        end_time: DateTime::ymd_hms(2018, 1, 1, 0, 0, 2),
+    };
+    let request = HistoryUpdateRequest {
+        request_header,
+        history_update_details: Some(vec![ExtensionObject::from_encodable(ObjectId::DeleteRawModifiedDetails_Encoding_DefaultBinary, details)]),
+    };
+    let response = supported_message_as!(ats.history_update(&st.get_address_space(), request).unwrap(), HistoryUpdateResponse);
+    let string_table = response.response_header.string_table.unwrap();
+    let diagnostic_infos = response.diagnostic_infos.unwrap();
+    assert_eq!(diagnostic_infos.len(), 1);
+    assert_eq!(string_table[diagnostic_infos[0].symbolic_id.unwrap() as usize].as_ref(), "BadNodeIdUnknown");
+}
+
+#[test]
+fn history_update_delete_test() {
+    let st = ServiceTest::new();
+
+    let node_id = NodeId::new_string(1, "History");
+    {
+        let mut address_space = st.get_address_space();
+        add_historizing_variable(&mut address_space, &node_id, &[1, 2, 3, 4, 5]);
+    }
+
+    let history_update = |node_id: &NodeId, is_delete_modified: bool| {
+        let ats = AttributeService::new();
+        let address_space = st.get_address_space();
+        let details = DeleteRawModifiedDetails {
+            node_id: node_id.clone(),
+            is_delete_modified,
+            start_time: DateTime::ymd_hms(2018, 1, 1, 0, 0, 1),
+            end_time: DateTime::ymd_hms(2018, 1, 1, 0, 0, 2),
+        };
+        let request = HistoryUpdateRequest {
+            request_header: make_request_header(),
+            history_update_details: Some(vec![ExtensionObject::from_encodable(ObjectId::DeleteRawModifiedDetails_Encoding_DefaultBinary, details)]),
+        };
+        let response = ats.history_update(&address_space, request);
+        let response: HistoryUpdateResponse = supported_message_as!(response.unwrap(), HistoryUpdateResponse);
+        response.results.unwrap()[0].status_code
+    };
+
+    // Deletes need history write access
+    assert_eq!(history_update(&node_id, false), BadUserAccessDenied);
+    {
+        let mut address_space = st.get_address_space();
+        let variable = address_space.find_variable_by_node_id(&node_id).unwrap();
+        let user_access_level = variable.user_access_level() | user_access_level::HISTORY_WRITE;
+        variable.set_user_access_level(user_access_level);
+    }
+
+    // Delete the raw values, then delete them again
+    assert_eq!(history_update(&node_id, false), Good);
+    assert_eq!(history_update(&node_id, false), BadNoData);
+
+    let start_time = DateTime::ymd_hms(2018, 1, 1, 0, 0, 0);
+    let end_time = DateTime::ymd_hms(2018, 1, 1, 0, 0, 4);
+    let results = history_read(&st, read_raw_details(start_time.clone(), end_time.clone(), 0), false, vec![history_read_value_id(&node_id, ByteString::null())]);
+    assert_eq!(history_values(&results[0]), int32_values(&[1, 4, 5]));
+
+    // The deleted values are read back as modified values
+    let mut details = read_raw_details(start_time.clone(), end_time.clone(), 0);
+    details.is_read_modified = true;
+    let results = history_read(&st, details.clone(), false, vec![history_read_value_id(&node_id, ByteString::null())]);
+    let history_data = results[0].history_data.decode_inner::<HistoryModifiedData>().unwrap();
+    let values: Vec<Variant> = history_data.data_values.unwrap().into_iter().map(|v| v.value.unwrap()).collect();
+    assert_eq!(values, int32_values(&[2, 3]));
+    let modification_infos = history_data.modification_infos.unwrap();
+    assert!(modification_infos.iter().all(|m| m.update_type == HistoryUpdateType::Delete));
+
+    // Delete the modified values
+    assert_eq!(history_update(&node_id, true), Good);
+    let results = history_read(&st, details, false, vec![history_read_value_id(&node_id, ByteString::null())]);
+    assert_eq!(results[0].status_code, GoodNoData);
+
+    // A node that does not exist
+    assert_eq!(history_update(&NodeId::new_string(1, "Unknown"), false), BadNodeIdUnknown);
+}
+
+fn read_processed_details(start_time: DateTime, end_time: DateTime, processing_interval: Double, aggregate_type: Vec<NodeId>) -> ExtensionObject {
+    ExtensionObject::from_encodable(ObjectId::ReadProcessedDetails_Encoding_DefaultBinary, ReadProcessedDetails {
+        start_time,
+        end_time,
+        processing_interval,
+        aggregate_type: Some(aggregate_type),
+        aggregate_configuration: AggregateConfiguration {
+            use_server_capabilities_defaults: true,
+            treat_uncertain_as_bad: false,
+            percent_data_bad: 0,
+            percent_data_good: 0,
+            use_sloped_extrapolation: false,
+        },
+    })
+}
+
+fn history_data_values(result: &HistoryReadResult) -> Vec<DataValue> {
+    result.history_data.decode_inner::<HistoryData>().unwrap().data_values.unwrap()
+}
+
+#[test]
+fn history_read_processed_test() {
+    let st = ServiceTest::new();
+
+    let node_id = NodeId::new_string(1, "History");
+    {
+        let mut address_space = st.get_address_space();
+        add_historizing_variable(&mut address_space, &node_id, &[1, 2, 3, 4, 5]);
+    }
+
+    let average: NodeId = ObjectId::AggregateFunction_Average.into();
+    let read_processed = |start_time: DateTime, end_time: DateTime, processing_interval: Double, aggregate_type: NodeId| {
+        let details = read_processed_details(start_time, end_time, processing_interval, vec![aggregate_type]);
+        let response: HistoryReadResponse = supported_message_as!(history_read_response(&st, details, false, vec![history_read_value_id(&node_id, ByteString::null())]), HistoryReadResponse);
+        response.results.unwrap().remove(0)
+    };
+
+    let start_time = DateTime::ymd_hms(2018, 1, 1, 0, 0, 0);
+    let end_time = DateTime::ymd_hms(2018, 1, 1, 0, 0, 4);
+
+    // The average of each 2 second interval, timestamped with the start of the interval
+    let result = read_processed(start_time.clone(), end_time.clone(), 2000f64, average.clone());
+    assert_eq!(result.status_code, Good);
+    let values = history_data_values(&result);
+    assert_eq!(values.len(), 2);
+    assert_eq!(values[0].value, Some(Variant::Double(1.5f64)));
+    assert_eq!(values[0].status, Some(Good));
+    assert_eq!(values[0].status_info_bits, status_info_bits::INFO_TYPE_DATA_VALUE | historian_bits::CALCULATED as UInt16);
+    assert_eq!(values[0].source_timestamp, Some(start_time.clone()));
+    assert_eq!(values[1].value, Some(Variant::Double(3.5f64)));
+    assert_eq!(values[1].source_timestamp, Some(DateTime::ymd_hms(2018, 1, 1, 0, 0, 2)));
+
+    // Intervals run backwards from a start time after the end time
+    let result = read_processed(end_time.clone(), start_time.clone(), 2000f64, average.clone());
+    let values: Vec<Variant> = history_data_values(&result).into_iter().map(|v| v.value.unwrap()).collect();
+    assert_eq!(values, vec![Variant::Double(3.5f64), Variant::Double(1.5f64)]);
+
+    // A processing interval of zero is one interval over the whole range, and the last interval
+    // is cut short by the end time
+    let result = read_processed(start_time.clone(), end_time.clone(), 0f64, ObjectId::AggregateFunction_Count.into());
+    assert_eq!(history_values(&result), vec![Variant::Int32(4)]);
+    let result = read_processed(start_time.clone(), DateTime::ymd_hms(2018, 1, 1, 0, 0, 3), 2000f64, average.clone());
+    assert_eq!(history_values(&result), vec![Variant::Double(1.5f64), Variant::Double(3f64)]);
+    assert_eq!(history_data_values(&result)[1].status_info_bits, status_info_bits::INFO_TYPE_DATA_VALUE | (historian_bits::CALCULATED | historian_bits::PARTIAL) as UInt16);
+
+    // An aggregate that is not supported and a time that is not specified
+    let result = read_processed(start_time.clone(), end_time.clone(), 2000f64, NodeId::new(1, 100));
+    assert_eq!(result.status_code, BadAggregateNotSupported);
+    let result = read_processed(start_time.clone(), DateTime::epoch(), 2000f64, average.clone());
+    assert_eq!(result.status_code, BadHistoryOperationInvalid);
+
+    // Each node must have an aggregate
+    let details = read_processed_details(start_time.clone(), end_time.clone(), 2000f64, vec![average.clone()]);
+    let response = history_read_response(&st, details, false, vec![
+        history_read_value_id(&node_id, ByteString::null()),
+        history_read_value_id(&node_id, ByteString::null()),
+    ]);
+    assert_eq!(supported_message_as!(response, ServiceFault).response_header.service_result, BadAggregateListMismatch);
+}
+
+#[test]
+fn history_read_at_time_test() {
+    let st = ServiceTest::new();
+
+    let node_id = NodeId::new_string(1, "History");
+    {
+        let mut address_space = st.get_address_space();
+        add_historizing_variable(&mut address_space, &node_id, &[1, 2, 3, 4, 5]);
+    }
+
+    let at = |seconds: f64| DateTime::from(DateTime::ymd_hms(2018, 1, 1, 0, 0, 0).ticks() + (seconds * 10_000_000f64) as i64);
+    let read_at_time = |req_times: Vec<DateTime>, use_simple_bounds: bool| {
+        let details = ExtensionObject::from_encodable(ObjectId::ReadAtTimeDetails_Encoding_DefaultBinary, ReadAtTimeDetails {
+            req_times: Some(req_times),
+            use_simple_bounds,
+        });
+        let response: HistoryReadResponse = supported_message_as!(history_read_response(&st, details, false, vec![history_read_value_id(&node_id, ByteString::null())]), HistoryReadResponse);
+        history_data_values(&response.results.unwrap()[0])
+    };
+
+    // Values between raw values are interpolated, raw values are returned as they are and values
+    // after the last raw value are extrapolated as uncertain. There is nothing before the first.
+    let values = read_at_time(vec![at(0.5f64), at(2f64), at(10f64), at(-1f64)], false);
+    assert_eq!(values[0].value, Some(Variant::Double(1.5f64)));
+    assert_eq!(values[0].status, Some(Good));
+    assert_eq!(values[0].source_timestamp, Some(at(0.5f64)));
+    assert_eq!(values[1].value, Some(Variant::Int32(3)));
+    assert_eq!(values[1].status, Some(Good));
+    assert_eq!(values[2].value, Some(Variant::Int32(5)));
+    assert_eq!(values[2].status, Some(UncertainDataSubNormal));
+    assert_eq!(values[3].value, None);
+    assert_eq!(values[3].status, Some(BadNoData));
+
+    // Simple bounds hold the value before
+    let values = read_at_time(vec![at(0.5f64)], true);
+    assert_eq!(values[0].value, Some(Variant::Int32(1)));
+
+    // So do the values of a variable whose historical configuration says they are stepped
+    {
+        let mut address_space = st.get_address_space();
+        let configuration_id = NodeId::new_string(1, "HAConfiguration");
+        let stepped_id = NodeId::new_string(1, "Stepped");
+        address_space.insert(Object::new(&configuration_id, "HA Configuration", "HA Configuration", ""));
+        address_space.insert_reference(&node_id, &configuration_id, ReferenceTypeId::HasHistoricalConfiguration);
+        address_space.insert(Variable::new(&stepped_id, "Stepped", "Stepped", "", true));
+        address_space.add_has_property(&configuration_id, &stepped_id);
+    }
+    let values = read_at_time(vec![at(0.5f64)], false);
+    assert_eq!(values[0].value, Some(Variant::Int32(1)));
+}
+
+/// A setter that fails with a diagnostic describing the fault of the device it writes to
+struct FailingSetter;
+
+impl AttributeSetter for FailingSetter {
+    fn set(&mut self, node_id: NodeId, attribute_id: AttributeId, data_value: DataValue) -> Result<(), StatusCode> {
+        self.set_with_diagnostic(node_id, attribute_id, data_value).map_err(|diagnostic| diagnostic.status_code)
+    }
+
+    fn set_with_diagnostic(&mut self, _node_id: NodeId, _attribute_id: AttributeId, _data_value: DataValue) -> Result<(), Diagnostic> {
+        Err(Diagnostic::new(BadDeviceFailure)
+            .with_symbolic_id("urn:pump", "PumpOffline")
+            .with_additional_info("Pump 3 did not respond"))
+    }
+}
+
+#[test]
+fn write_diagnostics_test() {
+    let st = ServiceTest::new();
+
+    let node_id = NodeId::new_string(1, "pump");
+    {
+        let mut address_space = st.get_address_space();
+        let mut variable = Variable::new(&node_id, "pump", "pump", "", 0 as Int32);
+        variable.set_access_level(access_level::CURRENT_READ | access_level::CURRENT_WRITE);
+        variable.set_user_access_level(user_access_level::CURRENT_READ | user_access_level::CURRENT_WRITE);
+        variable.set_value_setter(Arc::new(Mutex::new(FailingSetter)));
+        let _ = address_space.add_variable(variable, &AddressSpace::objects_folder_id());
+    }
+
+    let write = |return_diagnostics: UInt32| {
+        let mut request_header = make_request_header();
+        request_header.return_diagnostics = return_diagnostics;
+        let request = WriteRequest {
+            request_header,
+            nodes_to_write: Some(vec![
+                write_value(&node_id, AttributeId::Value, DataValue::new(1 as Int32)),
+                write_value(&NodeId::new_string(1, "vxxx"), AttributeId::Value, DataValue::new(1 as Int32)),
+            ]),
+        };
+        let session = st.session.read().unwrap();
+        let mut address_space = st.get_address_space();
+        let response = AttributeService::new().write(&session, &mut address_space, request);
+        let response: WriteResponse = supported_message_as!(response.unwrap(), WriteResponse);
+        response
+    };
+
+    // No diagnostics unless they are asked for
+    let response = write(0);
+    assert_eq!(response.results.unwrap(), vec![BadDeviceFailure, BadNodeIdUnknown]);
+    assert!(response.diagnostic_infos.is_none());
+    assert!(response.response_header.string_table.is_none());
+
+    // Operation level symbolic ids and additional info
+    let response = write(DiagnosticFlags::OPERATIONS_LEVEL_SYMBOLIC_ID | DiagnosticFlags::OPERATIONS_LEVEL_ADDITIONAL_INFO);
+    let string_table = response.response_header.string_table.unwrap();
+    let diagnostic_infos = response.diagnostic_infos.unwrap();
+    assert_eq!(diagnostic_infos.len(), 2);
+    let string = |idx: Option<Int32>| string_table[idx.unwrap() as usize].as_ref().to_string();
+
+    // The setter's diagnostic
+    assert_eq!(string(diagnostic_infos[0].symbolic_id), "PumpOffline");
+    assert_eq!(string(diagnostic_infos[0].namespace_uri), "urn:pump");
+    assert_eq!(diagnostic_infos[0].additional_info, Some(UAString::from("Pump 3 did not respond")));
+    assert!(diagnostic_infos[0].localized_text.is_none());
+
+    // A diagnostic made from the status code
+    assert_eq!(string(diagnostic_infos[1].symbolic_id), "BadNodeIdUnknown");
+    assert_eq!(string(diagnostic_infos[1].namespace_uri), "http://opcfoundation.org/UA/");
+    assert!(diagnostic_infos[1].additional_info.is_none());
+}
+
+#[test]
+fn service_fault_diagnostics_test() {
+    let st = ServiceTest::new();
+    let mut request_header = make_request_header();
+    request_header.return_diagnostics = DiagnosticFlags::SERVICE_LEVEL_SYMBOLIC_ID | DiagnosticFlags::SERVICE_LEVEL_LOCALIZED_TEXT;
+    let request = ReadRequest {
+        request_header,
+        max_age: 0f64,
+        timestamps_to_return: TimestampsToReturn::Both,
+        nodes_to_read: None,
+    };
+    let session = st.session.read().unwrap();
+    let address_space = st.server.address_space.read().unwrap();
+    let response = AttributeService::new().read(&session, &address_space, request);
+    let response: ServiceFault = supported_message_as!(response.unwrap(), ServiceFault);
+    let response_header = response.response_header;
+    assert_eq!(response_header.service_result, BadNothingToDo);
+    let string_table = response_header.string_table.unwrap();
+    let service_diagnostics = response_header.service_diagnostics;
+    assert_eq!(string_table[service_diagnostics.symbolic_id.unwrap() as usize].as_ref(), "BadNothingToDo");
+    assert_eq!(string_table[service_diagnostics.localized_text.unwrap() as usize].as_ref(), BadNothingToDo.description());
+    assert!(service_diagnostics.locale.is_none());
+}
+
+#[test]
+fn read_write_diagnostics_access() {
+    let st = ServiceTest::new();
+    let security_diagnostics_id: NodeId = VariableId::Server_ServerDiagnostics_SessionsDiagnosticsSummary_SessionSecurityDiagnosticsArray.into();
+
+    // Anonymous users cannot read the security diagnostics of sessions, which their user access
+    // level says, but can read the other attributes of the node
+    let results = read_values(&st, vec![
+        read_value(&security_diagnostics_id, AttributeId::Value),
+        read_value(&security_diagnostics_id, AttributeId::UserAccessLevel),
+        read_value(&security_diagnostics_id, AttributeId::BrowseName),
+    ]);
+    assert_eq!(results[0].status, Some(BadUserAccessDenied));
+    assert!(results[0].value.is_none());
+    assert_eq!(results[1].value, Some(Variant::Byte(0)));
+    assert!(results[2].value.is_some());
+
+    // Other users can
+    st.session.write().unwrap().user_token_id = Some("sample_user".to_string());
+    let results = read_values(&st, vec![
+        read_value(&security_diagnostics_id, AttributeId::Value),
+        read_value(&security_diagnostics_id, AttributeId::UserAccessLevel),
+    ]);
+    assert_ne!(results[0].status, Some(BadUserAccessDenied));
+    assert!(results[0].value.is_some());
+    assert_eq!(results[1].value, Some(Variant::Byte(user_access_level::CURRENT_READ)));
+
+    // No user can turn diagnostics off
+    let request = WriteRequest {
+        request_header: make_request_header(),
+        nodes_to_write: Some(vec![write_value(&VariableId::Server_ServerDiagnostics_EnabledFlag.into(), AttributeId::Value, DataValue::new(false))]),
+    };
+    let session = st.session.read().unwrap();
+    let mut address_space = st.get_address_space();
+    let response = AttributeService::new().write(&session, &mut address_space, request);
+    let response: WriteResponse = supported_message_as!(response.unwrap(), WriteResponse);
+    assert_eq!(response.results.unwrap()[0], BadUserAccessDenied);
+}
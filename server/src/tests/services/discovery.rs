@@ -1,9 +1,81 @@
+use chrono;
+use time;
+
 use opcua_core;
-use opcua_types::UAString;
+use opcua_core::comms::secure_channel::SecureChannel;
+use opcua_core::crypto::{CertificateStore, SecurityPolicy, X509Data};
+use opcua_types::{MessageSecurityMode, UAString};
+use opcua_types::node_ids::ObjectId;
 
 use super::*;
+use constants;
+use discovery::mdns;
+use discovery::registered_servers::RegisteredServers;
 use services::discovery::DiscoveryService;
 
+fn make_registered_server(server_uri: &str) -> RegisteredServer {
+    RegisteredServer {
+        server_uri: UAString::from(server_uri),
+        product_uri: UAString::from("urn:product"),
+        server_names: Some(vec![LocalizedText::new("en", "Server"), LocalizedText::new("de", "Dienst")]),
+        server_type: ApplicationType::Server,
+        gateway_server_uri: UAString::null(),
+        discovery_urls: Some(vec![UAString::from("opc.tcp://localhost:4856/")]),
+        semaphore_file_path: UAString::null(),
+        is_online: true,
+    }
+}
+
+/// Makes a secure channel opened by the server with the application uri
+fn make_secure_channel(server_uri: &str, security_mode: MessageSecurityMode) -> SecureChannel {
+    let args = X509Data {
+        key_size: 1024,
+        common_name: "x".to_string(),
+        organization: "x.org".to_string(),
+        organizational_unit: "x.org ops".to_string(),
+        country: "EN".to_string(),
+        state: "London".to_string(),
+        alt_host_names: vec![server_uri.to_string(), "localhost".to_string()],
+        certificate_duration_days: 60,
+    };
+    let (cert, _) = CertificateStore::create_cert_and_pkey(&args).unwrap();
+    let mut secure_channel: SecureChannel = (SecurityPolicy::None, MessageSecurityMode::None).into();
+    secure_channel.set_security_mode(security_mode);
+    secure_channel.set_remote_cert(Some(cert));
+    secure_channel
+}
+
+fn register_server(ds: &DiscoveryService, server_state: &mut ServerState, secure_channel: &SecureChannel, server: RegisteredServer) -> SupportedMessage {
+    let request = RegisterServerRequest {
+        request_header: make_request_header(),
+        server,
+    };
+    ds.register_server(server_state, secure_channel, request).unwrap()
+}
+
+fn find_servers(ds: &DiscoveryService, server_state: &mut ServerState, locale_ids: Option<Vec<UAString>>, server_uris: Option<Vec<UAString>>) -> Vec<ApplicationDescription> {
+    let request = FindServersRequest {
+        request_header: make_request_header(),
+        endpoint_url: UAString::null(),
+        locale_ids,
+        server_uris,
+    };
+    let response = ds.find_servers(server_state, request).unwrap();
+    let response = supported_message_as!(response, FindServersResponse);
+    response.servers.unwrap()
+}
+
+fn expect_register_fault(ds: &DiscoveryService, server_state: &mut ServerState, secure_channel: &SecureChannel, server: RegisteredServer, expected: StatusCode) {
+    let response = register_server(ds, server_state, secure_channel, server);
+    let response = supported_message_as!(response, ServiceFault);
+    assert_eq!(response.response_header.service_result, expected);
+}
+
+fn set_discovery_server(server_state: &mut ServerState) {
+    let mut config = server_state.config.write().unwrap();
+    config.discovery_server = true;
+}
+
 #[test]
 fn get_endpoints() {
     opcua_core::init_logging();
@@ -88,4 +160,273 @@ fn discovery_test() {
         let endpoints = result.endpoints.unwrap();
         assert!(!endpoints.is_empty())
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn find_servers_returns_self() {
+    let st = ServiceTest::new();
+    let (mut server_state, _) = st.get_server_state_and_session();
+    let ds = DiscoveryService::new();
+
+    let servers = find_servers(&ds, &mut server_state, None, None);
+    assert_eq!(servers.len(), 1);
+    assert_eq!(servers[0].application_uri, UAString::from("urn:foo"));
+    assert_eq!(servers[0].application_type, ApplicationType::Server);
+
+    // Filtered out by server uri
+    let servers = find_servers(&ds, &mut server_state, None, Some(vec![UAString::from("urn:xyz")]));
+    assert!(servers.is_empty());
+}
+
+#[test]
+fn register_server_unsupported() {
+    let st = ServiceTest::new();
+    let (mut server_state, _) = st.get_server_state_and_session();
+    let ds = DiscoveryService::new();
+
+    // A server that is not a discovery server does not accept registrations
+    let secure_channel = make_secure_channel("urn:server1", MessageSecurityMode::SignAndEncrypt);
+    let response = register_server(&ds, &mut server_state, &secure_channel, make_registered_server("urn:server1"));
+    let response = supported_message_as!(response, ServiceFault);
+    assert_eq!(response.response_header.service_result, BadServiceUnsupported);
+    assert!(server_state.registered_servers.is_empty());
+}
+
+#[test]
+fn register_and_find_servers() {
+    let st = ServiceTest::new();
+    let (mut server_state, _) = st.get_server_state_and_session();
+    let ds = DiscoveryService::new();
+    set_discovery_server(&mut server_state);
+    let secure_channel1 = make_secure_channel("urn:server1", MessageSecurityMode::SignAndEncrypt);
+    let secure_channel2 = make_secure_channel("urn:server2", MessageSecurityMode::SignAndEncrypt);
+
+    let response = register_server(&ds, &mut server_state, &secure_channel1, make_registered_server("urn:server1"));
+    let _ = supported_message_as!(response, RegisterServerResponse);
+    let response = register_server(&ds, &mut server_state, &secure_channel2, make_registered_server("urn:server2"));
+    let _ = supported_message_as!(response, RegisterServerResponse);
+    assert_eq!(server_state.registered_servers.len(), 2);
+
+    // This server describes itself as a discovery server, followed by the registered servers
+    let servers = find_servers(&ds, &mut server_state, None, None);
+    assert_eq!(servers.len(), 3);
+    assert_eq!(servers[0].application_type, ApplicationType::DiscoveryServer);
+    assert_eq!(servers[1].application_uri, UAString::from("urn:server1"));
+    assert_eq!(servers[1].application_name, LocalizedText::new("en", "Server"));
+    assert_eq!(servers[1].discovery_urls, Some(vec![UAString::from("opc.tcp://localhost:4856/")]));
+    assert_eq!(servers[2].application_uri, UAString::from("urn:server2"));
+
+    // Filter by server uri and pick names by locale
+    let servers = find_servers(&ds, &mut server_state, Some(vec![UAString::from("fr"), UAString::from("de")]), Some(vec![UAString::from("urn:server2")]));
+    assert_eq!(servers.len(), 1);
+    assert_eq!(servers[0].application_uri, UAString::from("urn:server2"));
+    assert_eq!(servers[0].application_name, LocalizedText::new("de", "Dienst"));
+
+    // A server that goes offline is unregistered
+    let mut server = make_registered_server("urn:server1");
+    server.is_online = false;
+    let response = register_server(&ds, &mut server_state, &secure_channel1, server);
+    let _ = supported_message_as!(response, RegisterServerResponse);
+    assert_eq!(server_state.registered_servers.len(), 1);
+    assert!(server_state.registered_servers.get("urn:server1").is_none());
+}
+
+#[test]
+fn register_server_invalid() {
+    let st = ServiceTest::new();
+    let (mut server_state, _) = st.get_server_state_and_session();
+    let ds = DiscoveryService::new();
+    set_discovery_server(&mut server_state);
+    let secure_channel = make_secure_channel("urn:server1", MessageSecurityMode::SignAndEncrypt);
+
+    let server = make_registered_server("");
+    expect_register_fault(&ds, &mut server_state, &secure_channel, server, BadServerUriInvalid);
+
+    let mut server = make_registered_server("urn:server1");
+    server.server_names = None;
+    expect_register_fault(&ds, &mut server_state, &secure_channel, server, BadServerNameMissing);
+
+    let mut server = make_registered_server("urn:server1");
+    server.discovery_urls = Some(vec![]);
+    expect_register_fault(&ds, &mut server_state, &secure_channel, server, BadDiscoveryUrlMissing);
+
+    let mut server = make_registered_server("urn:server1");
+    server.server_type = ApplicationType::Client;
+    expect_register_fault(&ds, &mut server_state, &secure_channel, server, BadInvalidArgument);
+
+    assert!(server_state.registered_servers.is_empty());
+}
+
+#[test]
+fn register_server_expires() {
+    let st = ServiceTest::new();
+    let (mut server_state, _) = st.get_server_state_and_session();
+    let ds = DiscoveryService::new();
+    set_discovery_server(&mut server_state);
+
+    let secure_channel = make_secure_channel("urn:server1", MessageSecurityMode::SignAndEncrypt);
+    let _ = register_server(&ds, &mut server_state, &secure_channel, make_registered_server("urn:server1"));
+    assert_eq!(server_state.registered_servers.len(), 1);
+
+    // Nothing expires before the expiry period
+    let now = chrono::Utc::now();
+    server_state.registered_servers.remove_expired(&(now + time::Duration::milliseconds(constants::DISCOVERY_REGISTRATION_EXPIRY_MS / 2)));
+    assert_eq!(server_state.registered_servers.len(), 1);
+
+    // Registration is dropped after it
+    server_state.registered_servers.remove_expired(&(now + time::Duration::milliseconds(constants::DISCOVERY_REGISTRATION_EXPIRY_MS + 1000)));
+    assert!(server_state.registered_servers.is_empty());
+    let servers = find_servers(&ds, &mut server_state, None, None);
+    assert_eq!(servers.len(), 1);
+}
+
+#[test]
+fn register_server_insecure() {
+    let st = ServiceTest::new();
+    let (mut server_state, _) = st.get_server_state_and_session();
+    let ds = DiscoveryService::new();
+    set_discovery_server(&mut server_state);
+
+    // The secure channel must sign and encrypt
+    for security_mode in &[MessageSecurityMode::None, MessageSecurityMode::Sign] {
+        let secure_channel = make_secure_channel("urn:server1", *security_mode);
+        expect_register_fault(&ds, &mut server_state, &secure_channel, make_registered_server("urn:server1"), BadSecurityChecksFailed);
+    }
+
+    // A server cannot register another server
+    let secure_channel = make_secure_channel("urn:server2", MessageSecurityMode::SignAndEncrypt);
+    expect_register_fault(&ds, &mut server_state, &secure_channel, make_registered_server("urn:server1"), BadSecurityChecksFailed);
+
+    // Nor without a certificate
+    let mut secure_channel: SecureChannel = (SecurityPolicy::None, MessageSecurityMode::None).into();
+    secure_channel.set_security_mode(MessageSecurityMode::SignAndEncrypt);
+    expect_register_fault(&ds, &mut server_state, &secure_channel, make_registered_server("urn:server1"), BadSecurityChecksFailed);
+
+    assert!(server_state.registered_servers.is_empty());
+}
+
+#[test]
+fn register_server_limit() {
+    let mut registered_servers = RegisteredServers::new();
+    let now = chrono::Utc::now();
+    for i in 0..constants::MAX_REGISTERED_SERVERS {
+        assert!(registered_servers.register(make_registered_server(&format!("urn:server{}", i)), None, &now).is_ok());
+    }
+
+    // No more servers can register, though registered servers can register again
+    let server = make_registered_server(&format!("urn:server{}", constants::MAX_REGISTERED_SERVERS));
+    assert_eq!(registered_servers.register(server, None, &now), Err(BadTooManyOperations));
+    assert!(registered_servers.register(make_registered_server("urn:server0"), None, &now).is_ok());
+    assert_eq!(registered_servers.len(), constants::MAX_REGISTERED_SERVERS);
+}
+
+#[test]
+fn register_server2() {
+    let st = ServiceTest::new();
+    let (mut server_state, _) = st.get_server_state_and_session();
+    let ds = DiscoveryService::new();
+    set_discovery_server(&mut server_state);
+
+    let mdns_configuration = MdnsDiscoveryConfiguration {
+        mdns_server_name: UAString::from("Server1"),
+        server_capabilities: Some(vec![UAString::from("DA")]),
+    };
+    let request = RegisterServer2Request {
+        request_header: make_request_header(),
+        server: make_registered_server("urn:server1"),
+        discovery_configuration: Some(vec![
+            ExtensionObject::from_encodable(ObjectId::MdnsDiscoveryConfiguration_Encoding_DefaultBinary, mdns_configuration.clone()),
+            ExtensionObject::from_encodable(ObjectId::ReadRequest_Encoding_DefaultBinary, mdns_configuration.clone()),
+        ]),
+    };
+    let secure_channel = make_secure_channel("urn:server1", MessageSecurityMode::SignAndEncrypt);
+    let response = ds.register_server2(&mut server_state, &secure_channel, request).unwrap();
+    let response = supported_message_as!(response, RegisterServer2Response);
+    assert_eq!(response.configuration_results, Some(vec![Good, BadNotSupported]));
+
+    let registration = server_state.registered_servers.get("urn:server1").unwrap();
+    assert_eq!(registration.mdns_configuration, Some(mdns_configuration));
+}
+
+#[test]
+fn register_server2_diagnostics() {
+    let st = ServiceTest::new();
+    let (mut server_state, _) = st.get_server_state_and_session();
+    let ds = DiscoveryService::new();
+    set_discovery_server(&mut server_state);
+
+    // A diagnostic for the discovery configuration that is not supported
+    let mdns_configuration = MdnsDiscoveryConfiguration {
+        mdns_server_name: UAString::from("Server1"),
+        server_capabilities: None,
+    };
+    let mut request_header = make_request_header();
+    request_header.return_diagnostics = DiagnosticFlags::OPERATIONS_LEVEL_SYMBOLIC_ID;
+    let request = RegisterServer2Request {
+        request_header,
+        server: make_registered_server("urn:server1"),
+        discovery_configuration: Some(vec![
+            ExtensionObject::from_encodable(ObjectId::MdnsDiscoveryConfiguration_Encoding_DefaultBinary, mdns_configuration.clone()),
+            ExtensionObject::from_encodable(ObjectId::ReadRequest_Encoding_DefaultBinary, mdns_configuration),
+        ]),
+    };
+    let secure_channel = make_secure_channel("urn:server1", MessageSecurityMode::SignAndEncrypt);
+    let response = supported_message_as!(ds.register_server2(&mut server_state, &secure_channel, request).unwrap(), RegisterServer2Response);
+    let string_table = response.response_header.string_table.unwrap();
+    let diagnostic_infos = response.diagnostic_infos.unwrap();
+    assert_eq!(diagnostic_infos.len(), 2);
+    assert_eq!(diagnostic_infos[0], DiagnosticInfo::new());
+    assert_eq!(string_table[diagnostic_infos[1].symbolic_id.unwrap() as usize].as_ref(), "BadNotSupported");
+}
+
+#[test]
+fn find_servers_on_network() {
+    let st = ServiceTest::new();
+    let (mut server_state, _) = st.get_server_state_and_session();
+    let ds = DiscoveryService::new();
+
+    let request = FindServersOnNetworkRequest {
+        request_header: make_request_header(),
+        starting_record_id: 0,
+        max_records_to_return: 0,
+        server_capability_filter: None,
+    };
+
+    // The service needs multicast discovery
+    let response = ds.find_servers_on_network(&mut server_state, request.clone()).unwrap();
+    let response = supported_message_as!(response, ServiceFault);
+    assert_eq!(response.response_header.service_result, BadServiceUnsupported);
+
+    {
+        let mut config = server_state.config.write().unwrap();
+        config.multicast_discovery = true;
+        config.server_capabilities = vec!["DA".to_string()];
+    }
+
+    // The server caches itself when it announces itself
+    let services = mdns::services(&server_state);
+    assert_eq!(services.len(), 1);
+    assert_eq!(services[0].capabilities, vec!["DA".to_string()]);
+    let now = chrono::Utc::now();
+    server_state.servers_on_network.update(&services[0], constants::MDNS_RECORD_TTL, &now);
+
+    let response = ds.find_servers_on_network(&mut server_state, request).unwrap();
+    let response = supported_message_as!(response, FindServersOnNetworkResponse);
+    assert_eq!(response.last_counter_reset_time, server_state.servers_on_network.last_counter_reset_time());
+    let servers = response.servers.unwrap();
+    assert_eq!(servers.len(), 1);
+    assert_eq!(servers[0].record_id, 1);
+    assert_eq!(servers[0].server_name, UAString::from("foo"));
+    assert_eq!(servers[0].discovery_url, UAString::from("opc.tcp://127.0.0.1:4855/"));
+
+    // Filtered out by capability
+    let request = FindServersOnNetworkRequest {
+        request_header: make_request_header(),
+        starting_record_id: 0,
+        max_records_to_return: 0,
+        server_capability_filter: Some(vec![UAString::from("LDS")]),
+    };
+    let response = ds.find_servers_on_network(&mut server_state, request).unwrap();
+    let response = supported_message_as!(response, FindServersOnNetworkResponse);
+    assert!(response.servers.unwrap().is_empty());
+}
@@ -0,0 +1,296 @@
+use std::sync::{Arc, Mutex, RwLock};
+
+use super::*;
+use services::method::MethodService;
+use diagnostics::ServerDiagnostics;
+
+fn argument(name: &str, data_type: DataTypeId) -> Argument {
+    Argument {
+        name: UAString::from(name),
+        data_type: data_type.into(),
+        value_rank: -1,
+        array_dimensions: None,
+        description: LocalizedText::new("", name),
+    }
+}
+
+fn call_method_request(object_id: &NodeId, method_id: &NodeId, input_arguments: Option<Vec<Variant>>) -> CallMethodRequest {
+    CallMethodRequest {
+        object_id: object_id.clone(),
+        method_id: method_id.clone(),
+        input_arguments,
+    }
+}
+
+/// Adds an object with an "Add" method that sums two Int32 arguments
+fn add_calculator_to_address_space(address_space: &mut AddressSpace) -> (NodeId, NodeId) {
+    let object_id = NodeId::new_string(1, "Calculator");
+    let _ = address_space.add_organized_node(&object_id, "Calculator", "Calculator", &AddressSpace::objects_folder_id(), ObjectTypeId::BaseObjectType);
+
+    let method_id = NodeId::new_string(1, "Add");
+    let mut method = Method::new(&method_id, "Add", "Add", "", false, true, true);
+    let callback = MethodFnCallback::new(|_: &Session, _: &mut AddressSpace, _: &NodeId, input_arguments: &[Variant]| -> Result<Vec<Variant>, StatusCode> {
+        match (&input_arguments[0], &input_arguments[1]) {
+            (&Variant::Int32(a), &Variant::Int32(b)) => Ok(vec![Variant::Int32(a + b)]),
+            _ => Err(BadInvalidArgument)
+        }
+    });
+    method.set_callback(Arc::new(Mutex::new(callback)));
+
+    let input_arguments = [argument("a", DataTypeId::Int32), argument("b", DataTypeId::Int32)];
+    let output_arguments = [argument("sum", DataTypeId::Int32)];
+    let _ = address_space.add_method(method, &object_id, &input_arguments, &output_arguments);
+
+    (object_id, method_id)
+}
+
+#[test]
+fn call_test() {
+    let st = ServiceTest::new();
+
+    let (object_id, method_id) = {
+        let mut address_space = st.get_address_space();
+        add_calculator_to_address_space(&mut address_space)
+    };
+
+    {
+        let address_space = st.get_address_space();
+        let input_arguments = address_space.find_method_input_arguments(&method_id).unwrap();
+        assert_eq!(input_arguments.len(), 2);
+        assert_eq!(input_arguments[0].name.as_ref(), "a");
+        let output_arguments = address_space.find_method_output_arguments(&method_id).unwrap();
+        assert_eq!(output_arguments.len(), 1);
+        assert!(address_space.is_method_of_object(&object_id, &method_id));
+        assert!(!address_space.is_method_of_object(&AddressSpace::objects_folder_id(), &method_id));
+    }
+
+    let ms = MethodService::new();
+
+    let methods_to_call = vec![
+        // 1. a valid call
+        call_method_request(&object_id, &method_id, Some(vec![Variant::Int32(3), Variant::Int32(4)])),
+        // 2. an unknown object
+        call_method_request(&NodeId::new_string(1, "Unknown"), &method_id, Some(vec![Variant::Int32(3), Variant::Int32(4)])),
+        // 3. an unknown method
+        call_method_request(&object_id, &NodeId::new_string(1, "Unknown"), None),
+        // 4. a method that does not belong to the object
+        call_method_request(&AddressSpace::objects_folder_id(), &method_id, Some(vec![Variant::Int32(3), Variant::Int32(4)])),
+        // 5. too few arguments
+        call_method_request(&object_id, &method_id, Some(vec![Variant::Int32(3)])),
+        // 6. too many arguments
+        call_method_request(&object_id, &method_id, Some(vec![Variant::Int32(3), Variant::Int32(4), Variant::Int32(5)])),
+        // 7. an argument of the wrong type
+        call_method_request(&object_id, &method_id, Some(vec![Variant::Int32(3), Variant::from("4")])),
+    ];
+
+    let (_, mut session) = st.get_server_state_and_session();
+    let mut address_space = st.get_address_space();
+
+    let request = CallRequest {
+        request_header: make_request_header(),
+        methods_to_call: Some(methods_to_call),
+    };
+    let response = ms.call(&mut session, &mut address_space, request);
+    assert!(response.is_ok());
+    let response: CallResponse = supported_message_as!(response.unwrap(), CallResponse);
+    let results = response.results.unwrap();
+
+    // 1. a valid call
+    assert_eq!(results[0].status_code, Good);
+    assert_eq!(results[0].output_arguments.as_ref().unwrap(), &vec![Variant::Int32(7)]);
+
+    // 2. an unknown object
+    assert_eq!(results[1].status_code, BadNodeIdUnknown);
+
+    // 3. an unknown method
+    assert_eq!(results[2].status_code, BadMethodInvalid);
+
+    // 4. a method that does not belong to the object
+    assert_eq!(results[3].status_code, BadMethodInvalid);
+
+    // 5. too few arguments
+    assert_eq!(results[4].status_code, BadArgumentsMissing);
+
+    // 6. too many arguments
+    assert_eq!(results[5].status_code, BadTooManyArguments);
+
+    // 7. an argument of the wrong type
+    assert_eq!(results[6].status_code, BadInvalidArgument);
+    assert_eq!(results[6].input_argument_results.as_ref().unwrap(), &vec![Good, BadTypeMismatch]);
+    assert!(results[6].output_arguments.is_none());
+}
+
+#[test]
+fn call_nothing_to_do() {
+    let st = ServiceTest::new();
+    let ms = MethodService::new();
+
+    let (_, mut session) = st.get_server_state_and_session();
+    let mut address_space = st.get_address_space();
+
+    let request = CallRequest {
+        request_header: make_request_header(),
+        methods_to_call: None,
+    };
+    let response = ms.call(&mut session, &mut address_space, request);
+    let response: ServiceFault = supported_message_as!(response.unwrap(), ServiceFault);
+    assert_eq!(response.response_header.service_result, BadNothingToDo);
+}
+
+/// Adds a tank with a level variable and an exclusive limit alarm on the level
+fn add_level_alarm_to_address_space(address_space: &mut AddressSpace) -> (NodeId, NodeId) {
+    let tank_id = NodeId::new_string(1, "Tank");
+    let _ = address_space.add_organized_node(&tank_id, "Tank", "Tank", &AddressSpace::objects_folder_id(), ObjectTypeId::BaseObjectType);
+    let level_id = NodeId::new_string(1, "Level");
+    let _ = address_space.add_variable(Variable::new(&level_id, "Level", "Level", "", 50f64), &tank_id);
+    let alarm_id = NodeId::new_string(1, "LevelAlarm");
+    let limits = Limits { high_high: Some(95f64), high: Some(80f64), low: Some(20f64), low_low: None };
+    let alarm = Condition::new_exclusive_limit_alarm(&alarm_id, "LevelAlarm", &tank_id, "Tank", 700, &level_id, limits);
+    assert!(address_space.add_condition(alarm).is_ok());
+    (level_id, alarm_id)
+}
+
+fn call_methods(st: &ServiceTest, methods_to_call: Vec<CallMethodRequest>) -> Vec<CallMethodResult> {
+    let ms = MethodService::new();
+    let (_, mut session) = st.get_server_state_and_session();
+    let mut address_space = st.get_address_space();
+    let request = CallRequest {
+        request_header: make_request_header(),
+        methods_to_call: Some(methods_to_call),
+    };
+    let response: CallResponse = supported_message_as!(ms.call(&mut session, &mut address_space, request).unwrap(), CallResponse);
+    response.results.unwrap()
+}
+
+fn comment_arguments(event_id: &ByteString, comment: &str) -> Option<Vec<Variant>> {
+    Some(vec![Variant::from(event_id.clone()), Variant::from(LocalizedText::new("", comment))])
+}
+
+#[test]
+fn limit_alarm_test() {
+    let st = ServiceTest::new();
+    let (level_id, alarm_id) = add_level_alarm_to_address_space(&mut st.get_address_space());
+
+    let mut address_space = st.get_address_space();
+    assert!(address_space.has_limit_alarms());
+    {
+        let alarm = address_space.find_condition(&alarm_id).unwrap();
+        assert!(alarm.enabled && !alarm.active && alarm.acked && !alarm.retain);
+        assert_eq!(alarm.limit_state, None);
+    }
+
+    // The alarm follows the level from one limit to another
+    assert!(address_space.set_value_by_node_id(&level_id, Variant::Double(85f64)));
+    {
+        let alarm = address_space.find_condition(&alarm_id).unwrap();
+        assert!(alarm.active && !alarm.acked && !alarm.confirmed && alarm.retain);
+        assert_eq!(alarm.limit_state, Some(LimitState::High));
+    }
+    assert!(address_space.set_value_by_node_id(&level_id, Variant::Double(99f64)));
+    assert_eq!(address_space.find_condition(&alarm_id).unwrap().limit_state, Some(LimitState::HighHigh));
+    assert!(address_space.set_value_by_node_id(&level_id, Variant::Double(10f64)));
+    assert_eq!(address_space.find_condition(&alarm_id).unwrap().limit_state, Some(LimitState::Low));
+    assert!(address_space.set_value_by_node_id(&level_id, Variant::Double(50f64)));
+    {
+        let alarm = address_space.find_condition(&alarm_id).unwrap();
+        assert!(!alarm.active && !alarm.acked && alarm.retain);
+        assert_eq!(alarm.limit_state, None);
+    }
+
+    // The condition's variables mirror its state
+    let active_state_id = address_space.find_nodes_relative_path(&alarm_id, &RelativePath {
+        elements: Some(vec![RelativePathElement {
+            reference_type_id: ReferenceTypeId::HasComponent.into(),
+            is_inverse: false,
+            include_subtypes: true,
+            target_name: QualifiedName::new(0, "AckedState"),
+        }]),
+    }).unwrap();
+    assert_eq!(active_state_id.len(), 1);
+    if let Some(&NodeType::Variable(ref variable)) = address_space.find_node(&active_state_id[0]) {
+        assert_eq!(variable.value().value.unwrap(), Variant::from(LocalizedText::new("", "Unacknowledged")));
+    } else {
+        panic!("Expected an AckedState variable");
+    }
+}
+
+#[test]
+fn condition_methods_test() {
+    let st = ServiceTest::new();
+    let (level_id, alarm_id) = add_level_alarm_to_address_space(&mut st.get_address_space());
+    let event_id = {
+        let mut address_space = st.get_address_space();
+        let _ = address_space.set_value_by_node_id(&level_id, Variant::Double(85f64));
+        address_space.find_condition(&alarm_id).unwrap().event_id.clone()
+    };
+
+    let acknowledge_id: NodeId = MethodId::AcknowledgeableConditionType_Acknowledge.into();
+    let confirm_id: NodeId = MethodId::AcknowledgeableConditionType_Confirm.into();
+    let results = call_methods(&st, vec![
+        // An unknown event cannot be acknowledged
+        call_method_request(&alarm_id, &acknowledge_id, comment_arguments(&ByteString::from(vec![1u8, 2u8]), ""))
+    ]);
+    assert_eq!(results[0].status_code, BadEventIdUnknown);
+
+    // Acknowledging produces a new event so the next call uses the new event id
+    let results = call_methods(&st, vec![call_method_request(&alarm_id, &acknowledge_id, comment_arguments(&event_id, "On it"))]);
+    assert_eq!(results[0].status_code, Good);
+    let event_id = {
+        let address_space = st.get_address_space();
+        let alarm = address_space.find_condition(&alarm_id).unwrap();
+        assert!(alarm.acked && !alarm.confirmed && alarm.retain);
+        assert_eq!(alarm.comment.text.as_ref(), "On it");
+        alarm.event_id.clone()
+    };
+    let results = call_methods(&st, vec![
+        call_method_request(&alarm_id, &acknowledge_id, comment_arguments(&event_id, "")),
+        call_method_request(&alarm_id, &confirm_id, comment_arguments(&event_id, "")),
+    ]);
+    assert_eq!(results[0].status_code, BadConditionBranchAlreadyAcked);
+    assert_eq!(results[1].status_code, Good);
+
+    // Once inactive, acknowledged and confirmed the condition is no longer retained
+    {
+        let mut address_space = st.get_address_space();
+        let _ = address_space.set_value_by_node_id(&level_id, Variant::Double(50f64));
+        assert!(!address_space.find_condition(&alarm_id).unwrap().retain);
+    }
+
+    // Disable and enable
+    let disable_id: NodeId = MethodId::ConditionType_Disable.into();
+    let enable_id: NodeId = MethodId::ConditionType_Enable.into();
+    let results = call_methods(&st, vec![
+        call_method_request(&alarm_id, &disable_id, None),
+        call_method_request(&alarm_id, &disable_id, None),
+        call_method_request(&alarm_id, &acknowledge_id, comment_arguments(&event_id, "")),
+        call_method_request(&alarm_id, &enable_id, None),
+        // Conditions methods can't be called on other objects
+        call_method_request(&AddressSpace::objects_folder_id(), &enable_id, None),
+    ]);
+    assert_eq!(results[0].status_code, Good);
+    assert_eq!(results[1].status_code, BadConditionAlreadyDisabled);
+    assert_eq!(results[2].status_code, BadConditionDisabled);
+    assert_eq!(results[3].status_code, Good);
+    assert_eq!(results[4].status_code, BadMethodInvalid);
+}
+
+#[test]
+fn condition_refresh_test() {
+    let st = ServiceTest::new();
+    let _ = add_level_alarm_to_address_space(&mut st.get_address_space());
+    {
+        let mut session = st.session.write().unwrap();
+        let subscription = Subscription::new(Arc::new(RwLock::new(ServerDiagnostics::new())), 100, true, 100f64, 300, 100, 0, 0);
+        session.subscriptions.insert(100, subscription);
+    }
+
+    // A session can only refresh the conditions of its own subscriptions
+    let condition_type_id: NodeId = ObjectTypeId::ConditionType.into();
+    let condition_refresh_id: NodeId = MethodId::ConditionType_ConditionRefresh.into();
+    let results = call_methods(&st, vec![
+        call_method_request(&condition_type_id, &condition_refresh_id, Some(vec![Variant::UInt32(100)])),
+        call_method_request(&condition_type_id, &condition_refresh_id, Some(vec![Variant::UInt32(101)])),
+    ]);
+    assert_eq!(results[0].status_code, Good);
+    assert_eq!(results[1].status_code, BadSubscriptionIdInvalid);
+}
@@ -68,7 +68,10 @@ fn add_many_vars_to_address_space(address_space: &mut AddressSpace, vars_to_add:
 
 pub mod attribute;
 pub mod discovery;
+pub mod method;
 pub mod session;
 pub mod monitored_item;
+pub mod node_management;
+pub mod query;
 pub mod subscription;
 pub mod view;
\ No newline at end of file
@@ -1,6 +1,14 @@
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use chrono;
+use time;
 
 use prelude::*;
+use DateTimeUtc;
+use address_space::event_notifier;
+use aggregates;
+use diagnostics::ServerDiagnostics;
 use super::*;
 
 fn test_var_node_id() -> NodeId {
@@ -50,6 +58,7 @@ fn data_change_filter_test() {
     let mut v1 = DataValue {
         value: None,
         status: None,
+        status_info_bits: 0,
         source_timestamp: None,
         source_picoseconds: None,
         server_timestamp: None,
@@ -59,6 +68,7 @@ fn data_change_filter_test() {
     let mut v2 = DataValue {
         value: None,
         status: None,
+        status_info_bits: 0,
         source_timestamp: None,
         source_picoseconds: None,
         server_timestamp: None,
@@ -110,6 +120,7 @@ fn data_change_deadband_abs_test() {
     let v1 = DataValue {
         value: Some(Variant::Double(10f64)),
         status: None,
+        status_info_bits: 0,
         source_timestamp: None,
         source_picoseconds: None,
         server_timestamp: None,
@@ -119,6 +130,7 @@ fn data_change_deadband_abs_test() {
     let mut v2 = DataValue {
         value: Some(Variant::Double(10f64)),
         status: None,
+        status_info_bits: 0,
         source_timestamp: None,
         source_picoseconds: None,
         server_timestamp: None,
@@ -141,6 +153,54 @@ fn data_change_deadband_abs_test() {
     assert_eq!(filter.compare(&v1, &v2, None), false);
 }
 
+#[test]
+fn data_change_deadband_pct_test() {
+    let filter = DataChangeFilter {
+        trigger: DataChangeTrigger::StatusValue,
+        // Percent compare
+        deadband_type: 2,
+        deadband_value: 10f64,
+    };
+
+    let v1 = Variant::Double(10f64);
+    let v2 = Variant::Double(19f64);
+
+    // A percent deadband needs a valid range
+    assert_eq!(filter.compare_value(&v1, &v2, None), Err(BadDeadbandFilterInvalid));
+    assert_eq!(filter.compare_value(&v1, &v2, Some((100f64, 0f64))), Err(BadDeadbandFilterInvalid));
+
+    // 9% of the range is within the deadband, 11% is not
+    assert_eq!(filter.compare_value(&v1, &v2, Some((0f64, 100f64))), Ok(true));
+    assert_eq!(filter.compare_value(&v1, &Variant::Double(21f64), Some((0f64, 100f64))), Ok(false));
+}
+
+#[test]
+fn data_change_deadband_array_test() {
+    let filter = DataChangeFilter {
+        trigger: DataChangeTrigger::StatusValue,
+        deadband_type: 1,
+        deadband_value: 1f64,
+    };
+
+    // Every element must be within the deadband for the arrays to be the same
+    let v1 = Variant::new_i32_array(&[10, 20, 30]);
+    assert_eq!(filter.compare_value(&v1, &Variant::new_i32_array(&[11, 19, 30]), None), Ok(true));
+    assert_eq!(filter.compare_value(&v1, &Variant::new_i32_array(&[11, 22, 30]), None), Ok(false));
+
+    // Arrays of a different length always differ
+    assert_eq!(filter.compare_value(&v1, &Variant::new_i32_array(&[10, 20]), None), Ok(false));
+
+    // Percent deadband applies to each element against the range
+    let filter = DataChangeFilter {
+        trigger: DataChangeTrigger::StatusValue,
+        deadband_type: 2,
+        deadband_value: 10f64,
+    };
+    assert_eq!(filter.compare_value(&v1, &Variant::new_i32_array(&[15, 25, 35]), Some((0f64, 100f64))), Ok(true));
+    assert_eq!(filter.compare_value(&v1, &Variant::new_i32_array(&[15, 25, 45]), Some((0f64, 100f64))), Ok(false));
+    assert_eq!(filter.compare_value(&v1, &Variant::new_i32_array(&[15, 25, 35]), None), Err(BadDeadbandFilterInvalid));
+}
+
 // Straight tests of abs function
 #[test]
 fn deadband_abs() {
@@ -169,7 +229,7 @@ fn monitored_item_data_change_filter() {
 
     // Create request should monitor attribute of variable, e.g. value
     // Sample interval is negative so it will always test on repeated calls
-    let mut monitored_item = MonitoredItem::new(1, TimestampsToReturn::Both, &make_create_request(-1f64, 5)).unwrap();
+    let mut monitored_item = MonitoredItem::new(&address_space, 1, TimestampsToReturn::Both, &make_create_request(-1f64, 5)).unwrap();
 
     let now = chrono::Utc::now();
 
@@ -198,9 +258,183 @@ fn monitored_item_data_change_filter() {
     assert_eq!(monitored_item.notification_queue.len(), 2);
 }
 
+fn value_listener_count(address_space: &AddressSpace, node_id: &NodeId) -> usize {
+    let value_changes = address_space.value_changes();
+    let value_changes = value_changes.lock().unwrap();
+    value_changes.listener_count(node_id)
+}
+
+#[test]
+fn monitored_item_value_changes() {
+    let mut address_space = make_address_space();
+    let now = chrono::Utc::now();
+
+    // An item with a sampling interval of zero reports every value set on the variable
+    let mut monitored_item = MonitoredItem::new(&address_space, 1, TimestampsToReturn::Both, &make_create_request(0f64, 5)).unwrap();
+    assert_eq!(monitored_item.sampling_interval, 0f64);
+    assert_eq!(value_listener_count(&address_space, &test_var_node_id()), 1);
+    assert_eq!(monitored_item.tick(&address_space, &now, TickReason::TickTimerFired), true);
+    set_test_var_value(&mut address_space, 1);
+    set_test_var_value(&mut address_space, 2);
+    set_test_var_value(&mut address_space, 3);
+    assert_eq!(monitored_item.tick(&address_space, &now, TickReason::TickTimerFired), true);
+    assert_eq!(monitored_item.notification_queue.len(), 4);
+    assert_eq!(monitored_item.tick(&address_space, &now, TickReason::TickTimerFired), false);
+
+    // Other items report the latest value set since they last sampled
+    let mut sampling_item = MonitoredItem::new(&address_space, 2, TimestampsToReturn::Both, &make_create_request(-1f64, 5)).unwrap();
+    assert_eq!(value_listener_count(&address_space, &test_var_node_id()), 2);
+    assert_eq!(sampling_item.tick(&address_space, &now, TickReason::TickTimerFired), true);
+    set_test_var_value(&mut address_space, 4);
+    set_test_var_value(&mut address_space, 5);
+    assert_eq!(sampling_item.tick(&address_space, &now, TickReason::TickTimerFired), true);
+    assert_eq!(sampling_item.notification_queue.len(), 2);
+    if let Some(&Notification::MonitoredItemNotification(ref notification)) = sampling_item.notification_queue.back() {
+        assert_eq!(notification.value.value, Some(Variant::UInt32(5)));
+    } else {
+        panic!("Expected a data change notification");
+    }
+
+    // Items stop listening when they are dropped
+    drop(monitored_item);
+    drop(sampling_item);
+    assert_eq!(value_listener_count(&address_space, &test_var_node_id()), 0);
+
+    // Values supplied by a getter are sampled
+    let getter_id = NodeId::new(1, 2);
+    let mut variable = Variable::new(&getter_id, "getter", "getter", "", 0 as UInt32);
+    variable.set_value_getter(Arc::new(Mutex::new(AttrFnGetter::new(|_, _| -> Result<Option<DataValue>, StatusCode> {
+        Ok(Some(DataValue::new(1 as UInt32)))
+    }))));
+    let _ = address_space.add_variable(variable, &AddressSpace::objects_folder_id());
+    let mut request = make_create_request(0f64, 5);
+    request.item_to_monitor.node_id = getter_id.clone();
+    let mut monitored_item = MonitoredItem::new(&address_space, 3, TimestampsToReturn::Both, &request).unwrap();
+    assert_eq!(monitored_item.sampling_interval, ::constants::MIN_SAMPLING_INTERVAL);
+    assert_eq!(value_listener_count(&address_space, &getter_id), 0);
+    let now = chrono::Utc::now() + time::Duration::seconds(1);
+    assert_eq!(monitored_item.tick(&address_space, &now, TickReason::TickTimerFired), true);
+}
+
+fn sampled_item_count(address_space: &AddressSpace, node_id: &NodeId, sampling_interval: Double) -> usize {
+    let sampler = address_space.sampler();
+    let mut sampler = sampler.lock().unwrap();
+    sampler.item_count(node_id, AttributeId::Value, &UAString::null(), sampling_interval)
+}
+
+#[test]
+fn monitored_item_shared_samples() {
+    let mut address_space = make_address_space();
+
+    // A getter that counts the times it is called
+    let getter_id = NodeId::new(1, 2);
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut variable = Variable::new(&getter_id, "getter", "getter", "", 0 as UInt32);
+    {
+        let calls = calls.clone();
+        variable.set_value_getter(Arc::new(Mutex::new(AttrFnGetter::new(move |_, _| -> Result<Option<DataValue>, StatusCode> {
+            Ok(Some(DataValue::new(calls.fetch_add(1, Ordering::SeqCst) as UInt32)))
+        }))));
+    }
+    let _ = address_space.add_variable(variable, &AddressSpace::objects_folder_id());
+    let make_request = |sampling_interval: Duration| {
+        let mut request = make_create_request(sampling_interval, 5);
+        request.item_to_monitor.node_id = getter_id.clone();
+        request
+    };
+
+    // Items of different sessions sampling at the same interval share each sample
+    let mut item1 = MonitoredItem::new(&address_space, 1, TimestampsToReturn::Both, &make_request(500f64)).unwrap();
+    let mut item2 = MonitoredItem::new(&address_space, 1, TimestampsToReturn::Both, &make_request(500f64)).unwrap();
+    let mut item3 = MonitoredItem::new(&address_space, 2, TimestampsToReturn::Both, &make_request(1000f64)).unwrap();
+    assert_eq!(sampled_item_count(&address_space, &getter_id, 500f64), 2);
+    assert_eq!(sampled_item_count(&address_space, &getter_id, 1000f64), 1);
+
+    let now = chrono::Utc::now() + time::Duration::seconds(1);
+    assert_eq!(item1.tick(&address_space, &now, TickReason::TickTimerFired), true);
+    assert_eq!(item2.tick(&address_space, &now, TickReason::TickTimerFired), true);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert_eq!(item3.tick(&address_space, &now, TickReason::TickTimerFired), true);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    // The getter is called again once the interval has elapsed
+    let now = now + time::Duration::milliseconds(500);
+    assert_eq!(item2.tick(&address_space, &now, TickReason::TickTimerFired), true);
+    assert_eq!(item1.tick(&address_space, &now, TickReason::TickTimerFired), true);
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+    if let Some(&Notification::MonitoredItemNotification(ref notification)) = item1.notification_queue.back() {
+        assert_eq!(notification.value.value, Some(Variant::UInt32(2)));
+    } else {
+        panic!("Expected a data change notification");
+    }
+
+    // The sampling interval diagnostics count the items, and those disabled
+    item3.set_monitoring_mode(MonitoringMode::Disabled);
+    let diagnostics = address_space.sampler().lock().unwrap().sampling_interval_diagnostics();
+    assert_eq!(diagnostics, vec![
+        SamplingIntervalDiagnosticsDataType {
+            sampling_interval: 500f64,
+            monitored_item_count: 2,
+            max_monitored_item_count: 2,
+            disabled_monitored_item_count: 0,
+        },
+        SamplingIntervalDiagnosticsDataType {
+            sampling_interval: 1000f64,
+            monitored_item_count: 1,
+            max_monitored_item_count: 1,
+            disabled_monitored_item_count: 1,
+        },
+    ]);
+
+    // Items stop sharing samples when they are dropped
+    drop(item1);
+    drop(item3);
+    assert_eq!(sampled_item_count(&address_space, &getter_id, 500f64), 1);
+    let diagnostics = address_space.sampler().lock().unwrap().sampling_interval_diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].monitored_item_count, 1);
+    assert_eq!(diagnostics[0].max_monitored_item_count, 2);
+    drop(item2);
+    assert_eq!(sampled_item_count(&address_space, &getter_id, 500f64), 0);
+}
+
+#[test]
+fn monitored_item_sampling_diagnostics() {
+    let address_space = make_address_space();
+    let diagnostics = |address_space: &AddressSpace| address_space.sampler().lock().unwrap().sampling_interval_diagnostics();
+
+    // A sampling interval that is not a number is revised to the fastest the server supports
+    let item1 = MonitoredItem::new(&address_space, 1, TimestampsToReturn::Both, &make_create_request(::std::f64::NAN, 5)).unwrap();
+    assert_eq!(item1.sampling_interval, ::constants::MIN_SAMPLING_INTERVAL);
+
+    // Items listening for the values set on their variable are counted
+    let item2 = MonitoredItem::new(&address_space, 2, TimestampsToReturn::Both, &make_create_request(500f64, 5)).unwrap();
+    let item3 = MonitoredItem::new(&address_space, 3, TimestampsToReturn::Both, &make_create_request(500f64, 5)).unwrap();
+    assert_eq!(value_listener_count(&address_space, &test_var_node_id()), 3);
+    let diagnostics1 = diagnostics(&address_space);
+    assert_eq!(diagnostics1.len(), 2);
+    assert_eq!(diagnostics1[0].sampling_interval, ::constants::MIN_SAMPLING_INTERVAL);
+    assert_eq!(diagnostics1[0].monitored_item_count, 1);
+    assert_eq!(diagnostics1[1].sampling_interval, 500f64);
+    assert_eq!(diagnostics1[1].monitored_item_count, 2);
+    assert_eq!(diagnostics1[1].max_monitored_item_count, 2);
+
+    // The most items of an interval are forgotten once no item samples at it
+    drop(item1);
+    drop(item2);
+    drop(item3);
+    assert!(diagnostics(&address_space).is_empty());
+    let _item4 = MonitoredItem::new(&address_space, 4, TimestampsToReturn::Both, &make_create_request(500f64, 5)).unwrap();
+    let diagnostics2 = diagnostics(&address_space);
+    assert_eq!(diagnostics2.len(), 1);
+    assert_eq!(diagnostics2[0].monitored_item_count, 1);
+    assert_eq!(diagnostics2[0].max_monitored_item_count, 1);
+}
+
 fn populate_monitored_item(discard_oldest: bool) -> MonitoredItem {
     let client_handle = 999;
-    let mut monitored_item = MonitoredItem::new(1, TimestampsToReturn::Both, &make_create_request(-1f64, 5)).unwrap();
+    let address_space = make_address_space();
+    let mut monitored_item = MonitoredItem::new(&address_space, 1, TimestampsToReturn::Both, &make_create_request(-1f64, 5)).unwrap();
     monitored_item.discard_oldest = discard_oldest;
     for i in 0..5 {
         monitored_item.enqueue_notification_message(MonitoredItemNotification {
@@ -219,7 +453,11 @@ fn populate_monitored_item(discard_oldest: bool) -> MonitoredItem {
 }
 
 fn assert_first_notification_is_i32(monitored_item: &mut MonitoredItem, value: i32) {
-    assert_eq!(monitored_item.remove_first_notification_message().unwrap().value.value.unwrap(), Variant::Int32(value));
+    if let Notification::MonitoredItemNotification(notification) = monitored_item.remove_first_notification_message().unwrap() {
+        assert_eq!(notification.value.value.unwrap(), Variant::Int32(value));
+    } else {
+        panic!("Expected a data change notification");
+    }
 }
 
 #[test]
@@ -247,3 +485,497 @@ fn monitored_item_queue_discard_oldest() {
         assert_first_notification_is_i32(&mut monitored_item, 10);
     }
 }
+
+fn boiler_id() -> NodeId {
+    NodeId::new_string(1, "Boiler")
+}
+
+/// Adds a boiler object that clients can subscribe to events from, and a pump object whose
+/// events are notified through the boiler
+fn make_event_address_space() -> AddressSpace {
+    let mut address_space = AddressSpace::new();
+    let _ = address_space.add_organized_node(&boiler_id(), "Boiler", "Boiler", &AddressSpace::objects_folder_id(), ObjectTypeId::BaseObjectType);
+    if let &mut NodeType::Object(ref mut boiler) = address_space.find_node_mut(&boiler_id()).unwrap() {
+        boiler.set_event_notifier(event_notifier::SUBSCRIBE_TO_EVENTS);
+    }
+    let pump_id = NodeId::new_string(1, "Pump");
+    let _ = address_space.add_organized_node(&pump_id, "Pump", "Pump", &boiler_id(), ObjectTypeId::BaseObjectType);
+    address_space.insert_reference(&boiler_id(), &pump_id, ReferenceTypeId::HasEventSource);
+    let _ = address_space.add_organized_node(&NodeId::new_string(1, "Tank"), "Tank", "Tank", &AddressSpace::objects_folder_id(), ObjectTypeId::BaseObjectType);
+    address_space
+}
+
+fn select_clause(name: &str) -> SimpleAttributeOperand {
+    SimpleAttributeOperand {
+        type_definition_id: ObjectTypeId::BaseEventType.into(),
+        browse_path: Some(vec![QualifiedName::new(0, name)]),
+        attribute_id: AttributeId::Value as UInt32,
+        index_range: UAString::null(),
+    }
+}
+
+fn make_event_filter(select_clauses: Vec<SimpleAttributeOperand>) -> ExtensionObject {
+    ExtensionObject::from_encodable(ObjectId::EventFilter_Encoding_DefaultBinary, EventFilter {
+        select_clauses: Some(select_clauses),
+        where_clause: ContentFilter {
+            elements: None,
+        },
+    })
+}
+
+fn make_event_create_request(node_id: &NodeId, attribute_id: AttributeId, filter: ExtensionObject) -> MonitoredItemCreateRequest {
+    MonitoredItemCreateRequest {
+        item_to_monitor: ReadValueId {
+            node_id: node_id.clone(),
+            attribute_id: attribute_id as UInt32,
+            index_range: UAString::null(),
+            data_encoding: QualifiedName::null(),
+        },
+        monitoring_mode: MonitoringMode::Reporting,
+        requested_parameters: MonitoringParameters {
+            client_handle: 999,
+            sampling_interval: -1f64,
+            filter,
+            queue_size: 0,
+            discard_oldest: true,
+        },
+    }
+}
+
+fn boiler_event_filter() -> ExtensionObject {
+    let mut invalid_clause = select_clause("Severity");
+    invalid_clause.attribute_id = 999;
+    make_event_filter(vec![
+        select_clause("EventType"),
+        select_clause("SourceNode"),
+        select_clause("Message"),
+        select_clause("Severity"),
+        select_clause("Temperature"),
+        invalid_clause,
+    ])
+}
+
+fn event_fields(monitored_item: &mut MonitoredItem) -> Vec<Variant> {
+    if let Notification::EventFieldList(event_field_list) = monitored_item.remove_first_notification_message().unwrap() {
+        assert_eq!(event_field_list.client_handle, 999);
+        event_field_list.event_fields.unwrap()
+    } else {
+        panic!("Expected an event notification");
+    }
+}
+
+#[test]
+fn monitored_item_event_filter() {
+    let mut address_space = make_event_address_space();
+    let now = chrono::Utc::now();
+
+    let mut monitored_item = MonitoredItem::new(&address_space, 1, TimestampsToReturn::Both, &make_event_create_request(&boiler_id(), AttributeId::EventNotifier, boiler_event_filter())).unwrap();
+    assert_eq!(monitored_item.sampling_interval, 0f64);
+    assert_eq!(monitored_item.queue_size, ::constants::DEFAULT_EVENT_QUEUE_SIZE);
+    let filter_result = monitored_item.filter_result.decode_inner::<EventFilterResult>().unwrap();
+    assert_eq!(filter_result.select_clause_results.unwrap(), vec![Good, Good, Good, Good, Good, BadAttributeIdInvalid]);
+
+    // No events yet
+    assert_eq!(monitored_item.tick(&address_space, &now, TickReason::TickTimerFired), false);
+
+    // An event on the boiler is delivered
+    let mut event = BaseEventType::new(ObjectTypeId::BaseEventType, &boiler_id(), "Boiler", "Boiler is overheating", 900);
+    event.set_field(&[QualifiedName::new(0, "Temperature")], 120.5f64);
+    assert!(address_space.raise_event(event).is_ok());
+    assert_eq!(monitored_item.tick(&address_space, &now, TickReason::TickTimerFired), true);
+    let fields = event_fields(&mut monitored_item);
+    assert_eq!(fields[0], Variant::from(Into::<NodeId>::into(ObjectTypeId::BaseEventType)));
+    assert_eq!(fields[1], Variant::from(boiler_id()));
+    assert_eq!(fields[2], Variant::from(LocalizedText::new("", "Boiler is overheating")));
+    assert_eq!(fields[3], Variant::UInt16(900));
+    assert_eq!(fields[4], Variant::Double(120.5f64));
+    assert_eq!(fields[5], Variant::StatusCode(BadAttributeIdInvalid));
+
+    // The event is only delivered once
+    assert_eq!(monitored_item.tick(&address_space, &now, TickReason::TickTimerFired), false);
+
+    // An event on the pump is delivered because the boiler is its event source, but not an event on the tank
+    let event = BaseEventType::new(ObjectTypeId::AuditEventType, &NodeId::new_string(1, "Pump"), "Pump", "Pump stopped", 100);
+    assert!(address_space.raise_event(event).is_ok());
+    let event = BaseEventType::new(ObjectTypeId::BaseEventType, &NodeId::new_string(1, "Tank"), "Tank", "Tank is full", 100);
+    assert!(address_space.raise_event(event).is_ok());
+    assert_eq!(monitored_item.tick(&address_space, &now, TickReason::TickTimerFired), true);
+    assert_eq!(monitored_item.notification_queue.len(), 1);
+    let fields = event_fields(&mut monitored_item);
+    assert_eq!(fields[0], Variant::from(Into::<NodeId>::into(ObjectTypeId::AuditEventType)));
+    assert_eq!(fields[4], Variant::Empty);
+
+    // Events can't be raised on nodes that do not exist
+    let event = BaseEventType::new(ObjectTypeId::BaseEventType, &NodeId::new_string(1, "Unknown"), "Unknown", "", 100);
+    assert_eq!(address_space.raise_event(event).unwrap_err(), BadNodeIdUnknown);
+}
+
+#[test]
+fn monitored_item_event_filter_server() {
+    // The server object is the notifier of all events
+    let mut address_space = make_event_address_space();
+    let now = chrono::Utc::now();
+    let mut monitored_item = MonitoredItem::new(&address_space, 1, TimestampsToReturn::Both, &make_event_create_request(&ObjectId::Server.into(), AttributeId::EventNotifier, boiler_event_filter())).unwrap();
+    let event = BaseEventType::new(ObjectTypeId::BaseEventType, &NodeId::new_string(1, "Tank"), "Tank", "Tank is full", 100);
+    assert!(address_space.raise_event(event).is_ok());
+    assert_eq!(monitored_item.tick(&address_space, &now, TickReason::TickTimerFired), true);
+    assert_eq!(event_fields(&mut monitored_item)[1], Variant::from(NodeId::new_string(1, "Tank")));
+
+    // A node that is not an event notifier delivers nothing
+    let mut monitored_item = MonitoredItem::new(&address_space, 1, TimestampsToReturn::Both, &make_event_create_request(&NodeId::new_string(1, "Tank"), AttributeId::EventNotifier, boiler_event_filter())).unwrap();
+    let event = BaseEventType::new(ObjectTypeId::BaseEventType, &NodeId::new_string(1, "Tank"), "Tank", "Tank is full", 100);
+    assert!(address_space.raise_event(event).is_ok());
+    assert_eq!(monitored_item.tick(&address_space, &now, TickReason::TickTimerFired), false);
+}
+
+#[test]
+fn monitored_item_event_filter_not_allowed() {
+    let address_space = make_event_address_space();
+    // An event filter on a value
+    let request = make_event_create_request(&test_var_node_id(), AttributeId::Value, boiler_event_filter());
+    assert_eq!(MonitoredItem::new(&address_space, 1, TimestampsToReturn::Both, &request).unwrap_err().0, BadFilterNotAllowed);
+
+    // No event filter on an event notifier
+    let request = make_event_create_request(&boiler_id(), AttributeId::EventNotifier, ExtensionObject::null());
+    assert_eq!(MonitoredItem::new(&address_space, 1, TimestampsToReturn::Both, &request).unwrap_err().0, BadFilterNotAllowed);
+
+    // An event filter that selects nothing
+    let request = make_event_create_request(&boiler_id(), AttributeId::EventNotifier, make_event_filter(vec![]));
+    assert_eq!(MonitoredItem::new(&address_space, 1, TimestampsToReturn::Both, &request).unwrap_err().0, BadEventFilterInvalid);
+}
+
+#[test]
+fn monitored_item_event_filter_where_clause() {
+    let mut address_space = make_event_address_space();
+    let now = chrono::Utc::now();
+
+    // Only events with a severity of at least 500 are delivered
+    let severity = ExtensionObject::from_encodable(ObjectId::SimpleAttributeOperand_Encoding_DefaultBinary, select_clause("Severity"));
+    let limit = ExtensionObject::from_encodable(ObjectId::LiteralOperand_Encoding_DefaultBinary, LiteralOperand { value: Variant::Int32(500) });
+    let filter = ExtensionObject::from_encodable(ObjectId::EventFilter_Encoding_DefaultBinary, EventFilter {
+        select_clauses: Some(vec![select_clause("Message")]),
+        where_clause: ContentFilter {
+            elements: Some(vec![ContentFilterElement {
+                filter_operator: FilterOperator::GreaterThanOrEqual,
+                filter_operands: Some(vec![severity.clone(), limit]),
+            }]),
+        },
+    });
+    let mut monitored_item = MonitoredItem::new(&address_space, 1, TimestampsToReturn::Both, &make_event_create_request(&boiler_id(), AttributeId::EventNotifier, filter)).unwrap();
+
+    let event = BaseEventType::new(ObjectTypeId::BaseEventType, &boiler_id(), "Boiler", "Boiler is warm", 100);
+    assert!(address_space.raise_event(event).is_ok());
+    let event = BaseEventType::new(ObjectTypeId::BaseEventType, &boiler_id(), "Boiler", "Boiler is overheating", 900);
+    assert!(address_space.raise_event(event).is_ok());
+    assert_eq!(monitored_item.tick(&address_space, &now, TickReason::TickTimerFired), true);
+    assert_eq!(monitored_item.notification_queue.len(), 1);
+    assert_eq!(event_fields(&mut monitored_item)[0], Variant::from(LocalizedText::new("", "Boiler is overheating")));
+
+    // A where clause with the wrong number of operands is rejected with the reason
+    let filter = ExtensionObject::from_encodable(ObjectId::EventFilter_Encoding_DefaultBinary, EventFilter {
+        select_clauses: Some(vec![select_clause("Message")]),
+        where_clause: ContentFilter {
+            elements: Some(vec![ContentFilterElement {
+                filter_operator: FilterOperator::GreaterThanOrEqual,
+                filter_operands: Some(vec![severity]),
+            }]),
+        },
+    });
+    let (status_code, filter_result) = MonitoredItem::new(&address_space, 1, TimestampsToReturn::Both, &make_event_create_request(&boiler_id(), AttributeId::EventNotifier, filter)).unwrap_err();
+    assert_eq!(status_code, BadEventFilterInvalid);
+    let filter_result = filter_result.decode_inner::<EventFilterResult>().unwrap();
+    assert_eq!(filter_result.where_clause_result.element_results.unwrap()[0].status_code, BadFilterOperandCountMismatch);
+}
+
+#[test]
+fn monitored_item_condition_refresh() {
+    let mut address_space = make_event_address_space();
+    let now = chrono::Utc::now();
+    let alarm_id = NodeId::new_string(1, "BoilerAlarm");
+    let alarm = Condition::new(&alarm_id, ObjectTypeId::AlarmConditionType, "BoilerAlarm", &boiler_id(), "Boiler", 800);
+    assert!(address_space.add_condition(alarm).is_ok());
+    assert!(address_space.update_condition(&alarm_id, |alarm| {
+        alarm.set_active(true, "Boiler is overheating");
+        Ok(())
+    }).is_ok());
+
+    let mut condition_id_clause = select_clause("EventType");
+    condition_id_clause.type_definition_id = ObjectTypeId::ConditionType.into();
+    condition_id_clause.browse_path = None;
+    condition_id_clause.attribute_id = AttributeId::NodeId as UInt32;
+    let filter = make_event_filter(vec![select_clause("EventType"), condition_id_clause]);
+    let mut refreshed_item = MonitoredItem::new(&address_space, 1, TimestampsToReturn::Both, &make_event_create_request(&boiler_id(), AttributeId::EventNotifier, filter.clone())).unwrap();
+    refreshed_item.set_subscription_id(1);
+    let mut other_item = MonitoredItem::new(&address_space, 2, TimestampsToReturn::Both, &make_event_create_request(&boiler_id(), AttributeId::EventNotifier, filter)).unwrap();
+    other_item.set_subscription_id(2);
+
+    // Only the subscription that asked for the refresh is sent the state of the retained alarm
+    address_space.refresh_conditions(1);
+    assert_eq!(refreshed_item.tick(&address_space, &now, TickReason::TickTimerFired), true);
+    assert_eq!(other_item.tick(&address_space, &now, TickReason::TickTimerFired), false);
+    let event_types: Vec<Variant> = (0..3).map(|_| event_fields(&mut refreshed_item)[0].clone()).collect();
+    assert_eq!(event_types, vec![
+        Variant::from(Into::<NodeId>::into(ObjectTypeId::RefreshStartEventType)),
+        Variant::from(Into::<NodeId>::into(ObjectTypeId::AlarmConditionType)),
+        Variant::from(Into::<NodeId>::into(ObjectTypeId::RefreshEndEventType)),
+    ]);
+
+    // The condition id is the node id of the condition
+    let event_id = address_space.find_condition(&alarm_id).unwrap().event_id.clone();
+    assert!(address_space.update_condition(&alarm_id, |alarm| alarm.acknowledge(&event_id, LocalizedText::new("", ""))).is_ok());
+    assert_eq!(other_item.tick(&address_space, &now, TickReason::TickTimerFired), true);
+    assert_eq!(event_fields(&mut other_item)[1], Variant::from(alarm_id));
+}
+
+#[test]
+fn subscription_event_notification() {
+    let mut address_space = make_event_address_space();
+    let mut subscription = Subscription::new(Arc::new(RwLock::new(ServerDiagnostics::new())), 1, true, 100f64, 100, 100, 0, 0);
+    subscription.state = SubscriptionState::Normal;
+    let results = subscription.create_monitored_items(&address_space, TimestampsToReturn::Both, &[
+        make_event_create_request(&boiler_id(), AttributeId::EventNotifier, make_event_filter(vec![select_clause("Message")]))
+    ]);
+    assert_eq!(results[0].status_code, Good);
+
+    let event = BaseEventType::new(ObjectTypeId::BaseEventType, &boiler_id(), "Boiler", "Boiler is overheating", 900);
+    assert!(address_space.raise_event(event).is_ok());
+
+    // The publishing interval has elapsed and a publish request is queued, so the event is published
+    let now = chrono::Utc::now() + time::Duration::seconds(1);
+    let notification_message = subscription.tick(&address_space, TickReason::TickTimerFired, true, &now).pop().unwrap();
+    assert!(notification_message.data_change_notifications().is_empty());
+    let event_notifications = notification_message.event_notifications();
+    assert_eq!(event_notifications.len(), 1);
+    let events = event_notifications[0].events.as_ref().unwrap();
+    assert_eq!(events.len(), 1);
+    // An empty locale is not encoded so only the text survives the round trip
+    if let Variant::LocalizedText(ref message) = events[0].event_fields.as_ref().unwrap()[0] {
+        assert_eq!(message.text, UAString::from("Boiler is overheating"));
+    } else {
+        panic!("Expected the message of the event");
+    }
+}
+
+fn set_test_var_value(address_space: &mut AddressSpace, value: UInt32) {
+    if let &mut NodeType::Variable(ref mut node) = address_space.find_node_mut(&test_var_node_id()).unwrap() {
+        let mut data_value = node.value();
+        data_value.value = Some(Variant::UInt32(value));
+        node.set_value(data_value);
+    } else {
+        panic!("Expected a variable, didn't get one!!");
+    }
+}
+
+fn data_change_client_handles(notification_message: &NotificationMessage) -> Vec<UInt32> {
+    notification_message.data_change_notifications().iter().flat_map(|n| {
+        n.monitored_items.as_ref().unwrap().iter().map(|m| m.client_handle).collect::<Vec<_>>()
+    }).collect()
+}
+
+#[test]
+fn subscription_triggered_sampling_item() {
+    let mut address_space = make_address_space();
+    let mut subscription = Subscription::new(Arc::new(RwLock::new(ServerDiagnostics::new())), 1, true, 100f64, 100, 100, 0, 0);
+    subscription.state = SubscriptionState::Normal;
+
+    let mut triggering_request = make_create_request(-1f64, 5);
+    triggering_request.requested_parameters.client_handle = 1;
+    let mut triggered_request = make_create_request(-1f64, 5);
+    triggered_request.requested_parameters.client_handle = 2;
+    triggered_request.monitoring_mode = MonitoringMode::Sampling;
+    let results = subscription.create_monitored_items(&address_space, TimestampsToReturn::Both, &[triggering_request, triggered_request]);
+    let triggering_item_id = results[0].monitored_item_id;
+    let triggered_item_id = results[1].monitored_item_id;
+
+    // The sampling item does not report on its own
+    let now = chrono::Utc::now() + time::Duration::seconds(1);
+    let notification_message = subscription.tick(&address_space, TickReason::TickTimerFired, true, &now).pop().unwrap();
+    assert_eq!(data_change_client_handles(&notification_message), vec![1]);
+
+    // Links to items that do not exist are rejected
+    assert_eq!(subscription.set_triggering(999, &[triggered_item_id], &[]), Err(BadMonitoredItemIdInvalid));
+    let (add_results, remove_results) = subscription.set_triggering(triggering_item_id, &[triggered_item_id, 999], &[]).unwrap();
+    assert_eq!(add_results, vec![Good, BadMonitoredItemIdInvalid]);
+    assert!(remove_results.is_empty());
+
+    // When the triggering item reports, the sampled values of the triggered item are reported with it
+    set_test_var_value(&mut address_space, 1);
+    let now = now + time::Duration::seconds(1);
+    let notification_message = subscription.tick(&address_space, TickReason::TickTimerFired, true, &now).pop().unwrap();
+    let mut client_handles = data_change_client_handles(&notification_message);
+    client_handles.sort();
+    assert_eq!(client_handles, vec![1, 2, 2]);
+
+    // Disabling the triggered item stops it sampling and clears its queue
+    set_test_var_value(&mut address_space, 2);
+    assert_eq!(subscription.set_monitoring_mode(MonitoringMode::Disabled, &[triggered_item_id, 999]), vec![Good, BadMonitoredItemIdInvalid]);
+    set_test_var_value(&mut address_space, 3);
+    let now = now + time::Duration::seconds(1);
+    let notification_message = subscription.tick(&address_space, TickReason::TickTimerFired, true, &now).pop().unwrap();
+    assert_eq!(data_change_client_handles(&notification_message), vec![1]);
+
+    // Removing the link leaves the triggered item sampling silently again
+    let (_, remove_results) = subscription.set_triggering(triggering_item_id, &[], &[triggered_item_id, 999]).unwrap();
+    assert_eq!(remove_results, vec![Good, BadMonitoredItemIdInvalid]);
+}
+
+#[test]
+fn monitored_item_index_range() {
+    let mut address_space = make_address_space();
+    let array_id = NodeId::new(1, 2);
+    let _ = address_space.add_variable(Variable::new_array(&array_id, "array", "array", "", DataTypeId::Int32, DataValue::new(Variant::new_i32_array(&[0, 1, 2, 3])), &[4]), &AddressSpace::objects_folder_id());
+
+    let mut request = make_create_request(-1f64, 5);
+    request.item_to_monitor.node_id = array_id.clone();
+    request.requested_parameters.filter = ExtensionObject::null();
+    request.item_to_monitor.index_range = UAString::from("x");
+    assert_eq!(MonitoredItem::new(&address_space, 1, TimestampsToReturn::Both, &request).unwrap_err().0, BadIndexRangeInvalid);
+
+    // Only the elements in the range are reported, and only when they change
+    request.item_to_monitor.index_range = UAString::from("1:2");
+    let mut monitored_item = MonitoredItem::new(&address_space, 1, TimestampsToReturn::Both, &request).unwrap();
+    let now = chrono::Utc::now();
+    assert_eq!(monitored_item.tick(&address_space, &now, TickReason::TickTimerFired), true);
+    if let Notification::MonitoredItemNotification(notification) = monitored_item.remove_first_notification_message().unwrap() {
+        assert_eq!(notification.value.value.unwrap(), Variant::new_i32_array(&[1, 2]));
+    } else {
+        panic!("Expected a data change notification");
+    }
+
+    let set_array = |address_space: &mut AddressSpace, values: &[Int32]| {
+        if let &mut NodeType::Variable(ref mut node) = address_space.find_node_mut(&array_id).unwrap() {
+            node.set_value(DataValue::new(Variant::new_i32_array(values)));
+        }
+    };
+    set_array(&mut address_space, &[10, 1, 2, 30]);
+    assert_eq!(monitored_item.tick(&address_space, &now, TickReason::TickTimerFired), false);
+    set_array(&mut address_space, &[10, 1, 20, 30]);
+    assert_eq!(monitored_item.tick(&address_space, &now, TickReason::TickTimerFired), true);
+}
+
+fn add_eu_range(address_space: &mut AddressSpace, node_id: &NodeId, low: f64, high: f64) {
+    let property_id = NodeId::new_string(1, "EURange");
+    let range = ExtensionObject::from_encodable(ObjectId::Range_Encoding_DefaultBinary, Range { low, high });
+    address_space.insert(Variable::new(&property_id, "EURange", "EURange", "", range));
+    address_space.add_has_property(node_id, &property_id);
+}
+
+fn make_pct_deadband_request(deadband_value: f64) -> MonitoredItemCreateRequest {
+    let mut request = make_create_request(-1f64, 5);
+    request.requested_parameters.filter = ExtensionObject::from_encodable(ObjectId::DataChangeFilter_Encoding_DefaultBinary, DataChangeFilter {
+        trigger: DataChangeTrigger::StatusValue,
+        deadband_type: 2,
+        deadband_value,
+    });
+    request
+}
+
+#[test]
+fn monitored_item_pct_deadband() {
+    let mut address_space = make_address_space();
+
+    // A percent deadband is rejected on a variable without an EURange
+    assert_eq!(MonitoredItem::new(&address_space, 1, TimestampsToReturn::Both, &make_pct_deadband_request(10f64)).unwrap_err().0, BadDeadbandFilterInvalid);
+
+    // Or with an invalid deadband
+    add_eu_range(&mut address_space, &test_var_node_id(), 0f64, 200f64);
+    assert_eq!(MonitoredItem::new(&address_space, 1, TimestampsToReturn::Both, &make_pct_deadband_request(-1f64)).unwrap_err().0, BadDeadbandFilterInvalid);
+
+    let mut monitored_item = MonitoredItem::new(&address_space, 1, TimestampsToReturn::Both, &make_pct_deadband_request(10f64)).unwrap();
+    let now = chrono::Utc::now();
+    assert_eq!(monitored_item.tick(&address_space, &now, TickReason::TickTimerFired), true);
+
+    // A change of 10% of the range is not reported, more than that is
+    set_test_var_value(&mut address_space, 20);
+    assert_eq!(monitored_item.tick(&address_space, &now, TickReason::TickTimerFired), false);
+    set_test_var_value(&mut address_space, 21);
+    assert_eq!(monitored_item.tick(&address_space, &now, TickReason::TickTimerFired), true);
+
+    // Modifying the item to a percent deadband on a variable without a range is rejected
+    let mut request = make_create_request(-1f64, 5);
+    request.item_to_monitor.node_id = NodeId::new(1, 2);
+    let _ = address_space.add_variable(Variable::new(&NodeId::new(1, 2), "other", "other", "", 0 as UInt32), &AddressSpace::objects_folder_id());
+    let mut monitored_item = MonitoredItem::new(&address_space, 2, TimestampsToReturn::Both, &request).unwrap();
+    let modify_request = MonitoredItemModifyRequest {
+        monitored_item_id: 2,
+        requested_parameters: make_pct_deadband_request(10f64).requested_parameters,
+    };
+    assert_eq!(monitored_item.modify(&address_space, TimestampsToReturn::Both, &modify_request).unwrap_err().0, BadDeadbandFilterInvalid);
+}
+
+fn set_test_var_value_at(address_space: &mut AddressSpace, value: Double, timestamp: &DateTimeUtc) {
+    if let &mut NodeType::Variable(ref mut node) = address_space.find_node_mut(&test_var_node_id()).unwrap() {
+        let mut data_value = DataValue::new(value);
+        data_value.source_timestamp = Some(DateTime::from(timestamp.clone()));
+        node.set_value(data_value);
+    } else {
+        panic!("Expected a variable, didn't get one!!");
+    }
+}
+
+fn make_aggregate_request(aggregate_type: ObjectId, start_time: &DateTimeUtc, processing_interval: Double) -> MonitoredItemCreateRequest {
+    let mut request = make_create_request(-1f64, 5);
+    request.requested_parameters.filter = ExtensionObject::from_encodable(ObjectId::AggregateFilter_Encoding_DefaultBinary, AggregateFilter {
+        start_time: DateTime::from(start_time.clone()),
+        aggregate_type: aggregate_type.into(),
+        processing_interval,
+        aggregate_configuration: AggregateConfiguration {
+            use_server_capabilities_defaults: true,
+            treat_uncertain_as_bad: false,
+            percent_data_bad: 0,
+            percent_data_good: 0,
+            use_sloped_extrapolation: false,
+        },
+    });
+    request
+}
+
+#[test]
+fn monitored_item_aggregate_filter() {
+    let mut address_space = make_address_space();
+    let start = chrono::Utc::now();
+
+    // Unsupported aggregates and aggregates of anything but the value are rejected
+    let request = make_aggregate_request(ObjectId::AggregateFunction_AnnotationCount, &start, 1000f64);
+    assert_eq!(MonitoredItem::new(&address_space, 1, TimestampsToReturn::Both, &request).unwrap_err().0, BadAggregateNotSupported);
+    let mut request = make_aggregate_request(ObjectId::AggregateFunction_Average, &start, 1000f64);
+    request.item_to_monitor.attribute_id = AttributeId::DisplayName as UInt32;
+    assert_eq!(MonitoredItem::new(&address_space, 1, TimestampsToReturn::Both, &request).unwrap_err().0, BadFilterNotAllowed);
+
+    // The filter result holds the revised filter
+    let request = make_aggregate_request(ObjectId::AggregateFunction_Average, &start, 1000f64);
+    let mut monitored_item = MonitoredItem::new(&address_space, 1, TimestampsToReturn::Both, &request).unwrap();
+    let filter_result = monitored_item.filter_result.decode_inner::<AggregateFilterResult>().unwrap();
+    assert_eq!(filter_result.revised_processing_interval, 1000f64);
+    assert_eq!(filter_result.revised_aggregate_configuration, aggregates::default_configuration());
+
+    // Values are sampled without being reported until the processing interval ends
+    set_test_var_value_at(&mut address_space, 10f64, &(start + time::Duration::milliseconds(100)));
+    assert_eq!(monitored_item.tick(&address_space, &(start + time::Duration::milliseconds(200)), TickReason::TickTimerFired), false);
+    set_test_var_value_at(&mut address_space, 20f64, &(start + time::Duration::milliseconds(500)));
+    assert_eq!(monitored_item.tick(&address_space, &(start + time::Duration::milliseconds(600)), TickReason::TickTimerFired), false);
+    assert!(monitored_item.notification_queue.is_empty());
+
+    // Then the average of the interval is reported once
+    assert_eq!(monitored_item.tick(&address_space, &(start + time::Duration::milliseconds(1100)), TickReason::TickTimerFired), true);
+    assert_eq!(monitored_item.notification_queue.len(), 1);
+    if let Notification::MonitoredItemNotification(notification) = monitored_item.remove_first_notification_message().unwrap() {
+        assert_eq!(notification.value.value, Some(Variant::Double(15f64)));
+        assert_eq!(notification.value.source_timestamp, Some(DateTime::from(start)));
+    } else {
+        panic!("Expected a data change notification");
+    }
+    assert_eq!(monitored_item.tick(&address_space, &(start + time::Duration::milliseconds(1200)), TickReason::TickTimerFired), false);
+
+    // Processing intervals that are not a number are rejected, others are revised into range
+    let processing_interval = |requested: Double| {
+        let request = make_aggregate_request(ObjectId::AggregateFunction_Average, &start, requested);
+        MonitoredItem::new(&address_space, 1, TimestampsToReturn::Both, &request)
+            .map(|monitored_item| monitored_item.filter_result.decode_inner::<AggregateFilterResult>().unwrap().revised_processing_interval)
+            .map_err(|e| e.0)
+    };
+    assert_eq!(processing_interval(::std::f64::NAN), Err(BadMonitoredItemFilterInvalid));
+    assert_eq!(processing_interval(::std::f64::INFINITY), Err(BadMonitoredItemFilterInvalid));
+    assert_eq!(processing_interval(0.1f64), Ok(::constants::MIN_PROCESSING_INTERVAL));
+    assert_eq!(processing_interval(1e300f64), Ok(::constants::MAX_PROCESSING_INTERVAL));
+}
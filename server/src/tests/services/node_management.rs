@@ -0,0 +1,268 @@
+use super::*;
+use config::ANONYMOUS_USER_TOKEN_ID;
+use services::node_management::NodeManagementService;
+
+/// Permits the anonymous user of the session to manage nodes
+fn permit_node_management(st: &ServiceTest) {
+    let (server_state, mut session) = st.get_server_state_and_session();
+    let mut config = server_state.config.write().unwrap();
+    config.node_management_user_token_ids.insert(ANONYMOUS_USER_TOKEN_ID.to_string());
+    session.user_token_id = Some(ANONYMOUS_USER_TOKEN_ID.to_string());
+}
+
+fn object_attributes(display_name: &str) -> ExtensionObject {
+    let attributes = ObjectAttributes {
+        specified_attributes: (1 << 6) | (1 << 5),
+        display_name: LocalizedText::new("", display_name),
+        description: LocalizedText::new("", "A description"),
+        write_mask: 0,
+        user_write_mask: 0,
+        event_notifier: 0,
+    };
+    ExtensionObject::from_encodable(ObjectId::ObjectAttributes_Encoding_DefaultBinary, attributes)
+}
+
+fn add_folder_item(node_id: &NodeId, browse_name: &str, parent_node_id: &NodeId) -> AddNodesItem {
+    AddNodesItem {
+        parent_node_id: ExpandedNodeId::new(parent_node_id.clone()),
+        reference_type_id: ReferenceTypeId::Organizes.into(),
+        requested_new_node_id: ExpandedNodeId::new(node_id.clone()),
+        browse_name: QualifiedName::new(2, browse_name),
+        node_class: NodeClass::Object,
+        node_attributes: object_attributes(browse_name),
+        type_definition: ExpandedNodeId::new::<NodeId>(ObjectTypeId::FolderType.into()),
+    }
+}
+
+fn add_nodes(st: &ServiceTest, nodes_to_add: Vec<AddNodesItem>) -> Vec<AddNodesResult> {
+    let nms = NodeManagementService::new();
+    let (server_state, session) = st.get_server_state_and_session();
+    let mut address_space = st.get_address_space();
+    let request = AddNodesRequest {
+        request_header: make_request_header(),
+        nodes_to_add: Some(nodes_to_add),
+    };
+    let response = nms.add_nodes(&server_state, &session, &mut address_space, request);
+    let response: AddNodesResponse = supported_message_as!(response.unwrap(), AddNodesResponse);
+    response.results.unwrap()
+}
+
+#[test]
+fn add_nodes_test() {
+    let st = ServiceTest::new();
+    permit_node_management(&st);
+
+    let objects_folder_id = AddressSpace::objects_folder_id();
+    let last_modified = st.get_address_space().last_modified;
+
+    let results = add_nodes(&st, vec![
+        // 1. a valid folder
+        add_folder_item(&NodeId::new_string(2, "Tags"), "Tags", &objects_folder_id),
+        // 2. a folder whose node id already exists
+        add_folder_item(&objects_folder_id, "Tags2", &AddressSpace::root_folder_id()),
+        // 3. a folder with a parent that does not exist
+        add_folder_item(&NodeId::new_string(2, "Tags3"), "Tags3", &NodeId::new_string(2, "Unknown")),
+        // 4. a folder with a generated node id
+        add_folder_item(&NodeId::null(), "Tags4", &objects_folder_id),
+    ]);
+
+    // 1. a valid folder
+    assert_eq!(results[0].status_code, Good);
+    assert_eq!(results[0].added_node_id, NodeId::new_string(2, "Tags"));
+    // 2. a folder whose node id already exists
+    assert_eq!(results[1].status_code, BadNodeIdExists);
+    // 3. a folder with a parent that does not exist
+    assert_eq!(results[2].status_code, BadParentNodeIdInvalid);
+    // 4. a folder with a generated node id
+    assert_eq!(results[3].status_code, Good);
+    assert!(!results[3].added_node_id.is_null());
+
+    let address_space = st.get_address_space();
+    assert!(address_space.last_modified > last_modified);
+
+    let node = address_space.find_node(&NodeId::new_string(2, "Tags")).unwrap().as_node();
+    assert_eq!(node.node_class(), NodeClass::Object);
+    assert_eq!(node.browse_name(), QualifiedName::new(2, "Tags"));
+    // An empty locale is not encoded so only the text survives the round trip
+    assert_eq!(node.display_name().text, UAString::from("Tags"));
+    assert_eq!(node.description().unwrap().text, UAString::from("A description"));
+    assert!(address_space.has_reference(&objects_folder_id, &NodeId::new_string(2, "Tags"), ReferenceTypeId::Organizes));
+    assert!(address_space.has_reference(&NodeId::new_string(2, "Tags"), &ObjectTypeId::FolderType.into(), ReferenceTypeId::HasTypeDefinition));
+}
+
+#[test]
+fn add_nodes_not_permitted() {
+    let st = ServiceTest::new();
+    let nms = NodeManagementService::new();
+    let (server_state, session) = st.get_server_state_and_session();
+    let mut address_space = st.get_address_space();
+    let request = AddNodesRequest {
+        request_header: make_request_header(),
+        nodes_to_add: Some(vec![add_folder_item(&NodeId::new_string(2, "Tags"), "Tags", &AddressSpace::objects_folder_id())]),
+    };
+    let response = nms.add_nodes(&server_state, &session, &mut address_space, request);
+    let response: ServiceFault = supported_message_as!(response.unwrap(), ServiceFault);
+    assert_eq!(response.response_header.service_result, BadUserAccessDenied);
+    assert!(!address_space.node_exists(&NodeId::new_string(2, "Tags")));
+}
+
+#[test]
+fn add_and_delete_references_test() {
+    let st = ServiceTest::new();
+    permit_node_management(&st);
+
+    let objects_folder_id = AddressSpace::objects_folder_id();
+    let tags_id = NodeId::new_string(2, "Tags");
+    let alarms_id = NodeId::new_string(2, "Alarms");
+    let _ = add_nodes(&st, vec![
+        add_folder_item(&tags_id, "Tags", &objects_folder_id),
+        add_folder_item(&alarms_id, "Alarms", &objects_folder_id),
+    ]);
+
+    let nms = NodeManagementService::new();
+    let (server_state, session) = st.get_server_state_and_session();
+    let mut address_space = st.get_address_space();
+
+    let add_reference_item = |source_node_id: &NodeId, target_node_id: &NodeId| {
+        AddReferencesItem {
+            source_node_id: source_node_id.clone(),
+            reference_type_id: ReferenceTypeId::Organizes.into(),
+            is_forward: true,
+            target_server_uri: UAString::null(),
+            target_node_id: ExpandedNodeId::new(target_node_id.clone()),
+            target_node_class: NodeClass::Unspecified,
+        }
+    };
+
+    let request = AddReferencesRequest {
+        request_header: make_request_header(),
+        references_to_add: Some(vec![
+            // 1. a valid reference
+            add_reference_item(&tags_id, &alarms_id),
+            // 2. a duplicate reference
+            add_reference_item(&tags_id, &alarms_id),
+            // 3. an unknown source
+            add_reference_item(&NodeId::new_string(2, "Unknown"), &alarms_id),
+            // 4. an unknown target
+            add_reference_item(&tags_id, &NodeId::new_string(2, "Unknown")),
+        ]),
+    };
+    let response = nms.add_references(&server_state, &session, &mut address_space, request);
+    let response: AddReferencesResponse = supported_message_as!(response.unwrap(), AddReferencesResponse);
+    assert_eq!(response.results.unwrap(), vec![Good, BadDuplicateReferenceNotAllowed, BadSourceNodeIdInvalid, BadTargetNodeIdInvalid]);
+    assert!(address_space.has_reference(&tags_id, &alarms_id, ReferenceTypeId::Organizes));
+    assert!(address_space.find_references_to(&alarms_id, Some((ReferenceTypeId::Organizes, false))).unwrap().iter().any(|r| r.node_id == tags_id));
+
+    let delete_reference_item = DeleteReferencesItem {
+        source_node_id: tags_id.clone(),
+        reference_type_id: ReferenceTypeId::Organizes.into(),
+        is_forward: true,
+        target_node_id: ExpandedNodeId::new(alarms_id.clone()),
+        delete_bidirectional: true,
+    };
+    let request = DeleteReferencesRequest {
+        request_header: make_request_header(),
+        references_to_delete: Some(vec![delete_reference_item.clone(), delete_reference_item]),
+    };
+    let response = nms.delete_references(&server_state, &session, &mut address_space, request);
+    let response: DeleteReferencesResponse = supported_message_as!(response.unwrap(), DeleteReferencesResponse);
+    assert_eq!(response.results.unwrap(), vec![Good, BadNotFound]);
+    assert!(!address_space.has_reference(&tags_id, &alarms_id, ReferenceTypeId::Organizes));
+    assert!(!address_space.find_references_to(&alarms_id, None).unwrap().iter().any(|r| r.node_id == tags_id));
+}
+
+#[test]
+fn delete_nodes_test() {
+    let st = ServiceTest::new();
+    permit_node_management(&st);
+
+    let objects_folder_id = AddressSpace::objects_folder_id();
+    let tags_id = NodeId::new_string(2, "Tags");
+    let _ = add_nodes(&st, vec![add_folder_item(&tags_id, "Tags", &objects_folder_id)]);
+
+    let nms = NodeManagementService::new();
+    let (server_state, session) = st.get_server_state_and_session();
+    let mut address_space = st.get_address_space();
+
+    let mut request_header = make_request_header();
+    request_header.return_diagnostics = DiagnosticFlags::OPERATIONS_LEVEL_SYMBOLIC_ID;
+    let request = DeleteNodesRequest {
+        request_header,
+        nodes_to_delete: Some(vec![
+            DeleteNodesItem { node_id: tags_id.clone(), delete_target_references: true },
+            DeleteNodesItem { node_id: NodeId::new_string(2, "Unknown"), delete_target_references: true },
+        ]),
+    };
+    let response = nms.delete_nodes(&server_state, &session, &mut address_space, request);
+    let response: DeleteNodesResponse = supported_message_as!(response.unwrap(), DeleteNodesResponse);
+    assert_eq!(response.results.unwrap(), vec![Good, BadNodeIdUnknown]);
+    // The node that could not be deleted is described
+    let string_table = response.response_header.string_table.unwrap();
+    let diagnostic_infos = response.diagnostic_infos.unwrap();
+    assert_eq!(diagnostic_infos.len(), 2);
+    assert_eq!(diagnostic_infos[0], DiagnosticInfo::new());
+    assert_eq!(string_table[diagnostic_infos[1].symbolic_id.unwrap() as usize].as_ref(), "BadNodeIdUnknown");
+    assert!(!address_space.node_exists(&tags_id));
+    assert!(!address_space.has_reference(&objects_folder_id, &tags_id, ReferenceTypeId::Organizes));
+    assert!(address_space.find_references_from(&tags_id, None).is_none());
+}
+
+#[test]
+fn delete_nodes_keep_target_references() {
+    let st = ServiceTest::new();
+    permit_node_management(&st);
+
+    let objects_folder_id = AddressSpace::objects_folder_id();
+    let folder_type_id: NodeId = ObjectTypeId::FolderType.into();
+    let tags_id = NodeId::new_string(2, "Tags");
+    let alarms_id = NodeId::new_string(2, "Alarms");
+    let _ = add_nodes(&st, vec![
+        add_folder_item(&tags_id, "Tags", &objects_folder_id),
+        add_folder_item(&alarms_id, "Alarms", &tags_id),
+    ]);
+
+    let nms = NodeManagementService::new();
+    let (server_state, session) = st.get_server_state_and_session();
+    let mut address_space = st.get_address_space();
+    address_space.insert_reference(&alarms_id, &tags_id, ReferenceTypeId::HasNotifier);
+
+    let request = DeleteNodesRequest {
+        request_header: make_request_header(),
+        nodes_to_delete: Some(vec![DeleteNodesItem { node_id: tags_id.clone(), delete_target_references: false }]),
+    };
+    let response = nms.delete_nodes(&server_state, &session, &mut address_space, request);
+    let response: DeleteNodesResponse = supported_message_as!(response.unwrap(), DeleteNodesResponse);
+    assert_eq!(response.results.unwrap(), vec![Good]);
+    assert!(!address_space.node_exists(&tags_id));
+
+    // References from and to the node are gone in both directions
+    assert!(address_space.find_references_from(&tags_id, None).is_none());
+    assert!(address_space.find_references_to(&tags_id, None).is_none());
+    assert!(!address_space.find_references_to(&alarms_id, None).map_or(false, |references| references.iter().any(|r| r.node_id == tags_id)));
+    assert!(!address_space.find_references_to(&folder_type_id, None).unwrap().iter().any(|r| r.node_id == tags_id));
+
+    // The references other nodes own to the node are kept
+    assert!(address_space.has_reference(&objects_folder_id, &tags_id, ReferenceTypeId::Organizes));
+    assert!(address_space.has_reference(&alarms_id, &tags_id, ReferenceTypeId::HasNotifier));
+}
+
+#[test]
+fn add_nodes_non_hierarchical_reference() {
+    let st = ServiceTest::new();
+    permit_node_management(&st);
+
+    let objects_folder_id = AddressSpace::objects_folder_id();
+    let mut item = add_folder_item(&NodeId::new_string(2, "Tags"), "Tags", &objects_folder_id);
+    item.reference_type_id = ReferenceTypeId::HasTypeDefinition.into();
+    let mut item2 = add_folder_item(&NodeId::new_string(2, "Tags2"), "Tags2", &objects_folder_id);
+    item2.reference_type_id = ReferenceTypeId::HasComponent.into();
+
+    // A non-hierarchical reference type is rejected, a subtype of HierarchicalReferences is not
+    let results = add_nodes(&st, vec![item, item2]);
+    assert_eq!(results[0].status_code, BadReferenceTypeIdInvalid);
+    assert_eq!(results[1].status_code, Good);
+
+    let address_space = st.get_address_space();
+    assert!(!address_space.node_exists(&NodeId::new_string(2, "Tags")));
+    assert!(address_space.has_reference(&objects_folder_id, &NodeId::new_string(2, "Tags2"), ReferenceTypeId::HasComponent));
+}
@@ -0,0 +1,265 @@
+use std::collections::HashSet;
+
+use prelude::*;
+use services::query::QueryService;
+use super::*;
+
+// Query service tests
+
+fn pump_type_id() -> NodeId { NodeId::new_string(1, "PumpType") }
+
+fn dosing_pump_type_id() -> NodeId { NodeId::new_string(1, "DosingPumpType") }
+
+fn pump_id(i: usize) -> NodeId { NodeId::new_string(1, &format!("Pump{}", i)) }
+
+/// Adds a pump type with a dosing pump subtype, and pumps of each. Every third pump is a dosing
+/// pump and every other pump is at fault.
+fn add_pumps_to_address_space(address_space: &mut AddressSpace, pumps_to_add: usize) {
+    address_space.insert(ObjectType::new(&pump_type_id(), "PumpType", "PumpType", "", false));
+    address_space.insert_reference(&ObjectTypeId::BaseObjectType.into(), &pump_type_id(), ReferenceTypeId::HasSubtype);
+    address_space.insert(ObjectType::new(&dosing_pump_type_id(), "DosingPumpType", "DosingPumpType", "", false));
+    address_space.insert_reference(&pump_type_id(), &dosing_pump_type_id(), ReferenceTypeId::HasSubtype);
+
+    let pumps_id = address_space.add_folder("Pumps", "Pumps", &AddressSpace::objects_folder_id()).unwrap();
+    for i in 0..pumps_to_add {
+        let pump_id = pump_id(i);
+        let name = format!("Pump{}", i);
+        address_space.insert(Object::new(&pump_id, &name, &name, ""));
+        address_space.add_organizes(&pumps_id, &pump_id);
+        let type_id = if i % 3 == 0 { dosing_pump_type_id() } else { pump_type_id() };
+        address_space.insert_reference(&pump_id, &type_id, ReferenceTypeId::HasTypeDefinition);
+        let status = if i % 2 == 0 { "Fault" } else { "Running" };
+        let status_id = NodeId::new_string(1, &format!("Pump{}.Status", i));
+        let _ = address_space.add_variable(Variable::new(&status_id, "Status", "Status", "", UAString::from(status)), &pump_id);
+    }
+}
+
+fn status_is_fault_filter() -> ContentFilter {
+    let status = ExtensionObject::from_encodable(ObjectId::SimpleAttributeOperand_Encoding_DefaultBinary, SimpleAttributeOperand {
+        type_definition_id: pump_type_id(),
+        browse_path: Some(vec![QualifiedName::new(0, "Status")]),
+        attribute_id: AttributeId::Value as UInt32,
+        index_range: UAString::null(),
+    });
+    let fault = ExtensionObject::from_encodable(ObjectId::LiteralOperand_Encoding_DefaultBinary, LiteralOperand { value: Variant::from("Fault") });
+    ContentFilter {
+        elements: Some(vec![ContentFilterElement {
+            filter_operator: FilterOperator::Equals,
+            filter_operands: Some(vec![status, fault]),
+        }]),
+    }
+}
+
+fn make_query_first_request(type_id: NodeId, include_sub_types: bool, filter: ContentFilter, max_data_sets_to_return: UInt32) -> QueryFirstRequest {
+    QueryFirstRequest {
+        request_header: make_request_header(),
+        view: ViewDescription {
+            view_id: NodeId::null(),
+            timestamp: DateTime::epoch(),
+            view_version: 0,
+        },
+        node_types: Some(vec![NodeTypeDescription {
+            type_definition_node: type_id.into(),
+            include_sub_types,
+            data_to_return: Some(vec![QueryDataDescription {
+                relative_path: RelativePath {
+                    elements: Some(vec![RelativePathElement {
+                        reference_type_id: ReferenceTypeId::Organizes.into(),
+                        is_inverse: false,
+                        include_subtypes: true,
+                        target_name: QualifiedName::new(0, "Status"),
+                    }]),
+                },
+                attribute_id: AttributeId::Value as UInt32,
+                index_range: UAString::null(),
+            }]),
+        }]),
+        filter,
+        max_data_sets_to_return,
+        max_references_to_return: 0,
+    }
+}
+
+fn data_set_node_ids(query_data_sets: &[QueryDataSet]) -> HashSet<NodeId> {
+    query_data_sets.iter().map(|data_set| data_set.node_id.node_id.clone()).collect()
+}
+
+#[test]
+fn query_first_by_type() {
+    let st = ServiceTest::new();
+    let qs = QueryService::new();
+    add_pumps_to_address_space(&mut st.get_address_space(), 6);
+    let mut session = st.session.write().unwrap();
+    let address_space = st.server.address_space.read().unwrap();
+
+    // All pumps, including dosing pumps, that are at fault
+    let request = make_query_first_request(pump_type_id(), true, status_is_fault_filter(), 0);
+    let response = supported_message_as!(qs.query_first(&mut session, &address_space, request).unwrap(), QueryFirstResponse);
+    assert_eq!(response.response_header.service_result, Good);
+    assert!(response.continuation_point.is_null());
+    let query_data_sets = response.query_data_sets.unwrap();
+    assert_eq!(data_set_node_ids(&query_data_sets), [pump_id(0), pump_id(2), pump_id(4)].iter().cloned().collect());
+    for data_set in &query_data_sets {
+        let expected_type_id = if data_set.node_id.node_id == pump_id(0) { dosing_pump_type_id() } else { pump_type_id() };
+        assert_eq!(data_set.type_definition_node.node_id, expected_type_id);
+        assert_eq!(data_set.values.as_ref().unwrap(), &vec![Variant::from("Fault")]);
+    }
+
+    // Without subtypes the dosing pumps are left out
+    let request = make_query_first_request(pump_type_id(), false, status_is_fault_filter(), 0);
+    let response = supported_message_as!(qs.query_first(&mut session, &address_space, request).unwrap(), QueryFirstResponse);
+    assert_eq!(data_set_node_ids(&response.query_data_sets.unwrap()), [pump_id(2), pump_id(4)].iter().cloned().collect());
+
+    // An empty filter selects every instance
+    let request = make_query_first_request(pump_type_id(), true, ContentFilter { elements: None }, 0);
+    let response = supported_message_as!(qs.query_first(&mut session, &address_space, request).unwrap(), QueryFirstResponse);
+    assert_eq!(response.query_data_sets.unwrap().len(), 6);
+
+    // A node type that is not a type is reported in the parsing results
+    let request = make_query_first_request(pump_id(0), true, status_is_fault_filter(), 0);
+    let response = supported_message_as!(qs.query_first(&mut session, &address_space, request).unwrap(), QueryFirstResponse);
+    assert_eq!(response.response_header.service_result, BadInvalidArgument);
+    assert_eq!(response.parsing_results.unwrap()[0].status_code, BadNotTypeDefinition);
+
+    // So is a filter that is not valid
+    let mut filter = status_is_fault_filter();
+    filter.elements.as_mut().unwrap()[0].filter_operands.as_mut().unwrap().pop();
+    let request = make_query_first_request(pump_type_id(), true, filter, 0);
+    let response = supported_message_as!(qs.query_first(&mut session, &address_space, request).unwrap(), QueryFirstResponse);
+    assert_eq!(response.response_header.service_result, BadContentFilterInvalid);
+    assert_eq!(response.filter_result.element_results.unwrap()[0].status_code, BadFilterOperandCountMismatch);
+
+    // No node types is nothing to do
+    let mut request = make_query_first_request(pump_type_id(), true, status_is_fault_filter(), 0);
+    request.node_types = None;
+    let response = qs.query_first(&mut session, &address_space, request).unwrap();
+    assert_eq!(supported_message_as!(response, ServiceFault).response_header.service_result, BadNothingToDo);
+}
+
+#[test]
+fn query_next() {
+    let st = ServiceTest::new();
+    let qs = QueryService::new();
+    add_pumps_to_address_space(&mut st.get_address_space(), 10);
+    let mut session = st.session.write().unwrap();
+    let address_space = st.server.address_space.read().unwrap();
+
+    let query_next = |session: &mut Session, continuation_point: &ByteString, release_continuation_point: bool| {
+        let request = QueryNextRequest {
+            request_header: make_request_header(),
+            release_continuation_point,
+            continuation_point: continuation_point.clone(),
+        };
+        qs.query_next(session, &address_space, request).unwrap()
+    };
+
+    // Ten pumps are returned four at a time
+    let request = make_query_first_request(pump_type_id(), true, ContentFilter { elements: None }, 4);
+    let response = supported_message_as!(qs.query_first(&mut session, &address_space, request).unwrap(), QueryFirstResponse);
+    let mut node_ids = data_set_node_ids(&response.query_data_sets.unwrap());
+    assert_eq!(node_ids.len(), 4);
+    assert!(!response.continuation_point.is_null());
+
+    let response = supported_message_as!(query_next(&mut session, &response.continuation_point, false), QueryNextResponse);
+    let query_data_sets = response.query_data_sets.unwrap();
+    assert_eq!(query_data_sets.len(), 4);
+    node_ids.extend(data_set_node_ids(&query_data_sets));
+    let continuation_point = response.revised_continuation_point;
+    assert!(!continuation_point.is_null());
+
+    let response = supported_message_as!(query_next(&mut session, &continuation_point, false), QueryNextResponse);
+    let query_data_sets = response.query_data_sets.unwrap();
+    assert_eq!(query_data_sets.len(), 2);
+    assert!(response.revised_continuation_point.is_null());
+    node_ids.extend(data_set_node_ids(&query_data_sets));
+    assert_eq!(node_ids, (0..10).map(pump_id).collect());
+
+    // A continuation point can only be used once
+    let response = query_next(&mut session, &continuation_point, false);
+    assert_eq!(supported_message_as!(response, ServiceFault).response_header.service_result, BadContinuationPointInvalid);
+
+    // A released continuation point returns nothing and cannot be used again
+    let request = make_query_first_request(pump_type_id(), true, ContentFilter { elements: None }, 4);
+    let response = supported_message_as!(qs.query_first(&mut session, &address_space, request).unwrap(), QueryFirstResponse);
+    let continuation_point = response.continuation_point;
+    let response = supported_message_as!(query_next(&mut session, &continuation_point, true), QueryNextResponse);
+    assert!(response.query_data_sets.unwrap().is_empty());
+    assert!(response.revised_continuation_point.is_null());
+    let response = query_next(&mut session, &continuation_point, false);
+    assert_eq!(supported_message_as!(response, ServiceFault).response_header.service_result, BadContinuationPointInvalid);
+}
+
+#[test]
+fn query_not_readable() {
+    let st = ServiceTest::new();
+    let qs = QueryService::new();
+    {
+        let mut address_space = st.get_address_space();
+        add_pumps_to_address_space(&mut address_space, 6);
+        // The status of the first pump cannot be read
+        let status_id = NodeId::new_string(1, "Pump0.Status");
+        address_space.find_variable_by_node_id(&status_id).unwrap().set_access_level(0);
+    }
+    let mut session = st.session.write().unwrap();
+    let address_space = st.server.address_space.read().unwrap();
+
+    // A filter cannot see the value, so the first pump is not at fault
+    let request = make_query_first_request(pump_type_id(), true, status_is_fault_filter(), 0);
+    let response = supported_message_as!(qs.query_first(&mut session, &address_space, request).unwrap(), QueryFirstResponse);
+    assert_eq!(data_set_node_ids(&response.query_data_sets.unwrap()), [pump_id(2), pump_id(4)].iter().cloned().collect());
+
+    // Nor is the value returned in a data set
+    let request = make_query_first_request(dosing_pump_type_id(), false, ContentFilter { elements: None }, 0);
+    let response = supported_message_as!(qs.query_first(&mut session, &address_space, request).unwrap(), QueryFirstResponse);
+    let query_data_sets = response.query_data_sets.unwrap();
+    let data_set = query_data_sets.iter().find(|data_set| data_set.node_id.node_id == pump_id(0)).unwrap();
+    assert_eq!(data_set.values.as_ref().unwrap(), &vec![Variant::from(BadNotReadable)]);
+}
+
+#[test]
+fn query_diagnostics() {
+    let st = ServiceTest::new();
+    let qs = QueryService::new();
+    add_pumps_to_address_space(&mut st.get_address_space(), 6);
+    let mut session = st.session.write().unwrap();
+    let address_space = st.server.address_space.read().unwrap();
+
+    // A diagnostic for the node type that is not a type
+    let mut request = make_query_first_request(pump_id(0), true, status_is_fault_filter(), 0);
+    request.request_header.return_diagnostics = DiagnosticFlags::OPERATIONS_LEVEL_SYMBOLIC_ID;
+    let response = supported_message_as!(qs.query_first(&mut session, &address_space, request).unwrap(), QueryFirstResponse);
+    let string_table = response.response_header.string_table.unwrap();
+    let diagnostic_infos = response.diagnostic_infos.unwrap();
+    assert_eq!(diagnostic_infos.len(), 1);
+    assert_eq!(string_table[diagnostic_infos[0].symbolic_id.unwrap() as usize].as_ref(), "BadNotTypeDefinition");
+
+    // And for the filter element that is not valid
+    let mut filter = status_is_fault_filter();
+    filter.elements.as_mut().unwrap()[0].filter_operands.as_mut().unwrap().pop();
+    let mut request = make_query_first_request(pump_type_id(), true, filter, 0);
+    request.request_header.return_diagnostics = DiagnosticFlags::OPERATIONS_LEVEL_SYMBOLIC_ID;
+    let response = supported_message_as!(qs.query_first(&mut session, &address_space, request).unwrap(), QueryFirstResponse);
+    let string_table = response.response_header.string_table.unwrap();
+    let element_diagnostic_infos = response.filter_result.element_diagnostic_infos.unwrap();
+    assert_eq!(element_diagnostic_infos.len(), 1);
+    assert_eq!(string_table[element_diagnostic_infos[0].symbolic_id.unwrap() as usize].as_ref(), "BadFilterOperandCountMismatch");
+
+    // No diagnostics unless they are asked for
+    let request = make_query_first_request(pump_id(0), true, status_is_fault_filter(), 0);
+    let response = supported_message_as!(qs.query_first(&mut session, &address_space, request).unwrap(), QueryFirstResponse);
+    assert!(response.diagnostic_infos.is_none());
+    assert!(response.response_header.string_table.is_none());
+
+    // QueryNext describes a continuation point that is not valid
+    let mut request_header = make_request_header();
+    request_header.return_diagnostics = DiagnosticFlags::SERVICE_LEVEL_SYMBOLIC_ID;
+    let request = QueryNextRequest {
+        request_header,
+        release_continuation_point: false,
+        continuation_point: ByteString::from(vec![1u8, 2, 3]),
+    };
+    let response = supported_message_as!(qs.query_next(&mut session, &address_space, request).unwrap(), ServiceFault);
+    let response_header = response.response_header;
+    let string_table = response_header.string_table.unwrap();
+    assert_eq!(string_table[response_header.service_diagnostics.symbolic_id.unwrap() as usize].as_ref(), "BadContinuationPointInvalid");
+}
@@ -2,6 +2,8 @@ use tests::*;
 
 use config::*;
 use server::Server;
+use comms::transport::Transport;
+use services::session::SessionService;
 
 #[test]
 fn anonymous_user_token() {
@@ -19,15 +21,15 @@ fn anonymous_user_token() {
 
     let result = server_state.authenticate_endpoint("opc.tcp://localhost:4855/", SecurityPolicy::None, MessageSecurityMode::None, &token);
     trace!("result = {:?}", result);
-    assert!(result.is_good());
+    assert_eq!(result.unwrap(), ANONYMOUS_USER_TOKEN_ID);
 
     let result = server_state.authenticate_endpoint("opc.tcp://localhost:4855/x", SecurityPolicy::None, MessageSecurityMode::None, &token);
     trace!("result = {:?}", result);
-    assert_eq!(result, BadTcpEndpointUrlInvalid);
+    assert_eq!(result.unwrap_err(), BadTcpEndpointUrlInvalid);
 
     let result = server_state.authenticate_endpoint("opc.tcp://localhost:4855/noaccess", SecurityPolicy::None, MessageSecurityMode::None, &token);
     trace!("result = {:?}", result);
-    assert_eq!(result, BadIdentityTokenRejected);
+    assert_eq!(result.unwrap_err(), BadIdentityTokenRejected);
 }
 
 fn make_user_name_identity_token(user: &str, pass: &[u8]) -> ExtensionObject {
@@ -51,18 +53,105 @@ fn user_name_pass_token() {
     // Test that a good user authenticates
     let token = make_user_name_identity_token("sample", b"sample1");
     let result = server_state.authenticate_endpoint("opc.tcp://localhost:4855/", SecurityPolicy::None, MessageSecurityMode::None, &token);
-    assert!(result.is_good());
+    assert_eq!(result.unwrap(), "sample_user");
 
     // Invalid tests
     let token = make_user_name_identity_token("samplex", b"sample1");
     let result = server_state.authenticate_endpoint("opc.tcp://localhost:4855/", SecurityPolicy::None, MessageSecurityMode::None, &token);
-    assert_eq!(result, BadIdentityTokenRejected);
+    assert_eq!(result.unwrap_err(), BadIdentityTokenRejected);
 
     let token = make_user_name_identity_token("sample", b"sample");
     let result = server_state.authenticate_endpoint("opc.tcp://localhost:4855/", SecurityPolicy::None, MessageSecurityMode::None, &token);
-    assert_eq!(result, BadIdentityTokenRejected);
+    assert_eq!(result.unwrap_err(), BadIdentityTokenRejected);
 
     let token = make_user_name_identity_token("", b"sample");
     let result = server_state.authenticate_endpoint("opc.tcp://localhost:4855/", SecurityPolicy::None, MessageSecurityMode::None, &token);
-    assert_eq!(result, BadIdentityTokenRejected);
+    assert_eq!(result.unwrap_err(), BadIdentityTokenRejected);
+}
+
+fn make_activate_session_request(authentication_token: &NodeId, user_identity_token: ExtensionObject) -> ActivateSessionRequest {
+    ActivateSessionRequest {
+        request_header: RequestHeader::new(authentication_token, &DateTime::now(), 1),
+        client_signature: SignatureData::null(),
+        client_software_certificates: None,
+        locale_ids: None,
+        user_identity_token,
+        user_token_signature: SignatureData::null(),
+    }
+}
+
+#[test]
+fn resume_detached_session() {
+    opcua_core::init_logging();
+
+    let server = Server::new(ServerConfig::new_sample());
+    let ss = SessionService::new();
+    let authentication_token = NodeId::new(0, ByteString::random(32));
+
+    // Activate a session on the first connection
+    let mut transport = server.new_transport();
+    {
+        let mut server_state = server.server_state.write().unwrap();
+        let session = transport.session();
+        let mut session = session.write().unwrap();
+        session.session_id = NodeId::new(1, 100);
+        session.authentication_token = authentication_token.clone();
+        session.session_timeout = 50000f64;
+        session.endpoint_url = UAString::from("opc.tcp://localhost:4855/");
+        let response = ss.activate_session(&mut server_state, &mut session, make_activate_session_request(&authentication_token, ExtensionObject::null())).unwrap();
+        let _: ActivateSessionResponse = supported_message_as!(response, ActivateSessionResponse);
+        assert!(session.is_resumable());
+    }
+
+    // The connection drops and the session is kept
+    transport.terminate_session(BadConnectionClosed);
+    assert_eq!(server.server_state.read().unwrap().detached_sessions.len(), 1);
+
+    let transport = server.new_transport();
+    let session = transport.session();
+    let mut session = session.write().unwrap();
+    let mut server_state = server.server_state.write().unwrap();
+
+    // A token that no session has is rejected
+    let request = make_activate_session_request(&NodeId::new(0, 99), ExtensionObject::null());
+    let response: ServiceFault = supported_message_as!(ss.activate_session(&mut server_state, &mut session, request).unwrap(), ServiceFault);
+    assert_eq!(response.response_header.service_result, BadIdentityTokenRejected);
+    session.terminate_session = false;
+
+    // A failed activation leaves the session detached
+    let request = make_activate_session_request(&authentication_token, make_user_name_identity_token("sample", b"x"));
+    let response: ServiceFault = supported_message_as!(ss.activate_session(&mut server_state, &mut session, request).unwrap(), ServiceFault);
+    assert_eq!(response.response_header.service_result, BadIdentityTokenRejected);
+    assert_eq!(server_state.detached_sessions.len(), 1);
+    assert!(session.authentication_token.is_null());
+
+    // Activation from the new connection resumes the session
+    let request = make_activate_session_request(&authentication_token, ExtensionObject::null());
+    let _: ActivateSessionResponse = supported_message_as!(ss.activate_session(&mut server_state, &mut session, request).unwrap(), ActivateSessionResponse);
+    assert!(server_state.detached_sessions.is_empty());
+    assert_eq!(session.session_id, NodeId::new(1, 100));
+    assert_eq!(session.authentication_token, authentication_token);
+    assert!(!session.terminated());
+    assert!(!session.terminate_session);
+}
+
+#[test]
+fn expire_detached_session() {
+    let server = Server::new(ServerConfig::new_sample());
+    let transport = server.new_transport();
+    let session = transport.session();
+    let terminated_at = {
+        let mut session = session.write().unwrap();
+        session.authentication_token = NodeId::new(0, 99);
+        session.session_timeout = 1000f64;
+        session.set_terminated();
+        session.terminated_at
+    };
+
+    let mut server_state = server.server_state.write().unwrap();
+    server_state.detach_session(session);
+    server_state.remove_expired_sessions(&(terminated_at + time::Duration::milliseconds(999)));
+    assert_eq!(server_state.detached_sessions.len(), 1);
+    server_state.remove_expired_sessions(&(terminated_at + time::Duration::milliseconds(1000)));
+    assert!(server_state.detached_sessions.is_empty());
 }
@@ -1,3 +1,4 @@
+use std;
 use std::ops::Add;
 
 use prelude::*;
@@ -6,6 +7,8 @@ use chrono::Utc;
 
 use opcua_core;
 
+use config::ANONYMOUS_USER_TOKEN_ID;
+use constants;
 use services::subscription::SubscriptionService;
 use services::monitored_item::MonitoredItemService;
 
@@ -100,7 +103,7 @@ fn publish_response_subscription() {
     {
         let request = create_monitored_items_request(subscription_id, VariableId::Server_ServerStatus_CurrentTime);
         debug!("CreateMonitoredItemsRequest {:#?}", request);
-        let response: CreateMonitoredItemsResponse = supported_message_as!(mis.create_monitored_items(&mut session, request).unwrap(), CreateMonitoredItemsResponse);
+        let response: CreateMonitoredItemsResponse = supported_message_as!(mis.create_monitored_items(&mut session, &address_space, request).unwrap(), CreateMonitoredItemsResponse);
         debug!("CreateMonitoredItemsResponse {:#?}", response);
         // let result = response.results.unwrap()[0].monitored_item_id;
     }
@@ -242,4 +245,261 @@ fn republish() {
     };
     let response: ServiceFault = supported_message_as!(ss.republish(&mut session, request).unwrap(), ServiceFault);
     assert_eq!(response.response_header.service_result, StatusCode::BadMessageNotAvailable);
-}
\ No newline at end of file
+}
+
+#[test]
+fn transfer_subscriptions() {
+    opcua_core::init_logging();
+
+    let st = ServiceTest::new();
+    let ss = SubscriptionService::new();
+    let sequence_number = 5;
+
+    // A session whose connection dropped has a subscription with an unacknowledged notification
+    let subscription_id = {
+        let detached_session = st.server.new_transport().session();
+        let mut server_state = st.server_state.write().unwrap();
+        let subscription_id = {
+            let mut detached_session = detached_session.write().unwrap();
+            detached_session.user_token_id = Some(ANONYMOUS_USER_TOKEN_ID.to_string());
+            detached_session.session_timeout = 50000f64;
+            detached_session.set_terminated();
+            let response: CreateSubscriptionResponse = supported_message_as!(ss.create_subscription(&mut server_state, &mut detached_session, create_subscription_request()).unwrap(), CreateSubscriptionResponse);
+            let notification = NotificationMessage::new_data_change(sequence_number, DateTime::now(), vec![]);
+            detached_session.subscriptions.retransmission_queue().insert(sequence_number, (response.subscription_id, notification));
+            response.subscription_id
+        };
+        server_state.detach_session(detached_session);
+        subscription_id
+    };
+
+    let (mut server_state, mut session) = st.get_server_state_and_session();
+    let make_request = |subscription_ids: Vec<UInt32>| TransferSubscriptionsRequest {
+        request_header: RequestHeader::new(&NodeId::null(), &DateTime::now(), 1),
+        subscription_ids: Some(subscription_ids),
+        send_initial_values: true,
+    };
+
+    // Another user's session cannot take the subscription
+    session.user_token_id = Some("sample_user".to_string());
+    let response: TransferSubscriptionsResponse = supported_message_as!(ss.transfer_subscriptions(&mut server_state, &mut session, &st.session, make_request(vec![subscription_id])).unwrap(), TransferSubscriptionsResponse);
+    let results = response.results.unwrap();
+    assert_eq!(results[0].status_code, BadUserAccessDenied);
+    assert!(!session.subscriptions.contains(subscription_id));
+
+    // The same user's session takes it with the notification waiting for acknowledgement
+    session.user_token_id = Some(ANONYMOUS_USER_TOKEN_ID.to_string());
+    let response: TransferSubscriptionsResponse = supported_message_as!(ss.transfer_subscriptions(&mut server_state, &mut session, &st.session, make_request(vec![subscription_id, subscription_id + 1])).unwrap(), TransferSubscriptionsResponse);
+    let results = response.results.unwrap();
+    assert_eq!(results[0].status_code, Good);
+    assert_eq!(results[0].available_sequence_numbers, Some(vec![sequence_number]));
+    assert_eq!(results[1].status_code, BadSubscriptionIdInvalid);
+    assert!(session.subscriptions.contains(subscription_id));
+    {
+        // The detached session is left to tell its client where the subscription went
+        let detached_session = server_state.detached_sessions[0].read().unwrap();
+        assert!(!detached_session.subscriptions.contains(subscription_id));
+        assert!(detached_session.subscriptions.has_unsent_notifications());
+    }
+
+    // The notification can be republished from the new session
+    let request = RepublishRequest {
+        request_header: RequestHeader::new(&NodeId::null(), &DateTime::now(), 1),
+        subscription_id,
+        retransmit_sequence_number: sequence_number,
+    };
+    let response: RepublishResponse = supported_message_as!(ss.republish(&mut session, request).unwrap(), RepublishResponse);
+    assert_eq!(response.notification_message.sequence_number, sequence_number);
+
+    // Transferring a subscription the session already has succeeds
+    let response: TransferSubscriptionsResponse = supported_message_as!(ss.transfer_subscriptions(&mut server_state, &mut session, &st.session, make_request(vec![subscription_id])).unwrap(), TransferSubscriptionsResponse);
+    assert_eq!(response.results.unwrap()[0].status_code, Good);
+}
+
+#[test]
+fn transfer_subscriptions_from_live_session() {
+    opcua_core::init_logging();
+
+    let st = ServiceTest::new();
+    let ss = SubscriptionService::new();
+
+    // Another connection of the same user has a subscription
+    let other_session = st.server.new_transport().session();
+    let subscription_id = {
+        let mut server_state = st.server_state.write().unwrap();
+        let mut other_session = other_session.write().unwrap();
+        other_session.user_token_id = Some(ANONYMOUS_USER_TOKEN_ID.to_string());
+        let response: CreateSubscriptionResponse = supported_message_as!(ss.create_subscription(&mut server_state, &mut other_session, create_subscription_request()).unwrap(), CreateSubscriptionResponse);
+        response.subscription_id
+    };
+
+    {
+        let (mut server_state, mut session) = st.get_server_state_and_session();
+        session.user_token_id = Some(ANONYMOUS_USER_TOKEN_ID.to_string());
+        let request = TransferSubscriptionsRequest {
+            request_header: RequestHeader::new(&NodeId::null(), &DateTime::now(), 1),
+            subscription_ids: Some(vec![subscription_id]),
+            send_initial_values: false,
+        };
+        let response: TransferSubscriptionsResponse = supported_message_as!(ss.transfer_subscriptions(&mut server_state, &mut session, &st.session, request).unwrap(), TransferSubscriptionsResponse);
+        assert_eq!(response.results.unwrap()[0].status_code, Good);
+        assert!(session.subscriptions.contains(subscription_id));
+    }
+
+    // The other session's next publish response says the subscription was transferred
+    let address_space = st.server.address_space.read().unwrap();
+    let mut other_session = other_session.write().unwrap();
+    assert!(!other_session.subscriptions.contains(subscription_id));
+    let request = PublishRequest {
+        request_header: RequestHeader::new(&NodeId::null(), &DateTime::now(), 1),
+        subscription_acknowledgements: None,
+    };
+    assert!(ss.publish(&mut other_session, 1, &address_space, request).unwrap().is_none());
+    assert!(other_session.tick_subscriptions(&Utc::now(), &address_space, TickReason::TickTimerFired).is_ok());
+    let response = other_session.subscriptions.take_publish_responses().unwrap().pop_back().unwrap();
+    let response: PublishResponse = supported_message_as!(response.response, PublishResponse);
+    assert_eq!(response.subscription_id, subscription_id);
+    let status_changes = response.notification_message.status_change_notifications();
+    assert_eq!(status_changes.len(), 1);
+    assert_eq!(status_changes[0].status, GoodSubscriptionTransferred);
+}
+
+#[test]
+fn server_diagnostics() {
+    opcua_core::init_logging();
+
+    let st = ServiceTest::new();
+    let ss = SubscriptionService::new();
+    let diagnostics = st.server_state.read().unwrap().diagnostics.clone();
+
+    // A session makes some requests, one of them rejected
+    let (session_id, subscription_id) = {
+        let (mut server_state, mut session) = st.get_server_state_and_session();
+        session.session_id = session.next_session_id();
+        let response: CreateSubscriptionResponse = supported_message_as!(ss.create_subscription(&mut server_state, &mut session, create_subscription_request()).unwrap(), CreateSubscriptionResponse);
+        let request = SetPublishingModeRequest {
+            request_header: RequestHeader::new(&NodeId::null(), &DateTime::now(), 1),
+            publishing_enabled: false,
+            subscription_ids: Some(vec![response.subscription_id]),
+        };
+        let _ = ss.set_publishing_mode(&mut session, request).unwrap();
+
+        let mut diagnostics = diagnostics.write().unwrap();
+        diagnostics.on_service_request(&session, ObjectId::CreateSubscriptionRequest_Encoding_DefaultBinary, Good);
+        diagnostics.on_service_request(&session, ObjectId::SetPublishingModeRequest_Encoding_DefaultBinary, Good);
+        diagnostics.on_service_request(&session, ObjectId::ReadRequest_Encoding_DefaultBinary, BadUserAccessDenied);
+        (session.session_id.clone(), response.subscription_id)
+    };
+
+    let value = |variable_id: VariableId| st.get_address_space().find_variable_by_variable_id(variable_id).unwrap().value().value.unwrap();
+    let extension_objects = |variable_id: VariableId| match value(variable_id) {
+        Variant::Array(values) => values.into_iter().map(|value| match value {
+            Variant::ExtensionObject(extension_object) => *extension_object,
+            _ => panic!("Expected an extension object")
+        }).collect::<Vec<ExtensionObject>>(),
+        _ => panic!("Expected an array")
+    };
+
+    // The session's service counters
+    let sessions = extension_objects(VariableId::Server_ServerDiagnostics_SessionsDiagnosticsSummary_SessionDiagnosticsArray);
+    assert_eq!(sessions.len(), 1);
+    let session_diagnostics = sessions[0].decode_inner::<SessionDiagnosticsDataType>().unwrap();
+    assert_eq!(session_diagnostics.session_id, session_id);
+    assert_eq!(session_diagnostics.current_subscriptions_count, 1);
+    assert_eq!(session_diagnostics.total_request_count, ServiceCounterDataType { total_count: 3, error_count: 1 });
+    assert_eq!(session_diagnostics.unauthorized_request_count, 1);
+    assert_eq!(session_diagnostics.create_subscription_count, ServiceCounterDataType { total_count: 1, error_count: 0 });
+    assert_eq!(session_diagnostics.read_count, ServiceCounterDataType { total_count: 1, error_count: 1 });
+    assert_eq!(session_diagnostics.write_count, ServiceCounterDataType { total_count: 0, error_count: 0 });
+
+    let sessions = extension_objects(VariableId::Server_ServerDiagnostics_SessionsDiagnosticsSummary_SessionSecurityDiagnosticsArray);
+    assert_eq!(sessions.len(), 1);
+    let session_security_diagnostics = sessions[0].decode_inner::<SessionSecurityDiagnosticsDataType>().unwrap();
+    assert_eq!(session_security_diagnostics.session_id, session_id);
+
+    // The subscription's counts
+    let subscriptions = extension_objects(VariableId::Server_ServerDiagnostics_SubscriptionDiagnosticsArray);
+    assert_eq!(subscriptions.len(), 1);
+    let subscription_diagnostics = subscriptions[0].decode_inner::<SubscriptionDiagnosticsDataType>().unwrap();
+    assert_eq!(subscription_diagnostics.session_id, session_id);
+    assert_eq!(subscription_diagnostics.subscription_id, subscription_id);
+    assert_eq!(subscription_diagnostics.publishing_enabled, false);
+    assert_eq!(subscription_diagnostics.disable_count, 1);
+
+    // The summary is live
+    assert_eq!(value(VariableId::Server_ServerDiagnostics_ServerDiagnosticsSummary_CurrentSessionCount), Variant::UInt32(1));
+    assert_eq!(value(VariableId::Server_ServerDiagnostics_ServerDiagnosticsSummary_CurrentSubscriptionCount), Variant::UInt32(1));
+    assert_eq!(value(VariableId::Server_ServerDiagnostics_ServerDiagnosticsSummary_RejectedRequestsCount), Variant::UInt32(1));
+    assert_eq!(value(VariableId::Server_ServerDiagnostics_ServerDiagnosticsSummary_SecurityRejectedRequestsCount), Variant::UInt32(1));
+
+    // Turning diagnostics off through the enabled flag empties the arrays
+    assert_eq!(value(VariableId::Server_ServerDiagnostics_EnabledFlag), Variant::Boolean(true));
+    st.get_address_space().find_variable_by_variable_id(VariableId::Server_ServerDiagnostics_EnabledFlag).unwrap().set_value(DataValue::new(false));
+    assert_eq!(value(VariableId::Server_ServerDiagnostics_EnabledFlag), Variant::Boolean(false));
+    assert!(extension_objects(VariableId::Server_ServerDiagnostics_SessionsDiagnosticsSummary_SessionDiagnosticsArray).is_empty());
+    assert!(extension_objects(VariableId::Server_ServerDiagnostics_SubscriptionDiagnosticsArray).is_empty());
+}
+
+#[test]
+fn publishing_interval_not_a_number() {
+    let st = ServiceTest::new();
+    let ss = SubscriptionService::new();
+    let (mut server_state, mut session) = st.get_server_state_and_session();
+
+    // A publishing interval that is not a number is revised to the default
+    let mut request = create_subscription_request();
+    request.requested_publishing_interval = std::f64::NAN;
+    let response: CreateSubscriptionResponse = supported_message_as!(ss.create_subscription(&mut server_state, &mut session, request).unwrap(), CreateSubscriptionResponse);
+    assert_eq!(response.revised_publishing_interval, constants::DEFAULT_PUBLISHING_INTERVAL);
+
+    let diagnostics = server_state.diagnostics.read().unwrap();
+    assert_eq!(diagnostics.server_diagnostics_summary().publishing_interval_count, 1);
+}
+
+#[test]
+fn create_monitored_items_user_access() {
+    let st = ServiceTest::new();
+    let ss = SubscriptionService::new();
+    let mis = MonitoredItemService::new();
+    let (mut server_state, mut session) = st.get_server_state_and_session();
+    let address_space = st.get_address_space();
+
+    let response: CreateSubscriptionResponse = supported_message_as!(ss.create_subscription(&mut server_state, &mut session, create_subscription_request()).unwrap(), CreateSubscriptionResponse);
+    let subscription_id = response.subscription_id;
+
+    // Anonymous users cannot monitor the security diagnostics of sessions, the items either side
+    // of it are created and the results are in the order of the request
+    let mut request = create_monitored_items_request(subscription_id, VariableId::Server_ServerStatus_CurrentTime);
+    {
+        let items_to_create = request.items_to_create.as_mut().unwrap();
+        let item_to_create = items_to_create[0].clone();
+        items_to_create.push(MonitoredItemCreateRequest {
+            item_to_monitor: ReadValueId {
+                node_id: VariableId::Server_ServerDiagnostics_SessionsDiagnosticsSummary_SessionSecurityDiagnosticsArray.into(),
+                ..item_to_create.item_to_monitor.clone()
+            },
+            ..item_to_create.clone()
+        });
+        items_to_create.push(MonitoredItemCreateRequest {
+            item_to_monitor: ReadValueId {
+                node_id: VariableId::Server_ServerStatus_StartTime.into(),
+                ..item_to_create.item_to_monitor.clone()
+            },
+            ..item_to_create
+        });
+    }
+    let response: CreateMonitoredItemsResponse = supported_message_as!(mis.create_monitored_items(&mut session, &address_space, request).unwrap(), CreateMonitoredItemsResponse);
+    let results = response.results.unwrap();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].status_code, Good);
+    assert_eq!(results[1].status_code, BadUserAccessDenied);
+    assert_eq!(results[1].monitored_item_id, 0);
+    assert_eq!(results[2].status_code, Good);
+    assert_ne!(results[0].monitored_item_id, results[2].monitored_item_id);
+
+    // Other users can
+    session.user_token_id = Some("sample_user".to_string());
+    let request = create_monitored_items_request(subscription_id, VariableId::Server_ServerDiagnostics_SessionsDiagnosticsSummary_SessionSecurityDiagnosticsArray);
+    let response: CreateMonitoredItemsResponse = supported_message_as!(mis.create_monitored_items(&mut session, &address_space, request).unwrap(), CreateMonitoredItemsResponse);
+    assert_eq!(response.results.unwrap()[0].status_code, Good);
+}
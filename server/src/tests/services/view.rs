@@ -258,3 +258,225 @@ fn translate_browse_paths_to_node_ids() {
         */
     }
 }
+
+#[test]
+fn browse_diagnostics() {
+    let st = ServiceTest::new();
+    let (_, mut session) = st.get_server_state_and_session();
+    let vs = ViewService::new();
+    let address_space = st.server.address_space.read().unwrap();
+    let nodes: Vec<NodeId> = vec![ObjectId::RootFolder.into(), NodeId::new_string(1, "vxxx")];
+
+    // No diagnostics unless they are asked for
+    let response = do_browse(&vs, &mut session, &address_space, &nodes, 1000);
+    assert!(response.diagnostic_infos.is_none());
+
+    // A diagnostic for the node that could not be browsed
+    let mut request = make_browse_request(&nodes, 1000, BrowseDirection::Forward, ReferenceTypeId::Organizes);
+    request.request_header.return_diagnostics = DiagnosticFlags::OPERATIONS_LEVEL_SYMBOLIC_ID;
+    let response = supported_message_as!(vs.browse(&mut session, &address_space, request).unwrap(), BrowseResponse);
+    let string_table = response.response_header.string_table.unwrap();
+    let diagnostic_infos = response.diagnostic_infos.unwrap();
+    assert_eq!(diagnostic_infos.len(), 2);
+    assert_eq!(diagnostic_infos[0], DiagnosticInfo::new());
+    assert_eq!(string_table[diagnostic_infos[1].symbolic_id.unwrap() as usize].as_ref(), "BadNodeIdUnknown");
+
+    // Translating a path with no elements is described too
+    let mut request_header = make_request_header();
+    request_header.return_diagnostics = DiagnosticFlags::OPERATIONS_LEVEL_SYMBOLIC_ID;
+    let request = TranslateBrowsePathsToNodeIdsRequest {
+        request_header,
+        browse_paths: Some(vec![BrowsePath {
+            starting_node: ObjectId::RootFolder.into(),
+            relative_path: RelativePath {
+                elements: None,
+            },
+        }]),
+    };
+    let response = supported_message_as!(vs.translate_browse_paths_to_node_ids(&address_space, request).unwrap(), TranslateBrowsePathsToNodeIdsResponse);
+    let string_table = response.response_header.string_table.unwrap();
+    let diagnostic_infos = response.diagnostic_infos.unwrap();
+    assert_eq!(diagnostic_infos.len(), 1);
+    assert_eq!(string_table[diagnostic_infos[0].symbolic_id.unwrap() as usize].as_ref(), "BadNothingToDo");
+}
+
+/// Adds a plant folder with an operator view that hides its calibration variable
+fn add_operator_view(address_space: &mut AddressSpace) -> (NodeId, NodeId) {
+    let plant_id = address_space.add_folder("Plant", "Plant", &AddressSpace::objects_folder_id()).unwrap();
+    let _ = address_space.add_variables(vec![
+        Variable::new(&NodeId::new_string(1, "Temperature"), "Temperature", "Temperature", "", 20 as Int32),
+        Variable::new(&NodeId::new_string(1, "Calibration"), "Calibration", "Calibration", "", 1 as Int32),
+    ], &plant_id);
+
+    let view_id = NodeId::new_string(1, "Operator");
+    let _ = address_space.add_view(View::new(&view_id, "Operator", "Operator", "", false, true), &AddressSpace::views_folder_id());
+    address_space.add_organizes(&view_id, &plant_id);
+    assert!(address_space.add_nodes_to_view(&view_id, &[plant_id.clone(), NodeId::new_string(1, "Temperature")]));
+    (view_id, plant_id)
+}
+
+fn browse_in_view(vs: &ViewService, session: &mut Session, address_space: &AddressSpace, view: ViewDescription, nodes: &[NodeId]) -> SupportedMessage {
+    let mut request = make_browse_request(nodes, 1000, BrowseDirection::Forward, ReferenceTypeId::HierarchicalReferences);
+    request.view = view;
+    vs.browse(session, address_space, request).unwrap()
+}
+
+fn view_description(view_id: &NodeId, timestamp: DateTime, view_version: UInt32) -> ViewDescription {
+    ViewDescription {
+        view_id: view_id.clone(),
+        timestamp,
+        view_version,
+    }
+}
+
+#[test]
+fn browse_view() {
+    let st = ServiceTest::new();
+    let (_, mut session) = st.get_server_state_and_session();
+    let vs = ViewService::new();
+
+    let mut address_space = st.server.address_space.write().unwrap();
+    let (view_id, plant_id) = add_operator_view(&mut address_space);
+    let view_version = address_space.find_view(&view_id).unwrap().view_version();
+    let view = view_description(&view_id, DateTime::epoch(), view_version);
+
+    // The view node is the entry point to the view
+    let response = supported_message_as!(browse_in_view(&vs, &mut session, &address_space, view.clone(), &[view_id.clone()]), BrowseResponse);
+    let references = response.results.unwrap()[0].references.clone().unwrap();
+    assert_eq!(references.len(), 1);
+    assert_eq!(references[0].node_id.node_id, plant_id);
+
+    // Only the nodes in the view are seen through it
+    let response = supported_message_as!(browse_in_view(&vs, &mut session, &address_space, view.clone(), &[plant_id.clone(), NodeId::new_string(1, "Calibration")]), BrowseResponse);
+    let results = response.results.unwrap();
+    let references = results[0].references.as_ref().unwrap();
+    assert_eq!(references.len(), 1);
+    assert_eq!(references[0].browse_name, QualifiedName::new(0, "Temperature"));
+    assert_eq!(results[1].status_code, BadNodeNotInView);
+
+    // Without the view everything is seen
+    let response = supported_message_as!(browse_in_view(&vs, &mut session, &address_space, view_description(&NodeId::null(), DateTime::epoch(), 0), &[plant_id.clone()]), BrowseResponse);
+    assert_eq!(response.results.unwrap()[0].references.as_ref().unwrap().len(), 2);
+
+    // Views must exist, be browsed as they are now and have the version supplied
+    let bad_views = vec![
+        (view_description(&plant_id, DateTime::epoch(), 0), BadViewIdUnknown),
+        (view_description(&view_id, DateTime::now(), 0), BadViewTimestampInvalid),
+        (view_description(&view_id, DateTime::epoch(), view_version + 1), BadViewVersionInvalid),
+    ];
+    for (view, status_code) in bad_views {
+        let response = supported_message_as!(browse_in_view(&vs, &mut session, &address_space, view, &[plant_id.clone()]), ServiceFault);
+        assert_eq!(response.response_header.service_result, status_code);
+    }
+}
+
+#[test]
+fn translate_browse_paths_in_view() {
+    let st = ServiceTest::new();
+    let vs = ViewService::new();
+    let view_id = {
+        let mut address_space = st.server.address_space.write().unwrap();
+        add_operator_view(&mut address_space).0
+    };
+
+    let browse_path = |target_name: &str| {
+        BrowsePath {
+            starting_node: view_id.clone(),
+            relative_path: RelativePath {
+                elements: Some(vec![
+                    RelativePathElement {
+                        reference_type_id: ReferenceTypeId::Organizes.into(),
+                        is_inverse: false,
+                        include_subtypes: true,
+                        target_name: QualifiedName::new(0, "Plant"),
+                    },
+                    RelativePathElement {
+                        reference_type_id: ReferenceTypeId::Organizes.into(),
+                        is_inverse: false,
+                        include_subtypes: true,
+                        target_name: QualifiedName::new(0, target_name),
+                    },
+                ]),
+            },
+        }
+    };
+    let request = TranslateBrowsePathsToNodeIdsRequest {
+        request_header: make_request_header(),
+        browse_paths: Some(vec![browse_path("Temperature"), browse_path("Calibration")]),
+    };
+
+    // Paths that start from the view node stay inside the view
+    let address_space = st.server.address_space.read().unwrap();
+    let response = vs.translate_browse_paths_to_node_ids(&address_space, request).unwrap();
+    let results = supported_message_as!(response, TranslateBrowsePathsToNodeIdsResponse).results.unwrap();
+    assert_eq!(results[0].targets.as_ref().unwrap()[0].target_id.node_id, NodeId::new_string(1, "Temperature"));
+    assert!(results[1].targets.is_none());
+}
+
+#[test]
+fn register_nodes() {
+    use services::attribute::AttributeService;
+
+    let st = ServiceTest::new();
+    let vs = ViewService::new();
+    let ats = AttributeService::new();
+    let (_, node_ids) = add_many_vars_to_address_space(&mut st.get_address_space(), 2);
+    let mut session = st.session.write().unwrap();
+    let address_space = st.server.address_space.read().unwrap();
+
+    let register_nodes = |session: &mut Session, nodes_to_register: Vec<NodeId>| {
+        let request = RegisterNodesRequest {
+            request_header: make_request_header(),
+            nodes_to_register: Some(nodes_to_register),
+        };
+        vs.register_nodes(session, &address_space, request).unwrap()
+    };
+    let read = |session: &Session, node_id: &NodeId| {
+        let request = ReadRequest {
+            request_header: make_request_header(),
+            max_age: 0f64,
+            timestamps_to_return: TimestampsToReturn::Neither,
+            nodes_to_read: Some(vec![ReadValueId {
+                node_id: node_id.clone(),
+                attribute_id: AttributeId::Value as UInt32,
+                index_range: UAString::null(),
+                data_encoding: QualifiedName::null(),
+            }]),
+        };
+        let response = supported_message_as!(ats.read(session, &address_space, request).unwrap(), ReadResponse);
+        response.results.unwrap().remove(0)
+    };
+
+    // String node ids are given numeric aliases, numeric node ids stay as they are
+    let response = register_nodes(&mut session, vec![node_ids[0].clone(), node_ids[1].clone(), ObjectId::Server.into()]);
+    let aliases = supported_message_as!(response, RegisterNodesResponse).registered_node_ids.unwrap();
+    assert!(aliases[0].is_numeric());
+    assert!(aliases[1].is_numeric());
+    assert_ne!(aliases[0], aliases[1]);
+    assert_eq!(aliases[2], ObjectId::Server.into());
+
+    // Registering a node again gives it the same alias
+    let response = register_nodes(&mut session, vec![node_ids[1].clone()]);
+    assert_eq!(supported_message_as!(response, RegisterNodesResponse).registered_node_ids.unwrap(), vec![aliases[1].clone()]);
+
+    // Aliases are read as the node they stand for
+    assert_eq!(read(&session, &aliases[1]).value.unwrap(), Variant::Int32(1));
+
+    let response = register_nodes(&mut session, vec![]);
+    assert_eq!(supported_message_as!(response, ServiceFault).response_header.service_result, BadNothingToDo);
+
+    // Unregistered aliases no longer resolve
+    let request = UnregisterNodesRequest {
+        request_header: make_request_header(),
+        nodes_to_unregister: Some(vec![aliases[1].clone()]),
+    };
+    let _ = supported_message_as!(vs.unregister_nodes(&mut session, request).unwrap(), UnregisterNodesResponse);
+    assert_eq!(read(&session, &aliases[1]).status.unwrap(), BadNodeIdUnknown);
+    assert_eq!(read(&session, &aliases[0]).value.unwrap(), Variant::Int32(0));
+
+    // A node that is registered again after it was unregistered is given a new alias
+    let response = register_nodes(&mut session, vec![node_ids[1].clone()]);
+    let new_aliases = supported_message_as!(response, RegisterNodesResponse).registered_node_ids.unwrap();
+    assert_ne!(new_aliases[0], aliases[1]);
+    assert_eq!(read(&session, &new_aliases[0]).value.unwrap(), Variant::Int32(1));
+}
@@ -1,2 +1,173 @@
 // Tests related to the Subscriptions struct go here, in particular relating to publish request
-// and response handling.
\ No newline at end of file
+// and response handling.
+
+use std::sync::{Arc, RwLock};
+
+use chrono;
+use time;
+
+use prelude::*;
+use diagnostics::ServerDiagnostics;
+use subscriptions::subscriptions::Subscriptions;
+
+fn make_subscriptions_with_notifications(subscription_id: UInt32, sequence_numbers: &[UInt32]) -> Subscriptions {
+    let mut subscriptions = Subscriptions::new(10, 30000);
+    let subscription = Subscription::new(Arc::new(RwLock::new(ServerDiagnostics::new())), subscription_id, true, 1000f64, 300, 100, 0, 0);
+    subscriptions.insert(subscription_id, subscription);
+    for sequence_number in sequence_numbers {
+        let notification = NotificationMessage::new_data_change(*sequence_number, DateTime::now(), vec![]);
+        subscriptions.retransmission_queue().insert(*sequence_number, (subscription_id, notification));
+    }
+    subscriptions
+}
+
+#[test]
+fn transfer_subscription_between_subscriptions() {
+    let mut from = make_subscriptions_with_notifications(1, &[3, 4]);
+    let mut to = make_subscriptions_with_notifications(2, &[1, 4]);
+
+    // Taking the subscription takes its unacknowledged notifications too
+    let transferred = from.take_subscription(1).unwrap();
+    assert!(!from.contains(1));
+    assert!(from.retransmission_queue().is_empty());
+    assert_eq!(transferred.unacknowledged.len(), 2);
+    // The session is left with a status change to say where the subscription went
+    assert!(from.has_unsent_notifications());
+    assert!(from.take_subscription(1).is_none());
+
+    // Sequence numbers are kept unless they clash with the session's own
+    let available_sequence_numbers = to.adopt_subscription(transferred);
+    assert!(to.contains(1));
+    assert_eq!(available_sequence_numbers, vec![3, 5]);
+    assert_eq!(to.available_sequence_numbers(1), Some(vec![3, 5]));
+    assert_eq!(to.available_sequence_numbers(2), Some(vec![1, 4]));
+
+    // The notifications can still be republished
+    assert_eq!(to.find_notification_message(1, 3).unwrap().sequence_number, 3);
+    assert_eq!(to.find_notification_message(1, 5).unwrap().sequence_number, 5);
+}
+
+fn make_subscription(subscription_id: UInt32, max_notifications_per_publish: UInt32, priority: Byte) -> Subscription {
+    let mut subscription = Subscription::new(Arc::new(RwLock::new(ServerDiagnostics::new())), subscription_id, true, 100f64, 300, 100, max_notifications_per_publish, priority);
+    subscription.state = SubscriptionState::Normal;
+    subscription
+}
+
+fn make_address_space(variable_count: UInt32) -> AddressSpace {
+    let mut address_space = AddressSpace::new();
+    for i in 0..variable_count {
+        let _ = address_space.add_variable(Variable::new(&NodeId::new(1, i), "test", "test", "", i), &AddressSpace::objects_folder_id());
+    }
+    address_space
+}
+
+fn make_monitored_item_requests(variable_count: UInt32) -> Vec<MonitoredItemCreateRequest> {
+    (0..variable_count).map(|i| MonitoredItemCreateRequest {
+        item_to_monitor: ReadValueId {
+            node_id: NodeId::new(1, i),
+            attribute_id: AttributeId::Value as UInt32,
+            index_range: UAString::null(),
+            data_encoding: QualifiedName::null(),
+        },
+        monitoring_mode: MonitoringMode::Reporting,
+        requested_parameters: MonitoringParameters {
+            client_handle: i,
+            sampling_interval: -1f64,
+            filter: ExtensionObject::null(),
+            queue_size: 1,
+            discard_oldest: true,
+        },
+    }).collect()
+}
+
+fn enqueue_publish_requests(subscriptions: &mut Subscriptions, address_space: &AddressSpace, count: UInt32) {
+    for request_id in 0..count {
+        let request = PublishRequest {
+            request_header: RequestHeader::new(&NodeId::null(), &DateTime::now(), 1),
+            subscription_acknowledgements: None,
+        };
+        assert!(subscriptions.enqueue_publish_request(address_space, request_id, request).is_ok());
+    }
+}
+
+/// Takes the publish responses in the order they were made
+fn publish_responses(subscriptions: &mut Subscriptions) -> Vec<PublishResponse> {
+    subscriptions.take_publish_responses().unwrap().into_iter().rev().map(|response| {
+        supported_message_as!(response.response, PublishResponse)
+    }).collect()
+}
+
+#[test]
+fn max_notifications_per_publish() {
+    let address_space = make_address_space(5);
+    let mut subscriptions = Subscriptions::new(10, 30000);
+    let mut subscription = make_subscription(1, 2, 0);
+    let _ = subscription.create_monitored_items(&address_space, TimestampsToReturn::Both, &make_monitored_item_requests(5));
+    subscriptions.insert(1, subscription);
+    enqueue_publish_requests(&mut subscriptions, &address_space, 5);
+
+    // The five notifications are split across three responses
+    let now = chrono::Utc::now() + time::Duration::seconds(1);
+    assert!(subscriptions.tick(&now, &address_space, TickReason::TickTimerFired).is_ok());
+    let responses = publish_responses(&mut subscriptions);
+    assert_eq!(responses.len(), 3);
+    let notification_counts: Vec<usize> = responses.iter().map(|response| {
+        response.notification_message.data_change_notifications()[0].monitored_items.as_ref().unwrap().len()
+    }).collect();
+    assert_eq!(notification_counts, vec![2, 2, 1]);
+    let more_notifications: Vec<bool> = responses.iter().map(|response| response.more_notifications).collect();
+    assert_eq!(more_notifications, vec![true, true, false]);
+    assert_eq!(subscriptions.publish_request_queue.len(), 2);
+}
+
+#[test]
+fn publish_by_priority() {
+    let address_space = make_address_space(2);
+    let mut subscriptions = Subscriptions::new(10, 30000);
+    let requests = make_monitored_item_requests(2);
+    let mut low_priority = make_subscription(1, 0, 1);
+    let _ = low_priority.create_monitored_items(&address_space, TimestampsToReturn::Both, &requests[0..1]);
+    subscriptions.insert(1, low_priority);
+    let mut high_priority = make_subscription(2, 0, 200);
+    let _ = high_priority.create_monitored_items(&address_space, TimestampsToReturn::Both, &requests[1..2]);
+    subscriptions.insert(2, high_priority);
+    enqueue_publish_requests(&mut subscriptions, &address_space, 2);
+
+    // The subscription with the higher priority gets the oldest publish request
+    let now = chrono::Utc::now() + time::Duration::seconds(1);
+    assert!(subscriptions.tick(&now, &address_space, TickReason::TickTimerFired).is_ok());
+    let subscription_ids: Vec<UInt32> = publish_responses(&mut subscriptions).iter().map(|response| response.subscription_id).collect();
+    assert_eq!(subscription_ids, vec![2, 1]);
+}
+
+#[test]
+fn subscription_lifetime_expires() {
+    let address_space = make_address_space(1);
+    let mut subscriptions = Subscriptions::new(10, 30000);
+    let mut subscription = make_subscription(1, 0, 0);
+    let _ = subscription.create_monitored_items(&address_space, TimestampsToReturn::Both, &make_monitored_item_requests(1));
+    // The client has not sent a publish request for so long that one more publishing interval
+    // without one expires the subscription
+    subscription.state = SubscriptionState::Late;
+    subscription.lifetime_counter = 2;
+    subscriptions.insert(1, subscription);
+
+    let now = chrono::Utc::now() + time::Duration::seconds(1);
+    assert!(subscriptions.tick(&now, &address_space, TickReason::TickTimerFired).is_ok());
+    assert!(!subscriptions.contains(1));
+    assert!(subscriptions.has_unsent_notifications());
+
+    // The next publish request tells the client what happened to the subscription
+    enqueue_publish_requests(&mut subscriptions, &address_space, 1);
+    let now = now + time::Duration::seconds(1);
+    assert!(subscriptions.tick(&now, &address_space, TickReason::TickTimerFired).is_ok());
+    let responses = publish_responses(&mut subscriptions);
+    assert_eq!(responses.len(), 1);
+    assert_eq!(responses[0].subscription_id, 1);
+    assert_eq!(responses[0].more_notifications, false);
+    let status_changes = responses[0].notification_message.status_change_notifications();
+    assert_eq!(status_changes.len(), 1);
+    assert_eq!(status_changes[0].status, StatusCode::BadTimeout);
+    assert!(!subscriptions.has_unsent_notifications());
+    assert!(subscriptions.available_sequence_numbers(1).is_none());
+}
@@ -98,6 +98,34 @@ impl SupportedMessage {
         };
         Ok(decoded_message)
     }
+
+    /// Returns the request header if the message is a request
+    pub fn request_header(&self) -> Option<&RequestHeader> {
+        match *self {
+`;
+
+    _.each(_.filter(message_types, function (message_type) { return message_type.endsWith("Request"); }), function (message_type) {
+        contents += `            SupportedMessage::${message_type}(ref value) => Some(&value.request_header),
+`;
+    });
+
+    contents += `            _ => None
+        }
+    }
+
+    /// Returns the response header if the message is a response or service fault
+    pub fn response_header(&self) -> Option<&ResponseHeader> {
+        match *self {
+`;
+
+    _.each(_.filter(message_types, function (message_type) { return message_type === "ServiceFault" || message_type.endsWith("Response"); }), function (message_type) {
+        contents += `            SupportedMessage::${message_type}(ref value) => Some(&value.response_header),
+`;
+    });
+
+    contents += `            _ => None
+        }
+    }
 }
 
 // These are all the messages handled into and out of streams by the OPCUA server / client code
@@ -125,7 +153,9 @@ generate_supported_message([
     // Discovery service
     "GetEndpointsRequest", "GetEndpointsResponse",
     "FindServersRequest", "FindServersResponse",
+    "FindServersOnNetworkRequest", "FindServersOnNetworkResponse",
     "RegisterServerRequest", "RegisterServerResponse",
+    "RegisterServer2Request", "RegisterServer2Response",
     // Session service
     "CreateSessionRequest", "CreateSessionResponse",
     "CloseSessionRequest", "CloseSessionResponse",
@@ -134,20 +164,34 @@ generate_supported_message([
     "CreateMonitoredItemsRequest", "CreateMonitoredItemsResponse",
     "ModifyMonitoredItemsRequest", "ModifyMonitoredItemsResponse",
     "DeleteMonitoredItemsRequest", "DeleteMonitoredItemsResponse",
+    "SetMonitoringModeRequest", "SetMonitoringModeResponse",
+    "SetTriggeringRequest", "SetTriggeringResponse",
     // Subscription service
     "CreateSubscriptionRequest", "CreateSubscriptionResponse",
     "ModifySubscriptionRequest", "ModifySubscriptionResponse",
     "DeleteSubscriptionsRequest", "DeleteSubscriptionsResponse",
     "SetPublishingModeRequest", "SetPublishingModeResponse",
+    "TransferSubscriptionsRequest", "TransferSubscriptionsResponse",
     // View service
     "BrowseRequest", "BrowseResponse",
     "BrowseNextRequest", "BrowseNextResponse",
     "PublishRequest", "PublishResponse",
     "RepublishRequest", "RepublishResponse",
     "TranslateBrowsePathsToNodeIdsRequest", "TranslateBrowsePathsToNodeIdsResponse",
+    "RegisterNodesRequest", "RegisterNodesResponse",
+    "UnregisterNodesRequest", "UnregisterNodesResponse",
+    "QueryFirstRequest", "QueryFirstResponse",
+    "QueryNextRequest", "QueryNextResponse",
     // Attribute service
     "ReadRequest", "ReadResponse",
     "WriteRequest", "WriteResponse",
+    "HistoryReadRequest", "HistoryReadResponse",
+    "HistoryUpdateRequest", "HistoryUpdateResponse",
     // Method service
     "CallRequest", "CallResponse",
+    // NodeManagement service
+    "AddNodesRequest", "AddNodesResponse",
+    "AddReferencesRequest", "AddReferencesResponse",
+    "DeleteNodesRequest", "DeleteNodesResponse",
+    "DeleteReferencesRequest", "DeleteReferencesResponse",
 ])
\ No newline at end of file
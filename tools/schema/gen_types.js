@@ -19,11 +19,9 @@ var ignored_types = [
     "Node", "InstanceNode", "TypeNode", "ObjectNode", "ObjectTypeNode", "VariableNode", "VariableTypeNode", "ReferenceTypeNode",
     "MethodNode", "ViewNode", "DataTypeNode", "ReferenceNode",
     // Excluded because they use unimplemented enums, or are used by unimplemented services
-    "ModificationInfo", "HistoryModifiedData", "UpdateDataDetails", "UpdateEventDetails", "UpdateStructureDataDetails", "RedundantServerDataType",
-    "ServerStatusDataType", "AxisInformation", "RegisterServer2Request", "RegisterServer2Response", "HistoryData", "HistoryEvent", "HistoryReadDetails",
-    "HistoryEventFieldList", "HistoryReadRequest", "HistoryReadResponse", "HistoryReadResult", "HistoryReadValueId", "HistoryUpdateDetails",
-    "HistoryUpdateRequest", "HistoryUpdateResponse", "HistoryUpdateResult", "SemanticChangeStructureDataType", "SemanticChangeStructureDataType",
-    "ReadAtTimeDetails", "ReadProcessedDetails"
+    "UpdateDataDetails", "UpdateEventDetails", "UpdateStructureDataDetails", "RedundantServerDataType",
+    "ServerStatusDataType", "AxisInformation", "HistoryEvent", "HistoryReadDetails",
+    "HistoryEventFieldList", "HistoryUpdateDetails", "SemanticChangeStructureDataType", "SemanticChangeStructureDataType"
 ];
 
 var basic_types_import_map = {
@@ -34,7 +32,7 @@ var basic_types_import_map = {
     "basic_types": ["ExtensionObject", "LocalizedText", "QualifiedName", "DiagnosticInfo"],
     "data_types": ["MessageSecurityMode", "Duration", "UtcTime", "MonitoringMode"],
     "service_types::impls": ["RequestHeader", "ResponseHeader"],
-    "service_types::enums": ["TimestampsToReturn", "FilterOperator", "BrowseDirection", "NodeClass", "SecurityTokenRequestType", "ApplicationType", "UserTokenType", "DataChangeTrigger"],
+    "service_types::enums": ["TimestampsToReturn", "FilterOperator", "BrowseDirection", "NodeClass", "SecurityTokenRequestType", "ApplicationType", "UserTokenType", "DataChangeTrigger", "HistoryUpdateType"],
     "node_id": ["NodeId", "ExpandedNodeId"],
     "data_value": ["DataValue"],
     "date_time": ["DateTime"],
@@ -524,9 +524,9 @@ pub mod DiagnosticFlags {
     pub const SERVICE_LEVEL_INNER_DIAGNOSTICS: u32 = 1 << 4;
     pub const OPERATIONS_LEVEL_SYMBOLIC_ID: u32 = 1 << 5;
     pub const OPERATIONS_LEVEL_LOCALIZED_TEXT: u32 = 1 << 6;
-    pub const OPERATIONS_LEVEL_ADDITIONAL_INFO: u32 = 1 << 8;
-    pub const OPERATIONS_LEVEL_INNER_STATUS_CODE: u32 = 1 << 9;
-    pub const OPERATIONS_LEVEL_INNER_DIAGNOSTICS: u32 = 1 << 10;
+    pub const OPERATIONS_LEVEL_ADDITIONAL_INFO: u32 = 1 << 7;
+    pub const OPERATIONS_LEVEL_INNER_STATUS_CODE: u32 = 1 << 8;
+    pub const OPERATIONS_LEVEL_INNER_DIAGNOSTICS: u32 = 1 << 9;
 }
 
 /// Data type ID 25
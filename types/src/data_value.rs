@@ -20,6 +20,17 @@ const HAS_SOURCE_PICOSECONDS: u8 = 0x10;
 /// False if the Server Picoseconds is 0.
 const HAS_SERVER_PICOSECONDS: u8 = 0x20;
 
+/// The info bits of the status code of a data value, held in its low 16 bits
+pub mod status_info_bits {
+    use basic_types::UInt16;
+
+    /// The info bits qualify the value of a data value, e.g. with the historian bits of a value
+    /// calculated by an aggregate
+    pub const INFO_TYPE_DATA_VALUE: UInt16 = 0x0400;
+    /// The bits that qualify the value when the info type is `INFO_TYPE_DATA_VALUE`
+    pub const DATA_VALUE_MASK: UInt16 = 0x03FF;
+}
+
 /// Data type ID 23
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DataValue {
@@ -29,6 +40,10 @@ pub struct DataValue {
     /// The status associated with the value.
     /// Not present if the StatusCode bit in the EncodingMask is False
     pub status: Option<StatusCode>,
+    /// The info bits of the status, see `status_info_bits`. The `StatusCode` type only holds the
+    /// defined codes so the bits are held here and encoded in the low 16 bits of the status code.
+    /// A status with info bits is Good if there is no status.
+    pub status_info_bits: UInt16,
     /// The source timestamp associated with the value.
     /// Not present if the SourceTimestamp bit in the EncodingMask is False.
     pub source_timestamp: Option<DateTime>,
@@ -53,7 +68,7 @@ impl BinaryEncoder<DataValue> for DataValue {
             size += self.value.as_ref().unwrap().byte_len();
         }
         if encoding_mask & HAS_STATUS != 0 {
-            size += self.status.as_ref().unwrap().byte_len();
+            size += self.status.unwrap_or(Good).byte_len();
         }
         if encoding_mask & HAS_SOURCE_TIMESTAMP != 0 {
             size += self.source_timestamp.as_ref().unwrap().byte_len();
@@ -80,7 +95,7 @@ impl BinaryEncoder<DataValue> for DataValue {
             size += self.value.as_ref().unwrap().encode(stream)?;
         }
         if encoding_mask & HAS_STATUS != 0 {
-            size += self.status.as_ref().unwrap().encode(stream)?;
+            size += write_u32(stream, self.status.unwrap_or(Good) as u32 | self.status_info_bits as u32)?;
         }
         if encoding_mask & HAS_SOURCE_TIMESTAMP != 0 {
             size += self.source_timestamp.as_ref().unwrap().encode(stream)?;
@@ -107,11 +122,13 @@ impl BinaryEncoder<DataValue> for DataValue {
             None
         };
 
-        // Status
-        let status = if encoding_mask & HAS_STATUS != 0 {
-            Some(StatusCode::decode(stream)?)
+        // Status, with any info bits in the low 16 bits
+        let (status, status_info_bits) = if encoding_mask & HAS_STATUS != 0 {
+            let code = read_u32(stream)?;
+            let status = StatusCode::from_u32(code & 0xFFFF0000).unwrap_or(StatusCode::BadUnexpectedError);
+            (Some(status), (code & 0xFFFF) as UInt16)
         } else {
-            None
+            (None, 0)
         };
 
         // Source timestamp
@@ -140,6 +157,7 @@ impl BinaryEncoder<DataValue> for DataValue {
         Ok(DataValue {
             value,
             status,
+            status_info_bits,
             source_picoseconds: if source_timestamp.is_some() { source_picoseconds } else { None },
             source_timestamp,
             server_picoseconds: if server_timestamp.is_some() { server_picoseconds } else { None },
@@ -154,6 +172,7 @@ impl DataValue {
         DataValue {
             value: Some(Variant::new(value)),
             status: Some(Good),
+            status_info_bits: 0,
             source_timestamp: Some(now.clone()),
             source_picoseconds: Some(0),
             server_timestamp: Some(now.clone()),
@@ -166,6 +185,7 @@ impl DataValue {
         DataValue {
             value: None,
             status: Some(Good),
+            status_info_bits: 0,
             source_timestamp: Some(now.clone()),
             source_picoseconds: Some(0),
             server_timestamp: Some(now.clone()),
@@ -178,7 +198,7 @@ impl DataValue {
         if self.value.is_some() {
             encoding_mask |= HAS_VALUE;
         }
-        if self.status.is_some() {
+        if self.status.is_some() || self.status_info_bits != 0 {
             encoding_mask |= HAS_STATUS;
         }
         if self.source_timestamp.is_some() {
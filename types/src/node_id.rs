@@ -2,7 +2,7 @@ use basic_types::*;
 use byte_string::ByteString;
 use encoding::*;
 use guid::Guid;
-use node_ids::{ObjectId, ReferenceTypeId};
+use node_ids::{DataTypeId, ObjectId, ReferenceTypeId};
 use status_codes::StatusCode;
 use status_codes::StatusCode::BadNodeIdInvalid;
 use std;
@@ -317,6 +317,14 @@ impl NodeId {
         }
     }
 
+    /// Extracts a DataTypeId from a node id, providing the node id holds a data type id
+    pub fn as_data_type_id(&self) -> std::result::Result<DataTypeId, ()> {
+        match self.identifier {
+            Identifier::Numeric(id) if self.namespace == 0 => DataTypeId::from_u32(id),
+            _ => Err(())
+        }
+    }
+
     pub fn to_string(&self) -> String {
         let mut result = String::new();
         if self.namespace != 0 {
@@ -3,23 +3,52 @@
 use date_time::DateTime;
 use basic_types::*;
 use node_ids::ObjectId;
-use service_types::{NotificationMessage, MonitoredItemNotification, DataChangeNotification};
+use status_codes::StatusCode;
+use service_types::{NotificationMessage, MonitoredItemNotification, DataChangeNotification, EventFieldList, EventNotificationList, StatusChangeNotification};
 
 impl NotificationMessage {
     pub fn new_data_change(sequence_number: UInt32, publish_time: DateTime, monitored_items: Vec<MonitoredItemNotification>) -> NotificationMessage {
-        let data_change_notification = DataChangeNotification {
-            monitored_items: Some(monitored_items),
-            diagnostic_infos: None,
-        };
+        Self::new(sequence_number, publish_time, monitored_items, Vec::new())
+    }
 
-        trace!("data change notification = {:?}", data_change_notification);
+    /// Creates a notification message holding data change and / or event notifications. A list
+    /// that is empty is left out of the message.
+    pub fn new(sequence_number: UInt32, publish_time: DateTime, monitored_items: Vec<MonitoredItemNotification>, events: Vec<EventFieldList>) -> NotificationMessage {
+        let mut notification_data = Vec::with_capacity(2);
+        if !monitored_items.is_empty() {
+            let data_change_notification = DataChangeNotification {
+                monitored_items: Some(monitored_items),
+                diagnostic_infos: None,
+            };
+            trace!("data change notification = {:?}", data_change_notification);
+            // Serialize to extension object
+            notification_data.push(ExtensionObject::from_encodable(ObjectId::DataChangeNotification_Encoding_DefaultBinary, data_change_notification));
+        }
+        if !events.is_empty() {
+            let event_notification_list = EventNotificationList {
+                events: Some(events),
+            };
+            trace!("event notification list = {:?}", event_notification_list);
+            notification_data.push(ExtensionObject::from_encodable(ObjectId::EventNotificationList_Encoding_DefaultBinary, event_notification_list));
+        }
+        NotificationMessage {
+            sequence_number,
+            publish_time,
+            notification_data: Some(notification_data),
+        }
+    }
 
-        // Serialize to extension object
-        let notification_data = ExtensionObject::from_encodable(ObjectId::DataChangeNotification_Encoding_DefaultBinary, data_change_notification);
+    /// Creates a notification message telling the client that the status of its subscription has
+    /// changed, e.g. to `BadTimeout` when the subscription's lifetime expired
+    pub fn status_change(sequence_number: UInt32, publish_time: DateTime, status: StatusCode) -> NotificationMessage {
+        let status_change_notification = StatusChangeNotification {
+            status,
+            diagnostic_info: DiagnosticInfo::new(),
+        };
         NotificationMessage {
             sequence_number,
             publish_time,
-            notification_data: Some(vec![notification_data]),
+            notification_data: Some(vec![ExtensionObject::from_encodable(ObjectId::StatusChangeNotification_Encoding_DefaultBinary, status_change_notification)]),
         }
     }
 
@@ -36,4 +65,30 @@ impl NotificationMessage {
         }
         result
     }
+
+    pub fn event_notifications(&self) -> Vec<EventNotificationList> {
+        let mut result = Vec::with_capacity(10);
+        if let Some(ref notification_data) = self.notification_data {
+            for n in notification_data {
+                if n.node_id != ObjectId::EventNotificationList_Encoding_DefaultBinary.into() {
+                    continue;
+                }
+                result.push(n.decode_inner::<EventNotificationList>().unwrap());
+            }
+        }
+        result
+    }
+
+    pub fn status_change_notifications(&self) -> Vec<StatusChangeNotification> {
+        let mut result = Vec::new();
+        if let Some(ref notification_data) = self.notification_data {
+            for n in notification_data {
+                if n.node_id != ObjectId::StatusChangeNotification_Encoding_DefaultBinary.into() {
+                    continue;
+                }
+                result.push(n.decode_inner::<StatusChangeNotification>().unwrap());
+            }
+        }
+        result
+    }
 }
@@ -1,8 +1,12 @@
+use std::cmp;
 use std::str::FromStr;
 
 use regex::Regex;
 
 use basic_types::UInt32;
+use status_codes::StatusCode;
+use status_codes::StatusCode::*;
+use string::UAString;
 use variant::Variant;
 
 /// See OPCUA Part 4 7.22
@@ -141,6 +145,18 @@ impl NumericRange {
         Self::from_str(s.into().as_ref())
     }
 
+    /// Parses the index range of a value being read or written, e.g. `ReadValueId::index_range`.
+    /// A null or empty index range means the whole value. The error is `BadIndexRangeInvalid` if
+    /// the range is malformed.
+    pub fn from_index_range(index_range: &UAString) -> Result<Option<NumericRange>, StatusCode> {
+        match index_range.value {
+            Some(ref index_range) if !index_range.is_empty() => {
+                Self::from_str(index_range).map(Some).map_err(|_| BadIndexRangeInvalid)
+            }
+            _ => Ok(None)
+        }
+    }
+
     pub fn as_string(&self) -> String {
         match *self {
             NumericRange::Index(idx) => {
@@ -229,21 +245,52 @@ impl NumericRange {
         }
     }
 
-    // This version should test the range against the supplied array
+    /// Returns the index or range of each dimension that the range selects. A single index or
+    /// range selects the only dimension.
+    pub fn dimensions(&self) -> Vec<&NumericRange> {
+        match *self {
+            NumericRange::MultipleRanges(ref ranges) => ranges.iter().collect(),
+            _ => vec![self]
+        }
+    }
+
+    /// Returns the number of elements that the range selects, regardless of the size of the
+    /// value it is applied to
+    pub fn element_count(&self) -> usize {
+        match *self {
+            NumericRange::Index(_) => 1,
+            NumericRange::Range(min, max) => (max - min) as usize + 1,
+            NumericRange::MultipleRanges(ref ranges) => ranges.iter().map(|r| r.element_count()).product()
+        }
+    }
+
+    /// Returns the start and (exclusive) end of the elements that an index or range selects in a
+    /// dimension of the supplied length. The end is clipped to the length of the dimension.
+    ///
+    /// The error is `BadIndexRangeNoData` if the range starts beyond the end of the dimension,
+    /// or `BadIndexRangeInvalid` for a multiple range.
+    pub fn bounds(&self, len: usize) -> Result<(usize, usize), StatusCode> {
+        let (min, max) = match *self {
+            NumericRange::Index(idx) => (idx as usize, idx as usize),
+            NumericRange::Range(min, max) => (min as usize, max as usize),
+            NumericRange::MultipleRanges(_) => {
+                return Err(BadIndexRangeInvalid);
+            }
+        };
+        if min >= len {
+            Err(BadIndexRangeNoData)
+        } else {
+            Ok((min, cmp::min(max.saturating_add(1), len)))
+        }
+    }
+
+    /// Tests if the range selects any elements of the supplied array. A multi dimension array
+    /// must have an index or range for each of its dimensions.
     pub fn is_valid_for_array(&self, array: &Variant) -> bool {
         match *array {
-            Variant::Array(_) => {
-                // Only accept range / index numeric ranges. Members of range have to be inside array
-                unimplemented!();
-            }
-            Variant::MultiDimensionArray(_) => {
-                // Only accept multi dimension numeric range with same # of dimensions as the array
-                // ensure that each range / index is inside that array dimension
-                unimplemented!();
-            }
-            _ => {
-                panic!("The array is not an array")
-            }
+            Variant::Array(ref values) => self.bounds(values.len()).is_ok(),
+            Variant::MultiDimensionArray(ref mda) => mda.range_bounds(self).is_ok(),
+            _ => false
         }
     }
 }
@@ -213,6 +213,9 @@ pub enum FilterOperator {
     And = 10,
     Or = 11,
     Cast = 12,
+    InView = 13,
+    OfType = 14,
+    RelatedTo = 15,
     BitwiseAnd = 16,
     BitwiseOr = 17,
 }
@@ -244,6 +247,9 @@ impl BinaryEncoder<FilterOperator> for FilterOperator {
             10 => Ok(FilterOperator::And),
             11 => Ok(FilterOperator::Or),
             12 => Ok(FilterOperator::Cast),
+            13 => Ok(FilterOperator::InView),
+            14 => Ok(FilterOperator::OfType),
+            15 => Ok(FilterOperator::RelatedTo),
             16 => Ok(FilterOperator::BitwiseAnd),
             17 => Ok(FilterOperator::BitwiseOr),
             _ => {
@@ -358,3 +364,38 @@ impl BinaryEncoder<ServerState> for ServerState {
         }
     }
 }
+
+/// The type of modification that was made to a historical value
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum HistoryUpdateType {
+    Insert = 1,
+    Replace = 2,
+    Update = 3,
+    Delete = 4,
+}
+
+impl BinaryEncoder<HistoryUpdateType> for HistoryUpdateType {
+    fn byte_len(&self) -> usize {
+        4
+    }
+
+    fn encode<S: Write>(&self, stream: &mut S) -> EncodingResult<usize> {
+        // All enums are Int32
+        write_i32(stream, *self as Int32)
+    }
+
+    fn decode<S: Read>(stream: &mut S) -> EncodingResult<Self> {
+        // All enums are Int32
+        let value = read_i32(stream)?;
+        match value {
+            1 => Ok(HistoryUpdateType::Insert),
+            2 => Ok(HistoryUpdateType::Replace),
+            3 => Ok(HistoryUpdateType::Update),
+            4 => Ok(HistoryUpdateType::Delete),
+            _ => {
+                error!("Don't know what history update type {} is", value);
+                Err(BadUnexpectedError)
+            }
+        }
+    }
+}
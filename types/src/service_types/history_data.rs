@@ -0,0 +1,45 @@
+// This file was autogenerated from Opc.Ua.Types.bsd.xml by tools/schema/gen_types.js
+// DO NOT EDIT THIS FILE
+
+use std::io::{Read, Write};
+
+use encoding::*;
+#[allow(unused_imports)]
+use basic_types::*;
+use service_types::impls::MessageInfo;
+use node_ids::ObjectId;
+use data_value::DataValue;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryData {
+    pub data_values: Option<Vec<DataValue>>,
+}
+
+impl MessageInfo for HistoryData {
+    fn object_id(&self) -> ObjectId {
+        ObjectId::HistoryData_Encoding_DefaultBinary
+    }
+}
+
+impl BinaryEncoder<HistoryData> for HistoryData {
+    fn byte_len(&self) -> usize {
+        let mut size = 0;
+        size += byte_len_array(&self.data_values);
+        size
+    }
+
+    #[allow(unused_variables)]
+    fn encode<S: Write>(&self, stream: &mut S) -> EncodingResult<usize> {
+        let mut size = 0;
+        size += write_array(stream, &self.data_values)?;
+        Ok(size)
+    }
+
+    #[allow(unused_variables)]
+    fn decode<S: Read>(stream: &mut S) -> EncodingResult<Self> {
+        let data_values: Option<Vec<DataValue>> = read_array(stream)?;
+        Ok(HistoryData {
+            data_values,
+        })
+    }
+}
@@ -0,0 +1,51 @@
+// This file was autogenerated from Opc.Ua.Types.bsd.xml by tools/schema/gen_types.js
+// DO NOT EDIT THIS FILE
+
+use std::io::{Read, Write};
+
+use encoding::*;
+#[allow(unused_imports)]
+use basic_types::*;
+use service_types::impls::MessageInfo;
+use node_ids::ObjectId;
+use service_types::impls::RequestHeader;
+use basic_types::ExtensionObject;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryUpdateRequest {
+    pub request_header: RequestHeader,
+    pub history_update_details: Option<Vec<ExtensionObject>>,
+}
+
+impl MessageInfo for HistoryUpdateRequest {
+    fn object_id(&self) -> ObjectId {
+        ObjectId::HistoryUpdateRequest_Encoding_DefaultBinary
+    }
+}
+
+impl BinaryEncoder<HistoryUpdateRequest> for HistoryUpdateRequest {
+    fn byte_len(&self) -> usize {
+        let mut size = 0;
+        size += self.request_header.byte_len();
+        size += byte_len_array(&self.history_update_details);
+        size
+    }
+
+    #[allow(unused_variables)]
+    fn encode<S: Write>(&self, stream: &mut S) -> EncodingResult<usize> {
+        let mut size = 0;
+        size += self.request_header.encode(stream)?;
+        size += write_array(stream, &self.history_update_details)?;
+        Ok(size)
+    }
+
+    #[allow(unused_variables)]
+    fn decode<S: Read>(stream: &mut S) -> EncodingResult<Self> {
+        let request_header = RequestHeader::decode(stream)?;
+        let history_update_details: Option<Vec<ExtensionObject>> = read_array(stream)?;
+        Ok(HistoryUpdateRequest {
+            request_header,
+            history_update_details,
+        })
+    }
+}
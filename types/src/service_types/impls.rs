@@ -319,40 +319,73 @@ impl DataChangeFilter {
     /// BadDeadbandFilterInvalid indicates the deadband settings were invalid, e.g. an invalid
     /// type, or the args were invalid. A (low, high) range must be supplied for a percentage deadband compare.
     pub fn compare_value(&self, v1: &Variant, v2: &Variant, eu_range: Option<(f64, f64)>) -> std::result::Result<bool, StatusCode> {
-        // TODO be able to compare arrays of numbers
-
         if self.deadband_type == 0 {
             // Straight comparison of values
-            Ok(v1 == v2)
-        } else {
-            // Absolute
-            let v1 = v1.as_f64();
-            let v2 = v2.as_f64();
-            if v1.is_none() || v2.is_none() {
-                return Ok(false);
+            return Ok(v1 == v2);
+        }
+        // Deadbands are applied element-wise to arrays of numbers. Arrays of a different length or
+        // dimensions are always different.
+        match (v1, v2) {
+            (&Variant::Array(ref v1), &Variant::Array(ref v2)) => {
+                self.compare_elements(v1, v2, eu_range)
             }
+            (&Variant::MultiDimensionArray(ref v1), &Variant::MultiDimensionArray(ref v2)) => {
+                if v1.dimensions != v2.dimensions {
+                    self.validate_deadband(eu_range)?;
+                    Ok(false)
+                } else {
+                    self.compare_elements(&v1.values, &v2.values, eu_range)
+                }
+            }
+            _ => self.compare_scalar(v1, v2, eu_range)
+        }
+    }
 
-            let v1 = v1.unwrap();
-            let v2 = v2.unwrap();
-
-            if self.deadband_value < 0f64 {
-                return Err(BadDeadbandFilterInvalid);
+    fn compare_elements(&self, v1: &[Variant], v2: &[Variant], eu_range: Option<(f64, f64)>) -> std::result::Result<bool, StatusCode> {
+        if v1.len() != v2.len() {
+            self.validate_deadband(eu_range)?;
+            return Ok(false);
+        }
+        for (v1, v2) in v1.iter().zip(v2.iter()) {
+            if !self.compare_scalar(v1, v2, eu_range)? {
+                return Ok(false);
             }
-            if self.deadband_type == 1 {
-                Ok(DataChangeFilter::abs_compare(v1, v2, self.deadband_value))
-            } else if self.deadband_type == 2 {
-                if eu_range.is_none() {
-                    return Err(BadDeadbandFilterInvalid);
-                }
-                let (low, high) = eu_range.unwrap();
-                if low >= high {
-                    return Err(BadDeadbandFilterInvalid);
-                }
-                Ok(DataChangeFilter::pct_compare(v1, v2, low, high, self.deadband_value))
-            } else {
-                // Type is not recognized
-                return Err(BadDeadbandFilterInvalid);
+        }
+        Ok(true)
+    }
+
+    /// Tests that the deadband type and value are valid, and that there is a usable range for a
+    /// percentage deadband.
+    fn validate_deadband(&self, eu_range: Option<(f64, f64)>) -> std::result::Result<(), StatusCode> {
+        if self.deadband_value < 0f64 {
+            Err(BadDeadbandFilterInvalid)
+        } else if self.deadband_type == 1 {
+            Ok(())
+        } else if self.deadband_type == 2 {
+            match eu_range {
+                Some((low, high)) if low < high => Ok(()),
+                _ => Err(BadDeadbandFilterInvalid)
             }
+        } else {
+            // Type is not recognized
+            Err(BadDeadbandFilterInvalid)
+        }
+    }
+
+    fn compare_scalar(&self, v1: &Variant, v2: &Variant, eu_range: Option<(f64, f64)>) -> std::result::Result<bool, StatusCode> {
+        self.validate_deadband(eu_range)?;
+        let v1 = v1.as_f64();
+        let v2 = v2.as_f64();
+        if v1.is_none() || v2.is_none() {
+            return Ok(false);
+        }
+        let v1 = v1.unwrap();
+        let v2 = v2.unwrap();
+        if self.deadband_type == 1 {
+            Ok(DataChangeFilter::abs_compare(v1, v2, self.deadband_value))
+        } else {
+            let (low, high) = eu_range.unwrap();
+            Ok(DataChangeFilter::pct_compare(v1, v2, low, high, self.deadband_value))
         }
     }
 
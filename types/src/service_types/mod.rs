@@ -32,6 +32,8 @@ mod register_server_request;
 mod register_server_response;
 mod discovery_configuration;
 mod mdns_discovery_configuration;
+mod register_server2_request;
+mod register_server2_response;
 mod channel_security_token;
 mod open_secure_channel_request;
 mod open_secure_channel_response;
@@ -115,14 +117,26 @@ mod query_next_response;
 mod read_value_id;
 mod read_request;
 mod read_response;
+mod history_read_value_id;
+mod history_read_result;
 mod read_event_details;
 mod read_raw_modified_details;
+mod read_processed_details;
+mod read_at_time_details;
+mod history_data;
+mod modification_info;
+mod history_modified_data;
+mod history_read_request;
+mod history_read_response;
 mod write_value;
 mod write_request;
 mod write_response;
 mod delete_raw_modified_details;
 mod delete_at_time_details;
 mod delete_event_details;
+mod history_update_result;
+mod history_update_request;
+mod history_update_response;
 mod call_method_request;
 mod call_method_result;
 mod call_request;
@@ -213,6 +227,8 @@ pub use self::register_server_request::*;
 pub use self::register_server_response::*;
 pub use self::discovery_configuration::*;
 pub use self::mdns_discovery_configuration::*;
+pub use self::register_server2_request::*;
+pub use self::register_server2_response::*;
 pub use self::channel_security_token::*;
 pub use self::open_secure_channel_request::*;
 pub use self::open_secure_channel_response::*;
@@ -296,14 +312,26 @@ pub use self::query_next_response::*;
 pub use self::read_value_id::*;
 pub use self::read_request::*;
 pub use self::read_response::*;
+pub use self::history_read_value_id::*;
+pub use self::history_read_result::*;
 pub use self::read_event_details::*;
 pub use self::read_raw_modified_details::*;
+pub use self::read_processed_details::*;
+pub use self::read_at_time_details::*;
+pub use self::history_data::*;
+pub use self::modification_info::*;
+pub use self::history_modified_data::*;
+pub use self::history_read_request::*;
+pub use self::history_read_response::*;
 pub use self::write_value::*;
 pub use self::write_request::*;
 pub use self::write_response::*;
 pub use self::delete_raw_modified_details::*;
 pub use self::delete_at_time_details::*;
 pub use self::delete_event_details::*;
+pub use self::history_update_result::*;
+pub use self::history_update_request::*;
+pub use self::history_update_response::*;
 pub use self::call_method_request::*;
 pub use self::call_method_result::*;
 pub use self::call_request::*;
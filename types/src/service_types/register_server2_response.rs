@@ -0,0 +1,57 @@
+// This file was autogenerated from Opc.Ua.Types.bsd.xml by tools/schema/gen_types.js
+// DO NOT EDIT THIS FILE
+
+use std::io::{Read, Write};
+
+use encoding::*;
+#[allow(unused_imports)]
+use basic_types::*;
+use service_types::impls::MessageInfo;
+use node_ids::ObjectId;
+use service_types::impls::ResponseHeader;
+use status_codes::StatusCode;
+use basic_types::DiagnosticInfo;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegisterServer2Response {
+    pub response_header: ResponseHeader,
+    pub configuration_results: Option<Vec<StatusCode>>,
+    pub diagnostic_infos: Option<Vec<DiagnosticInfo>>,
+}
+
+impl MessageInfo for RegisterServer2Response {
+    fn object_id(&self) -> ObjectId {
+        ObjectId::RegisterServer2Response_Encoding_DefaultBinary
+    }
+}
+
+impl BinaryEncoder<RegisterServer2Response> for RegisterServer2Response {
+    fn byte_len(&self) -> usize {
+        let mut size = 0;
+        size += self.response_header.byte_len();
+        size += byte_len_array(&self.configuration_results);
+        size += byte_len_array(&self.diagnostic_infos);
+        size
+    }
+
+    #[allow(unused_variables)]
+    fn encode<S: Write>(&self, stream: &mut S) -> EncodingResult<usize> {
+        let mut size = 0;
+        size += self.response_header.encode(stream)?;
+        size += write_array(stream, &self.configuration_results)?;
+        size += write_array(stream, &self.diagnostic_infos)?;
+        Ok(size)
+    }
+
+    #[allow(unused_variables)]
+    fn decode<S: Read>(stream: &mut S) -> EncodingResult<Self> {
+        let response_header = ResponseHeader::decode(stream)?;
+        let configuration_results: Option<Vec<StatusCode>> = read_array(stream)?;
+        let diagnostic_infos: Option<Vec<DiagnosticInfo>> = read_array(stream)?;
+        Ok(RegisterServer2Response {
+            response_header,
+            configuration_results,
+            diagnostic_infos,
+        })
+    }
+}
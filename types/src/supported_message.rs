@@ -97,12 +97,24 @@ impl SupportedMessage {
             ObjectId::FindServersResponse_Encoding_DefaultBinary => {
                 SupportedMessage::FindServersResponse(FindServersResponse::decode(stream)?)
             }
+            ObjectId::FindServersOnNetworkRequest_Encoding_DefaultBinary => {
+                SupportedMessage::FindServersOnNetworkRequest(FindServersOnNetworkRequest::decode(stream)?)
+            }
+            ObjectId::FindServersOnNetworkResponse_Encoding_DefaultBinary => {
+                SupportedMessage::FindServersOnNetworkResponse(FindServersOnNetworkResponse::decode(stream)?)
+            }
             ObjectId::RegisterServerRequest_Encoding_DefaultBinary => {
                 SupportedMessage::RegisterServerRequest(RegisterServerRequest::decode(stream)?)
             }
             ObjectId::RegisterServerResponse_Encoding_DefaultBinary => {
                 SupportedMessage::RegisterServerResponse(RegisterServerResponse::decode(stream)?)
             }
+            ObjectId::RegisterServer2Request_Encoding_DefaultBinary => {
+                SupportedMessage::RegisterServer2Request(RegisterServer2Request::decode(stream)?)
+            }
+            ObjectId::RegisterServer2Response_Encoding_DefaultBinary => {
+                SupportedMessage::RegisterServer2Response(RegisterServer2Response::decode(stream)?)
+            }
             ObjectId::CreateSessionRequest_Encoding_DefaultBinary => {
                 SupportedMessage::CreateSessionRequest(CreateSessionRequest::decode(stream)?)
             }
@@ -139,6 +151,18 @@ impl SupportedMessage {
             ObjectId::DeleteMonitoredItemsResponse_Encoding_DefaultBinary => {
                 SupportedMessage::DeleteMonitoredItemsResponse(DeleteMonitoredItemsResponse::decode(stream)?)
             }
+            ObjectId::SetMonitoringModeRequest_Encoding_DefaultBinary => {
+                SupportedMessage::SetMonitoringModeRequest(SetMonitoringModeRequest::decode(stream)?)
+            }
+            ObjectId::SetMonitoringModeResponse_Encoding_DefaultBinary => {
+                SupportedMessage::SetMonitoringModeResponse(SetMonitoringModeResponse::decode(stream)?)
+            }
+            ObjectId::SetTriggeringRequest_Encoding_DefaultBinary => {
+                SupportedMessage::SetTriggeringRequest(SetTriggeringRequest::decode(stream)?)
+            }
+            ObjectId::SetTriggeringResponse_Encoding_DefaultBinary => {
+                SupportedMessage::SetTriggeringResponse(SetTriggeringResponse::decode(stream)?)
+            }
             ObjectId::CreateSubscriptionRequest_Encoding_DefaultBinary => {
                 SupportedMessage::CreateSubscriptionRequest(CreateSubscriptionRequest::decode(stream)?)
             }
@@ -163,6 +187,12 @@ impl SupportedMessage {
             ObjectId::SetPublishingModeResponse_Encoding_DefaultBinary => {
                 SupportedMessage::SetPublishingModeResponse(SetPublishingModeResponse::decode(stream)?)
             }
+            ObjectId::TransferSubscriptionsRequest_Encoding_DefaultBinary => {
+                SupportedMessage::TransferSubscriptionsRequest(TransferSubscriptionsRequest::decode(stream)?)
+            }
+            ObjectId::TransferSubscriptionsResponse_Encoding_DefaultBinary => {
+                SupportedMessage::TransferSubscriptionsResponse(TransferSubscriptionsResponse::decode(stream)?)
+            }
             ObjectId::BrowseRequest_Encoding_DefaultBinary => {
                 SupportedMessage::BrowseRequest(BrowseRequest::decode(stream)?)
             }
@@ -193,6 +223,30 @@ impl SupportedMessage {
             ObjectId::TranslateBrowsePathsToNodeIdsResponse_Encoding_DefaultBinary => {
                 SupportedMessage::TranslateBrowsePathsToNodeIdsResponse(TranslateBrowsePathsToNodeIdsResponse::decode(stream)?)
             }
+            ObjectId::RegisterNodesRequest_Encoding_DefaultBinary => {
+                SupportedMessage::RegisterNodesRequest(RegisterNodesRequest::decode(stream)?)
+            }
+            ObjectId::RegisterNodesResponse_Encoding_DefaultBinary => {
+                SupportedMessage::RegisterNodesResponse(RegisterNodesResponse::decode(stream)?)
+            }
+            ObjectId::UnregisterNodesRequest_Encoding_DefaultBinary => {
+                SupportedMessage::UnregisterNodesRequest(UnregisterNodesRequest::decode(stream)?)
+            }
+            ObjectId::UnregisterNodesResponse_Encoding_DefaultBinary => {
+                SupportedMessage::UnregisterNodesResponse(UnregisterNodesResponse::decode(stream)?)
+            }
+            ObjectId::QueryFirstRequest_Encoding_DefaultBinary => {
+                SupportedMessage::QueryFirstRequest(QueryFirstRequest::decode(stream)?)
+            }
+            ObjectId::QueryFirstResponse_Encoding_DefaultBinary => {
+                SupportedMessage::QueryFirstResponse(QueryFirstResponse::decode(stream)?)
+            }
+            ObjectId::QueryNextRequest_Encoding_DefaultBinary => {
+                SupportedMessage::QueryNextRequest(QueryNextRequest::decode(stream)?)
+            }
+            ObjectId::QueryNextResponse_Encoding_DefaultBinary => {
+                SupportedMessage::QueryNextResponse(QueryNextResponse::decode(stream)?)
+            }
             ObjectId::ReadRequest_Encoding_DefaultBinary => {
                 SupportedMessage::ReadRequest(ReadRequest::decode(stream)?)
             }
@@ -205,12 +259,48 @@ impl SupportedMessage {
             ObjectId::WriteResponse_Encoding_DefaultBinary => {
                 SupportedMessage::WriteResponse(WriteResponse::decode(stream)?)
             }
+            ObjectId::HistoryReadRequest_Encoding_DefaultBinary => {
+                SupportedMessage::HistoryReadRequest(HistoryReadRequest::decode(stream)?)
+            }
+            ObjectId::HistoryReadResponse_Encoding_DefaultBinary => {
+                SupportedMessage::HistoryReadResponse(HistoryReadResponse::decode(stream)?)
+            }
+            ObjectId::HistoryUpdateRequest_Encoding_DefaultBinary => {
+                SupportedMessage::HistoryUpdateRequest(HistoryUpdateRequest::decode(stream)?)
+            }
+            ObjectId::HistoryUpdateResponse_Encoding_DefaultBinary => {
+                SupportedMessage::HistoryUpdateResponse(HistoryUpdateResponse::decode(stream)?)
+            }
             ObjectId::CallRequest_Encoding_DefaultBinary => {
                 SupportedMessage::CallRequest(CallRequest::decode(stream)?)
             }
             ObjectId::CallResponse_Encoding_DefaultBinary => {
                 SupportedMessage::CallResponse(CallResponse::decode(stream)?)
             }
+            ObjectId::AddNodesRequest_Encoding_DefaultBinary => {
+                SupportedMessage::AddNodesRequest(AddNodesRequest::decode(stream)?)
+            }
+            ObjectId::AddNodesResponse_Encoding_DefaultBinary => {
+                SupportedMessage::AddNodesResponse(AddNodesResponse::decode(stream)?)
+            }
+            ObjectId::AddReferencesRequest_Encoding_DefaultBinary => {
+                SupportedMessage::AddReferencesRequest(AddReferencesRequest::decode(stream)?)
+            }
+            ObjectId::AddReferencesResponse_Encoding_DefaultBinary => {
+                SupportedMessage::AddReferencesResponse(AddReferencesResponse::decode(stream)?)
+            }
+            ObjectId::DeleteNodesRequest_Encoding_DefaultBinary => {
+                SupportedMessage::DeleteNodesRequest(DeleteNodesRequest::decode(stream)?)
+            }
+            ObjectId::DeleteNodesResponse_Encoding_DefaultBinary => {
+                SupportedMessage::DeleteNodesResponse(DeleteNodesResponse::decode(stream)?)
+            }
+            ObjectId::DeleteReferencesRequest_Encoding_DefaultBinary => {
+                SupportedMessage::DeleteReferencesRequest(DeleteReferencesRequest::decode(stream)?)
+            }
+            ObjectId::DeleteReferencesResponse_Encoding_DefaultBinary => {
+                SupportedMessage::DeleteReferencesResponse(DeleteReferencesResponse::decode(stream)?)
+            }
 
             _ => {
                 debug!("decoding unsupported for object id {:?}", object_id);
@@ -219,6 +309,97 @@ impl SupportedMessage {
         };
         Ok(decoded_message)
     }
+
+    /// Returns the request header if the message is a request
+    pub fn request_header(&self) -> Option<&RequestHeader> {
+        match *self {
+            SupportedMessage::OpenSecureChannelRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::CloseSecureChannelRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::GetEndpointsRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::FindServersRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::FindServersOnNetworkRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::RegisterServerRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::RegisterServer2Request(ref value) => Some(&value.request_header),
+            SupportedMessage::CreateSessionRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::CloseSessionRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::ActivateSessionRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::CreateMonitoredItemsRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::ModifyMonitoredItemsRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::DeleteMonitoredItemsRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::SetMonitoringModeRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::SetTriggeringRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::CreateSubscriptionRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::ModifySubscriptionRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::DeleteSubscriptionsRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::SetPublishingModeRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::TransferSubscriptionsRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::BrowseRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::BrowseNextRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::PublishRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::RepublishRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::TranslateBrowsePathsToNodeIdsRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::RegisterNodesRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::UnregisterNodesRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::QueryFirstRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::QueryNextRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::ReadRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::WriteRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::HistoryReadRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::HistoryUpdateRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::CallRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::AddNodesRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::AddReferencesRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::DeleteNodesRequest(ref value) => Some(&value.request_header),
+            SupportedMessage::DeleteReferencesRequest(ref value) => Some(&value.request_header),
+            _ => None
+        }
+    }
+
+    /// Returns the response header if the message is a response or service fault
+    pub fn response_header(&self) -> Option<&ResponseHeader> {
+        match *self {
+            SupportedMessage::ServiceFault(ref value) => Some(&value.response_header),
+            SupportedMessage::OpenSecureChannelResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::CloseSecureChannelResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::GetEndpointsResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::FindServersResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::FindServersOnNetworkResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::RegisterServerResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::RegisterServer2Response(ref value) => Some(&value.response_header),
+            SupportedMessage::CreateSessionResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::CloseSessionResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::ActivateSessionResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::CreateMonitoredItemsResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::ModifyMonitoredItemsResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::DeleteMonitoredItemsResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::SetMonitoringModeResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::SetTriggeringResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::CreateSubscriptionResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::ModifySubscriptionResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::DeleteSubscriptionsResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::SetPublishingModeResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::TransferSubscriptionsResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::BrowseResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::BrowseNextResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::PublishResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::RepublishResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::TranslateBrowsePathsToNodeIdsResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::RegisterNodesResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::UnregisterNodesResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::QueryFirstResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::QueryNextResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::ReadResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::WriteResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::HistoryReadResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::HistoryUpdateResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::CallResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::AddNodesResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::AddReferencesResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::DeleteNodesResponse(ref value) => Some(&value.response_header),
+            SupportedMessage::DeleteReferencesResponse(ref value) => Some(&value.response_header),
+            _ => None
+        }
+    }
 }
 
 // These are all the messages handled into and out of streams by the OPCUA server / client code
@@ -232,8 +413,12 @@ supported_messages_enum![
     GetEndpointsResponse,
     FindServersRequest,
     FindServersResponse,
+    FindServersOnNetworkRequest,
+    FindServersOnNetworkResponse,
     RegisterServerRequest,
     RegisterServerResponse,
+    RegisterServer2Request,
+    RegisterServer2Response,
     CreateSessionRequest,
     CreateSessionResponse,
     CloseSessionRequest,
@@ -246,6 +431,10 @@ supported_messages_enum![
     ModifyMonitoredItemsResponse,
     DeleteMonitoredItemsRequest,
     DeleteMonitoredItemsResponse,
+    SetMonitoringModeRequest,
+    SetMonitoringModeResponse,
+    SetTriggeringRequest,
+    SetTriggeringResponse,
     CreateSubscriptionRequest,
     CreateSubscriptionResponse,
     ModifySubscriptionRequest,
@@ -254,6 +443,8 @@ supported_messages_enum![
     DeleteSubscriptionsResponse,
     SetPublishingModeRequest,
     SetPublishingModeResponse,
+    TransferSubscriptionsRequest,
+    TransferSubscriptionsResponse,
     BrowseRequest,
     BrowseResponse,
     BrowseNextRequest,
@@ -264,10 +455,30 @@ supported_messages_enum![
     RepublishResponse,
     TranslateBrowsePathsToNodeIdsRequest,
     TranslateBrowsePathsToNodeIdsResponse,
+    RegisterNodesRequest,
+    RegisterNodesResponse,
+    UnregisterNodesRequest,
+    UnregisterNodesResponse,
+    QueryFirstRequest,
+    QueryFirstResponse,
+    QueryNextRequest,
+    QueryNextResponse,
     ReadRequest,
     ReadResponse,
     WriteRequest,
     WriteResponse,
+    HistoryReadRequest,
+    HistoryReadResponse,
+    HistoryUpdateRequest,
+    HistoryUpdateResponse,
     CallRequest,
     CallResponse,
+    AddNodesRequest,
+    AddNodesResponse,
+    AddReferencesRequest,
+    AddReferencesResponse,
+    DeleteNodesRequest,
+    DeleteNodesResponse,
+    DeleteReferencesRequest,
+    DeleteReferencesResponse,
 ];
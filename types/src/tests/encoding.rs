@@ -369,6 +369,7 @@ fn variant() {
     let v = DataValue {
         value: Some(Variant::Double(1000f64)),
         status: Some(GoodClamped),
+        status_info_bits: 0,
         source_timestamp: Some(DateTime::now()),
         source_picoseconds: Some(333),
         server_timestamp: Some(DateTime::now()),
@@ -377,6 +378,36 @@ fn variant() {
     serialize_test(v);
 }
 
+#[test]
+fn data_value_status_info_bits() {
+    // The info bits are encoded in the low 16 bits of the status code
+    let v = DataValue {
+        value: None,
+        status: None,
+        status_info_bits: status_info_bits::INFO_TYPE_DATA_VALUE | 0x1,
+        source_timestamp: None,
+        source_picoseconds: None,
+        server_timestamp: None,
+        server_picoseconds: None,
+    };
+    serialize_and_compare(v.clone(), &[0x02, 0x01, 0x04, 0x00, 0x00]);
+    // A status with info bits and no code is Good
+    let mut expected = v.clone();
+    expected.status = Some(Good);
+    serialize_test_expected(v, expected);
+
+    let v = DataValue {
+        value: Some(Variant::Double(1000f64)),
+        status: Some(UncertainDataSubNormal),
+        status_info_bits: status_info_bits::INFO_TYPE_DATA_VALUE | 0x6,
+        source_timestamp: Some(DateTime::now()),
+        source_picoseconds: None,
+        server_timestamp: None,
+        server_picoseconds: None,
+    };
+    serialize_test(v);
+}
+
 #[test]
 fn variant_single_dimension_array() {
     let values = vec![Variant::Int32(100), Variant::Int32(200), Variant::Int32(300)];
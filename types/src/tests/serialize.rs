@@ -35,6 +35,7 @@ fn serialize_data_value() {
     let dv = DataValue {
         value: Some(Variant::from(100u16)),
         status: Some(StatusCode::BadAggregateListMismatch),
+        status_info_bits: 0,
         source_timestamp: Some(source_timestamp.clone()),
         source_picoseconds: Some(123),
         server_timestamp: Some(server_timestamp.clone()),
@@ -43,5 +44,5 @@ fn serialize_data_value() {
     let dvs = serde_json::to_string(&dv).unwrap();
     println!("dv = {}", dvs);
 
-    assert_eq!(dvs, format!("{{\"value\":{{\"UInt16\":100}},\"status\":\"BadAggregateListMismatch\",\"source_timestamp\":\"{}\",\"source_picoseconds\":123,\"server_timestamp\":\"{}\",\"server_picoseconds\":456}}", source_timestamp.to_string(), server_timestamp.to_string()));
+    assert_eq!(dvs, format!("{{\"value\":{{\"UInt16\":100}},\"status\":\"BadAggregateListMismatch\",\"status_info_bits\":0,\"source_timestamp\":\"{}\",\"source_picoseconds\":123,\"server_timestamp\":\"{}\",\"server_picoseconds\":456}}", source_timestamp.to_string(), server_timestamp.to_string()));
 }
@@ -0,0 +1,147 @@
+use ::*;
+use status_codes::StatusCode::*;
+use node_ids::DataTypeId;
+
+#[test]
+fn convert_numbers() {
+    // Widening and narrowing within range
+    assert_eq!(Variant::Byte(200).convert(DataTypeId::Int32), Variant::Int32(200));
+    assert_eq!(Variant::Int32(-5).convert(DataTypeId::Int16), Variant::Int16(-5));
+    assert_eq!(Variant::UInt64(10).convert(DataTypeId::Double), Variant::Double(10f64));
+    // Out of range
+    assert_eq!(Variant::Int32(300).convert(DataTypeId::Byte), Variant::Empty);
+    assert_eq!(Variant::Int32(-1).convert(DataTypeId::UInt32), Variant::Empty);
+    assert_eq!(Variant::UInt64(u64::max_value()).convert(DataTypeId::Int64), Variant::Empty);
+    // Floating point values are rounded
+    assert_eq!(Variant::Double(2.5f64).convert(DataTypeId::Int32), Variant::Int32(3));
+    assert_eq!(Variant::Float(-1.4f32).convert(DataTypeId::Int64), Variant::Int64(-1));
+    assert_eq!(Variant::Double(1e40f64).convert(DataTypeId::Float), Variant::Empty);
+    // Booleans
+    assert_eq!(Variant::Boolean(true).convert(DataTypeId::UInt16), Variant::UInt16(1));
+    assert_eq!(Variant::Int32(0).convert(DataTypeId::Boolean), Variant::Boolean(false));
+    assert_eq!(Variant::Double(0.5f64).convert(DataTypeId::Boolean), Variant::Boolean(true));
+}
+
+#[test]
+fn convert_strings() {
+    assert_eq!(Variant::from("123").convert(DataTypeId::Int32), Variant::Int32(123));
+    assert_eq!(Variant::from("12.7").convert(DataTypeId::Int32), Variant::Int32(13));
+    assert_eq!(Variant::from("12.5").convert(DataTypeId::Double), Variant::Double(12.5f64));
+    assert_eq!(Variant::from("abc").convert(DataTypeId::Int32), Variant::Empty);
+    assert_eq!(Variant::from("TRUE").convert(DataTypeId::Boolean), Variant::Boolean(true));
+    assert_eq!(Variant::from("no").convert(DataTypeId::Boolean), Variant::Empty);
+    assert_eq!(Variant::Int32(-7).convert(DataTypeId::String), Variant::from("-7"));
+    assert_eq!(Variant::Boolean(false).convert(DataTypeId::String), Variant::from("false"));
+
+    let node_id = NodeId::new_string(2, "Tags");
+    assert_eq!(Variant::from(node_id.to_string()).convert(DataTypeId::NodeId), Variant::from(node_id.clone()));
+    assert_eq!(Variant::from(node_id.clone()).convert(DataTypeId::String), Variant::from(node_id.to_string()));
+    assert_eq!(Variant::from(node_id.clone()).convert(DataTypeId::ExpandedNodeId), Variant::from(ExpandedNodeId::from(node_id)));
+
+    assert_eq!(Variant::from("Hello").convert(DataTypeId::LocalizedText), Variant::from(LocalizedText::new("", "Hello")));
+    assert_eq!(Variant::from(LocalizedText::new("en", "Hello")).convert(DataTypeId::String), Variant::from("Hello"));
+    assert_eq!(Variant::from(QualifiedName::new(2, "Hello")).convert(DataTypeId::String), Variant::from("2:Hello"));
+}
+
+#[test]
+fn convert_others() {
+    // Same type is unchanged
+    let now = DateTime::now();
+    assert_eq!(Variant::from(now.clone()).convert(DataTypeId::DateTime), Variant::from(now));
+    // Status codes
+    assert_eq!(Variant::UInt32(BadTypeMismatch as u32).convert(DataTypeId::StatusCode), Variant::StatusCode(BadTypeMismatch));
+    assert_eq!(Variant::StatusCode(Good).convert(DataTypeId::UInt32), Variant::UInt32(0));
+    // Conversions that are not allowed
+    assert_eq!(Variant::Int32(1).convert(DataTypeId::DateTime), Variant::Empty);
+    assert_eq!(Variant::Empty.convert(DataTypeId::Int32), Variant::Empty);
+}
+
+fn range(s: &str) -> NumericRange {
+    NumericRange::new(s).unwrap()
+}
+
+#[test]
+fn range_of_array() {
+    let array = Variant::new_i32_array(&[0, 1, 2, 3, 4]);
+    assert_eq!(array.range_of(&range("1")).unwrap(), Variant::new_i32_array(&[1]));
+    assert_eq!(array.range_of(&range("1:3")).unwrap(), Variant::new_i32_array(&[1, 2, 3]));
+    // Ranges past the end are clipped, ranges starting past the end select nothing
+    assert_eq!(array.range_of(&range("3:10")).unwrap(), Variant::new_i32_array(&[3, 4]));
+    assert_eq!(array.range_of(&range("5")).unwrap_err(), BadIndexRangeNoData);
+    // A single dimension array has a single range
+    assert_eq!(array.range_of(&range("1,1")).unwrap_err(), BadIndexRangeInvalid);
+    assert_eq!(Variant::Int32(1).range_of(&range("0")).unwrap_err(), BadIndexRangeInvalid);
+
+    assert!(range("4").is_valid_for_array(&array));
+    assert!(!range("5:6").is_valid_for_array(&array));
+    assert!(!range("0").is_valid_for_array(&Variant::Int32(1)));
+}
+
+#[test]
+fn range_of_multi_dimension_array() {
+    // A 3x3 matrix
+    let values = (0..9).map(|i| Variant::Int32(i)).collect();
+    let matrix = Variant::new_multi_dimension_array(values, vec![3, 3]);
+    assert_eq!(matrix.range_of(&range("1,2")).unwrap(), Variant::new_multi_dimension_array(vec![Variant::Int32(5)], vec![1, 1]));
+    assert_eq!(matrix.range_of(&range("1:2,0:1")).unwrap(), Variant::new_multi_dimension_array(vec![
+        Variant::Int32(3), Variant::Int32(4), Variant::Int32(6), Variant::Int32(7)
+    ], vec![2, 2]));
+    assert_eq!(matrix.range_of(&range("0:1")).unwrap_err(), BadIndexRangeInvalid);
+    assert_eq!(matrix.range_of(&range("0,3")).unwrap_err(), BadIndexRangeNoData);
+    assert!(range("2,2").is_valid_for_array(&matrix));
+}
+
+#[test]
+fn range_of_strings() {
+    assert_eq!(Variant::from("Hello world").range_of(&range("6:10")).unwrap(), Variant::from("world"));
+    assert_eq!(Variant::from("Hello").range_of(&range("5")).unwrap_err(), BadIndexRangeNoData);
+    assert_eq!(Variant::String(UAString::null()).range_of(&range("0")).unwrap_err(), BadIndexRangeNoData);
+    assert_eq!(Variant::from(ByteString::from(vec![1u8, 2, 3])).range_of(&range("0:1")).unwrap(), Variant::from(ByteString::from(vec![1u8, 2])));
+}
+
+#[test]
+fn set_range_of_array() {
+    let mut array = Variant::new_i32_array(&[0, 1, 2, 3, 4]);
+    assert!(array.set_range_of(&range("1:2"), &Variant::new_i32_array(&[10, 20])).is_ok());
+    assert_eq!(array, Variant::new_i32_array(&[0, 10, 20, 3, 4]));
+    assert!(array.set_range_of(&range("4"), &Variant::new_i32_array(&[40])).is_ok());
+    assert_eq!(array, Variant::new_i32_array(&[0, 10, 20, 3, 40]));
+
+    // The value must match the size of the range, which must be inside the array
+    assert_eq!(array.set_range_of(&range("1:2"), &Variant::new_i32_array(&[1])), Err(BadIndexRangeInvalid));
+    assert_eq!(array.set_range_of(&range("4:5"), &Variant::new_i32_array(&[1, 2])), Err(BadIndexRangeNoData));
+    // The value must be an array of the same type
+    assert_eq!(array.set_range_of(&range("1"), &Variant::Int32(1)), Err(BadTypeMismatch));
+    assert_eq!(array.set_range_of(&range("1"), &Variant::new_u32_array(&[1])), Err(BadTypeMismatch));
+    assert_eq!(array, Variant::new_i32_array(&[0, 10, 20, 3, 40]));
+}
+
+#[test]
+fn set_range_of_multi_dimension_array() {
+    let values = (0..9).map(|i| Variant::Int32(i)).collect();
+    let mut matrix = Variant::new_multi_dimension_array(values, vec![3, 3]);
+    let block = Variant::new_multi_dimension_array(vec![
+        Variant::Int32(10), Variant::Int32(11), Variant::Int32(12), Variant::Int32(13)
+    ], vec![2, 2]);
+    assert!(matrix.set_range_of(&range("1:2,1:2"), &block).is_ok());
+    assert_eq!(matrix.range_of(&range("0:2,0:2")).unwrap(), Variant::new_multi_dimension_array(vec![
+        Variant::Int32(0), Variant::Int32(1), Variant::Int32(2),
+        Variant::Int32(3), Variant::Int32(10), Variant::Int32(11),
+        Variant::Int32(6), Variant::Int32(12), Variant::Int32(13),
+    ], vec![3, 3]));
+    assert_eq!(matrix.set_range_of(&range("2:3,1:2"), &block), Err(BadIndexRangeNoData));
+    assert_eq!(matrix.set_range_of(&range("0:1,0"), &block), Err(BadIndexRangeInvalid));
+}
+
+#[test]
+fn set_range_of_strings() {
+    let mut value = Variant::from("Hello world");
+    assert!(value.set_range_of(&range("0:4"), &Variant::from("HELLO")).is_ok());
+    assert_eq!(value, Variant::from("HELLO world"));
+    assert_eq!(value.set_range_of(&range("10:11"), &Variant::from("ab")), Err(BadIndexRangeNoData));
+    assert_eq!(value.set_range_of(&range("0"), &Variant::new_i32_array(&[1])), Err(BadTypeMismatch));
+
+    let mut value = Variant::from(ByteString::from(vec![1u8, 2, 3]));
+    assert!(value.set_range_of(&range("2"), &Variant::from(ByteString::from(vec![30u8]))).is_ok());
+    assert_eq!(value, Variant::from(ByteString::from(vec![1u8, 2, 30])));
+}
@@ -7,7 +7,9 @@ use encoding::*;
 use guid::Guid;
 use node_id::{ExpandedNodeId, NodeId};
 use node_ids::DataTypeId;
+use numeric_range::NumericRange;
 use status_codes::StatusCode;
+use status_codes::StatusCode::*;
 use std::convert::Into;
 use std::io::{Read, Write};
 use string::{UAString, XmlElement};
@@ -21,6 +23,31 @@ pub struct MultiDimensionArray {
     pub dimensions: Vec<Int32>,
 }
 
+impl MultiDimensionArray {
+    /// Returns the start and (exclusive) end of the elements that the range selects in each
+    /// dimension of the array, which must have as many dimensions as the range
+    pub fn range_bounds(&self, range: &NumericRange) -> Result<Vec<(usize, usize)>, StatusCode> {
+        let ranges = range.dimensions();
+        let len = self.dimensions.iter().fold(1usize, |len, d| len.saturating_mul(*d as usize));
+        if ranges.len() != self.dimensions.len() || self.dimensions.iter().any(|d| *d < 0) || len != self.values.len() {
+            Err(BadIndexRangeInvalid)
+        } else {
+            ranges.iter().zip(self.dimensions.iter()).map(|(r, d)| r.bounds(*d as usize)).collect()
+        }
+    }
+
+    /// Returns the positions in the values of the elements inside the bounds, in the order that
+    /// they are encoded, i.e. with the last dimension varying fastest
+    fn value_indices(&self, bounds: &[(usize, usize)]) -> Vec<usize> {
+        let mut indices = vec![0];
+        for (dimension, &(start, end)) in self.dimensions.iter().zip(bounds.iter()) {
+            let dimension = *dimension as usize;
+            indices = indices.iter().flat_map(|offset| (start..end).map(move |i| offset * dimension + i)).collect();
+        }
+        indices
+    }
+}
+
 impl From<Boolean> for Variant {
     fn from(v: Boolean) -> Self {
         Variant::Boolean(v)
@@ -578,6 +605,114 @@ impl Variant {
         Variant::Array(values)
     }
 
+    /// Returns the elements of an array, the characters of a string or the bytes of a byte string
+    /// that the numeric range selects. A range that runs past the end of the value is clipped.
+    ///
+    /// The error is `BadIndexRangeNoData` if the range selects nothing from the value, or
+    /// `BadIndexRangeInvalid` if the value is a scalar or has a different number of dimensions.
+    pub fn range_of(&self, range: &NumericRange) -> Result<Variant, StatusCode> {
+        match *self {
+            Variant::Array(ref values) => {
+                let (start, end) = range.bounds(values.len())?;
+                Ok(Variant::Array(values[start..end].to_vec()))
+            }
+            Variant::MultiDimensionArray(ref mda) => {
+                let bounds = mda.range_bounds(range)?;
+                let values = mda.value_indices(&bounds).into_iter().map(|i| mda.values[i].clone()).collect();
+                let dimensions = bounds.iter().map(|&(start, end)| (end - start) as Int32).collect();
+                Ok(Variant::new_multi_dimension_array(values, dimensions))
+            }
+            Variant::String(ref value) => {
+                let chars: Vec<char> = value.value.as_ref().ok_or(BadIndexRangeNoData)?.chars().collect();
+                let (start, end) = range.bounds(chars.len())?;
+                Ok(Variant::String(UAString::from(chars[start..end].iter().collect::<String>())))
+            }
+            Variant::ByteString(ref value) => {
+                let bytes = value.value.as_ref().ok_or(BadIndexRangeNoData)?;
+                let (start, end) = range.bounds(bytes.len())?;
+                Ok(Variant::ByteString(ByteString::from(bytes[start..end].to_vec())))
+            }
+            _ => Err(BadIndexRangeInvalid)
+        }
+    }
+
+    /// Replaces the elements of an array, the characters of a string or the bytes of a byte
+    /// string that the numeric range selects with those of the supplied value. The value must
+    /// be of the same kind and hold exactly as many elements as the range, all of which must lie
+    /// inside this value.
+    ///
+    /// The error is `BadIndexRangeNoData` if the range runs past the end of this value,
+    /// `BadIndexRangeInvalid` if the supplied value does not match the size of the range and
+    /// `BadTypeMismatch` if it is of a different kind or element type.
+    pub fn set_range_of(&mut self, range: &NumericRange, other: &Variant) -> Result<(), StatusCode> {
+        match (self, other) {
+            (&mut Variant::Array(ref mut values), &Variant::Array(ref other_values)) => {
+                let (start, end) = Self::write_bounds(range, values.len())?;
+                if other_values.len() != end - start {
+                    Err(BadIndexRangeInvalid)
+                } else if other_values.iter().any(|v| v.data_type() != values[0].data_type()) {
+                    Err(BadTypeMismatch)
+                } else {
+                    values.splice(start..end, other_values.iter().cloned());
+                    Ok(())
+                }
+            }
+            (&mut Variant::MultiDimensionArray(ref mut mda), &Variant::MultiDimensionArray(ref other_mda)) => {
+                let bounds = mda.range_bounds(range)?;
+                if range.dimensions().iter().zip(bounds.iter()).any(|(r, &(start, end))| r.element_count() != end - start) {
+                    return Err(BadIndexRangeNoData);
+                }
+                let dimensions: Vec<Int32> = bounds.iter().map(|&(start, end)| (end - start) as Int32).collect();
+                if other_mda.dimensions != dimensions || other_mda.values.len() != range.element_count() {
+                    Err(BadIndexRangeInvalid)
+                } else if other_mda.values.iter().any(|v| v.data_type() != mda.values[0].data_type()) {
+                    Err(BadTypeMismatch)
+                } else {
+                    for (i, value) in mda.value_indices(&bounds).into_iter().zip(other_mda.values.iter()) {
+                        mda.values[i] = value.clone();
+                    }
+                    Ok(())
+                }
+            }
+            (&mut Variant::String(ref mut value), &Variant::String(ref other_value)) => {
+                let mut chars: Vec<char> = value.value.as_ref().ok_or(BadIndexRangeNoData)?.chars().collect();
+                let other_chars: Vec<char> = other_value.value.as_ref().ok_or(BadIndexRangeInvalid)?.chars().collect();
+                let (start, end) = Self::write_bounds(range, chars.len())?;
+                if other_chars.len() != end - start {
+                    Err(BadIndexRangeInvalid)
+                } else {
+                    chars.splice(start..end, other_chars);
+                    *value = UAString::from(chars.into_iter().collect::<String>());
+                    Ok(())
+                }
+            }
+            (&mut Variant::ByteString(ref mut value), &Variant::ByteString(ref other_value)) => {
+                let other_bytes = other_value.value.as_ref().ok_or(BadIndexRangeInvalid)?;
+                let bytes = value.value.as_mut().ok_or(BadIndexRangeNoData)?;
+                let (start, end) = Self::write_bounds(range, bytes.len())?;
+                if other_bytes.len() != end - start {
+                    Err(BadIndexRangeInvalid)
+                } else {
+                    bytes.splice(start..end, other_bytes.iter().cloned());
+                    Ok(())
+                }
+            }
+            (&mut Variant::Array(_), _) | (&mut Variant::MultiDimensionArray(_), _) |
+            (&mut Variant::String(_), _) | (&mut Variant::ByteString(_), _) => Err(BadTypeMismatch),
+            _ => Err(BadIndexRangeInvalid)
+        }
+    }
+
+    /// Bounds of a range being written, which unlike a read cannot be clipped
+    fn write_bounds(range: &NumericRange, len: usize) -> Result<(usize, usize), StatusCode> {
+        let (start, end) = range.bounds(len)?;
+        if end - start != range.element_count() {
+            Err(BadIndexRangeNoData)
+        } else {
+            Ok((start, end))
+        }
+    }
+
     /// Tests and returns true if the variant holds a numeric type
     pub fn is_numeric(&self) -> bool {
         match *self {
@@ -645,6 +780,165 @@ impl Variant {
         })
     }
 
+    /// Converts the value to the data type, following the conversion rules in OPC UA Part 4 that
+    /// filters use to compare values of different types. Numbers, booleans and strings convert
+    /// between each other, and strings convert to and from node ids, guids and text. The result is
+    /// `Variant::Empty` if the value cannot be converted, e.g. because it is out of range.
+    pub fn convert(&self, data_type: DataTypeId) -> Variant {
+        if self.data_type() == Some(data_type) {
+            return self.clone();
+        }
+        let result = match data_type {
+            DataTypeId::Boolean => self.to_bool().map(Variant::Boolean),
+            DataTypeId::SByte => self.to_i64().and_then(|v| {
+                if v >= i8::min_value() as i64 && v <= i8::max_value() as i64 { Some(Variant::SByte(v as SByte)) } else { None }
+            }),
+            DataTypeId::Byte => self.to_u64().and_then(|v| {
+                if v <= u8::max_value() as u64 { Some(Variant::Byte(v as Byte)) } else { None }
+            }),
+            DataTypeId::Int16 => self.to_i64().and_then(|v| {
+                if v >= i16::min_value() as i64 && v <= i16::max_value() as i64 { Some(Variant::Int16(v as Int16)) } else { None }
+            }),
+            DataTypeId::UInt16 => self.to_u64().and_then(|v| {
+                if v <= u16::max_value() as u64 { Some(Variant::UInt16(v as UInt16)) } else { None }
+            }),
+            DataTypeId::Int32 => self.to_i64().and_then(|v| {
+                if v >= i32::min_value() as i64 && v <= i32::max_value() as i64 { Some(Variant::Int32(v as Int32)) } else { None }
+            }),
+            DataTypeId::UInt32 => self.to_u64().and_then(|v| {
+                if v <= u32::max_value() as u64 { Some(Variant::UInt32(v as UInt32)) } else { None }
+            }),
+            DataTypeId::Int64 => self.to_i64().map(Variant::Int64),
+            DataTypeId::UInt64 => self.to_u64().map(Variant::UInt64),
+            DataTypeId::Float => self.to_f64().and_then(|v| {
+                if v.is_finite() && v.abs() <= ::std::f32::MAX as f64 { Some(Variant::Float(v as Float)) } else { None }
+            }),
+            DataTypeId::Double => self.to_f64().map(Variant::Double),
+            DataTypeId::String => self.to_ua_string().map(Variant::String),
+            DataTypeId::Guid => match *self {
+                Variant::String(ref v) if !v.is_null() => v.as_ref().parse::<Guid>().ok().map(Variant::Guid),
+                _ => None
+            },
+            DataTypeId::NodeId => match *self {
+                Variant::String(ref v) if !v.is_null() => v.as_ref().parse::<NodeId>().ok().map(Variant::from),
+                Variant::ExpandedNodeId(ref v) if v.namespace_uri.is_null() && v.server_index == 0 => Some(Variant::from(v.node_id.clone())),
+                _ => None
+            },
+            DataTypeId::ExpandedNodeId => match *self {
+                Variant::String(ref v) if !v.is_null() => v.as_ref().parse::<NodeId>().ok().map(|v| Variant::from(ExpandedNodeId::from(v))),
+                Variant::NodeId(ref v) => Some(Variant::from(ExpandedNodeId::from((**v).clone()))),
+                _ => None
+            },
+            DataTypeId::LocalizedText => match *self {
+                Variant::String(ref v) if !v.is_null() => Some(Variant::from(LocalizedText::new("", v.as_ref()))),
+                _ => None
+            },
+            DataTypeId::QualifiedName => match *self {
+                Variant::String(ref v) if !v.is_null() => Some(Variant::from(QualifiedName::new(0, v.as_ref()))),
+                _ => None
+            },
+            DataTypeId::StatusCode => match *self {
+                Variant::Int32(_) | Variant::UInt32(_) | Variant::Int64(_) | Variant::UInt64(_) => {
+                    self.to_u64().and_then(|v| StatusCode::from_u32(v as u32).ok()).map(Variant::StatusCode)
+                }
+                _ => None
+            },
+            _ => None
+        };
+        result.unwrap_or(Variant::Empty)
+    }
+
+    /// Converts the value to a boolean. Numbers are true when they are not zero.
+    fn to_bool(&self) -> Option<bool> {
+        match *self {
+            Variant::Boolean(v) => Some(v),
+            Variant::String(ref v) => match v.as_ref().to_lowercase().as_ref() {
+                "true" | "1" => Some(true),
+                "false" | "0" => Some(false),
+                _ => None
+            },
+            _ => self.as_f64().map(|v| v != 0f64)
+        }
+    }
+
+    /// Converts the value to a signed integer. Floating point values are rounded.
+    fn to_i64(&self) -> Option<i64> {
+        match *self {
+            Variant::Boolean(v) => Some(if v { 1 } else { 0 }),
+            Variant::SByte(v) => Some(v as i64),
+            Variant::Byte(v) => Some(v as i64),
+            Variant::Int16(v) => Some(v as i64),
+            Variant::UInt16(v) => Some(v as i64),
+            Variant::Int32(v) => Some(v as i64),
+            Variant::UInt32(v) => Some(v as i64),
+            Variant::Int64(v) => Some(v),
+            Variant::UInt64(v) => if v <= i64::max_value() as u64 { Some(v as i64) } else { None },
+            Variant::StatusCode(v) => Some(v as u32 as i64),
+            Variant::String(ref v) => {
+                if let Ok(v) = v.as_ref().parse::<i64>() { Some(v) } else { Variant::Double(v.as_ref().parse::<f64>().ok()?).to_i64() }
+            }
+            Variant::Float(_) | Variant::Double(_) => {
+                let v = self.as_f64().unwrap().round();
+                if v >= i64::min_value() as f64 && v <= i64::max_value() as f64 { Some(v as i64) } else { None }
+            }
+            _ => None
+        }
+    }
+
+    /// Converts the value to an unsigned integer. Floating point values are rounded.
+    fn to_u64(&self) -> Option<u64> {
+        match *self {
+            Variant::UInt64(v) => Some(v),
+            Variant::String(ref v) => {
+                if let Ok(v) = v.as_ref().parse::<u64>() { Some(v) } else { Variant::Double(v.as_ref().parse::<f64>().ok()?).to_u64() }
+            }
+            Variant::Float(_) | Variant::Double(_) => {
+                let v = self.as_f64().unwrap().round();
+                if v >= 0f64 && v <= u64::max_value() as f64 { Some(v as u64) } else { None }
+            }
+            _ => self.to_i64().and_then(|v| if v >= 0 { Some(v as u64) } else { None })
+        }
+    }
+
+    /// Converts the value to a double
+    fn to_f64(&self) -> Option<f64> {
+        match *self {
+            Variant::Boolean(v) => Some(if v { 1f64 } else { 0f64 }),
+            Variant::StatusCode(v) => Some(v as u32 as f64),
+            Variant::String(ref v) => v.as_ref().parse::<f64>().ok(),
+            _ => self.as_f64()
+        }
+    }
+
+    /// Converts the value to a string
+    fn to_ua_string(&self) -> Option<UAString> {
+        let value = match *self {
+            Variant::Boolean(v) => v.to_string(),
+            Variant::SByte(v) => v.to_string(),
+            Variant::Byte(v) => v.to_string(),
+            Variant::Int16(v) => v.to_string(),
+            Variant::UInt16(v) => v.to_string(),
+            Variant::Int32(v) => v.to_string(),
+            Variant::UInt32(v) => v.to_string(),
+            Variant::Int64(v) => v.to_string(),
+            Variant::UInt64(v) => v.to_string(),
+            Variant::Float(v) => v.to_string(),
+            Variant::Double(v) => v.to_string(),
+            Variant::DateTime(ref v) => v.to_string(),
+            Variant::Guid(ref v) => v.to_string(),
+            Variant::NodeId(ref v) => v.to_string(),
+            Variant::ExpandedNodeId(ref v) => v.node_id.to_string(),
+            Variant::LocalizedText(ref v) => return Some(v.text.clone()),
+            Variant::QualifiedName(ref v) => if v.namespace_index == 0 {
+                v.name.as_ref().to_string()
+            } else {
+                format!("{}:{}", v.namespace_index, v.name.as_ref())
+            },
+            _ => return None
+        };
+        Some(UAString::from(value))
+    }
+
     // Gets the encoding mask to write the variant to disk
     fn get_encoding_mask(&self) -> u8 {
         let encoding_mask = match *self {